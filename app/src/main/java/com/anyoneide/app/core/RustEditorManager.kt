@@ -45,6 +45,37 @@ class RustEditorManager(private val context: Context) {
         @JvmStatic external fun nativeFormatCode(content: String, language: String): String
         @JvmStatic external fun nativeParseCodeStructure(content: String, language: String): String
         @JvmStatic external fun nativeFindReferences(content: String, position: Int, language: String): String
+        @JvmStatic external fun nativeHoverInfo(content: String, position: Int, language: String): String
+        @JvmStatic external fun nativeFormatRange(content: String, startByte: Int, endByte: Int, language: String): String
+        @JvmStatic external fun nativeFormatCodeEdits(content: String, language: String): String
+        @JvmStatic external fun nativeDetectLanguage(fileName: String, content: String): String
+        @JvmStatic external fun nativeSemanticTokens(content: String, language: String): String
+        @JvmStatic external fun nativeGrep(root: String, pattern: String, optionsJson: String): String
+        @JvmStatic external fun nativeIndexProject(root: String, languagesJson: String): String
+        @JvmStatic external fun nativeLookupSymbol(name: String): String
+        @JvmStatic external fun nativeReindexFile(path: String): String
+        @JvmStatic external fun nativePollFileEvents(): String
+        @JvmStatic external fun nativeUnwatchFile(watchId: String): Boolean
+        @JvmStatic external fun nativeWatchFile(path: String): String
+        @JvmStatic external fun nativeIndentForNewLine(content: String, position: Int, language: String, indentUnit: String): String
+        @JvmStatic external fun nativeClearSnippets(language: String): Boolean
+        @JvmStatic external fun nativeRegisterSnippet(language: String, snippetJson: String): String
+        @JvmStatic external fun nativeFormatCodeWithDiagnostic(content: String, language: String): String
+        @JvmStatic external fun nativeSetFormatOptions(optionsJson: String): String
+        @JvmStatic external fun nativeNormalizeWhitespace(content: String, language: String, stripTrailing: Boolean, ensureFinalNewline: Boolean, trimTrailingBlankLines: Boolean): String
+        @JvmStatic external fun nativeFormatCodeWithTool(content: String, language: String, toolPath: String): String
+        @JvmStatic external fun nativeApplyEdits(content: String, editsJson: String): String
+        @JvmStatic external fun nativeGetCompletionList(content: String, position: Int, language: String): String
+        @JvmStatic external fun nativeOffsetToPosition(content: String, byteOffset: Int, utf16: Boolean): String
+        @JvmStatic external fun nativePositionToOffset(content: String, line: Int, column: Int, utf16: Boolean): Int
+        @JvmStatic external fun nativeFindReferencesUtf16(content: String, position: Int, language: String): String
+        @JvmStatic external fun nativeGetCompletionListUtf16(content: String, position: Int, language: String): String
+        @JvmStatic external fun nativeHighlightSyntaxUtf16(content: String, language: String): String
+        @JvmStatic external fun nativeHighlightBatch(requestsJson: String): String
+        @JvmStatic external fun nativeCancelEditorOp(docId: String): Boolean
+        @JvmStatic external fun nativeHighlightDocument(docId: String, content: String, language: String): String
+        @JvmStatic external fun nativeParseDocumentStructure(docId: String, content: String, language: String): String
+        @JvmStatic external fun nativeSetMaxHighlightBytes(maxBytes: Long)
     }
     
     private val fallbackSyntaxHighlighter = SyntaxHighlighter()
@@ -173,13 +204,17 @@ class RustEditorManager(private val context: Context) {
     }
     
     /**
-     * Parse highlights from JSON
+     * Parse highlights from the {"highlights", "degraded"} JSON of nativeHighlightSyntax
      */
     private fun parseHighlights(json: String): List<SyntaxHighlight> {
         val highlights = mutableListOf<SyntaxHighlight>()
         
         try {
-            val jsonArray = JSONArray(json)
+            val jsonObject = JSONObject(json)
+            if (jsonObject.optBoolean("degraded")) {
+                Log.i(TAG, "Document too large for full highlighting, only basic highlighting applied")
+            }
+            val jsonArray = jsonObject.getJSONArray("highlights")
             for (i in 0 until jsonArray.length()) {
                 val highlight = jsonArray.getJSONObject(i)
                 highlights.add(
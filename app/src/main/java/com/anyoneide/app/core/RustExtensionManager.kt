@@ -7,6 +7,7 @@ import kotlinx.coroutines.flow.Flow
 import kotlinx.coroutines.flow.flow
 import kotlinx.coroutines.flow.flowOn
 import kotlinx.coroutines.withContext
+import org.json.JSONArray
 import org.json.JSONObject
 import java.util.concurrent.atomic.AtomicBoolean
 
@@ -39,7 +40,8 @@ class RustExtensionManager(private val context: Context) {
         @JvmStatic external fun nativeRegisterExtension(extensionId: String, extensionType: String, extensionData: String): Boolean
         @JvmStatic external fun nativeUnregisterExtension(extensionId: String): Boolean
         @JvmStatic external fun nativeGetRegisteredExtensions(): String
-        @JvmStatic external fun nativeExecuteExtension(extensionId: String, data: String): String
+        @JvmStatic external fun nativeExecuteExtension(extensionId: String, data: String, timeoutMs: Long): String
+        @JvmStatic external fun nativeRegisterExtensionChecked(extensionId: String, extensionType: String, extensionData: String): String
     }
     
     /**
@@ -50,7 +52,8 @@ class RustExtensionManager(private val context: Context) {
     }
     
     /**
-     * Register an extension
+     * Register an extension. Fails with one "field: message" line per problem when the extension's
+     * data is missing something its type requires
      */
     suspend fun registerExtension(
         extensionId: String,
@@ -74,8 +77,12 @@ class RustExtensionManager(private val context: Context) {
                 put("configuration", JSONObject(extensionData.configuration))
             }.toString()
             
-            val success = nativeRegisterExtension(extensionId, extensionType, extensionDataJson)
-            Result.success(success)
+            val result = JSONObject(nativeRegisterExtensionChecked(extensionId, extensionType, extensionDataJson))
+            if (result.getBoolean("success")) {
+                Result.success(true)
+            } else {
+                Result.failure(Exception(parseExtensionDataErrors(result.optJSONArray("errors"))))
+            }
         } catch (e: Exception) {
             Log.e(TAG, "Error registering extension", e)
             Result.failure(e)
@@ -118,15 +125,15 @@ class RustExtensionManager(private val context: Context) {
     }
     
     /**
-     * Execute an extension
+     * Execute an extension, giving up after [timeoutMs] (0 uses the extension's own limit)
      */
-    suspend fun executeExtension(extensionId: String, data: String): Result<ExtensionResult> = withContext(Dispatchers.IO) {
+    suspend fun executeExtension(extensionId: String, data: String, timeoutMs: Long = 0): Result<ExtensionResult> = withContext(Dispatchers.IO) {
         if (!isLibraryLoaded.get()) {
             return@withContext Result.failure(Exception("Native extension library not loaded"))
         }
         
         try {
-            val resultJson = nativeExecuteExtension(extensionId, data)
+            val resultJson = nativeExecuteExtension(extensionId, data, timeoutMs)
             val result = parseExtensionResult(resultJson)
             Result.success(result)
         } catch (e: Exception) {
@@ -193,6 +200,22 @@ class RustExtensionManager(private val context: Context) {
         return extensions
     }
     
+    /**
+     * Join the problems found in an extension's data into one "field: message" line each
+     */
+    private fun parseExtensionDataErrors(errorsArray: JSONArray?): String {
+        if (errorsArray == null || errorsArray.length() == 0) {
+            return "Invalid extension data"
+        }
+        
+        val messages = mutableListOf<String>()
+        for (i in 0 until errorsArray.length()) {
+            val error = errorsArray.getJSONObject(i)
+            messages.add("${error.getString("field")}: ${error.getString("message")}")
+        }
+        return messages.joinToString("\n")
+    }
+    
     /**
      * Parse extension result from JSON
      */
@@ -201,6 +224,7 @@ class RustExtensionManager(private val context: Context) {
             val result = JSONObject(json)
             return ExtensionResult(
                 success = result.getBoolean("success"),
+                status = result.optString("status", if (result.getBoolean("success")) "ok" else "error"),
                 data = result.getString("data"),
                 error = if (result.has("error") && !result.isNull("error")) result.getString("error") else null
             )
@@ -208,6 +232,7 @@ class RustExtensionManager(private val context: Context) {
             Log.e(TAG, "Error parsing extension result JSON", e)
             return ExtensionResult(
                 success = false,
+                status = "error",
                 data = "",
                 error = "Failed to parse result: ${e.message}"
             )
@@ -247,10 +272,11 @@ class RustExtensionManager(private val context: Context) {
     }
     
     /**
-     * Extension result
+     * Extension result. `status` is "ok", or "error", "timeout" or "panic" when the extension failed
      */
     data class ExtensionResult(
         val success: Boolean,
+        val status: String,
         val data: String,
         val error: String?
     )
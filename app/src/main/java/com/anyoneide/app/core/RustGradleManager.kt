@@ -43,6 +43,7 @@ class RustGradleManager(private val context: Context) {
         @JvmStatic external fun nativeUpdateDependencies(filePath: String): String
         @JvmStatic external fun nativeFixCommonIssues(filePath: String): String
         @JvmStatic external fun nativeGenerateBuildReport(projectPath: String): String
+        @JvmStatic external fun nativeGradleDependencyTree(sdkRoot: String, projectPath: String, configuration: String): String
     }
     
     private val gradleBuildManager = GradleBuildManager(context)
@@ -38,16 +38,57 @@ class RustNativeBuildManager(private val context: Context) {
         
         // Native method declarations - these will throw UnsatisfiedLinkError if the library is not loaded
         @JvmStatic external fun nativeBuildProject(projectPath: String, buildType: String): String
-        @JvmStatic external fun nativeCleanProject(projectPath: String): String
+        @JvmStatic external fun nativeCleanProject(projectPath: String, target: String, release: Int, packageName: String): String
         @JvmStatic external fun nativeTestProject(projectPath: String, release: Boolean): String
         @JvmStatic external fun nativeCheckRustInstalled(): Boolean
         @JvmStatic external fun nativeGetRustVersion(): String
         @JvmStatic external fun nativeGetBuildSystemStatus(): String
         @JvmStatic external fun nativeIsValidRustProject(projectPath: String): Boolean
         @JvmStatic external fun nativeGetProjectInfo(projectPath: String): String
-        @JvmStatic external fun nativeCheckBuildSystemHealth(): String
+        @JvmStatic external fun nativeCheckBuildSystemHealth(projectPath: String): String
         @JvmStatic external fun nativeBuildForAndroidTarget(projectPath: String, target: String, release: Boolean): String
-        @JvmStatic external fun nativeGenerateAndroidBindings(projectPath: String, packageName: String): String
+        @JvmStatic external fun nativeGenerateAndroidBindings(projectPath: String, packageName: String, apiLevel: Int): String
+        @JvmStatic external fun nativeListRemoteSdkPackages(sdkRoot: String): String
+        @JvmStatic external fun nativeGetSdkDiskUsage(sdkRoot: String): String
+        @JvmStatic external fun nativeCancelBuild(buildId: String): Boolean
+        @JvmStatic external fun nativePollBuild(buildId: String): String
+        @JvmStatic external fun nativeStartBuild(projectPath: String, buildType: String): String
+        @JvmStatic external fun nativePollBuildOutput(buildId: String, sinceIndex: Int): String
+        @JvmStatic external fun nativeGenerateDocs(projectPath: String, open: Boolean, noDeps: Boolean): String
+        @JvmStatic external fun nativeBenchProject(projectPath: String, filter: String): String
+        @JvmStatic external fun nativeTestProjectFiltered(projectPath: String, release: Boolean, testFilter: String): String
+        @JvmStatic external fun nativeCheckProject(projectPath: String, target: String): String
+        @JvmStatic external fun nativeSetBuildToolchain(sdkRoot: String): Boolean
+        @JvmStatic external fun nativeCompileSingleFile(filePath: String, language: String, outputDir: String, sdkRoot: String): String
+        @JvmStatic external fun nativeAdbDevices(sdkRoot: String): String
+        @JvmStatic external fun nativeAdbInstall(sdkRoot: String, apkPath: String, deviceSerial: String): String
+        @JvmStatic external fun nativeStartAdbInstall(sdkRoot: String, apkPath: String, deviceSerial: String): String
+        @JvmStatic external fun nativePollWatch(watchId: String): String
+        @JvmStatic external fun nativeStartWatch(projectPath: String, buildType: String): String
+        @JvmStatic external fun nativeStopWatch(watchId: String): Boolean
+        @JvmStatic external fun nativeGitDiff(repoPath: String, file: String): String
+        @JvmStatic external fun nativeGitStatus(repoPath: String): String
+        @JvmStatic external fun nativeGitCommit(repoPath: String, message: String, author: String): String
+        @JvmStatic external fun nativeGitStage(repoPath: String, pathsJson: String): String
+        @JvmStatic external fun nativeGitUnstage(repoPath: String, pathsJson: String): String
+        @JvmStatic external fun nativeGenerateRustBuildReport(projectPath: String): String
+        @JvmStatic external fun nativeGetAndroidTargets(): String
+        @JvmStatic external fun nativeBuildAllAndroidTargets(projectPath: String, targetsJson: String, release: Boolean): String
+        @JvmStatic external fun nativeCreateProject(projectPath: String, template: String, name: String, force: Boolean): String
+        @JvmStatic external fun nativeAddCargoDependency(projectPath: String, crateName: String, version: String, featuresJson: String, dev: Boolean): String
+        @JvmStatic external fun nativeRemoveCargoDependency(projectPath: String, crateName: String, dev: Boolean): String
+        @JvmStatic external fun nativeCheckOutdated(projectPath: String): String
+        @JvmStatic external fun nativeAudit(projectPath: String): String
+        @JvmStatic external fun nativeListBuildProfiles(projectPath: String): String
+        @JvmStatic external fun nativeSetBuildConfig(configJson: String): String
+        @JvmStatic external fun nativeBuildProjectWithOptions(projectPath: String, optionsJson: String): String
+        @JvmStatic external fun nativeBuildAuto(sdkRoot: String, projectPath: String, buildType: String): String
+        @JvmStatic external fun nativeRunGradle(sdkRoot: String, projectPath: String, tasksJson: String, argsJson: String): String
+        @JvmStatic external fun nativeAdbConnect(sdkRoot: String, hostPort: String): String
+        @JvmStatic external fun nativeAdbPair(sdkRoot: String, hostPort: String, pairingCode: String): String
+        @JvmStatic external fun nativeListAvds(sdkRoot: String): String
+        @JvmStatic external fun nativeSignApk(sdkRoot: String, unsignedApk: String, keystore: String, keyAlias: String, storePass: String, keyPass: String, output: String): String
+        @JvmStatic external fun nativeDiagnoseAndroidLink(projectPath: String, target: String): String
     }
     
     private val sdkManager = SDKManager(context)
@@ -189,9 +230,10 @@ class RustNativeBuildManager(private val context: Context) {
     }
     
     /**
-     * Check build system health
+     * Check build system health. With a project path, the NDK linkers are checked at the API
+     * levels the project's .cargo/config.toml links with
      */
-    suspend fun checkBuildSystemHealth(): Map<String, Any> = withContext(Dispatchers.IO) {
+    suspend fun checkBuildSystemHealth(projectPath: String = ""): Map<String, Any> = withContext(Dispatchers.IO) {
         if (!isLibraryLoaded.get()) {
             return@withContext mapOf(
                 "status" to "unhealthy",
@@ -200,7 +242,7 @@ class RustNativeBuildManager(private val context: Context) {
         }
         
         try {
-            val healthJson = nativeCheckBuildSystemHealth()
+            val healthJson = nativeCheckBuildSystemHealth(projectPath)
             val jsonObject = JSONObject(healthJson)
             val result = mutableMapOf<String, Any>()
             
@@ -352,9 +394,15 @@ class RustNativeBuildManager(private val context: Context) {
     }.flowOn(Dispatchers.IO)
     
     /**
-     * Clean a project using the native Rust build system
+     * Clean a project using the native Rust build system. An empty target or package and a
+     * negative release flag leave that part of the clean unrestricted
      */
-    fun cleanProject(projectPath: String): Flow<BuildOutputMessage> = flow {
+    fun cleanProject(
+        projectPath: String,
+        target: String = "",
+        release: Int = -1,
+        packageName: String = ""
+    ): Flow<BuildOutputMessage> = flow {
         emit(BuildOutputMessage(BuildOutputType.INFO, "Cleaning project with Rust native build system..."))
         
         if (!isLibraryLoaded.get()) {
@@ -376,7 +424,7 @@ class RustNativeBuildManager(private val context: Context) {
             }
             
             // Call the native clean function
-            val resultJson = nativeCleanProject(projectPath)
+            val resultJson = nativeCleanProject(projectPath, target, release, packageName)
             val result = parseBuildResult(resultJson)
             
             // Emit all output messages
@@ -561,7 +609,8 @@ class RustNativeBuildManager(private val context: Context) {
      */
     fun generateAndroidBindings(
         projectPath: String,
-        packageName: String = "com.example.rustlib"
+        packageName: String = "com.example.rustlib",
+        apiLevel: Int = 21
     ): Flow<BuildOutputMessage> = flow {
         emit(BuildOutputMessage(BuildOutputType.INFO, "Generating Android bindings..."))
         
@@ -584,7 +633,7 @@ class RustNativeBuildManager(private val context: Context) {
             }
             
             // Call the native generate Android bindings function
-            val resultJson = nativeGenerateAndroidBindings(projectPath, packageName)
+            val resultJson = nativeGenerateAndroidBindings(projectPath, packageName, apiLevel)
             val result = parseBuildResult(resultJson)
             
             // Emit all output messages
@@ -58,7 +58,7 @@ class RustPluginManager(private val context: Context) {
     }
     
     /**
-     * Load a plugin
+     * Load a plugin. Fails with every problem found in its plugin.json when the manifest is invalid
      */
     suspend fun loadPlugin(pluginPath: String): Result<PluginMetadata> = withContext(Dispatchers.IO) {
         if (!isLibraryLoaded.get()) {
@@ -66,9 +66,12 @@ class RustPluginManager(private val context: Context) {
         }
         
         try {
-            val metadataJson = nativeLoadPlugin(pluginPath)
-            val metadata = parsePluginMetadata(metadataJson)
-            Result.success(metadata)
+            val loadResult = JSONObject(nativeLoadPlugin(pluginPath))
+            if (loadResult.getBoolean("success")) {
+                Result.success(parsePluginMetadata(loadResult.getJSONObject("manifest").toString()))
+            } else {
+                Result.failure(Exception(parseManifestErrors(loadResult.optJSONArray("errors"))))
+            }
         } catch (e: Exception) {
             Log.e(TAG, "Error loading plugin", e)
             Result.failure(e)
@@ -121,6 +124,9 @@ class RustPluginManager(private val context: Context) {
         try {
             val resultJson = nativeExecutePluginHook(pluginId, hookName, data)
             val result = parsePluginHookResult(resultJson)
+            if (!result.success) {
+                Log.w(TAG, "Plugin hook $hookName of $pluginId failed with status ${result.status}: ${result.error}")
+            }
             Result.success(result)
         } catch (e: Exception) {
             Log.e(TAG, "Error executing plugin hook", e)
@@ -225,62 +231,74 @@ class RustPluginManager(private val context: Context) {
     }
     
     /**
-     * Parse plugin metadata from JSON
+     * Parse plugin metadata from a plugin.json manifest. The manifest has no store listing, so
+     * download URL, screenshots, rating and download count are only filled in when present
      */
     private fun parsePluginMetadata(json: String): PluginMetadata {
         val jsonObject = JSONObject(json)
         
         val dependencies = mutableListOf<PluginDependency>()
-        val dependenciesArray = jsonObject.getJSONArray("dependencies")
+        val dependenciesArray = jsonObject.optJSONArray("dependencies") ?: JSONArray()
         for (i in 0 until dependenciesArray.length()) {
             val dependency = dependenciesArray.getJSONObject(i)
             dependencies.add(
                 PluginDependency(
                     id = dependency.getString("id"),
                     minVersion = dependency.getString("min_version"),
-                    optional = dependency.getBoolean("optional")
+                    optional = dependency.optBoolean("optional")
                 )
             )
         }
         
-        val tags = mutableListOf<String>()
-        val tagsArray = jsonObject.getJSONArray("tags")
-        for (i in 0 until tagsArray.length()) {
-            tags.add(tagsArray.getString(i))
-        }
-        
-        val extensionPoints = mutableListOf<String>()
-        val extensionPointsArray = jsonObject.getJSONArray("extension_points")
-        for (i in 0 until extensionPointsArray.length()) {
-            extensionPoints.add(extensionPointsArray.getString(i))
-        }
-        
-        val screenshots = mutableListOf<String>()
-        val screenshotsArray = jsonObject.getJSONArray("screenshots")
-        for (i in 0 until screenshotsArray.length()) {
-            screenshots.add(screenshotsArray.getString(i))
-        }
-        
         return PluginMetadata(
             id = jsonObject.getString("id"),
             name = jsonObject.getString("name"),
-            description = jsonObject.getString("description"),
+            description = jsonObject.optString("description"),
             version = jsonObject.getString("version"),
-            author = jsonObject.getString("author"),
-            category = jsonObject.getString("category"),
-            tags = tags,
-            extensionPoints = extensionPoints,
+            author = jsonObject.optString("author"),
+            category = jsonObject.optString("category"),
+            tags = parseStringArray(jsonObject.optJSONArray("tags")),
+            extensionPoints = parseStringArray(jsonObject.optJSONArray("extension_points")),
             dependencies = dependencies,
-            minIdeVersion = jsonObject.getString("min_ide_version"),
-            downloadUrl = jsonObject.getString("download_url"),
+            minIdeVersion = jsonObject.optString("min_ide_version"),
+            downloadUrl = jsonObject.optString("download_url"),
             iconUrl = if (jsonObject.has("icon_url") && !jsonObject.isNull("icon_url")) 
                 jsonObject.getString("icon_url") else null,
-            screenshots = screenshots,
-            rating = jsonObject.getDouble("rating").toFloat(),
-            downloadCount = jsonObject.getLong("download_count")
+            screenshots = parseStringArray(jsonObject.optJSONArray("screenshots")),
+            rating = jsonObject.optDouble("rating", 0.0).toFloat(),
+            downloadCount = jsonObject.optLong("download_count")
         )
     }
     
+    /**
+     * Parse a JSON array of strings, treating a missing array as empty
+     */
+    private fun parseStringArray(jsonArray: JSONArray?): List<String> {
+        val strings = mutableListOf<String>()
+        if (jsonArray != null) {
+            for (i in 0 until jsonArray.length()) {
+                strings.add(jsonArray.getString(i))
+            }
+        }
+        return strings
+    }
+    
+    /**
+     * Describe the {"field", "message"} errors of a manifest that failed to load, one per line
+     */
+    private fun parseManifestErrors(errorsArray: JSONArray?): String {
+        if (errorsArray == null || errorsArray.length() == 0) {
+            return "Invalid plugin manifest"
+        }
+        
+        val messages = mutableListOf<String>()
+        for (i in 0 until errorsArray.length()) {
+            val error = errorsArray.getJSONObject(i)
+            messages.add("${error.getString("field")}: ${error.getString("message")}")
+        }
+        return messages.joinToString("\n")
+    }
+    
     /**
      * Parse plugins from JSON
      */
@@ -308,6 +326,7 @@ class RustPluginManager(private val context: Context) {
             val result = JSONObject(json)
             return PluginHookResult(
                 success = result.getBoolean("success"),
+                status = result.optString("status", if (result.getBoolean("success")) "ok" else "hook_failed"),
                 data = result.getString("data"),
                 error = if (result.has("error") && !result.isNull("error")) result.getString("error") else null
             )
@@ -315,6 +334,7 @@ class RustPluginManager(private val context: Context) {
             Log.e(TAG, "Error parsing plugin hook result JSON", e)
             return PluginHookResult(
                 success = false,
+                status = "hook_failed",
                 data = "",
                 error = "Failed to parse result: ${e.message}"
             )
@@ -352,10 +372,13 @@ class RustPluginManager(private val context: Context) {
     )
     
     /**
-     * Plugin hook result
+     * Plugin hook result. `status` is "ok" or why the hook didn't run or failed: "plugin_not_found",
+     * "plugin_disabled", "unknown_hook", "hook_not_registered", "invalid_payload", "hook_failed"
+     * or "timed_out"
      */
     data class PluginHookResult(
         val success: Boolean,
+        val status: String,
         val data: String,
         val error: String?
     )
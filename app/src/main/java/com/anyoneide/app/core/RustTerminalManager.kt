@@ -40,16 +40,34 @@ class RustTerminalManager(private val context: Context) {
         
         // Native method declarations
         @JvmStatic external fun nativeExecuteCommand(command: String, workingDir: String): String
-        @JvmStatic external fun nativeExecuteRootCommand(command: String): String
+        @JvmStatic external fun nativeExecuteRootCommand(command: String, confirmed: Boolean): String
         @JvmStatic external fun nativeIsRootAvailable(): Boolean
         @JvmStatic external fun nativeGetTerminalInfo(): String
         @JvmStatic external fun nativeCreateSession(workingDir: String): String
         @JvmStatic external fun nativeCloseSession(sessionId: String): Boolean
         @JvmStatic external fun nativeGetEnvironmentVariables(): String
-        @JvmStatic external fun nativeSetEnvironmentVariable(name: String, value: String): Boolean
+        @JvmStatic external fun nativeSetEnvironmentVariable(sessionId: String, name: String, value: String): Boolean
         @JvmStatic external fun nativeGetWorkingDirectory(sessionId: String): String
         @JvmStatic external fun nativeChangeDirectory(sessionId: String, directory: String): Boolean
         @JvmStatic external fun nativeStopCommand(sessionId: String): Boolean
+        @JvmStatic external fun nativePeekOutput(sessionId: String): String
+        @JvmStatic external fun nativeGetProcessTree(): String
+        @JvmStatic external fun nativeKillProcessTree(pid: Int): Boolean
+        @JvmStatic external fun nativeParseAnsi(input: String): String
+        @JvmStatic external fun nativeStartLogcat(sdkRoot: String, deviceSerial: String, filter: String, parsed: Boolean): String
+        @JvmStatic external fun nativeSearchHistory(sessionId: String, query: String): String
+        @JvmStatic external fun nativeSetHistoryOptions(sessionId: String, maxEntries: Int, skipDuplicates: Boolean): Boolean
+        @JvmStatic external fun nativeSetGlobalEnv(name: String, value: String): Boolean
+        @JvmStatic external fun nativeSetRootPolicy(policyJson: String): String
+        @JvmStatic external fun nativeRefreshRootStatus(): Boolean
+        @JvmStatic external fun nativeExecuteCommandWithInput(command: String, workingDir: String, stdinData: String): String
+        @JvmStatic external fun nativeSetOutputLimit(maxBytes: Long)
+        @JvmStatic external fun nativeLaunchAvd(sdkRoot: String, avdName: String, optionsJson: String): String
+        @JvmStatic external fun nativeStopAvd(sessionId: String): Boolean
+        @JvmStatic external fun nativeExecuteInSession(sessionId: String, command: String): String
+        @JvmStatic external fun nativeSafeRemove(path: String, recursive: Boolean): String
+        @JvmStatic external fun nativeSetRemoveGuardPolicy(policyJson: String): String
+        @JvmStatic external fun nativeExecuteCommandWithEncoding(command: String, workingDir: String, encoding: String): String
     }
     
     private val sdkManager = SDKManager(context)
@@ -83,9 +101,10 @@ class RustTerminalManager(private val context: Context) {
     }
     
     /**
-     * Execute a command in a terminal session
+     * Execute a command in a terminal session. A root command the policy wants confirmed first is
+     * not run; send it again with `confirmRoot = true` once the user has confirmed it
      */
-    suspend fun executeCommand(sessionId: String, command: String): Flow<TerminalOutputInternal> = flow {
+    suspend fun executeCommand(sessionId: String, command: String, confirmRoot: Boolean = false): Flow<TerminalOutputInternal> = flow {
         if (!isLibraryLoaded.get()) {
             fallbackTerminalManager.executeCommand(sessionId, command).collect { output ->
                 emit(output)
@@ -130,9 +149,16 @@ class RustTerminalManager(private val context: Context) {
                     // Root command
                     val rootCommand = if (command == "su") "" else command.substring(3)
                     try {
-                        val output = nativeExecuteRootCommand(rootCommand)
-                        parseCommandOutput(output).forEach { line ->
-                            emit(TerminalOutputInternal(TerminalOutputType.STDOUT, line))
+                        val result = parseRootCommandResult(nativeExecuteRootCommand(rootCommand, confirmRoot))
+                        if (result.needsConfirmation) {
+                            emit(TerminalOutputInternal(TerminalOutputType.SYSTEM, "Root command requires confirmation: $rootCommand"))
+                        } else {
+                            result.output.forEach { line ->
+                                emit(TerminalOutputInternal(TerminalOutputType.STDOUT, line))
+                            }
+                            result.errorOutput.forEach { line ->
+                                emit(TerminalOutputInternal(TerminalOutputType.STDERR, line))
+                            }
                         }
                     } catch (e: Exception) {
                         emit(TerminalOutputInternal(TerminalOutputType.STDERR, "Root execution error: ${e.message}"))
@@ -252,15 +278,15 @@ class RustTerminalManager(private val context: Context) {
     }
     
     /**
-     * Set an environment variable
+     * Set an environment variable in a session
      */
-    suspend fun setEnvironmentVariable(name: String, value: String): Result<Boolean> = withContext(Dispatchers.IO) {
+    suspend fun setEnvironmentVariable(sessionId: String, name: String, value: String): Result<Boolean> = withContext(Dispatchers.IO) {
         if (!isLibraryLoaded.get()) {
             return@withContext Result.failure(Exception("Native terminal not available"))
         }
         
         try {
-            val success = nativeSetEnvironmentVariable(name, value)
+            val success = nativeSetEnvironmentVariable(sessionId, name, value)
             Result.success(success)
         } catch (e: Exception) {
             Log.e(TAG, "Error setting environment variable", e)
@@ -292,6 +318,40 @@ class RustTerminalManager(private val context: Context) {
         }
     }
     
+    /**
+     * Execute a root command. When the result needs confirmation nothing was run; call again with
+     * `confirmed = true` after the user has confirmed the command
+     */
+    suspend fun executeRootCommand(command: String, confirmed: Boolean = false): Result<RootCommandResult> = withContext(Dispatchers.IO) {
+        if (!isLibraryLoaded.get()) {
+            return@withContext Result.failure(Exception("Native terminal library not loaded"))
+        }
+        
+        try {
+            Result.success(parseRootCommandResult(nativeExecuteRootCommand(command, confirmed)))
+        } catch (e: Exception) {
+            Log.e(TAG, "Error executing root command", e)
+            Result.failure(e)
+        }
+    }
+    
+    /**
+     * Parse the JSON result of a root command
+     */
+    private fun parseRootCommandResult(json: String): RootCommandResult {
+        val result = org.json.JSONObject(json)
+        fun lines(name: String): List<String> =
+            result.optJSONArray(name)?.let { array -> List(array.length()) { array.getString(it) } } ?: emptyList()
+        
+        return RootCommandResult(
+            success = result.optBoolean("success", false),
+            output = lines("output"),
+            errorOutput = lines("error_output"),
+            exitCode = result.optInt("exit_code", -1),
+            needsConfirmation = result.optBoolean("needs_confirmation", false)
+        )
+    }
+    
     /**
      * Parse command output into lines
      */
@@ -320,4 +380,16 @@ class RustTerminalManager(private val context: Context) {
             mapOf("error" to "Failed to parse JSON: ${e.message}")
         }
     }
+    
+    /**
+     * Result of a root command. `needsConfirmation` means the command was not run and has to be sent
+     * again confirmed
+     */
+    data class RootCommandResult(
+        val success: Boolean,
+        val output: List<String>,
+        val errorOutput: List<String>,
+        val exitCode: Int,
+        val needsConfirmation: Boolean
+    )
 }
\ No newline at end of file
@@ -1,9 +1,101 @@
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Command, Stdio, Child, ExitStatus};
 use std::io::{BufRead, BufReader};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
 use serde::{Serialize, Deserialize};
 use anyhow::{Result, anyhow};
+use lazy_static::lazy_static;
+use regex::Regex;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use uuid::Uuid;
+use crate::sdkmanager::SdkManager;
+
+// Explicit toolchain for builds, for layouts where cargo and rustc are not on PATH
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ToolchainPaths {
+    pub cargo: PathBuf,
+    pub rustc: PathBuf,
+    pub ndk_home: Option<PathBuf>,
+    pub env: Vec<(String, String)>,
+}
+
+impl ToolchainPaths {
+    // Use the toolchain managed by the SDK manager
+    pub fn from_sdk_manager(sdk_manager: &SdkManager) -> Self {
+        ToolchainPaths {
+            cargo: sdk_manager.get_cargo_path(),
+            rustc: sdk_manager.get_rustc_path(),
+            ndk_home: Some(sdk_manager.get_ndk_path()).filter(|_| sdk_manager.is_ndk_installed()),
+            env: sdk_manager.rust_environment(),
+        }
+    }
+    
+    // Create a cargo command that runs entirely on this toolchain
+    fn cargo_command(&self) -> Command {
+        let mut cmd = Command::new(&self.cargo);
+        cmd.env("CARGO", &self.cargo);
+        cmd.env("RUSTC", &self.rustc);
+        
+        // Put the toolchain first on PATH so rustdoc, build scripts and proc macros resolve the same tools
+        let mut paths: Vec<PathBuf> = self.cargo.parent().into_iter().map(Path::to_path_buf).collect();
+        if let Some(path) = std::env::var_os("PATH") {
+            paths.extend(std::env::split_paths(&path));
+        }
+        if let Ok(path) = std::env::join_paths(paths) {
+            cmd.env("PATH", path);
+        }
+        
+        if let Some(ndk_home) = &self.ndk_home {
+            cmd.env("ANDROID_NDK_HOME", ndk_home);
+        }
+        
+        for (key, value) in &self.env {
+            cmd.env(key, value);
+        }
+        
+        cmd
+    }
+    
+    // Create a command for the rustup installed next to this toolchain's cargo
+    fn rustup_command(&self) -> Command {
+        let rustup_name = if cfg!(windows) { "rustup.exe" } else { "rustup" };
+        let mut cmd = Command::new(self.cargo.with_file_name(rustup_name));
+        
+        for (key, value) in &self.env {
+            cmd.env(key, value);
+        }
+        
+        cmd
+    }
+}
+
+// What cargo clean should remove; the default removes everything
+#[derive(Serialize, Deserialize, Default)]
+pub struct CleanOptions {
+    pub target: Option<String>,
+    // Some(true) cleans only the release profile, Some(false) only the dev profile
+    pub release: Option<bool>,
+    pub package: Option<String>,
+}
+
+// Options for build_project_with_options; fields left out of the JSON take their defaults
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct BuildOptions {
+    // "debug", "release" or any profile from list_build_profiles; None builds the dev profile
+    pub profile: Option<String>,
+    // Target triple to cross-compile for, e.g. "aarch64-linux-android"
+    pub target: Option<String>,
+    // Report the cargo command that would run, with its working directory and environment, without running it
+    pub dry_run: bool,
+    // Lines of process output kept in the result, 0 for no limit; None uses BuildConfig::max_output_lines
+    pub max_output_lines: Option<u32>,
+}
 
 // Build system status
 #[derive(Serialize, Deserialize)]
@@ -17,15 +109,30 @@ pub struct BuildSystemStatus {
     pub build_config: BuildConfig,
 }
 
-// Build configuration
-#[derive(Serialize, Deserialize)]
+// Build configuration, changed with set_build_config
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct BuildConfig {
     pub timeout_seconds: u32,
+    // Lines of process output kept in a build's result when its BuildOptions don't say, 0 for no limit
     pub max_output_lines: u32,
     pub enable_verbose_output: bool,
+    // When a build fails, suggest `rustup target add` for a missing target or otherwise build
+    // again with --keep-going to surface every error; when off, builds stop at the first failure
     pub enable_error_recovery: bool,
 }
 
+impl Default for BuildConfig {
+    fn default() -> Self {
+        BuildConfig {
+            timeout_seconds: 300,
+            max_output_lines: 10000,
+            enable_verbose_output: true,
+            enable_error_recovery: true,
+        }
+    }
+}
+
 // Project information
 #[derive(Serialize, Deserialize)]
 pub struct ProjectInfo {
@@ -62,17 +169,490 @@ pub struct BuildResult {
     pub success: bool,
     pub output_messages: Vec<OutputMessage>,
     pub duration_ms: u64,
+    // Paths only, kept for existing callers; artifact_details carries kind and size
     pub artifacts: Vec<String>,
+    pub artifact_details: Vec<Artifact>,
+    pub test_summary: Option<TestSummary>,
+    pub test_cases: Vec<TestCaseResult>,
+    // Compiler errors and failed tasks parsed from Gradle output; empty for cargo builds
+    pub diagnostics: Vec<Diagnostic>,
 }
 
-// Output message
+// Results of building several Android targets in one go
+#[derive(Serialize, Deserialize)]
+pub struct AggregateBuildResult {
+    // True only when every target built
+    pub success: bool,
+    pub duration_ms: u64,
+    pub targets: Vec<TargetBuildResult>,
+    pub failed_targets: Vec<String>,
+}
+
+// One target's build within an AggregateBuildResult; its duration is result.duration_ms
+#[derive(Serialize, Deserialize)]
+pub struct TargetBuildResult {
+    pub target: String,
+    // ABI directory name for the standard Android triples
+    pub abi: Option<String>,
+    pub result: BuildResult,
+}
+
+// Build output file, classified so the UI can offer "Run" for binaries and "Copy to jniLibs" for .so files
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Artifact {
+    pub path: String,
+    // "bin", "cdylib", "staticlib", "example", "test" or "apk"
+    pub kind: String,
+    pub size_bytes: u64,
+}
+
+// Where the time of a build went
+#[derive(Serialize, Deserialize)]
+pub struct BuildReport {
+    pub success: bool,
+    pub total_ms: u64,
+    // Crates compiled by the build, slowest first
+    pub per_crate: Vec<CrateTiming>,
+    pub artifact_sizes: Vec<Artifact>,
+    // "json" or "html" for the cargo timing data used, "none" when only the total is known
+    pub timing_source: String,
+    pub output_messages: Vec<OutputMessage>,
+}
+
+// Compile time of one crate, summed over its build script, library and binaries
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CrateTiming {
+    pub name: String,
+    pub ms: u64,
+}
+
+// Result of checking a project's dependencies for newer releases
+#[derive(Serialize, Deserialize)]
+pub struct OutdatedReport {
+    pub dependencies: Vec<OutdatedDep>,
+    // Set when crates.io could not be reached; the list is then empty
+    pub offline: bool,
+    // "cargo-outdated" or "crates.io", "none" when nothing was checked
+    pub source: String,
+    pub error: Option<String>,
+}
+
+// A direct dependency with a newer release available
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OutdatedDep {
+    pub name: String,
+    pub current: String,
+    pub latest: String,
+    // "normal", "dev" or "build"
+    pub kind: String,
+}
+
+// Result of running cargo audit on a project
+#[derive(Serialize, Deserialize)]
+pub struct AuditReport {
+    // False when cargo-audit is not installed; `error` then says how to install it
+    pub installed: bool,
+    pub advisories: Vec<Advisory>,
+    // Advisory entries that could not be read from cargo audit's output
+    pub skipped: usize,
+    pub error: Option<String>,
+}
+
+// A RustSec advisory affecting a crate in the lock file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Advisory {
+    pub crate_name: String,
+    pub version: String,
+    pub advisory_id: String,
+    // "critical", "high", "medium", "low" or "none" from the CVSS score, "unknown" for
+    // vulnerabilities without one, or the warning kind, e.g. "unmaintained" or "unsound"
+    pub severity: String,
+    pub title: String,
+    pub url: String,
+}
+
+// Counts from cargo's "test result:" summary lines, summed over every test binary
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct TestSummary {
+    pub passed: u32,
+    pub failed: u32,
+    pub ignored: u32,
+}
+
+// Outcome of a single test, from cargo test's "test path::to::test ... ok" lines
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TestCaseResult {
+    pub name: String,
+    pub status: String,
+    pub duration_ms: Option<u64>,
+}
+
+// Result of a cargo check run
 #[derive(Serialize, Deserialize)]
+pub struct CheckResult {
+    pub success: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    pub output_messages: Vec<OutputMessage>,
+    pub duration_ms: u64,
+}
+
+// Compiler diagnostic from cargo's JSON message format
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: String,
+    pub message: String,
+    pub code: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub end_line: Option<u32>,
+    pub end_column: Option<u32>,
+    pub rendered: Option<String>,
+}
+
+// Output message
+#[derive(Serialize, Deserialize, Clone)]
 pub struct OutputMessage {
     pub message_type: String,
     pub content: String,
     pub timestamp: u64,
 }
 
+// Rust target triple for an Android ABI, and whether rustup already has its standard library
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AndroidTarget {
+    pub triple: String,
+    // ABI directory name under jniLibs, e.g. "arm64-v8a"
+    pub abi: String,
+    pub min_api: u32,
+    pub rustup_installed: bool,
+}
+
+// Android device as listed by `adb devices -l`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AdbDevice {
+    pub serial: String,
+    pub state: String, // device, offline, unauthorized, ...
+    pub model: Option<String>,
+}
+
+// Outcome of `adb pair` or `adb connect`. `message` is adb's confirmation, or everything it
+// printed when the attempt failed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AdbConnectionResult {
+    pub success: bool,
+    pub host_port: String,
+    pub message: String,
+}
+
+impl AdbConnectionResult {
+    fn failed(host_port: &str, message: &str) -> Self {
+        AdbConnectionResult {
+            success: false,
+            host_port: host_port.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+// State of a background build
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum BuildState {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+// Status of a background build, as returned to pollers
+#[derive(Serialize, Deserialize)]
+pub struct BuildStatus {
+    pub build_id: String,
+    pub state: BuildState,
+    pub result: Option<BuildResult>,
+}
+
+// New output from a background build since a poller's cursor
+#[derive(Serialize, Deserialize)]
+pub struct BuildOutputChunk {
+    pub build_id: String,
+    pub messages: Vec<OutputMessage>,
+    pub next_index: usize,
+    pub finished: bool,
+    pub success: Option<bool>,
+    pub cancelled: bool,
+    pub elapsed_ms: u64,
+}
+
+// Collects a build's output as it is produced and lets another thread cancel it by killing its cargo process
+#[derive(Default)]
+pub struct BuildControl {
+    child: Mutex<Option<Child>>,
+    cancelled: AtomicBool,
+    // What the build reports, with process output cut off at the cap
+    output: Mutex<Vec<OutputMessage>>,
+    // Output past the cap that the build's results are parsed from
+    result_lines: Mutex<ResultLines>,
+    output_cap: OutputCap,
+}
+
+impl BuildControl {
+    fn with_output_cap(output_cap: OutputCap) -> Self {
+        BuildControl {
+            output_cap,
+            ..BuildControl::default()
+        }
+    }
+    
+    // Request cancellation, killing the process if it has already started
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        
+        if let Some(child) = self.child.lock().unwrap().as_mut() {
+            let _ = child.kill();
+        }
+    }
+    
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+    
+    // Record an output message
+    fn push(&self, message: OutputMessage) {
+        self.output.lock().unwrap().push(message);
+    }
+    
+    // Record a line of the process's output, which is only reported while under the cap
+    fn push_output(&self, message: OutputMessage) {
+        let mut output = self.output.lock().unwrap();
+        if let Some(message) = self.output_cap.push(&mut output, message) {
+            self.result_lines.lock().unwrap().keep(output.len(), message);
+        }
+    }
+    
+    // Snapshot of the output to report so far
+    fn messages(&self) -> Vec<OutputMessage> {
+        self.output.lock().unwrap().clone()
+    }
+    
+    // Snapshot of the output so far with the lines past the cap that results are parsed from
+    fn result_messages(&self) -> Vec<OutputMessage> {
+        let output = self.output.lock().unwrap();
+        self.result_lines.lock().unwrap().merged_with(&output)
+    }
+    
+    // Output recorded at or after an index
+    fn messages_since(&self, index: usize) -> Vec<OutputMessage> {
+        let output = self.output.lock().unwrap();
+        output.get(index..).map(|messages| messages.to_vec()).unwrap_or_default()
+    }
+    
+    // Take ownership of the spawned process, killing it straight away if cancel arrived first
+    fn attach(&self, mut child: Child) {
+        if self.is_cancelled() {
+            let _ = child.kill();
+        }
+        
+        *self.child.lock().unwrap() = Some(child);
+    }
+    
+    // Wait for the attached process to exit. The process stays attached while it runs, so
+    // polling rather than blocking in Child::wait leaves cancel able to kill it
+    fn wait(&self) -> std::io::Result<ExitStatus> {
+        loop {
+            {
+                let mut child = self.child.lock().unwrap();
+                let process = child
+                    .as_mut()
+                    .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No build process attached"))?;
+                
+                if let Some(status) = process.try_wait()? {
+                    *child = None;
+                    return Ok(status);
+                }
+            }
+            
+            thread::sleep(BUILD_WAIT_POLL_INTERVAL);
+        }
+    }
+}
+
+// How often a build's process is checked for exit while waiting on it
+const BUILD_WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Limits how many lines of process output a build reports, see BuildOptions::max_output_lines.
+// Later lines must still be read so the process never blocks on a full pipe, but they are left
+// out of the result, apart from those ResultLines keeps for parsing diagnostics and test counts
+struct OutputCap {
+    limit: usize,
+    lines: AtomicUsize,
+}
+
+impl Default for OutputCap {
+    fn default() -> Self {
+        OutputCap::new(None)
+    }
+}
+
+impl OutputCap {
+    fn new(max_output_lines: Option<u32>) -> Self {
+        OutputCap {
+            limit: max_output_lines.unwrap_or_else(|| build_config().max_output_lines) as usize,
+            lines: AtomicUsize::new(0),
+        }
+    }
+    
+    // Append an output line to a build's messages while under the limit, and a single truncation
+    // notice once it is reached. Lines past the limit are handed back instead
+    fn push(&self, messages: &mut Vec<OutputMessage>, message: OutputMessage) -> Option<OutputMessage> {
+        let index = self.lines.fetch_add(1, Ordering::SeqCst);
+        
+        if self.limit == 0 || index < self.limit {
+            messages.push(message);
+            return None;
+        }
+        
+        if index == self.limit {
+            messages.push(OutputMessage {
+                message_type: "WARNING".to_string(),
+                content: format!("Output truncated after {} lines; raise max_output_lines to keep more", self.limit),
+                timestamp: message.timestamp,
+            });
+        }
+        Some(message)
+    }
+}
+
+// The lines of process output past the cap that a build's results are parsed from: cargo's JSON
+// diagnostics and timings, test results, and Gradle and compiler diagnostics. Keeping only these
+// bounds memory however much a build prints
+#[derive(Default)]
+struct ResultLines {
+    // Each line with the number of messages reported before it, to merge them back in order
+    lines: Vec<(usize, OutputMessage)>,
+    // Lines still to keep after one that starts a multi-line diagnostic, e.g. javac's source line and caret
+    following: usize,
+    // Inside Gradle's "* What went wrong:" summary, which runs to a blank line or the next section
+    in_summary: bool,
+}
+
+impl ResultLines {
+    // Keep a line past the cap if results are parsed from it
+    fn keep(&mut self, reported: usize, message: OutputMessage) {
+        if self.is_result_line(&message.content) {
+            self.lines.push((reported, message));
+        }
+    }
+    
+    fn is_result_line(&mut self, line: &str) -> bool {
+        let trimmed = line.trim();
+        
+        if self.following > 0 {
+            self.following -= 1;
+            return true;
+        }
+        if self.in_summary {
+            // The line that ends the summary is kept too, so it still ends where it did
+            self.in_summary = !trimmed.is_empty() && !line.starts_with("* ");
+            return true;
+        }
+        
+        if trimmed == "* What went wrong:" {
+            self.in_summary = true;
+            return true;
+        }
+        if JAVAC_DIAGNOSTIC_REGEX.is_match(trimmed) {
+            self.following = 2;
+            return true;
+        }
+        
+        (trimmed.starts_with('{') && (trimmed.contains("\"compiler-message\"") || trimmed.contains("\"timing-info\"")))
+            || (trimmed.starts_with("test ") && trimmed.contains(" ... "))
+            || trimmed.contains("test result:")
+            || trimmed.starts_with("Running ")
+            || (trimmed.starts_with("> Task ") && trimmed.ends_with(" FAILED"))
+            || trimmed.starts_with("BUILD FAILED")
+            || trimmed.contains("target may not be installed")
+            || trimmed.contains("--timings")
+            || trimmed.contains("Failure [")
+            || KOTLIN_DIAGNOSTIC_REGEX.is_match(trimmed)
+            || KOTLIN_LEGACY_DIAGNOSTIC_REGEX.is_match(trimmed)
+    }
+    
+    // The reported messages with the kept lines merged back where they were produced
+    fn merged_with(&self, reported: &[OutputMessage]) -> Vec<OutputMessage> {
+        let mut merged = Vec::with_capacity(reported.len() + self.lines.len());
+        let mut kept = self.lines.iter().peekable();
+        
+        for (index, message) in reported.iter().enumerate() {
+            while let Some((_, line)) = kept.next_if(|(position, _)| *position <= index) {
+                merged.push(line.clone());
+            }
+            merged.push(message.clone());
+        }
+        merged.extend(kept.map(|(_, line)| line.clone()));
+        
+        merged
+    }
+}
+
+// A build started in the background
+struct BackgroundBuild {
+    control: Arc<BuildControl>,
+    result: Arc<Mutex<Option<BuildResult>>>,
+    start_time: Instant,
+}
+
+// How long the sources must be quiet before a watch rebuild starts
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+// A watch-mode session that rebuilds whenever the project's sources change
+struct BuildWatch {
+    // Dropping the watcher closes its event channel, which ends the watch thread
+    _watcher: RecommendedWatcher,
+    stopped: Arc<AtomicBool>,
+    building: Arc<AtomicBool>,
+    control: Arc<Mutex<Arc<BuildControl>>>,
+    results: Arc<Mutex<Vec<BuildResult>>>,
+}
+
+// Builds finished by a watch since it was last polled
+#[derive(Serialize, Deserialize)]
+pub struct WatchStatus {
+    pub watch_id: String,
+    pub building: bool,
+    pub results: Vec<BuildResult>,
+}
+
+// Alias and passwords of the standard Android debug key
+const DEBUG_KEY_ALIAS: &str = "androiddebugkey";
+const DEBUG_KEYSTORE_PASSWORD: &str = "android";
+
+lazy_static! {
+    // Background builds keyed by build id; finished builds are removed once their result or last output is polled
+    static ref BACKGROUND_BUILDS: Mutex<HashMap<String, BackgroundBuild>> = Mutex::new(HashMap::new());
+    
+    // Watch-mode sessions keyed by watch id, removed by stop_watch
+    static ref BUILD_WATCHES: Mutex<HashMap<String, BuildWatch>> = Mutex::new(HashMap::new());
+    
+    static ref BUILD_CONFIG: Mutex<BuildConfig> = Mutex::new(BuildConfig::default());
+    
+    // Gradle compiler output, see parse_gradle_output
+    static ref KOTLIN_DIAGNOSTIC_REGEX: Regex = Regex::new(r"^([ew]): (.+?):(\d+):(\d+) (.*)$").unwrap();
+    static ref KOTLIN_LEGACY_DIAGNOSTIC_REGEX: Regex = Regex::new(r"^([ew]): (.+?): \((\d+), (\d+)\): (.*)$").unwrap();
+    static ref JAVAC_DIAGNOSTIC_REGEX: Regex = Regex::new(r"^(?:ERROR:\s*)?(.+?):(\d+):(?:(\d+):)? (?:AAPT: )?(error|warning): (.*)$").unwrap();
+}
+
+// The current build configuration
+pub fn build_config() -> BuildConfig {
+    BUILD_CONFIG.lock().unwrap().clone()
+}
+
+// Replace the build configuration; builds already running keep the settings they started with
+pub fn set_build_config(config: BuildConfig) {
+    *BUILD_CONFIG.lock().unwrap() = config;
+}
+
 // Get build system status
 pub fn get_build_system_status() -> BuildSystemStatus {
     let rust_version = match Command::new("rustc").arg("--version").output() {
@@ -101,12 +681,7 @@ pub fn get_build_system_status() -> BuildSystemStatus {
         ],
         os_info,
         ndk_installed: is_ndk_installed(),
-        build_config: BuildConfig {
-            timeout_seconds: 300,
-            max_output_lines: 10000,
-            enable_verbose_output: true,
-            enable_error_recovery: true,
-        },
+        build_config: build_config(),
     }
 }
 
@@ -149,12 +724,13 @@ pub fn get_project_info(project_path: &str) -> ProjectInfo {
     }
 }
 
-// Check build system health
-pub fn check_build_system_health() -> HealthCheck {
+// Check build system health. With a project, the NDK linkers are checked at the API levels its
+// .cargo/config.toml links with
+pub fn check_build_system_health(project_path: Option<&str>) -> HealthCheck {
     let rust_installed = Command::new("rustc").arg("--version").output().is_ok();
     let cargo_installed = Command::new("cargo").arg("--version").output().is_ok();
     
-    let checks = vec![
+    let mut checks = vec![
         Check {
             name: "Rust Installation".to_string(),
             status: if rust_installed { "passed" } else { "failed" },
@@ -184,6 +760,10 @@ pub fn check_build_system_health() -> HealthCheck {
         },
     ];
     
+    if let Some(check) = ndk_compatibility_check(project_path.map(Path::new)) {
+        checks.push(check);
+    }
+    
     let status = if rust_installed && cargo_installed {
         "healthy"
     } else {
@@ -201,225 +781,525 @@ pub fn check_build_system_health() -> HealthCheck {
     }
 }
 
-// Build project
-pub fn build_project(project_path: &str, build_type: &str) -> BuildResult {
-    let start_time = Instant::now();
-    let mut output_messages = Vec::new();
-    let mut artifacts = Vec::new();
+// Build project; `build_type` is "debug", "release" or any profile from list_build_profiles
+pub fn build_project(project_path: &str, build_type: &str, toolchain: Option<&ToolchainPaths>) -> BuildResult {
+    run_build(project_path, build_type, toolchain, &BuildControl::default(), false)
+}
+
+// Build a project with the given options
+pub fn build_project_with_options(project_path: &str, options: &BuildOptions, toolchain: Option<&ToolchainPaths>) -> BuildResult {
+    let build_type = options.profile.as_deref().unwrap_or("debug");
     
-    // Add initial message
-    output_messages.push(OutputMessage {
-        message_type: "INFO".to_string(),
-        content: format!("Building project with type: {}", build_type),
-        timestamp: current_time_millis(),
-    });
+    let output_cap = OutputCap::new(options.max_output_lines);
     
-    // Check if project exists
-    let project_dir = Path::new(project_path);
-    if !project_dir.exists() || !project_dir.is_dir() {
-        output_messages.push(OutputMessage {
-            message_type: "ERROR".to_string(),
-            content: format!("Project directory does not exist: {}", project_path),
-            timestamp: current_time_millis(),
-        });
-        
-        return BuildResult {
-            success: false,
-            output_messages,
-            duration_ms: start_time.elapsed().as_millis() as u64,
-            artifacts,
-        };
+    match &options.target {
+        Some(target) => run_target_build(project_path, target, build_type, options.dry_run, output_cap, toolchain),
+        None => run_build(project_path, build_type, toolchain, &BuildControl::with_output_cap(output_cap), options.dry_run),
     }
+}
+
+// Build whatever kind of project get_project_info detects: Cargo for "rust", the Gradle assemble
+// task for "gradle", and for "hybrid" the Rust library for every Android ABI, copied into
+// app/src/main/jniLibs, and then the app that packages it. `build_type` is "debug", "release" or
+// a Cargo profile; Android targets and Gradle build release only for "release"
+pub fn build_auto(project_path: &str, build_type: &str, sdk_manager: &SdkManager, toolchain: Option<&ToolchainPaths>) -> BuildResult {
+    let gradle_task = if build_type == "release" { "assembleRelease" } else { "assembleDebug" };
     
-    // Check if Cargo.toml exists
-    let cargo_toml_path = project_dir.join("Cargo.toml");
-    if !cargo_toml_path.exists() {
-        output_messages.push(OutputMessage {
-            message_type: "ERROR".to_string(),
-            content: "Cargo.toml not found. Not a valid Rust project.".to_string(),
-            timestamp: current_time_millis(),
-        });
-        
-        return BuildResult {
+    match get_project_info(project_path).project_type.as_str() {
+        "rust" => build_project(project_path, build_type, toolchain),
+        "gradle" => run_gradle(project_path, vec![gradle_task.to_string()], Vec::new(), sdk_manager),
+        "hybrid" => {
+            let mut result = build_jni_libs(project_path, build_type == "release", toolchain);
+            if !result.success {
+                return result;
+            }
+            
+            let gradle_result = run_gradle(project_path, vec![gradle_task.to_string()], Vec::new(), sdk_manager);
+            result.success = gradle_result.success;
+            result.duration_ms += gradle_result.duration_ms;
+            result.output_messages.extend(gradle_result.output_messages);
+            result.artifacts.extend(gradle_result.artifacts);
+            result.artifact_details.extend(gradle_result.artifact_details);
+            result.diagnostics.extend(gradle_result.diagnostics);
+            result
+        }
+        _ => BuildResult {
             success: false,
-            output_messages,
-            duration_ms: start_time.elapsed().as_millis() as u64,
-            artifacts,
-        };
+            output_messages: vec![OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: "Neither Cargo.toml nor build.gradle found; can't tell how to build this project".to_string(),
+                timestamp: current_time_millis(),
+            }],
+            duration_ms: 0,
+            artifacts: Vec::new(),
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
+        },
     }
+}
+
+// Build the Rust library for every Android target and copy each ABI's .so into the app
+// module's jniLibs, where Gradle packages it from
+fn build_jni_libs(project_path: &str, release: bool, toolchain: Option<&ToolchainPaths>) -> BuildResult {
+    let aggregate = build_all_android_targets(project_path, Vec::new(), release, toolchain);
+    let jni_libs_dir = Path::new(project_path).join("app").join("src").join("main").join("jniLibs");
     
-    // Build command
-    let mut cmd = Command::new("cargo");
-    cmd.current_dir(project_dir);
+    let mut result = BuildResult {
+        success: aggregate.success,
+        output_messages: Vec::new(),
+        duration_ms: aggregate.duration_ms,
+        artifacts: Vec::new(),
+        artifact_details: Vec::new(),
+        test_summary: None,
+        test_cases: Vec::new(),
+        diagnostics: Vec::new(),
+    };
     
-    match build_type {
-        "release" => {
-            cmd.arg("build");
-            cmd.arg("--release");
-        }
-        "debug" => {
-            cmd.arg("build");
-        }
-        _ => {
-            cmd.arg("build");
-            cmd.arg(build_type);
+    for TargetBuildResult { abi, result: target_result, .. } in aggregate.targets {
+        result.output_messages.extend(target_result.output_messages);
+        result.diagnostics.extend(target_result.diagnostics);
+        
+        let abi = match abi {
+            Some(abi) if target_result.success => abi,
+            _ => continue,
+        };
+        
+        for artifact in target_result.artifact_details {
+            if artifact.kind != "cdylib" || !artifact.path.ends_with(".so") {
+                continue;
+            }
+            
+            let source = Path::new(&artifact.path);
+            let destination = jni_libs_dir.join(&abi).join(source.file_name().unwrap_or_default());
+            let copied = std::fs::create_dir_all(jni_libs_dir.join(&abi)).and_then(|_| std::fs::copy(source, &destination));
+            
+            match copied {
+                Ok(_) => {
+                    result.output_messages.push(OutputMessage {
+                        message_type: "INFO".to_string(),
+                        content: format!("Copied {} to {}", artifact.path, destination.display()),
+                        timestamp: current_time_millis(),
+                    });
+                    result.artifacts.push(destination.to_string_lossy().to_string());
+                    result.artifact_details.push(artifact_for(&destination, "cdylib"));
+                }
+                Err(e) => {
+                    result.success = false;
+                    result.output_messages.push(OutputMessage {
+                        message_type: "ERROR".to_string(),
+                        content: format!("Failed to copy {} to {}: {}", artifact.path, destination.display(), e),
+                        timestamp: current_time_millis(),
+                    });
+                }
+            }
         }
     }
     
-    // Execute command
-    output_messages.push(OutputMessage {
-        message_type: "INFO".to_string(),
-        content: format!("Running: {:?}", cmd),
-        timestamp: current_time_millis(),
-    });
+    result
+}
+
+// Run Gradle tasks, e.g. ["assembleDebug"], ["test"] or ["clean"], with extra command line
+// arguments. Uses the project's wrapper when it has one, otherwise the SDK manager's Gradle. The
+// build only succeeds if Gradle exits cleanly without reporting BUILD FAILED; APKs are collected
+// when an assemble task ran
+pub fn run_gradle(project_path: &str, tasks: Vec<String>, args: Vec<String>, sdk_manager: &SdkManager) -> BuildResult {
+    let start_time = Instant::now();
+    let control = BuildControl::default();
+    let project_dir = Path::new(project_path);
+    let task_list = if tasks.is_empty() { "default tasks".to_string() } else { tasks.join(" ") };
     
-    match cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
-        Ok(mut child) => {
-            // Read stdout
-            if let Some(stdout) = child.stdout.take() {
-                let reader = BufReader::new(stdout);
-                for line in reader.lines() {
-                    if let Ok(line) = line {
-                        let message_type = if line.contains("error") {
-                            "ERROR"
-                        } else if line.contains("warning") {
-                            "WARNING"
-                        } else {
-                            "INFO"
-                        };
-                        
-                        output_messages.push(OutputMessage {
-                            message_type: message_type.to_string(),
-                            content: line,
-                            timestamp: current_time_millis(),
-                        });
-                    }
-                }
-            }
+    let success = match gradle_command(project_dir, sdk_manager) {
+        Ok(mut cmd) => {
+            cmd.current_dir(project_dir);
+            cmd.args(&tasks);
+            cmd.arg("--console=plain");
+            cmd.args(&args);
             
-            // Read stderr
-            if let Some(stderr) = child.stderr.take() {
-                let reader = BufReader::new(stderr);
-                for line in reader.lines() {
-                    if let Ok(line) = line {
-                        output_messages.push(OutputMessage {
-                            message_type: "ERROR".to_string(),
-                            content: line,
-                            timestamp: current_time_millis(),
-                        });
-                    }
-                }
+            // Use the SDK manager's JDK when it has one rather than whatever JAVA_HOME says
+            if sdk_manager.get_java_path().exists() {
+                cmd.env("JAVA_HOME", sdk_manager.get_jdk_path());
             }
             
-            // Wait for the process to complete
-            match child.wait() {
+            control.push(OutputMessage {
+                message_type: "INFO".to_string(),
+                content: format!("Running: {:?}", cmd),
+                timestamp: current_time_millis(),
+            });
+            
+            match run_streamed_command(&mut cmd, &control, classify_gradle_line) {
                 Ok(status) => {
-                    let success = status.success();
+                    let reported_failure = control
+                        .result_messages()
+                        .iter()
+                        .any(|message| message.content.trim_start().starts_with("BUILD FAILED"));
                     
-                    if success {
-                        output_messages.push(OutputMessage {
-                            message_type: "SUCCESS".to_string(),
-                            content: "Build completed successfully".to_string(),
-                            timestamp: current_time_millis(),
-                        });
-                        
-                        // Find artifacts
-                        let target_dir = project_dir.join("target");
-                        let profile_dir = if build_type == "release" {
-                            target_dir.join("release")
-                        } else {
-                            target_dir.join("debug")
+                    if !status.success() || reported_failure {
+                        let content = match status.code() {
+                            Some(0) => format!("Gradle {} failed", task_list),
+                            code => format!("Gradle {} failed with exit code: {}", task_list, code.unwrap_or(-1)),
                         };
-                        
-                        if profile_dir.exists() {
-                            if let Ok(entries) = std::fs::read_dir(&profile_dir) {
-                                for entry in entries.filter_map(Result::ok) {
-                                    let path = entry.path();
-                                    if path.is_file() {
-                                        let file_name = path.file_name().unwrap().to_string_lossy();
-                                        
-                                        // Skip common non-executable files
-                                        if file_name.ends_with(".d") || file_name.ends_with(".rlib") || 
-                                           file_name.ends_with(".rmeta") || file_name.ends_with(".pdb") {
-                                            continue;
-                                        }
-                                        
-                                        artifacts.push(path.to_string_lossy().to_string());
-                                        
-                                        output_messages.push(OutputMessage {
-                                            message_type: "ARTIFACT".to_string(),
-                                            content: format!("Generated: {}", path.to_string_lossy()),
-                                            timestamp: current_time_millis(),
-                                        });
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        output_messages.push(OutputMessage {
+                        control.push(OutputMessage {
                             message_type: "ERROR".to_string(),
-                            content: format!("Build failed with exit code: {}", status.code().unwrap_or(-1)),
+                            content,
                             timestamp: current_time_millis(),
                         });
                     }
-                    
-                    BuildResult {
-                        success,
-                        output_messages,
-                        duration_ms: start_time.elapsed().as_millis() as u64,
-                        artifacts,
-                    }
+                    status.success() && !reported_failure && !control.is_cancelled()
                 }
                 Err(e) => {
-                    output_messages.push(OutputMessage {
+                    control.push(OutputMessage {
                         message_type: "ERROR".to_string(),
-                        content: format!("Failed to wait for process: {}", e),
+                        content: format!("Failed to start Gradle: {}", e),
                         timestamp: current_time_millis(),
                     });
-                    
-                    BuildResult {
-                        success: false,
-                        output_messages,
-                        duration_ms: start_time.elapsed().as_millis() as u64,
-                        artifacts,
-                    }
+                    false
                 }
             }
         }
         Err(e) => {
-            output_messages.push(OutputMessage {
+            control.push(OutputMessage {
                 message_type: "ERROR".to_string(),
-                content: format!("Failed to start process: {}", e),
+                content: e.to_string(),
+                timestamp: current_time_millis(),
+            });
+            false
+        }
+    };
+    
+    // Only the variants this run assembled, so APKs left over from other variants aren't reported
+    let assembled: Vec<&str> = tasks
+        .iter()
+        .filter_map(|task| task.rsplit(':').next().and_then(|name| name.strip_prefix("assemble")))
+        .collect();
+    let artifact_details = if success && !assembled.is_empty() { collect_apks(project_dir, &assembled) } else { Vec::new() };
+    
+    if success {
+        control.push(OutputMessage {
+            message_type: "SUCCESS".to_string(),
+            content: format!("Gradle {} completed successfully", task_list),
+            timestamp: current_time_millis(),
+        });
+        
+        for artifact in &artifact_details {
+            control.push(OutputMessage {
+                message_type: "ARTIFACT".to_string(),
+                content: format!("Generated: {} ({})", artifact.path, artifact.kind),
                 timestamp: current_time_millis(),
             });
+        }
+    }
+    
+    BuildResult {
+        success,
+        diagnostics: parse_gradle_output(&control.result_messages()),
+        output_messages: control.messages(),
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        artifacts: artifact_details.iter().map(|artifact| artifact.path.clone()).collect(),
+        artifact_details,
+        test_summary: None,
+        test_cases: Vec::new(),
+    }
+}
+
+// The project's Gradle wrapper, or the Gradle installed by the SDK manager
+pub(crate) fn gradle_command(project_dir: &Path, sdk_manager: &SdkManager) -> Result<Command> {
+    let wrapper = project_dir.join(if cfg!(windows) { "gradlew.bat" } else { "gradlew" });
+    if wrapper.exists() {
+        if cfg!(windows) {
+            return Ok(Command::new(wrapper));
+        }
+        
+        // Go through sh so a wrapper that lost its executable bit, e.g. when unzipped, still runs
+        let mut cmd = Command::new("sh");
+        cmd.arg(wrapper);
+        return Ok(cmd);
+    }
+    
+    let gradle = sdk_manager.get_gradle_path();
+    if gradle.exists() {
+        return Ok(Command::new(gradle));
+    }
+    
+    Err(anyhow!("Gradle not found. Add a Gradle wrapper to the project or install Gradle from the SDK manager"))
+}
+
+// Profiles a project can be built with: dev and release, then the custom [profile.*] sections
+// of Cargo.toml and the project's .cargo/config.toml, in the order they are defined
+pub fn list_build_profiles(project_path: &str) -> Result<Vec<String>> {
+    let project_dir = Path::new(project_path);
+    let cargo_toml_path = project_dir.join("Cargo.toml");
+    if !cargo_toml_path.exists() {
+        return Err(anyhow!("Cargo.toml not found. Not a valid Rust project."));
+    }
+    
+    let mut profiles = vec!["dev".to_string(), "release".to_string()];
+    let config_paths = [
+        cargo_toml_path,
+        project_dir.join(".cargo").join("config.toml"),
+        project_dir.join(".cargo").join("config"),
+    ];
+    
+    for path in config_paths {
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        
+        let table = content
+            .parse::<toml::Table>()
+            .map_err(|e| anyhow!("Failed to parse {}: {}", path.display(), e))?;
+        
+        if let Some(sections) = table.get("profile").and_then(|profile| profile.as_table()) {
+            for name in sections.keys() {
+                if !profiles.contains(name) {
+                    profiles.push(name.clone());
+                }
+            }
+        }
+    }
+    
+    Ok(profiles)
+}
+
+// Start building a project on a background thread and return its build id
+pub fn start_build(project_path: &str, build_type: &str, toolchain: Option<ToolchainPaths>) -> String {
+    let build_id = Uuid::new_v4().to_string();
+    let control = Arc::new(BuildControl::default());
+    let result = Arc::new(Mutex::new(None));
+    
+    BACKGROUND_BUILDS.lock().unwrap().insert(build_id.clone(), BackgroundBuild {
+        control: control.clone(),
+        result: result.clone(),
+        start_time: Instant::now(),
+    });
+    
+    let project_path = project_path.to_string();
+    let build_type = build_type.to_string();
+    
+    thread::spawn(move || {
+        let build_result = run_build(&project_path, &build_type, toolchain.as_ref(), &control, false);
+        *result.lock().unwrap() = Some(build_result);
+    });
+    
+    build_id
+}
+
+// Cancel a background build
+pub fn cancel_build(build_id: &str) -> Result<()> {
+    let builds = BACKGROUND_BUILDS.lock().unwrap();
+    let build = builds.get(build_id).ok_or_else(|| anyhow!("Unknown build id: {}", build_id))?;
+    
+    build.control.cancel();
+    
+    Ok(())
+}
+
+// Poll a background build for its status
+pub fn poll_build(build_id: &str) -> Result<BuildStatus> {
+    let mut builds = BACKGROUND_BUILDS.lock().unwrap();
+    let build = builds.get(build_id).ok_or_else(|| anyhow!("Unknown build id: {}", build_id))?;
+    
+    if build.result.lock().unwrap().is_none() {
+        return Ok(BuildStatus {
+            build_id: build_id.to_string(),
+            state: BuildState::Running,
+            result: None,
+        });
+    }
+    
+    // The build has finished, so hand its result over and forget it
+    let build = builds.remove(build_id).unwrap();
+    let result = build.result.lock().unwrap().take();
+    
+    let state = match &result {
+        Some(result) if result.success => BuildState::Succeeded,
+        _ if build.control.is_cancelled() => BuildState::Cancelled,
+        _ => BuildState::Failed,
+    };
+    
+    Ok(BuildStatus {
+        build_id: build_id.to_string(),
+        state,
+        result,
+    })
+}
+
+// Poll a background build for output produced since `since_index`
+pub fn poll_build_output(build_id: &str, since_index: usize) -> Result<BuildOutputChunk> {
+    let mut builds = BACKGROUND_BUILDS.lock().unwrap();
+    let build = builds.get(build_id).ok_or_else(|| anyhow!("Unknown build id: {}", build_id))?;
+    
+    // Check for completion before reading output so no message pushed before the result can be missed
+    let (finished, success, duration_ms) = match build.result.lock().unwrap().as_ref() {
+        Some(result) => (true, Some(result.success), Some(result.duration_ms)),
+        None => (false, None, None),
+    };
+    
+    let messages = build.control.messages_since(since_index);
+    let next_index = since_index + messages.len();
+    let cancelled = build.control.is_cancelled();
+    let elapsed_ms = duration_ms.unwrap_or_else(|| build.start_time.elapsed().as_millis() as u64);
+    
+    // Every message of a finished build has now been delivered
+    if finished {
+        builds.remove(build_id);
+    }
+    
+    Ok(BuildOutputChunk {
+        build_id: build_id.to_string(),
+        messages,
+        next_index,
+        finished,
+        success,
+        cancelled,
+        elapsed_ms,
+    })
+}
+
+// Build once, then rebuild whenever src/ or Cargo.toml changes, like `cargo watch` without needing it installed.
+// Changes are debounced so a burst of saves triggers a single build; results are collected for poll_watch.
+pub fn watch_build(project_path: &str, build_type: &str, toolchain: Option<ToolchainPaths>) -> Result<String> {
+    let project_dir = PathBuf::from(project_path);
+    let src_dir = project_dir.join("src");
+    
+    if !project_dir.join("Cargo.toml").exists() {
+        return Err(anyhow!("Cargo.toml not found. Not a valid Rust project."));
+    }
+    
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = sender.send(event);
+    })?;
+    
+    // Cargo.toml is watched through its directory so editors that save by renaming are still seen
+    watcher.watch(&project_dir, RecursiveMode::NonRecursive)?;
+    if src_dir.exists() {
+        watcher.watch(&src_dir, RecursiveMode::Recursive)?;
+    }
+    
+    let watch_id = Uuid::new_v4().to_string();
+    let stopped = Arc::new(AtomicBool::new(false));
+    let building = Arc::new(AtomicBool::new(false));
+    let control = Arc::new(Mutex::new(Arc::new(BuildControl::default())));
+    let results = Arc::new(Mutex::new(Vec::new()));
+    
+    BUILD_WATCHES.lock().unwrap().insert(watch_id.clone(), BuildWatch {
+        _watcher: watcher,
+        stopped: stopped.clone(),
+        building: building.clone(),
+        control: control.clone(),
+        results: results.clone(),
+    });
+    
+    let build_type = build_type.to_string();
+    
+    thread::spawn(move || {
+        let run = || {
+            let build_control = Arc::new(BuildControl::default());
+            *control.lock().unwrap() = build_control.clone();
             
-            BuildResult {
-                success: false,
-                output_messages,
-                duration_ms: start_time.elapsed().as_millis() as u64,
-                artifacts,
+            building.store(true, Ordering::SeqCst);
+            let result = run_build(&project_dir.to_string_lossy(), &build_type, toolchain.as_ref(), &build_control, false);
+            building.store(false, Ordering::SeqCst);
+            
+            if !stopped.load(Ordering::SeqCst) {
+                results.lock().unwrap().push(result);
+            }
+        };
+        
+        run();
+        
+        loop {
+            // Wait for a relevant change, then for the sources to go quiet
+            match receiver.recv() {
+                Ok(Ok(event)) if is_watch_trigger(&event, &project_dir) => {}
+                Ok(_) => continue,
+                Err(_) => return,
+            }
+            
+            loop {
+                match receiver.recv_timeout(WATCH_DEBOUNCE) {
+                    Ok(_) => continue,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            
+            if stopped.load(Ordering::SeqCst) {
+                return;
             }
+            
+            run();
         }
+    });
+    
+    Ok(watch_id)
+}
+
+// Take the results of watch builds finished since the last poll
+pub fn poll_watch(watch_id: &str) -> Result<WatchStatus> {
+    let watches = BUILD_WATCHES.lock().unwrap();
+    let watch = watches.get(watch_id).ok_or_else(|| anyhow!("Unknown watch id: {}", watch_id))?;
+    
+    let results = watch.results.lock().unwrap().drain(..).collect();
+    
+    Ok(WatchStatus {
+        watch_id: watch_id.to_string(),
+        building: watch.building.load(Ordering::SeqCst),
+        results,
+    })
+}
+
+// Stop watching, cancelling a rebuild that is in progress
+pub fn stop_watch(watch_id: &str) -> Result<()> {
+    let watch = BUILD_WATCHES
+        .lock()
+        .unwrap()
+        .remove(watch_id)
+        .ok_or_else(|| anyhow!("Unknown watch id: {}", watch_id))?;
+    
+    watch.stopped.store(true, Ordering::SeqCst);
+    watch.control.lock().unwrap().cancel();
+    
+    Ok(())
+}
+
+// Whether a file system event should trigger a watch rebuild.
+// Reads are ignored, as is anything under target/ or in the project root other than Cargo.toml.
+fn is_watch_trigger(event: &Event, project_dir: &Path) -> bool {
+    if matches!(event.kind, EventKind::Access(_)) {
+        return false;
     }
+    
+    let target_dir = project_dir.join("target");
+    let src_dir = project_dir.join("src");
+    let cargo_toml = project_dir.join("Cargo.toml");
+    
+    event.paths.iter().any(|path| {
+        !path.starts_with(&target_dir) && (path.starts_with(&src_dir) || *path == cargo_toml)
+    })
 }
 
-// Clean project
-pub fn clean_project(project_path: &str) -> BuildResult {
+// Run a cargo build, registering the process with `control` so it can be cancelled
+fn run_build(project_path: &str, build_type: &str, toolchain: Option<&ToolchainPaths>, control: &BuildControl, dry_run: bool) -> BuildResult {
     let start_time = Instant::now();
-    let mut output_messages = Vec::new();
-    let artifacts = Vec::new();
+    let config = build_config();
+    let mut artifacts = Vec::new();
+    let mut artifact_details = Vec::new();
     
     // Add initial message
-    output_messages.push(OutputMessage {
+    control.push(OutputMessage {
         message_type: "INFO".to_string(),
-        content: "Cleaning project".to_string(),
+        content: format!("Building project with type: {}", build_type),
         timestamp: current_time_millis(),
     });
     
     // Check if project exists
     let project_dir = Path::new(project_path);
     if !project_dir.exists() || !project_dir.is_dir() {
-        output_messages.push(OutputMessage {
+        control.push(OutputMessage {
             message_type: "ERROR".to_string(),
             content: format!("Project directory does not exist: {}", project_path),
             timestamp: current_time_millis(),
@@ -427,16 +1307,20 @@ pub fn clean_project(project_path: &str) -> BuildResult {
         
         return BuildResult {
             success: false,
-            output_messages,
+            output_messages: control.messages(),
             duration_ms: start_time.elapsed().as_millis() as u64,
             artifacts,
+            artifact_details,
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
         };
     }
     
     // Check if Cargo.toml exists
     let cargo_toml_path = project_dir.join("Cargo.toml");
     if !cargo_toml_path.exists() {
-        output_messages.push(OutputMessage {
+        control.push(OutputMessage {
             message_type: "ERROR".to_string(),
             content: "Cargo.toml not found. Not a valid Rust project.".to_string(),
             timestamp: current_time_millis(),
@@ -444,19 +1328,29 @@ pub fn clean_project(project_path: &str) -> BuildResult {
         
         return BuildResult {
             success: false,
-            output_messages,
+            output_messages: control.messages(),
             duration_ms: start_time.elapsed().as_millis() as u64,
             artifacts,
+            artifact_details,
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
         };
     }
     
-    // Clean command
-    let mut cmd = Command::new("cargo");
+    // Build command
+    let mut cmd = cargo_command(toolchain);
     cmd.current_dir(project_dir);
-    cmd.arg("clean");
+    
+    cmd.arg("build");
+    add_profile_args(&mut cmd, build_type);
+    
+    if dry_run {
+        return dry_run_result(&cmd, start_time);
+    }
     
     // Execute command
-    output_messages.push(OutputMessage {
+    control.push(OutputMessage {
         message_type: "INFO".to_string(),
         content: format!("Running: {:?}", cmd),
         timestamp: current_time_millis(),
@@ -464,62 +1358,120 @@ pub fn clean_project(project_path: &str) -> BuildResult {
     
     match cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
         Ok(mut child) => {
-            // Read stdout
-            if let Some(stdout) = child.stdout.take() {
-                let reader = BufReader::new(stdout);
-                for line in reader.lines() {
-                    if let Ok(line) = line {
-                        output_messages.push(OutputMessage {
-                            message_type: "INFO".to_string(),
-                            content: line,
-                            timestamp: current_time_millis(),
-                        });
+            let stdout = child.stdout.take();
+            let stderr = child.stderr.take();
+            control.attach(child);
+            
+            // Read stdout and stderr concurrently so messages arrive in the order cargo prints them
+            thread::scope(|scope| {
+                if let Some(stderr) = stderr {
+                    scope.spawn(move || {
+                        let reader = BufReader::new(stderr);
+                        for line in reader.lines() {
+                            if let Ok(line) = line {
+                                control.push_output(OutputMessage {
+                                    message_type: "ERROR".to_string(),
+                                    content: line,
+                                    timestamp: current_time_millis(),
+                                });
+                            }
+                        }
+                    });
+                }
+                
+                if let Some(stdout) = stdout {
+                    let reader = BufReader::new(stdout);
+                    for line in reader.lines() {
+                        if let Ok(line) = line {
+                            let message_type = if line.contains("error") {
+                                "ERROR"
+                            } else if line.contains("warning") {
+                                "WARNING"
+                            } else {
+                                "INFO"
+                            };
+                            
+                            control.push_output(OutputMessage {
+                                message_type: message_type.to_string(),
+                                content: line,
+                                timestamp: current_time_millis(),
+                            });
+                        }
                     }
                 }
-            }
+            });
             
-            // Read stderr
-            if let Some(stderr) = child.stderr.take() {
-                let reader = BufReader::new(stderr);
-                for line in reader.lines() {
-                    if let Ok(line) = line {
-                        output_messages.push(OutputMessage {
+            // Wait for the process to complete
+            match control.wait() {
+                Ok(_) if control.is_cancelled() => {
+                    control.push(OutputMessage {
+                        message_type: "ERROR".to_string(),
+                        content: "Build cancelled".to_string(),
+                        timestamp: current_time_millis(),
+                    });
+                    
+                    BuildResult {
+                        success: false,
+                        output_messages: control.messages(),
+                        duration_ms: start_time.elapsed().as_millis() as u64,
+                        artifacts,
+                        artifact_details,
+                        test_summary: None,
+                        test_cases: Vec::new(),
+                        diagnostics: Vec::new(),
+                    }
+                }
+                Ok(status) => {
+                    let mut success = status.success();
+                    
+                    if !success {
+                        control.push(OutputMessage {
                             message_type: "ERROR".to_string(),
-                            content: line,
+                            content: format!("Build failed with exit code: {}", status.code().unwrap_or(-1)),
                             timestamp: current_time_millis(),
                         });
+                        
+                        if config.enable_error_recovery {
+                            success = recover_failed_build(&mut cmd, control);
+                        }
                     }
-                }
-            }
-            
-            // Wait for the process to complete
-            match child.wait() {
-                Ok(status) => {
-                    let success = status.success();
                     
                     if success {
-                        output_messages.push(OutputMessage {
+                        control.push(OutputMessage {
                             message_type: "SUCCESS".to_string(),
-                            content: "Clean completed successfully".to_string(),
-                            timestamp: current_time_millis(),
-                        });
-                    } else {
-                        output_messages.push(OutputMessage {
-                            message_type: "ERROR".to_string(),
-                            content: format!("Clean failed with exit code: {}", status.code().unwrap_or(-1)),
+                            content: "Build completed successfully".to_string(),
                             timestamp: current_time_millis(),
                         });
+                        
+                        // Find artifacts
+                        let profile_dir = project_dir.join("target").join(profile_dir_name(build_type));
+                        
+                        for artifact in collect_artifacts(&profile_dir) {
+                            artifacts.push(artifact.path.clone());
+                            
+                            control.push(OutputMessage {
+                                message_type: "ARTIFACT".to_string(),
+                                content: format!("Generated: {} ({})", artifact.path, artifact.kind),
+                                timestamp: current_time_millis(),
+                            });
+                            
+                            artifact_details.push(artifact);
+                        }
                     }
                     
                     BuildResult {
                         success,
-                        output_messages,
+                        output_messages: control.messages(),
                         duration_ms: start_time.elapsed().as_millis() as u64,
                         artifacts,
+                        artifact_details,
+                        test_summary: None,
+                        test_cases: Vec::new(),
+                        diagnostics: Vec::new(),
                     }
                 }
                 Err(e) => {
-                    output_messages.push(OutputMessage {
+                    control.push(OutputMessage {
                         message_type: "ERROR".to_string(),
                         content: format!("Failed to wait for process: {}", e),
                         timestamp: current_time_millis(),
@@ -527,15 +1479,19 @@ pub fn clean_project(project_path: &str) -> BuildResult {
                     
                     BuildResult {
                         success: false,
-                        output_messages,
+                        output_messages: control.messages(),
                         duration_ms: start_time.elapsed().as_millis() as u64,
                         artifacts,
+                        artifact_details,
+                        test_summary: None,
+                        test_cases: Vec::new(),
+                        diagnostics: Vec::new(),
                     }
                 }
             }
         }
         Err(e) => {
-            output_messages.push(OutputMessage {
+            control.push(OutputMessage {
                 message_type: "ERROR".to_string(),
                 content: format!("Failed to start process: {}", e),
                 timestamp: current_time_millis(),
@@ -543,16 +1499,71 @@ pub fn clean_project(project_path: &str) -> BuildResult {
             
             BuildResult {
                 success: false,
-                output_messages,
+                output_messages: control.messages(),
                 duration_ms: start_time.elapsed().as_millis() as u64,
                 artifacts,
+                artifact_details,
+                test_summary: None,
+                test_cases: Vec::new(),
+                diagnostics: Vec::new(),
             }
         }
     }
 }
 
-// Test project
-pub fn test_project(project_path: &str, release: bool) -> BuildResult {
+// Error recovery for a failed build, see BuildConfig::enable_error_recovery. A missing standard
+// library for the target gets a `rustup target add` suggestion, since building again can't help.
+// Otherwise the build is rerun with --keep-going so every crate that can be built is, and all
+// independent errors are reported in one pass. Returns whether the rerun succeeded
+fn recover_failed_build(cmd: &mut Command, control: &BuildControl) -> bool {
+    if let Some(target) = missing_target(&control.result_messages()) {
+        control.push(OutputMessage {
+            message_type: "INFO".to_string(),
+            content: format!("The {} target is not installed; install it with `rustup target add {}`", target, target),
+            timestamp: current_time_millis(),
+        });
+        return false;
+    }
+    
+    control.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: "Building again with --keep-going to report every error".to_string(),
+        timestamp: current_time_millis(),
+    });
+    
+    cmd.arg("--keep-going");
+    match run_cargo_command(cmd, control) {
+        Ok(status) if status.success() && !control.is_cancelled() => true,
+        Ok(status) => {
+            control.push(OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: format!("Build failed with exit code: {}", status.code().unwrap_or(-1)),
+                timestamp: current_time_millis(),
+            });
+            false
+        }
+        Err(e) => {
+            control.push(OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: format!("Failed to start process: {}", e),
+                timestamp: current_time_millis(),
+            });
+            false
+        }
+    }
+}
+
+// The target named by rustc's "the `<triple>` target may not be installed" note
+fn missing_target(messages: &[OutputMessage]) -> Option<String> {
+    messages.iter().find_map(|message| {
+        let (_, rest) = message.content.split_once("the `")?;
+        let (target, rest) = rest.split_once('`')?;
+        rest.trim_start().starts_with("target may not be installed").then(|| target.to_string())
+    })
+}
+
+// Clean project
+pub fn clean_project(project_path: &str, options: &CleanOptions, toolchain: Option<&ToolchainPaths>) -> BuildResult {
     let start_time = Instant::now();
     let mut output_messages = Vec::new();
     let artifacts = Vec::new();
@@ -560,7 +1571,7 @@ pub fn test_project(project_path: &str, release: bool) -> BuildResult {
     // Add initial message
     output_messages.push(OutputMessage {
         message_type: "INFO".to_string(),
-        content: "Running tests".to_string(),
+        content: "Cleaning project".to_string(),
         timestamp: current_time_millis(),
     });
     
@@ -578,6 +1589,10 @@ pub fn test_project(project_path: &str, release: bool) -> BuildResult {
             output_messages,
             duration_ms: start_time.elapsed().as_millis() as u64,
             artifacts,
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
         };
     }
     
@@ -595,16 +1610,38 @@ pub fn test_project(project_path: &str, release: bool) -> BuildResult {
             output_messages,
             duration_ms: start_time.elapsed().as_millis() as u64,
             artifacts,
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
         };
     }
     
-    // Test command
-    let mut cmd = Command::new("cargo");
+    // Clean command
+    let mut cmd = cargo_command(toolchain);
     cmd.current_dir(project_dir);
-    cmd.arg("test");
+    cmd.arg("clean");
     
-    if release {
-        cmd.arg("--release");
+    if let Some(target) = &options.target {
+        cmd.arg("--target");
+        cmd.arg(target);
+    }
+    
+    match options.release {
+        Some(true) => {
+            cmd.arg("--release");
+        }
+        Some(false) => {
+            cmd.arg("--profile");
+            cmd.arg("dev");
+        }
+        None => {}
+    }
+    
+    // Cleaning just the workspace's own packages keeps the expensive dependency builds
+    if let Some(package) = &options.package {
+        cmd.arg("-p");
+        cmd.arg(package);
     }
     
     // Execute command
@@ -614,6 +1651,8 @@ pub fn test_project(project_path: &str, release: bool) -> BuildResult {
         timestamp: current_time_millis(),
     });
     
+    let output_cap = OutputCap::default();
+    
     match cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
         Ok(mut child) => {
             // Read stdout
@@ -621,18 +1660,8 @@ pub fn test_project(project_path: &str, release: bool) -> BuildResult {
                 let reader = BufReader::new(stdout);
                 for line in reader.lines() {
                     if let Ok(line) = line {
-                        let message_type = if line.contains("error") {
-                            "ERROR"
-                        } else if line.contains("warning") {
-                            "WARNING"
-                        } else if line.contains("test result: ok") {
-                            "SUCCESS"
-                        } else {
-                            "INFO"
-                        };
-                        
-                        output_messages.push(OutputMessage {
-                            message_type: message_type.to_string(),
+                        output_cap.push(&mut output_messages, OutputMessage {
+                            message_type: "INFO".to_string(),
                             content: line,
                             timestamp: current_time_millis(),
                         });
@@ -645,7 +1674,7 @@ pub fn test_project(project_path: &str, release: bool) -> BuildResult {
                 let reader = BufReader::new(stderr);
                 for line in reader.lines() {
                     if let Ok(line) = line {
-                        output_messages.push(OutputMessage {
+                        output_cap.push(&mut output_messages, OutputMessage {
                             message_type: "ERROR".to_string(),
                             content: line,
                             timestamp: current_time_millis(),
@@ -662,13 +1691,13 @@ pub fn test_project(project_path: &str, release: bool) -> BuildResult {
                     if success {
                         output_messages.push(OutputMessage {
                             message_type: "SUCCESS".to_string(),
-                            content: "Tests completed successfully".to_string(),
+                            content: "Clean completed successfully".to_string(),
                             timestamp: current_time_millis(),
                         });
                     } else {
                         output_messages.push(OutputMessage {
                             message_type: "ERROR".to_string(),
-                            content: format!("Tests failed with exit code: {}", status.code().unwrap_or(-1)),
+                            content: format!("Clean failed with exit code: {}", status.code().unwrap_or(-1)),
                             timestamp: current_time_millis(),
                         });
                     }
@@ -678,6 +1707,10 @@ pub fn test_project(project_path: &str, release: bool) -> BuildResult {
                         output_messages,
                         duration_ms: start_time.elapsed().as_millis() as u64,
                         artifacts,
+                        artifact_details: Vec::new(),
+                        test_summary: None,
+                        test_cases: Vec::new(),
+                        diagnostics: Vec::new(),
                     }
                 }
                 Err(e) => {
@@ -692,6 +1725,10 @@ pub fn test_project(project_path: &str, release: bool) -> BuildResult {
                         output_messages,
                         duration_ms: start_time.elapsed().as_millis() as u64,
                         artifacts,
+                        artifact_details: Vec::new(),
+                        test_summary: None,
+                        test_cases: Vec::new(),
+                        diagnostics: Vec::new(),
                     }
                 }
             }
@@ -708,21 +1745,25 @@ pub fn test_project(project_path: &str, release: bool) -> BuildResult {
                 output_messages,
                 duration_ms: start_time.elapsed().as_millis() as u64,
                 artifacts,
+                artifact_details: Vec::new(),
+                test_summary: None,
+                test_cases: Vec::new(),
+                diagnostics: Vec::new(),
             }
         }
     }
 }
 
-// Build for Android target
-pub fn build_for_android_target(project_path: &str, target: &str, release: bool) -> BuildResult {
+// Test project
+pub fn test_project(project_path: &str, release: bool, test_filter: Option<&str>, toolchain: Option<&ToolchainPaths>) -> BuildResult {
     let start_time = Instant::now();
     let mut output_messages = Vec::new();
-    let mut artifacts = Vec::new();
+    let artifacts = Vec::new();
     
     // Add initial message
     output_messages.push(OutputMessage {
         message_type: "INFO".to_string(),
-        content: format!("Building for Android target: {}", target),
+        content: "Running tests".to_string(),
         timestamp: current_time_millis(),
     });
     
@@ -740,6 +1781,10 @@ pub fn build_for_android_target(project_path: &str, target: &str, release: bool)
             output_messages,
             duration_ms: start_time.elapsed().as_millis() as u64,
             artifacts,
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
         };
     }
     
@@ -757,20 +1802,26 @@ pub fn build_for_android_target(project_path: &str, target: &str, release: bool)
             output_messages,
             duration_ms: start_time.elapsed().as_millis() as u64,
             artifacts,
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
         };
     }
     
-    // Build command
-    let mut cmd = Command::new("cargo");
+    // Test command
+    let mut cmd = cargo_command(toolchain);
     cmd.current_dir(project_dir);
-    cmd.arg("build");
-    cmd.arg("--target");
-    cmd.arg(target);
+    cmd.arg("test");
     
     if release {
         cmd.arg("--release");
     }
     
+    if let Some(filter) = test_filter {
+        cmd.arg(filter);
+    }
+    
     // Execute command
     output_messages.push(OutputMessage {
         message_type: "INFO".to_string(),
@@ -778,6 +1829,10 @@ pub fn build_for_android_target(project_path: &str, target: &str, release: bool)
         timestamp: current_time_millis(),
     });
     
+    // The summary and results are parsed from the output, including lines past the cap
+    let output_cap = OutputCap::default();
+    let mut result_lines = ResultLines::default();
+    
     match cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
         Ok(mut child) => {
             // Read stdout
@@ -789,15 +1844,20 @@ pub fn build_for_android_target(project_path: &str, target: &str, release: bool)
                             "ERROR"
                         } else if line.contains("warning") {
                             "WARNING"
+                        } else if line.contains("test result: ok") {
+                            "SUCCESS"
                         } else {
                             "INFO"
                         };
                         
-                        output_messages.push(OutputMessage {
+                        let message = OutputMessage {
                             message_type: message_type.to_string(),
                             content: line,
                             timestamp: current_time_millis(),
-                        });
+                        };
+                        if let Some(message) = output_cap.push(&mut output_messages, message) {
+                            result_lines.keep(output_messages.len(), message);
+                        }
                     }
                 }
             }
@@ -807,11 +1867,14 @@ pub fn build_for_android_target(project_path: &str, target: &str, release: bool)
                 let reader = BufReader::new(stderr);
                 for line in reader.lines() {
                     if let Ok(line) = line {
-                        output_messages.push(OutputMessage {
+                        let message = OutputMessage {
                             message_type: "ERROR".to_string(),
                             content: line,
                             timestamp: current_time_millis(),
-                        });
+                        };
+                        if let Some(message) = output_cap.push(&mut output_messages, message) {
+                            result_lines.keep(output_messages.len(), message);
+                        }
                     }
                 }
             }
@@ -824,55 +1887,32 @@ pub fn build_for_android_target(project_path: &str, target: &str, release: bool)
                     if success {
                         output_messages.push(OutputMessage {
                             message_type: "SUCCESS".to_string(),
-                            content: format!("Build for {} completed successfully", target),
+                            content: "Tests completed successfully".to_string(),
                             timestamp: current_time_millis(),
                         });
-                        
-                        // Find artifacts
-                        let target_dir = project_dir.join("target").join(target);
-                        let profile_dir = if release {
-                            target_dir.join("release")
-                        } else {
-                            target_dir.join("debug")
-                        };
-                        
-                        if profile_dir.exists() {
-                            if let Ok(entries) = std::fs::read_dir(&profile_dir) {
-                                for entry in entries.filter_map(Result::ok) {
-                                    let path = entry.path();
-                                    if path.is_file() {
-                                        let file_name = path.file_name().unwrap().to_string_lossy();
-                                        
-                                        // Skip common non-executable files
-                                        if file_name.ends_with(".d") || file_name.ends_with(".rlib") || 
-                                           file_name.ends_with(".rmeta") || file_name.ends_with(".pdb") {
-                                            continue;
-                                        }
-                                        
-                                        artifacts.push(path.to_string_lossy().to_string());
-                                        
-                                        output_messages.push(OutputMessage {
-                                            message_type: "ARTIFACT".to_string(),
-                                            content: format!("Generated: {}", path.to_string_lossy()),
-                                            timestamp: current_time_millis(),
-                                        });
-                                    }
-                                }
-                            }
-                        }
                     } else {
                         output_messages.push(OutputMessage {
                             message_type: "ERROR".to_string(),
-                            content: format!("Build for {} failed with exit code: {}", target, status.code().unwrap_or(-1)),
+                            content: format!("Tests failed with exit code: {}", status.code().unwrap_or(-1)),
                             timestamp: current_time_millis(),
                         });
                     }
                     
+                    let test_output = result_lines.merged_with(&output_messages);
+                    let test_summary = parse_test_summary(&test_output);
+                    let test_cases = parse_test_results(&test_output);
+                    let artifact_details = parse_test_artifacts(&test_output, project_dir);
+                    let artifacts = artifact_details.iter().map(|artifact| artifact.path.clone()).collect();
+                    
                     BuildResult {
                         success,
                         output_messages,
                         duration_ms: start_time.elapsed().as_millis() as u64,
                         artifacts,
+                        artifact_details,
+                        test_summary,
+                        test_cases,
+                        diagnostics: Vec::new(),
                     }
                 }
                 Err(e) => {
@@ -887,6 +1927,10 @@ pub fn build_for_android_target(project_path: &str, target: &str, release: bool)
                         output_messages,
                         duration_ms: start_time.elapsed().as_millis() as u64,
                         artifacts,
+                        artifact_details: Vec::new(),
+                        test_summary: None,
+                        test_cases: Vec::new(),
+                        diagnostics: Vec::new(),
                     }
                 }
             }
@@ -903,45 +1947,33 @@ pub fn build_for_android_target(project_path: &str, target: &str, release: bool)
                 output_messages,
                 duration_ms: start_time.elapsed().as_millis() as u64,
                 artifacts,
+                artifact_details: Vec::new(),
+                test_summary: None,
+                test_cases: Vec::new(),
+                diagnostics: Vec::new(),
             }
         }
     }
 }
 
-// Generate Android bindings
-pub fn generate_android_bindings(project_path: &str, package_name: &str) -> BuildResult {
+// Generate documentation with cargo doc
+pub fn generate_docs(project_path: &str, open: bool, no_deps: bool, toolchain: Option<&ToolchainPaths>) -> BuildResult {
     let start_time = Instant::now();
-    let mut output_messages = Vec::new();
+    let control = BuildControl::default();
     let mut artifacts = Vec::new();
     
     // Add initial message
-    output_messages.push(OutputMessage {
+    control.push(OutputMessage {
         message_type: "INFO".to_string(),
-        content: "Generating Android bindings".to_string(),
+        content: "Generating documentation".to_string(),
         timestamp: current_time_millis(),
     });
     
-    // Check if project exists
-    let project_dir = Path::new(project_path);
-    if !project_dir.exists() || !project_dir.is_dir() {
-        output_messages.push(OutputMessage {
-            message_type: "ERROR".to_string(),
-            content: format!("Project directory does not exist: {}", project_path),
-            timestamp: current_time_millis(),
-        });
-        
-        return BuildResult {
-            success: false,
-            output_messages,
-            duration_ms: start_time.elapsed().as_millis() as u64,
-            artifacts,
-        };
-    }
-    
     // Check if Cargo.toml exists
+    let project_dir = Path::new(project_path);
     let cargo_toml_path = project_dir.join("Cargo.toml");
     if !cargo_toml_path.exists() {
-        output_messages.push(OutputMessage {
+        control.push(OutputMessage {
             message_type: "ERROR".to_string(),
             content: "Cargo.toml not found. Not a valid Rust project.".to_string(),
             timestamp: current_time_millis(),
@@ -949,200 +1981,656 @@ pub fn generate_android_bindings(project_path: &str, package_name: &str) -> Buil
         
         return BuildResult {
             success: false,
-            output_messages,
+            output_messages: control.messages(),
             duration_ms: start_time.elapsed().as_millis() as u64,
             artifacts,
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
         };
     }
     
-    // Create src directory if it doesn't exist
-    let src_dir = project_dir.join("src");
-    if !src_dir.exists() {
-        if let Err(e) = std::fs::create_dir_all(&src_dir) {
-            output_messages.push(OutputMessage {
+    // The docs land in target/doc/<crate>, so the crate name is needed up front
+    let crate_name = match read_crate_name(&cargo_toml_path) {
+        Ok(crate_name) => crate_name,
+        Err(e) => {
+            control.push(OutputMessage {
                 message_type: "ERROR".to_string(),
-                content: format!("Failed to create src directory: {}", e),
+                content: e.to_string(),
                 timestamp: current_time_millis(),
             });
             
             return BuildResult {
                 success: false,
-                output_messages,
+                output_messages: control.messages(),
                 duration_ms: start_time.elapsed().as_millis() as u64,
                 artifacts,
+                artifact_details: Vec::new(),
+                test_summary: None,
+                test_cases: Vec::new(),
+                diagnostics: Vec::new(),
             };
         }
-    }
+    };
     
-    // Create lib.rs with JNI bindings
-    let lib_rs_path = src_dir.join("lib.rs");
-    let lib_rs_content = format!(
-        r#"use jni::JNIEnv;
-use jni::objects::{{JClass, JString}};
-use jni::sys::jstring;
-
-#[no_mangle]
-pub extern "C" fn Java_{0}_RustLib_getGreeting(env: JNIEnv, _class: JClass) -> jstring {{
-    let output = env.new_string("Hello from Rust!")
-        .expect("Couldn't create Java string!");
-    output.into_raw()
-}}
-
-#[no_mangle]
-pub extern "C" fn Java_{0}_RustLib_processString(env: JNIEnv, _class: JClass, input: JString) -> jstring {{
-    let input: String = env.get_string(input)
-        .expect("Couldn't get Java string!")
-        .into();
-    let output = format!("Rust processed: {{}}", input);
-    let output = env.new_string(output)
-        .expect("Couldn't create Java string!");
-    output.into_raw()
-}}"#,
-        package_name.replace(".", "_")
-    );
+    // Doc command
+    let mut cmd = cargo_command(toolchain);
+    cmd.current_dir(project_dir);
+    cmd.arg("doc");
     
-    if let Err(e) = std::fs::write(&lib_rs_path, lib_rs_content) {
-        output_messages.push(OutputMessage {
-            message_type: "ERROR".to_string(),
-            content: format!("Failed to write lib.rs: {}", e),
-            timestamp: current_time_millis(),
-        });
-        
-        return BuildResult {
-            success: false,
-            output_messages,
-            duration_ms: start_time.elapsed().as_millis() as u64,
-            artifacts,
-        };
+    if no_deps {
+        cmd.arg("--no-deps");
     }
     
-    output_messages.push(OutputMessage {
+    control.push(OutputMessage {
         message_type: "INFO".to_string(),
-        content: "Generated lib.rs with JNI bindings".to_string(),
+        content: format!("Running: {:?}", cmd),
         timestamp: current_time_millis(),
     });
     
-    artifacts.push(lib_rs_path.to_string_lossy().to_string());
-    
-    // Create .cargo directory and config.toml
-    let cargo_config_dir = project_dir.join(".cargo");
-    if !cargo_config_dir.exists() {
-        if let Err(e) = std::fs::create_dir_all(&cargo_config_dir) {
-            output_messages.push(OutputMessage {
+    let success = match run_cargo_command(&mut cmd, &control) {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            control.push(OutputMessage {
                 message_type: "ERROR".to_string(),
-                content: format!("Failed to create .cargo directory: {}", e),
+                content: format!("cargo doc failed with exit code: {}", status.code().unwrap_or(-1)),
+                timestamp: current_time_millis(),
+            });
+            false
+        }
+        Err(e) => {
+            control.push(OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: format!("Failed to run cargo doc: {}", e),
                 timestamp: current_time_millis(),
             });
+            false
+        }
+    };
+    
+    if success {
+        let index_path = project_dir.join("target").join("doc").join(&crate_name).join("index.html");
+        
+        if index_path.exists() {
+            artifacts.push(index_path.to_string_lossy().to_string());
             
-            return BuildResult {
-                success: false,
-                output_messages,
-                duration_ms: start_time.elapsed().as_millis() as u64,
-                artifacts,
-            };
+            control.push(OutputMessage {
+                message_type: "ARTIFACT".to_string(),
+                content: format!("Generated: {}", index_path.to_string_lossy()),
+                timestamp: current_time_millis(),
+            });
+            
+            if open {
+                if let Err(e) = open_in_browser(&index_path) {
+                    control.push(OutputMessage {
+                        message_type: "WARNING".to_string(),
+                        content: format!("Could not open documentation: {}", e),
+                        timestamp: current_time_millis(),
+                    });
+                }
+            }
+        } else {
+            control.push(OutputMessage {
+                message_type: "WARNING".to_string(),
+                content: format!("Documentation index not found at {}", index_path.to_string_lossy()),
+                timestamp: current_time_millis(),
+            });
         }
+        
+        control.push(OutputMessage {
+            message_type: "SUCCESS".to_string(),
+            content: "Documentation generated successfully".to_string(),
+            timestamp: current_time_millis(),
+        });
     }
     
-    let cargo_config_path = cargo_config_dir.join("config.toml");
-    let cargo_config_content = r#"[target.aarch64-linux-android]
-ar = "aarch64-linux-android-ar"
-linker = "aarch64-linux-android-clang"
+    BuildResult {
+        success,
+        output_messages: control.messages(),
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        artifacts,
+        artifact_details: Vec::new(),
+        test_summary: None,
+        test_cases: Vec::new(),
+        diagnostics: Vec::new(),
+    }
+}
 
-[target.armv7-linux-androideabi]
-ar = "arm-linux-androideabi-ar"
-linker = "arm-linux-androideabi-clang"
-
-[target.i686-linux-android]
-ar = "i686-linux-android-ar"
-linker = "i686-linux-android-clang"
-
-[target.x86_64-linux-android]
-ar = "x86_64-linux-android-ar"
-linker = "x86_64-linux-android-clang"
-"#;
+// Run benchmarks with cargo bench
+pub fn bench_project(project_path: &str, filter: Option<&str>, toolchain: Option<&ToolchainPaths>) -> BuildResult {
+    let start_time = Instant::now();
+    let control = BuildControl::default();
     
-    if let Err(e) = std::fs::write(&cargo_config_path, cargo_config_content) {
-        output_messages.push(OutputMessage {
+    // Add initial message
+    control.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: "Running benchmarks".to_string(),
+        timestamp: current_time_millis(),
+    });
+    
+    // Check if Cargo.toml exists
+    let project_dir = Path::new(project_path);
+    if !project_dir.join("Cargo.toml").exists() {
+        control.push(OutputMessage {
             message_type: "ERROR".to_string(),
-            content: format!("Failed to write .cargo/config.toml: {}", e),
+            content: "Cargo.toml not found. Not a valid Rust project.".to_string(),
             timestamp: current_time_millis(),
         });
         
         return BuildResult {
             success: false,
-            output_messages,
+            output_messages: control.messages(),
             duration_ms: start_time.elapsed().as_millis() as u64,
-            artifacts,
+            artifacts: Vec::new(),
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
         };
     }
     
-    output_messages.push(OutputMessage {
+    // Bench command
+    let mut cmd = cargo_command(toolchain);
+    cmd.current_dir(project_dir);
+    cmd.arg("bench");
+    
+    if let Some(filter) = filter {
+        cmd.arg(filter);
+    }
+    
+    control.push(OutputMessage {
         message_type: "INFO".to_string(),
-        content: "Generated .cargo/config.toml for Android targets".to_string(),
+        content: format!("Running: {:?}", cmd),
         timestamp: current_time_millis(),
     });
     
-    artifacts.push(cargo_config_path.to_string_lossy().to_string());
+    let success = match run_cargo_command(&mut cmd, &control) {
+        Ok(status) if status.success() => {
+            control.push(OutputMessage {
+                message_type: "SUCCESS".to_string(),
+                content: "Benchmarks completed successfully".to_string(),
+                timestamp: current_time_millis(),
+            });
+            true
+        }
+        Ok(status) => {
+            control.push(OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: format!("Benchmarks failed with exit code: {}", status.code().unwrap_or(-1)),
+                timestamp: current_time_millis(),
+            });
+            false
+        }
+        Err(e) => {
+            control.push(OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: format!("Failed to run cargo bench: {}", e),
+                timestamp: current_time_millis(),
+            });
+            false
+        }
+    };
     
-    // Update Cargo.toml to ensure it's a library with cdylib type
-    if let Ok(cargo_toml_content) = std::fs::read_to_string(&cargo_toml_path) {
-        let mut updated_content = cargo_toml_content.clone();
+    BuildResult {
+        success,
+        output_messages: control.messages(),
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        artifacts: Vec::new(),
+        artifact_details: Vec::new(),
+        test_summary: parse_test_summary(&control.result_messages()),
+        test_cases: Vec::new(),
+        diagnostics: Vec::new(),
+    }
+}
+
+// Sum the "test result: ok. 3 passed; 1 failed; 0 ignored; ..." lines printed for each test binary
+pub fn parse_test_summary(output: &[OutputMessage]) -> Option<TestSummary> {
+    let mut summary: Option<TestSummary> = None;
+    
+    for message in output {
+        let index = match message.content.find("test result:") {
+            Some(index) => index,
+            None => continue,
+        };
         
-        if !cargo_toml_content.contains("[lib]") {
-            updated_content.push_str("\n[lib]\ncrate-type = [\"cdylib\", \"staticlib\", \"rlib\"]\n");
-        } else if !cargo_toml_content.contains("crate-type") {
-            updated_content = updated_content.replace(
-                "[lib]",
-                "[lib]\ncrate-type = [\"cdylib\", \"staticlib\", \"rlib\"]"
-            );
-        }
+        let counts = summary.get_or_insert_with(TestSummary::default);
         
-        if !cargo_toml_content.contains("jni =") {
-            if cargo_toml_content.contains("[dependencies]") {
-                updated_content = updated_content.replace(
-                    "[dependencies]",
-                    "[dependencies]\njni = { version = \"0.21.1\", features = [\"invocation\"] }"
-                );
-            } else {
-                updated_content.push_str("\n[dependencies]\njni = { version = \"0.21.1\", features = [\"invocation\"] }\n");
+        for part in message.content[index + "test result:".len()..].split(|c| c == ';' || c == '.') {
+            let mut words = part.split_whitespace();
+            
+            if let (Some(count), Some(label)) = (words.next(), words.next()) {
+                if let Ok(count) = count.parse::<u32>() {
+                    match label {
+                        "passed" => counts.passed += count,
+                        "failed" => counts.failed += count,
+                        "ignored" => counts.ignored += count,
+                        _ => {}
+                    }
+                }
             }
         }
+    }
+    
+    summary
+}
+
+// Parse "test path::to::test ... ok" lines, including the "<0.012s>" timing printed with --report-time
+pub fn parse_test_results(output: &[OutputMessage]) -> Vec<TestCaseResult> {
+    let mut results = Vec::new();
+    
+    for message in output {
+        let line = message.content.trim();
         
-        if updated_content != cargo_toml_content {
-            if let Err(e) = std::fs::write(&cargo_toml_path, updated_content) {
-                output_messages.push(OutputMessage {
-                    message_type: "ERROR".to_string(),
-                    content: format!("Failed to update Cargo.toml: {}", e),
-                    timestamp: current_time_millis(),
-                });
-                
-                return BuildResult {
-                    success: false,
-                    output_messages,
-                    duration_ms: start_time.elapsed().as_millis() as u64,
-                    artifacts,
-                };
+        let rest = match line.strip_prefix("test ") {
+            Some(rest) => rest,
+            None => continue,
+        };
+        
+        let (name, outcome) = match rest.split_once(" ... ") {
+            Some(parts) => parts,
+            None => continue,
+        };
+        
+        let mut words = outcome.split_whitespace();
+        let status = match words.next().map(|word| word.trim_end_matches(',')) {
+            Some("ok") => "ok",
+            Some("FAILED") => "failed",
+            Some("ignored") => "ignored",
+            _ => continue,
+        };
+        
+        let duration_ms = outcome
+            .rfind('<')
+            .and_then(|start| outcome[start + 1..].strip_suffix("s>"))
+            .and_then(|seconds| seconds.parse::<f64>().ok())
+            .map(|seconds| (seconds * 1000.0).round() as u64);
+        
+        results.push(TestCaseResult {
+            name: name.trim().to_string(),
+            status: status.to_string(),
+            duration_ms,
+        });
+    }
+    
+    results
+}
+
+// Time a build of a project crate by crate with cargo's --timings, to find slow dependencies.
+// Nightly cargo reports timings as JSON messages; stable cargo writes an HTML report whose data
+// is read back, and a cargo too old for either does a plain build with only the total time.
+// Crates that are already up to date are not compiled and so are not in the breakdown
+pub fn generate_build_report(project_path: &str, toolchain: Option<&ToolchainPaths>) -> BuildReport {
+    let control = BuildControl::default();
+    let mut report = BuildReport {
+        success: false,
+        total_ms: 0,
+        per_crate: Vec::new(),
+        artifact_sizes: Vec::new(),
+        timing_source: "none".to_string(),
+        output_messages: Vec::new(),
+    };
+    
+    let project_dir = Path::new(project_path);
+    if !project_dir.join("Cargo.toml").exists() {
+        control.push(OutputMessage {
+            message_type: "ERROR".to_string(),
+            content: "Cargo.toml not found. Not a valid Rust project.".to_string(),
+            timestamp: current_time_millis(),
+        });
+        report.output_messages = control.messages();
+        return report;
+    }
+    
+    let nightly = cargo_is_nightly(toolchain);
+    let build_started = SystemTime::now();
+    let timing_args: &[&str] = if nightly { &["-Z", "unstable-options", "--timings=json"] } else { &["--timings"] };
+    
+    let (success, total_ms) = run_timed_build(project_dir, timing_args, toolchain, &control);
+    let result_messages = control.result_messages();
+    
+    let timings = if nightly {
+        parse_timing_messages(&result_messages)
+    } else {
+        read_timing_report(&project_dir.join("target").join("cargo-timings").join("cargo-timing.html"), build_started)
+    };
+    
+    let timing_unsupported = result_messages
+        .iter()
+        .any(|message| message.message_type == "ERROR" && message.content.contains("--timings"));
+    if !success && timings.is_none() && timing_unsupported {
+        let plain = BuildControl::default();
+        let (success, total_ms) = run_timed_build(project_dir, &[], toolchain, &plain);
+        report.success = success;
+        report.total_ms = total_ms;
+        report.output_messages = plain.messages();
+    } else {
+        report.success = success;
+        report.total_ms = total_ms;
+        // The JSON timing messages are in the report itself, not needed in the log
+        report.output_messages = control.messages().into_iter().filter(|message| !message.content.contains("\"timing-info\"")).collect();
+    }
+    
+    if let Some((source, per_crate)) = timings {
+        report.timing_source = source.to_string();
+        report.per_crate = per_crate;
+    }
+    
+    if report.success {
+        report.artifact_sizes = collect_artifacts(&project_dir.join("target").join("debug"));
+    }
+    
+    report
+}
+
+// Run a debug build with extra arguments, returning whether it succeeded and how long it took
+fn run_timed_build(project_dir: &Path, args: &[&str], toolchain: Option<&ToolchainPaths>, control: &BuildControl) -> (bool, u64) {
+    let start_time = Instant::now();
+    
+    let mut cmd = cargo_command(toolchain);
+    cmd.current_dir(project_dir);
+    cmd.arg("build");
+    cmd.args(args);
+    
+    control.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: format!("Running: {:?}", cmd),
+        timestamp: current_time_millis(),
+    });
+    
+    let success = match run_cargo_command(&mut cmd, control) {
+        Ok(status) => status.success(),
+        Err(e) => {
+            control.push(OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: format!("Failed to run cargo build: {}", e),
+                timestamp: current_time_millis(),
+            });
+            false
+        }
+    };
+    
+    (success, start_time.elapsed().as_millis() as u64)
+}
+
+// Whether the cargo that would run is a nightly, which is needed for JSON timings
+fn cargo_is_nightly(toolchain: Option<&ToolchainPaths>) -> bool {
+    cargo_command(toolchain)
+        .arg("--version")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains("-nightly"))
+        .unwrap_or(false)
+}
+
+// Per-crate times from the "timing-info" messages of a --timings=json build
+fn parse_timing_messages(messages: &[OutputMessage]) -> Option<(&'static str, Vec<CrateTiming>)> {
+    let units: Vec<(String, f64)> = messages
+        .iter()
+        .filter_map(|message| serde_json::from_str::<serde_json::Value>(&message.content).ok())
+        .filter(|json| json["reason"] == "timing-info")
+        .filter_map(|json| Some((json["target"]["name"].as_str()?.to_string(), json["duration"].as_f64()?)))
+        .collect();
+    
+    if units.is_empty() {
+        None
+    } else {
+        Some(("json", sum_crate_timings(units)))
+    }
+}
+
+// Per-crate times from the UNIT_DATA table embedded in cargo's HTML timing report,
+// if the report was written since `since` rather than left over from an earlier build
+fn read_timing_report(report_path: &Path, since: SystemTime) -> Option<(&'static str, Vec<CrateTiming>)> {
+    let modified = std::fs::metadata(report_path).and_then(|metadata| metadata.modified()).ok()?;
+    if modified < since {
+        return None;
+    }
+    
+    let html = std::fs::read_to_string(report_path).ok()?;
+    let data_start = html.find("const UNIT_DATA = ")? + "const UNIT_DATA = ".len();
+    
+    // The table is followed by more script, so only parse the first JSON value
+    let data = serde_json::Deserializer::from_str(&html[data_start..])
+        .into_iter::<serde_json::Value>()
+        .next()?
+        .ok()?;
+    
+    let units: Vec<(String, f64)> = data
+        .as_array()?
+        .iter()
+        .filter_map(|unit| Some((unit["name"].as_str()?.to_string(), unit["duration"].as_f64()?)))
+        .collect();
+    
+    Some(("html", sum_crate_timings(units)))
+}
+
+// Add up the units of each crate (build script, library, binaries), slowest crate first
+fn sum_crate_timings(units: Vec<(String, f64)>) -> Vec<CrateTiming> {
+    let mut totals: HashMap<String, f64> = HashMap::new();
+    for (name, seconds) in units {
+        *totals.entry(name).or_insert(0.0) += seconds;
+    }
+    
+    let mut per_crate: Vec<CrateTiming> = totals
+        .into_iter()
+        .map(|(name, seconds)| CrateTiming { name, ms: (seconds * 1000.0).round() as u64 })
+        .collect();
+    per_crate.sort_by(|a, b| b.ms.cmp(&a.ms).then_with(|| a.name.cmp(&b.name)));
+    per_crate
+}
+
+// Type-check a project with cargo check, which skips codegen and is much faster than a build
+pub fn check_project(project_path: &str, target: Option<&str>, toolchain: Option<&ToolchainPaths>) -> CheckResult {
+    let start_time = Instant::now();
+    let control = BuildControl::default();
+    
+    // Check if Cargo.toml exists
+    let project_dir = Path::new(project_path);
+    if !project_dir.join("Cargo.toml").exists() {
+        control.push(OutputMessage {
+            message_type: "ERROR".to_string(),
+            content: "Cargo.toml not found. Not a valid Rust project.".to_string(),
+            timestamp: current_time_millis(),
+        });
+        
+        return CheckResult {
+            success: false,
+            diagnostics: Vec::new(),
+            output_messages: control.messages(),
+            duration_ms: start_time.elapsed().as_millis() as u64,
+        };
+    }
+    
+    // Check command
+    let mut cmd = cargo_command(toolchain);
+    cmd.current_dir(project_dir);
+    cmd.arg("check");
+    cmd.arg("--message-format=json");
+    
+    if let Some(target) = target {
+        cmd.arg("--target");
+        cmd.arg(target);
+    }
+    
+    let status = run_cargo_command(&mut cmd, &control);
+    
+    // JSON lines become diagnostics; anything else (e.g. manifest errors on stderr) is kept as
+    // plain output, up to the cap
+    let mut diagnostics = Vec::new();
+    let mut output_messages = Vec::new();
+    let output_cap = OutputCap::default();
+    
+    for message in control.result_messages() {
+        if message.content.starts_with('{') {
+            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&message.content) {
+                if let Some(diagnostic) = parse_cargo_diagnostic(&json, project_dir) {
+                    diagnostics.push(diagnostic);
+                }
+                continue;
             }
-            
+        }
+        
+        output_cap.push(&mut output_messages, message);
+    }
+    
+    // Warnings never fail a check, only errors (or cargo itself failing) do
+    let has_errors = diagnostics.iter().any(|diagnostic| diagnostic.severity == "error");
+    let success = match status {
+        Ok(status) => status.success() && !has_errors,
+        Err(e) => {
             output_messages.push(OutputMessage {
-                message_type: "INFO".to_string(),
-                content: "Updated Cargo.toml with library configuration".to_string(),
+                message_type: "ERROR".to_string(),
+                content: format!("Failed to run cargo check: {}", e),
                 timestamp: current_time_millis(),
             });
+            false
+        }
+    };
+    
+    CheckResult {
+        success,
+        diagnostics,
+        output_messages,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+    }
+}
+
+// Convert a cargo "compiler-message" into a diagnostic located at its primary span
+fn parse_cargo_diagnostic(json: &serde_json::Value, project_dir: &Path) -> Option<Diagnostic> {
+    if json["reason"] != "compiler-message" {
+        return None;
+    }
+    
+    let message = &json["message"];
+    let severity = message["level"].as_str()?.to_string();
+    let text = message["message"].as_str()?.to_string();
+    let spans = message["spans"].as_array().cloned().unwrap_or_default();
+    
+    // Skip rustc's closing summaries such as "aborting due to 2 previous errors", "3 warnings emitted" and --explain hints
+    if severity == "failure-note" || (spans.is_empty() && (text.starts_with("aborting due to") || text.ends_with("emitted"))) {
+        return None;
+    }
+    
+    let primary_span = spans
+        .iter()
+        .find(|span| span["is_primary"].as_bool().unwrap_or(false))
+        .or_else(|| spans.first());
+    
+    let span_number = |key: &str| primary_span.and_then(|span| span[key].as_u64()).map(|value| value as u32);
+    
+    let file = primary_span
+        .and_then(|span| span["file_name"].as_str())
+        .map(|file_name| project_dir.join(file_name).to_string_lossy().to_string());
+    
+    Some(Diagnostic {
+        severity,
+        message: text,
+        code: message["code"]["code"].as_str().map(|code| code.to_string()),
+        file,
+        line: span_number("line_start"),
+        column: span_number("column_start"),
+        end_line: span_number("line_end"),
+        end_column: span_number("column_end"),
+        rendered: message["rendered"].as_str().map(|rendered| rendered.to_string()),
+    })
+}
+
+// Diagnostics from Gradle output: kotlinc errors and warnings ("e: file:///src/Main.kt:12:5 message",
+// or "e: /src/Main.kt: (12, 5): message" from older versions), javac and AAPT errors
+// ("/src/Main.java:12: error: message"), the "What went wrong" summary of a failed build and
+// "> Task :app:xyz FAILED" markers for tasks that summary doesn't already explain
+pub fn parse_gradle_output(output: &[OutputMessage]) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = output.iter().map(|message| message.content.as_str()).collect();
+    let mut diagnostics = Vec::new();
+    let mut failed_tasks = Vec::new();
+    let mut summaries = Vec::new();
+    
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index].trim_end();
+        index += 1;
+        
+        if let Some(captures) = KOTLIN_DIAGNOSTIC_REGEX.captures(line).or_else(|| KOTLIN_LEGACY_DIAGNOSTIC_REGEX.captures(line)) {
+            let severity = if &captures[1] == "e" { "error" } else { "warning" };
+            diagnostics.push(located_diagnostic(severity, &captures[5], Some(&captures[2]), captures[3].parse().ok(), captures[4].parse().ok()));
+        } else if let Some(captures) = JAVAC_DIAGNOSTIC_REGEX.captures(line) {
+            // javac follows the message with the source line and a caret under the column
+            let column = match (lines.get(index), lines.get(index + 1)) {
+                (Some(_), Some(caret)) if caret.trim() == "^" => Some(caret.find('^').unwrap() as u32 + 1),
+                _ => captures.get(3).and_then(|column| column.as_str().parse().ok()),
+            };
+            diagnostics.push(located_diagnostic(&captures[4], &captures[5], Some(&captures[1]), captures[2].parse().ok(), column));
+        } else if let Some(task) = line.strip_prefix("> Task ").and_then(|rest| rest.strip_suffix(" FAILED")) {
+            failed_tasks.push(task.trim().to_string());
+        } else if line.trim() == "* What went wrong:" {
+            // The summary runs until the next "* Try:"-style section or a blank line
+            let mut summary = Vec::new();
+            while index < lines.len() && !lines[index].trim().is_empty() && !lines[index].starts_with("* ") {
+                summary.push(lines[index].trim());
+                index += 1;
+            }
             
-            artifacts.push(cargo_toml_path.to_string_lossy().to_string());
+            if !summary.is_empty() {
+                summaries.push(summary.join(" "));
+            }
         }
     }
     
-    // Create Java wrapper class
-    let java_dir = project_dir.join("android/src/main/java");
-    let package_path = package_name.replace(".", "/");
-    let java_package_dir = java_dir.join(&package_path);
+    for task in failed_tasks {
+        if !summaries.iter().any(|summary| summary.contains(&format!("'{}'", task))) {
+            diagnostics.push(simple_diagnostic("error", &format!("Task {} failed", task)));
+        }
+    }
+    for summary in summaries {
+        diagnostics.push(simple_diagnostic("error", &summary));
+    }
     
-    if let Err(e) = std::fs::create_dir_all(&java_package_dir) {
+    diagnostics
+}
+
+fn simple_diagnostic(severity: &str, message: &str) -> Diagnostic {
+    located_diagnostic(severity, message, None, None, None)
+}
+
+fn located_diagnostic(severity: &str, message: &str, file: Option<&str>, line: Option<u32>, column: Option<u32>) -> Diagnostic {
+    Diagnostic {
+        severity: severity.to_string(),
+        message: message.trim().to_string(),
+        code: None,
+        file: file.map(|file| file.trim_start_matches("file://").to_string()),
+        line,
+        column,
+        end_line: None,
+        end_column: None,
+        rendered: None,
+    }
+}
+
+// Build for Android target
+pub fn build_for_android_target(project_path: &str, target: &str, release: bool, toolchain: Option<&ToolchainPaths>) -> BuildResult {
+    run_target_build(project_path, target, if release { "release" } else { "debug" }, false, OutputCap::default(), toolchain)
+}
+
+// Cross-compile for a target triple with a build type as accepted by build_project
+fn run_target_build(project_path: &str, target: &str, build_type: &str, dry_run: bool, output_cap: OutputCap, toolchain: Option<&ToolchainPaths>) -> BuildResult {
+    let start_time = Instant::now();
+    let mut output_messages = Vec::new();
+    let mut artifacts = Vec::new();
+    let mut artifact_details = Vec::new();
+    
+    // Add initial message
+    output_messages.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: format!("Building for Android target: {}", target),
+        timestamp: current_time_millis(),
+    });
+    
+    // Check if project exists
+    let project_dir = Path::new(project_path);
+    if !project_dir.exists() || !project_dir.is_dir() {
         output_messages.push(OutputMessage {
             message_type: "ERROR".to_string(),
-            content: format!("Failed to create Java package directory: {}", e),
+            content: format!("Project directory does not exist: {}", project_path),
             timestamp: current_time_millis(),
         });
         
@@ -1151,81 +2639,2175 @@ linker = "x86_64-linux-android-clang"
             output_messages,
             duration_ms: start_time.elapsed().as_millis() as u64,
             artifacts,
+            artifact_details,
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
         };
     }
     
-    let java_wrapper_path = java_package_dir.join("RustLib.java");
-    let java_wrapper_content = format!(
-        r#"package {0};
+    // Check if Cargo.toml exists
+    let cargo_toml_path = project_dir.join("Cargo.toml");
+    if !cargo_toml_path.exists() {
+        output_messages.push(OutputMessage {
+            message_type: "ERROR".to_string(),
+            content: "Cargo.toml not found. Not a valid Rust project.".to_string(),
+            timestamp: current_time_millis(),
+        });
+        
+        return BuildResult {
+            success: false,
+            output_messages,
+            duration_ms: start_time.elapsed().as_millis() as u64,
+            artifacts,
+            artifact_details,
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+    }
+    
+    // Build command
+    let mut cmd = cargo_command(toolchain);
+    cmd.current_dir(project_dir);
+    cmd.arg("build");
+    cmd.arg("--target");
+    cmd.arg(target);
+    add_profile_args(&mut cmd, build_type);
+    
+    if dry_run {
+        return dry_run_result(&cmd, start_time);
+    }
+    
+    // Execute command
+    output_messages.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: format!("Running: {:?}", cmd),
+        timestamp: current_time_millis(),
+    });
+    
+    match cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
+        Ok(mut child) => {
+            // Read stdout
+            if let Some(stdout) = child.stdout.take() {
+                let reader = BufReader::new(stdout);
+                for line in reader.lines() {
+                    if let Ok(line) = line {
+                        let message_type = if line.contains("error") {
+                            "ERROR"
+                        } else if line.contains("warning") {
+                            "WARNING"
+                        } else {
+                            "INFO"
+                        };
+                        
+                        output_cap.push(&mut output_messages, OutputMessage {
+                            message_type: message_type.to_string(),
+                            content: line,
+                            timestamp: current_time_millis(),
+                        });
+                    }
+                }
+            }
+            
+            // Read stderr
+            if let Some(stderr) = child.stderr.take() {
+                let reader = BufReader::new(stderr);
+                for line in reader.lines() {
+                    if let Ok(line) = line {
+                        output_cap.push(&mut output_messages, OutputMessage {
+                            message_type: "ERROR".to_string(),
+                            content: line,
+                            timestamp: current_time_millis(),
+                        });
+                    }
+                }
+            }
+            
+            // Wait for the process to complete
+            match child.wait() {
+                Ok(status) => {
+                    let success = status.success();
+                    
+                    if success {
+                        output_messages.push(OutputMessage {
+                            message_type: "SUCCESS".to_string(),
+                            content: format!("Build for {} completed successfully", target),
+                            timestamp: current_time_millis(),
+                        });
+                        
+                        // Find artifacts
+                        let profile_dir = project_dir.join("target").join(target).join(profile_dir_name(build_type));
+                        
+                        for artifact in collect_artifacts(&profile_dir) {
+                            artifacts.push(artifact.path.clone());
+                            
+                            output_messages.push(OutputMessage {
+                                message_type: "ARTIFACT".to_string(),
+                                content: format!("Generated: {} ({})", artifact.path, artifact.kind),
+                                timestamp: current_time_millis(),
+                            });
+                            
+                            artifact_details.push(artifact);
+                        }
+                    } else {
+                        output_messages.push(OutputMessage {
+                            message_type: "ERROR".to_string(),
+                            content: format!("Build for {} failed with exit code: {}", target, status.code().unwrap_or(-1)),
+                            timestamp: current_time_millis(),
+                        });
+                    }
+                    
+                    // Explain failed Android builds, which are mostly toolchain setup problems
+                    let diagnostics = if !success && target.contains("-android") {
+                        diagnose_android_link(project_path, target, toolchain)
+                    } else {
+                        Vec::new()
+                    };
+                    
+                    BuildResult {
+                        success,
+                        output_messages,
+                        duration_ms: start_time.elapsed().as_millis() as u64,
+                        artifacts,
+                        artifact_details,
+                        test_summary: None,
+                        test_cases: Vec::new(),
+                        diagnostics,
+                    }
+                }
+                Err(e) => {
+                    output_messages.push(OutputMessage {
+                        message_type: "ERROR".to_string(),
+                        content: format!("Failed to wait for process: {}", e),
+                        timestamp: current_time_millis(),
+                    });
+                    
+                    BuildResult {
+                        success: false,
+                        output_messages,
+                        duration_ms: start_time.elapsed().as_millis() as u64,
+                        artifacts,
+                        artifact_details,
+                        test_summary: None,
+                        test_cases: Vec::new(),
+                        diagnostics: Vec::new(),
+                    }
+                }
+            }
+        }
+        Err(e) => {
+            output_messages.push(OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: format!("Failed to start process: {}", e),
+                timestamp: current_time_millis(),
+            });
+            
+            BuildResult {
+                success: false,
+                output_messages,
+                duration_ms: start_time.elapsed().as_millis() as u64,
+                artifacts,
+                artifact_details,
+                test_summary: None,
+                test_cases: Vec::new(),
+                diagnostics: Vec::new(),
+            }
+        }
+    }
+}
+
+// Work out why linking for an Android target fails: whether rustup has the target, whether
+// .cargo/config.toml names a linker that exists, whether ANDROID_NDK_HOME points at an NDK and
+// whether that NDK has the clang wrapper for the API level. Returns one diagnostic per problem,
+// so an empty list means the setup looks right
+pub fn diagnose_android_link(project_path: &str, target: &str, toolchain: Option<&ToolchainPaths>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let project_dir = Path::new(project_path);
+    
+    if !ANDROID_TARGETS.iter().any(|(triple, _, _)| *triple == target) {
+        let known: Vec<&str> = ANDROID_TARGETS.iter().map(|(triple, _, _)| *triple).collect();
+        diagnostics.push(simple_diagnostic("error", &format!("{} is not an Android target. Use one of {}", target, known.join(", "))));
+        return diagnostics;
+    }
+    
+    let mut rustup = match toolchain {
+        Some(toolchain) => toolchain.rustup_command(),
+        None => Command::new("rustup"),
+    };
+    match rustup.args(["target", "list", "--installed"]).output() {
+        Ok(output) if output.status.success() => {
+            if !String::from_utf8_lossy(&output.stdout).lines().any(|line| line.trim() == target) {
+                diagnostics.push(simple_diagnostic("error", &format!("The Rust standard library for {0} is not installed. Run `rustup target add {0}`", target)));
+            }
+        }
+        _ => diagnostics.push(simple_diagnostic("warning", "Couldn't run rustup to check the installed targets")),
+    }
+    
+    let configured_linker = target_linker(project_dir, target);
+    
+    let mut api_level = DEFAULT_ANDROID_API_LEVEL;
+    match &configured_linker {
+        Some((linker, source)) => {
+            if let Some(level) = linker_api_level(linker, target) {
+                api_level = level;
+            }
+            
+            if resolve_linker(linker, project_dir).is_none() {
+                diagnostics.push(simple_diagnostic("error", &format!(
+                    "The linker for {} set in {} doesn't exist: {}. Regenerate the Android bindings or fix the path",
+                    target, source, linker
+                )));
+            }
+        }
+        None => diagnostics.push(simple_diagnostic("error", &format!(
+            "No linker is configured for {0}, so cargo uses the host's `cc`. Generate the Android bindings or add `linker` under [target.{0}] in .cargo/config.toml",
+            target
+        ))),
+    }
+    
+    let ndk_root = match std::env::var("ANDROID_NDK_HOME") {
+        Ok(ndk_home) => {
+            let ndk_home = PathBuf::from(ndk_home);
+            if find_ndk_toolchain_bin(&ndk_home).is_some() {
+                Some(ndk_home)
+            } else {
+                diagnostics.push(simple_diagnostic("error", &format!(
+                    "ANDROID_NDK_HOME is set to {}, which doesn't contain an NDK toolchain",
+                    ndk_home.display()
+                )));
+                None
+            }
+        }
+        Err(_) => {
+            let found = find_ndk_build().and_then(|ndk_build| ndk_build.parent().map(Path::to_path_buf));
+            if found.is_none() {
+                diagnostics.push(simple_diagnostic("error", "Android NDK not found. Install the NDK and set ANDROID_NDK_HOME to it"));
+            }
+            found
+        }
+    };
+    
+    if let Some(bin_dir) = ndk_root.as_deref().and_then(find_ndk_toolchain_bin) {
+        let clang_triple = ndk_clang_triple(target);
+        let wrapper_extension = if cfg!(windows) { ".cmd" } else { "" };
+        
+        if !bin_dir.join(format!("{}{}-clang{}", clang_triple, api_level, wrapper_extension)).exists() {
+            let mut available: Vec<u32> = std::fs::read_dir(&bin_dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .filter_map(|entry| {
+                            let name = entry.file_name().to_string_lossy().to_string();
+                            name.strip_prefix(clang_triple)?.strip_suffix(&format!("-clang{}", wrapper_extension))?.parse().ok()
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            available.sort_unstable();
+            
+            let message = match (available.first(), available.last()) {
+                (Some(lowest), Some(highest)) => format!(
+                    "The NDK has no clang wrapper for {} at API level {}; it supports API levels {} to {}",
+                    target, api_level, lowest, highest
+                ),
+                _ => format!("The NDK has no clang wrappers for {}", target),
+            };
+            diagnostics.push(simple_diagnostic("error", &message));
+        }
+    }
+    
+    diagnostics
+}
+
+// The linker cargo uses for `target` in a project, with where it was set. An environment
+// override wins over the config files, as it does for cargo
+fn target_linker(project_dir: &Path, target: &str) -> Option<(String, String)> {
+    let env_var = format!("CARGO_TARGET_{}_LINKER", target.to_uppercase().replace('-', "_"));
+    std::env::var(&env_var)
+        .ok()
+        .map(|linker| (linker, env_var))
+        .or_else(|| configured_linker(project_dir, target))
+}
+
+// The API level in the name of an NDK clang wrapper, e.g. 21 for aarch64-linux-android21-clang
+fn linker_api_level(linker: &str, target: &str) -> Option<u32> {
+    let file_name = Path::new(linker).file_name()?.to_string_lossy().to_string();
+    file_name.strip_prefix(ndk_clang_triple(target))?.split('-').next()?.parse().ok()
+}
+
+// The linker configured for `target` in the project's cargo config, with the file it came from
+fn configured_linker(project_dir: &Path, target: &str) -> Option<(String, String)> {
+    [".cargo/config.toml", ".cargo/config"].iter().find_map(|relative_path| {
+        let table = std::fs::read_to_string(project_dir.join(relative_path)).ok()?.parse::<toml::Table>().ok()?;
+        let linker = table.get("target")?.get(target)?.get("linker")?.as_str()?;
+        Some((linker.to_string(), relative_path.to_string()))
+    })
+}
+
+// Find a linker the way cargo does: paths with a separator are taken relative to the project,
+// bare names are looked up on PATH
+fn resolve_linker(linker: &str, project_dir: &Path) -> Option<PathBuf> {
+    if linker.contains('/') || linker.contains('\\') {
+        let path = project_dir.join(linker);
+        return path.exists().then(|| path);
+    }
+    
+    let extensions: &[&str] = if cfg!(windows) { &["", ".exe", ".cmd"] } else { &[""] };
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .flat_map(|dir| extensions.iter().map(move |extension| dir.join(format!("{}{}", linker, extension))))
+        .find(|path| path.is_file())
+}
+
+// Generate Android bindings
+pub fn generate_android_bindings(project_path: &str, package_name: &str, api_level: u32) -> BuildResult {
+    let start_time = Instant::now();
+    let mut output_messages = Vec::new();
+    let mut artifacts = Vec::new();
+    
+    // Add initial message
+    output_messages.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: "Generating Android bindings".to_string(),
+        timestamp: current_time_millis(),
+    });
+    
+    // Check if project exists
+    let project_dir = Path::new(project_path);
+    if !project_dir.exists() || !project_dir.is_dir() {
+        output_messages.push(OutputMessage {
+            message_type: "ERROR".to_string(),
+            content: format!("Project directory does not exist: {}", project_path),
+            timestamp: current_time_millis(),
+        });
+        
+        return BuildResult {
+            success: false,
+            output_messages,
+            duration_ms: start_time.elapsed().as_millis() as u64,
+            artifacts,
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+    }
+    
+    // Check if Cargo.toml exists
+    let cargo_toml_path = project_dir.join("Cargo.toml");
+    if !cargo_toml_path.exists() {
+        output_messages.push(OutputMessage {
+            message_type: "ERROR".to_string(),
+            content: "Cargo.toml not found. Not a valid Rust project.".to_string(),
+            timestamp: current_time_millis(),
+        });
+        
+        return BuildResult {
+            success: false,
+            output_messages,
+            duration_ms: start_time.elapsed().as_millis() as u64,
+            artifacts,
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+    }
+    
+    // Resolve the NDK linkers before touching the project
+    let cargo_config_content = match generate_cargo_config(api_level) {
+        Ok(content) => content,
+        Err(e) => {
+            output_messages.push(OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: e.to_string(),
+                timestamp: current_time_millis(),
+            });
+            
+            return BuildResult {
+                success: false,
+                output_messages,
+                duration_ms: start_time.elapsed().as_millis() as u64,
+                artifacts,
+                artifact_details: Vec::new(),
+                test_summary: None,
+                test_cases: Vec::new(),
+                diagnostics: Vec::new(),
+            };
+        }
+    };
+    
+    // Create src directory if it doesn't exist
+    let src_dir = project_dir.join("src");
+    if !src_dir.exists() {
+        if let Err(e) = std::fs::create_dir_all(&src_dir) {
+            output_messages.push(OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: format!("Failed to create src directory: {}", e),
+                timestamp: current_time_millis(),
+            });
+            
+            return BuildResult {
+                success: false,
+                output_messages,
+                duration_ms: start_time.elapsed().as_millis() as u64,
+                artifacts,
+                artifact_details: Vec::new(),
+                test_summary: None,
+                test_cases: Vec::new(),
+                diagnostics: Vec::new(),
+            };
+        }
+    }
+    
+    // Create lib.rs with JNI bindings
+    let lib_rs_path = src_dir.join("lib.rs");
+    let lib_rs_content = android_jni_lib_source(package_name);
+    
+    if let Err(e) = std::fs::write(&lib_rs_path, lib_rs_content) {
+        output_messages.push(OutputMessage {
+            message_type: "ERROR".to_string(),
+            content: format!("Failed to write lib.rs: {}", e),
+            timestamp: current_time_millis(),
+        });
+        
+        return BuildResult {
+            success: false,
+            output_messages,
+            duration_ms: start_time.elapsed().as_millis() as u64,
+            artifacts,
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+    }
+    
+    output_messages.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: "Generated lib.rs with JNI bindings".to_string(),
+        timestamp: current_time_millis(),
+    });
+    
+    artifacts.push(lib_rs_path.to_string_lossy().to_string());
+    
+    // Create .cargo directory and config.toml
+    let cargo_config_dir = project_dir.join(".cargo");
+    if !cargo_config_dir.exists() {
+        if let Err(e) = std::fs::create_dir_all(&cargo_config_dir) {
+            output_messages.push(OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: format!("Failed to create .cargo directory: {}", e),
+                timestamp: current_time_millis(),
+            });
+            
+            return BuildResult {
+                success: false,
+                output_messages,
+                duration_ms: start_time.elapsed().as_millis() as u64,
+                artifacts,
+                artifact_details: Vec::new(),
+                test_summary: None,
+                test_cases: Vec::new(),
+                diagnostics: Vec::new(),
+            };
+        }
+    }
+    
+    let cargo_config_path = cargo_config_dir.join("config.toml");
+    
+    if let Err(e) = std::fs::write(&cargo_config_path, cargo_config_content) {
+        output_messages.push(OutputMessage {
+            message_type: "ERROR".to_string(),
+            content: format!("Failed to write .cargo/config.toml: {}", e),
+            timestamp: current_time_millis(),
+        });
+        
+        return BuildResult {
+            success: false,
+            output_messages,
+            duration_ms: start_time.elapsed().as_millis() as u64,
+            artifacts,
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+    }
+    
+    output_messages.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: format!("Generated .cargo/config.toml for Android targets (API level {})", api_level),
+        timestamp: current_time_millis(),
+    });
+    
+    artifacts.push(cargo_config_path.to_string_lossy().to_string());
+    
+    // Update Cargo.toml to ensure it's a library with cdylib type
+    if let Ok(cargo_toml_content) = std::fs::read_to_string(&cargo_toml_path) {
+        let mut updated_content = cargo_toml_content.clone();
+        
+        if !cargo_toml_content.contains("[lib]") {
+            updated_content.push_str("\n[lib]\ncrate-type = [\"cdylib\", \"staticlib\", \"rlib\"]\n");
+        } else if !cargo_toml_content.contains("crate-type") {
+            updated_content = updated_content.replace(
+                "[lib]",
+                "[lib]\ncrate-type = [\"cdylib\", \"staticlib\", \"rlib\"]"
+            );
+        }
+        
+        if !cargo_toml_content.contains("jni =") {
+            if cargo_toml_content.contains("[dependencies]") {
+                updated_content = updated_content.replace(
+                    "[dependencies]",
+                    "[dependencies]\njni = { version = \"0.21.1\", features = [\"invocation\"] }"
+                );
+            } else {
+                updated_content.push_str("\n[dependencies]\njni = { version = \"0.21.1\", features = [\"invocation\"] }\n");
+            }
+        }
+        
+        if updated_content != cargo_toml_content {
+            if let Err(e) = std::fs::write(&cargo_toml_path, updated_content) {
+                output_messages.push(OutputMessage {
+                    message_type: "ERROR".to_string(),
+                    content: format!("Failed to update Cargo.toml: {}", e),
+                    timestamp: current_time_millis(),
+                });
+                
+                return BuildResult {
+                    success: false,
+                    output_messages,
+                    duration_ms: start_time.elapsed().as_millis() as u64,
+                    artifacts,
+                    artifact_details: Vec::new(),
+                    test_summary: None,
+                    test_cases: Vec::new(),
+                    diagnostics: Vec::new(),
+                };
+            }
+            
+            output_messages.push(OutputMessage {
+                message_type: "INFO".to_string(),
+                content: "Updated Cargo.toml with library configuration".to_string(),
+                timestamp: current_time_millis(),
+            });
+            
+            artifacts.push(cargo_toml_path.to_string_lossy().to_string());
+        }
+    }
+    
+    // Create Java wrapper class
+    let java_dir = project_dir.join("android/src/main/java");
+    let package_path = package_name.replace(".", "/");
+    let java_package_dir = java_dir.join(&package_path);
+    
+    if let Err(e) = std::fs::create_dir_all(&java_package_dir) {
+        output_messages.push(OutputMessage {
+            message_type: "ERROR".to_string(),
+            content: format!("Failed to create Java package directory: {}", e),
+            timestamp: current_time_millis(),
+        });
+        
+        return BuildResult {
+            success: false,
+            output_messages,
+            duration_ms: start_time.elapsed().as_millis() as u64,
+            artifacts,
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+    }
+    
+    let java_wrapper_path = java_package_dir.join(format!("{}.java", JNI_BINDINGS_CLASS));
+    let java_wrapper_content = format!(
+        r#"package {0};
+
+import androidx.annotation.NonNull;
+
+/**
+ * Java wrapper for Rust library
+ */
+public class {1} {{
+    
+    static {{
+        System.loadLibrary("rust_lib");
+    }}
+    
+    /**
+     * Example method that calls into Rust code
+     * @return String returned from Rust
+     */
+    @NonNull
+    public static native String getGreeting();
+    
+    /**
+     * Example method that passes data to Rust
+     * @param input String to process
+     * @return Processed string from Rust
+     */
+    @NonNull
+    public static native String processString(@NonNull String input);
+}}"#,
+        package_name,
+        JNI_BINDINGS_CLASS,
+    );
+    
+    if let Err(e) = std::fs::write(&java_wrapper_path, java_wrapper_content) {
+        output_messages.push(OutputMessage {
+            message_type: "ERROR".to_string(),
+            content: format!("Failed to write Java wrapper class: {}", e),
+            timestamp: current_time_millis(),
+        });
+        
+        return BuildResult {
+            success: false,
+            output_messages,
+            duration_ms: start_time.elapsed().as_millis() as u64,
+            artifacts,
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+    }
+    
+    output_messages.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: format!("Generated Java wrapper class: {}.java", JNI_BINDINGS_CLASS),
+        timestamp: current_time_millis(),
+    });
+    
+    artifacts.push(java_wrapper_path.to_string_lossy().to_string());
+    
+    output_messages.push(OutputMessage {
+        message_type: "SUCCESS".to_string(),
+        content: "Android bindings generated successfully".to_string(),
+        timestamp: current_time_millis(),
+    });
+    
+    BuildResult {
+        success: true,
+        output_messages,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        artifacts,
+        artifact_details: Vec::new(),
+        test_summary: None,
+        test_cases: Vec::new(),
+        diagnostics: Vec::new(),
+    }
+}
+
+// API level the android-jni template configures the NDK linkers for
+const DEFAULT_ANDROID_API_LEVEL: u32 = 21;
+
+// Create a new Rust project from a template: "bin", "lib", "android-jni" (a cdylib exporting JNI
+// functions, with .cargo/config.toml for the NDK when one is installed) or "workspace" (with one
+// library member). An empty name uses the directory name. A directory that already has files is
+// only written into with `force`, and then only the template's files are replaced.
+// Returns the paths of the files written
+pub fn create_project(project_path: &str, template: &str, name: &str, force: bool) -> Result<Vec<String>> {
+    let project_dir = Path::new(project_path);
+    
+    let name = if name.is_empty() {
+        project_dir.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+    } else {
+        name.to_string()
+    };
+    let valid_name = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && name.chars().next().map_or(false, |c| c.is_ascii_alphabetic() || c == '_');
+    if !valid_name {
+        return Err(anyhow!("Invalid crate name: {:?}", name));
+    }
+    
+    let has_files = std::fs::read_dir(project_dir).map_or(false, |mut entries| entries.next().is_some());
+    if has_files && !force {
+        return Err(anyhow!("Directory is not empty: {}", project_path));
+    }
+    
+    // Templates that need more than their own files are checked before anything is written
+    let cargo_config = match template {
+        "bin" | "lib" | "workspace" => None,
+        "android-jni" => generate_cargo_config(DEFAULT_ANDROID_API_LEVEL).ok(),
+        _ => return Err(anyhow!("Unknown project template: {}", template)),
+    };
+    
+    let mut created = Vec::new();
+    write_project_file(project_dir, ".gitignore", "/target\n", &mut created)?;
+    
+    match template {
+        "bin" => {
+            write_project_file(project_dir, "Cargo.toml", &package_manifest(&name, ""), &mut created)?;
+            write_project_file(project_dir, "src/main.rs", "fn main() {\n    println!(\"Hello, world!\");\n}\n", &mut created)?;
+        }
+        "lib" => {
+            write_project_file(project_dir, "Cargo.toml", &package_manifest(&name, ""), &mut created)?;
+            write_project_file(project_dir, "src/lib.rs", LIB_TEMPLATE_SOURCE, &mut created)?;
+        }
+        "android-jni" => {
+            let lib_section = "\n[lib]\ncrate-type = [\"cdylib\"]\n\n[dependencies]\njni = \"0.21.1\"\n";
+            let package_name = format!("com.example.{}", name.replace('-', "_"));
+            
+            write_project_file(project_dir, "Cargo.toml", &package_manifest(&name, lib_section), &mut created)?;
+            write_project_file(project_dir, "src/lib.rs", &android_jni_lib_source(&package_name), &mut created)?;
+            if let Some(cargo_config) = cargo_config {
+                write_project_file(project_dir, ".cargo/config.toml", &cargo_config, &mut created)?;
+            }
+        }
+        _ => {
+            let workspace_manifest = format!("[workspace]\nmembers = [\"{}\"]\nresolver = \"2\"\n", name);
+            write_project_file(project_dir, "Cargo.toml", &workspace_manifest, &mut created)?;
+            write_project_file(project_dir, &format!("{}/Cargo.toml", name), &package_manifest(&name, ""), &mut created)?;
+            write_project_file(project_dir, &format!("{}/src/lib.rs", name), LIB_TEMPLATE_SOURCE, &mut created)?;
+        }
+    }
+    
+    Ok(created)
+}
+
+// Library source of the "lib" and "workspace" templates
+const LIB_TEMPLATE_SOURCE: &str = r#"pub fn add(left: u64, right: u64) -> u64 {
+    left + right
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        assert_eq!(add(2, 2), 4);
+    }
+}
+"#;
+
+// Cargo.toml for a package, with extra sections appended
+fn package_manifest(name: &str, extra: &str) -> String {
+    format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n{}", name, extra)
+}
+
+// Write a file of a new project, creating its directories, and record its path
+fn write_project_file(project_dir: &Path, relative_path: &str, content: &str, created: &mut Vec<String>) -> Result<()> {
+    let path = project_dir.join(relative_path);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| anyhow!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    
+    std::fs::write(&path, content).map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+    created.push(path.to_string_lossy().to_string());
+    Ok(())
+}
+
+// Name of the Java class generated Android bindings belong to
+const JNI_BINDINGS_CLASS: &str = "RustLib";
+
+// JNI symbol of a native method as the JVM looks it up: "Java_", the mangled package and class
+// names, then the mangled method name, joined by "_". Overloads, which need the mangled argument
+// signature appended, aren't covered
+pub fn jni_mangle(package: &str, class: &str, method: &str) -> String {
+    let mut symbol = String::from("Java_");
+    
+    for segment in package.split(|c| c == '.' || c == '/').filter(|segment| !segment.is_empty()) {
+        mangle_jni_name(segment, &mut symbol);
+        symbol.push('_');
+    }
+    mangle_jni_name(class, &mut symbol);
+    symbol.push('_');
+    mangle_jni_name(method, &mut symbol);
+    
+    symbol
+}
+
+// Escape one name for a JNI symbol: ASCII letters and digits are kept, "_", ";" and "[" become
+// "_1", "_2" and "_3", and anything else, such as "$" in nested class names, becomes "_0"
+// followed by the four lowercase hex digits of each UTF-16 unit
+fn mangle_jni_name(name: &str, symbol: &mut String) {
+    for c in name.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => symbol.push(c),
+            '_' => symbol.push_str("_1"),
+            ';' => symbol.push_str("_2"),
+            '[' => symbol.push_str("_3"),
+            _ => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    symbol.push_str(&format!("_0{:04x}", unit));
+                }
+            }
+        }
+    }
+}
+
+// lib.rs exporting example JNI functions for the JNI_BINDINGS_CLASS class of a Java package
+fn android_jni_lib_source(package_name: &str) -> String {
+    format!(
+        r#"use jni::JNIEnv;
+use jni::objects::{{JClass, JString}};
+use jni::sys::jstring;
+
+#[no_mangle]
+pub extern "C" fn {0}(env: JNIEnv, _class: JClass) -> jstring {{
+    let output = env.new_string("Hello from Rust!")
+        .expect("Couldn't create Java string!");
+    output.into_raw()
+}}
+
+#[no_mangle]
+pub extern "C" fn {1}(mut env: JNIEnv, _class: JClass, input: JString) -> jstring {{
+    let input: String = env.get_string(&input)
+        .expect("Couldn't get Java string!")
+        .into();
+    let output = format!("Rust processed: {{}}", input);
+    let output = env.new_string(output)
+        .expect("Couldn't create Java string!");
+    output.into_raw()
+}}
+"#,
+        jni_mangle(package_name, JNI_BINDINGS_CLASS, "getGreeting"),
+        jni_mangle(package_name, JNI_BINDINGS_CLASS, "processString"),
+    )
+}
+
+// Add a dependency, or change the version and features of an existing one, under [dependencies]
+// or [dev-dependencies]. Uses `cargo add` when this cargo has it, otherwise edits Cargo.toml in
+// place so its comments and ordering survive. Without a version, cargo picks the latest one
+// while the direct edit can only use "*"
+pub fn add_cargo_dependency(project_path: &str, crate_name: &str, version: Option<String>, features: Vec<String>, dev: bool, toolchain: Option<&ToolchainPaths>) -> Result<()> {
+    let cargo_toml_path = Path::new(project_path).join("Cargo.toml");
+    if !cargo_toml_path.exists() {
+        return Err(anyhow!("Cargo.toml not found. Not a valid Rust project."));
+    }
+    if crate_name.trim().is_empty() {
+        return Err(anyhow!("Crate name cannot be empty"));
+    }
+    
+    let mut cmd = cargo_command(toolchain);
+    cmd.current_dir(project_path).arg("add");
+    match &version {
+        Some(version) => cmd.arg(format!("{}@{}", crate_name, version)),
+        None => cmd.arg(crate_name),
+    };
+    if !features.is_empty() {
+        cmd.arg("--features").arg(features.join(","));
+    }
+    if dev {
+        cmd.arg("--dev");
+    }
+    
+    if run_cargo_edit(&mut cmd)? {
+        return Ok(());
+    }
+    
+    let version = version.unwrap_or_else(|| "*".to_string());
+    edit_cargo_manifest(&cargo_toml_path, |document| {
+        let table = document
+            .as_table_mut()
+            .entry(dependency_table(dev))
+            .or_insert(toml_edit::table())
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("{} is not a table", dependency_table(dev)))?;
+        
+        match table.get_mut(crate_name).and_then(|item| item.as_table_like_mut()) {
+            // Keep keys like path, optional or default-features of an existing detailed entry
+            Some(entry) => {
+                entry.insert("version", toml_edit::value(version));
+                if !features.is_empty() {
+                    entry.insert("features", toml_edit::value(features.into_iter().collect::<toml_edit::Array>()));
+                }
+            }
+            None if features.is_empty() => {
+                table.insert(crate_name, toml_edit::value(version));
+            }
+            None => {
+                let mut entry = toml_edit::InlineTable::new();
+                entry.insert("version", version.into());
+                entry.insert("features", features.into_iter().collect::<toml_edit::Array>().into());
+                table.insert(crate_name, toml_edit::value(entry));
+            }
+        }
+        
+        // Respace inline tables, which otherwise get lopsided around the new keys
+        if let Some(entry) = table.get_mut(crate_name).and_then(|item| item.as_inline_table_mut()) {
+            entry.fmt();
+        }
+        
+        Ok(())
+    })
+}
+
+// Remove a dependency from [dependencies] or [dev-dependencies], with `cargo remove` when this
+// cargo has it and by editing Cargo.toml in place otherwise
+pub fn remove_cargo_dependency(project_path: &str, crate_name: &str, dev: bool, toolchain: Option<&ToolchainPaths>) -> Result<()> {
+    let cargo_toml_path = Path::new(project_path).join("Cargo.toml");
+    if !cargo_toml_path.exists() {
+        return Err(anyhow!("Cargo.toml not found. Not a valid Rust project."));
+    }
+    
+    let mut cmd = cargo_command(toolchain);
+    cmd.current_dir(project_path).arg("remove").arg(crate_name);
+    if dev {
+        cmd.arg("--dev");
+    }
+    
+    if run_cargo_edit(&mut cmd)? {
+        return Ok(());
+    }
+    
+    edit_cargo_manifest(&cargo_toml_path, |document| {
+        document
+            .get_mut(dependency_table(dev))
+            .and_then(|item| item.as_table_like_mut())
+            .and_then(|table| table.remove(crate_name))
+            .map(|_| ())
+            .ok_or_else(|| anyhow!("{} is not in {}", crate_name, dependency_table(dev)))
+    })
+}
+
+fn dependency_table(dev: bool) -> &'static str {
+    if dev { "dev-dependencies" } else { "dependencies" }
+}
+
+// Run `cargo add` or `cargo remove`. Ok(false) when this cargo is too old to have the command
+// or could not be started, so the caller should edit Cargo.toml itself
+fn run_cargo_edit(cmd: &mut Command) -> Result<bool> {
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(_) => return Ok(false),
+    };
+    
+    if output.status.success() {
+        return Ok(true);
+    }
+    
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("no such command") || stderr.contains("no such subcommand") {
+        return Ok(false);
+    }
+    
+    // From the first error on, which includes cargo's "Caused by" details
+    match stderr.find("error") {
+        Some(start) => Err(anyhow!("{}", stderr[start..].trim())),
+        None => Err(anyhow!("cargo exited with code {}", output.status.code().unwrap_or(-1))),
+    }
+}
+
+// Parse Cargo.toml keeping its formatting, change it and write it back
+fn edit_cargo_manifest(cargo_toml_path: &Path, edit: impl FnOnce(&mut toml_edit::Document) -> Result<()>) -> Result<()> {
+    let content = std::fs::read_to_string(cargo_toml_path)?;
+    let mut document: toml_edit::Document = content
+        .parse()
+        .map_err(|e| anyhow!("Failed to parse Cargo.toml: {}", e))?;
+    
+    edit(&mut document)?;
+    
+    std::fs::write(cargo_toml_path, document.to_string())?;
+    Ok(())
+}
+
+// Direct dependencies with a newer release than the one in use. Uses cargo-outdated when it is
+// installed, otherwise compares the versions in Cargo.lock against the crates.io index; without
+// a lock file only releases outside the Cargo.toml requirement count. When the index can't be
+// reached the list is empty and `offline` is set
+pub fn check_outdated(project_path: &str, toolchain: Option<&ToolchainPaths>) -> OutdatedReport {
+    let mut report = OutdatedReport {
+        dependencies: Vec::new(),
+        offline: false,
+        source: "none".to_string(),
+        error: None,
+    };
+    
+    let project_dir = Path::new(project_path);
+    let manifest = match std::fs::read_to_string(project_dir.join("Cargo.toml")) {
+        Ok(content) => content,
+        Err(_) => {
+            report.error = Some("Cargo.toml not found. Not a valid Rust project.".to_string());
+            return report;
+        }
+    };
+    
+    if let Some(dependencies) = run_cargo_outdated(project_dir, toolchain) {
+        report.dependencies = dependencies;
+        report.source = "cargo-outdated".to_string();
+        return report;
+    }
+    
+    let manifest = match manifest.parse::<toml::Table>() {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            report.error = Some(format!("Failed to parse Cargo.toml: {}", e));
+            return report;
+        }
+    };
+    let locked = read_locked_versions(&project_dir.join("Cargo.lock"));
+    
+    report.source = "crates.io".to_string();
+    for (name, requirement, kind) in manifest_dependencies(&manifest) {
+        let locked_version = locked.get(&name).cloned();
+        let current = locked_version
+            .clone()
+            .unwrap_or_else(|| requirement.trim_start_matches(|c: char| "^=~>< ".contains(c)).to_string());
+        
+        match latest_crate_version(&name) {
+            Ok(Some(latest)) => {
+                let outdated = match locked_version {
+                    Some(_) => version_key(&latest) > version_key(&current),
+                    None => version_key(&latest) > version_key(&current) && !semver_compatible(&current, &latest),
+                };
+                if outdated {
+                    report.dependencies.push(OutdatedDep { name, current, latest, kind });
+                }
+            }
+            Ok(None) => {}
+            Err(CrateIndexError::Offline) => {
+                report.dependencies.clear();
+                report.offline = true;
+                return report;
+            }
+            Err(CrateIndexError::Other(e)) => {
+                report.error = Some(e);
+                return report;
+            }
+        }
+    }
+    
+    report
+}
+
+// cargo outdated's report for the direct dependencies, or None if it isn't installed or fails
+fn run_cargo_outdated(project_dir: &Path, toolchain: Option<&ToolchainPaths>) -> Option<Vec<OutdatedDep>> {
+    let output = cargo_command(toolchain)
+        .current_dir(project_dir)
+        .args(["outdated", "--format", "json", "--root-deps-only"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    
+    // One JSON object per workspace member
+    let mut dependencies = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let member: serde_json::Value = match serde_json::from_str(line) {
+            Ok(member) => member,
+            Err(_) => continue,
+        };
+        
+        for dependency in member["dependencies"].as_array().into_iter().flatten() {
+            let (name, current, latest) = match (dependency["name"].as_str(), dependency["project"].as_str(), dependency["latest"].as_str()) {
+                (Some(name), Some(current), Some(latest)) => (name, current, latest),
+                _ => continue,
+            };
+            
+            // "---" marks a dependency that was removed upstream rather than updated
+            if latest == current || latest == "---" {
+                continue;
+            }
+            
+            let kind = match dependency["kind"].as_str() {
+                Some("Development") => "dev",
+                Some("Build") => "build",
+                _ => "normal",
+            };
+            dependencies.push(OutdatedDep {
+                name: name.to_string(),
+                current: current.to_string(),
+                latest: latest.to_string(),
+                kind: kind.to_string(),
+            });
+        }
+    }
+    
+    Some(dependencies)
+}
+
+// (crate name on crates.io, version requirement, "normal" / "dev" / "build") of every registry
+// dependency; path and git dependencies have no release to compare against
+fn manifest_dependencies(manifest: &toml::Table) -> Vec<(String, String, String)> {
+    let workspace_dependencies = manifest.get("workspace").and_then(|workspace| workspace.get("dependencies"));
+    let sections = [
+        (manifest.get("dependencies"), "normal"),
+        (manifest.get("dev-dependencies"), "dev"),
+        (manifest.get("build-dependencies"), "build"),
+        (workspace_dependencies, "normal"),
+    ];
+    
+    let mut dependencies = Vec::new();
+    for (section, kind) in sections {
+        let table = match section.and_then(|section| section.as_table()) {
+            Some(table) => table,
+            None => continue,
+        };
+        
+        for (key, value) in table {
+            let (name, requirement) = match value {
+                toml::Value::String(requirement) => (key.as_str(), requirement.as_str()),
+                toml::Value::Table(detail) => match detail.get("version").and_then(|version| version.as_str()) {
+                    Some(requirement) if !detail.contains_key("path") && !detail.contains_key("git") => {
+                        let name = detail.get("package").and_then(|package| package.as_str()).unwrap_or(key);
+                        (name, requirement)
+                    }
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            
+            dependencies.push((name.to_string(), requirement.to_string(), kind.to_string()));
+        }
+    }
+    
+    dependencies
+}
+
+// Highest locked version of each package in Cargo.lock
+fn read_locked_versions(lock_path: &Path) -> HashMap<String, String> {
+    let mut versions: HashMap<String, String> = HashMap::new();
+    let lock = match std::fs::read_to_string(lock_path).ok().and_then(|content| content.parse::<toml::Table>().ok()) {
+        Some(lock) => lock,
+        None => return versions,
+    };
+    
+    for package in lock.get("package").and_then(|packages| packages.as_array()).into_iter().flatten() {
+        if let (Some(name), Some(version)) = (package.get("name").and_then(|v| v.as_str()), package.get("version").and_then(|v| v.as_str())) {
+            let newer = versions.get(name).map_or(true, |existing| version_key(version) > version_key(existing));
+            if newer {
+                versions.insert(name.to_string(), version.to_string());
+            }
+        }
+    }
+    
+    versions
+}
+
+// Numeric major, minor and patch for comparing versions; pre-release and build suffixes are ignored
+fn version_key(version: &str) -> Vec<u64> {
+    let release = version.split(|c| c == '-' || c == '+').next().unwrap_or("");
+    let mut key: Vec<u64> = release.split('.').map(|part| part.trim().parse().unwrap_or(0)).collect();
+    key.resize(3, 0);
+    key
+}
+
+// Whether `candidate` is allowed by the caret requirement `base`, e.g. "1.2" allows 1.9.0 but
+// "0.2" does not allow 0.3.0
+fn semver_compatible(base: &str, candidate: &str) -> bool {
+    let (base, candidate) = (version_key(base), version_key(candidate));
+    if base[0] > 0 {
+        base[0] == candidate[0]
+    } else if base[1] > 0 {
+        candidate[0] == 0 && base[1] == candidate[1]
+    } else {
+        candidate[..2] == [0, 0] && base[2] == candidate[2]
+    }
+}
+
+// Why the crates.io index could not answer
+enum CrateIndexError {
+    #[cfg_attr(not(feature = "download-support"), allow(dead_code))]
+    Offline,
+    Other(String),
+}
+
+// Newest stable, non-yanked version of a crate from the crates.io sparse index, None if there is no such crate
+#[cfg(feature = "download-support")]
+fn latest_crate_version(name: &str) -> std::result::Result<Option<String>, CrateIndexError> {
+    let name = name.to_lowercase();
+    let prefix = match name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    };
+    
+    let client = reqwest::blocking::Client::builder()
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(Duration::from_secs(15))
+        .build()
+        .map_err(|e| CrateIndexError::Other(e.to_string()))?;
+    
+    let response = client
+        .get(format!("https://index.crates.io/{}/{}", prefix, name))
+        .send()
+        .map_err(|e| if e.is_connect() || e.is_timeout() { CrateIndexError::Offline } else { CrateIndexError::Other(e.to_string()) })?;
+    
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let body = response
+        .error_for_status()
+        .and_then(|response| response.text())
+        .map_err(|e| CrateIndexError::Other(e.to_string()))?;
+    
+    // One JSON line per published version
+    let latest = body
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|release| !release["yanked"].as_bool().unwrap_or(false))
+        .filter_map(|release| release["vers"].as_str().map(str::to_string))
+        .filter(|version| !version.contains('-'))
+        .max_by_key(|version| version_key(version));
+    
+    Ok(latest)
+}
+
+#[cfg(not(feature = "download-support"))]
+fn latest_crate_version(_name: &str) -> std::result::Result<Option<String>, CrateIndexError> {
+    Err(CrateIndexError::Other(
+        "Checking crates.io is not enabled; install cargo-outdated or rebuild with the download-support feature".to_string(),
+    ))
+}
+
+// Check the project's lock file against the RustSec advisory database with cargo audit
+pub fn audit(project_path: &str, toolchain: Option<&ToolchainPaths>) -> AuditReport {
+    let mut report = AuditReport {
+        installed: true,
+        advisories: Vec::new(),
+        skipped: 0,
+        error: None,
+    };
+    
+    let project_dir = Path::new(project_path);
+    if !project_dir.join("Cargo.toml").exists() {
+        report.error = Some("Cargo.toml not found. Not a valid Rust project.".to_string());
+        return report;
+    }
+    
+    let output = match cargo_command(toolchain).current_dir(project_dir).args(["audit", "--json"]).output() {
+        Ok(output) => output,
+        Err(e) => {
+            report.error = Some(format!("Failed to run cargo: {}", e));
+            return report;
+        }
+    };
+    
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("no such command") {
+        report.installed = false;
+        report.error = Some("cargo-audit is not installed. Install it with `cargo install cargo-audit`".to_string());
+        return report;
+    }
+    
+    // cargo audit exits with an error when it finds vulnerabilities, so go by the JSON instead
+    let result: serde_json::Value = match serde_json::from_slice(&output.stdout) {
+        Ok(result) => result,
+        Err(_) => {
+            let message = stderr
+                .lines()
+                .map(str::trim)
+                .find(|line| line.starts_with("error"))
+                .unwrap_or_else(|| stderr.trim());
+            report.error = Some(if message.is_empty() {
+                format!("cargo audit failed with exit code: {}", output.status.code().unwrap_or(-1))
+            } else {
+                message.to_string()
+            });
+            return report;
+        }
+    };
+    
+    let vulnerabilities = result["vulnerabilities"]["list"].as_array().into_iter().flatten();
+    let warnings = result["warnings"]
+        .as_object()
+        .into_iter()
+        .flat_map(|warnings| warnings.values())
+        .filter_map(|entries| entries.as_array())
+        .flatten()
+        // Yanked crates are reported without an advisory
+        .filter(|entry| !entry["advisory"].is_null());
+    
+    let entries = vulnerabilities
+        .map(|entry| (entry, None))
+        .chain(warnings.map(|entry| (entry, entry["kind"].as_str())));
+    
+    for (entry, warning_kind) in entries {
+        match parse_advisory(entry, warning_kind) {
+            Some(advisory) => report.advisories.push(advisory),
+            None => report.skipped += 1,
+        }
+    }
+    
+    report
+}
+
+// One entry of cargo audit's vulnerability or warning list; `warning_kind` is set for warnings
+fn parse_advisory(entry: &serde_json::Value, warning_kind: Option<&str>) -> Option<Advisory> {
+    let advisory = &entry["advisory"];
+    let package = &entry["package"];
+    let advisory_id = advisory["id"].as_str()?.to_string();
+    
+    let severity = match warning_kind {
+        Some(kind) => kind.to_string(),
+        None => advisory["cvss"]
+            .as_str()
+            .and_then(cvss_severity)
+            .unwrap_or("unknown")
+            .to_string(),
+    };
+    
+    let url = advisory["url"]
+        .as_str()
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("https://rustsec.org/advisories/{}", advisory_id));
+    
+    Some(Advisory {
+        crate_name: package["name"].as_str()?.to_string(),
+        version: package["version"].as_str()?.to_string(),
+        advisory_id,
+        severity,
+        title: advisory["title"].as_str().unwrap_or_default().to_string(),
+        url,
+    })
+}
+
+// Qualitative severity of a CVSS v3 vector such as "CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H",
+// using the base score formula from the CVSS v3.1 specification
+fn cvss_severity(vector: &str) -> Option<&'static str> {
+    let metrics: HashMap<&str, &str> = vector
+        .split('/')
+        .skip(1)
+        .filter_map(|metric| metric.split_once(':'))
+        .collect();
+    let scope_changed = *metrics.get("S")? == "C";
+    
+    let attack_vector = match *metrics.get("AV")? {
+        "N" => 0.85,
+        "A" => 0.62,
+        "L" => 0.55,
+        "P" => 0.2,
+        _ => return None,
+    };
+    let attack_complexity = match *metrics.get("AC")? {
+        "L" => 0.77,
+        "H" => 0.44,
+        _ => return None,
+    };
+    let privileges_required = match (*metrics.get("PR")?, scope_changed) {
+        ("N", _) => 0.85,
+        ("L", false) => 0.62,
+        ("L", true) => 0.68,
+        ("H", false) => 0.27,
+        ("H", true) => 0.5,
+        _ => return None,
+    };
+    let user_interaction = match *metrics.get("UI")? {
+        "N" => 0.85,
+        "R" => 0.62,
+        _ => return None,
+    };
+    
+    let mut unaffected = 1.0;
+    for key in ["C", "I", "A"] {
+        unaffected *= match *metrics.get(key)? {
+            "H" => 1.0 - 0.56,
+            "L" => 1.0 - 0.22,
+            "N" => 1.0,
+            _ => return None,
+        };
+    }
+    let impact_subscore: f64 = 1.0 - unaffected;
+    
+    let impact = if scope_changed {
+        7.52 * (impact_subscore - 0.029) - 3.25 * (impact_subscore - 0.02).powi(15)
+    } else {
+        6.42 * impact_subscore
+    };
+    let exploitability = 8.22 * attack_vector * attack_complexity * privileges_required * user_interaction;
+    
+    let score = if impact <= 0.0 {
+        0.0
+    } else if scope_changed {
+        (1.08 * (impact + exploitability)).min(10.0)
+    } else {
+        (impact + exploitability).min(10.0)
+    };
+    // CVSS rounds up to one decimal place, working in integers to avoid floating point error
+    let scaled = (score * 100_000.0).round() as u64;
+    let tenths = if scaled % 10_000 == 0 { scaled / 10_000 } else { scaled / 10_000 + 1 };
+    let score = tenths as f64 / 10.0;
+    
+    Some(match score {
+        s if s >= 9.0 => "critical",
+        s if s >= 7.0 => "high",
+        s if s >= 4.0 => "medium",
+        s if s > 0.0 => "low",
+        _ => "none",
+    })
+}
+
+// List connected Android devices and emulators
+pub fn adb_devices(sdk_manager: &SdkManager) -> Result<Vec<AdbDevice>> {
+    let adb = find_adb(sdk_manager)?;
+    
+    let output = Command::new(&adb).args(["devices", "-l"]).output()?;
+    if !output.status.success() {
+        return Err(anyhow!("adb devices failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    
+    Ok(parse_adb_devices(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// Parse `adb devices -l` output, e.g. "emulator-5554  device product:sdk model:Pixel_6 device:emu64a"
+pub fn parse_adb_devices(output: &str) -> Vec<AdbDevice> {
+    output.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("List of devices") && !line.starts_with('*'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let serial = fields.next()?.to_string();
+            let state = fields.next()?.to_string();
+            let model = fields
+                .find_map(|field| field.strip_prefix("model:"))
+                .map(str::to_string);
+            
+            Some(AdbDevice {
+                serial,
+                state,
+                model,
+            })
+        })
+        .collect()
+}
+
+// Pair with a device over Wi-Fi using the host:port and six-digit code shown under
+// Developer options > Wireless debugging > Pair device with pairing code (Android 11+)
+pub fn adb_pair(host_port: &str, pairing_code: &str, sdk_manager: &SdkManager) -> AdbConnectionResult {
+    let host_port = host_port.trim();
+    let pairing_code = pairing_code.trim();
+    if !host_port.contains(':') {
+        return AdbConnectionResult::failed(host_port, "Pairing needs the host:port shown on the device's pairing dialog");
+    }
+    if pairing_code.is_empty() {
+        return AdbConnectionResult::failed(host_port, "Pairing code is empty");
+    }
+    
+    // "Successfully paired to 192.168.1.5:37099 [guid=adb-...]"
+    run_adb_network_command(sdk_manager, &["pair", host_port, pairing_code], host_port, |line| {
+        line.starts_with("Successfully paired")
+    })
+}
+
+// Connect to a device over the network. After pairing, use the address shown on the Wireless
+// debugging screen, which has a different port from the pairing dialog
+pub fn adb_connect(host_port: &str, sdk_manager: &SdkManager) -> AdbConnectionResult {
+    let host_port = host_port.trim();
+    if host_port.is_empty() {
+        return AdbConnectionResult::failed(host_port, "Device address is empty");
+    }
+    
+    // "connected to 192.168.1.5:5555" or "already connected to 192.168.1.5:5555"
+    run_adb_network_command(sdk_manager, &["connect", host_port], host_port, |line| {
+        line.starts_with("connected to") || line.starts_with("already connected to")
+    })
+}
+
+// Run adb pair or connect and decide the outcome from what it printed, since adb exits with 0
+// after "failed to connect to ..." on many versions
+fn run_adb_network_command(sdk_manager: &SdkManager, args: &[&str], host_port: &str, succeeded: fn(&str) -> bool) -> AdbConnectionResult {
+    let adb = match find_adb(sdk_manager) {
+        Ok(adb) => adb,
+        Err(e) => return AdbConnectionResult::failed(host_port, &e.to_string()),
+    };
+    
+    let output = match Command::new(&adb).args(args).stdin(Stdio::null()).output() {
+        Ok(output) => output,
+        Err(e) => return AdbConnectionResult::failed(host_port, &format!("Failed to run adb: {}", e)),
+    };
+    
+    let text = format!("{}\n{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    let lines: Vec<&str> = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    
+    if let Some(line) = lines.iter().find(|line| succeeded(line)) {
+        return AdbConnectionResult {
+            success: true,
+            host_port: host_port.to_string(),
+            message: line.to_string(),
+        };
+    }
+    
+    let message = if lines.is_empty() {
+        format!("adb {} exited with {}", args[0], output.status)
+    } else {
+        lines.join("\n")
+    };
+    AdbConnectionResult::failed(host_port, &message)
+}
+
+// Zipalign and sign an APK. Without a keystore the SDK's debug keystore is used, and created
+// with keytool on first use; `key_alias` and the passwords are then ignored. An empty `output`
+// writes "<name>-signed.apk" next to the input
+pub fn sign_apk(unsigned_apk: &str, keystore: Option<&str>, key_alias: &str, store_pass: &str, key_pass: &str, output: &str, sdk_manager: &SdkManager) -> BuildResult {
+    let start_time = Instant::now();
+    let control = BuildControl::default();
+    
+    let key = keystore.filter(|keystore| !keystore.is_empty()).map(|keystore| SigningKey {
+        keystore,
+        alias: key_alias,
+        store_pass,
+        key_pass,
+    });
+    let result = run_sign_apk(unsigned_apk, key, output, sdk_manager, &control);
+    
+    let (success, artifact_details) = match result {
+        Ok(signed_apk) => {
+            control.push(OutputMessage {
+                message_type: "SUCCESS".to_string(),
+                content: format!("Signed APK written to {}", signed_apk.display()),
+                timestamp: current_time_millis(),
+            });
+            (true, vec![artifact_for(&signed_apk, "apk")])
+        }
+        Err(e) => {
+            control.push(OutputMessage {
+                message_type: "ERROR".to_string(),
+                content: e.to_string(),
+                timestamp: current_time_millis(),
+            });
+            (false, Vec::new())
+        }
+    };
+    
+    BuildResult {
+        success,
+        output_messages: control.messages(),
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        artifacts: artifact_details.iter().map(|artifact| artifact.path.clone()).collect(),
+        artifact_details,
+        test_summary: None,
+        test_cases: Vec::new(),
+        diagnostics: Vec::new(),
+    }
+}
+
+// Release key for sign_apk
+struct SigningKey<'a> {
+    keystore: &'a str,
+    alias: &'a str,
+    store_pass: &'a str,
+    key_pass: &'a str,
+}
+
+fn run_sign_apk(unsigned_apk: &str, key: Option<SigningKey>, output: &str, sdk_manager: &SdkManager, control: &BuildControl) -> Result<PathBuf> {
+    let unsigned_apk = Path::new(unsigned_apk);
+    if !unsigned_apk.is_file() {
+        return Err(anyhow!("APK not found: {}", unsigned_apk.display()));
+    }
+    
+    let build_tools = sdk_manager
+        .get_build_tools_dir()
+        .ok_or_else(|| anyhow!("Android build-tools not found. Install build-tools from the SDK manager"))?;
+    let zipalign = build_tools.join(if cfg!(windows) { "zipalign.exe" } else { "zipalign" });
+    let apksigner = build_tools.join(if cfg!(windows) { "apksigner.bat" } else { "apksigner" });
+    if !zipalign.exists() || !apksigner.exists() {
+        return Err(anyhow!("zipalign or apksigner missing from {}", build_tools.display()));
+    }
+    
+    let output = if output.trim().is_empty() {
+        let stem = unsigned_apk.file_stem().unwrap_or_default().to_string_lossy();
+        let stem = stem.strip_suffix("-unsigned").unwrap_or(&stem);
+        unsigned_apk.with_file_name(format!("{}-signed.apk", stem))
+    } else {
+        PathBuf::from(output)
+    };
+    
+    let (keystore, key_alias, store_pass, key_pass) = match key {
+        Some(key) => (PathBuf::from(key.keystore), key.alias, key.store_pass, key.key_pass),
+        None => (debug_keystore(sdk_manager, control)?, DEBUG_KEY_ALIAS, DEBUG_KEYSTORE_PASSWORD, DEBUG_KEYSTORE_PASSWORD),
+    };
+    if !keystore.is_file() {
+        return Err(anyhow!("Keystore not found: {}", keystore.display()));
+    }
+    
+    // apksigner needs the aligned file as input and can't write over it, so align to a temporary
+    // file next to the output
+    let aligned = output.with_extension("aligned.apk");
+    let mut align = Command::new(&zipalign);
+    align.args(["-f", "-p", "4"]).arg(unsigned_apk).arg(&aligned);
+    run_signing_step(&mut align, "zipalign", control)?;
+    
+    // Passwords go through the environment rather than the command line, where other processes
+    // could read them
+    let mut sign = Command::new(&apksigner);
+    sign.arg("sign")
+        .arg("--ks").arg(&keystore)
+        .args(["--ks-key-alias", key_alias, "--ks-pass", "env:APKSIGNER_KS_PASS", "--key-pass", "env:APKSIGNER_KEY_PASS"])
+        .arg("--out").arg(&output)
+        .arg(&aligned)
+        .env("APKSIGNER_KS_PASS", store_pass)
+        .env("APKSIGNER_KEY_PASS", key_pass);
+    if sdk_manager.get_java_path().exists() {
+        sign.env("JAVA_HOME", sdk_manager.get_jdk_path());
+    }
+    
+    let signed = run_signing_step(&mut sign, "apksigner", control);
+    let _ = std::fs::remove_file(&aligned);
+    signed?;
+    
+    Ok(output)
+}
+
+// Run one signing tool, streaming its output to `control`
+fn run_signing_step(cmd: &mut Command, tool: &str, control: &BuildControl) -> Result<()> {
+    control.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: format!("Running {}", tool),
+        timestamp: current_time_millis(),
+    });
+    
+    let status = run_cargo_command(cmd, control).map_err(|e| anyhow!("Failed to run {}: {}", tool, e))?;
+    if !status.success() {
+        return Err(anyhow!("{} failed with exit code: {}", tool, status.code().unwrap_or(-1)));
+    }
+    
+    Ok(())
+}
+
+// The SDK's debug keystore, generated with the standard debug key the first time it's needed
+fn debug_keystore(sdk_manager: &SdkManager, control: &BuildControl) -> Result<PathBuf> {
+    let keystore = sdk_manager.get_debug_keystore_path();
+    if keystore.exists() {
+        return Ok(keystore);
+    }
+    
+    if let Some(parent) = keystore.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    
+    // Fall back to a keytool on PATH when the SDK manager has no JDK
+    let keytool = sdk_manager.get_keytool_path();
+    let mut cmd = Command::new(if keytool.exists() { keytool.as_os_str() } else { "keytool".as_ref() });
+    cmd.args(["-genkeypair", "-noprompt", "-keystore"])
+        .arg(&keystore)
+        .args([
+            "-storepass", DEBUG_KEYSTORE_PASSWORD,
+            "-alias", DEBUG_KEY_ALIAS,
+            "-keypass", DEBUG_KEYSTORE_PASSWORD,
+            "-keyalg", "RSA",
+            "-keysize", "2048",
+            "-validity", "10000",
+            "-dname", "CN=Android Debug,O=Android,C=US",
+        ]);
+    
+    control.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: format!("Creating debug keystore at {}", keystore.display()),
+        timestamp: current_time_millis(),
+    });
+    run_signing_step(&mut cmd, "keytool", control)?;
+    
+    Ok(keystore)
+}
+
+// Install an APK on a device, replacing any existing install
+pub fn adb_install(apk_path: &str, device_serial: Option<String>, sdk_manager: &SdkManager) -> BuildResult {
+    run_adb_install(apk_path, device_serial.as_deref(), sdk_manager, &BuildControl::default())
+}
+
+// Start installing an APK on a background thread; output is streamed through poll_build_output
+pub fn start_adb_install(apk_path: &str, device_serial: Option<String>, sdk_manager: SdkManager) -> String {
+    let build_id = Uuid::new_v4().to_string();
+    let control = Arc::new(BuildControl::default());
+    let result = Arc::new(Mutex::new(None));
+    
+    BACKGROUND_BUILDS.lock().unwrap().insert(build_id.clone(), BackgroundBuild {
+        control: control.clone(),
+        result: result.clone(),
+        start_time: Instant::now(),
+    });
+    
+    let apk_path = apk_path.to_string();
+    
+    thread::spawn(move || {
+        let install_result = run_adb_install(&apk_path, device_serial.as_deref(), &sdk_manager, &control);
+        *result.lock().unwrap() = Some(install_result);
+    });
+    
+    build_id
+}
+
+// Run `adb install -r`, recording its output on `control`
+fn run_adb_install(apk_path: &str, device_serial: Option<&str>, sdk_manager: &SdkManager, control: &BuildControl) -> BuildResult {
+    let start_time = Instant::now();
+    
+    let failure = |message: String| {
+        control.push(OutputMessage {
+            message_type: "ERROR".to_string(),
+            content: message,
+            timestamp: current_time_millis(),
+        });
+        
+        BuildResult {
+            success: false,
+            output_messages: control.messages(),
+            duration_ms: start_time.elapsed().as_millis() as u64,
+            artifacts: Vec::new(),
+            artifact_details: Vec::new(),
+            test_summary: None,
+            test_cases: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    };
+    
+    if !Path::new(apk_path).is_file() {
+        return failure(format!("APK not found: {}", apk_path));
+    }
+    
+    let adb = match find_adb(sdk_manager) {
+        Ok(adb) => adb,
+        Err(e) => return failure(e.to_string()),
+    };
+    
+    let mut cmd = Command::new(&adb);
+    if let Some(serial) = device_serial.filter(|serial| !serial.is_empty()) {
+        cmd.args(["-s", serial]);
+    }
+    cmd.args(["install", "-r", apk_path]);
+    
+    control.push(OutputMessage {
+        message_type: "INFO".to_string(),
+        content: format!("Installing {}", apk_path),
+        timestamp: current_time_millis(),
+    });
+    
+    let status = match run_cargo_command(&mut cmd, control) {
+        Ok(status) => status,
+        Err(e) => return failure(format!("Failed to run adb: {}", e)),
+    };
+    
+    // Older adb versions exit with 0 even when the install fails, so check what it printed too
+    let reported_failure = control.result_messages().iter().any(|message| message.content.contains("Failure ["));
+    
+    if !status.success() || reported_failure {
+        if control.is_cancelled() {
+            return failure("Install cancelled".to_string());
+        }
+        return failure("Install failed".to_string());
+    }
+    
+    control.push(OutputMessage {
+        message_type: "SUCCESS".to_string(),
+        content: "Install completed successfully".to_string(),
+        timestamp: current_time_millis(),
+    });
+    
+    BuildResult {
+        success: true,
+        output_messages: control.messages(),
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        artifacts: vec![apk_path.to_string()],
+        artifact_details: Vec::new(),
+        test_summary: None,
+        test_cases: Vec::new(),
+        diagnostics: Vec::new(),
+    }
+}
+
+// Helper functions
+
+// Resolve adb from the SDK manager's platform-tools
+fn find_adb(sdk_manager: &SdkManager) -> Result<PathBuf> {
+    let adb = sdk_manager.get_adb_path();
+    
+    if adb.exists() {
+        Ok(adb)
+    } else {
+        Err(anyhow!("adb not found. Install Android SDK platform-tools from the SDK manager"))
+    }
+}
+
+// Add the cargo arguments selecting a build type: "debug", "release" or a custom profile name.
+// Anything starting with '-' is passed through as extra cargo arguments
+fn add_profile_args(cmd: &mut Command, build_type: &str) {
+    match build_type {
+        "release" => {
+            cmd.arg("--release");
+        }
+        "debug" | "dev" => {}
+        // Any other profile from list_build_profiles, e.g. "release-lto"
+        profile if !profile.starts_with('-') => {
+            cmd.arg("--profile");
+            cmd.arg(profile);
+        }
+        _ => {
+            cmd.arg(build_type);
+        }
+    }
+}
+
+// Result of a dry run: a single message with the command that would have run
+fn dry_run_result(cmd: &Command, start_time: Instant) -> BuildResult {
+    BuildResult {
+        success: true,
+        output_messages: vec![OutputMessage {
+            message_type: "INFO".to_string(),
+            content: describe_command(cmd),
+            timestamp: current_time_millis(),
+        }],
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        artifacts: Vec::new(),
+        artifact_details: Vec::new(),
+        test_summary: None,
+        test_cases: Vec::new(),
+        diagnostics: Vec::new(),
+    }
+}
+
+// A command as it could be typed into a shell: working directory, environment changes, program and arguments
+fn describe_command(cmd: &Command) -> String {
+    let mut parts = Vec::new();
+    
+    if let Some(dir) = cmd.get_current_dir() {
+        parts.push(format!("cd {} &&", shell_quote(&dir.to_string_lossy())));
+    }
+    
+    let (removed, set): (Vec<_>, Vec<_>) = cmd.get_envs().partition(|(_, value)| value.is_none());
+    if !removed.is_empty() {
+        parts.push("env".to_string());
+        for (key, _) in removed {
+            parts.push(format!("-u {}", key.to_string_lossy()));
+        }
+    }
+    for (key, value) in set {
+        let value = value.map(|value| value.to_string_lossy()).unwrap_or_default();
+        parts.push(format!("{}={}", key.to_string_lossy(), shell_quote(&value)));
+    }
+    
+    parts.push(shell_quote(&cmd.get_program().to_string_lossy()));
+    parts.extend(cmd.get_args().map(|arg| shell_quote(&arg.to_string_lossy())));
+    
+    parts.join(" ")
+}
+
+// Quote a word for a POSIX shell when it contains anything but plain path characters
+fn shell_quote(word: &str) -> String {
+    let plain = !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c));
+    
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+// Directory under target/ that cargo writes a profile's output to
+fn profile_dir_name(profile: &str) -> &str {
+    match profile {
+        "release" | "bench" => "release",
+        "dev" | "debug" | "test" => "debug",
+        // Extra cargo arguments rather than a profile name build the dev profile
+        _ if profile.starts_with('-') => "debug",
+        _ => profile,
+    }
+}
+
+// Classified artifacts in a cargo profile directory (target/[<triple>/]debug or release) and its examples/
+pub fn collect_artifacts(profile_dir: &Path) -> Vec<Artifact> {
+    let mut artifacts = Vec::new();
+    
+    for dir in [profile_dir.to_path_buf(), profile_dir.join("examples")] {
+        let mut paths: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .collect(),
+            Err(_) => continue,
+        };
+        paths.sort();
+        
+        for path in paths {
+            if let Some(kind) = classify_artifact(&path) {
+                artifacts.push(artifact_for(&path, kind));
+            }
+        }
+    }
+    
+    artifacts
+}
+
+// Work out an artifact's kind from its extension, executable bit and directory.
+// Intermediate files (.d, .rlib, .rmeta, .pdb, lock files) and the hashed copies
+// cargo keeps next to examples return None.
+pub fn classify_artifact(path: &Path) -> Option<&'static str> {
+    let file_name = path.file_name()?.to_string_lossy();
+    if file_name.starts_with('.') {
+        return None;
+    }
+    
+    let dir_name = path
+        .parent()
+        .and_then(|parent| parent.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    
+    if dir_name == "examples" && has_metadata_hash(path) {
+        return None;
+    }
+    
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("so") | Some("dylib") | Some("dll") => Some("cdylib"),
+        Some("a") => Some("staticlib"),
+        Some("exe") | None if is_executable(path) => match dir_name.as_str() {
+            "examples" => Some("example"),
+            "deps" => Some("test"),
+            _ => Some("bin"),
+        },
+        _ => None,
+    }
+}
+
+// Test binaries cargo ran, from lines like "Running unittests src/lib.rs (target/debug/deps/app-1a2b3c4d5e6f7a8b)"
+// or the older "Running target/debug/deps/app-1a2b3c4d5e6f7a8b"
+fn parse_test_artifacts(output: &[OutputMessage], project_dir: &Path) -> Vec<Artifact> {
+    let mut artifacts: Vec<Artifact> = Vec::new();
+    
+    for message in output {
+        let line = message.content.trim();
+        let rest = match line.strip_prefix("Running ") {
+            Some(rest) => rest.trim(),
+            None => continue,
+        };
+        
+        let binary = match (rest.rfind('('), rest.ends_with(')')) {
+            (Some(open), true) => &rest[open + 1..rest.len() - 1],
+            _ => rest,
+        };
+        
+        let path = project_dir.join(binary);
+        if path.is_file() && !artifacts.iter().any(|artifact| Path::new(&artifact.path) == path) {
+            artifacts.push(artifact_for(&path, "test"));
+        }
+    }
+    
+    artifacts
+}
+
+// APKs under build/outputs/apk of the root project and each module, e.g. app/build/outputs/apk/debug/app-debug.apk,
+// for the variants named as in their assemble tasks: "Debug" for assembleDebug, "FreeRelease"
+// for assembleFreeRelease and "" for every variant
+fn collect_apks(project_dir: &Path, variants: &[&str]) -> Vec<Artifact> {
+    let mut module_dirs = vec![project_dir.to_path_buf()];
+    if let Ok(entries) = std::fs::read_dir(project_dir) {
+        module_dirs.extend(entries.filter_map(Result::ok).map(|entry| entry.path()).filter(|path| path.is_dir()));
+    }
+    
+    let mut apks = Vec::new();
+    for module_dir in module_dirs {
+        let apk_dir = module_dir.join("build").join("outputs").join("apk");
+        let mut module_apks = Vec::new();
+        find_apks(&apk_dir, &mut module_apks);
+        
+        // The directories below apk/ spell the variant, e.g. free/release/ for FreeRelease
+        apks.extend(module_apks.into_iter().filter(|apk| {
+            let variant: String = apk
+                .parent()
+                .and_then(|dir| dir.strip_prefix(&apk_dir).ok())
+                .map(|dir| dir.iter().map(|name| name.to_string_lossy().to_lowercase()).collect())
+                .unwrap_or_default();
+            variants.iter().any(|wanted| wanted.is_empty() || wanted.to_lowercase() == variant)
+        }));
+    }
+    
+    apks.sort();
+    apks.iter().map(|path| artifact_for(path, "apk")).collect()
+}
+
+// Collect .apk files below a directory; variants with product flavors nest one level deeper
+fn find_apks(dir: &Path, apks: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    
+    for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        if path.is_dir() {
+            find_apks(&path, apks);
+        } else if path.extension().map_or(false, |ext| ext == "apk") {
+            apks.push(path);
+        }
+    }
+}
+
+fn artifact_for(path: &Path, kind: &str) -> Artifact {
+    Artifact {
+        path: path.to_string_lossy().to_string(),
+        kind: kind.to_string(),
+        size_bytes: std::fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0),
+    }
+}
+
+// Whether a file name ends in cargo's 16-hex-digit metadata hash, e.g. "demo-1a2b3c4d5e6f7a8b"
+fn has_metadata_hash(path: &Path) -> bool {
+    let stem = match path.file_stem() {
+        Some(stem) => stem.to_string_lossy(),
+        None => return false,
+    };
+    
+    match stem.rsplit_once('-') {
+        Some((_, hash)) => hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    
+    std::fs::metadata(path)
+        .map(|metadata| metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "exe")
+}
+
+// Create a cargo command, using the explicit toolchain when one is given and PATH otherwise
+fn cargo_command(toolchain: Option<&ToolchainPaths>) -> Command {
+    match toolchain {
+        Some(toolchain) => toolchain.cargo_command(),
+        None => Command::new("cargo"),
+    }
+}
+
+// Run a cargo command to completion, recording its output on `control` as it is produced
+fn run_cargo_command(cmd: &mut Command, control: &BuildControl) -> std::io::Result<ExitStatus> {
+    run_streamed_command(cmd, control, classify_cargo_line)
+}
+
+// Run a command to completion, recording its output on `control` as it is produced. `classify`
+// picks each line's message type from the line and whether it came from stderr
+fn run_streamed_command(cmd: &mut Command, control: &BuildControl, classify: fn(&str, bool) -> &'static str) -> std::io::Result<ExitStatus> {
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    control.attach(child);
+    
+    thread::scope(|scope| {
+        if let Some(stderr) = stderr {
+            scope.spawn(move || {
+                for line in BufReader::new(stderr).lines().filter_map(Result::ok) {
+                    control.push_output(OutputMessage {
+                        message_type: classify(&line, true).to_string(),
+                        content: line,
+                        timestamp: current_time_millis(),
+                    });
+                }
+            });
+        }
+        
+        if let Some(stdout) = stdout {
+            for line in BufReader::new(stdout).lines().filter_map(Result::ok) {
+                control.push_output(OutputMessage {
+                    message_type: classify(&line, false).to_string(),
+                    content: line,
+                    timestamp: current_time_millis(),
+                });
+            }
+        }
+    });
+    
+    control.wait()
+}
 
-import androidx.annotation.NonNull;
+// Message type of a line of cargo output
+fn classify_cargo_line(line: &str, from_stderr: bool) -> &'static str {
+    if from_stderr {
+        // cargo reports progress on stderr too, so only flag real diagnostics
+        if line.trim_start().starts_with("error") {
+            "ERROR"
+        } else if line.trim_start().starts_with("warning") {
+            "WARNING"
+        } else {
+            "INFO"
+        }
+    } else if line.contains("error") {
+        "ERROR"
+    } else if line.contains("warning") {
+        "WARNING"
+    } else if line.contains("test result: ok") {
+        "SUCCESS"
+    } else {
+        "INFO"
+    }
+}
 
-/**
- * Java wrapper for Rust library
- */
-public class RustLib {{
-    
-    static {{
-        System.loadLibrary("rust_lib");
-    }}
+// Message type of a line of Gradle output, which mixes task progress, compiler diagnostics
+// ("e: ..." from kotlinc, "...: error: ..." from javac) and the final BUILD line
+fn classify_gradle_line(line: &str, _from_stderr: bool) -> &'static str {
+    let line = line.trim_start();
     
-    /**
-     * Example method that calls into Rust code
-     * @return String returned from Rust
-     */
-    @NonNull
-    public static native String getGreeting();
+    if line.starts_with("BUILD SUCCESSFUL") {
+        "SUCCESS"
+    } else if line.starts_with("BUILD FAILED")
+        || line.starts_with("FAILURE:")
+        || line.starts_with("e: ")
+        || line.contains(": error:")
+        || (line.starts_with("> Task ") && line.ends_with(" FAILED"))
+    {
+        "ERROR"
+    } else if line.starts_with("w: ") || line.contains(": warning:") {
+        "WARNING"
+    } else {
+        "INFO"
+    }
+}
+
+// Read the library crate name from Cargo.toml, preferring [lib] name over [package] name
+fn read_crate_name(cargo_toml_path: &Path) -> Result<String> {
+    let content = std::fs::read_to_string(cargo_toml_path)?;
     
-    /**
-     * Example method that passes data to Rust
-     * @param input String to process
-     * @return Processed string from Rust
-     */
-    @NonNull
-    public static native String processString(@NonNull String input);
-}}"#,
-        package_name
-    );
+    let mut section = String::new();
+    let mut package_name = None;
+    let mut lib_name = None;
     
-    if let Err(e) = std::fs::write(&java_wrapper_path, java_wrapper_content) {
-        output_messages.push(OutputMessage {
-            message_type: "ERROR".to_string(),
-            content: format!("Failed to write Java wrapper class: {}", e),
-            timestamp: current_time_millis(),
-        });
+    for line in content.lines() {
+        let line = line.trim();
         
-        return BuildResult {
-            success: false,
-            output_messages,
-            duration_ms: start_time.elapsed().as_millis() as u64,
-            artifacts,
-        };
+        if line.starts_with('[') {
+            section = line.trim_matches(|c| c == '[' || c == ']').trim().to_string();
+            continue;
+        }
+        
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim() != "name" {
+                continue;
+            }
+            
+            let value = value.trim().trim_matches('"').to_string();
+            match section.as_str() {
+                "package" => package_name = Some(value),
+                "lib" => lib_name = Some(value),
+                _ => {}
+            }
+        }
     }
     
-    output_messages.push(OutputMessage {
-        message_type: "INFO".to_string(),
-        content: "Generated Java wrapper class: RustLib.java".to_string(),
-        timestamp: current_time_millis(),
-    });
+    lib_name
+        .or(package_name)
+        .filter(|name| !name.is_empty())
+        .map(|name| name.replace('-', "_"))
+        .ok_or_else(|| anyhow!("Could not determine crate name from {}", cargo_toml_path.display()))
+}
+
+// Open a file with the desktop's default handler; there is nothing to open it with on Android
+fn open_in_browser(path: &Path) -> Result<()> {
+    if cfg!(target_os = "android") {
+        return Err(anyhow!("Opening documentation is not supported on Android"));
+    }
     
-    artifacts.push(java_wrapper_path.to_string_lossy().to_string());
+    let mut cmd = if cfg!(target_os = "windows") {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", "start", ""]);
+        cmd
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
     
-    output_messages.push(OutputMessage {
-        message_type: "SUCCESS".to_string(),
-        content: "Android bindings generated successfully".to_string(),
-        timestamp: current_time_millis(),
-    });
+    cmd.arg(path).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
     
-    BuildResult {
-        success: true,
-        output_messages,
-        duration_ms: start_time.elapsed().as_millis() as u64,
-        artifacts,
-    }
+    Ok(())
 }
 
-// Helper functions
-
 // Get OS information
 fn get_os_info() -> String {
     let mut info = String::new();
@@ -1273,6 +4855,109 @@ fn get_os_info() -> String {
 
 // Check if NDK is installed
 fn is_ndk_installed() -> bool {
+    find_ndk_build().is_some()
+}
+
+// Whether the installed NDK is recent enough for Rust's Android targets and has the clang
+// wrappers each target links with: at the API level of the project's configured linker, or
+// the one generate_cargo_config uses. None when there is no NDK, which the installation check
+// already reports
+fn ndk_compatibility_check(project_dir: Option<&Path>) -> Option<Check> {
+    let ndk_root = find_ndk_build()?.parent()?.to_path_buf();
+    let name = "NDK Compatibility".to_string();
+    
+    // "Pkg.Revision = 25.2.9519653"
+    let version = std::fs::read_to_string(ndk_root.join("source.properties"))
+        .ok()
+        .and_then(|properties| {
+            properties.lines().find_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                (key.trim() == "Pkg.Revision").then(|| value.trim().to_string())
+            })
+        });
+    
+    let version = match version {
+        Some(version) => version,
+        None => {
+            return Some(Check {
+                name,
+                status: "warning".to_string(),
+                message: format!("Couldn't read the NDK version from {}", ndk_root.join("source.properties").display()),
+            });
+        }
+    };
+    
+    let major = version.split('.').next().and_then(|major| major.parse::<u32>().ok()).unwrap_or(0);
+    if major < MIN_NDK_MAJOR_VERSION {
+        return Some(Check {
+            name,
+            status: "warning".to_string(),
+            message: format!("NDK {} is older than r{}, the oldest release Rust's Android targets support", version, MIN_NDK_MAJOR_VERSION),
+        });
+    }
+    
+    let api_levels: Vec<(&str, u32)> = ANDROID_TARGETS
+        .iter()
+        .map(|(triple, _, _)| {
+            let level = project_dir
+                .and_then(|project_dir| target_linker(project_dir, triple))
+                .and_then(|(linker, _)| linker_api_level(&linker, triple))
+                .unwrap_or(DEFAULT_ANDROID_API_LEVEL);
+            (*triple, level)
+        })
+        .collect();
+    
+    let wrapper_extension = if cfg!(windows) { ".cmd" } else { "" };
+    let bin_dir = find_ndk_toolchain_bin(&ndk_root);
+    let missing: Vec<String> = api_levels
+        .iter()
+        .filter(|(triple, level)| {
+            !bin_dir.as_ref().map_or(false, |bin_dir| {
+                bin_dir.join(format!("{}{}-clang{}", ndk_clang_triple(triple), level, wrapper_extension)).exists()
+            })
+        })
+        .map(|(triple, level)| format!("{} at API level {}", triple, level))
+        .collect();
+    
+    Some(if missing.is_empty() {
+        let mut levels: Vec<String> = api_levels.iter().map(|(_, level)| level.to_string()).collect();
+        levels.dedup();
+        Check {
+            name,
+            status: "passed".to_string(),
+            message: format!("NDK {} has linkers for all Android targets at API level {}", version, levels.join(", ")),
+        }
+    } else {
+        Check {
+            name,
+            status: "warning".to_string(),
+            message: format!("NDK {} has no linker for {}", version, missing.join(", ")),
+        }
+    })
+}
+
+// Clang triple prefix the NDK uses for a Rust Android target, e.g. armv7a-linux-androideabi
+// for armv7-linux-androideabi
+fn ndk_clang_triple(rust_target: &str) -> &str {
+    match rust_target {
+        "armv7-linux-androideabi" => "armv7a-linux-androideabi",
+        other => other,
+    }
+}
+
+// Locate ndk-build, checking ANDROID_NDK_HOME / ANDROID_NDK_ROOT before common install locations
+fn find_ndk_build() -> Option<PathBuf> {
+    let ndk_build_name = if cfg!(windows) { "ndk-build.cmd" } else { "ndk-build" };
+    
+    for var in &["ANDROID_NDK_HOME", "ANDROID_NDK_ROOT"] {
+        if let Ok(ndk_home) = std::env::var(var) {
+            let ndk_build = Path::new(&ndk_home).join(ndk_build_name);
+            if ndk_build.exists() {
+                return Some(ndk_build);
+            }
+        }
+    }
+    
     let ndk_build_paths = [
         "/opt/android-ndk/ndk-build",
         "/opt/android-sdk/ndk-bundle/ndk-build",
@@ -1285,33 +4970,186 @@ fn is_ndk_installed() -> bool {
     ];
     
     for path_pattern in &ndk_build_paths {
-        if path_pattern.contains("*") {
-            // Handle wildcard paths
-            let base_path = path_pattern.split("*").next().unwrap_or("");
-            let base_dir = Path::new(base_path);
-            
-            if base_dir.exists() && base_dir.is_dir() {
-                if let Ok(entries) = std::fs::read_dir(base_dir) {
-                    for entry in entries.filter_map(Result::ok) {
-                        let path = entry.path();
-                        if path.is_dir() {
-                            let ndk_build = path.join("ndk-build");
-                            if ndk_build.exists() {
-                                return true;
-                            }
-                        }
-                    }
+        if let Some(path) = expand_wildcard_path(path_pattern).into_iter().find(|path| path.is_file()) {
+            return Some(path);
+        }
+    }
+    
+    None
+}
+
+// Oldest NDK major release (r25) supported by Rust's Android targets
+const MIN_NDK_MAJOR_VERSION: u32 = 25;
+
+// The standard Android targets: Rust triple, ABI and the lowest API level the NDK builds it for
+const ANDROID_TARGETS: [(&str, &str, u32); 4] = [
+    ("aarch64-linux-android", "arm64-v8a", 21),
+    ("armv7-linux-androideabi", "armeabi-v7a", 19),
+    ("i686-linux-android", "x86", 19),
+    ("x86_64-linux-android", "x86_64", 21),
+];
+
+// Build several Android targets one after another; an empty list builds all four standard ones.
+// A failed target doesn't stop the rest, so every broken ABI shows up at once. The builds share
+// the target directory lock, so running them in parallel would not be any faster
+pub fn build_all_android_targets(project_path: &str, targets: Vec<String>, release: bool, toolchain: Option<&ToolchainPaths>) -> AggregateBuildResult {
+    let start_time = Instant::now();
+    let targets = if targets.is_empty() {
+        ANDROID_TARGETS.iter().map(|(triple, _, _)| triple.to_string()).collect()
+    } else {
+        targets
+    };
+    
+    let mut results = Vec::new();
+    let mut failed_targets = Vec::new();
+    
+    for target in targets {
+        let result = build_for_android_target(project_path, &target, release, toolchain);
+        if !result.success {
+            failed_targets.push(target.clone());
+        }
+        
+        let abi = ANDROID_TARGETS
+            .iter()
+            .find(|(triple, _, _)| *triple == target)
+            .map(|(_, abi, _)| abi.to_string());
+        results.push(TargetBuildResult { target, abi, result });
+    }
+    
+    AggregateBuildResult {
+        success: failed_targets.is_empty(),
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        targets: results,
+        failed_targets,
+    }
+}
+
+// The Android targets with their ABIs, marking the ones that still need `rustup target add`.
+// Without rustup none are marked installed
+pub fn android_targets(toolchain: Option<&ToolchainPaths>) -> Vec<AndroidTarget> {
+    let mut cmd = match toolchain {
+        Some(toolchain) => toolchain.rustup_command(),
+        None => Command::new("rustup"),
+    };
+    
+    let installed: Vec<String> = cmd
+        .args(["target", "list", "--installed"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).lines().map(|line| line.trim().to_string()).collect())
+        .unwrap_or_default();
+    
+    ANDROID_TARGETS
+        .iter()
+        .map(|(triple, abi, min_api)| AndroidTarget {
+            triple: triple.to_string(),
+            abi: abi.to_string(),
+            min_api: *min_api,
+            rustup_installed: installed.iter().any(|target| target == triple),
+        })
+        .collect()
+}
+
+// Generate a .cargo/config.toml pointing each Android target at the NDK's clang wrappers for an API level
+fn generate_cargo_config(api_level: u32) -> Result<String> {
+    let ndk_root = find_ndk_build()
+        .and_then(|ndk_build| ndk_build.parent().map(Path::to_path_buf))
+        .ok_or_else(|| anyhow!("Android NDK not found. Install the NDK or set ANDROID_NDK_HOME"))?;
+    
+    let bin_dir = find_ndk_toolchain_bin(&ndk_root)
+        .ok_or_else(|| anyhow!("No LLVM toolchain found in NDK at {}", ndk_root.display()))?;
+    
+    let (wrapper_extension, exe_extension) = if cfg!(windows) { (".cmd", ".exe") } else { ("", "") };
+    let ar_path = bin_dir.join(format!("llvm-ar{}", exe_extension));
+    
+    let mut config = String::new();
+    
+    for (rust_target, _, _) in &ANDROID_TARGETS {
+        let linker_path = bin_dir.join(format!("{}{}-clang{}", ndk_clang_triple(rust_target), api_level, wrapper_extension));
+        if !linker_path.exists() {
+            return Err(anyhow!(
+                "NDK at {} has no linker for {} at API level {}",
+                ndk_root.display(),
+                rust_target,
+                api_level
+            ));
+        }
+        
+        config.push_str(&format!(
+            "[target.{}]\nar = \"{}\"\nlinker = \"{}\"\n\n",
+            rust_target,
+            toml_path(&ar_path),
+            toml_path(&linker_path)
+        ));
+    }
+    
+    Ok(config.trim_end().to_string() + "\n")
+}
+
+// Locate toolchains/llvm/prebuilt/<host>/bin inside an NDK, preferring the current host's tag
+fn find_ndk_toolchain_bin(ndk_root: &Path) -> Option<PathBuf> {
+    let prebuilt_dir = ndk_root.join("toolchains").join("llvm").join("prebuilt");
+    
+    let host_tag = if cfg!(target_os = "windows") {
+        "windows-x86_64"
+    } else if cfg!(target_os = "macos") {
+        "darwin-x86_64"
+    } else {
+        "linux-x86_64"
+    };
+    
+    let host_bin = prebuilt_dir.join(host_tag).join("bin");
+    if host_bin.is_dir() {
+        return Some(host_bin);
+    }
+    
+    // Fall back to whatever host the NDK was packaged for (e.g. linux-aarch64 builds on Android)
+    std::fs::read_dir(&prebuilt_dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path().join("bin"))
+        .find(|bin| bin.is_dir())
+}
+
+// Format a path for a TOML string, using forward slashes so Windows paths need no escaping
+fn toml_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+// Expand a path pattern where any segment may be "*", e.g. /home/*/Android/Sdk/ndk/*/ndk-build
+fn expand_wildcard_path(pattern: &str) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::from("/")];
+    
+    for segment in pattern.split('/').filter(|segment| !segment.is_empty()) {
+        let mut next = Vec::new();
+        
+        for candidate in &candidates {
+            if segment == "*" {
+                if let Ok(entries) = std::fs::read_dir(candidate) {
+                    let mut dirs: Vec<PathBuf> = entries
+                        .filter_map(Result::ok)
+                        .map(|entry| entry.path())
+                        .filter(|path| path.is_dir())
+                        .collect();
+                    dirs.sort();
+                    next.extend(dirs);
+                }
+            } else {
+                let path = candidate.join(segment);
+                if path.exists() {
+                    next.push(path);
                 }
             }
-        } else {
-            // Handle exact paths
-            if Path::new(path_pattern).exists() {
-                return true;
-            }
         }
+        
+        if next.is_empty() {
+            return next;
+        }
+        candidates = next;
     }
     
-    false
+    candidates
 }
 
 // Get current time in milliseconds
@@ -1322,4 +5160,333 @@ fn current_time_millis() -> u64 {
         .duration_since(UNIX_EPOCH)
         .unwrap_or(Duration::from_secs(0))
         .as_millis() as u64
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // Fresh scratch directory for one test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("anyoneide-buildsystem-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    fn touch(path: &Path) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+    
+    #[test]
+    fn wildcard_paths_expand_every_segment() {
+        let root = temp_dir("wildcards");
+        touch(&root.join("home/alice/Android/Sdk/ndk/25.2.9519653/ndk-build"));
+        touch(&root.join("home/bob/Android/Sdk/ndk/26.1.10909125/ndk-build"));
+        std::fs::create_dir_all(root.join("home/carol/Android/Sdk/ndk/empty")).unwrap();
+        
+        let pattern = format!("{}/home/*/Android/Sdk/ndk/*/ndk-build", root.display());
+        let matches = expand_wildcard_path(&pattern);
+        
+        assert_eq!(
+            matches,
+            vec![
+                root.join("home/alice/Android/Sdk/ndk/25.2.9519653/ndk-build"),
+                root.join("home/bob/Android/Sdk/ndk/26.1.10909125/ndk-build"),
+            ]
+        );
+        
+        let _ = std::fs::remove_dir_all(&root);
+    }
+    
+    #[test]
+    fn wildcard_paths_without_matches_are_empty() {
+        let root = temp_dir("no-matches");
+        std::fs::create_dir_all(root.join("home/alice")).unwrap();
+        
+        let pattern = format!("{}/home/*/Android/Sdk/ndk/*/ndk-build", root.display());
+        assert!(expand_wildcard_path(&pattern).is_empty());
+        
+        let _ = std::fs::remove_dir_all(&root);
+    }
+    
+    #[test]
+    fn ndk_env_var_is_checked_first() {
+        let root = temp_dir("ndk-env");
+        let ndk_build_name = if cfg!(windows) { "ndk-build.cmd" } else { "ndk-build" };
+        touch(&root.join(ndk_build_name));
+        
+        std::env::set_var("ANDROID_NDK_HOME", &root);
+        let found = find_ndk_build();
+        std::env::remove_var("ANDROID_NDK_HOME");
+        
+        assert_eq!(found, Some(root.join(ndk_build_name)));
+        
+        let _ = std::fs::remove_dir_all(&root);
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn cancel_kills_a_process_being_waited_on() {
+        let control = Arc::new(BuildControl::default());
+        let child = Command::new("sleep").arg("30").spawn().unwrap();
+        control.attach(child);
+        
+        let waiter = {
+            let control = control.clone();
+            thread::spawn(move || control.wait())
+        };
+        
+        // Let the waiting thread start polling before cancelling
+        thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        control.cancel();
+        
+        let status = waiter.join().unwrap().unwrap();
+        assert!(!status.success());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(control.is_cancelled());
+    }
+    
+    // Run cargo on a generated project, using the cargo running the tests when there is one
+    fn cargo_succeeds(project_dir: &Path, args: &[&str]) -> bool {
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        Command::new(cargo)
+            .args(args)
+            .current_dir(project_dir)
+            .env("CARGO_TARGET_DIR", project_dir.join("target"))
+            .output()
+            .map_or(false, |output| output.status.success())
+    }
+    
+    #[test]
+    fn bin_lib_and_workspace_templates_build() {
+        for template in ["bin", "lib", "workspace"] {
+            let root = temp_dir(&format!("template-{}", template));
+            let created = create_project(&root.to_string_lossy(), template, "demo", false).unwrap();
+            
+            assert!(created.iter().all(|path| Path::new(path).is_file()));
+            assert!(root.join("Cargo.toml").is_file());
+            assert!(cargo_succeeds(&root, &["check", "--offline", "--quiet"]), "{} template does not build", template);
+            
+            let _ = std::fs::remove_dir_all(&root);
+        }
+    }
+    
+    #[test]
+    fn android_jni_template_has_jni_lib() {
+        let root = temp_dir("template-android-jni");
+        create_project(&root.to_string_lossy(), "android-jni", "demo-lib", false).unwrap();
+        
+        let manifest = std::fs::read_to_string(root.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("crate-type = [\"cdylib\"]"));
+        assert!(manifest.contains("jni = "));
+        
+        let source = std::fs::read_to_string(root.join("src/lib.rs")).unwrap();
+        assert!(source.contains(&jni_mangle("com.example.demo_lib", JNI_BINDINGS_CLASS, "getGreeting")));
+        assert!(source.contains(&jni_mangle("com.example.demo_lib", JNI_BINDINGS_CLASS, "processString")));
+        
+        // Building needs the jni crate, but the manifest itself must be valid
+        assert!(cargo_succeeds(&root, &["metadata", "--no-deps", "--offline", "--format-version", "1"]));
+        
+        let _ = std::fs::remove_dir_all(&root);
+    }
+    
+    #[test]
+    fn templates_refuse_non_empty_directories_without_force() {
+        let root = temp_dir("template-force");
+        touch(&root.join("notes.txt"));
+        
+        let error = create_project(&root.to_string_lossy(), "bin", "demo", false).unwrap_err();
+        assert!(error.to_string().starts_with("Directory is not empty"));
+        assert!(!root.join("Cargo.toml").exists());
+        
+        create_project(&root.to_string_lossy(), "bin", "demo", true).unwrap();
+        assert!(root.join("src/main.rs").is_file());
+        assert!(root.join("notes.txt").is_file());
+        
+        assert!(create_project(&root.to_string_lossy(), "applet", "demo", true).is_err());
+        assert!(create_project(&root.to_string_lossy(), "bin", "1demo", true).is_err());
+        
+        let _ = std::fs::remove_dir_all(&root);
+    }
+    
+    fn output_line(content: &str) -> OutputMessage {
+        OutputMessage {
+            message_type: "INFO".to_string(),
+            content: content.to_string(),
+            timestamp: 0,
+        }
+    }
+    
+    #[test]
+    fn result_lines_past_the_cap_are_parsed_but_not_reported() {
+        let control = BuildControl::with_output_cap(OutputCap::new(Some(2)));
+        control.push(output_line("Building project with type: debug"));
+        for line in ["a", "b", "c", "BUILD FAILED in 1s"] {
+            control.push_output(output_line(line));
+        }
+        control.push(output_line("Build failed"));
+        
+        let reported: Vec<String> = control.messages().into_iter().map(|message| message.content).collect();
+        assert_eq!(reported.len(), 5);
+        assert_eq!(reported[..3], ["Building project with type: debug", "a", "b"]);
+        assert!(reported[3].starts_with("Output truncated after 2 lines"));
+        assert_eq!(reported[4], "Build failed");
+        
+        // Only the lines results are parsed from are kept past the cap
+        let parsed: Vec<String> = control.result_messages().into_iter().map(|message| message.content).collect();
+        assert_eq!(parsed.len(), 6);
+        assert_eq!(parsed[4..], ["BUILD FAILED in 1s", "Build failed"]);
+    }
+    
+    #[test]
+    fn multi_line_diagnostics_past_the_cap_are_kept_whole() {
+        let control = BuildControl::with_output_cap(OutputCap::new(Some(1)));
+        let lines = [
+            "> Task :app:compileDebugJavaWithJavac",
+            "noise",
+            "/app/src/Main.java:3: error: ';' expected",
+            "    int x = 1",
+            "             ^",
+            "noise",
+            "* What went wrong:",
+            "Execution failed for task ':app:compileDebugJavaWithJavac'.",
+            "> Compilation failed; see the compiler error output for details.",
+            "",
+            "* Try:",
+            "noise",
+        ];
+        for line in lines {
+            control.push_output(output_line(line));
+        }
+        
+        let diagnostics = parse_gradle_output(&control.result_messages());
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].column, Some(14));
+        assert_eq!(
+            diagnostics[1].message,
+            "Execution failed for task ':app:compileDebugJavaWithJavac'. > Compilation failed; see the compiler error output for details."
+        );
+        assert!(control.result_messages().iter().all(|message| message.content != "noise"));
+    }
+    
+    #[test]
+    fn zero_output_cap_keeps_everything() {
+        let control = BuildControl::with_output_cap(OutputCap::new(Some(0)));
+        for index in 0..100 {
+            control.push_output(output_line(&index.to_string()));
+        }
+        assert_eq!(control.messages().len(), 100);
+    }
+    
+    #[test]
+    fn only_assembled_variants_are_collected() {
+        let root = temp_dir("apks");
+        touch(&root.join("app/build/outputs/apk/debug/app-debug.apk"));
+        touch(&root.join("app/build/outputs/apk/release/app-release-unsigned.apk"));
+        touch(&root.join("app/build/outputs/apk/free/debug/app-free-debug.apk"));
+        
+        let paths = |variants: &[&str]| -> Vec<String> {
+            collect_apks(&root, variants)
+                .into_iter()
+                .map(|artifact| Path::new(&artifact.path).file_name().unwrap().to_string_lossy().to_string())
+                .collect()
+        };
+        
+        assert_eq!(paths(&["Debug"]), vec!["app-debug.apk"]);
+        assert_eq!(paths(&["FreeDebug"]), vec!["app-free-debug.apk"]);
+        assert_eq!(paths(&[""]).len(), 3);
+        
+        let _ = std::fs::remove_dir_all(&root);
+    }
+    
+    fn gradle_diagnostics(lines: &[&str]) -> Vec<Diagnostic> {
+        let output: Vec<OutputMessage> = lines.iter().map(|line| output_line(line)).collect();
+        parse_gradle_output(&output)
+    }
+    
+    #[test]
+    fn kotlinc_errors_are_located() {
+        let diagnostics = gradle_diagnostics(&[
+            "> Task :app:compileDebugKotlin FAILED",
+            "e: file:///home/dev/App/app/src/main/java/com/example/MainActivity.kt:42:17 Unresolved reference: foo",
+            "w: /home/dev/App/app/src/main/java/com/example/Util.kt: (7, 9): Parameter 'x' is never used",
+        ]);
+        
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].severity, "error");
+        assert_eq!(diagnostics[0].file.as_deref(), Some("/home/dev/App/app/src/main/java/com/example/MainActivity.kt"));
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (Some(42), Some(17)));
+        assert_eq!(diagnostics[0].message, "Unresolved reference: foo");
+        
+        assert_eq!(diagnostics[1].severity, "warning");
+        assert_eq!(diagnostics[1].file.as_deref(), Some("/home/dev/App/app/src/main/java/com/example/Util.kt"));
+        assert_eq!((diagnostics[1].line, diagnostics[1].column), (Some(7), Some(9)));
+        
+        assert_eq!(diagnostics[2].message, "Task :app:compileDebugKotlin failed");
+        assert_eq!(diagnostics[2].file, None);
+    }
+    
+    #[test]
+    fn javac_and_aapt_errors_are_located() {
+        let diagnostics = gradle_diagnostics(&[
+            "/home/dev/App/app/src/main/java/com/example/Main.java:12: error: cannot find symbol",
+            "        Strin name = \"x\";",
+            "        ^",
+            "ERROR: /home/dev/App/app/src/main/res/layout/main.xml:5: AAPT: error: attribute android:textSize not found.",
+        ]);
+        
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].file.as_deref(), Some("/home/dev/App/app/src/main/java/com/example/Main.java"));
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (Some(12), Some(9)));
+        assert_eq!(diagnostics[0].message, "cannot find symbol");
+        
+        assert_eq!(diagnostics[1].severity, "error");
+        assert_eq!(diagnostics[1].file.as_deref(), Some("/home/dev/App/app/src/main/res/layout/main.xml"));
+        assert_eq!(diagnostics[1].line, Some(5));
+        assert_eq!(diagnostics[1].message, "attribute android:textSize not found.");
+    }
+    
+    #[test]
+    fn failed_task_explained_by_the_summary_is_reported_once() {
+        let diagnostics = gradle_diagnostics(&[
+            "> Task :app:mergeDebugResources FAILED",
+            "",
+            "FAILURE: Build failed with an exception.",
+            "",
+            "* What went wrong:",
+            "Execution failed for task ':app:mergeDebugResources'.",
+            "> Resource compilation failed.",
+            "",
+            "* Try:",
+            "> Run with --stacktrace option to get the stack trace.",
+            "",
+            "BUILD FAILED in 3s",
+        ]);
+        
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Execution failed for task ':app:mergeDebugResources'. > Resource compilation failed.");
+    }
+    
+    #[test]
+    fn jni_names_follow_the_spec_escapes() {
+        assert_eq!(jni_mangle("com.example", "RustLib", "getGreeting"), "Java_com_example_RustLib_getGreeting");
+        assert_eq!(jni_mangle("p.q.r", "A", "f"), "Java_p_q_r_A_f");
+        assert_eq!(jni_mangle("java/lang", "Object", "hashCode"), "Java_java_lang_Object_hashCode");
+        
+        // "_" is _1, ";" is _2, "[" is _3 and other characters are _0 and four lowercase hex digits
+        assert_eq!(jni_mangle("com.my_app", "Rust_Lib", "do_it"), "Java_com_my_1app_Rust_1Lib_do_1it");
+        assert_eq!(jni_mangle("a", "B", "f;[x"), "Java_a_B_f_2_3x");
+        assert_eq!(jni_mangle("com.example", "Café", "ü"), "Java_com_example_Caf_000e9__000fc");
+        assert_eq!(jni_mangle("a", "B", "😀"), "Java_a_B__0d83d_0de00");
+    }
+    
+    #[test]
+    fn jni_names_match_kotlin_companion_objects() {
+        assert_eq!(
+            jni_mangle("com.anyoneide.app.core", "RustNativeBuildManager$Companion", "nativeBuildProject"),
+            "Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeBuildProject"
+        );
+    }
+}
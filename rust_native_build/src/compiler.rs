@@ -1,11 +1,12 @@
 use std::process::{Command, Stdio};
 use std::io::{BufRead, BufReader};
-use std::path::{Path, PathBuf};
-use std::time::{Duration, Instant};
+use std::path::Path;
+use std::time::Instant;
 use std::fs;
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 use anyhow::{Result, anyhow};
+use crate::sdkmanager::SdkManager;
 
 // Compilation result
 #[derive(Serialize, Deserialize)]
@@ -38,6 +39,18 @@ pub struct CompilationWarning {
     pub code: Option<String>,
 }
 
+impl From<CompilationError> for CompilationWarning {
+    fn from(diagnostic: CompilationError) -> Self {
+        CompilationWarning {
+            file: diagnostic.file,
+            line: diagnostic.line,
+            column: diagnostic.column,
+            message: diagnostic.message,
+            code: diagnostic.code,
+        }
+    }
+}
+
 // Compiler options
 #[derive(Serialize, Deserialize)]
 pub struct CompilerOptions {
@@ -93,7 +106,9 @@ pub fn compile_rust(project_path: &str, options: &CompilerOptions) -> Result<Com
     // Add optimization level
     match options.optimization_level {
         0 => {} // Default debug build
-        1 => cmd.arg("--release"),
+        1 => {
+            cmd.arg("--release");
+        }
         2 => {
             cmd.arg("--release");
             cmd.env("RUSTFLAGS", "-C opt-level=2");
@@ -111,8 +126,8 @@ pub fn compile_rust(project_path: &str, options: &CompilerOptions) -> Result<Com
     // Add debug info
     if !options.debug_info {
         let rustflags = cmd.get_envs()
-            .find(|(key, _)| key == "RUSTFLAGS")
-            .map(|(_, value)| value.unwrap_or_default().to_string() + " -C debuginfo=0")
+            .find(|(key, _)| *key == "RUSTFLAGS")
+            .map(|(_, value)| value.map(|v| v.to_string_lossy().to_string()).unwrap_or_default() + " -C debuginfo=0")
             .unwrap_or_else(|| "-C debuginfo=0".to_string());
         
         cmd.env("RUSTFLAGS", rustflags);
@@ -171,7 +186,7 @@ pub fn compile_rust(project_path: &str, options: &CompilerOptions) -> Result<Com
                 // Parse warnings
                 if line.contains("warning:") {
                     if let Some(warning) = parse_diagnostic(&line, "warning:") {
-                        warnings.push(warning);
+                        warnings.push(warning.into());
                     }
                 }
             }
@@ -195,7 +210,7 @@ pub fn compile_rust(project_path: &str, options: &CompilerOptions) -> Result<Com
                 // Parse warnings in stderr
                 if line.contains("warning:") {
                     if let Some(warning) = parse_diagnostic(&line, "warning:") {
-                        warnings.push(warning);
+                        warnings.push(warning.into());
                     }
                 }
             }
@@ -259,18 +274,23 @@ pub fn compile_rust(project_path: &str, options: &CompilerOptions) -> Result<Com
 
 // Compile Kotlin code
 pub fn compile_kotlin(source_files: &[&str], output_dir: &str, classpath: &[&str], options: &HashMap<String, String>) -> Result<CompilationResult> {
-    let start_time = Instant::now();
-    let mut output = Vec::new();
-    let mut errors = Vec::new();
-    let mut warnings = Vec::new();
-    let mut artifacts = Vec::new();
-    
     // Check if Kotlin compiler is available
     let kotlinc = match find_kotlinc() {
         Some(path) => path,
         None => return Err(anyhow!("Kotlin compiler not found")),
     };
     
+    compile_kotlin_with(&kotlinc, source_files, output_dir, classpath, options, &[])
+}
+
+// Compile Kotlin code with a specific kotlinc
+fn compile_kotlin_with(kotlinc: &str, source_files: &[&str], output_dir: &str, classpath: &[&str], options: &HashMap<String, String>, env: &[(String, String)]) -> Result<CompilationResult> {
+    let start_time = Instant::now();
+    let mut output = Vec::new();
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut artifacts = Vec::new();
+    
     // Create output directory if it doesn't exist
     let output_path = Path::new(output_dir);
     if !output_path.exists() {
@@ -279,6 +299,7 @@ pub fn compile_kotlin(source_files: &[&str], output_dir: &str, classpath: &[&str
     
     // Build command
     let mut cmd = Command::new(kotlinc);
+    cmd.envs(env.iter().map(|(key, value)| (key, value)));
     
     // Add source files
     for source_file in source_files {
@@ -348,7 +369,7 @@ pub fn compile_kotlin(source_files: &[&str], output_dir: &str, classpath: &[&str
                     }
                 } else if line.contains("warning:") {
                     if let Some(warning) = parse_kotlin_diagnostic(&line, "warning:") {
-                        warnings.push(warning);
+                        warnings.push(warning.into());
                     }
                 }
             }
@@ -383,18 +404,23 @@ pub fn compile_kotlin(source_files: &[&str], output_dir: &str, classpath: &[&str
 
 // Compile Java code
 pub fn compile_java(source_files: &[&str], output_dir: &str, classpath: &[&str], options: &HashMap<String, String>) -> Result<CompilationResult> {
-    let start_time = Instant::now();
-    let mut output = Vec::new();
-    let mut errors = Vec::new();
-    let mut warnings = Vec::new();
-    let mut artifacts = Vec::new();
-    
     // Check if Java compiler is available
     let javac = match find_javac() {
         Some(path) => path,
         None => return Err(anyhow!("Java compiler not found")),
     };
     
+    compile_java_with(&javac, source_files, output_dir, classpath, options, &[])
+}
+
+// Compile Java code with a specific javac
+fn compile_java_with(javac: &str, source_files: &[&str], output_dir: &str, classpath: &[&str], options: &HashMap<String, String>, env: &[(String, String)]) -> Result<CompilationResult> {
+    let start_time = Instant::now();
+    let mut output = Vec::new();
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut artifacts = Vec::new();
+    
     // Create output directory if it doesn't exist
     let output_path = Path::new(output_dir);
     if !output_path.exists() {
@@ -403,6 +429,7 @@ pub fn compile_java(source_files: &[&str], output_dir: &str, classpath: &[&str],
     
     // Build command
     let mut cmd = Command::new(javac);
+    cmd.envs(env.iter().map(|(key, value)| (key, value)));
     
     // Add source files
     for source_file in source_files {
@@ -470,7 +497,7 @@ pub fn compile_java(source_files: &[&str], output_dir: &str, classpath: &[&str],
                     }
                 } else if line.contains("warning:") {
                     if let Some(warning) = parse_java_diagnostic(&line, "warning:") {
-                        warnings.push(warning);
+                        warnings.push(warning.into());
                     }
                 }
             }
@@ -503,6 +530,159 @@ pub fn compile_java(source_files: &[&str], output_dir: &str, classpath: &[&str],
     })
 }
 
+// Compile a single standalone source file.
+//
+// Inputs:
+//   file_path   - the .rs, .java or .kt file to compile
+//   language    - "rust", "java" or "kotlin"; empty to infer it from the file extension
+//   output_dir  - where the compiled output goes, created if missing
+//   sdk_manager - when given, its installed rustc/javac/kotlinc are preferred over the ones on PATH
+//
+// Output: a CompilationResult with the raw compiler output, errors and warnings with file/line/column,
+// and the produced artifacts (an executable or rlib for Rust, .class files for Java and Kotlin).
+// Compile failures are reported through `success` and `errors`; Err is only returned when the
+// file or a compiler cannot be found or the compiler cannot be started.
+pub fn compile_single_file(file_path: &str, language: &str, output_dir: &str, sdk_manager: Option<&SdkManager>) -> Result<CompilationResult> {
+    let source = Path::new(file_path);
+    if !source.is_file() {
+        return Err(anyhow!("Source file does not exist: {}", file_path));
+    }
+    
+    let language = if language.is_empty() {
+        match source.extension().and_then(|ext| ext.to_str()) {
+            Some("rs") => "rust",
+            Some("java") => "java",
+            Some("kt") | Some("kts") => "kotlin",
+            _ => return Err(anyhow!("Cannot infer language from file extension: {}", file_path)),
+        }
+    } else {
+        language
+    };
+    
+    match language.to_lowercase().as_str() {
+        "rust" => {
+            let (rustc, env) = match sdk_manager.filter(|sdk| sdk.is_rust_installed()) {
+                Some(sdk) => (sdk.get_rustc_path().to_string_lossy().to_string(), sdk.rust_environment()),
+                None => ("rustc".to_string(), Vec::new()),
+            };
+            
+            compile_rust_file(&rustc, file_path, output_dir, &env)
+        }
+        "java" => {
+            let (javac, env) = match sdk_manager.filter(|sdk| sdk.is_jdk_installed()) {
+                Some(sdk) => (sdk.get_javac_path().to_string_lossy().to_string(), sdk.java_environment()),
+                None => match find_javac() {
+                    Some(path) => (path, Vec::new()),
+                    None => return Err(anyhow!("Java compiler not found")),
+                },
+            };
+            
+            compile_java_with(&javac, &[file_path], output_dir, &[], &HashMap::new(), &env)
+        }
+        "kotlin" => {
+            let (kotlinc, env) = match sdk_manager.filter(|sdk| sdk.is_kotlin_installed()) {
+                Some(sdk) => (sdk.get_kotlinc_path().to_string_lossy().to_string(), sdk.java_environment()),
+                None => match find_kotlinc() {
+                    Some(path) => (path, Vec::new()),
+                    None => return Err(anyhow!("Kotlin compiler not found")),
+                },
+            };
+            
+            compile_kotlin_with(&kotlinc, &[file_path], output_dir, &[], &HashMap::new(), &env)
+        }
+        other => Err(anyhow!("Unsupported language: {}", other)),
+    }
+}
+
+// Compile a standalone Rust file with rustc
+fn compile_rust_file(rustc: &str, file_path: &str, output_dir: &str, env: &[(String, String)]) -> Result<CompilationResult> {
+    let start_time = Instant::now();
+    let mut output = Vec::new();
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut artifacts = Vec::new();
+    
+    // Create output directory if it doesn't exist
+    let output_path = Path::new(output_dir);
+    if !output_path.exists() {
+        fs::create_dir_all(output_path)?;
+    }
+    
+    // rustc needs a valid identifier for the crate name
+    let crate_name: String = Path::new(file_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "main".to_string())
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    
+    // Files without a main function are built as libraries
+    let is_binary = fs::read_to_string(file_path)
+        .map(|content| content.contains("fn main("))
+        .unwrap_or(true);
+    
+    // Build command
+    let mut cmd = Command::new(rustc);
+    cmd.envs(env.iter().map(|(key, value)| (key, value)));
+    cmd.arg(file_path);
+    cmd.args(["--edition", "2021"]);
+    cmd.args(["--crate-name", &crate_name]);
+    if !is_binary {
+        cmd.args(["--crate-type", "lib"]);
+    }
+    cmd.arg("--out-dir");
+    cmd.arg(output_path);
+    cmd.arg("--error-format=short");
+    
+    // Capture output
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    
+    output.push(format!("Running: {:?}", cmd));
+    
+    // Execute command; rustc reports everything on stderr
+    let result = cmd.output()?;
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    
+    for line in String::from_utf8_lossy(&result.stdout).lines().chain(stderr.lines()) {
+        output.push(line.to_string());
+        
+        match parse_rustc_short_diagnostic(line) {
+            Some((true, error)) => errors.push(error),
+            Some((false, warning)) => warnings.push(warning.into()),
+            None => {}
+        }
+    }
+    
+    let success = result.status.success();
+    
+    // Find artifacts if build was successful
+    if success {
+        let candidates = if is_binary {
+            vec![crate_name.clone(), format!("{}.exe", crate_name)]
+        } else {
+            vec![format!("lib{}.rlib", crate_name)]
+        };
+        
+        for candidate in candidates {
+            let path = output_path.join(candidate);
+            if path.is_file() {
+                artifacts.push(path.to_string_lossy().to_string());
+            }
+        }
+    }
+    
+    Ok(CompilationResult {
+        success,
+        output,
+        errors,
+        warnings,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        artifacts,
+    })
+}
+
 // Find Kotlin compiler
 fn find_kotlinc() -> Option<String> {
     // Try to find kotlinc in PATH
@@ -625,6 +805,37 @@ fn parse_diagnostic(line: &str, diagnostic_type: &str) -> Option<CompilationErro
     }
 }
 
+// Parse a rustc --error-format=short diagnostic, returning whether it is an error
+fn parse_rustc_short_diagnostic(line: &str) -> Option<(bool, CompilationError)> {
+    // Example: src/main.rs:10:5: error[E0308]: mismatched types
+    let (is_error, marker) = if let Some(idx) = line.find(": error") {
+        (true, idx)
+    } else if let Some(idx) = line.find(": warning") {
+        (false, idx)
+    } else {
+        return None;
+    };
+    
+    // Split from the right so paths containing ':' (Windows drives) survive
+    let mut location_parts = line[..marker].rsplitn(3, ':');
+    let column_num = location_parts.next()?.trim().parse::<u32>().ok()?;
+    let line_num = location_parts.next()?.trim().parse::<u32>().ok()?;
+    let file = location_parts.next()?.trim().to_string();
+    
+    let (kind, message) = line[marker + 2..].split_once(':')?;
+    let code = kind.find('[').and_then(|start| {
+        kind[start + 1..].find(']').map(|end| kind[start + 1..start + 1 + end].to_string())
+    });
+    
+    Some((is_error, CompilationError {
+        file,
+        line: line_num,
+        column: column_num,
+        message: message.trim().to_string(),
+        code,
+    }))
+}
+
 // Parse Kotlin compiler diagnostic message
 fn parse_kotlin_diagnostic(line: &str, diagnostic_type: &str) -> Option<CompilationError> {
     // Example: src/main/kotlin/com/example/Main.kt:10:5: error: expected ';', found '}'
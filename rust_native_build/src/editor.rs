@@ -1,9 +1,20 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex, Once};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex, MutexGuard, Once};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
 use anyhow::{Result, anyhow};
 use lazy_static::lazy_static;
 use regex::Regex;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use uuid::Uuid;
+use crate::sdkmanager::SdkManager;
+use crate::pluginsystem::{self, CompletionRequestPayload, HookPayload};
 
 // Optional tree-sitter support
 #[cfg(feature = "tree-sitter-support")]
@@ -17,16 +28,88 @@ pub struct SyntaxHighlight {
     pub type_: String,
 }
 
+// Highlights of a document. `degraded` is set when the document was over max_highlight_bytes
+// and only got the cheap highlighting, so the UI can say why types and calls are not colored
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DocumentHighlights {
+    pub highlights: Vec<SyntaxHighlight>,
+    pub degraded: bool,
+}
+
+// One document of a highlight_batch call; `id` is echoed back so callers can match up results
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HighlightRequest {
+    pub id: String,
+    pub content: String,
+    pub language: String,
+}
+
+// Highlights for one HighlightRequest. `cancelled` is set when nativeCancelEditorOp stopped it
+// for the request's id, leaving only the highlights found until then. `degraded` is set when the
+// document was over max_highlight_bytes and only got the cheap highlighting
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HighlightResult {
+    pub id: String,
+    pub highlights: Vec<SyntaxHighlight>,
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default)]
+    pub degraded: bool,
+}
+
+// Result of an editor operation that can be cancelled; when `cancelled` is set the result is
+// whatever was computed before the cancellation
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EditorOpResult<T> {
+    pub cancelled: bool,
+    pub result: T,
+}
+
+// What a completion item inserts; serialized as the lowercase names the UI maps to icons
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletionKind {
+    Keyword,
+    Snippet,
+    Function,
+    Variable,
+    Type,
+    Module,
+    Field,
+    Method,
+    Constant,
+    // A name found after a member-access token whose declaration isn't known
+    Member,
+}
+
 // Code completion
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CompletionItem {
     pub label: String,
-    pub kind: String,
+    pub kind: CompletionKind,
     pub detail: Option<String>,
     pub documentation: Option<String>,
     pub insert_text: String,
 }
 
+// Completions at a position. `trigger` is the member-access token before the word being
+// typed, e.g. "." in `foo.ba`, in which case the items are members rather than keywords and snippets
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompletionList {
+    pub trigger: Option<String>,
+    pub items: Vec<CompletionItem>,
+}
+
+// User-defined snippet, offered in completions when the word being typed is a prefix of `prefix`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnippetDef {
+    pub label: String,
+    pub prefix: String,
+    pub body: String, // ${1:placeholder} syntax, like the built-in snippets
+    #[serde(default)]
+    pub description: String,
+}
+
 // Code structure
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CodeStructure {
@@ -89,6 +172,101 @@ pub struct Reference {
     pub column: usize,
 }
 
+// Hover information
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HoverInfo {
+    pub signature: String,
+    pub doc: Option<String>,
+    pub kind: String, // function, class, struct, variable, etc.
+}
+
+// Semantic token: a name classified by its role, so themes can color declarations differently from uses
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SemanticToken {
+    pub start: usize,
+    pub end: usize,
+    pub token_type: String, // function, method, macro, class, struct, enum, enum_member, interface, type, namespace, parameter, variable, property
+    pub modifiers: Vec<String>, // declaration, mutable, static, async, readonly
+}
+
+// Replacement of the bytes start_byte..end_byte of a document
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub new_text: String,
+}
+
+// Settings used by format_code
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FormatOptions {
+    // Indentation to format with; empty uses each document's own
+    pub indent_unit: String,
+    // CSS: order declarations alphabetically within each rule
+    pub sort_properties: bool,
+    // Applied after each language's own formatter, except to Markdown, see normalize_whitespace
+    pub strip_trailing_whitespace: bool,
+    pub ensure_final_newline: bool,
+    pub trim_trailing_blank_lines: bool,
+    // Managed SDK whose JDK runs formatter jars; empty uses java from PATH
+    pub sdk_root: String,
+    // Kotlin and Java formatter executables or jars; empty looks for ktlint and
+    // google-java-format on PATH
+    pub ktlint_path: String,
+    pub google_java_format_path: String,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent_unit: String::new(),
+            sort_properties: false,
+            strip_trailing_whitespace: true,
+            ensure_final_newline: true,
+            trim_trailing_blank_lines: true,
+            sdk_root: String::new(),
+            ktlint_path: String::new(),
+            google_java_format_path: String::new(),
+        }
+    }
+}
+
+// Why a formatter left a document unchanged. Line and column are 1-based
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FormatDiagnostic {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+// Where a symbol is defined in a project
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SymbolLocation {
+    pub name: String,
+    pub file: String, // relative to the project root
+    pub line: usize,
+    pub kind: String, // class, interface, enum, etc., function or variable
+}
+
+// Symbols defined across a project, by name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectSymbolIndex {
+    pub root: String,
+    pub languages: Vec<String>, // empty for every language
+    pub files: HashSet<String>,
+    pub symbols: HashMap<String, Vec<SymbolLocation>>,
+}
+
+// A change to a watched file made outside the editor
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileEvent {
+    pub watch_id: String,
+    pub path: String,
+    pub kind: String, // created, modified, removed
+    pub timestamp: u64,
+}
+
 // Language configuration
 #[derive(Debug, Clone)]
 struct LanguageConfig {
@@ -101,33 +279,179 @@ struct LanguageConfig {
     comment_block_start: Option<String>,
     comment_block_end: Option<String>,
     string_delimiters: Vec<String>,
+    highlight_patterns: Option<HighlightPatterns>,
     #[cfg(feature = "tree-sitter-support")]
     tree_sitter_language: Option<fn() -> Language>,
 }
 
+// Regex highlight patterns for a language, compiled once when the language is registered
+#[derive(Debug, Clone)]
+struct HighlightPatterns {
+    keywords: Option<Regex>,
+    operators: Option<Regex>,
+    strings: Vec<Regex>,
+    line_comment: Option<Regex>,
+    block_comment: Option<Regex>,
+}
+
+impl HighlightPatterns {
+    fn compile(language_config: &LanguageConfig) -> Self {
+        // All keywords in one alternation so the content is scanned once instead of once per keyword
+        let keywords = if language_config.keywords.is_empty() {
+            None
+        } else {
+            let alternation: Vec<String> = language_config.keywords.iter().map(|keyword| regex::escape(keyword)).collect();
+            Regex::new(&format!(r"\b(?:{})\b", alternation.join("|"))).ok()
+        };
+        
+        // Longest operators first so `>>=` is not split into `>` and `>=`
+        let operators = if language_config.operators.is_empty() {
+            None
+        } else {
+            let mut operators: Vec<&String> = language_config.operators.iter().collect();
+            operators.sort_by(|a, b| b.len().cmp(&a.len()));
+            let alternation: Vec<String> = operators.iter().map(|operator| regex::escape(operator)).collect();
+            Regex::new(&alternation.join("|")).ok()
+        };
+        
+        let strings = language_config.string_delimiters.iter()
+            .filter_map(|delimiter| {
+                let escaped_delimiter = regex::escape(delimiter);
+                Regex::new(&format!(r"{0}(?:[^\\{0}]|\\.)*?{0}", escaped_delimiter)).ok()
+            })
+            .collect();
+        
+        let line_comment = language_config.comment_line.as_ref()
+            .and_then(|line_comment| Regex::new(&format!(r"(?m){0}.*$", regex::escape(line_comment))).ok());
+        
+        let block_comment = match (&language_config.comment_block_start, &language_config.comment_block_end) {
+            (Some(block_start), Some(block_end)) => {
+                Regex::new(&format!(r"{0}[\s\S]*?{1}", regex::escape(block_start), regex::escape(block_end))).ok()
+            }
+            _ => None,
+        };
+        
+        HighlightPatterns {
+            keywords,
+            operators,
+            strings,
+            line_comment,
+            block_comment,
+        }
+    }
+}
+
 // Editor state
 struct EditorState {
     initialized: bool,
     languages: HashMap<String, LanguageConfig>,
+    // Snippets registered for the session, by language
+    snippets: HashMap<String, Vec<SnippetDef>>,
+    format_options: FormatOptions,
+    // Documents larger than this get highlight_large_document instead of full highlighting
+    max_highlight_bytes: usize,
     #[cfg(feature = "tree-sitter-support")]
     parsers: HashMap<String, Parser>,
 }
 
+// Default for EditorState::max_highlight_bytes
+const DEFAULT_MAX_HIGHLIGHT_BYTES: usize = 1024 * 1024;
+
 // Global editor state
 lazy_static! {
     static ref EDITOR_STATE: Mutex<EditorState> = Mutex::new(EditorState {
         initialized: false,
         languages: HashMap::new(),
+        snippets: HashMap::new(),
+        format_options: FormatOptions::default(),
+        max_highlight_bytes: DEFAULT_MAX_HIGHLIGHT_BYTES,
         #[cfg(feature = "tree-sitter-support")]
         parsers: HashMap::new(),
     });
     
     static ref INIT_ONCE: Once = Once::new();
+    
+    // Symbol index of the last project indexed, kept for lookups and incremental updates
+    static ref PROJECT_INDEX: Mutex<Option<ProjectSymbolIndex>> = Mutex::new(None);
+    
+    // Files watched for outside changes, and the changes not yet polled
+    static ref FILE_WATCHES: Mutex<HashMap<String, RecommendedWatcher>> = Mutex::new(HashMap::new());
+    static ref FILE_EVENTS: Mutex<Vec<FileEvent>> = Mutex::new(Vec::new());
+    
+    // Cancellation flags of the editor operations running for each document id
+    static ref EDITOR_OPS: Mutex<HashMap<String, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+    
+    // Language-independent highlight patterns, compiled once
+    static ref NUMBER_REGEX: Regex = Regex::new(r"\b\d+(\.\d+)?([eE][+-]?\d+)?\b").unwrap();
+    static ref FUNCTION_CALL_REGEX: Regex = Regex::new(r"\b(\w+)\s*\(").unwrap();
+    static ref TYPE_NAME_REGEX: Regex = Regex::new(r"\b[A-Z][a-zA-Z0-9_]*\b").unwrap();
+    
+    // Names offered after a member-access token: ones accessed as members, and declared functions
+    static ref MEMBER_NAME_REGEX: Regex =
+        Regex::new(r"(?:\.|::|->)\s*([A-Za-z_][A-Za-z0-9_]*)|\b(?:fn|fun|def|function)\s+([A-Za-z_][A-Za-z0-9_]*)").unwrap();
+    
+    // Embedded code blocks in HTML
+    static ref SCRIPT_BLOCK_REGEX: Regex = Regex::new(r"(?is)<script\b([^>]*)>(.*?)</script\s*>").unwrap();
+    static ref STYLE_BLOCK_REGEX: Regex = Regex::new(r"(?is)<style\b[^>]*>(.*?)</style\s*>").unwrap();
+    static ref TYPE_ATTRIBUTE_REGEX: Regex = Regex::new(r#"(?i)\btype\s*=\s*["']?([^"'\s>]+)"#).unwrap();
+    
+    // Markdown inline syntax
+    static ref MARKDOWN_HEADING_REGEX: Regex = Regex::new(r"(?m)^ {0,3}#{1,6}(?:[ \t][^\n]*)?$").unwrap();
+    static ref MARKDOWN_CODE_SPAN_REGEX: Regex = Regex::new(r"`[^`\n]+`").unwrap();
+    static ref MARKDOWN_BOLD_REGEX: Regex = Regex::new(r"\*\*[^*\n]+\*\*|__[^_\n]+__").unwrap();
+    static ref MARKDOWN_ITALIC_REGEX: Regex = Regex::new(r"\*[^*\s][^*\n]*\*|\b_[^_\s][^_\n]*_\b").unwrap();
+    static ref MARKDOWN_LINK_REGEX: Regex = Regex::new(r"!?\[[^\]\n]*\]\([^)\n]*\)").unwrap();
+    
+    // Rust declarations for parse_rust_structure_regex
+    static ref RUST_STRUCT_REGEX: Regex = Regex::new(r"(?m)^(?:pub\s+)?struct\s+(\w+)").unwrap();
+    static ref RUST_ENUM_REGEX: Regex = Regex::new(r"(?m)^(?:pub\s+)?enum\s+(\w+)").unwrap();
+    static ref RUST_TRAIT_REGEX: Regex = Regex::new(r"(?m)^(?:pub\s+)?trait\s+(\w+)").unwrap();
+    static ref RUST_FUNCTION_REGEX: Regex = Regex::new(r"(?m)^(?:pub\s+)?fn\s+(\w+)").unwrap();
+    static ref RUST_VARIABLE_REGEX: Regex = Regex::new(r"(?m)^(?:let|const|static)\s+(?:mut\s+)?(\w+)").unwrap();
+    static ref RUST_IMPORT_REGEX: Regex = Regex::new(r"(?m)^use\s+([^;]+);").unwrap();
+    
+    // Kotlin declarations for parse_kotlin_structure_regex
+    static ref KOTLIN_CLASS_REGEX: Regex = Regex::new(r"(?m)^(?:(?:public|private|protected|internal)\s+)?(?:abstract\s+)?class\s+(\w+)").unwrap();
+    static ref KOTLIN_INTERFACE_REGEX: Regex = Regex::new(r"(?m)^(?:(?:public|private|protected|internal)\s+)?interface\s+(\w+)").unwrap();
+    static ref KOTLIN_FUNCTION_REGEX: Regex = Regex::new(r"(?m)^(?:(?:public|private|protected|internal)\s+)?(?:fun\s+)(\w+)").unwrap();
+    static ref KOTLIN_VARIABLE_REGEX: Regex = Regex::new(r"(?m)^(?:(?:public|private|protected|internal)\s+)?(?:val|var)\s+(\w+)").unwrap();
+    static ref KOTLIN_IMPORT_REGEX: Regex = Regex::new(r"(?m)^import\s+([^;]+)").unwrap();
+    
+    // Java declarations for parse_java_structure_regex
+    static ref JAVA_CLASS_REGEX: Regex = Regex::new(r"(?m)^(?:(?:public|private|protected)\s+)?(?:abstract\s+)?class\s+(\w+)").unwrap();
+    static ref JAVA_INTERFACE_REGEX: Regex = Regex::new(r"(?m)^(?:(?:public|private|protected)\s+)?interface\s+(\w+)").unwrap();
+    static ref JAVA_METHOD_REGEX: Regex = Regex::new(r"(?m)^(?:(?:public|private|protected)\s+)?(?:static\s+)?(?:final\s+)?(?:[\w<>\[\],\s]+)\s+(\w+)\s*\(").unwrap();
+    static ref JAVA_FIELD_REGEX: Regex = Regex::new(r"(?m)^(?:(?:public|private|protected)\s+)?(?:static\s+)?(?:final\s+)?(?:[\w<>\[\],\s]+)\s+(\w+)\s*=").unwrap();
+    static ref JAVA_IMPORT_REGEX: Regex = Regex::new(r"(?m)^import\s+([^;]+);").unwrap();
+    
+    // Python declarations for parse_python_structure_regex
+    static ref PYTHON_CLASS_REGEX: Regex = Regex::new(r"(?m)^class\s+(\w+)").unwrap();
+    static ref PYTHON_FUNCTION_REGEX: Regex = Regex::new(r"(?m)^def\s+(\w+)").unwrap();
+    static ref PYTHON_VARIABLE_REGEX: Regex = Regex::new(r"(?m)^(\w+)\s*=").unwrap();
+    static ref PYTHON_IMPORT_REGEX: Regex = Regex::new(r"(?m)^(?:import|from)\s+([^\n]+)").unwrap();
+    
+    // JavaScript and TypeScript declarations for parse_js_ts_structure_regex
+    static ref JS_CLASS_REGEX: Regex = Regex::new(r"(?m)^(?:export\s+)?class\s+(\w+)").unwrap();
+    static ref JS_FUNCTION_REGEX: Regex = Regex::new(r"(?m)^(?:export\s+)?function\s+(\w+)").unwrap();
+    static ref JS_VARIABLE_REGEX: Regex = Regex::new(r"(?m)^(?:export\s+)?(?:const|let|var)\s+(\w+)").unwrap();
+    static ref JS_IMPORT_REGEX: Regex = Regex::new(r"(?m)^import\s+([^\n]+)").unwrap();
+}
+
+thread_local! {
+    // Cancellation flag of the editor operation running on this thread, see is_cancelled
+    static CURRENT_EDITOR_OP: RefCell<Option<Arc<AtomicBool>>> = RefCell::new(None);
+}
+
+// Lock the editor state. A panic in one editor call must not take the editor down for the rest
+// of the session, so a poisoned lock is recovered instead of propagated; the state is only
+// written by initialize_editor, which leaves it usable at every step.
+fn editor_state() -> MutexGuard<'static, EditorState> {
+    EDITOR_STATE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
 }
 
 // Initialize the editor
 pub fn initialize_editor() -> Result<bool> {
-    let mut state = EDITOR_STATE.lock().unwrap();
+    let mut state = editor_state();
     
     if state.initialized {
         return Ok(true);
@@ -136,6 +460,19 @@ pub fn initialize_editor() -> Result<bool> {
     // Register languages
     register_languages(&mut state);
     
+    // Make sure the shared highlight patterns are compiled here rather than on first use
+    lazy_static::initialize(&NUMBER_REGEX);
+    lazy_static::initialize(&FUNCTION_CALL_REGEX);
+    lazy_static::initialize(&TYPE_NAME_REGEX);
+    lazy_static::initialize(&SCRIPT_BLOCK_REGEX);
+    lazy_static::initialize(&STYLE_BLOCK_REGEX);
+    lazy_static::initialize(&TYPE_ATTRIBUTE_REGEX);
+    lazy_static::initialize(&MARKDOWN_HEADING_REGEX);
+    lazy_static::initialize(&MARKDOWN_CODE_SPAN_REGEX);
+    lazy_static::initialize(&MARKDOWN_BOLD_REGEX);
+    lazy_static::initialize(&MARKDOWN_ITALIC_REGEX);
+    lazy_static::initialize(&MARKDOWN_LINK_REGEX);
+    
     // Initialize tree-sitter parsers
     #[cfg(feature = "tree-sitter-support")]
     initialize_parsers(&mut state);
@@ -166,6 +503,7 @@ fn register_languages(state: &mut EditorState) {
         comment_block_start: Some("/*".to_string()),
         comment_block_end: Some("*/".to_string()),
         string_delimiters: vec!["\"".to_string(), "r#\"".to_string()],
+        highlight_patterns: None,
         #[cfg(feature = "tree-sitter-support")]
         tree_sitter_language: Some(|| tree_sitter_rust::language()),
     });
@@ -192,6 +530,7 @@ fn register_languages(state: &mut EditorState) {
         comment_block_start: Some("/*".to_string()),
         comment_block_end: Some("*/".to_string()),
         string_delimiters: vec!["\"".to_string(), "\"\"\"".to_string()],
+        highlight_patterns: None,
         #[cfg(feature = "tree-sitter-support")]
         tree_sitter_language: Some(|| tree_sitter_kotlin::language()),
     });
@@ -216,6 +555,7 @@ fn register_languages(state: &mut EditorState) {
         comment_block_start: Some("/*".to_string()),
         comment_block_end: Some("*/".to_string()),
         string_delimiters: vec!["\"".to_string()],
+        highlight_patterns: None,
         #[cfg(feature = "tree-sitter-support")]
         tree_sitter_language: Some(|| tree_sitter_java::language()),
     });
@@ -243,6 +583,7 @@ fn register_languages(state: &mut EditorState) {
         comment_block_start: Some("/*".to_string()),
         comment_block_end: Some("*/".to_string()),
         string_delimiters: vec!["\"".to_string()],
+        highlight_patterns: None,
         #[cfg(feature = "tree-sitter-support")]
         tree_sitter_language: Some(|| tree_sitter_cpp::language()),
     });
@@ -265,6 +606,7 @@ fn register_languages(state: &mut EditorState) {
         comment_block_start: Some("\"\"\"".to_string()),
         comment_block_end: Some("\"\"\"".to_string()),
         string_delimiters: vec!["\"".to_string(), "'".to_string(), "\"\"\"".to_string(), "'''".to_string()],
+        highlight_patterns: None,
         #[cfg(feature = "tree-sitter-support")]
         tree_sitter_language: Some(|| tree_sitter_python::language()),
     });
@@ -288,6 +630,7 @@ fn register_languages(state: &mut EditorState) {
         comment_block_start: Some("/*".to_string()),
         comment_block_end: Some("*/".to_string()),
         string_delimiters: vec!["\"".to_string(), "'".to_string(), "`".to_string()],
+        highlight_patterns: None,
         #[cfg(feature = "tree-sitter-support")]
         tree_sitter_language: Some(|| tree_sitter_javascript::language()),
     });
@@ -296,7 +639,7 @@ fn register_languages(state: &mut EditorState) {
     state.languages.insert("typescript".to_string(), LanguageConfig {
         id: "typescript".to_string(),
         name: "TypeScript".to_string(),
-        extensions: vec!["ts".to_string(), "tsx".to_string()],
+        extensions: vec!["ts".to_string(), "mts".to_string(), "cts".to_string()],
         keywords: vec![
             "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete", "do", "else", "export", 
             "extends", "finally", "for", "function", "if", "import", "in", "instanceof", "new", "return", "super", "switch", 
@@ -313,10 +656,22 @@ fn register_languages(state: &mut EditorState) {
         comment_block_start: Some("/*".to_string()),
         comment_block_end: Some("*/".to_string()),
         string_delimiters: vec!["\"".to_string(), "'".to_string(), "`".to_string()],
+        highlight_patterns: None,
         #[cfg(feature = "tree-sitter-support")]
         tree_sitter_language: Some(|| tree_sitter_typescript::language_typescript()),
     });
     
+    // TSX is TypeScript with JSX, which needs its own grammar
+    let mut tsx = state.languages["typescript"].clone();
+    tsx.id = "tsx".to_string();
+    tsx.name = "TypeScript JSX".to_string();
+    tsx.extensions = vec!["tsx".to_string()];
+    #[cfg(feature = "tree-sitter-support")]
+    {
+        tsx.tree_sitter_language = Some(|| tree_sitter_typescript::language_tsx());
+    }
+    state.languages.insert("tsx".to_string(), tsx);
+    
     // HTML
     state.languages.insert("html".to_string(), LanguageConfig {
         id: "html".to_string(),
@@ -332,6 +687,7 @@ fn register_languages(state: &mut EditorState) {
         comment_block_start: Some("<!--".to_string()),
         comment_block_end: Some("-->".to_string()),
         string_delimiters: vec!["\"".to_string(), "'".to_string()],
+        highlight_patterns: None,
         #[cfg(feature = "tree-sitter-support")]
         tree_sitter_language: Some(|| tree_sitter_html::language()),
     });
@@ -352,6 +708,7 @@ fn register_languages(state: &mut EditorState) {
         comment_block_start: Some("/*".to_string()),
         comment_block_end: Some("*/".to_string()),
         string_delimiters: vec!["\"".to_string(), "'".to_string()],
+        highlight_patterns: None,
         #[cfg(feature = "tree-sitter-support")]
         tree_sitter_language: Some(|| tree_sitter_css::language()),
     });
@@ -367,10 +724,27 @@ fn register_languages(state: &mut EditorState) {
         comment_block_start: None,
         comment_block_end: None,
         string_delimiters: vec!["\"".to_string()],
+        highlight_patterns: None,
         #[cfg(feature = "tree-sitter-support")]
         tree_sitter_language: Some(|| tree_sitter_json::language()),
     });
     
+    // TOML
+    state.languages.insert("toml".to_string(), LanguageConfig {
+        id: "toml".to_string(),
+        name: "TOML".to_string(),
+        extensions: vec!["toml".to_string()],
+        keywords: vec!["true", "false", "inf", "nan"].iter().map(|s| s.to_string()).collect(),
+        operators: vec!["=".to_string()],
+        comment_line: Some("#".to_string()),
+        comment_block_start: None,
+        comment_block_end: None,
+        string_delimiters: vec!["\"".to_string(), "'".to_string()],
+        highlight_patterns: None,
+        #[cfg(feature = "tree-sitter-support")]
+        tree_sitter_language: None,
+    });
+    
     // XML
     state.languages.insert("xml".to_string(), LanguageConfig {
         id: "xml".to_string(),
@@ -382,9 +756,31 @@ fn register_languages(state: &mut EditorState) {
         comment_block_start: Some("<!--".to_string()),
         comment_block_end: Some("-->".to_string()),
         string_delimiters: vec!["\"".to_string(), "'".to_string()],
+        highlight_patterns: None,
         #[cfg(feature = "tree-sitter-support")]
         tree_sitter_language: Some(|| tree_sitter_xml::language()),
     });
+    
+    // Markdown
+    state.languages.insert("markdown".to_string(), LanguageConfig {
+        id: "markdown".to_string(),
+        name: "Markdown".to_string(),
+        extensions: vec!["md".to_string(), "markdown".to_string()],
+        keywords: vec![],
+        operators: vec![],
+        comment_line: None,
+        comment_block_start: Some("<!--".to_string()),
+        comment_block_end: Some("-->".to_string()),
+        string_delimiters: vec![],
+        highlight_patterns: None,
+        #[cfg(feature = "tree-sitter-support")]
+        tree_sitter_language: None,
+    });
+    
+    // Compile the regex highlight patterns up front so highlighting never builds them per call
+    for language_config in state.languages.values_mut() {
+        language_config.highlight_patterns = Some(HighlightPatterns::compile(language_config));
+    }
 }
 
 // Initialize tree-sitter parsers
@@ -401,245 +797,501 @@ fn initialize_parsers(state: &mut EditorState) {
     }
 }
 
-// Highlight syntax
-pub fn highlight_syntax(content: &str, language_id: &str) -> Vec<SyntaxHighlight> {
-    let state = EDITOR_STATE.lock().unwrap();
+// Work out a file's language from its extension, falling back to its content
+// (shebang, XML declaration, HTML doctype, JSON) for unknown or missing extensions
+pub fn detect_language(file_name: &str, content: &str) -> Option<String> {
+    let extension = std::path::Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
     
-    if !state.initialized {
-        return Vec::new();
+    if let Some(extension) = extension {
+        let state = editor_state();
+        let language = state
+            .languages
+            .values()
+            .find(|config| config.extensions.iter().any(|candidate| *candidate == extension));
+        
+        if let Some(config) = language {
+            return Some(config.id.clone());
+        }
     }
     
-    let language_config = match state.languages.get(language_id) {
-        Some(config) => config,
-        None => return Vec::new(),
-    };
+    detect_language_from_content(content)
+}
+
+fn detect_language_from_content(content: &str) -> Option<String> {
+    let text = content.trim_start_matches('\u{feff}').trim_start();
+    let first_line = text.lines().next().unwrap_or("");
     
-    #[cfg(feature = "tree-sitter-support")]
-    {
-        if let Some(parser) = state.parsers.get(language_id) {
-            return highlight_with_tree_sitter(content, parser, language_config);
-        }
+    if let Some(command) = first_line.strip_prefix("#!") {
+        return shebang_language(command).map(|id| id.to_string());
     }
     
-    // Fallback to regex-based highlighting
-    highlight_with_regex(content, language_config)
+    let head: String = text.chars().take(1024).collect::<String>().to_lowercase();
+    
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        return Some("html".to_string());
+    }
+    
+    if head.starts_with("<?xml") {
+        // XHTML documents start with an XML declaration too
+        let id = if head.contains("<!doctype html") || head.contains("<html") { "html" } else { "xml" };
+        return Some(id.to_string());
+    }
+    
+    if (text.starts_with('{') || text.starts_with('[')) && serde_json::from_str::<serde_json::Value>(text).is_ok() {
+        return Some("json".to_string());
+    }
+    
+    None
 }
 
-// Highlight with tree-sitter
-#[cfg(feature = "tree-sitter-support")]
-fn highlight_with_tree_sitter(content: &str, parser: &Parser, language_config: &LanguageConfig) -> Vec<SyntaxHighlight> {
-    let mut highlights = Vec::new();
-    
-    // Parse the code
-    let tree = match parser.parse(content, None) {
-        Some(tree) => tree,
-        None => return Vec::new(),
-    };
+// Language of a shebang interpreter, e.g. "/usr/bin/python3" or "/usr/bin/env -S node --harmony"
+fn shebang_language(command: &str) -> Option<&'static str> {
+    let mut words = command.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
     
-    // Create a cursor for traversing the tree
-    let mut cursor = tree.walk();
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
     
-    // Traverse the tree
-    traverse_tree(&mut cursor, content, &mut highlights);
+    let name = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
     
-    highlights
+    match name {
+        "python" | "pypy" => Some("python"),
+        "node" | "nodejs" | "deno" | "bun" => Some("javascript"),
+        "ts-node" | "tsx" => Some("typescript"),
+        "kotlin" | "kotlinc" | "kscript" => Some("kotlin"),
+        "java" => Some("java"),
+        "rust-script" | "cargo" => Some("rust"),
+        _ => None,
+    }
 }
 
-// Traverse tree-sitter tree
-#[cfg(feature = "tree-sitter-support")]
-fn traverse_tree(cursor: &mut TreeCursor, content: &str, highlights: &mut Vec<SyntaxHighlight>) {
-    loop {
-        let node = cursor.node();
-        
-        // Get node type
-        let node_type = node.kind();
-        
-        // Determine highlight type based on node type
-        let highlight_type = match node_type {
-            "identifier" => {
-                let text = node.utf8_text(content.as_bytes()).unwrap_or("");
-                if is_keyword(text) {
-                    "keyword"
-                } else {
-                    "variable"
-                }
-            },
-            "string" | "string_literal" | "raw_string_literal" => "string",
-            "comment" | "line_comment" | "block_comment" => "comment",
-            "number" | "integer_literal" | "float_literal" => "number",
-            "function" | "function_definition" | "method_definition" => "function",
-            "class" | "class_definition" | "struct_definition" | "enum_definition" => "type",
-            "operator" | "binary_operator" | "unary_operator" => "operator",
-            _ => "",
-        };
-        
-        // Add highlight if type is determined
-        if !highlight_type.is_empty() {
-            highlights.push(SyntaxHighlight {
-                start: node.start_byte(),
-                end: node.end_byte(),
-                type_: highlight_type.to_string(),
-            });
-        }
-        
-        // Go to first child
-        if cursor.goto_first_child() {
-            continue;
-        }
-        
-        // No children, try to go to next sibling
-        if cursor.goto_next_sibling() {
-            continue;
-        }
-        
-        // No siblings, go up and try to find a sibling
-        loop {
-            if !cursor.goto_parent() {
-                return;
-            }
+// Highlight syntax
+pub fn highlight_syntax(content: &str, language_id: &str) -> DocumentHighlights {
+    document_highlights(&editor_state(), content, language_id)
+}
+
+// Highlight several documents under a single lock of the editor state, e.g. every file
+// preview visible at once. Results are in request order. Each request's id doubles as the
+// document id nativeCancelEditorOp takes
+pub fn highlight_batch(requests: Vec<HighlightRequest>) -> Vec<HighlightResult> {
+    let state = editor_state();
+    
+    requests
+        .into_iter()
+        .map(|request| {
+            let op = EditorOp::begin(&request.id);
+            let document = document_highlights(&state, &request.content, &request.language);
             
-            if cursor.goto_next_sibling() {
-                break;
+            HighlightResult {
+                highlights: document.highlights,
+                cancelled: op.is_cancelled(),
+                degraded: document.degraded,
+                id: request.id,
             }
-        }
+        })
+        .collect()
+}
+
+// Highlight a document as an operation nativeCancelEditorOp can stop for `doc_id`
+pub fn highlight_syntax_for_document(doc_id: &str, content: &str, language_id: &str) -> EditorOpResult<DocumentHighlights> {
+    let op = EditorOp::begin(doc_id);
+    let highlights = highlight_syntax(content, language_id);
+    
+    EditorOpResult {
+        cancelled: op.is_cancelled(),
+        result: highlights,
     }
 }
 
-// Check if a string is a keyword
-#[cfg(feature = "tree-sitter-support")]
-fn is_keyword(text: &str) -> bool {
-    // Common keywords across languages
-    let keywords = [
-        "if", "else", "for", "while", "return", "break", "continue", "class", "struct", "enum", "interface", "function", "fn",
-        "def", "var", "let", "const", "public", "private", "protected", "static", "final", "abstract", "async", "await",
-        "import", "export", "from", "package", "namespace", "using", "true", "false", "null", "undefined", "this", "super",
-        "new", "try", "catch", "finally", "throw", "throws", "extends", "implements", "override", "virtual", "void", "int",
-        "float", "double", "boolean", "string", "char", "byte", "short", "long"
-    ];
+// Highlight a document with the editor state already locked; nothing is highlighted before
+// initialize_editor
+fn document_highlights(state: &EditorState, content: &str, language_id: &str) -> DocumentHighlights {
+    if !state.initialized {
+        return DocumentHighlights::default();
+    }
     
-    keywords.contains(&text)
+    DocumentHighlights {
+        highlights: highlight_document(state, content, language_id),
+        degraded: is_large_document(state, content),
+    }
 }
 
-// Highlight with regex
-fn highlight_with_regex(content: &str, language_config: &LanguageConfig) -> Vec<SyntaxHighlight> {
-    let mut highlights = Vec::new();
+// Set the size above which documents only get the cheap highlighting. 0 restores the default
+pub fn set_max_highlight_bytes(max_bytes: usize) {
+    editor_state().max_highlight_bytes = if max_bytes > 0 { max_bytes } else { DEFAULT_MAX_HIGHLIGHT_BYTES };
+}
+
+fn is_large_document(state: &EditorState, content: &str) -> bool {
+    content.len() > state.max_highlight_bytes
+}
+
+// Parse a document's structure as an operation nativeCancelEditorOp can stop for `doc_id`
+pub fn parse_code_structure_for_document(doc_id: &str, content: &str, language_id: &str) -> EditorOpResult<CodeStructure> {
+    let op = EditorOp::begin(doc_id);
+    let structure = parse_code_structure(content, language_id);
     
-    // Highlight keywords
-    for keyword in &language_config.keywords {
-        let pattern = format!(r"\b{}\b", regex::escape(keyword));
-        let regex = Regex::new(&pattern).unwrap();
-        
-        for mat in regex.find_iter(content) {
-            highlights.push(SyntaxHighlight {
-                start: mat.start(),
-                end: mat.end(),
-                type_: "keyword".to_string(),
-            });
+    EditorOpResult {
+        cancelled: op.is_cancelled(),
+        result: structure,
+    }
+}
+
+// Ask the operations running for `doc_id` to stop and return what they have so far. Returns
+// false when nothing is running for it; operations started afterwards are not affected
+pub fn cancel_editor_op(doc_id: &str) -> bool {
+    match EDITOR_OPS.lock().unwrap().remove(doc_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            true
         }
+        None => false,
     }
-    
-    // Highlight operators
-    for operator in &language_config.operators {
-        let pattern = regex::escape(operator);
-        let regex = Regex::new(&pattern).unwrap();
+}
+
+// An editor operation running on this thread for a document. Operations on the same document
+// share a cancellation flag, and the traversal loops poll it through is_cancelled. An operation
+// started inside another one hands the thread back to it when dropped
+struct EditorOp {
+    doc_id: String,
+    flag: Arc<AtomicBool>,
+    previous: Option<Arc<AtomicBool>>,
+}
+
+impl EditorOp {
+    fn begin(doc_id: &str) -> EditorOp {
+        let flag = EDITOR_OPS
+            .lock()
+            .unwrap()
+            .entry(doc_id.to_string())
+            .or_insert_with(|| Arc::new(AtomicBool::new(false)))
+            .clone();
         
-        for mat in regex.find_iter(content) {
-            highlights.push(SyntaxHighlight {
-                start: mat.start(),
-                end: mat.end(),
-                type_: "operator".to_string(),
-            });
+        let previous = CURRENT_EDITOR_OP.with(|current| current.replace(Some(flag.clone())));
+        
+        EditorOp {
+            doc_id: doc_id.to_string(),
+            flag,
+            previous,
         }
     }
     
-    // Highlight strings
-    for delimiter in &language_config.string_delimiters {
-        let escaped_delimiter = regex::escape(delimiter);
-        let pattern = format!(r"{0}(?:[^\\{0}]|\\.)*?{0}", escaped_delimiter);
-        let regex = Regex::new(&pattern).unwrap();
+    fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for EditorOp {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT_EDITOR_OP.with(|current| *current.borrow_mut() = previous);
+        
+        // Unregister the flag once the last operation using it is done; a cancelled flag was
+        // already removed and a new one may have taken its place
+        let mut ops = EDITOR_OPS.lock().unwrap();
+        let last_user = ops
+            .get(&self.doc_id)
+            .map_or(false, |flag| Arc::ptr_eq(flag, &self.flag) && Arc::strong_count(flag) == 2);
+        if last_user {
+            ops.remove(&self.doc_id);
+        }
+    }
+}
+
+// Whether the operation running on this thread was cancelled. Always false outside an EditorOp
+fn is_cancelled() -> bool {
+    CURRENT_EDITOR_OP.with(|current| current.borrow().as_ref().map_or(false, |flag| flag.load(Ordering::Relaxed)))
+}
+
+// Highlight a document, including code embedded in it in other languages
+fn highlight_document(state: &EditorState, content: &str, language_id: &str) -> Vec<SyntaxHighlight> {
+    if is_large_document(state, content) {
+        return highlight_large_document(state, content, language_id);
+    }
+    
+    let mut highlights = highlight_language(state, content, language_id);
+    
+    // Code embedded in another language is highlighted with its own language
+    let regions = match language_id {
+        "html" => embedded_html_regions(content),
+        "markdown" => markdown_fenced_blocks(content).into_iter()
+            .filter_map(|block| {
+                let language_id = block.language_id.filter(|id| state.languages.contains_key(id))?;
+                Some(EmbeddedRegion {
+                    start: block.body_start,
+                    end: block.body_end,
+                    language_id,
+                })
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    
+    if !regions.is_empty() {
+        highlight_embedded_regions(state, content, &regions, &mut highlights);
+    }
+    
+    highlights
+}
+
+// Highlighting for documents over max_highlight_bytes, which are usually generated or minified:
+// a single regex pass without tree-sitter, embedded languages, or the slow function-call and
+// type patterns
+fn highlight_large_document(state: &EditorState, content: &str, language_id: &str) -> Vec<SyntaxHighlight> {
+    state
+        .languages
+        .get(language_id)
+        .map(|language_config| highlight_with_regex(content, language_config, false))
+        .unwrap_or_default()
+}
+
+// A range of a document written in another language
+struct EmbeddedRegion {
+    start: usize,
+    end: usize,
+    language_id: String,
+}
+
+// Find <script> and <style> contents in an HTML document
+fn embedded_html_regions(content: &str) -> Vec<EmbeddedRegion> {
+    let mut regions = Vec::new();
+    
+    for captures in SCRIPT_BLOCK_REGEX.captures_iter(content) {
+        let attributes = captures.get(1).map_or("", |m| m.as_str());
+        let script_type = TYPE_ATTRIBUTE_REGEX.captures(attributes)
+            .and_then(|type_captures| type_captures.get(1))
+            .map(|m| m.as_str().to_lowercase());
+        
+        // Only scripts the browser would run are JavaScript; templates and data blocks are left alone
+        let language_id = match script_type.as_deref() {
+            None | Some("module") | Some("text/javascript") | Some("application/javascript") => "javascript",
+            Some("application/json") | Some("application/ld+json") | Some("importmap") => "json",
+            Some(_) => continue,
+        };
+        
+        if let Some(body) = captures.get(2) {
+            regions.push(EmbeddedRegion {
+                start: body.start(),
+                end: body.end(),
+                language_id: language_id.to_string(),
+            });
+        }
+    }
+    
+    for captures in STYLE_BLOCK_REGEX.captures_iter(content) {
+        if let Some(body) = captures.get(1) {
+            regions.push(EmbeddedRegion {
+                start: body.start(),
+                end: body.end(),
+                language_id: "css".to_string(),
+            });
+        }
+    }
+    
+    regions
+}
+
+// Replace the outer language's highlights inside each region with the region language's highlights
+fn highlight_embedded_regions(state: &EditorState, content: &str, regions: &[EmbeddedRegion], highlights: &mut Vec<SyntaxHighlight>) {
+    highlights.retain(|highlight| {
+        !regions.iter().any(|region| highlight.start < region.end && highlight.end > region.start)
+    });
+    
+    for region in regions {
+        if region.start >= region.end {
+            continue;
+        }
+        
+        let inner = &content[region.start..region.end];
+        for mut highlight in highlight_language(state, inner, &region.language_id) {
+            highlight.start += region.start;
+            highlight.end += region.start;
+            highlights.push(highlight);
+        }
+    }
+    
+    highlights.sort_by_key(|highlight| highlight.start);
+}
+
+// A fenced code block in a Markdown document
+struct FencedBlock {
+    start: usize,
+    body_start: usize,
+    body_end: usize,
+    end: usize,
+    language_id: Option<String>,
+}
+
+// Find ``` and ~~~ fenced code blocks; an unclosed fence runs to the end of the document
+fn markdown_fenced_blocks(content: &str) -> Vec<FencedBlock> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(char, usize, usize, usize, Option<String>)> = None;
+    let mut offset = 0;
+    
+    for line in content.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
         
-        for mat in regex.find_iter(content) {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let trimmed = line.trim_start_matches(' ').trim_end();
+        let marker = match trimmed.chars().next() {
+            Some(c) if indent <= 3 && (c == '`' || c == '~') => c,
+            _ => continue,
+        };
+        let marker_len = trimmed.chars().take_while(|&c| c == marker).count();
+        
+        match &open {
+            Some((open_marker, open_len, start, body_start, language_id)) => {
+                // A closing fence uses the same marker, at least as long, with nothing after it
+                if marker == *open_marker && marker_len >= *open_len && trimmed.len() == marker_len {
+                    blocks.push(FencedBlock {
+                        start: *start,
+                        body_start: *body_start,
+                        body_end: line_start,
+                        end: offset,
+                        language_id: language_id.clone(),
+                    });
+                    open = None;
+                }
+            }
+            None if marker_len >= 3 => {
+                let info = trimmed[marker_len..].trim();
+                if marker == '`' && info.contains('`') {
+                    continue;
+                }
+                
+                let language_id = info.split_whitespace().next().map(fence_language_id);
+                open = Some((marker, marker_len, line_start, offset, language_id));
+            }
+            None => {}
+        }
+    }
+    
+    if let Some((_, _, start, body_start, language_id)) = open {
+        blocks.push(FencedBlock {
+            start,
+            body_start,
+            body_end: content.len(),
+            end: content.len(),
+            language_id,
+        });
+    }
+    
+    blocks
+}
+
+// Map a fence info string such as "rs" or "Kotlin" to a language id
+fn fence_language_id(info: &str) -> String {
+    let info = info.to_lowercase();
+    match info.as_str() {
+        "rs" => "rust",
+        "kt" | "kts" => "kotlin",
+        "py" => "python",
+        "js" | "jsx" | "mjs" => "javascript",
+        "ts" => "typescript",
+        "c" | "c++" | "cc" | "cxx" | "h" | "hpp" => "cpp",
+        "htm" => "html",
+        "md" => "markdown",
+        _ => return info,
+    }.to_string()
+}
+
+// Highlight Markdown headings, emphasis, links and code; fenced block bodies in a known
+// language are left to the embedded-region pass
+fn highlight_markdown(state: &EditorState, content: &str) -> Vec<SyntaxHighlight> {
+    let mut highlights = Vec::new();
+    let blocks = markdown_fenced_blocks(content);
+    let in_block = |start: usize, end: usize| blocks.iter().any(|block| start < block.end && end > block.start);
+    
+    // Code spans first; nothing inside them is markup
+    let code_spans: Vec<(usize, usize)> = MARKDOWN_CODE_SPAN_REGEX.find_iter(content)
+        .map(|mat| (mat.start(), mat.end()))
+        .filter(|&(start, end)| !in_block(start, end))
+        .collect();
+    let in_code_span = |start: usize, end: usize| code_spans.iter().any(|&(s, e)| start < e && end > s);
+    
+    for &(start, end) in &code_spans {
+        highlights.push(SyntaxHighlight {
+            start,
+            end,
+            type_: "code".to_string(),
+        });
+    }
+    
+    for mat in MARKDOWN_HEADING_REGEX.find_iter(content) {
+        if !in_block(mat.start(), mat.end()) {
             highlights.push(SyntaxHighlight {
                 start: mat.start(),
                 end: mat.end(),
-                type_: "string".to_string(),
+                type_: "heading".to_string(),
             });
         }
     }
     
-    // Highlight comments
-    if let Some(line_comment) = &language_config.comment_line {
-        let pattern = format!(r"{0}.*$", regex::escape(line_comment));
-        let regex = Regex::new(&pattern).unwrap();
-        
-        for mat in regex.find_iter(content) {
+    let mut bold_spans = Vec::new();
+    for mat in MARKDOWN_BOLD_REGEX.find_iter(content) {
+        if !in_block(mat.start(), mat.end()) && !in_code_span(mat.start(), mat.end()) {
+            bold_spans.push((mat.start(), mat.end()));
             highlights.push(SyntaxHighlight {
                 start: mat.start(),
                 end: mat.end(),
-                type_: "comment".to_string(),
+                type_: "bold".to_string(),
             });
         }
     }
     
-    if let (Some(block_start), Some(block_end)) = (&language_config.comment_block_start, &language_config.comment_block_end) {
-        let pattern = format!(r"{0}[\s\S]*?{1}", regex::escape(block_start), regex::escape(block_end));
-        let regex = Regex::new(&pattern).unwrap();
-        
-        for mat in regex.find_iter(content) {
+    for mat in MARKDOWN_ITALIC_REGEX.find_iter(content) {
+        let in_bold = bold_spans.iter().any(|&(s, e)| mat.start() < e && mat.end() > s);
+        if !in_bold && !in_block(mat.start(), mat.end()) && !in_code_span(mat.start(), mat.end()) {
             highlights.push(SyntaxHighlight {
                 start: mat.start(),
                 end: mat.end(),
-                type_: "comment".to_string(),
+                type_: "italic".to_string(),
             });
         }
     }
     
-    // Highlight numbers
-    let number_regex = Regex::new(r"\b\d+(\.\d+)?([eE][+-]?\d+)?\b").unwrap();
-    for mat in number_regex.find_iter(content) {
-        highlights.push(SyntaxHighlight {
-            start: mat.start(),
-            end: mat.end(),
-            type_: "number".to_string(),
-        });
+    for mat in MARKDOWN_LINK_REGEX.find_iter(content) {
+        if !in_block(mat.start(), mat.end()) && !in_code_span(mat.start(), mat.end()) {
+            highlights.push(SyntaxHighlight {
+                start: mat.start(),
+                end: mat.end(),
+                type_: "link".to_string(),
+            });
+        }
     }
     
-    // Highlight function calls
-    let function_regex = Regex::new(r"\b(\w+)\s*\(").unwrap();
-    for captures in function_regex.captures_iter(content) {
-        if let Some(function_match) = captures.get(1) {
-            let function_name = function_match.as_str();
-            
-            // Skip if the function name is a keyword
-            if !language_config.keywords.contains(&function_name.to_string()) {
+    // Fence lines, and bodies whose language is not known, are plain code
+    for block in &blocks {
+        let known_language = block.language_id.as_ref().map_or(false, |id| state.languages.contains_key(id));
+        let code_ranges = if known_language {
+            vec![(block.start, block.body_start), (block.body_end, block.end)]
+        } else {
+            vec![(block.start, block.end)]
+        };
+        
+        for (start, end) in code_ranges {
+            if start < end {
                 highlights.push(SyntaxHighlight {
-                    start: function_match.start(),
-                    end: function_match.end(),
-                    type_: "function".to_string(),
+                    start,
+                    end,
+                    type_: "code".to_string(),
                 });
             }
         }
     }
     
-    // Highlight types (capitalized identifiers)
-    let type_regex = Regex::new(r"\b[A-Z][a-zA-Z0-9_]*\b").unwrap();
-    for mat in type_regex.find_iter(content) {
-        highlights.push(SyntaxHighlight {
-            start: mat.start(),
-            end: mat.end(),
-            type_: "type".to_string(),
-        });
-    }
-    
+    highlights.sort_by_key(|highlight| highlight.start);
     highlights
 }
 
-// Get code completions
-pub fn get_completions(content: &str, position: usize, language_id: &str) -> Vec<CompletionItem> {
-    let state = EDITOR_STATE.lock().unwrap();
-    
-    if !state.initialized {
-        return Vec::new();
+// Highlight content as a single language
+fn highlight_language(state: &EditorState, content: &str, language_id: &str) -> Vec<SyntaxHighlight> {
+    if language_id == "markdown" {
+        return highlight_markdown(state, content);
     }
     
     let language_config = match state.languages.get(language_id) {
@@ -650,18 +1302,18 @@ pub fn get_completions(content: &str, position: usize, language_id: &str) -> Vec
     #[cfg(feature = "tree-sitter-support")]
     {
         if let Some(parser) = state.parsers.get(language_id) {
-            return get_completions_with_tree_sitter(content, position, parser, language_config);
+            return highlight_with_tree_sitter(content, parser, language_config);
         }
     }
     
-    // Fallback to simple completions
-    get_simple_completions(content, position, language_config)
+    // Fallback to regex-based highlighting
+    highlight_with_regex(content, language_config, true)
 }
 
-// Get completions with tree-sitter
+// Highlight with tree-sitter
 #[cfg(feature = "tree-sitter-support")]
-fn get_completions_with_tree_sitter(content: &str, position: usize, parser: &Parser, language_config: &LanguageConfig) -> Vec<CompletionItem> {
-    let mut completions = Vec::new();
+fn highlight_with_tree_sitter(content: &str, parser: &Parser, language_config: &LanguageConfig) -> Vec<SyntaxHighlight> {
+    let mut highlights = Vec::new();
     
     // Parse the code
     let tree = match parser.parse(content, None) {
@@ -669,900 +1321,4217 @@ fn get_completions_with_tree_sitter(content: &str, position: usize, parser: &Par
         None => return Vec::new(),
     };
     
-    // Find the node at the cursor position
-    let node = find_node_at_position(&tree.root_node(), position);
-    
-    // Get the node type
-    let node_type = node.kind();
+    // Create a cursor for traversing the tree
+    let mut cursor = tree.walk();
     
-    // Get the text before the cursor
-    let text_before_cursor = &content[..position];
-    let current_line = get_current_line(text_before_cursor);
-    let current_word = get_current_word(current_line);
+    // Traverse the tree
+    traverse_tree(&mut cursor, content, &mut highlights);
     
-    // Add completions based on node type and context
-    match node_type {
-        "identifier" => {
-            // Add keyword completions
-            for keyword in &language_config.keywords {
-                if keyword.starts_with(&current_word) {
-                    completions.push(CompletionItem {
-                        label: keyword.clone(),
-                        kind: "keyword".to_string(),
-                        detail: None,
-                        documentation: None,
-                        insert_text: keyword.clone(),
-                    });
+    highlights
+}
+
+// Traverse tree-sitter tree
+#[cfg(feature = "tree-sitter-support")]
+fn traverse_tree(cursor: &mut TreeCursor, content: &str, highlights: &mut Vec<SyntaxHighlight>) {
+    loop {
+        if is_cancelled() {
+            return;
+        }
+        
+        let node = cursor.node();
+        
+        // Get node type
+        let node_type = node.kind();
+        
+        // Determine highlight type based on node type
+        let highlight_type = match node_type {
+            "identifier" => {
+                let text = node.utf8_text(content.as_bytes()).unwrap_or("");
+                if is_keyword(text) {
+                    "keyword"
+                } else {
+                    "variable"
                 }
+            },
+            "string" | "string_literal" | "raw_string_literal" => "string",
+            "comment" | "line_comment" | "block_comment" => "comment",
+            "number" | "integer_literal" | "float_literal" => "number",
+            "function" | "function_definition" | "method_definition" => "function",
+            "class" | "class_definition" | "struct_definition" | "enum_definition" => "type",
+            "operator" | "binary_operator" | "unary_operator" => "operator",
+            _ => "",
+        };
+        
+        // Add highlight if type is determined
+        if !highlight_type.is_empty() {
+            highlights.push(SyntaxHighlight {
+                start: node.start_byte(),
+                end: node.end_byte(),
+                type_: highlight_type.to_string(),
+            });
+        }
+        
+        // Go to first child
+        if cursor.goto_first_child() {
+            continue;
+        }
+        
+        // No children, try to go to next sibling
+        if cursor.goto_next_sibling() {
+            continue;
+        }
+        
+        // No siblings, go up and try to find a sibling
+        loop {
+            if !cursor.goto_parent() {
+                return;
             }
             
-            // Add language-specific completions
-            match language_config.id.as_str() {
-                "rust" => add_rust_completions(&mut completions, &current_word),
-                "kotlin" => add_kotlin_completions(&mut completions, &current_word),
-                "java" => add_java_completions(&mut completions, &current_word),
-                "python" => add_python_completions(&mut completions, &current_word),
-                "javascript" | "typescript" => add_js_ts_completions(&mut completions, &current_word),
-                _ => {}
-            }
-        },
-        "string" | "string_literal" => {
-            // Add string-specific completions
-            match language_config.id.as_str() {
-                "rust" => add_rust_string_completions(&mut completions, &current_word),
-                "kotlin" => add_kotlin_string_completions(&mut completions, &current_word),
-                "java" => add_java_string_completions(&mut completions, &current_word),
-                "python" => add_python_string_completions(&mut completions, &current_word),
-                "javascript" | "typescript" => add_js_ts_string_completions(&mut completions, &current_word),
-                _ => {}
+            if cursor.goto_next_sibling() {
+                break;
             }
-        },
-        _ => {
-            // Add general completions
-            add_general_completions(&mut completions, &current_word, language_config);
         }
     }
-    
-    completions
 }
 
-// Find node at position
+// Check if a string is a keyword
 #[cfg(feature = "tree-sitter-support")]
-fn find_node_at_position(node: &Node, position: usize) -> Node {
-    if position < node.start_byte() || position > node.end_byte() {
-        return *node;
+fn is_keyword(text: &str) -> bool {
+    // Common keywords across languages
+    let keywords = [
+        "if", "else", "for", "while", "return", "break", "continue", "class", "struct", "enum", "interface", "function", "fn",
+        "def", "var", "let", "const", "public", "private", "protected", "static", "final", "abstract", "async", "await",
+        "import", "export", "from", "package", "namespace", "using", "true", "false", "null", "undefined", "this", "super",
+        "new", "try", "catch", "finally", "throw", "throws", "extends", "implements", "override", "virtual", "void", "int",
+        "float", "double", "boolean", "string", "char", "byte", "short", "long"
+    ];
+    
+    keywords.contains(&text)
+}
+
+// Semantic tokens: function definitions vs calls, parameters vs locals, types vs enum variants,
+// with mut/static/async modifiers. These come from the tree-sitter tree, so without a parser
+// for the language this is empty and callers keep using highlight_syntax.
+pub fn semantic_tokens(content: &str, language_id: &str) -> Vec<SemanticToken> {
+    let state = editor_state();
+    
+    if !state.initialized || content.is_empty() || !state.languages.contains_key(language_id) {
+        return Vec::new();
     }
     
-    for i in 0..node.child_count() {
-        if let Some(child) = node.child(i) {
-            if position >= child.start_byte() && position <= child.end_byte() {
-                return find_node_at_position(&child, position);
-            }
+    #[cfg(feature = "tree-sitter-support")]
+    {
+        if let Some(parser) = state.parsers.get(language_id) {
+            return semantic_tokens_with_tree_sitter(content, parser, language_id);
         }
     }
     
-    *node
+    Vec::new()
 }
 
-// Get simple completions
-fn get_simple_completions(content: &str, position: usize, language_config: &LanguageConfig) -> Vec<CompletionItem> {
-    let mut completions = Vec::new();
+// Semantic tokens with tree-sitter
+#[cfg(feature = "tree-sitter-support")]
+fn semantic_tokens_with_tree_sitter(content: &str, parser: &Parser, language_id: &str) -> Vec<SemanticToken> {
+    let tree = match parser.parse(content, None) {
+        Some(tree) => tree,
+        None => return Vec::new(),
+    };
     
-    // Get the text before the cursor
-    let text_before_cursor = &content[..position];
-    let current_line = get_current_line(text_before_cursor);
-    let current_word = get_current_word(current_line);
+    let mut collector = SemanticTokenCollector::new(content, language_id);
+    collector.visit(tree.root_node());
     
-    // Add keyword completions
-    for keyword in &language_config.keywords {
-        if keyword.starts_with(&current_word) {
-            completions.push(CompletionItem {
-                label: keyword.clone(),
-                kind: "keyword".to_string(),
-                detail: None,
-                documentation: None,
-                insert_text: keyword.clone(),
-            });
+    let mut tokens = collector.tokens;
+    tokens.sort_by_key(|token| token.start);
+    tokens
+}
+
+// Collects semantic tokens from a tree-sitter tree, tracking declared names per scope so uses of
+// parameters and locals can be told apart, and which declaration each use resolves to
+#[cfg(feature = "tree-sitter-support")]
+struct SemanticTokenCollector<'a> {
+    source: &'a [u8],
+    language_id: &'a str,
+    tokens: Vec<SemanticToken>,
+    // Start bytes of nodes already classified through their parent
+    claimed: std::collections::HashSet<usize>,
+    // Names declared in each enclosing scope: the declaration's start byte, and the token type and
+    // modifiers their uses get
+    scopes: Vec<HashMap<String, (usize, &'static str, Vec<String>)>>,
+    // Start byte of every identifier bound to a local declaration, mapped to that declaration's start byte
+    bindings: HashMap<usize, usize>,
+}
+
+#[cfg(feature = "tree-sitter-support")]
+impl<'a> SemanticTokenCollector<'a> {
+    fn new(content: &'a str, language_id: &'a str) -> Self {
+        Self {
+            source: content.as_bytes(),
+            language_id,
+            tokens: Vec::new(),
+            claimed: std::collections::HashSet::new(),
+            scopes: vec![HashMap::new()],
+            bindings: HashMap::new(),
         }
     }
     
-    // Add language-specific completions
-    match language_config.id.as_str() {
-        "rust" => add_rust_completions(&mut completions, &current_word),
-        "kotlin" => add_kotlin_completions(&mut completions, &current_word),
-        "java" => add_java_completions(&mut completions, &current_word),
-        "python" => add_python_completions(&mut completions, &current_word),
-        "javascript" | "typescript" => add_js_ts_completions(&mut completions, &current_word),
-        _ => {}
+    fn visit(&mut self, node: Node) {
+        let opens_scope = match node.kind() {
+            // Python scopes are per function, not per block
+            "block" | "for_statement" if self.language_id == "python" => false,
+            "block" => true,
+            kind => is_function_node(kind)
+                || matches!(kind, "closure_expression" | "lambda" | "lambda_expression" | "arrow_function"
+                    | "statement_block" | "compound_statement" | "for_expression" | "for_statement"
+                    | "enhanced_for_statement" | "for_in_statement" | "if_expression" | "if_let_expression"
+                    | "while_expression" | "while_let_expression" | "match_arm"),
+        };
+        
+        if opens_scope {
+            self.scopes.push(HashMap::new());
+        }
+        
+        // The value of `let x = x + 1` or `if let Some(x) = x` still sees the outer `x`, so it is
+        // visited before the pattern declares the new one
+        let value = match node.kind() {
+            "let_declaration" | "let_condition" | "if_let_expression" | "while_let_expression" | "for_expression" => {
+                node.child_by_field_name("value")
+            }
+            _ => None,
+        };
+        if let Some(value) = value {
+            self.visit(value);
+        }
+        
+        self.classify(node);
+        
+        if matches!(node.kind(), "identifier" | "shorthand_property_identifier")
+            && !self.bindings.contains_key(&node.start_byte())
+            && !is_path_segment(node)
+        {
+            if let Some(&(declaration, _, _)) = self.resolve(node) {
+                self.bindings.insert(node.start_byte(), declaration);
+            }
+        }
+        
+        let mut cursor = node.walk();
+        let children: Vec<Node> = node.children(&mut cursor).collect();
+        for child in children {
+            if Some(child) != value {
+                self.visit(child);
+            }
+        }
+        
+        if opens_scope {
+            self.scopes.pop();
+        }
     }
     
-    completions
+    fn classify(&mut self, node: Node) {
+        let kind = node.kind();
+        
+        match kind {
+            _ if is_function_node(kind) => {
+                let token_type = if kind.starts_with("method") || kind == "constructor_declaration" || is_inside_type(node) {
+                    "method"
+                } else {
+                    "function"
+                };
+                
+                let mut modifiers = vec!["declaration".to_string()];
+                modifiers.extend(self.declaration_modifiers(node));
+                
+                if let Some(name) = definition_name(node) {
+                    self.emit(name, token_type, modifiers);
+                }
+            }
+            "struct_item" | "union_item" | "struct_specifier" => self.emit_definition(node, "struct", Vec::new()),
+            "enum_item" | "enum_declaration" | "enum_specifier" => self.emit_definition(node, "enum", Vec::new()),
+            "trait_item" | "interface_declaration" => self.emit_definition(node, "interface", Vec::new()),
+            "class_definition" | "class_declaration" | "class_specifier" | "record_declaration" | "object_declaration" => {
+                self.emit_definition(node, "class", Vec::new())
+            }
+            "type_item" | "type_alias_declaration" | "type_alias" => self.emit_definition(node, "type", Vec::new()),
+            "mod_item" | "namespace_definition" => self.emit_definition(node, "namespace", Vec::new()),
+            "enum_variant" | "enum_constant" => self.emit_definition(node, "enum_member", Vec::new()),
+            "const_item" => self.emit_definition(node, "variable", vec!["readonly".to_string()]),
+            "static_item" => {
+                let mut modifiers = vec!["static".to_string()];
+                if has_child_kind(node, "mutable_specifier") {
+                    modifiers.push("mutable".to_string());
+                }
+                self.emit_definition(node, "variable", modifiers);
+            }
+            "field_declaration" => {
+                let modifiers = self.declaration_modifiers(node);
+                if let Some(name) = node.child_by_field_name("name").or_else(|| node.child_by_field_name("declarator")) {
+                    if matches!(name.kind(), "field_identifier" | "identifier") {
+                        let mut modifiers = modifiers;
+                        modifiers.insert(0, "declaration".to_string());
+                        self.emit(name, "property", modifiers);
+                    }
+                }
+            }
+            
+            // Parameters
+            "parameter" | "formal_parameter" | "spread_parameter" | "catch_formal_parameter" | "required_parameter"
+            | "optional_parameter" | "parameter_declaration" | "default_parameter" | "typed_parameter"
+            | "typed_default_parameter" => {
+                let pattern = node
+                    .child_by_field_name("pattern")
+                    .or_else(|| node.child_by_field_name("name"))
+                    .or_else(|| node.child_by_field_name("declarator"))
+                    .or_else(|| node.named_child(0));
+                
+                if let Some(pattern) = pattern {
+                    let mutable = has_child_kind(node, "mutable_specifier");
+                    self.declare_pattern(pattern, "parameter", mutable);
+                }
+            }
+            "closure_parameters" | "parameters" | "lambda_parameters" | "formal_parameters" => {
+                let mut cursor = node.walk();
+                let names: Vec<Node> = node
+                    .named_children(&mut cursor)
+                    .filter_map(|child| match child.kind() {
+                        "identifier" => Some(child),
+                        // Python *args and **kwargs
+                        "list_splat_pattern" | "dictionary_splat_pattern" => child.named_child(0),
+                        _ => None,
+                    })
+                    .collect();
+                for name in names {
+                    self.declare(name, "parameter", Vec::new());
+                }
+            }
+            "arrow_function" => {
+                if let Some(parameter) = node.child_by_field_name("parameter") {
+                    self.declare(parameter, "parameter", Vec::new());
+                }
+            }
+            
+            // Locals
+            "let_declaration" | "for_expression" => {
+                if let Some(pattern) = node.child_by_field_name("pattern") {
+                    let mutable = has_child_kind(node, "mutable_specifier");
+                    self.declare_pattern(pattern, "variable", mutable);
+                }
+            }
+            "let_condition" | "if_let_expression" | "while_let_expression" | "match_arm" => {
+                if let Some(pattern) = node.child_by_field_name("pattern") {
+                    self.declare_pattern(pattern, "variable", false);
+                }
+            }
+            "variable_declarator" => {
+                let parent_kind = node.parent().map(|parent| parent.kind()).unwrap_or("");
+                
+                if let Some(name) = node.child_by_field_name("name") {
+                    if parent_kind == "field_declaration" {
+                        let mut modifiers = vec!["declaration".to_string()];
+                        modifiers.extend(node.parent().map(|parent| self.declaration_modifiers(parent)).unwrap_or_default());
+                        self.emit(name, "property", modifiers);
+                    } else {
+                        let constant = node.parent().map_or(false, |parent| {
+                            parent.child(0).map_or(false, |keyword| keyword.kind() == "const")
+                        });
+                        let modifiers = if constant { vec!["readonly".to_string()] } else { Vec::new() };
+                        self.declare_pattern_with(name, "variable", modifiers);
+                    }
+                }
+            }
+            "enhanced_for_statement" => {
+                if let Some(name) = node.child_by_field_name("name") {
+                    self.declare(name, "variable", Vec::new());
+                }
+            }
+            "assignment" | "for_in_statement" => {
+                if let Some(left) = node.child_by_field_name("left") {
+                    if node.child_by_field_name("kind").is_some() {
+                        // `for (const x of xs)` always declares a new binding
+                        self.declare_pattern(left, "variable", false);
+                    } else if left.kind() == "identifier" && self.lookup(left).is_none() {
+                        // Python assignments and for-loops declare a name the first time it is bound
+                        self.declare(left, "variable", Vec::new());
+                    }
+                }
+            }
+            "for_statement" if self.language_id == "python" => {
+                if let Some(left) = node.child_by_field_name("left") {
+                    self.declare_pattern(left, "variable", false);
+                }
+            }
+            
+            // Uses
+            "call_expression" | "call" => {
+                if let Some(function) = node.child_by_field_name("function") {
+                    self.classify_callee(function);
+                }
+            }
+            "method_invocation" => {
+                if let Some(name) = node.child_by_field_name("name") {
+                    self.emit(name, "method", Vec::new());
+                }
+            }
+            "macro_invocation" => {
+                if let Some(name) = node.child_by_field_name("macro") {
+                    let name = node_name_part(name);
+                    self.emit(name, "macro", Vec::new());
+                }
+            }
+            "scoped_identifier" => {
+                // Enum::Variant
+                let path = node.child_by_field_name("path");
+                let name = node.child_by_field_name("name");
+                if let (Some(path), Some(name)) = (path, name) {
+                    if starts_uppercase(self.text(path)) && starts_uppercase(self.text(name)) {
+                        self.emit(name, "enum_member", Vec::new());
+                    }
+                }
+            }
+            "field_expression" | "member_expression" | "attribute" | "field_access" => {
+                let field = node
+                    .child_by_field_name("field")
+                    .or_else(|| node.child_by_field_name("property"))
+                    .or_else(|| node.child_by_field_name("attribute"));
+                if let Some(field) = field {
+                    self.emit(field, "property", Vec::new());
+                }
+            }
+            "type_identifier" | "primitive_type" | "predefined_type" | "integral_type" | "floating_point_type" | "boolean_type" => {
+                self.emit(node, "type", Vec::new());
+            }
+            "identifier" | "simple_identifier" => {
+                if let Some((token_type, modifiers)) = self.lookup(node) {
+                    self.emit(node, token_type, modifiers);
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    // A called expression: plain functions, paths, methods and generic calls
+    fn classify_callee(&mut self, function: Node) {
+        match function.kind() {
+            "identifier" => {
+                // Calling a local closure is still a use of the variable
+                if self.lookup(function).is_none() {
+                    self.emit(function, "function", Vec::new());
+                }
+            }
+            "scoped_identifier" | "qualified_identifier" => {
+                if let Some(name) = function.child_by_field_name("name") {
+                    let token_type = if starts_uppercase(self.text(name)) { "enum_member" } else { "function" };
+                    self.emit(name, token_type, Vec::new());
+                }
+            }
+            "field_expression" | "member_expression" | "attribute" => {
+                let field = function
+                    .child_by_field_name("field")
+                    .or_else(|| function.child_by_field_name("property"))
+                    .or_else(|| function.child_by_field_name("attribute"));
+                if let Some(field) = field {
+                    self.emit(field, "method", Vec::new());
+                }
+            }
+            "generic_function" | "template_function" => {
+                if let Some(inner) = function.child_by_field_name("function").or_else(|| function.child_by_field_name("name")) {
+                    self.classify_callee(inner);
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    fn emit_definition(&mut self, node: Node, token_type: &'static str, extra_modifiers: Vec<String>) {
+        if let Some(name) = definition_name(node) {
+            let mut modifiers = vec!["declaration".to_string()];
+            modifiers.extend(extra_modifiers);
+            self.emit(name, token_type, modifiers);
+        }
+    }
+    
+    // Declare every name bound by a pattern, e.g. `(mut a, b)` or `Point { x, y }`
+    fn declare_pattern(&mut self, pattern: Node, token_type: &'static str, mutable: bool) {
+        let modifiers = if mutable { vec!["mutable".to_string()] } else { Vec::new() };
+        self.declare_pattern_with(pattern, token_type, modifiers);
+    }
+    
+    fn declare_pattern_with(&mut self, pattern: Node, token_type: &'static str, modifiers: Vec<String>) {
+        match pattern.kind() {
+            // Capitalized names in Rust patterns are unit variants and constants, e.g. `None`
+            "identifier" if self.language_id == "rust" && starts_uppercase(self.text(pattern)) => {}
+            "identifier" | "shorthand_field_identifier" | "simple_identifier" | "shorthand_property_identifier_pattern" => {
+                self.declare(pattern, token_type, modifiers)
+            }
+            // Paths and types inside patterns name existing items, not new bindings
+            "scoped_identifier" | "type_identifier" | "primitive_type" => {}
+            _ => {
+                let mut modifiers = modifiers;
+                if pattern.kind() == "mut_pattern" {
+                    modifiers.push("mutable".to_string());
+                }
+                
+                let mut cursor = pattern.walk();
+                let children: Vec<(Option<&str>, Node)> = pattern
+                    .children(&mut cursor)
+                    .enumerate()
+                    .map(|(index, child)| (pattern.field_name_for_child(index as u32), child))
+                    .collect();
+                
+                for (field, child) in children {
+                    let skipped = match field {
+                        Some("type") | Some("right") | Some("condition") => true,
+                        // In `{ key: value }` destructuring the value is the binding itself
+                        Some("value") => pattern.kind() != "pair_pattern",
+                        _ => false,
+                    };
+                    if skipped || !child.is_named() {
+                        continue;
+                    }
+                    self.declare_pattern_with(child, token_type, modifiers.clone());
+                }
+            }
+        }
+    }
+    
+    fn declare(&mut self, name: Node, token_type: &'static str, modifiers: Vec<String>) {
+        let text = self.text(name).to_string();
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(text, (name.start_byte(), token_type, modifiers.clone()));
+        }
+        self.bindings.insert(name.start_byte(), name.start_byte());
+        
+        let mut declaration_modifiers = vec!["declaration".to_string()];
+        declaration_modifiers.extend(modifiers);
+        self.emit(name, token_type, declaration_modifiers);
+    }
+    
+    fn lookup(&self, name: Node) -> Option<(&'static str, Vec<String>)> {
+        self.resolve(name).map(|(_, token_type, modifiers)| (*token_type, modifiers.clone()))
+    }
+    
+    // The innermost declaration in scope with this name
+    fn resolve(&self, name: Node) -> Option<&(usize, &'static str, Vec<String>)> {
+        let text = self.text(name);
+        self.scopes.iter().rev().find_map(|scope| scope.get(text))
+    }
+    
+    // async/static modifiers from keyword children and modifier lists
+    fn declaration_modifiers(&self, node: Node) -> Vec<String> {
+        let mut modifiers = Vec::new();
+        let mut cursor = node.walk();
+        
+        for child in node.children(&mut cursor) {
+            let words: Vec<&str> = match child.kind() {
+                "async" => vec!["async"],
+                "static" => vec!["static"],
+                "function_modifiers" | "modifiers" | "storage_class_specifier" => self.text(child).split_whitespace().collect(),
+                _ => continue,
+            };
+            
+            for word in words {
+                let modifier = match word {
+                    "async" | "suspend" => "async",
+                    "static" => "static",
+                    _ => continue,
+                };
+                if !modifiers.iter().any(|existing| existing == modifier) {
+                    modifiers.push(modifier.to_string());
+                }
+            }
+        }
+        
+        modifiers
+    }
+    
+    fn emit(&mut self, node: Node, token_type: &str, modifiers: Vec<String>) {
+        if !self.claimed.insert(node.start_byte()) {
+            return;
+        }
+        
+        self.tokens.push(SemanticToken {
+            start: node.start_byte(),
+            end: node.end_byte(),
+            token_type: token_type.to_string(),
+            modifiers,
+        });
+    }
+    
+    fn text(&self, node: Node) -> &'a str {
+        node.utf8_text(self.source).unwrap_or("")
+    }
 }
 
-// Get current line
-fn get_current_line(text: &str) -> String {
-    let lines: Vec<&str> = text.split('\n').collect();
-    lines.last().unwrap_or(&"").to_string()
+#[cfg(feature = "tree-sitter-support")]
+fn is_function_node(kind: &str) -> bool {
+    matches!(
+        kind,
+        "function_item" | "function_signature_item" | "function_definition" | "function_declaration"
+            | "generator_function_declaration" | "method_declaration" | "constructor_declaration" | "method_definition"
+    )
 }
 
-// Get current word
-fn get_current_word(line: String) -> String {
-    let words: Vec<&str> = line.split(|c: char| !c.is_alphanumeric() && c != '_').collect();
-    words.last().unwrap_or(&"").to_string()
+// Whether a function is declared inside an impl, trait, class or interface body
+#[cfg(feature = "tree-sitter-support")]
+fn is_inside_type(node: Node) -> bool {
+    let mut current = node.parent();
+    
+    while let Some(parent) = current {
+        match parent.kind() {
+            "impl_item" | "trait_item" | "class_definition" | "class_body" | "class_declaration" | "interface_body" => return true,
+            kind if is_function_node(kind) => return false,
+            _ => current = parent.parent(),
+        }
+    }
+    
+    false
 }
 
-// Add Rust completions
-fn add_rust_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
-    let rust_snippets = [
-        CompletionItem {
-            label: "fn".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Function declaration".to_string()),
-            documentation: Some("Create a new function".to_string()),
-            insert_text: "fn ${1:name}(${2:params}) -> ${3:return_type} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "struct".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Struct declaration".to_string()),
-            documentation: Some("Create a new struct".to_string()),
-            insert_text: "struct ${1:Name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "impl".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Implementation block".to_string()),
-            documentation: Some("Create an implementation block".to_string()),
-            insert_text: "impl ${1:Type} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "match".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Match expression".to_string()),
-            documentation: Some("Create a match expression".to_string()),
-            insert_text: "match ${1:expression} {\n\t${2:pattern} => ${3:expression},\n\t_ => ${0},\n}".to_string(),
-        },
-        CompletionItem {
-            label: "if".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("If statement".to_string()),
-            documentation: Some("Create an if statement".to_string()),
-            insert_text: "if ${1:condition} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "for".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("For loop".to_string()),
-            documentation: Some("Create a for loop".to_string()),
-            insert_text: "for ${1:item} in ${2:collection} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "while".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("While loop".to_string()),
-            documentation: Some("Create a while loop".to_string()),
-            insert_text: "while ${1:condition} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "let".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Variable declaration".to_string()),
-            documentation: Some("Create a variable declaration".to_string()),
-            insert_text: "let ${1:name}: ${2:type} = ${0:value};".to_string(),
-        },
-        CompletionItem {
-            label: "enum".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Enum declaration".to_string()),
-            documentation: Some("Create an enum declaration".to_string()),
-            insert_text: "enum ${1:Name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "trait".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Trait declaration".to_string()),
-            documentation: Some("Create a trait declaration".to_string()),
-            insert_text: "trait ${1:Name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "mod".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Module declaration".to_string()),
-            documentation: Some("Create a module declaration".to_string()),
-            insert_text: "mod ${1:name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "use".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Use declaration".to_string()),
-            documentation: Some("Create a use declaration".to_string()),
-            insert_text: "use ${1:path};".to_string(),
-        },
-        CompletionItem {
-            label: "println".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Print to console".to_string()),
-            documentation: Some("Print to standard output".to_string()),
-            insert_text: "println!(\"${1:{}}\", ${0});".to_string(),
+// The name node of a definition: its `name` field, a C/C++ declarator chain, or the first identifier child
+#[cfg(feature = "tree-sitter-support")]
+fn definition_name(node: Node) -> Option<Node> {
+    if let Some(name) = node.child_by_field_name("name") {
+        return Some(name);
+    }
+    
+    if let Some(mut declarator) = node.child_by_field_name("declarator") {
+        while let Some(inner) = declarator.child_by_field_name("declarator") {
+            declarator = inner;
+        }
+        return Some(node_name_part(declarator));
+    }
+    
+    let mut cursor = node.walk();
+    let name = node
+        .named_children(&mut cursor)
+        .find(|child| matches!(child.kind(), "identifier" | "simple_identifier" | "type_identifier"));
+    name
+}
+
+// Last segment of a possibly qualified name, e.g. `name` in `std::name` or `Class::name`
+#[cfg(feature = "tree-sitter-support")]
+fn node_name_part(node: Node) -> Node {
+    node.child_by_field_name("name").unwrap_or(node)
+}
+
+#[cfg(feature = "tree-sitter-support")]
+fn has_child_kind(node: Node, kind: &str) -> bool {
+    let mut cursor = node.walk();
+    let found = node.children(&mut cursor).any(|child| child.kind() == kind);
+    found
+}
+
+#[cfg(feature = "tree-sitter-support")]
+fn starts_uppercase(text: &str) -> bool {
+    text.chars().next().map_or(false, |c| c.is_uppercase())
+}
+
+// Segments of a path like `module::name` never name a local
+#[cfg(feature = "tree-sitter-support")]
+fn is_path_segment(node: Node) -> bool {
+    node.parent().map_or(false, |parent| {
+        matches!(parent.kind(), "scoped_identifier" | "scoped_type_identifier" | "scoped_use_list" | "use_as_clause")
+    })
+}
+
+// Identifier nodes that can name a variable, including shorthand `{ x }` fields
+#[cfg(feature = "tree-sitter-support")]
+fn is_reference_identifier(kind: &str) -> bool {
+    matches!(
+        kind,
+        "identifier" | "shorthand_field_identifier" | "shorthand_property_identifier" | "shorthand_property_identifier_pattern"
+    )
+}
+
+// Highlight with regex. Every loop stops once the operation is cancelled, keeping the
+// highlights found so far. Without `full` the function-call and type patterns are skipped
+fn highlight_with_regex(content: &str, language_config: &LanguageConfig, full: bool) -> Vec<SyntaxHighlight> {
+    let mut highlights = Vec::new();
+    
+    let compiled;
+    let patterns = match &language_config.highlight_patterns {
+        Some(patterns) => patterns,
+        None => {
+            compiled = HighlightPatterns::compile(language_config);
+            &compiled
+        }
+    };
+    
+    // Highlight keywords
+    if let Some(regex) = &patterns.keywords {
+        for mat in regex.find_iter(content).take_while(|_| !is_cancelled()) {
+            highlights.push(SyntaxHighlight {
+                start: mat.start(),
+                end: mat.end(),
+                type_: "keyword".to_string(),
+            });
+        }
+    }
+    
+    // Highlight operators
+    if let Some(regex) = &patterns.operators {
+        for mat in regex.find_iter(content).take_while(|_| !is_cancelled()) {
+            highlights.push(SyntaxHighlight {
+                start: mat.start(),
+                end: mat.end(),
+                type_: "operator".to_string(),
+            });
+        }
+    }
+    
+    // Highlight strings
+    for regex in &patterns.strings {
+        for mat in regex.find_iter(content).take_while(|_| !is_cancelled()) {
+            highlights.push(SyntaxHighlight {
+                start: mat.start(),
+                end: mat.end(),
+                type_: "string".to_string(),
+            });
+        }
+    }
+    
+    // Highlight comments
+    if let Some(regex) = &patterns.line_comment {
+        for mat in regex.find_iter(content).take_while(|_| !is_cancelled()) {
+            highlights.push(SyntaxHighlight {
+                start: mat.start(),
+                end: mat.end(),
+                type_: "comment".to_string(),
+            });
+        }
+    }
+    
+    if let Some(regex) = &patterns.block_comment {
+        for mat in regex.find_iter(content).take_while(|_| !is_cancelled()) {
+            highlights.push(SyntaxHighlight {
+                start: mat.start(),
+                end: mat.end(),
+                type_: "comment".to_string(),
+            });
+        }
+    }
+    
+    // Highlight numbers
+    for mat in NUMBER_REGEX.find_iter(content).take_while(|_| !is_cancelled()) {
+        highlights.push(SyntaxHighlight {
+            start: mat.start(),
+            end: mat.end(),
+            type_: "number".to_string(),
+        });
+    }
+    
+    if !full {
+        return highlights;
+    }
+    
+    // Highlight function calls
+    for captures in FUNCTION_CALL_REGEX.captures_iter(content).take_while(|_| !is_cancelled()) {
+        if let Some(function_match) = captures.get(1) {
+            let function_name = function_match.as_str();
+            
+            // Skip if the function name is a keyword
+            if !language_config.keywords.contains(&function_name.to_string()) {
+                highlights.push(SyntaxHighlight {
+                    start: function_match.start(),
+                    end: function_match.end(),
+                    type_: "function".to_string(),
+                });
+            }
+        }
+    }
+    
+    // Highlight types (capitalized identifiers)
+    for mat in TYPE_NAME_REGEX.find_iter(content).take_while(|_| !is_cancelled()) {
+        highlights.push(SyntaxHighlight {
+            start: mat.start(),
+            end: mat.end(),
+            type_: "type".to_string(),
+        });
+    }
+    
+    highlights
+}
+
+// Get code completions
+pub fn get_completions(content: &str, position: usize, language_id: &str) -> Vec<CompletionItem> {
+    get_completion_list(content, position, language_id).items
+}
+
+// How long completions wait for plugins before they are shown without the plugins' items
+const PLUGIN_COMPLETION_TIMEOUT_MS: u64 = 500;
+
+// Get code completions, saying whether they are members completed after a trigger like `foo.`.
+// Items from plugins handling onCompletionRequest are added after the built-in ones
+pub fn get_completion_list(content: &str, position: usize, language_id: &str) -> CompletionList {
+    let mut list = builtin_completion_list(content, position, language_id);
+    
+    // The editor state is released by now, since a plugin may call back into the editor
+    add_plugin_completions(&mut list.items, content, position, language_id);
+    
+    list
+}
+
+// Ask the plugins handling onCompletionRequest for more items, leaving out the ones that repeat
+// an item already offered. Completions are shown while typing, so plugins get much less time
+// than other hooks
+fn add_plugin_completions(completions: &mut Vec<CompletionItem>, content: &str, position: usize, language_id: &str) {
+    let (line, column) = offset_to_position(content, position, false);
+    let payload = HookPayload::CompletionRequest(CompletionRequestPayload {
+        path: String::new(),
+        content: content.to_string(),
+        line: line as u32,
+        column: column as u32,
+        position,
+        language: language_id.to_string(),
+    });
+    
+    let timeout = Duration::from_millis(PLUGIN_COMPLETION_TIMEOUT_MS);
+    for (_, data) in pluginsystem::broadcast_plugin_hook("onCompletionRequest", &payload, timeout) {
+        for item in parse_plugin_completions(&data) {
+            if !completions.iter().any(|existing| existing.label == item.label && existing.kind == item.kind) {
+                completions.push(item);
+            }
+        }
+    }
+}
+
+// The items of a plugin's onCompletionRequest result, a JSON array of completion items.
+// Malformed items, e.g. with an unknown kind, are skipped rather than failing the whole result
+fn parse_plugin_completions(data: &str) -> Vec<CompletionItem> {
+    match serde_json::from_str::<Vec<serde_json::Value>>(data) {
+        Ok(items) => items
+            .into_iter()
+            .filter_map(|item| serde_json::from_value::<CompletionItem>(item).ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// Completions from the editor itself
+fn builtin_completion_list(content: &str, position: usize, language_id: &str) -> CompletionList {
+    let state = editor_state();
+    let no_completions = CompletionList { trigger: None, items: Vec::new() };
+    
+    if !state.initialized {
+        return no_completions;
+    }
+    
+    let language_config = match state.languages.get(language_id) {
+        Some(config) => config,
+        None => return no_completions,
+    };
+    
+    // After `.`, `::` or `->` keywords and snippets make no sense, only members do
+    if let Some(trigger) = completion_trigger(&content[..position], language_config) {
+        return CompletionList {
+            trigger: Some(trigger.to_string()),
+            items: get_member_completions(content, position),
+        };
+    }
+    
+    let snippets = state.snippets.get(language_id).map(|snippets| snippets.as_slice()).unwrap_or(&[]);
+    
+    #[cfg(feature = "tree-sitter-support")]
+    {
+        if let Some(parser) = state.parsers.get(language_id) {
+            let items = get_completions_with_tree_sitter(content, position, parser, language_config, snippets);
+            return CompletionList { trigger: None, items };
+        }
+    }
+    
+    // Fallback to simple completions
+    let items = get_simple_completions(content, position, language_config, snippets);
+    CompletionList { trigger: None, items }
+}
+
+// Tokens after which completions are members of the expression before them
+fn member_triggers(language_id: &str) -> &'static [&'static str] {
+    match language_id {
+        "rust" | "kotlin" | "java" => &[".", "::"],
+        "cpp" => &[".", "::", "->"],
+        "python" | "javascript" | "typescript" | "tsx" => &["."],
+        _ => &[],
+    }
+}
+
+// The member-access token directly before the word being typed, as in `foo.ba` or `Vec::`
+fn completion_trigger(text_before_cursor: &str, language_config: &LanguageConfig) -> Option<&'static str> {
+    let current_line = get_current_line(text_before_cursor);
+    if let Some(comment) = &language_config.comment_line {
+        if current_line.contains(comment.as_str()) {
+            return None;
+        }
+    }
+    
+    let before_word = text_before_cursor.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+    let trigger = member_triggers(&language_config.id)
+        .iter()
+        .copied()
+        .find(|trigger| before_word.ends_with(trigger))?;
+    
+    // Not a range like `0..` or a number like `1.`, which are not followed by members.
+    // Tuple fields as in `pair.0.` are
+    if trigger == "." {
+        let receiver = &before_word[..before_word.len() - 1];
+        let token_start = receiver.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_').len();
+        let token = &receiver[token_start..];
+        let is_number = !token.is_empty() && token.chars().all(|c| c.is_ascii_digit()) && !receiver[..token_start].ends_with('.');
+        if receiver.ends_with('.') || is_number {
+            return None;
+        }
+    }
+    
+    Some(trigger)
+}
+
+// Names used as members or declared as functions anywhere in the document that start with the
+// word being typed, other than that word itself
+fn get_member_completions(content: &str, position: usize) -> Vec<CompletionItem> {
+    let current_word = get_current_word(get_current_line(&content[..position]));
+    let word_start = position - current_word.len();
+    
+    let mut names = std::collections::BTreeSet::new();
+    for captures in MEMBER_NAME_REGEX.captures_iter(content) {
+        let name = match captures.get(1).or_else(|| captures.get(2)) {
+            Some(name) => name,
+            None => continue,
+        };
+        if name.start() != word_start && name.as_str().starts_with(&current_word) {
+            names.insert(name.as_str());
+        }
+    }
+    
+    names
+        .into_iter()
+        .map(|name| CompletionItem {
+            label: name.to_string(),
+            kind: CompletionKind::Member,
+            detail: None,
+            documentation: None,
+            insert_text: name.to_string(),
+        })
+        .collect()
+}
+
+// Register a snippet for a language for the rest of the session. A snippet with the same label
+// replaces the earlier one, and replaces a built-in snippet of that label in completions
+pub fn register_snippet(language_id: &str, snippet: SnippetDef) -> Result<()> {
+    if snippet.label.trim().is_empty() || snippet.prefix.trim().is_empty() {
+        return Err(anyhow!("Snippet label and prefix cannot be empty"));
+    }
+    if snippet.body.is_empty() {
+        return Err(anyhow!("Snippet body cannot be empty"));
+    }
+    
+    let mut state = editor_state();
+    let snippets = state.snippets.entry(language_id.to_string()).or_default();
+    snippets.retain(|existing| existing.label != snippet.label);
+    snippets.push(snippet);
+    
+    Ok(())
+}
+
+// Remove the registered snippets of a language, or of every language when `language_id` is empty
+pub fn clear_snippets(language_id: &str) {
+    let mut state = editor_state();
+    
+    if language_id.is_empty() {
+        state.snippets.clear();
+    } else {
+        state.snippets.remove(language_id);
+    }
+}
+
+// Add the registered snippets whose prefix starts with the current word, in place of built-ins with the same label
+fn add_registered_snippets(completions: &mut Vec<CompletionItem>, current_word: &str, snippets: &[SnippetDef]) {
+    for snippet in snippets {
+        if !snippet.prefix.starts_with(current_word) {
+            continue;
+        }
+        
+        completions.retain(|item| !(item.kind == CompletionKind::Snippet && item.label == snippet.label));
+        completions.push(CompletionItem {
+            label: snippet.label.clone(),
+            kind: CompletionKind::Snippet,
+            detail: if snippet.description.is_empty() { None } else { Some(snippet.description.clone()) },
+            documentation: None,
+            insert_text: snippet.body.clone(),
+        });
+    }
+}
+
+// Get completions with tree-sitter
+#[cfg(feature = "tree-sitter-support")]
+fn get_completions_with_tree_sitter(content: &str, position: usize, parser: &Parser, language_config: &LanguageConfig, snippets: &[SnippetDef]) -> Vec<CompletionItem> {
+    let mut completions = Vec::new();
+    
+    // Parse the code
+    let tree = match parser.parse(content, None) {
+        Some(tree) => tree,
+        None => return Vec::new(),
+    };
+    
+    // Find the node at the cursor position
+    let node = find_node_at_position(&tree.root_node(), position);
+    
+    // Get the node type
+    let node_type = node.kind();
+    
+    // Get the text before the cursor
+    let text_before_cursor = &content[..position];
+    let current_line = get_current_line(text_before_cursor);
+    let current_word = get_current_word(current_line);
+    
+    // Add completions based on node type and context
+    match node_type {
+        "identifier" => {
+            // Add keyword completions
+            for keyword in &language_config.keywords {
+                if keyword.starts_with(&current_word) {
+                    completions.push(CompletionItem {
+                        label: keyword.clone(),
+                        kind: CompletionKind::Keyword,
+                        detail: None,
+                        documentation: None,
+                        insert_text: keyword.clone(),
+                    });
+                }
+            }
+            
+            // Add language-specific completions
+            match language_config.id.as_str() {
+                "rust" => add_rust_completions(&mut completions, &current_word),
+                "kotlin" => add_kotlin_completions(&mut completions, &current_word),
+                "java" => add_java_completions(&mut completions, &current_word),
+                "python" => add_python_completions(&mut completions, &current_word),
+                "javascript" | "typescript" | "tsx" => add_js_ts_completions(&mut completions, &current_word),
+                _ => {}
+            }
+            
+            add_registered_snippets(&mut completions, &current_word, snippets);
         },
-        CompletionItem {
-            label: "derive".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Derive attribute".to_string()),
-            documentation: Some("Add derive attribute".to_string()),
-            insert_text: "#[derive(${1:Debug})]".to_string(),
+        "string" | "string_literal" => {
+            // Add string-specific completions
+            match language_config.id.as_str() {
+                "rust" => add_rust_string_completions(&mut completions, &current_word),
+                "kotlin" => add_kotlin_string_completions(&mut completions, &current_word),
+                "java" => add_java_string_completions(&mut completions, &current_word),
+                "python" => add_python_string_completions(&mut completions, &current_word),
+                "javascript" | "typescript" | "tsx" => add_js_ts_string_completions(&mut completions, &current_word),
+                _ => {}
+            }
         },
-    ];
+        _ => {
+            // Add general completions
+            add_general_completions(&mut completions, &current_word, language_config);
+        }
+    }
     
-    for snippet in &rust_snippets {
-        if snippet.label.starts_with(current_word) {
-            completions.push(snippet.clone());
+    completions
+}
+
+// Find node at position
+#[cfg(feature = "tree-sitter-support")]
+fn find_node_at_position<'a>(node: &Node<'a>, position: usize) -> Node<'a> {
+    if position < node.start_byte() || position > node.end_byte() {
+        return *node;
+    }
+    
+    // A cursor between two nodes, as in `(x` or `x)`, belongs to the named one
+    let mut touching = None;
+    for i in 0..node.child_count() {
+        if let Some(child) = node.child(i) {
+            if position >= child.start_byte() && position < child.end_byte() {
+                let child = match touching {
+                    Some(previous) if !child.is_named() => previous,
+                    _ => child,
+                };
+                return find_node_at_position(&child, position);
+            }
+            if position == child.end_byte() && child.is_named() {
+                touching = Some(child);
+            }
+        }
+    }
+    
+    match touching {
+        Some(child) => find_node_at_position(&child, position),
+        None => *node,
+    }
+}
+
+// Get simple completions
+fn get_simple_completions(content: &str, position: usize, language_config: &LanguageConfig, snippets: &[SnippetDef]) -> Vec<CompletionItem> {
+    let mut completions = Vec::new();
+    
+    // Get the text before the cursor
+    let text_before_cursor = &content[..position];
+    let current_line = get_current_line(text_before_cursor);
+    let current_word = get_current_word(current_line);
+    
+    // Add keyword completions
+    for keyword in &language_config.keywords {
+        if keyword.starts_with(&current_word) {
+            completions.push(CompletionItem {
+                label: keyword.clone(),
+                kind: CompletionKind::Keyword,
+                detail: None,
+                documentation: None,
+                insert_text: keyword.clone(),
+            });
+        }
+    }
+    
+    // Add language-specific completions
+    match language_config.id.as_str() {
+        "rust" => add_rust_completions(&mut completions, &current_word),
+        "kotlin" => add_kotlin_completions(&mut completions, &current_word),
+        "java" => add_java_completions(&mut completions, &current_word),
+        "python" => add_python_completions(&mut completions, &current_word),
+        "javascript" | "typescript" | "tsx" => add_js_ts_completions(&mut completions, &current_word),
+        _ => {}
+    }
+    
+    add_registered_snippets(&mut completions, &current_word, snippets);
+    
+    completions
+}
+
+// Get current line
+fn get_current_line(text: &str) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    lines.last().unwrap_or(&"").to_string()
+}
+
+// Get current word
+fn get_current_word(line: String) -> String {
+    let words: Vec<&str> = line.split(|c: char| !c.is_alphanumeric() && c != '_').collect();
+    words.last().unwrap_or(&"").to_string()
+}
+
+// Add Rust completions
+fn add_rust_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
+    let rust_snippets = [
+        CompletionItem {
+            label: "fn".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Function declaration".to_string()),
+            documentation: Some("Create a new function".to_string()),
+            insert_text: "fn ${1:name}(${2:params}) -> ${3:return_type} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "struct".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Struct declaration".to_string()),
+            documentation: Some("Create a new struct".to_string()),
+            insert_text: "struct ${1:Name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "impl".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Implementation block".to_string()),
+            documentation: Some("Create an implementation block".to_string()),
+            insert_text: "impl ${1:Type} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "match".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Match expression".to_string()),
+            documentation: Some("Create a match expression".to_string()),
+            insert_text: "match ${1:expression} {\n\t${2:pattern} => ${3:expression},\n\t_ => ${0},\n}".to_string(),
+        },
+        CompletionItem {
+            label: "if".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("If statement".to_string()),
+            documentation: Some("Create an if statement".to_string()),
+            insert_text: "if ${1:condition} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "for".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("For loop".to_string()),
+            documentation: Some("Create a for loop".to_string()),
+            insert_text: "for ${1:item} in ${2:collection} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "while".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("While loop".to_string()),
+            documentation: Some("Create a while loop".to_string()),
+            insert_text: "while ${1:condition} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "let".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Variable declaration".to_string()),
+            documentation: Some("Create a variable declaration".to_string()),
+            insert_text: "let ${1:name}: ${2:type} = ${0:value};".to_string(),
+        },
+        CompletionItem {
+            label: "enum".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Enum declaration".to_string()),
+            documentation: Some("Create an enum declaration".to_string()),
+            insert_text: "enum ${1:Name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "trait".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Trait declaration".to_string()),
+            documentation: Some("Create a trait declaration".to_string()),
+            insert_text: "trait ${1:Name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "mod".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Module declaration".to_string()),
+            documentation: Some("Create a module declaration".to_string()),
+            insert_text: "mod ${1:name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "use".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Use declaration".to_string()),
+            documentation: Some("Create a use declaration".to_string()),
+            insert_text: "use ${1:path};".to_string(),
+        },
+        CompletionItem {
+            label: "println".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Print to console".to_string()),
+            documentation: Some("Print to standard output".to_string()),
+            insert_text: "println!(\"${1:{}}\", ${0});".to_string(),
+        },
+        CompletionItem {
+            label: "derive".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Derive attribute".to_string()),
+            documentation: Some("Add derive attribute".to_string()),
+            insert_text: "#[derive(${1:Debug})]".to_string(),
+        },
+    ];
+    
+    for snippet in &rust_snippets {
+        if snippet.label.starts_with(current_word) {
+            completions.push(snippet.clone());
+        }
+    }
+}
+
+// Add Kotlin completions
+fn add_kotlin_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
+    let kotlin_snippets = [
+        CompletionItem {
+            label: "fun".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Function declaration".to_string()),
+            documentation: Some("Create a new function".to_string()),
+            insert_text: "fun ${1:name}(${2:params}): ${3:Unit} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "class".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Class declaration".to_string()),
+            documentation: Some("Create a new class".to_string()),
+            insert_text: "class ${1:Name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "data class".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Data class declaration".to_string()),
+            documentation: Some("Create a data class".to_string()),
+            insert_text: "data class ${1:Name}(${0})".to_string(),
+        },
+        CompletionItem {
+            label: "interface".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Interface declaration".to_string()),
+            documentation: Some("Create an interface".to_string()),
+            insert_text: "interface ${1:Name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "when".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("When expression".to_string()),
+            documentation: Some("Create a when expression".to_string()),
+            insert_text: "when (${1:expression}) {\n\t${2:value} -> ${3:result}\n\telse -> ${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "if".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("If statement".to_string()),
+            documentation: Some("Create an if statement".to_string()),
+            insert_text: "if (${1:condition}) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "for".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("For loop".to_string()),
+            documentation: Some("Create a for loop".to_string()),
+            insert_text: "for (${1:item} in ${2:collection}) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "while".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("While loop".to_string()),
+            documentation: Some("Create a while loop".to_string()),
+            insert_text: "while (${1:condition}) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "val".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Immutable variable declaration".to_string()),
+            documentation: Some("Create an immutable variable".to_string()),
+            insert_text: "val ${1:name}: ${2:Type} = ${0}".to_string(),
+        },
+        CompletionItem {
+            label: "var".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Mutable variable declaration".to_string()),
+            documentation: Some("Create a mutable variable".to_string()),
+            insert_text: "var ${1:name}: ${2:Type} = ${0}".to_string(),
+        },
+        CompletionItem {
+            label: "companion object".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Companion object declaration".to_string()),
+            documentation: Some("Create a companion object".to_string()),
+            insert_text: "companion object {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "object".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Object declaration".to_string()),
+            documentation: Some("Create an object".to_string()),
+            insert_text: "object ${1:Name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "enum class".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Enum class declaration".to_string()),
+            documentation: Some("Create an enum class".to_string()),
+            insert_text: "enum class ${1:Name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "println".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Print to console".to_string()),
+            documentation: Some("Print to standard output".to_string()),
+            insert_text: "println(\"${0}\")".to_string(),
+        },
+    ];
+    
+    for snippet in &kotlin_snippets {
+        if snippet.label.starts_with(current_word) {
+            completions.push(snippet.clone());
+        }
+    }
+}
+
+// Add Java completions
+fn add_java_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
+    let java_snippets = [
+        CompletionItem {
+            label: "class".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Class declaration".to_string()),
+            documentation: Some("Create a new class".to_string()),
+            insert_text: "class ${1:Name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "interface".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Interface declaration".to_string()),
+            documentation: Some("Create an interface".to_string()),
+            insert_text: "interface ${1:Name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "enum".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Enum declaration".to_string()),
+            documentation: Some("Create an enum".to_string()),
+            insert_text: "enum ${1:Name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "public class".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Public class declaration".to_string()),
+            documentation: Some("Create a public class".to_string()),
+            insert_text: "public class ${1:Name} {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "public static void main".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Main method".to_string()),
+            documentation: Some("Create a main method".to_string()),
+            insert_text: "public static void main(String[] args) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "if".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("If statement".to_string()),
+            documentation: Some("Create an if statement".to_string()),
+            insert_text: "if (${1:condition}) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "for".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("For loop".to_string()),
+            documentation: Some("Create a for loop".to_string()),
+            insert_text: "for (int ${1:i} = 0; ${1:i} < ${2:size}; ${1:i}++) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "foreach".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("For-each loop".to_string()),
+            documentation: Some("Create a for-each loop".to_string()),
+            insert_text: "for (${1:Type} ${2:item} : ${3:collection}) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "while".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("While loop".to_string()),
+            documentation: Some("Create a while loop".to_string()),
+            insert_text: "while (${1:condition}) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "switch".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Switch statement".to_string()),
+            documentation: Some("Create a switch statement".to_string()),
+            insert_text: "switch (${1:expression}) {\n\tcase ${2:value}:\n\t\t${0}\n\t\tbreak;\n\tdefault:\n\t\tbreak;\n}".to_string(),
+        },
+        CompletionItem {
+            label: "try".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Try-catch block".to_string()),
+            documentation: Some("Create a try-catch block".to_string()),
+            insert_text: "try {\n\t${0}\n} catch (${1:Exception} e) {\n\te.printStackTrace();\n}".to_string(),
+        },
+        CompletionItem {
+            label: "sout".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("System.out.println".to_string()),
+            documentation: Some("Print to standard output".to_string()),
+            insert_text: "System.out.println(${0});".to_string(),
+        },
+    ];
+    
+    for snippet in &java_snippets {
+        if snippet.label.starts_with(current_word) {
+            completions.push(snippet.clone());
+        }
+    }
+}
+
+// Add Python completions
+fn add_python_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
+    let python_snippets = [
+        CompletionItem {
+            label: "def".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Function definition".to_string()),
+            documentation: Some("Define a new function".to_string()),
+            insert_text: "def ${1:name}(${2:params}):\n\t${0}".to_string(),
+        },
+        CompletionItem {
+            label: "class".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Class definition".to_string()),
+            documentation: Some("Define a new class".to_string()),
+            insert_text: "class ${1:Name}:\n\tdef __init__(self, ${2:params}):\n\t\t${0}".to_string(),
+        },
+        CompletionItem {
+            label: "if".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("If statement".to_string()),
+            documentation: Some("Create an if statement".to_string()),
+            insert_text: "if ${1:condition}:\n\t${0}".to_string(),
+        },
+        CompletionItem {
+            label: "for".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("For loop".to_string()),
+            documentation: Some("Create a for loop".to_string()),
+            insert_text: "for ${1:item} in ${2:collection}:\n\t${0}".to_string(),
+        },
+        CompletionItem {
+            label: "while".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("While loop".to_string()),
+            documentation: Some("Create a while loop".to_string()),
+            insert_text: "while ${1:condition}:\n\t${0}".to_string(),
+        },
+        CompletionItem {
+            label: "try".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Try-except block".to_string()),
+            documentation: Some("Create a try-except block".to_string()),
+            insert_text: "try:\n\t${1}\nexcept ${2:Exception} as e:\n\t${0}".to_string(),
+        },
+        CompletionItem {
+            label: "import".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Import statement".to_string()),
+            documentation: Some("Import a module".to_string()),
+            insert_text: "import ${0}".to_string(),
+        },
+        CompletionItem {
+            label: "from".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("From import statement".to_string()),
+            documentation: Some("Import from a module".to_string()),
+            insert_text: "from ${1:module} import ${0}".to_string(),
+        },
+        CompletionItem {
+            label: "print".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Print statement".to_string()),
+            documentation: Some("Print to standard output".to_string()),
+            insert_text: "print(${0})".to_string(),
+        },
+        CompletionItem {
+            label: "lambda".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Lambda expression".to_string()),
+            documentation: Some("Create a lambda expression".to_string()),
+            insert_text: "lambda ${1:params}: ${0}".to_string(),
+        },
+    ];
+    
+    for snippet in &python_snippets {
+        if snippet.label.starts_with(current_word) {
+            completions.push(snippet.clone());
+        }
+    }
+}
+
+// Add JavaScript/TypeScript completions
+fn add_js_ts_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
+    let js_ts_snippets = [
+        CompletionItem {
+            label: "function".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Function declaration".to_string()),
+            documentation: Some("Create a new function".to_string()),
+            insert_text: "function ${1:name}(${2:params}) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "arrow".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Arrow function".to_string()),
+            documentation: Some("Create an arrow function".to_string()),
+            insert_text: "(${1:params}) => {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "class".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Class declaration".to_string()),
+            documentation: Some("Create a new class".to_string()),
+            insert_text: "class ${1:Name} {\n\tconstructor(${2:params}) {\n\t\t${0}\n\t}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "if".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("If statement".to_string()),
+            documentation: Some("Create an if statement".to_string()),
+            insert_text: "if (${1:condition}) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "for".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("For loop".to_string()),
+            documentation: Some("Create a for loop".to_string()),
+            insert_text: "for (let ${1:i} = 0; ${1:i} < ${2:array}.length; ${1:i}++) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "forin".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("For-in loop".to_string()),
+            documentation: Some("Create a for-in loop".to_string()),
+            insert_text: "for (const ${1:key} in ${2:object}) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "forof".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("For-of loop".to_string()),
+            documentation: Some("Create a for-of loop".to_string()),
+            insert_text: "for (const ${1:item} of ${2:array}) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "while".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("While loop".to_string()),
+            documentation: Some("Create a while loop".to_string()),
+            insert_text: "while (${1:condition}) {\n\t${0}\n}".to_string(),
+        },
+        CompletionItem {
+            label: "switch".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Switch statement".to_string()),
+            documentation: Some("Create a switch statement".to_string()),
+            insert_text: "switch (${1:expression}) {\n\tcase ${2:value}:\n\t\t${0}\n\t\tbreak;\n\tdefault:\n\t\tbreak;\n}".to_string(),
+        },
+        CompletionItem {
+            label: "try".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Try-catch block".to_string()),
+            documentation: Some("Create a try-catch block".to_string()),
+            insert_text: "try {\n\t${0}\n} catch (${1:error}) {\n\tconsole.error(${1:error});\n}".to_string(),
+        },
+        CompletionItem {
+            label: "import".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Import statement".to_string()),
+            documentation: Some("Import a module".to_string()),
+            insert_text: "import { ${1} } from '${2:module}';".to_string(),
+        },
+        CompletionItem {
+            label: "console.log".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("Console log".to_string()),
+            documentation: Some("Log to the console".to_string()),
+            insert_text: "console.log(${0});".to_string(),
+        },
+    ];
+    
+    for snippet in &js_ts_snippets {
+        if snippet.label.starts_with(current_word) {
+            completions.push(snippet.clone());
+        }
+    }
+}
+
+// Add Rust string completions
+fn add_rust_string_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
+    // No specific string completions for Rust
+}
+
+// Add Kotlin string completions
+fn add_kotlin_string_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
+    // No specific string completions for Kotlin
+}
+
+// Add Java string completions
+fn add_java_string_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
+    // No specific string completions for Java
+}
+
+// Add Python string completions
+fn add_python_string_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
+    // No specific string completions for Python
+}
+
+// Add JavaScript/TypeScript string completions
+fn add_js_ts_string_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
+    // No specific string completions for JavaScript/TypeScript
+}
+
+// Add general completions
+fn add_general_completions(completions: &mut Vec<CompletionItem>, current_word: &str, language_config: &LanguageConfig) {
+    // Add common completions for all languages
+    let common_snippets = [
+        CompletionItem {
+            label: "if".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("If statement".to_string()),
+            documentation: Some("Create an if statement".to_string()),
+            insert_text: match language_config.id.as_str() {
+                "python" => "if ${1:condition}:\n\t${0}".to_string(),
+                "rust" => "if ${1:condition} {\n\t${0}\n}".to_string(),
+                _ => "if (${1:condition}) {\n\t${0}\n}".to_string(),
+            },
+        },
+        CompletionItem {
+            label: "for".to_string(),
+            kind: CompletionKind::Snippet,
+            detail: Some("For loop".to_string()),
+            documentation: Some("Create a for loop".to_string()),
+            insert_text: match language_config.id.as_str() {
+                "python" => "for ${1:item} in ${2:collection}:\n\t${0}".to_string(),
+                "rust" => "for ${1:item} in ${2:collection} {\n\t${0}\n}".to_string(),
+                "kotlin" => "for (${1:item} in ${2:collection}) {\n\t${0}\n}".to_string(),
+                "java" => "for (int ${1:i} = 0; ${1:i} < ${2:size}; ${1:i}++) {\n\t${0}\n}".to_string(),
+                "javascript" | "typescript" | "tsx" => "for (let ${1:i} = 0; ${1:i} < ${2:array}.length; ${1:i}++) {\n\t${0}\n}".to_string(),
+                _ => "for (${1:init}; ${2:condition}; ${3:update}) {\n\t${0}\n}".to_string(),
+            },
+        },
+    ];
+    
+    for snippet in &common_snippets {
+        if snippet.label.starts_with(current_word) {
+            completions.push(snippet.clone());
+        }
+    }
+}
+
+// Replace the options used by format_code for the rest of the session
+pub fn set_format_options(options: FormatOptions) {
+    editor_state().format_options = options;
+}
+
+// Format code
+pub fn format_code(content: &str, language_id: &str) -> String {
+    format_code_with_diagnostic(content, language_id).0
+}
+
+// google-java-format uses javac internals that JDK 16 and later only open up on request
+const GOOGLE_JAVA_FORMAT_JVM_ARGS: &[&str] = &[
+    "--add-exports=jdk.compiler/com.sun.tools.javac.api=ALL-UNNAMED",
+    "--add-exports=jdk.compiler/com.sun.tools.javac.code=ALL-UNNAMED",
+    "--add-exports=jdk.compiler/com.sun.tools.javac.file=ALL-UNNAMED",
+    "--add-exports=jdk.compiler/com.sun.tools.javac.parser=ALL-UNNAMED",
+    "--add-exports=jdk.compiler/com.sun.tools.javac.tree=ALL-UNNAMED",
+    "--add-exports=jdk.compiler/com.sun.tools.javac.util=ALL-UNNAMED",
+];
+
+// Format code, also saying why the document was returned unchanged when it could not be parsed
+pub fn format_code_with_diagnostic(content: &str, language_id: &str) -> (String, Option<FormatDiagnostic>) {
+    let options = {
+        let state = editor_state();
+        if !state.initialized {
+            return (content.to_string(), None);
+        }
+        state.format_options.clone()
+    };
+    
+    // External formatters can take seconds to start, so they run without holding the editor state
+    let external = match language_id {
+        "kotlin" => format_with_tool(content, language_id, &options.ktlint_path, &options.sdk_root).ok(),
+        "java" => format_with_tool(content, language_id, &options.google_java_format_path, &options.sdk_root).ok(),
+        "python" => format_python_with_tool(content),
+        _ => None,
+    };
+    
+    let (formatted, diagnostic) = match external {
+        Some(formatted) => (formatted, None),
+        None => format_document(&editor_state(), content, language_id),
+    };
+    
+    if diagnostic.is_some() {
+        return (formatted, diagnostic);
+    }
+    
+    (normalize_formatted(&formatted, language_id, &options), None)
+}
+
+// Whitespace is tidied for every language with a formatter of its own or none, except Markdown
+fn normalize_formatted(formatted: &str, language_id: &str, options: &FormatOptions) -> String {
+    normalize_whitespace(
+        formatted,
+        language_id,
+        options.strip_trailing_whitespace,
+        options.ensure_final_newline,
+        options.trim_trailing_blank_lines,
+    )
+}
+
+// Strip trailing spaces and tabs from each line, drop blank lines at the end of the document
+// and make sure a non-empty document ends with a line break. Each step can be turned off.
+// Line endings keep the document's style. Lines ending inside a TOML or Python multi-line
+// string keep their trailing whitespace, which is part of the string, and Markdown is left
+// alone since two trailing spaces are a line break there
+pub fn normalize_whitespace(content: &str, language_id: &str, strip_trailing: bool, ensure_final_newline: bool, trim_trailing_blank_lines: bool) -> String {
+    if language_id == "markdown" {
+        return content.to_string();
+    }
+    
+    let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<&str> = content.lines().collect();
+    
+    if strip_trailing {
+        let in_string = lines_ending_in_string(&lines, language_id);
+        for (line, in_string) in lines.iter_mut().zip(in_string) {
+            if !in_string {
+                *line = line.trim_end_matches([' ', '\t']);
+            }
+        }
+    }
+    
+    if trim_trailing_blank_lines {
+        while lines.last().map_or(false, |last| last.trim().is_empty()) {
+            lines.pop();
+        }
+    }
+    
+    let mut normalized = lines.join(line_ending);
+    if !lines.is_empty() && (ensure_final_newline || content.ends_with('\n')) {
+        normalized.push_str(line_ending);
+    }
+    
+    normalized
+}
+
+// Whether each line ends inside a multi-line string, for the languages that have them
+fn lines_ending_in_string(lines: &[&str], language_id: &str) -> Vec<bool> {
+    match language_id {
+        "toml" => {
+            let mut open_string = None;
+            let mut depth = 0;
+            lines
+                .iter()
+                .map(|line| {
+                    open_string = scan_toml_line(line, open_string, &mut depth);
+                    open_string.is_some()
+                })
+                .collect()
+        }
+        "python" => {
+            let mut state = PythonLineState::default();
+            lines
+                .iter()
+                .map(|line| {
+                    state.scan(line);
+                    state.open_string.is_some()
+                })
+                .collect()
+        }
+        _ => vec![false; lines.len()],
+    }
+}
+
+// Run the language's own formatter
+fn format_document(state: &EditorState, content: &str, language_id: &str) -> (String, Option<FormatDiagnostic>) {
+    let language_config = match state.languages.get(language_id) {
+        Some(config) => config,
+        None => return (content.to_string(), None),
+    };
+    
+    // Data formats and markup are parsed and written back out, which needs neither tree-sitter nor regex
+    let options = &state.format_options;
+    let parsed = match language_id {
+        "json" => Some(format_json(content, &options.indent_unit)),
+        "toml" => Some(format_toml(content, &options.indent_unit)),
+        "html" => Some(format_markup(content, true, &options.indent_unit)),
+        "xml" => Some(format_markup(content, false, &options.indent_unit)),
+        "css" => Some(format_css(content, &options.indent_unit, options.sort_properties)),
+        // black and autopep8 already ran in format_code_with_diagnostic, outside the lock
+        "python" => Some(reindent_python(content, &options.indent_unit)),
+        _ => None,
+    };
+    
+    if let Some(result) = parsed {
+        return match result {
+            Ok(formatted) => (formatted, None),
+            Err(diagnostic) => (content.to_string(), Some(diagnostic)),
+        };
+    }
+    
+    #[cfg(feature = "tree-sitter-support")]
+    {
+        if let Some(parser) = state.parsers.get(language_id) {
+            return (format_with_tree_sitter(content, parser, language_config), None);
+        }
+    }
+    
+    // Fallback to simple formatting
+    (format_with_regex(content, language_config), None)
+}
+
+// Format with tree-sitter
+#[cfg(feature = "tree-sitter-support")]
+fn format_with_tree_sitter(content: &str, parser: &Parser, language_config: &LanguageConfig) -> String {
+    // Parse the code
+    let tree = match parser.parse(content, None) {
+        Some(tree) => tree,
+        None => return content.to_string(),
+    };
+    
+    // Format based on language
+    match language_config.id.as_str() {
+        "rust" => format_rust_code(content, &tree),
+        "javascript" | "typescript" | "tsx" => format_js_ts_code(content, &tree),
+        _ => content.to_string(),
+    }
+}
+
+// Format with regex
+fn format_with_regex(content: &str, language_config: &LanguageConfig) -> String {
+    // Simple formatting based on language
+    match language_config.id.as_str() {
+        "rust" => format_rust_code_regex(content),
+        "javascript" | "typescript" | "tsx" => format_js_ts_code_regex(content),
+        _ => content.to_string(),
+    }
+}
+
+// Format Rust code with tree-sitter
+#[cfg(feature = "tree-sitter-support")]
+fn format_rust_code(content: &str, tree: &Tree) -> String {
+    // In a real implementation, this would use tree-sitter to format Rust code
+    // For now, just return the original content
+    content.to_string()
+}
+
+// Format JavaScript/TypeScript code with tree-sitter
+#[cfg(feature = "tree-sitter-support")]
+fn format_js_ts_code(content: &str, tree: &Tree) -> String {
+    // In a real implementation, this would use tree-sitter to format JavaScript/TypeScript code
+    // For now, just return the original content
+    content.to_string()
+}
+
+// Format Rust code with regex
+fn format_rust_code_regex(content: &str) -> String {
+    // In a real implementation, this would use regex to format Rust code
+    // For now, just return the original content
+    content.to_string()
+}
+
+// Format JavaScript/TypeScript code with regex
+fn format_js_ts_code_regex(content: &str) -> String {
+    // In a real implementation, this would use regex to format JavaScript/TypeScript code
+    // For now, just return the original content
+    content.to_string()
+}
+
+// Indentation for JSON that has none of its own, e.g. minified documents
+const DEFAULT_JSON_INDENT: &str = "  ";
+
+// Pretty-print JSON with one value per line. Only the whitespace between tokens changes, so
+// numbers, string escapes and repeated keys are kept exactly as written. An empty `indent_unit`
+// uses the document's own indentation
+pub fn format_json(content: &str, indent_unit: &str) -> std::result::Result<String, FormatDiagnostic> {
+    // The document is only checked here, nothing is kept from the parse
+    serde_json::from_str::<serde::de::IgnoredAny>(content).map_err(|e| FormatDiagnostic {
+        message: format!("Invalid JSON: {}", e),
+        line: e.line(),
+        column: e.column(),
+    })?;
+    
+    let unit = if !indent_unit.is_empty() {
+        indent_unit.to_string()
+    } else if content.lines().any(|line| line.starts_with([' ', '\t'])) {
+        detect_indent_unit(content)
+    } else {
+        DEFAULT_JSON_INDENT.to_string()
+    };
+    
+    let is_space = |byte: u8| matches!(byte, b' ' | b'\t' | b'\n' | b'\r');
+    let new_line = |formatted: &mut String, depth: usize| {
+        formatted.push('\n');
+        formatted.push_str(&unit.repeat(depth));
+    };
+    
+    let bytes = content.as_bytes();
+    let mut formatted = String::with_capacity(content.len());
+    let mut depth = 0usize;
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        match bytes[i] {
+            byte if is_space(byte) => i += 1,
+            b'"' => {
+                let start = i;
+                i += 1;
+                while bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i += 1;
+                formatted.push_str(&content[start..i]);
+            }
+            open @ (b'{' | b'[') => {
+                formatted.push(open as char);
+                i += 1;
+                
+                // Empty objects and arrays stay on one line
+                let next = bytes[i..].iter().position(|&byte| !is_space(byte)).map_or(bytes.len(), |offset| i + offset);
+                if matches!(bytes.get(next), Some(b'}') | Some(b']')) {
+                    formatted.push(bytes[next] as char);
+                    i = next + 1;
+                } else {
+                    depth += 1;
+                    new_line(&mut formatted, depth);
+                }
+            }
+            close @ (b'}' | b']') => {
+                depth -= 1;
+                new_line(&mut formatted, depth);
+                formatted.push(close as char);
+                i += 1;
+            }
+            b',' => {
+                formatted.push(',');
+                new_line(&mut formatted, depth);
+                i += 1;
+            }
+            b':' => {
+                formatted.push_str(": ");
+                i += 1;
+            }
+            _ => {
+                // Numbers, true, false and null run up to the next space or punctuation
+                let start = i;
+                while i < bytes.len() && !is_space(bytes[i]) && !matches!(bytes[i], b',' | b':' | b'{' | b'}' | b'[' | b']' | b'"') {
+                    i += 1;
+                }
+                formatted.push_str(&content[start..i]);
+            }
+        }
+    }
+    
+    if content.ends_with('\n') {
+        formatted.push('\n');
+    }
+    
+    Ok(formatted)
+}
+
+// Tidy TOML layout: no indentation outside multi-line arrays and inline tables, single spaces
+// around `=`, a blank line before each table and no runs of blank lines. Values and comments
+// are kept as written, and the result is only used if it parses to the same document
+pub fn format_toml(content: &str, indent_unit: &str) -> std::result::Result<String, FormatDiagnostic> {
+    let document = content.parse::<toml::Table>().map_err(|e| {
+        let offset = e.span().map_or(0, |span| span.start);
+        diagnostic_at(content, offset, format!("Invalid TOML: {}", e.message()))
+    })?;
+    
+    let unit = if indent_unit.is_empty() { detect_indent_unit(content) } else { indent_unit.to_string() };
+    let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    
+    let mut lines: Vec<String> = Vec::new();
+    let mut depth = 0usize;
+    let mut open_string = None;
+    
+    for line in content.lines() {
+        // Lines inside a multi-line string are part of its value
+        if open_string.is_some() {
+            lines.push(line.to_string());
+            open_string = scan_toml_line(line, open_string, &mut depth);
+            continue;
+        }
+        
+        let trimmed = line.trim();
+        
+        if trimmed.is_empty() {
+            if lines.last().map_or(false, |last| !last.is_empty()) {
+                lines.push(String::new());
+            }
+            continue;
+        }
+        
+        if depth == 0 && trimmed.starts_with('[') {
+            // Comments directly above a table header stay attached to it
+            let mut at = lines.len();
+            while at > 0 && lines[at - 1].starts_with('#') {
+                at -= 1;
+            }
+            if at > 0 && !lines[at - 1].is_empty() {
+                lines.insert(at, String::new());
+            }
+        }
+        
+        let closers = trimmed.chars().take_while(|c| matches!(c, ']' | '}')).count();
+        let text = if depth == 0 { normalize_toml_line(trimmed) } else { trimmed.to_string() };
+        lines.push(format!("{}{}", unit.repeat(depth.saturating_sub(closers)), text));
+        
+        open_string = scan_toml_line(trimmed, None, &mut depth);
+    }
+    
+    while lines.last().map_or(false, |last| last.is_empty()) {
+        lines.pop();
+    }
+    
+    let mut formatted = lines.join(line_ending);
+    if content.ends_with('\n') {
+        formatted.push_str(line_ending);
+    }
+    
+    match formatted.parse::<toml::Table>() {
+        Ok(reformatted) if reformatted == document => Ok(formatted),
+        _ => Err(FormatDiagnostic {
+            message: "Formatting would change the meaning of the document".to_string(),
+            line: 1,
+            column: 1,
+        }),
+    }
+}
+
+// Tidy a top-level TOML line: `[ table ]` headers lose their inner padding and `key=value`
+// pairs get single spaces around `=`
+fn normalize_toml_line(line: &str) -> String {
+    if line.starts_with('[') {
+        let (open, close) = if line.starts_with("[[") { ("[[", "]]") } else { ("[", "]") };
+        
+        return match find_unquoted_toml(line, b']') {
+            Some(end) if line[end..].starts_with(close) => {
+                let rest = line[end + close.len()..].trim_start();
+                let header = format!("{}{}{}", open, line[open.len()..end].trim(), close);
+                if rest.is_empty() { header } else { format!("{} {}", header, rest) }
+            }
+            _ => line.to_string(),
+        };
+    }
+    
+    match find_unquoted_toml(line, b'=') {
+        Some(equals) => format!("{} = {}", line[..equals].trim_end(), line[equals + 1..].trim_start()),
+        None => line.to_string(),
+    }
+}
+
+// Byte index of the first `target` outside strings and before any comment
+fn find_unquoted_toml(line: &str, target: u8) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        match bytes[i] {
+            byte if byte == target => return Some(i),
+            b'#' => return None,
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if quote == b'"' && bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    
+    None
+}
+
+// Follow brackets and strings through one TOML line, updating the bracket depth. Returns the
+// delimiter of a multi-line string that is still open at the end of the line
+fn scan_toml_line(line: &str, mut open_string: Option<&'static str>, depth: &mut usize) -> Option<&'static str> {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        if let Some(delimiter) = open_string {
+            if bytes[i..].starts_with(delimiter.as_bytes()) {
+                // Up to two quotes directly before the delimiter belong to the string
+                let quote = bytes[i];
+                let run = bytes[i..].iter().take(5).take_while(|&&byte| byte == quote).count();
+                i += run;
+                open_string = None;
+                continue;
+            }
+            
+            if delimiter == "\"\"\"" && bytes[i] == b'\\' {
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+        
+        match bytes[i] {
+            b'#' => break,
+            quote @ (b'"' | b'\'') => {
+                let delimiter = if quote == b'"' { "\"\"\"" } else { "'''" };
+                if bytes[i..].starts_with(delimiter.as_bytes()) {
+                    open_string = Some(delimiter);
+                    i += 3;
+                    continue;
+                }
+                
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if quote == b'"' && bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'[' | b'{' => *depth += 1,
+            b']' | b'}' => *depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        i += 1;
+    }
+    
+    open_string
+}
+
+// A diagnostic pointing at a byte offset in the document
+fn diagnostic_at(content: &str, offset: usize, message: String) -> FormatDiagnostic {
+    let offset = floor_char_boundary(content, offset.min(content.len()));
+    let line_start = content[..offset].rfind('\n').map_or(0, |index| index + 1);
+    
+    FormatDiagnostic {
+        message,
+        line: get_line_number(content, offset) + 1,
+        column: content[line_start..offset].chars().count() + 1,
+    }
+}
+
+// HTML elements that never have an end tag
+const HTML_VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr",
+];
+
+// HTML elements laid out on the same line as the text around them
+const HTML_INLINE_ELEMENTS: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "br", "button", "cite", "code", "data", "dfn", "em", "i", "img", "input", "kbd",
+    "label", "mark", "q", "s", "samp", "select", "small", "span", "strong", "sub", "sup", "time", "u", "var", "wbr",
+];
+
+// HTML elements whose content is whitespace-sensitive or not markup, and is kept exactly as
+// written. XML has no such elements, so an Android `<style>` resource is formatted like any other
+const VERBATIM_ELEMENTS: &[&str] = &["pre", "script", "style", "textarea"];
+
+// Markup as parsed for formatting
+enum MarkupNode {
+    Element {
+        name: String,
+        open_tag: String,
+        close_tag: Option<String>, // None for void and self-closing elements
+        children: Vec<MarkupNode>,
+    },
+    Text(String),
+    // Comments, doctypes, processing instructions, CDATA and verbatim elements, written out as they are
+    Raw(String),
+}
+
+// Re-indent HTML or XML with one level per nested element. Whitespace between elements is
+// collapsed, keeping single blank lines, while text and inline HTML elements stay together on
+// one line. Tags spread over several lines get one attribute per line. An empty `indent_unit`
+// uses the document's own indentation
+pub fn format_markup(content: &str, html: bool, indent_unit: &str) -> std::result::Result<String, FormatDiagnostic> {
+    let nodes = parse_markup(content, html)?;
+    
+    let unit = if indent_unit.is_empty() { detect_indent_unit(content) } else { indent_unit.to_string() };
+    let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    
+    let mut lines = Vec::new();
+    render_markup_children(&nodes, 0, &unit, html, &mut lines);
+    
+    let mut formatted = lines.join(line_ending);
+    if content.ends_with('\n') {
+        formatted.push_str(line_ending);
+    }
+    
+    Ok(formatted)
+}
+
+// Parse markup into a tree, failing on unterminated constructs and unbalanced tags
+fn parse_markup(content: &str, html: bool) -> std::result::Result<Vec<MarkupNode>, FormatDiagnostic> {
+    let bytes = content.as_bytes();
+    let same_name = |a: &str, b: &str| if html { a.eq_ignore_ascii_case(b) } else { a == b };
+    let unterminated = |offset: usize, what: &str| diagnostic_at(content, offset, format!("Unterminated {}", what));
+    
+    // Open elements as (name, open tag, offset, children), below a root holding the top-level nodes
+    let mut open: Vec<(String, String, usize, Vec<MarkupNode>)> = vec![(String::new(), String::new(), 0, Vec::new())];
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        let rest = &content[i..];
+        let next = bytes.get(i + 1).copied().unwrap_or(0);
+        
+        let node = if rest.starts_with("<!--") {
+            let end = rest.find("-->").ok_or_else(|| unterminated(i, "comment"))? + 3;
+            MarkupNode::Raw(rest[..end].to_string())
+        } else if rest.starts_with("<![CDATA[") {
+            let end = rest.find("]]>").ok_or_else(|| unterminated(i, "CDATA section"))? + 3;
+            MarkupNode::Raw(rest[..end].to_string())
+        } else if rest.starts_with("<?") {
+            let end = rest.find("?>").ok_or_else(|| unterminated(i, "processing instruction"))? + 2;
+            MarkupNode::Raw(rest[..end].to_string())
+        } else if rest.starts_with("<!") {
+            let end = markup_tag_end(rest).ok_or_else(|| unterminated(i, "declaration"))?;
+            MarkupNode::Raw(rest[..end].to_string())
+        } else if rest.starts_with("</") {
+            let end = markup_tag_end(rest).ok_or_else(|| unterminated(i, "tag"))?;
+            let name = markup_tag_name(&rest[2..]);
+            
+            let (open_name, open_tag, _, children) = match open.pop() {
+                Some(element) if open.len() > 0 && same_name(&element.0, name) => element,
+                _ => return Err(diagnostic_at(content, i, format!("Unexpected closing tag </{}>", name))),
+            };
+            
+            i += end;
+            open.last_mut().unwrap().3.push(MarkupNode::Element {
+                name: open_name,
+                open_tag,
+                close_tag: Some(rest[..end].to_string()),
+                children,
+            });
+            continue;
+        } else if rest.starts_with('<') && (next.is_ascii_alphabetic() || (!html && (next == b'_' || next == b':'))) {
+            let end = markup_tag_end(rest).ok_or_else(|| unterminated(i, "tag"))?;
+            let tag = &rest[..end];
+            let name = markup_tag_name(&rest[1..]);
+            let lower_name = name.to_ascii_lowercase();
+            
+            if tag.ends_with("/>") || (html && HTML_VOID_ELEMENTS.contains(&lower_name.as_str())) {
+                MarkupNode::Element {
+                    name: name.to_string(),
+                    open_tag: tag.to_string(),
+                    close_tag: None,
+                    children: Vec::new(),
+                }
+            } else if html && VERBATIM_ELEMENTS.contains(&lower_name.as_str()) {
+                // Everything up to the matching end tag is content, even if it looks like markup
+                let close = format!("</{}", lower_name);
+                let content_end = rest[end..]
+                    .to_ascii_lowercase()
+                    .find(&close)
+                    .map(|index| end + index)
+                    .ok_or_else(|| unterminated(i, &format!("<{}> element", name)))?;
+                let close_end = markup_tag_end(&rest[content_end..])
+                    .map(|index| content_end + index)
+                    .ok_or_else(|| unterminated(i + content_end, "tag"))?;
+                MarkupNode::Raw(rest[..close_end].to_string())
+            } else {
+                open.push((name.to_string(), tag.to_string(), i, Vec::new()));
+                i += end;
+                continue;
+            }
+        } else {
+            // Text runs to the next `<` that starts markup; a lone `<`, as in `a < b`, is text
+            let mut end = rest.chars().next().map_or(1, char::len_utf8);
+            while let Some(index) = rest[end..].find('<') {
+                let after = rest.as_bytes().get(end + index + 1).copied().unwrap_or(0);
+                if after.is_ascii_alphabetic() || matches!(after, b'/' | b'!' | b'?' | b'_' | b':') {
+                    end += index;
+                    break;
+                }
+                end += index + 1;
+            }
+            if !rest[end..].starts_with('<') {
+                end = rest.len();
+            }
+            MarkupNode::Text(rest[..end].to_string())
+        };
+        
+        i += match &node {
+            MarkupNode::Element { open_tag, .. } => open_tag.len(),
+            MarkupNode::Text(text) | MarkupNode::Raw(text) => text.len(),
+        };
+        open.last_mut().unwrap().3.push(node);
+    }
+    
+    if open.len() > 1 {
+        let (name, _, offset, _) = open.pop().unwrap();
+        return Err(diagnostic_at(content, offset, format!("Element <{}> is never closed", name)));
+    }
+    
+    Ok(open.pop().map(|root| root.3).unwrap_or_default())
+}
+
+// Length of a tag up to and including its `>`, skipping quoted attribute values
+fn markup_tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    
+    for (index, c) in tag.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return Some(index + 1),
+            _ => {}
+        }
+    }
+    
+    None
+}
+
+fn markup_tag_name(tag: &str) -> &str {
+    let end = tag.find(|c: char| c.is_whitespace() || c == '/' || c == '>').unwrap_or(tag.len());
+    &tag[..end]
+}
+
+// Whether a node can sit on a line together with the text around it
+fn is_inline_markup(node: &MarkupNode, html: bool) -> bool {
+    match node {
+        MarkupNode::Text(_) => true,
+        MarkupNode::Raw(_) => false,
+        MarkupNode::Element { name, open_tag, children, .. } => {
+            html
+                && !open_tag.contains('\n')
+                && HTML_INLINE_ELEMENTS.contains(&name.to_ascii_lowercase().as_str())
+                && children.iter().all(|child| is_inline_markup(child, html))
+        }
+    }
+}
+
+// An inline node on one line, with runs of whitespace collapsed to single spaces
+fn inline_markup(node: &MarkupNode) -> String {
+    match node {
+        MarkupNode::Text(text) => {
+            let mut collapsed = String::with_capacity(text.len());
+            for (index, word) in text.split_whitespace().enumerate() {
+                if index > 0 {
+                    collapsed.push(' ');
+                }
+                collapsed.push_str(word);
+            }
+            
+            if collapsed.is_empty() {
+                return if text.is_empty() { String::new() } else { " ".to_string() };
+            }
+            if text.starts_with(char::is_whitespace) {
+                collapsed.insert(0, ' ');
+            }
+            if text.ends_with(char::is_whitespace) {
+                collapsed.push(' ');
+            }
+            collapsed
+        }
+        MarkupNode::Raw(raw) => raw.clone(),
+        MarkupNode::Element { open_tag, close_tag, children, .. } => {
+            let inner: String = children.iter().map(inline_markup).collect();
+            format!("{}{}{}", normalize_open_tag(open_tag, None), inner, close_tag.as_deref().unwrap_or(""))
+        }
+    }
+}
+
+// An open tag with single spaces between attributes, or with `attribute_indent`, one attribute
+// per line below the element name. Quoted values are kept as written
+fn normalize_open_tag(tag: &str, attribute_indent: Option<&str>) -> String {
+    let self_closing = tag.ends_with("/>");
+    let inner = tag[1..tag.len() - if self_closing { 2 } else { 1 }].trim_end();
+    let name = markup_tag_name(inner);
+    
+    // Split attributes on whitespace outside quotes, joining `name = "value"` back together
+    let mut attributes: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut chars = inner[name.len()..].trim().chars().peekable();
+    
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(open) => {
+                current.push(c);
+                if c == open {
+                    quote = None;
+                }
+            }
+            None if c == '"' || c == '\'' => {
+                current.push(c);
+                quote = Some(c);
+            }
+            None if c.is_whitespace() => {
+                while chars.peek().map_or(false, |next| next.is_whitespace()) {
+                    chars.next();
+                }
+                if current.ends_with('=') || chars.peek() == Some(&'=') {
+                    continue;
+                }
+                if !current.is_empty() {
+                    attributes.push(std::mem::take(&mut current));
+                }
+            }
+            None => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        attributes.push(current);
+    }
+    
+    let ending = match (self_closing, tag[..tag.len() - 2].ends_with(char::is_whitespace)) {
+        (true, true) => " />",
+        (true, false) => "/>",
+        _ => ">",
+    };
+    
+    match attribute_indent {
+        Some(indent) if !attributes.is_empty() => {
+            let mut lines = format!("<{}", name);
+            for attribute in &attributes {
+                lines.push('\n');
+                lines.push_str(indent);
+                lines.push_str(attribute);
+            }
+            let ending = if self_closing { " />" } else { ">" };
+            format!("{}{}", lines, ending)
+        }
+        _ if attributes.is_empty() => format!("<{}{}", name, ending),
+        _ => format!("<{} {}{}", name, attributes.join(" "), ending),
+    }
+}
+
+fn render_markup_children(nodes: &[MarkupNode], depth: usize, unit: &str, html: bool, lines: &mut Vec<String>) {
+    let indent = unit.repeat(depth);
+    let first_line = lines.len();
+    let mut run = String::new();
+    let mut blank_line = false;
+    
+    // Start a line or block, after a blank line if the source had one there
+    let start_item = |lines: &mut Vec<String>, blank_line: &mut bool| {
+        if std::mem::take(blank_line) && lines.len() > first_line {
+            lines.push(String::new());
+        }
+    };
+    
+    let flush_run = |run: &mut String, lines: &mut Vec<String>, blank_line: &mut bool| {
+        let text = run.trim();
+        if !text.is_empty() {
+            start_item(lines, blank_line);
+            lines.push(format!("{}{}", indent, text));
+        }
+        run.clear();
+    };
+    
+    for node in nodes {
+        if let MarkupNode::Text(text) = node {
+            if text.trim().is_empty() {
+                if text.matches('\n').count() >= 2 {
+                    flush_run(&mut run, lines, &mut blank_line);
+                    blank_line = true;
+                } else if !run.is_empty() {
+                    run.push(' ');
+                }
+                continue;
+            }
+        }
+        
+        if is_inline_markup(node, html) {
+            run.push_str(&inline_markup(node));
+            continue;
+        }
+        
+        flush_run(&mut run, lines, &mut blank_line);
+        start_item(lines, &mut blank_line);
+        render_markup_block(node, depth, unit, html, lines);
+    }
+    
+    flush_run(&mut run, lines, &mut blank_line);
+}
+
+fn render_markup_block(node: &MarkupNode, depth: usize, unit: &str, html: bool, lines: &mut Vec<String>) {
+    let indent = unit.repeat(depth);
+    
+    match node {
+        MarkupNode::Element { open_tag, close_tag, children, .. } => {
+            // Tags the author spread over several lines keep one attribute per line
+            let attribute_indent = format!("{}{}", indent, unit);
+            let open_tag = normalize_open_tag(open_tag, open_tag.contains('\n').then_some(attribute_indent.as_str()));
+            let close_tag = close_tag.as_deref().unwrap_or("");
+            
+            let has_content = children.iter().any(|child| !matches!(child, MarkupNode::Text(text) if text.trim().is_empty()));
+            let all_inline = children.iter().all(|child| is_inline_markup(child, html));
+            
+            if !has_content || all_inline {
+                let inner: String = children.iter().map(inline_markup).collect();
+                lines.push(format!("{}{}{}{}", indent, open_tag, inner.trim(), close_tag));
+            } else {
+                lines.push(format!("{}{}", indent, open_tag));
+                render_markup_children(children, depth + 1, unit, html, lines);
+                if !close_tag.is_empty() {
+                    lines.push(format!("{}{}", indent, close_tag));
+                }
+            }
+        }
+        MarkupNode::Raw(raw) | MarkupNode::Text(raw) => {
+            // Only the first line is indented; the rest is content
+            lines.push(format!("{}{}", indent, raw.trim()));
+        }
+    }
+}
+
+// CSS as parsed for formatting
+enum CssNode {
+    Comment(String),
+    // A declaration, or an at-rule without a block such as @import, without its `;`
+    Declaration(String),
+    Rule { prelude: String, children: Vec<CssNode> },
+}
+
+// Lay out CSS with one declaration per line, `property: value;` spacing, indented rule bodies,
+// one selector per line and a blank line between rules. Comments are kept. With
+// `sort_properties`, declarations are ordered by property name. An empty `indent_unit` uses the
+// document's own indentation
+pub fn format_css(content: &str, indent_unit: &str, sort_properties: bool) -> std::result::Result<String, FormatDiagnostic> {
+    let mut index = 0;
+    let nodes = parse_css_block(content, &mut index, None)?;
+    
+    let unit = if indent_unit.is_empty() { detect_indent_unit(content) } else { indent_unit.to_string() };
+    let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    
+    let mut lines = Vec::new();
+    render_css(&nodes, 0, &unit, sort_properties, &mut lines);
+    
+    let mut formatted = lines.join(line_ending);
+    if content.ends_with('\n') {
+        formatted.push_str(line_ending);
+    }
+    
+    Ok(formatted)
+}
+
+// Parse CSS up to the `}` closing the block opened at `open`, or to the end of the document
+fn parse_css_block(content: &str, index: &mut usize, open: Option<usize>) -> std::result::Result<Vec<CssNode>, FormatDiagnostic> {
+    let bytes = content.as_bytes();
+    let mut nodes = Vec::new();
+    let mut text = String::new();
+    let mut parens = 0;
+    
+    while *index < bytes.len() {
+        let start = *index;
+        
+        if content[start..].starts_with("/*") {
+            let end = content[start + 2..]
+                .find("*/")
+                .map(|end| start + 2 + end + 2)
+                .ok_or_else(|| diagnostic_at(content, start, "Unterminated comment".to_string()))?;
+            
+            // Comments between statements get a line of their own; others stay where they are
+            if text.trim().is_empty() {
+                nodes.push(CssNode::Comment(content[start..end].to_string()));
+                text.clear();
+            } else {
+                text.push_str(&content[start..end]);
+            }
+            *index = end;
+            continue;
+        }
+        
+        match bytes[start] {
+            quote @ (b'"' | b'\'') => {
+                let mut end = start + 1;
+                while end < bytes.len() && bytes[end] != quote && bytes[end] != b'\n' {
+                    end += if bytes[end] == b'\\' { 2 } else { 1 };
+                }
+                if end >= bytes.len() || bytes[end] != quote {
+                    return Err(diagnostic_at(content, start, "Unterminated string".to_string()));
+                }
+                text.push_str(&content[start..=end]);
+                *index = end + 1;
+                continue;
+            }
+            b';' if parens == 0 => {
+                push_css_statement(&mut nodes, &text);
+                text.clear();
+                *index += 1;
+                continue;
+            }
+            b'{' if parens == 0 => {
+                *index += 1;
+                let children = parse_css_block(content, index, Some(start))?;
+                nodes.push(CssNode::Rule { prelude: collapse_css_whitespace(&text), children });
+                text.clear();
+                continue;
+            }
+            b'}' if parens == 0 => {
+                if open.is_none() {
+                    return Err(diagnostic_at(content, start, "Unexpected }".to_string()));
+                }
+                push_css_statement(&mut nodes, &text);
+                *index += 1;
+                return Ok(nodes);
+            }
+            b'(' => parens += 1,
+            b')' => parens = (parens - 1).max(0),
+            _ => {}
+        }
+        
+        let c = content[start..].chars().next().unwrap();
+        text.push(c);
+        *index += c.len_utf8();
+    }
+    
+    if let Some(open) = open {
+        return Err(diagnostic_at(content, open, "Unclosed {".to_string()));
+    }
+    
+    push_css_statement(&mut nodes, &text);
+    Ok(nodes)
+}
+
+// Add a declaration as `property: value`, or an at-rule statement as written
+fn push_css_statement(nodes: &mut Vec<CssNode>, text: &str) {
+    let text = collapse_css_whitespace(text);
+    if text.is_empty() {
+        return;
+    }
+    
+    let colon = if text.starts_with('@') { None } else { find_css_top_level(&text, b':') };
+    let statement = match colon {
+        Some(colon) => format!("{}: {}", text[..colon].trim_end(), text[colon + 1..].trim_start()),
+        None => text,
+    };
+    
+    nodes.push(CssNode::Declaration(statement));
+}
+
+// Byte indices of `target` outside strings, parentheses and brackets
+fn find_css_top_level_all(text: &str, target: u8) -> Vec<usize> {
+    let bytes = text.as_bytes();
+    let mut found = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        let byte = bytes[i];
+        match quote {
+            Some(open) => {
+                if byte == b'\\' {
+                    i += 1;
+                } else if byte == open {
+                    quote = None;
+                }
+            }
+            None => match byte {
+                b'"' | b'\'' => quote = Some(byte),
+                b'(' | b'[' => depth += 1,
+                b')' | b']' => depth -= 1,
+                _ if byte == target && depth == 0 => found.push(i),
+                _ => {}
+            },
+        }
+        i += 1;
+    }
+    
+    found
+}
+
+fn find_css_top_level(text: &str, target: u8) -> Option<usize> {
+    find_css_top_level_all(text, target).first().copied()
+}
+
+// Trim and collapse runs of whitespace outside strings to single spaces
+fn collapse_css_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut quote = None;
+    let mut escaped = false;
+    let mut space = false;
+    
+    for c in text.chars() {
+        if let Some(open) = quote {
+            collapsed.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == open {
+                quote = None;
+            }
+            continue;
+        }
+        
+        if c.is_whitespace() {
+            space = !collapsed.is_empty();
+            continue;
+        }
+        
+        if space {
+            collapsed.push(' ');
+            space = false;
+        }
+        if c == '"' || c == '\'' {
+            quote = Some(c);
+        }
+        collapsed.push(c);
+    }
+    
+    collapsed
+}
+
+fn render_css(nodes: &[CssNode], depth: usize, unit: &str, sort_properties: bool, lines: &mut Vec<String>) {
+    let indent = unit.repeat(depth);
+    let ordered = if sort_properties { sorted_css_nodes(nodes) } else { nodes.iter().collect() };
+    let mut previous: Option<&CssNode> = None;
+    
+    for node in ordered {
+        // Rules are separated from whatever comes before them, comments included
+        let blank_line = match (previous, node) {
+            (Some(CssNode::Rule { .. }), _) => true,
+            (Some(CssNode::Declaration(_)), CssNode::Rule { .. }) => true,
+            _ => false,
+        };
+        if blank_line {
+            lines.push(String::new());
+        }
+        
+        match node {
+            CssNode::Comment(comment) => lines.push(format!("{}{}", indent, comment)),
+            CssNode::Declaration(declaration) => lines.push(format!("{}{};", indent, declaration)),
+            CssNode::Rule { prelude, children } => {
+                let prelude = if prelude.starts_with('@') {
+                    prelude.clone()
+                } else {
+                    let mut selectors = Vec::new();
+                    let mut from = 0;
+                    for comma in find_css_top_level_all(prelude, b',') {
+                        selectors.push(prelude[from..comma].trim());
+                        from = comma + 1;
+                    }
+                    selectors.push(prelude[from..].trim());
+                    selectors.join(&format!(",\n{}", indent))
+                };
+                
+                if children.is_empty() {
+                    lines.push(format!("{}{} {{}}", indent, prelude));
+                } else {
+                    lines.push(format!("{}{} {{", indent, prelude));
+                    render_css(children, depth + 1, unit, sort_properties, lines);
+                    lines.push(format!("{}}}", indent));
+                }
+            }
+        }
+        
+        previous = Some(node);
+    }
+}
+
+// Declarations ordered by property name, each keeping the comments above it. Nested rules and
+// at-rule statements stay in place and only the declarations between them are sorted
+fn sorted_css_nodes(nodes: &[CssNode]) -> Vec<&CssNode> {
+    let mut ordered = Vec::with_capacity(nodes.len());
+    let mut declarations: Vec<(String, Vec<&CssNode>)> = Vec::new();
+    let mut comments: Vec<&CssNode> = Vec::new();
+    
+    for node in nodes {
+        match node {
+            CssNode::Comment(_) => comments.push(node),
+            CssNode::Declaration(declaration) if !declaration.starts_with('@') => {
+                let property = declaration.split(':').next().unwrap_or("").trim().to_ascii_lowercase();
+                comments.push(node);
+                declarations.push((property, std::mem::take(&mut comments)));
+            }
+            _ => {
+                append_sorted_declarations(&mut ordered, &mut declarations);
+                ordered.append(&mut comments);
+                ordered.push(node);
+            }
+        }
+    }
+    
+    append_sorted_declarations(&mut ordered, &mut declarations);
+    ordered.append(&mut comments);
+    ordered
+}
+
+fn append_sorted_declarations<'a>(ordered: &mut Vec<&'a CssNode>, declarations: &mut Vec<(String, Vec<&'a CssNode>)>) {
+    // Stable, so repeated properties keep their relative order
+    declarations.sort_by(|a, b| a.0.cmp(&b.0));
+    for (_, group) in declarations.drain(..) {
+        ordered.extend(group);
+    }
+}
+
+// Format Kotlin with ktlint or Java with google-java-format. `tool_path` is an executable or a
+// jar, which runs on the JDK managed under `sdk_root` when there is one; empty looks the tool up
+// on PATH
+pub fn format_with_tool(content: &str, language_id: &str, tool_path: &str, sdk_root: &str) -> Result<String> {
+    let (default_tool, args): (&str, &[&str]) = match language_id {
+        "kotlin" => ("ktlint", &["--stdin", "--format", "--log-level=none"]),
+        "java" => ("google-java-format", &["-"]),
+        _ => return Err(anyhow!("No external formatter for {}", language_id)),
+    };
+    let tool = if tool_path.is_empty() { default_tool } else { tool_path };
+    
+    let mut command = if tool.ends_with(".jar") {
+        let mut command = Command::new(formatter_java(sdk_root));
+        if language_id == "java" {
+            command.args(GOOGLE_JAVA_FORMAT_JVM_ARGS);
+        }
+        command.arg("-jar").arg(tool);
+        command
+    } else {
+        Command::new(tool)
+    };
+    command.args(args);
+    
+    let output = pipe_through_command(&mut command, content).ok_or_else(|| anyhow!("Could not run {}", tool))?;
+    let formatted = String::from_utf8(output.stdout).map_err(|_| anyhow!("{} returned invalid UTF-8", tool))?;
+    
+    // ktlint fails when violations it cannot fix remain, but still prints everything it did fix
+    let fixed_anyway = language_id == "kotlin" && !formatted.trim().is_empty();
+    if output.status.success() || fixed_anyway {
+        return Ok(formatted);
+    }
+    
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = stderr.lines().find(|line| !line.trim().is_empty()).unwrap_or("no output");
+    Err(anyhow!("{} failed: {}", tool, reason.trim()))
+}
+
+// Format with a specific formatter executable or jar instead of the configured one. When the
+// tool can't be run the document only has its whitespace normalized, and the reason is returned
+pub fn format_code_with_tool(content: &str, language_id: &str, tool_path: &str) -> (String, Option<String>) {
+    let options = editor_state().format_options.clone();
+    
+    match format_with_tool(content, language_id, tool_path, &options.sdk_root) {
+        Ok(formatted) => (normalize_formatted(&formatted, language_id, &options), None),
+        Err(e) => (normalize_formatted(content, language_id, &options), Some(e.to_string())),
+    }
+}
+
+// The managed JDK's java when the SDK has one, otherwise java from PATH
+fn formatter_java(sdk_root: &str) -> PathBuf {
+    if !sdk_root.is_empty() {
+        let sdk_manager = SdkManager::new(Path::new(sdk_root));
+        if sdk_manager.is_jdk_installed() {
+            return sdk_manager.get_java_path();
+        }
+    }
+    
+    PathBuf::from("java")
+}
+
+// Format Python with black, or autopep8, when one is on PATH. Otherwise indentation is
+// normalized to a single unit, trailing whitespace is removed and top-level definitions are
+// separated by one blank line. An empty `indent_unit` uses the unit most of the file uses
+pub fn format_python(content: &str, indent_unit: &str) -> std::result::Result<String, FormatDiagnostic> {
+    match format_python_with_tool(content) {
+        Some(formatted) => Ok(formatted),
+        None => reindent_python(content, indent_unit),
+    }
+}
+
+// Format Python with black or autopep8, or None when neither is on PATH or both fail
+fn format_python_with_tool(content: &str) -> Option<String> {
+    pipe_through_formatter("black", &["--quiet", "-"], content)
+        .or_else(|| pipe_through_formatter("autopep8", &["-"], content))
+}
+
+// Python lexical state carried from one line to the next
+#[derive(Default)]
+struct PythonLineState {
+    // Delimiter of an open triple-quoted string
+    open_string: Option<&'static str>,
+    // Open brackets
+    depth: i32,
+    // The line ended with a backslash
+    continued: bool,
+}
+
+impl PythonLineState {
+    fn in_statement(&self) -> bool {
+        self.open_string.is_some() || self.depth > 0 || self.continued
+    }
+    
+    fn scan(&mut self, line: &str) {
+        let bytes = line.as_bytes();
+        let mut i = 0;
+        self.continued = false;
+        
+        while i < bytes.len() {
+            if let Some(delimiter) = self.open_string {
+                if bytes[i] == b'\\' {
+                    i += 2;
+                    continue;
+                }
+                if bytes[i..].starts_with(delimiter.as_bytes()) {
+                    self.open_string = None;
+                    i += 3;
+                    continue;
+                }
+                i += 1;
+                continue;
+            }
+            
+            match bytes[i] {
+                b'#' => break,
+                quote @ (b'"' | b'\'') => {
+                    let triple = if quote == b'"' { "\"\"\"" } else { "'''" };
+                    if bytes[i..].starts_with(triple.as_bytes()) {
+                        self.open_string = Some(triple);
+                        i += 3;
+                        continue;
+                    }
+                    
+                    i += 1;
+                    while i < bytes.len() && bytes[i] != quote {
+                        i += if bytes[i] == b'\\' { 2 } else { 1 };
+                    }
+                }
+                b'(' | b'[' | b'{' => self.depth += 1,
+                b')' | b']' | b'}' => self.depth -= 1,
+                b'\\' if i == bytes.len() - 1 => self.continued = true,
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+}
+
+// Re-indent Python by block level. Continuation lines move with the line that starts their
+// statement and triple-quoted strings are left alone. Anything Python itself would reject,
+// such as a dedent to no enclosing level or tabs that only line up at one tab size, leaves
+// the document unchanged
+fn reindent_python(content: &str, indent_unit: &str) -> std::result::Result<String, FormatDiagnostic> {
+    let unit = if indent_unit.is_empty() { dominant_python_indent(content) } else { indent_unit.to_string() };
+    let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let ambiguous = |line: usize, message: &str| FormatDiagnostic {
+        message: message.to_string(),
+        line: line + 1,
+        column: 1,
+    };
+    
+    // Widths of the enclosing blocks with tabs counted as 8 columns and as 1
+    let mut blocks: Vec<(usize, usize)> = vec![(0, 0)];
+    let mut state = PythonLineState::default();
+    let mut lines: Vec<String> = Vec::new();
+    let mut statement_indent = (String::new(), String::new());
+    
+    for (number, line) in content.lines().enumerate() {
+        if state.in_statement() {
+            let was_in_string = state.open_string.is_some();
+            state.scan(line);
+            
+            if was_in_string {
+                lines.push(line.to_string());
+                continue;
+            }
+            
+            // Shift continuation lines by as much as the start of their statement moved
+            let line = if state.open_string.is_some() { line } else { line.trim_end() };
+            let shifted = match line.strip_prefix(statement_indent.0.as_str()) {
+                Some(rest) if !line.trim().is_empty() => format!("{}{}", statement_indent.1, rest),
+                _ => line.to_string(),
+            };
+            lines.push(shifted);
+            continue;
+        }
+        
+        // Runs of blank lines collapse to one
+        let text = line.trim_start();
+        if text.trim_end().is_empty() {
+            if lines.last().map_or(false, |last| !last.is_empty()) {
+                lines.push(String::new());
+            }
+            continue;
+        }
+        
+        let leading = &line[..line.len() - text.len()];
+        let width = (python_indent_width(leading, 8), python_indent_width(leading, 1));
+        
+        let level = if text.starts_with('#') {
+            // Comments take the level of the block their indentation falls in
+            blocks.iter().filter(|block| block.0 <= width.0).count().max(1) - 1
+                + usize::from(width.0 > blocks.last().unwrap().0)
+        } else {
+            let current = *blocks.last().unwrap();
+            if width.0 > current.0 {
+                if width.1 <= current.1 {
+                    return Err(ambiguous(number, "Inconsistent use of tabs and spaces in indentation"));
+                }
+                blocks.push(width);
+            } else {
+                while blocks.last().unwrap().0 > width.0 {
+                    blocks.pop();
+                }
+                if blocks.last().unwrap().0 != width.0 {
+                    return Err(ambiguous(number, "Unindent does not match any outer indentation level"));
+                }
+                if blocks.last().unwrap().1 != width.1 {
+                    return Err(ambiguous(number, "Inconsistent use of tabs and spaces in indentation"));
+                }
+            }
+            blocks.len() - 1
+        };
+        
+        let is_definition = ["def ", "async def ", "class ", "@"].iter().any(|keyword| text.starts_with(keyword));
+        if level == 0 && is_definition {
+            separate_python_definition(&mut lines);
+        }
+        
+        let indent = unit.repeat(level);
+        state.scan(line);
+        let text = if state.open_string.is_some() { text } else { text.trim_end() };
+        lines.push(format!("{}{}", indent, text));
+        
+        if !text.starts_with('#') {
+            statement_indent = (leading.to_string(), indent);
+        }
+    }
+    
+    if state.open_string.is_some() || state.depth != 0 {
+        return Err(ambiguous(content.lines().count().saturating_sub(1), "Unterminated string or bracket"));
+    }
+    
+    while lines.last().map_or(false, |last| last.is_empty()) {
+        lines.pop();
+    }
+    
+    let mut formatted = lines.join(line_ending);
+    if content.ends_with('\n') {
+        formatted.push_str(line_ending);
+    }
+    
+    Ok(formatted)
+}
+
+// Leave exactly one blank line above a top-level definition about to be added, and above the
+// comments directly before it. Decorated definitions stay attached to their decorators
+fn separate_python_definition(lines: &mut Vec<String>) {
+    while lines.last().map_or(false, |last| last.is_empty()) {
+        lines.pop();
+    }
+    
+    let mut at = lines.len();
+    while at > 0 && lines[at - 1].starts_with('#') {
+        at -= 1;
+    }
+    
+    if at > 0 && lines[at - 1].starts_with('@') {
+        return;
+    }
+    
+    let mut start = at;
+    while start > 0 && lines[start - 1].is_empty() {
+        start -= 1;
+    }
+    
+    let blank_lines = if start == 0 { 0 } else { 1 };
+    lines.splice(start..at, std::iter::repeat(String::new()).take(blank_lines));
+}
+
+// Width of leading whitespace with tabs advancing to the next multiple of `tab_size`
+fn python_indent_width(leading: &str, tab_size: usize) -> usize {
+    leading.chars().fold(0, |width, c| if c == '\t' { (width / tab_size + 1) * tab_size } else { width + 1 })
+}
+
+// The indentation most of a Python file uses: a tab when more lines are indented with tabs than
+// with spaces, otherwise the most common step between one line's indentation and the next
+fn dominant_python_indent(content: &str) -> String {
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    let mut steps: HashMap<usize, usize> = HashMap::new();
+    let mut previous = 0;
+    
+    for line in content.lines() {
+        let text = line.trim_start();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        
+        let leading = &line[..line.len() - text.len()];
+        if leading.starts_with('\t') {
+            tab_lines += 1;
+        } else if !leading.is_empty() {
+            space_lines += 1;
+        }
+        
+        if !leading.contains('\t') {
+            if leading.len() > previous && leading.len() - previous <= 8 {
+                *steps.entry(leading.len() - previous).or_insert(0) += 1;
+            }
+            previous = leading.len();
+        }
+    }
+    
+    if tab_lines > space_lines {
+        return "\t".to_string();
+    }
+    
+    let step = steps
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        .map_or(4, |(step, _)| step);
+    " ".repeat(step)
+}
+
+// Largest changed region (original lines x formatted lines) diffed exactly;
+// bigger regions are first split on lines that appear once on each side
+const MAX_DIFF_CELLS: usize = 1_000_000;
+
+// Format code and return only what changed, as edits against the original content,
+// so the editor can apply them without losing the caret or scroll position
+pub fn format_code_edits(content: &str, language_id: &str) -> Vec<TextEdit> {
+    let formatted = format_code(content, language_id);
+    diff_text_edits(content, &formatted)
+}
+
+// Line-based diff of two versions of a document, as non-overlapping edits to `original` in document order
+pub fn diff_text_edits(original: &str, updated: &str) -> Vec<TextEdit> {
+    let old_lines: Vec<&str> = original.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = updated.split_inclusive('\n').collect();
+    
+    // Byte offset of the start of every original line, plus the end of the document
+    let mut offsets = Vec::with_capacity(old_lines.len() + 1);
+    offsets.push(0);
+    for line in &old_lines {
+        offsets.push(offsets[offsets.len() - 1] + line.len());
+    }
+    
+    let mut matches = Vec::new();
+    collect_line_matches(&old_lines, &new_lines, 0, 0, &mut matches);
+    
+    // Every gap between consecutive matching lines becomes one edit
+    let mut edits = Vec::new();
+    let (mut old_index, mut new_index) = (0, 0);
+    
+    for (old_match, new_match) in matches.into_iter().chain(std::iter::once((old_lines.len(), new_lines.len()))) {
+        if old_match > old_index || new_match > new_index {
+            edits.push(TextEdit {
+                start_byte: offsets[old_index],
+                end_byte: offsets[old_match],
+                new_text: new_lines[new_index..new_match].concat(),
+            });
+        }
+        
+        old_index = old_match + 1;
+        new_index = new_match + 1;
+    }
+    
+    edits
+}
+
+// Zero-based (line, column) of a byte offset. Columns count characters, or UTF-16 code units
+// when `utf16` is set to match Java string indices. Offsets past the end or inside a character
+// are moved back to the nearest character boundary
+pub fn offset_to_position(content: &str, byte_offset: usize, utf16: bool) -> (usize, usize) {
+    let mut offset = byte_offset.min(content.len());
+    while !content.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    
+    let before = &content[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.bytes().filter(|&b| b == b'\n').count();
+    
+    let line_text = &before[line_start..];
+    let column = if utf16 { line_text.encode_utf16().count() } else { line_text.chars().count() };
+    
+    (line, column)
+}
+
+// Byte offset of a zero-based (line, column), the inverse of offset_to_position. Lines past the
+// end map to the end of the document, columns past the end of a line to the end of that line,
+// and a UTF-16 column inside a surrogate pair to the start of its character
+pub fn position_to_offset(content: &str, line: usize, column: usize, utf16: bool) -> usize {
+    let line_start = match line {
+        0 => 0,
+        _ => match content.match_indices('\n').nth(line - 1) {
+            Some((index, _)) => index + 1,
+            None => return content.len(),
+        },
+    };
+    
+    let line_text = content[line_start..].split('\n').next().unwrap_or("");
+    let mut width = 0;
+    
+    for (index, c) in line_text.char_indices() {
+        width += if utf16 { c.len_utf16() } else { 1 };
+        if width > column {
+            return line_start + index;
+        }
+    }
+    
+    line_start + line_text.len()
+}
+
+// Converts between byte offsets and UTF-16 code unit offsets, which is how Java strings are
+// indexed. Only the end of every non-ASCII character is recorded, since the two agree within
+// runs of ASCII
+pub struct Utf16Offsets<'a> {
+    content: &'a str,
+    // (byte offset, UTF-16 offset) just past each non-ASCII character
+    checkpoints: Vec<(usize, usize)>,
+}
+
+impl<'a> Utf16Offsets<'a> {
+    pub fn new(content: &'a str) -> Self {
+        let mut checkpoints = Vec::new();
+        let mut utf16 = 0;
+        
+        for (index, c) in content.char_indices() {
+            utf16 += c.len_utf16();
+            if !c.is_ascii() {
+                checkpoints.push((index + c.len_utf8(), utf16));
+            }
+        }
+        
+        Utf16Offsets { content, checkpoints }
+    }
+    
+    // UTF-16 offset of a byte offset; offsets inside a character count from its start
+    pub fn to_utf16(&self, byte_offset: usize) -> usize {
+        let mut byte_offset = byte_offset.min(self.content.len());
+        while !self.content.is_char_boundary(byte_offset) {
+            byte_offset -= 1;
+        }
+        
+        let index = self.checkpoints.partition_point(|&(byte, _)| byte <= byte_offset);
+        let (byte, utf16) = if index == 0 { (0, 0) } else { self.checkpoints[index - 1] };
+        utf16 + (byte_offset - byte)
+    }
+    
+    // Byte offset of a UTF-16 offset; an offset inside a surrogate pair maps to the start of its character
+    pub fn to_byte(&self, utf16_offset: usize) -> usize {
+        let index = self.checkpoints.partition_point(|&(_, utf16)| utf16 <= utf16_offset);
+        let (byte, utf16) = if index == 0 { (0, 0) } else { self.checkpoints[index - 1] };
+        
+        let mut byte_offset = (byte + (utf16_offset - utf16)).min(self.content.len());
+        while !self.content.is_char_boundary(byte_offset) {
+            byte_offset -= 1;
         }
+        byte_offset
     }
 }
 
-// Add Kotlin completions
-fn add_kotlin_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
-    let kotlin_snippets = [
-        CompletionItem {
-            label: "fun".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Function declaration".to_string()),
-            documentation: Some("Create a new function".to_string()),
-            insert_text: "fun ${1:name}(${2:params}): ${3:Unit} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "class".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Class declaration".to_string()),
-            documentation: Some("Create a new class".to_string()),
-            insert_text: "class ${1:Name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "data class".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Data class declaration".to_string()),
-            documentation: Some("Create a data class".to_string()),
-            insert_text: "data class ${1:Name}(${0})".to_string(),
-        },
-        CompletionItem {
-            label: "interface".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Interface declaration".to_string()),
-            documentation: Some("Create an interface".to_string()),
-            insert_text: "interface ${1:Name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "when".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("When expression".to_string()),
-            documentation: Some("Create a when expression".to_string()),
-            insert_text: "when (${1:expression}) {\n\t${2:value} -> ${3:result}\n\telse -> ${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "if".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("If statement".to_string()),
-            documentation: Some("Create an if statement".to_string()),
-            insert_text: "if (${1:condition}) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "for".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("For loop".to_string()),
-            documentation: Some("Create a for loop".to_string()),
-            insert_text: "for (${1:item} in ${2:collection}) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "while".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("While loop".to_string()),
-            documentation: Some("Create a while loop".to_string()),
-            insert_text: "while (${1:condition}) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "val".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Immutable variable declaration".to_string()),
-            documentation: Some("Create an immutable variable".to_string()),
-            insert_text: "val ${1:name}: ${2:Type} = ${0}".to_string(),
-        },
-        CompletionItem {
-            label: "var".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Mutable variable declaration".to_string()),
-            documentation: Some("Create a mutable variable".to_string()),
-            insert_text: "var ${1:name}: ${2:Type} = ${0}".to_string(),
-        },
-        CompletionItem {
-            label: "companion object".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Companion object declaration".to_string()),
-            documentation: Some("Create a companion object".to_string()),
-            insert_text: "companion object {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "object".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Object declaration".to_string()),
-            documentation: Some("Create an object".to_string()),
-            insert_text: "object ${1:Name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "enum class".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Enum class declaration".to_string()),
-            documentation: Some("Create an enum class".to_string()),
-            insert_text: "enum class ${1:Name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "println".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Print to console".to_string()),
-            documentation: Some("Print to standard output".to_string()),
-            insert_text: "println(\"${0}\")".to_string(),
-        },
-    ];
+// highlight_syntax with offsets in UTF-16 code units
+pub fn highlight_syntax_utf16(content: &str, language_id: &str) -> DocumentHighlights {
+    let offsets = Utf16Offsets::new(content);
+    let document = highlight_syntax(content, language_id);
     
-    for snippet in &kotlin_snippets {
-        if snippet.label.starts_with(current_word) {
-            completions.push(snippet.clone());
-        }
+    DocumentHighlights {
+        highlights: document
+            .highlights
+            .into_iter()
+            .map(|highlight| SyntaxHighlight {
+                start: offsets.to_utf16(highlight.start),
+                end: offsets.to_utf16(highlight.end),
+                type_: highlight.type_,
+            })
+            .collect(),
+        degraded: document.degraded,
     }
 }
 
-// Add Java completions
-fn add_java_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
-    let java_snippets = [
-        CompletionItem {
-            label: "class".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Class declaration".to_string()),
-            documentation: Some("Create a new class".to_string()),
-            insert_text: "class ${1:Name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "interface".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Interface declaration".to_string()),
-            documentation: Some("Create an interface".to_string()),
-            insert_text: "interface ${1:Name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "enum".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Enum declaration".to_string()),
-            documentation: Some("Create an enum".to_string()),
-            insert_text: "enum ${1:Name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "public class".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Public class declaration".to_string()),
-            documentation: Some("Create a public class".to_string()),
-            insert_text: "public class ${1:Name} {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "public static void main".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Main method".to_string()),
-            documentation: Some("Create a main method".to_string()),
-            insert_text: "public static void main(String[] args) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "if".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("If statement".to_string()),
-            documentation: Some("Create an if statement".to_string()),
-            insert_text: "if (${1:condition}) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "for".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("For loop".to_string()),
-            documentation: Some("Create a for loop".to_string()),
-            insert_text: "for (int ${1:i} = 0; ${1:i} < ${2:size}; ${1:i}++) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "foreach".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("For-each loop".to_string()),
-            documentation: Some("Create a for-each loop".to_string()),
-            insert_text: "for (${1:Type} ${2:item} : ${3:collection}) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "while".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("While loop".to_string()),
-            documentation: Some("Create a while loop".to_string()),
-            insert_text: "while (${1:condition}) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "switch".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Switch statement".to_string()),
-            documentation: Some("Create a switch statement".to_string()),
-            insert_text: "switch (${1:expression}) {\n\tcase ${2:value}:\n\t\t${0}\n\t\tbreak;\n\tdefault:\n\t\tbreak;\n}".to_string(),
-        },
-        CompletionItem {
-            label: "try".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Try-catch block".to_string()),
-            documentation: Some("Create a try-catch block".to_string()),
-            insert_text: "try {\n\t${0}\n} catch (${1:Exception} e) {\n\te.printStackTrace();\n}".to_string(),
-        },
-        CompletionItem {
-            label: "sout".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("System.out.println".to_string()),
-            documentation: Some("Print to standard output".to_string()),
-            insert_text: "System.out.println(${0});".to_string(),
-        },
-    ];
+// get_completion_list with the cursor position in UTF-16 code units
+pub fn get_completion_list_utf16(content: &str, position: usize, language_id: &str) -> CompletionList {
+    get_completion_list(content, Utf16Offsets::new(content).to_byte(position), language_id)
+}
+
+// find_references with the position, offsets and columns in UTF-16 code units
+pub fn find_references_utf16(content: &str, position: usize, language_id: &str) -> Vec<Reference> {
+    let offsets = Utf16Offsets::new(content);
     
-    for snippet in &java_snippets {
-        if snippet.label.starts_with(current_word) {
-            completions.push(snippet.clone());
+    find_references(content, offsets.to_byte(position), language_id)
+        .into_iter()
+        .map(|reference| {
+            let line_start = content[..reference.start].rfind('\n').map_or(0, |i| i + 1);
+            let start = offsets.to_utf16(reference.start);
+            
+            Reference {
+                start,
+                end: offsets.to_utf16(reference.end),
+                line: reference.line,
+                column: start - offsets.to_utf16(line_start),
+            }
+        })
+        .collect()
+}
+
+// Apply several edits made against the same version of `content` at once, e.g. one per cursor.
+// Edits are applied from the end of the document backwards so every offset stays valid. Edits
+// may touch but not overlap, and two insertions at the same offset are rejected because their
+// order would be ambiguous
+pub fn apply_edits(content: &str, mut edits: Vec<TextEdit>) -> Result<String> {
+    for edit in &edits {
+        if edit.start_byte > edit.end_byte || edit.end_byte > content.len() {
+            return Err(anyhow!("Edit {}..{} is outside the document", edit.start_byte, edit.end_byte));
+        }
+        if !content.is_char_boundary(edit.start_byte) || !content.is_char_boundary(edit.end_byte) {
+            return Err(anyhow!("Edit {}..{} splits a character", edit.start_byte, edit.end_byte));
+        }
+    }
+    
+    // An insertion sorts after a replacement starting at the same offset, so it lands in front of it
+    edits.sort_by(|a, b| b.start_byte.cmp(&a.start_byte).then(b.end_byte.cmp(&a.end_byte)));
+    
+    for pair in edits.windows(2) {
+        let (later, earlier) = (&pair[0], &pair[1]);
+        let same_insertion_point = earlier.start_byte == later.end_byte && earlier.end_byte == later.start_byte;
+        if earlier.end_byte > later.start_byte || same_insertion_point {
+            return Err(anyhow!(
+                "Edits {}..{} and {}..{} overlap",
+                earlier.start_byte,
+                earlier.end_byte,
+                later.start_byte,
+                later.end_byte
+            ));
         }
     }
+    
+    let mut result = content.to_string();
+    for edit in &edits {
+        result.replace_range(edit.start_byte..edit.end_byte, &edit.new_text);
+    }
+    
+    Ok(result)
 }
 
-// Add Python completions
-fn add_python_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
-    let python_snippets = [
-        CompletionItem {
-            label: "def".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Function definition".to_string()),
-            documentation: Some("Define a new function".to_string()),
-            insert_text: "def ${1:name}(${2:params}):\n\t${0}".to_string(),
-        },
-        CompletionItem {
-            label: "class".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Class definition".to_string()),
-            documentation: Some("Define a new class".to_string()),
-            insert_text: "class ${1:Name}:\n\tdef __init__(self, ${2:params}):\n\t\t${0}".to_string(),
-        },
-        CompletionItem {
-            label: "if".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("If statement".to_string()),
-            documentation: Some("Create an if statement".to_string()),
-            insert_text: "if ${1:condition}:\n\t${0}".to_string(),
-        },
-        CompletionItem {
-            label: "for".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("For loop".to_string()),
-            documentation: Some("Create a for loop".to_string()),
-            insert_text: "for ${1:item} in ${2:collection}:\n\t${0}".to_string(),
-        },
-        CompletionItem {
-            label: "while".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("While loop".to_string()),
-            documentation: Some("Create a while loop".to_string()),
-            insert_text: "while ${1:condition}:\n\t${0}".to_string(),
-        },
-        CompletionItem {
-            label: "try".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Try-except block".to_string()),
-            documentation: Some("Create a try-except block".to_string()),
-            insert_text: "try:\n\t${1}\nexcept ${2:Exception} as e:\n\t${0}".to_string(),
-        },
-        CompletionItem {
-            label: "import".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Import statement".to_string()),
-            documentation: Some("Import a module".to_string()),
-            insert_text: "import ${0}".to_string(),
-        },
-        CompletionItem {
-            label: "from".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("From import statement".to_string()),
-            documentation: Some("Import from a module".to_string()),
-            insert_text: "from ${1:module} import ${0}".to_string(),
-        },
-        CompletionItem {
-            label: "print".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Print statement".to_string()),
-            documentation: Some("Print to standard output".to_string()),
-            insert_text: "print(${0})".to_string(),
-        },
-        CompletionItem {
-            label: "lambda".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Lambda expression".to_string()),
-            documentation: Some("Create a lambda expression".to_string()),
-            insert_text: "lambda ${1:params}: ${0}".to_string(),
-        },
-    ];
+// Append the index pairs of matching lines, in order, offsetting them by where the slices start
+fn collect_line_matches(old_lines: &[&str], new_lines: &[&str], old_offset: usize, new_offset: usize, matches: &mut Vec<(usize, usize)>) {
+    let prefix = old_lines.iter().zip(new_lines).take_while(|(a, b)| a == b).count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    
+    matches.extend((0..prefix).map(|k| (old_offset + k, new_offset + k)));
+    
+    let old_middle = &old_lines[prefix..old_lines.len() - suffix];
+    let new_middle = &new_lines[prefix..new_lines.len() - suffix];
+    let (old_offset_middle, new_offset_middle) = (old_offset + prefix, new_offset + prefix);
+    
+    if !old_middle.is_empty() && !new_middle.is_empty() {
+        if old_middle.len() * new_middle.len() <= MAX_DIFF_CELLS {
+            let common = longest_common_lines(old_middle, new_middle);
+            matches.extend(common.into_iter().map(|(i, j)| (old_offset_middle + i, new_offset_middle + j)));
+        } else {
+            // With no unique anchors the whole middle is left to be replaced as one edit
+            let (mut old_start, mut new_start) = (0, 0);
+            let anchors = unique_line_anchors(old_middle, new_middle);
+            let anchored = !anchors.is_empty();
+            
+            for (i, j) in anchors {
+                collect_line_matches(&old_middle[old_start..i], &new_middle[new_start..j], old_offset_middle + old_start, new_offset_middle + new_start, matches);
+                matches.push((old_offset_middle + i, new_offset_middle + j));
+                old_start = i + 1;
+                new_start = j + 1;
+            }
+            
+            if anchored {
+                collect_line_matches(&old_middle[old_start..], &new_middle[new_start..], old_offset_middle + old_start, new_offset_middle + new_start, matches);
+            }
+        }
+    }
     
-    for snippet in &python_snippets {
-        if snippet.label.starts_with(current_word) {
-            completions.push(snippet.clone());
+    let old_tail = old_lines.len() - suffix;
+    let new_tail = new_lines.len() - suffix;
+    matches.extend((0..suffix).map(|k| (old_offset + old_tail + k, new_offset + new_tail + k)));
+}
+
+// Index pairs of a longest common subsequence of lines, in order
+fn longest_common_lines(old_lines: &[&str], new_lines: &[&str]) -> Vec<(usize, usize)> {
+    let (n, m) = (old_lines.len(), new_lines.len());
+    
+    // lengths[i * (m + 1) + j] is the LCS length of old_lines[i..] and new_lines[j..]
+    let mut lengths = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i * (m + 1) + j] = if old_lines[i] == new_lines[j] {
+                lengths[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                lengths[(i + 1) * (m + 1) + j].max(lengths[i * (m + 1) + j + 1])
+            };
+        }
+    }
+    
+    let mut matches = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old_lines[i] == new_lines[j] {
+            matches.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * (m + 1) + j] >= lengths[i * (m + 1) + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
         }
     }
+    
+    matches
 }
 
-// Add JavaScript/TypeScript completions
-fn add_js_ts_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
-    let js_ts_snippets = [
-        CompletionItem {
-            label: "function".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Function declaration".to_string()),
-            documentation: Some("Create a new function".to_string()),
-            insert_text: "function ${1:name}(${2:params}) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "arrow".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Arrow function".to_string()),
-            documentation: Some("Create an arrow function".to_string()),
-            insert_text: "(${1:params}) => {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "class".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Class declaration".to_string()),
-            documentation: Some("Create a new class".to_string()),
-            insert_text: "class ${1:Name} {\n\tconstructor(${2:params}) {\n\t\t${0}\n\t}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "if".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("If statement".to_string()),
-            documentation: Some("Create an if statement".to_string()),
-            insert_text: "if (${1:condition}) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "for".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("For loop".to_string()),
-            documentation: Some("Create a for loop".to_string()),
-            insert_text: "for (let ${1:i} = 0; ${1:i} < ${2:array}.length; ${1:i}++) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "forin".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("For-in loop".to_string()),
-            documentation: Some("Create a for-in loop".to_string()),
-            insert_text: "for (const ${1:key} in ${2:object}) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "forof".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("For-of loop".to_string()),
-            documentation: Some("Create a for-of loop".to_string()),
-            insert_text: "for (const ${1:item} of ${2:array}) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "while".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("While loop".to_string()),
-            documentation: Some("Create a while loop".to_string()),
-            insert_text: "while (${1:condition}) {\n\t${0}\n}".to_string(),
-        },
-        CompletionItem {
-            label: "switch".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Switch statement".to_string()),
-            documentation: Some("Create a switch statement".to_string()),
-            insert_text: "switch (${1:expression}) {\n\tcase ${2:value}:\n\t\t${0}\n\t\tbreak;\n\tdefault:\n\t\tbreak;\n}".to_string(),
-        },
-        CompletionItem {
-            label: "try".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Try-catch block".to_string()),
-            documentation: Some("Create a try-catch block".to_string()),
-            insert_text: "try {\n\t${0}\n} catch (${1:error}) {\n\tconsole.error(${1:error});\n}".to_string(),
-        },
-        CompletionItem {
-            label: "import".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Import statement".to_string()),
-            documentation: Some("Import a module".to_string()),
-            insert_text: "import { ${1} } from '${2:module}';".to_string(),
-        },
-        CompletionItem {
-            label: "console.log".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("Console log".to_string()),
-            documentation: Some("Log to the console".to_string()),
-            insert_text: "console.log(${0});".to_string(),
-        },
-    ];
+// Lines that occur exactly once in both slices, keeping the longest run that is in order on both sides
+fn unique_line_anchors(old_lines: &[&str], new_lines: &[&str]) -> Vec<(usize, usize)> {
+    // line -> (count in old, count in new, index in old, index in new)
+    let mut occurrences: HashMap<&str, (usize, usize, usize, usize)> = HashMap::new();
+    for (index, line) in old_lines.iter().enumerate() {
+        let entry = occurrences.entry(line).or_insert((0, 0, index, 0));
+        entry.0 += 1;
+    }
+    for (index, line) in new_lines.iter().enumerate() {
+        if let Some(entry) = occurrences.get_mut(line) {
+            entry.1 += 1;
+            entry.3 = index;
+        }
+    }
     
-    for snippet in &js_ts_snippets {
-        if snippet.label.starts_with(current_word) {
-            completions.push(snippet.clone());
+    let mut pairs: Vec<(usize, usize)> = occurrences
+        .values()
+        .filter(|(old_count, new_count, _, _)| *old_count == 1 && *new_count == 1)
+        .map(|&(_, _, old_index, new_index)| (old_index, new_index))
+        .collect();
+    pairs.sort();
+    
+    // Longest increasing run of new indices, by patience sorting
+    let mut pile_tops: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; pairs.len()];
+    for (k, &(_, new_index)) in pairs.iter().enumerate() {
+        let pile = pile_tops.partition_point(|&top| pairs[top].1 < new_index);
+        previous[k] = if pile > 0 { Some(pile_tops[pile - 1]) } else { None };
+        if pile == pile_tops.len() {
+            pile_tops.push(k);
+        } else {
+            pile_tops[pile] = k;
         }
     }
+    
+    let mut anchors = Vec::new();
+    let mut current = pile_tops.last().copied();
+    while let Some(k) = current {
+        anchors.push(pairs[k]);
+        current = previous[k];
+    }
+    anchors.reverse();
+    
+    anchors
 }
 
-// Add Rust string completions
-fn add_rust_string_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
-    // No specific string completions for Rust
+// Format only the lines touched by [start_byte, end_byte) and return the whole document.
+// Rust sends the enclosing top-level items through rustfmt; other languages, and Rust when
+// rustfmt is unavailable or rejects the code, are re-indented by bracket depth.
+// Everything outside the affected lines is returned exactly as it was.
+pub fn format_range(content: &str, start_byte: usize, end_byte: usize, language_id: &str) -> String {
+    let start_byte = floor_char_boundary(content, start_byte.min(content.len()));
+    let end_byte = floor_char_boundary(content, end_byte.min(content.len())).max(start_byte);
+    
+    let first_line = get_line_number(content, start_byte);
+    let mut last_line = get_line_number(content, end_byte);
+    
+    // A selection that ends at the start of a line does not include that line
+    if last_line > first_line && content[..end_byte].ends_with('\n') {
+        last_line -= 1;
+    }
+    
+    if language_id == "rust" {
+        if let Some(formatted) = format_rust_items(content, first_line, last_line) {
+            return formatted;
+        }
+    }
+    
+    reindent_lines(content, first_line, last_line, language_id)
 }
 
-// Add Kotlin string completions
-fn add_kotlin_string_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
-    // No specific string completions for Kotlin
+// Run rustfmt over the top-level items containing the given lines and splice the result back
+fn format_rust_items(content: &str, first_line: usize, last_line: usize) -> Option<String> {
+    let lines: Vec<&str> = content.split('\n').collect();
+    let depths = line_bracket_depths(content, "rust");
+    let at_top_level = |index: usize| depths.get(index).copied().flatten() == Some(0);
+    
+    let mut start = first_line;
+    while start > 0 && !at_top_level(start) {
+        start -= 1;
+    }
+    
+    // Keep attributes and doc comments with their item
+    while start > 0 && at_top_level(start - 1) && {
+        let previous = lines[start - 1].trim_start();
+        previous.starts_with("#[") || previous.starts_with("///")
+    } {
+        start -= 1;
+    }
+    
+    let mut end = last_line.min(lines.len() - 1);
+    while end + 1 < lines.len() && !at_top_level(end + 1) {
+        end += 1;
+    }
+    
+    let region = lines[start..=end].join("\n");
+    if region.trim().is_empty() {
+        return None;
+    }
+    
+    let formatted = run_rustfmt(&format!("{}\n", region))?;
+    let formatted = formatted.strip_suffix('\n').unwrap_or(&formatted);
+    
+    let mut result: Vec<&str> = lines[..start].to_vec();
+    result.push(formatted);
+    result.extend_from_slice(&lines[end + 1..]);
+    
+    Some(result.join("\n"))
 }
 
-// Add Java string completions
-fn add_java_string_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
-    // No specific string completions for Java
+// Format Rust source with rustfmt from PATH, or None if it is missing or the source does not parse
+fn run_rustfmt(source: &str) -> Option<String> {
+    pipe_through_formatter("rustfmt", &["--edition", "2021", "--emit", "stdout"], source)
 }
 
-// Add Python string completions
-fn add_python_string_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
-    // No specific string completions for Python
+// Run a formatter that reads source on stdin and writes the result to stdout.
+// None if the program is missing or fails, e.g. because the source does not parse
+fn pipe_through_formatter(program: &str, args: &[&str], source: &str) -> Option<String> {
+    let output = pipe_through_command(Command::new(program).args(args), source)?;
+    if output.status.success() {
+        String::from_utf8(output.stdout).ok()
+    } else {
+        None
+    }
 }
 
-// Add JavaScript/TypeScript string completions
-fn add_js_ts_string_completions(completions: &mut Vec<CompletionItem>, current_word: &str) {
-    // No specific string completions for JavaScript/TypeScript
+// Feed source to a command on stdin and collect what it prints. None if it could not be started
+fn pipe_through_command(command: &mut Command, source: &str) -> Option<std::process::Output> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    
+    // Write from another thread so a large file can't fill the output pipe while we are still writing
+    let mut stdin = child.stdin.take()?;
+    let source = source.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(source.as_bytes()));
+    
+    let output = child.wait_with_output().ok()?;
+    writer.join().ok()?.ok()?;
+    Some(output)
 }
 
-// Add general completions
-fn add_general_completions(completions: &mut Vec<CompletionItem>, current_word: &str, language_config: &LanguageConfig) {
-    // Add common completions for all languages
-    let common_snippets = [
-        CompletionItem {
-            label: "if".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("If statement".to_string()),
-            documentation: Some("Create an if statement".to_string()),
-            insert_text: match language_config.id.as_str() {
-                "python" => "if ${1:condition}:\n\t${0}".to_string(),
-                "rust" => "if ${1:condition} {\n\t${0}\n}".to_string(),
-                _ => "if (${1:condition}) {\n\t${0}\n}".to_string(),
-            },
-        },
-        CompletionItem {
-            label: "for".to_string(),
-            kind: "snippet".to_string(),
-            detail: Some("For loop".to_string()),
-            documentation: Some("Create a for loop".to_string()),
-            insert_text: match language_config.id.as_str() {
-                "python" => "for ${1:item} in ${2:collection}:\n\t${0}".to_string(),
-                "rust" => "for ${1:item} in ${2:collection} {\n\t${0}\n}".to_string(),
-                "kotlin" => "for (${1:item} in ${2:collection}) {\n\t${0}\n}".to_string(),
-                "java" => "for (int ${1:i} = 0; ${1:i} < ${2:size}; ${1:i}++) {\n\t${0}\n}".to_string(),
-                "javascript" | "typescript" => "for (let ${1:i} = 0; ${1:i} < ${2:array}.length; ${1:i}++) {\n\t${0}\n}".to_string(),
-                _ => "for (${1:init}; ${2:condition}; ${3:update}) {\n\t${0}\n}".to_string(),
-            },
-        },
-    ];
+// Re-indent lines first_line..=last_line by bracket depth, using the document's own indent unit.
+// Lines that start inside a comment or string are left alone; languages without bracket
+// blocks only have trailing whitespace removed.
+fn reindent_lines(content: &str, first_line: usize, last_line: usize, language_id: &str) -> String {
+    let depths = line_bracket_depths(content, language_id);
+    let unit = detect_indent_unit(content);
+    let bracket_indented = is_bracket_indented(language_id);
     
-    for snippet in &common_snippets {
-        if snippet.label.starts_with(current_word) {
-            completions.push(snippet.clone());
-        }
+    let mut lines: Vec<String> = content.split('\n').map(|line| line.to_string()).collect();
+    let last_line = last_line.min(lines.len() - 1);
+    
+    for index in first_line..=last_line {
+        let line = &lines[index];
+        let (text, line_ending) = match line.strip_suffix('\r') {
+            Some(text) => (text, "\r"),
+            None => (line.as_str(), ""),
+        };
+        let trimmed = text.trim();
+        
+        let formatted = match depths.get(index).copied().flatten() {
+            None => continue,
+            Some(_) if trimmed.is_empty() => String::new(),
+            Some(depth) if bracket_indented => {
+                let closers = trimmed.chars().take_while(|c| matches!(c, '}' | ']' | ')')).count() as i32;
+                format!("{}{}", unit.repeat((depth - closers).max(0) as usize), trimmed)
+            }
+            Some(_) => text.trim_end().to_string(),
+        };
+        
+        lines[index] = format!("{}{}", formatted, line_ending);
     }
+    
+    lines.join("\n")
 }
 
-// Format code
-pub fn format_code(content: &str, language_id: &str) -> String {
-    let state = EDITOR_STATE.lock().unwrap();
+// Languages whose blocks are delimited, and indented, by brackets
+fn is_bracket_indented(language_id: &str) -> bool {
+    matches!(
+        language_id,
+        "rust" | "kotlin" | "java" | "cpp" | "javascript" | "typescript" | "tsx" | "css" | "json"
+    )
+}
+
+// Bracket depth at the start of each line, or None for lines that start inside a comment or string
+fn line_bracket_depths(content: &str, language_id: &str) -> Vec<Option<i32>> {
+    let bytes = content.as_bytes();
+    let single_quote_strings = matches!(language_id, "javascript" | "typescript" | "tsx" | "json");
     
-    if !state.initialized {
-        return content.to_string();
+    let mut depths = vec![Some(0)];
+    let mut depth = 0i32;
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        if let Some(end) = skip_comment_or_literal(bytes, i, single_quote_strings) {
+            depths.extend(bytes[i..end].iter().filter(|&&c| c == b'\n').map(|_| None));
+            i = end.max(i + 1);
+            continue;
+        }
+        
+        match bytes[i] {
+            b'{' | b'[' | b'(' => depth += 1,
+            b'}' | b']' | b')' => depth = (depth - 1).max(0),
+            b'\n' => depths.push(Some(depth)),
+            _ => {}
+        }
+        i += 1;
     }
     
-    let language_config = match state.languages.get(language_id) {
-        Some(config) => config,
-        None => return content.to_string(),
-    };
+    depths
+}
+
+// The indentation step used in a document: a tab, or the smallest space indent of 2 to 8, defaulting to 4
+fn detect_indent_unit(content: &str) -> String {
+    let mut smallest = None;
     
-    #[cfg(feature = "tree-sitter-support")]
-    {
-        if let Some(parser) = state.parsers.get(language_id) {
-            return format_with_tree_sitter(content, parser, language_config);
+    for line in content.lines() {
+        if line.starts_with('\t') {
+            return "\t".to_string();
+        }
+        
+        let trimmed = line.trim_start_matches(' ');
+        let width = line.len() - trimmed.len();
+        
+        // Skip blank lines and the " * " continuation lines of block comments
+        if trimmed.is_empty() || trimmed.starts_with('*') || width < 2 || width > 8 {
+            continue;
         }
+        
+        smallest = Some(smallest.map_or(width, |current: usize| current.min(width)));
     }
     
-    // Fallback to simple formatting
-    format_with_regex(content, language_config)
+    " ".repeat(smallest.unwrap_or(4))
 }
 
-// Format with tree-sitter
-#[cfg(feature = "tree-sitter-support")]
-fn format_with_tree_sitter(content: &str, parser: &Parser, language_config: &LanguageConfig) -> String {
-    // Parse the code
-    let tree = match parser.parse(content, None) {
-        Some(tree) => tree,
-        None => return content.to_string(),
+fn floor_char_boundary(content: &str, mut index: usize) -> usize {
+    while index > 0 && !content.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+// Whitespace to start the new line with when Enter is pressed at `position`: the current line's
+// indentation, one level deeper after an opening bracket (or a Python `:`), and one level
+// shallower when the text moving to the new line starts with a closing bracket. With the cursor
+// between a pair, as in `{|}`, the deeper indentation is returned and the caller puts the
+// closing bracket on a line of its own. An empty `indent_unit` uses the document's own.
+pub fn indent_for_new_line(content: &str, position: usize, language_id: &str, indent_unit: &str) -> String {
+    let position = floor_char_boundary(content, position.min(content.len()));
+    let before = &content[..position];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let line_end = content[position..].find('\n').map_or(content.len(), |index| position + index);
+    
+    let line = &content[line_start..line_end];
+    let base = &line[..line.len() - line.trim_start_matches(|c| c == ' ' || c == '\t').len()];
+    let base = &base[..base.len().min(position - line_start)];
+    let line_before_cursor = &content[line_start..position];
+    let rest_of_line = content[position..line_end].trim_start();
+    
+    let unit = if indent_unit.is_empty() { detect_indent_unit(content) } else { indent_unit.to_string() };
+    let indent = base.to_string();
+    
+    if language_id == "python" {
+        let code = strip_python_comment(line_before_cursor).trim_end();
+        let first_word = code.trim_start().split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or("");
+        let open_brackets = code.chars().filter(|c| matches!(c, '(' | '[' | '{')).count();
+        let close_brackets = code.chars().filter(|c| matches!(c, ')' | ']' | '}')).count();
+        
+        return if code.ends_with(':') || open_brackets > close_brackets {
+            indent + &unit
+        } else if matches!(first_word, "return" | "pass" | "break" | "continue" | "raise") {
+            dedent(&indent, &unit)
+        } else {
+            indent
+        };
+    }
+    
+    if !is_bracket_indented(language_id) {
+        return indent;
+    }
+    
+    // Compare the bracket depth at the start of the current line with the depth at the cursor,
+    // which skips brackets in comments and strings
+    let depths = line_bracket_depths(&format!("{}\n", before), language_id);
+    let line_depth = depths.get(depths.len().saturating_sub(2)).copied().flatten();
+    let cursor_depth = depths.last().copied().flatten();
+    
+    let (line_depth, cursor_depth) = match (line_depth, cursor_depth) {
+        (Some(line_depth), Some(cursor_depth)) => (line_depth, cursor_depth),
+        // Inside a block comment or string: keep the indentation as it is
+        _ => return indent,
     };
     
-    // Format based on language
-    match language_config.id.as_str() {
-        "rust" => format_rust_code(content, &tree),
-        "kotlin" => format_kotlin_code(content, &tree),
-        "java" => format_java_code(content, &tree),
-        "python" => format_python_code(content, &tree),
-        "javascript" | "typescript" => format_js_ts_code(content, &tree),
-        "html" | "xml" => format_html_xml_code(content, &tree),
-        "css" => format_css_code(content, &tree),
-        "json" => format_json_code(content, &tree),
-        _ => content.to_string(),
+    let opened = cursor_depth > line_depth;
+    let closes = rest_of_line.starts_with(|c| matches!(c, '}' | ']' | ')'));
+    
+    if opened {
+        indent + &unit
+    } else if closes {
+        dedent(&indent, &unit)
+    } else {
+        indent
     }
 }
 
-// Format with regex
-fn format_with_regex(content: &str, language_config: &LanguageConfig) -> String {
-    // Simple formatting based on language
-    match language_config.id.as_str() {
-        "rust" => format_rust_code_regex(content),
-        "kotlin" => format_kotlin_code_regex(content),
-        "java" => format_java_code_regex(content),
-        "python" => format_python_code_regex(content),
-        "javascript" | "typescript" => format_js_ts_code_regex(content),
-        "html" | "xml" => format_html_xml_code_regex(content),
-        "css" => format_css_code_regex(content),
-        "json" => format_json_code_regex(content),
-        _ => content.to_string(),
+// Remove one indentation level from the end of `indent`
+fn dedent(indent: &str, unit: &str) -> String {
+    if let Some(shorter) = indent.strip_suffix(unit) {
+        return shorter.to_string();
+    }
+    if let Some(shorter) = indent.strip_suffix('\t') {
+        return shorter.to_string();
+    }
+    
+    let spaces = indent.len() - indent.trim_end_matches(' ').len();
+    indent[..indent.len() - spaces.min(unit.len().max(1))].to_string()
+}
+
+// A Python line without its `#` comment, leaving `#` inside string literals alone
+fn strip_python_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    
+    for (index, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return &line[..index],
+            None => {}
+        }
+    }
+    
+    line
+}
+
+// Parse code structure
+pub fn parse_code_structure(content: &str, language_id: &str) -> CodeStructure {
+    let state = editor_state();
+    
+    if !state.initialized {
+        return CodeStructure {
+            classes: Vec::new(),
+            functions: Vec::new(),
+            variables: Vec::new(),
+            imports: Vec::new(),
+        };
     }
+    
+    parse_structure(&state, content, language_id).unwrap_or_else(|| CodeStructure {
+        classes: Vec::new(),
+        functions: Vec::new(),
+        variables: Vec::new(),
+        imports: Vec::new(),
+    })
 }
 
-// Format Rust code with tree-sitter
-#[cfg(feature = "tree-sitter-support")]
-fn format_rust_code(content: &str, tree: &Tree) -> String {
-    // In a real implementation, this would use tree-sitter to format Rust code
-    // For now, just return the original content
-    content.to_string()
+// Parse code structure with tree-sitter when there is a parser for the language
+fn parse_structure(state: &EditorState, content: &str, language_id: &str) -> Option<CodeStructure> {
+    let language_config = state.languages.get(language_id)?;
+    
+    #[cfg(feature = "tree-sitter-support")]
+    {
+        if let Some(parser) = state.parsers.get(language_id) {
+            return Some(parse_structure_with_tree_sitter(content, parser, language_config));
+        }
+    }
+    
+    // Fallback to regex-based parsing
+    Some(parse_structure_with_regex(content, language_config))
 }
 
-// Format Kotlin code with tree-sitter
-#[cfg(feature = "tree-sitter-support")]
-fn format_kotlin_code(content: &str, tree: &Tree) -> String {
-    // In a real implementation, this would use tree-sitter to format Kotlin code
-    // For now, just return the original content
-    content.to_string()
-}
+// Source files larger than this, usually generated, are left out of the project index
+const MAX_INDEXED_FILE_BYTES: u64 = 1024 * 1024;
 
-// Format Java code with tree-sitter
-#[cfg(feature = "tree-sitter-support")]
-fn format_java_code(content: &str, tree: &Tree) -> String {
-    // In a real implementation, this would use tree-sitter to format Java code
-    // For now, just return the original content
-    content.to_string()
-}
+// Most results returned by a workspace symbol lookup
+const MAX_SYMBOL_LOOKUP_RESULTS: usize = 200;
 
-// Format Python code with tree-sitter
-#[cfg(feature = "tree-sitter-support")]
-fn format_python_code(content: &str, tree: &Tree) -> String {
-    // In a real implementation, this would use tree-sitter to format Python code
-    // For now, just return the original content
-    content.to_string()
+fn project_index() -> MutexGuard<'static, Option<ProjectSymbolIndex>> {
+    PROJECT_INDEX.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
 }
 
-// Format JavaScript/TypeScript code with tree-sitter
-#[cfg(feature = "tree-sitter-support")]
-fn format_js_ts_code(content: &str, tree: &Tree) -> String {
-    // In a real implementation, this would use tree-sitter to format JavaScript/TypeScript code
-    // For now, just return the original content
-    content.to_string()
+// Index the symbols of every source file under `root` in one of `languages` (every language when
+// empty), skipping files excluded by .gitignore and .ignore. The index replaces the cached one
+// used by lookup_symbol and reindex_file
+pub fn index_project(root: &str, languages: Vec<String>) -> Result<ProjectSymbolIndex> {
+    let root_path = Path::new(root);
+    if !root_path.is_dir() {
+        return Err(anyhow!("Directory not found: {}", root));
+    }
+    
+    if !editor_state().initialized {
+        return Err(anyhow!("Editor is not initialized"));
+    }
+    
+    // File watches report resolved paths, so the root is resolved too for reindex_file to match them
+    let root_path = fs::canonicalize(root_path)?;
+    
+    let mut index = ProjectSymbolIndex {
+        root: root_path.to_string_lossy().to_string(),
+        languages,
+        files: HashSet::new(),
+        symbols: HashMap::new(),
+    };
+    
+    let walker = ignore::WalkBuilder::new(root_path)
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    
+    for entry in walker.filter_map(|entry| entry.ok()) {
+        if entry.file_type().map_or(false, |file_type| file_type.is_file()) {
+            index.index_file(entry.path());
+        }
+    }
+    
+    *project_index() = Some(index.clone());
+    Ok(index)
 }
 
-// Format HTML/XML code with tree-sitter
-#[cfg(feature = "tree-sitter-support")]
-fn format_html_xml_code(content: &str, tree: &Tree) -> String {
-    // In a real implementation, this would use tree-sitter to format HTML/XML code
-    // For now, just return the original content
-    content.to_string()
+// Re-parse one file of the indexed project after it changed, was created or was deleted,
+// returning how many symbols it now defines
+pub fn reindex_file(path: &str) -> Result<usize> {
+    let mut cache = project_index();
+    let index = cache.as_mut().ok_or_else(|| anyhow!("No project has been indexed"))?;
+    
+    let path = if Path::new(path).is_absolute() {
+        PathBuf::from(path)
+    } else {
+        Path::new(&index.root).join(path)
+    };
+    
+    // A deleted file cannot be resolved itself, but the directory it was in usually still can
+    let path = fs::canonicalize(&path)
+        .ok()
+        .or_else(|| Some(fs::canonicalize(path.parent()?).ok()?.join(path.file_name()?)))
+        .unwrap_or(path);
+    
+    if !path.starts_with(&index.root) {
+        return Err(anyhow!("{} is outside the indexed project {}", path.display(), index.root));
+    }
+    
+    Ok(index.index_file(&path))
 }
 
-// Format CSS code with tree-sitter
-#[cfg(feature = "tree-sitter-support")]
-fn format_css_code(content: &str, tree: &Tree) -> String {
-    // In a real implementation, this would use tree-sitter to format CSS code
-    // For now, just return the original content
-    content.to_string()
+// Symbols named exactly `query`, then those whose name contains it ignoring case, shortest names first
+pub fn lookup_symbol(query: &str) -> Vec<SymbolLocation> {
+    let cache = project_index();
+    let index = match cache.as_ref() {
+        Some(index) if !query.is_empty() => index,
+        _ => return Vec::new(),
+    };
+    
+    let mut results: Vec<SymbolLocation> = index.symbols.get(query).cloned().unwrap_or_default();
+    
+    let lowered = query.to_lowercase();
+    let mut partial: Vec<&SymbolLocation> = index
+        .symbols
+        .iter()
+        .filter(|(name, _)| name.as_str() != query && name.to_lowercase().contains(&lowered))
+        .flat_map(|(_, locations)| locations)
+        .collect();
+    partial.sort_by(|a, b| (a.name.len(), &a.name, &a.file, a.line).cmp(&(b.name.len(), &b.name, &b.file, b.line)));
+    
+    results.extend(partial.into_iter().cloned());
+    results.truncate(MAX_SYMBOL_LOOKUP_RESULTS);
+    results
 }
 
-// Format JSON code with tree-sitter
-#[cfg(feature = "tree-sitter-support")]
-fn format_json_code(content: &str, tree: &Tree) -> String {
-    // In a real implementation, this would use tree-sitter to format JSON code
-    // For now, just return the original content
-    content.to_string()
+impl ProjectSymbolIndex {
+    pub fn symbol_count(&self) -> usize {
+        self.symbols.values().map(|locations| locations.len()).sum()
+    }
+    
+    // Parse a file and replace its symbols, returning how many it defines
+    fn index_file(&mut self, path: &Path) -> usize {
+        let file = path
+            .strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        
+        if self.files.remove(&file) {
+            self.symbols.retain(|_, locations| {
+                locations.retain(|location| location.file != file);
+                !locations.is_empty()
+            });
+        }
+        
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let language_id = match detect_language(&file_name, "") {
+            Some(id) if self.languages.is_empty() || self.languages.contains(&id) => id,
+            _ => return 0,
+        };
+        
+        if fs::metadata(path).map_or(true, |metadata| metadata.len() > MAX_INDEXED_FILE_BYTES) {
+            return 0;
+        }
+        
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => return 0,
+        };
+        
+        let structure = parse_code_structure(&content, &language_id);
+        
+        let classes = structure.classes.into_iter().map(|class| (class.name, class.start_line, class.kind));
+        let functions = structure.functions.into_iter().map(|function| (function.name, function.start_line, "function".to_string()));
+        let variables = structure.variables.into_iter().map(|variable| (variable.name, variable.line, "variable".to_string()));
+        
+        let mut count = 0;
+        for (name, line, kind) in classes.chain(functions).chain(variables) {
+            self.symbols.entry(name.clone()).or_default().push(SymbolLocation {
+                name,
+                file: file.clone(),
+                line,
+                kind,
+            });
+            count += 1;
+        }
+        
+        self.files.insert(file);
+        count
+    }
 }
 
-// Format Rust code with regex
-fn format_rust_code_regex(content: &str) -> String {
-    // In a real implementation, this would use regex to format Rust code
-    // For now, just return the original content
-    content.to_string()
-}
+// File events kept for the UI to poll; older ones are dropped past this
+const MAX_PENDING_FILE_EVENTS: usize = 1000;
 
-// Format Kotlin code with regex
-fn format_kotlin_code_regex(content: &str) -> String {
-    // In a real implementation, this would use regex to format Kotlin code
-    // For now, just return the original content
-    content.to_string()
+// Watch a file for changes made outside the editor, e.g. by a formatter or a git checkout.
+// Each change refreshes the file's entries in the project symbol index and queues a FileEvent
+// for poll_file_events
+pub fn watch_file(path: &str) -> Result<String> {
+    let file = fs::canonicalize(path).map_err(|e| anyhow!("Cannot watch {}: {}", path, e))?;
+    let directory = file
+        .parent()
+        .ok_or_else(|| anyhow!("Cannot watch {}: no parent directory", path))?
+        .to_path_buf();
+    
+    let watch_id = Uuid::new_v4().to_string();
+    
+    let event_watch_id = watch_id.clone();
+    let watched_file = file.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        if let Ok(event) = event {
+            if !matches!(event.kind, EventKind::Access(_)) && event.paths.iter().any(|path| *path == watched_file) {
+                record_file_event(&event_watch_id, &watched_file, &event.kind);
+            }
+        }
+    })?;
+    
+    // The directory is watched so saves that replace the file by renaming are still seen
+    watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+    
+    FILE_WATCHES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(watch_id.clone(), watcher);
+    
+    Ok(watch_id)
 }
 
-// Format Java code with regex
-fn format_java_code_regex(content: &str) -> String {
-    // In a real implementation, this would use regex to format Java code
-    // For now, just return the original content
-    content.to_string()
+// Stop watching a file, discarding its events that were not polled yet
+pub fn unwatch_file(watch_id: &str) -> Result<()> {
+    FILE_WATCHES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .remove(watch_id)
+        .ok_or_else(|| anyhow!("Unknown watch id: {}", watch_id))?;
+    
+    FILE_EVENTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .retain(|event| event.watch_id != watch_id);
+    
+    Ok(())
 }
 
-// Format Python code with regex
-fn format_python_code_regex(content: &str) -> String {
-    // In a real implementation, this would use regex to format Python code
-    // For now, just return the original content
-    content.to_string()
+// Take the file events recorded since the last poll
+pub fn poll_file_events() -> Vec<FileEvent> {
+    FILE_EVENTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .drain(..)
+        .collect()
 }
 
-// Format JavaScript/TypeScript code with regex
-fn format_js_ts_code_regex(content: &str) -> String {
-    // In a real implementation, this would use regex to format JavaScript/TypeScript code
-    // For now, just return the original content
-    content.to_string()
+fn record_file_event(watch_id: &str, path: &Path, kind: &EventKind) {
+    let kind = if !path.exists() {
+        "removed"
+    } else if matches!(kind, EventKind::Create(_)) {
+        "created"
+    } else {
+        "modified"
+    };
+    
+    // Files outside the indexed project, or with no project indexed, are simply not in the index
+    let _ = reindex_file(&path.to_string_lossy());
+    
+    let mut events = FILE_EVENTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    
+    // A single save usually arrives as several events; one is enough until it has been polled
+    if events.last().map_or(false, |last| last.watch_id == watch_id && last.kind == kind) {
+        return;
+    }
+    
+    if events.len() >= MAX_PENDING_FILE_EVENTS {
+        events.remove(0);
+    }
+    
+    events.push(FileEvent {
+        watch_id: watch_id.to_string(),
+        path: path.to_string_lossy().to_string(),
+        kind: kind.to_string(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or(0),
+    });
 }
 
-// Format HTML/XML code with regex
-fn format_html_xml_code_regex(content: &str) -> String {
-    // In a real implementation, this would use regex to format HTML/XML code
-    // For now, just return the original content
-    content.to_string()
+// Get hover information for the symbol at a position
+pub fn hover_info(content: &str, position: usize, language_id: &str) -> Option<HoverInfo> {
+    let state = editor_state();
+    
+    if !state.initialized {
+        return None;
+    }
+    
+    let word = get_word_at_position(content, position);
+    if word.is_empty() {
+        return None;
+    }
+    
+    let structure = parse_structure(&state, content, language_id)?;
+    let lines: Vec<&str> = content.lines().collect();
+    
+    if let Some(function) = structure.functions.iter().find(|function| function.name == word) {
+        return Some(HoverInfo {
+            signature: declaration_signature(&lines, function.start_line),
+            doc: doc_comment(&lines, function.start_line, language_id),
+            kind: "function".to_string(),
+        });
+    }
+    
+    if let Some(class) = structure.classes.iter().find(|class| class.name == word) {
+        return Some(HoverInfo {
+            signature: declaration_signature(&lines, class.start_line),
+            doc: doc_comment(&lines, class.start_line, language_id),
+            kind: class.kind.clone(),
+        });
+    }
+    
+    // Variables: the closest declaration before the cursor, else the first one anywhere
+    let line = find_variable_declaration(content, position, &word, language_id)
+        .or_else(|| structure.variables.iter().find(|variable| variable.name == word).map(|variable| variable.line))?;
+    
+    Some(HoverInfo {
+        signature: lines.get(line).map_or(String::new(), |line| line.trim().to_string()),
+        doc: doc_comment(&lines, line, language_id),
+        kind: "variable".to_string(),
+    })
 }
 
-// Format CSS code with regex
-fn format_css_code_regex(content: &str) -> String {
-    // In a real implementation, this would use regex to format CSS code
-    // For now, just return the original content
-    content.to_string()
+// Declaration text from its first line up to the start of the body
+fn declaration_signature(lines: &[&str], start_line: usize) -> String {
+    let mut signature = Vec::new();
+    
+    for line in lines.iter().skip(start_line).take(5) {
+        let trimmed = line.trim();
+        
+        if let Some(body_start) = trimmed.find('{') {
+            signature.push(trimmed[..body_start].trim_end());
+            break;
+        }
+        
+        signature.push(trimmed);
+        if trimmed.ends_with(';') || trimmed.ends_with(':') || trimmed.ends_with(')') && !trimmed.ends_with("->") {
+            break;
+        }
+    }
+    
+    signature.join(" ").trim_end_matches(';').trim().to_string()
 }
 
-// Format JSON code with regex
-fn format_json_code_regex(content: &str) -> String {
-    // In a real implementation, this would use regex to format JSON code
-    // For now, just return the original content
-    content.to_string()
+// Documentation attached to a declaration: `///` lines in Rust, `/** */` blocks in C-like
+// languages and docstrings in Python
+fn doc_comment(lines: &[&str], start_line: usize, language_id: &str) -> Option<String> {
+    if language_id == "python" {
+        return python_docstring(lines, start_line);
+    }
+    
+    // Walk up over attributes and annotations to the comment
+    let mut line = start_line;
+    while line > 0 {
+        let previous = lines[line - 1].trim();
+        if previous.starts_with("#[") || (previous.starts_with('@') && language_id != "rust") {
+            line -= 1;
+        } else {
+            break;
+        }
+    }
+    
+    if line == 0 {
+        return None;
+    }
+    
+    let mut doc_lines = Vec::new();
+    
+    if language_id == "rust" {
+        while line > 0 && lines[line - 1].trim().starts_with("///") {
+            line -= 1;
+            doc_lines.push(lines[line].trim().trim_start_matches("///").trim());
+        }
+        doc_lines.reverse();
+    } else if lines[line - 1].trim().ends_with("*/") {
+        let end = line - 1;
+        let mut start = end;
+        while !lines[start].contains("/**") {
+            if start == 0 || lines[start].contains("/*") && !lines[start].contains("/**") {
+                return None;
+            }
+            start -= 1;
+        }
+        
+        for doc_line in &lines[start..=end] {
+            let text = doc_line.trim()
+                .trim_start_matches("/**")
+                .trim_end_matches("*/")
+                .trim()
+                .trim_start_matches('*')
+                .trim();
+            doc_lines.push(text);
+        }
+    }
+    
+    let doc = doc_lines.join("\n").trim().to_string();
+    if doc.is_empty() {
+        None
+    } else {
+        Some(doc)
+    }
 }
 
-// Parse code structure
-pub fn parse_code_structure(content: &str, language_id: &str) -> CodeStructure {
-    let state = EDITOR_STATE.lock().unwrap();
+// Docstring that opens the body of a Python def or class
+fn python_docstring(lines: &[&str], start_line: usize) -> Option<String> {
+    // Skip to the end of a multi-line signature
+    let mut line = start_line;
+    while line < lines.len() && !lines[line].trim_end().ends_with(':') {
+        line += 1;
+    }
     
-    if !state.initialized {
-        return CodeStructure {
-            classes: Vec::new(),
-            functions: Vec::new(),
-            variables: Vec::new(),
-            imports: Vec::new(),
-        };
+    let first = lines.get(line + 1)?.trim();
+    let quote = if first.starts_with("\"\"\"") {
+        "\"\"\""
+    } else if first.starts_with("'''") {
+        "'''"
+    } else {
+        return None;
+    };
+    
+    let mut doc_lines = Vec::new();
+    let rest = &first[3..];
+    if let Some(end) = rest.find(quote) {
+        doc_lines.push(&rest[..end]);
+    } else {
+        doc_lines.push(rest);
+        for doc_line in lines.iter().skip(line + 2) {
+            match doc_line.find(quote) {
+                Some(end) => {
+                    doc_lines.push(&doc_line[..end]);
+                    break;
+                }
+                None => doc_lines.push(doc_line),
+            }
+        }
     }
     
-    let language_config = match state.languages.get(language_id) {
-        Some(config) => config,
-        None => return CodeStructure {
-            classes: Vec::new(),
-            functions: Vec::new(),
-            variables: Vec::new(),
-            imports: Vec::new(),
-        },
+    let doc = doc_lines.iter().map(|line| line.trim()).collect::<Vec<_>>().join("\n").trim().to_string();
+    if doc.is_empty() {
+        None
+    } else {
+        Some(doc)
+    }
+}
+
+// Line of the declaration of a local variable, preferring the closest one before the position
+fn find_variable_declaration(content: &str, position: usize, name: &str, language_id: &str) -> Option<usize> {
+    let name = regex::escape(name);
+    let pattern = match language_id {
+        "python" => format!(r"(?m)^[ \t]*{}\s*(?::[^=\n]+)?=[^=]", name),
+        "java" | "cpp" => format!(r"\b(?:final\s+)?[\w.]+(?:<[^>;]*>)?(?:\[\])*\s+{}\s*[=;:,)]", name),
+        _ => format!(r"\b(?:let|var|val|const|static)\s+(?:mut\s+)?{}\b", name),
     };
+    let regex = Regex::new(&pattern).ok()?;
     
-    #[cfg(feature = "tree-sitter-support")]
-    {
-        if let Some(parser) = state.parsers.get(language_id) {
-            return parse_structure_with_tree_sitter(content, parser, language_config);
+    let mut first = None;
+    let mut closest = None;
+    for mat in regex.find_iter(content) {
+        if first.is_none() {
+            first = Some(mat.start());
+        }
+        if mat.start() <= position {
+            closest = Some(mat.start());
         }
     }
     
-    // Fallback to regex-based parsing
-    parse_structure_with_regex(content, language_config)
+    closest.or(first).map(|start| get_line_number(content, start))
 }
 
 // Parse code structure with tree-sitter
@@ -1610,6 +5579,10 @@ fn traverse_structure_tree(
     language_config: &LanguageConfig
 ) {
     loop {
+        if is_cancelled() {
+            return;
+        }
+        
         let node = cursor.node();
         
         // Get node type
@@ -1925,10 +5898,13 @@ fn traverse_structure_tree(
                     _ => {}
                 }
             },
-            "javascript" | "typescript" => {
+            "javascript" | "typescript" | "tsx" => {
                 match node_type {
                     "class_declaration" => {
-                        if let Some(name_node) = find_child_by_type(&node, "identifier") {
+                        // The TypeScript and TSX grammars name classes with a type_identifier
+                        let name_node = find_child_by_type(&node, "identifier")
+                            .or_else(|| find_child_by_type(&node, "type_identifier"));
+                        if let Some(name_node) = name_node {
                             let name = name_node.utf8_text(content.as_bytes()).unwrap_or("").to_string();
                             
                             let start_line = node.start_position().row;
@@ -2109,7 +6085,7 @@ fn parse_structure_with_regex(content: &str, language_config: &LanguageConfig) -
         "kotlin" => parse_kotlin_structure_regex(content, &mut classes, &mut functions, &mut variables, &mut imports),
         "java" => parse_java_structure_regex(content, &mut classes, &mut functions, &mut variables, &mut imports),
         "python" => parse_python_structure_regex(content, &mut classes, &mut functions, &mut variables, &mut imports),
-        "javascript" | "typescript" => parse_js_ts_structure_regex(content, &mut classes, &mut functions, &mut variables, &mut imports),
+        "javascript" | "typescript" | "tsx" => parse_js_ts_structure_regex(content, &mut classes, &mut functions, &mut variables, &mut imports),
         _ => {}
     }
     
@@ -2130,8 +6106,7 @@ fn parse_rust_structure_regex(
     imports: &mut Vec<ImportInfo>
 ) {
     // Parse structs
-    let struct_regex = Regex::new(r"(?m)^(?:pub\s+)?struct\s+(\w+)").unwrap();
-    for captures in struct_regex.captures_iter(content) {
+    for captures in RUST_STRUCT_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2139,14 +6114,13 @@ fn parse_rust_structure_regex(
             name,
             kind: "struct".to_string(),
             start_line: line,
-            end_line: line + 5, // Approximate
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "rust"),
             modifiers: Vec::new(),
         });
     }
     
     // Parse enums
-    let enum_regex = Regex::new(r"(?m)^(?:pub\s+)?enum\s+(\w+)").unwrap();
-    for captures in enum_regex.captures_iter(content) {
+    for captures in RUST_ENUM_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2154,14 +6128,13 @@ fn parse_rust_structure_regex(
             name,
             kind: "enum".to_string(),
             start_line: line,
-            end_line: line + 5, // Approximate
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "rust"),
             modifiers: Vec::new(),
         });
     }
     
     // Parse traits
-    let trait_regex = Regex::new(r"(?m)^(?:pub\s+)?trait\s+(\w+)").unwrap();
-    for captures in trait_regex.captures_iter(content) {
+    for captures in RUST_TRAIT_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2169,21 +6142,20 @@ fn parse_rust_structure_regex(
             name,
             kind: "trait".to_string(),
             start_line: line,
-            end_line: line + 5, // Approximate
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "rust"),
             modifiers: Vec::new(),
         });
     }
     
     // Parse functions
-    let function_regex = Regex::new(r"(?m)^(?:pub\s+)?fn\s+(\w+)").unwrap();
-    for captures in function_regex.captures_iter(content) {
+    for captures in RUST_FUNCTION_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
         functions.push(FunctionInfo {
             name,
             start_line: line,
-            end_line: line + 5, // Approximate
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "rust"),
             parameters: Vec::new(),
             return_type: None,
             modifiers: Vec::new(),
@@ -2191,8 +6163,7 @@ fn parse_rust_structure_regex(
     }
     
     // Parse variables
-    let variable_regex = Regex::new(r"(?m)^(?:let|const|static)\s+(?:mut\s+)?(\w+)").unwrap();
-    for captures in variable_regex.captures_iter(content) {
+    for captures in RUST_VARIABLE_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2205,8 +6176,7 @@ fn parse_rust_structure_regex(
     }
     
     // Parse imports
-    let import_regex = Regex::new(r"(?m)^use\s+([^;]+);").unwrap();
-    for captures in import_regex.captures_iter(content) {
+    for captures in RUST_IMPORT_REGEX.captures_iter(content) {
         let path = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2226,8 +6196,7 @@ fn parse_kotlin_structure_regex(
     imports: &mut Vec<ImportInfo>
 ) {
     // Parse classes
-    let class_regex = Regex::new(r"(?m)^(?:(?:public|private|protected|internal)\s+)?(?:abstract\s+)?class\s+(\w+)").unwrap();
-    for captures in class_regex.captures_iter(content) {
+    for captures in KOTLIN_CLASS_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2235,14 +6204,13 @@ fn parse_kotlin_structure_regex(
             name,
             kind: "class".to_string(),
             start_line: line,
-            end_line: line + 10, // Approximate
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "kotlin"),
             modifiers: Vec::new(),
         });
     }
     
     // Parse interfaces
-    let interface_regex = Regex::new(r"(?m)^(?:(?:public|private|protected|internal)\s+)?interface\s+(\w+)").unwrap();
-    for captures in interface_regex.captures_iter(content) {
+    for captures in KOTLIN_INTERFACE_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2250,21 +6218,20 @@ fn parse_kotlin_structure_regex(
             name,
             kind: "interface".to_string(),
             start_line: line,
-            end_line: line + 5, // Approximate
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "kotlin"),
             modifiers: Vec::new(),
         });
     }
     
     // Parse functions
-    let function_regex = Regex::new(r"(?m)^(?:(?:public|private|protected|internal)\s+)?(?:fun\s+)(\w+)").unwrap();
-    for captures in function_regex.captures_iter(content) {
+    for captures in KOTLIN_FUNCTION_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
         functions.push(FunctionInfo {
             name,
             start_line: line,
-            end_line: line + 5, // Approximate
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "kotlin"),
             parameters: Vec::new(),
             return_type: None,
             modifiers: Vec::new(),
@@ -2272,8 +6239,7 @@ fn parse_kotlin_structure_regex(
     }
     
     // Parse variables
-    let variable_regex = Regex::new(r"(?m)^(?:(?:public|private|protected|internal)\s+)?(?:val|var)\s+(\w+)").unwrap();
-    for captures in variable_regex.captures_iter(content) {
+    for captures in KOTLIN_VARIABLE_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2286,8 +6252,7 @@ fn parse_kotlin_structure_regex(
     }
     
     // Parse imports
-    let import_regex = Regex::new(r"(?m)^import\s+([^;]+)").unwrap();
-    for captures in import_regex.captures_iter(content) {
+    for captures in KOTLIN_IMPORT_REGEX.captures_iter(content) {
         let path = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2307,8 +6272,7 @@ fn parse_java_structure_regex(
     imports: &mut Vec<ImportInfo>
 ) {
     // Parse classes
-    let class_regex = Regex::new(r"(?m)^(?:(?:public|private|protected)\s+)?(?:abstract\s+)?class\s+(\w+)").unwrap();
-    for captures in class_regex.captures_iter(content) {
+    for captures in JAVA_CLASS_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2316,14 +6280,13 @@ fn parse_java_structure_regex(
             name,
             kind: "class".to_string(),
             start_line: line,
-            end_line: line + 10, // Approximate
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "java"),
             modifiers: Vec::new(),
         });
     }
     
     // Parse interfaces
-    let interface_regex = Regex::new(r"(?m)^(?:(?:public|private|protected)\s+)?interface\s+(\w+)").unwrap();
-    for captures in interface_regex.captures_iter(content) {
+    for captures in JAVA_INTERFACE_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2331,21 +6294,24 @@ fn parse_java_structure_regex(
             name,
             kind: "interface".to_string(),
             start_line: line,
-            end_line: line + 5, // Approximate
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "java"),
             modifiers: Vec::new(),
         });
     }
     
     // Parse methods
-    let method_regex = Regex::new(r"(?m)^(?:(?:public|private|protected)\s+)?(?:static\s+)?(?:final\s+)?(?:[\w<>[\],\s]+)\s+(\w+)\s*\(").unwrap();
-    for captures in method_regex.captures_iter(content) {
+    for captures in JAVA_METHOD_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
-        let line = get_line_number(content, captures.get(0).unwrap().start());
+        
+        // The return type pattern also matches line breaks, so start from the first non-blank character
+        let declaration = captures.get(0).unwrap();
+        let leading_whitespace = declaration.as_str().len() - declaration.as_str().trim_start().len();
+        let line = get_line_number(content, declaration.start() + leading_whitespace);
         
         functions.push(FunctionInfo {
             name,
             start_line: line,
-            end_line: line + 5, // Approximate
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "java"),
             parameters: Vec::new(),
             return_type: None,
             modifiers: Vec::new(),
@@ -2353,8 +6319,7 @@ fn parse_java_structure_regex(
     }
     
     // Parse fields
-    let field_regex = Regex::new(r"(?m)^(?:(?:public|private|protected)\s+)?(?:static\s+)?(?:final\s+)?(?:[\w<>[\],\s]+)\s+(\w+)\s*=").unwrap();
-    for captures in field_regex.captures_iter(content) {
+    for captures in JAVA_FIELD_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2367,8 +6332,7 @@ fn parse_java_structure_regex(
     }
     
     // Parse imports
-    let import_regex = Regex::new(r"(?m)^import\s+([^;]+);").unwrap();
-    for captures in import_regex.captures_iter(content) {
+    for captures in JAVA_IMPORT_REGEX.captures_iter(content) {
         let path = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2388,8 +6352,7 @@ fn parse_python_structure_regex(
     imports: &mut Vec<ImportInfo>
 ) {
     // Parse classes
-    let class_regex = Regex::new(r"(?m)^class\s+(\w+)").unwrap();
-    for captures in class_regex.captures_iter(content) {
+    for captures in PYTHON_CLASS_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2397,21 +6360,20 @@ fn parse_python_structure_regex(
             name,
             kind: "class".to_string(),
             start_line: line,
-            end_line: line + 10, // Approximate
+            end_line: find_indented_block_end_line(content, captures.get(0).unwrap().start()),
             modifiers: Vec::new(),
         });
     }
     
     // Parse functions
-    let function_regex = Regex::new(r"(?m)^def\s+(\w+)").unwrap();
-    for captures in function_regex.captures_iter(content) {
+    for captures in PYTHON_FUNCTION_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
         functions.push(FunctionInfo {
             name,
             start_line: line,
-            end_line: line + 5, // Approximate
+            end_line: find_indented_block_end_line(content, captures.get(0).unwrap().start()),
             parameters: Vec::new(),
             return_type: None,
             modifiers: Vec::new(),
@@ -2419,8 +6381,7 @@ fn parse_python_structure_regex(
     }
     
     // Parse variables
-    let variable_regex = Regex::new(r"(?m)^(\w+)\s*=").unwrap();
-    for captures in variable_regex.captures_iter(content) {
+    for captures in PYTHON_VARIABLE_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2433,8 +6394,7 @@ fn parse_python_structure_regex(
     }
     
     // Parse imports
-    let import_regex = Regex::new(r"(?m)^(?:import|from)\s+([^\n]+)").unwrap();
-    for captures in import_regex.captures_iter(content) {
+    for captures in PYTHON_IMPORT_REGEX.captures_iter(content) {
         let path = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2454,8 +6414,7 @@ fn parse_js_ts_structure_regex(
     imports: &mut Vec<ImportInfo>
 ) {
     // Parse classes
-    let class_regex = Regex::new(r"(?m)^(?:export\s+)?class\s+(\w+)").unwrap();
-    for captures in class_regex.captures_iter(content) {
+    for captures in JS_CLASS_REGEX.captures_iter(content) {
         let name = captures.get(1).unwrap().as_str().to_string();
         let line = get_line_number(content, captures.get(0).unwrap().start());
         
@@ -2463,52 +6422,213 @@ fn parse_js_ts_structure_regex(
             name,
             kind: "class".to_string(),
             start_line: line,
-            end_line: line + 10, // Approximate
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "javascript"),
+            modifiers: Vec::new(),
+        });
+    }
+    
+    // Parse functions
+    for captures in JS_FUNCTION_REGEX.captures_iter(content) {
+        let name = captures.get(1).unwrap().as_str().to_string();
+        let line = get_line_number(content, captures.get(0).unwrap().start());
+        
+        functions.push(FunctionInfo {
+            name,
+            start_line: line,
+            end_line: find_block_end_line(content, captures.get(1).unwrap().start(), "javascript"),
+            parameters: Vec::new(),
+            return_type: None,
+            modifiers: Vec::new(),
+        });
+    }
+    
+    // Parse variables
+    for captures in JS_VARIABLE_REGEX.captures_iter(content) {
+        let name = captures.get(1).unwrap().as_str().to_string();
+        let line = get_line_number(content, captures.get(0).unwrap().start());
+        
+        variables.push(VariableInfo {
+            name,
+            type_: None,
+            line,
             modifiers: Vec::new(),
         });
     }
     
-    // Parse functions
-    let function_regex = Regex::new(r"(?m)^(?:export\s+)?function\s+(\w+)").unwrap();
-    for captures in function_regex.captures_iter(content) {
-        let name = captures.get(1).unwrap().as_str().to_string();
-        let line = get_line_number(content, captures.get(0).unwrap().start());
-        
-        functions.push(FunctionInfo {
-            name,
-            start_line: line,
-            end_line: line + 5, // Approximate
-            parameters: Vec::new(),
-            return_type: None,
-            modifiers: Vec::new(),
-        });
+    // Parse imports
+    for captures in JS_IMPORT_REGEX.captures_iter(content) {
+        let path = captures.get(1).unwrap().as_str().to_string();
+        let line = get_line_number(content, captures.get(0).unwrap().start());
+        
+        imports.push(ImportInfo {
+            path,
+            line,
+        });
+    }
+}
+
+// Last line of a declaration in a brace language, scanning from its name: the line of the brace
+// closing its body, the line of a `;` ending a bodyless declaration, or otherwise the last line
+// before the next line at the declaration's indentation
+fn find_block_end_line(content: &str, start: usize, language_id: &str) -> usize {
+    let bytes = content.as_bytes();
+    let single_quote_strings = matches!(language_id, "javascript" | "typescript" | "tsx");
+    
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let indent = content[line_start..start].len() - content[line_start..start].trim_start().len();
+    
+    let mut line = get_line_number(content, start);
+    let mut last_code_line = line;
+    let mut paren_depth = 0i32;
+    let mut brace_depth = 0i32;
+    let mut current_line_start = line_start;
+    let mut at_line_start = false;
+    let mut i = start;
+    
+    while i < bytes.len() {
+        let c = bytes[i];
+        
+        if c == b'\n' {
+            line += 1;
+            i += 1;
+            current_line_start = i;
+            at_line_start = true;
+            continue;
+        }
+        
+        if at_line_start {
+            if c == b' ' || c == b'\t' || c == b'\r' {
+                i += 1;
+                continue;
+            }
+            at_line_start = false;
+            
+            // A declaration without a body ends where the next one at its level begins
+            let continues_header = matches!(c, b'{' | b'(' | b')' | b':' | b'.' | b'=' | b'-' | b',' | b'<' | b'>' | b'/')
+                || content[i..].starts_with("where")
+                || content[i..].starts_with("implements")
+                || content[i..].starts_with("extends")
+                || content[i..].starts_with("throws");
+            if brace_depth == 0 && paren_depth == 0 && i - current_line_start <= indent && !continues_header {
+                return last_code_line;
+            }
+        }
+        
+        // Skip comments and literals so braces inside them are not counted
+        if let Some(end) = skip_comment_or_literal(bytes, i, single_quote_strings) {
+            line += bytes[i..end].iter().filter(|&&b| b == b'\n').count();
+            if let Some(newline) = bytes[i..end].iter().rposition(|&b| b == b'\n') {
+                current_line_start = i + newline + 1;
+            }
+            if !(bytes[i] == b'/' && i + 1 < bytes.len() && (bytes[i + 1] == b'/' || bytes[i + 1] == b'*')) {
+                last_code_line = line;
+            }
+            i = end;
+            continue;
+        }
+        
+        match c {
+            b'(' | b'[' => paren_depth += 1,
+            b')' | b']' => paren_depth -= 1,
+            b'{' => brace_depth += 1,
+            b'}' => {
+                brace_depth -= 1;
+                // Braces inside the parameter list, e.g. destructuring, don't close the body
+                if brace_depth <= 0 && paren_depth <= 0 {
+                    return line;
+                }
+            }
+            b';' if brace_depth == 0 && paren_depth <= 0 => return line,
+            _ => {}
+        }
+        
+        if !c.is_ascii_whitespace() {
+            last_code_line = line;
+        }
+        i += 1;
+    }
+    
+    last_code_line
+}
+
+// End of the comment or literal starting at `i`, if one starts there
+fn skip_comment_or_literal(bytes: &[u8], i: usize, single_quote_strings: bool) -> Option<usize> {
+    let at = |offset: usize| bytes.get(i + offset).copied();
+    let find_from = |from: usize, needle: &[u8]| {
+        (from..bytes.len()).find(|&j| bytes[j..].starts_with(needle)).map_or(bytes.len(), |j| j + needle.len())
+    };
+    let find_quote = |from: usize, quote: u8| {
+        let mut j = from;
+        while j < bytes.len() {
+            if bytes[j] == b'\\' {
+                j += 2;
+            } else if bytes[j] == quote {
+                return j + 1;
+            } else {
+                j += 1;
+            }
+        }
+        bytes.len()
+    };
+    
+    match bytes[i] {
+        b'/' if at(1) == Some(b'/') => Some((i..bytes.len()).find(|&j| bytes[j] == b'\n').unwrap_or(bytes.len())),
+        b'/' if at(1) == Some(b'*') => Some(find_from(i + 2, b"*/")),
+        b'"' if bytes[i..].starts_with(b"\"\"\"") => Some(find_from(i + 3, b"\"\"\"")),
+        b'"' => Some(find_quote(i + 1, b'"')),
+        b'`' if single_quote_strings => Some(find_quote(i + 1, b'`')),
+        b'\'' if single_quote_strings => Some(find_quote(i + 1, b'\'')),
+        // Character literals, but not Rust lifetimes
+        b'\'' if at(1) == Some(b'\\') => Some(find_quote(i + 2, b'\'')),
+        b'\'' if at(2) == Some(b'\'') => Some(i + 3),
+        // Rust raw strings
+        b'r' if i == 0 || !(bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_') => {
+            let hashes = bytes[i + 1..].iter().take_while(|&&b| b == b'#').count();
+            if at(1 + hashes) != Some(b'"') {
+                return None;
+            }
+            let mut terminator = vec![b'"'];
+            terminator.extend(std::iter::repeat(b'#').take(hashes));
+            Some(find_from(i + 2 + hashes, &terminator))
+        }
+        _ => None,
     }
+}
+
+// Last line of a Python block: the last non-blank line indented deeper than its header
+fn find_indented_block_end_line(content: &str, start: usize) -> usize {
+    let lines: Vec<&str> = content.split('\n').collect();
+    let start_line = get_line_number(content, start);
+    let indentation = |line: &str| line.len() - line.trim_start().len();
+    let indent = indentation(lines[start_line]);
     
-    // Parse variables
-    let variable_regex = Regex::new(r"(?m)^(?:export\s+)?(?:const|let|var)\s+(\w+)").unwrap();
-    for captures in variable_regex.captures_iter(content) {
-        let name = captures.get(1).unwrap().as_str().to_string();
-        let line = get_line_number(content, captures.get(0).unwrap().start());
-        
-        variables.push(VariableInfo {
-            name,
-            type_: None,
-            line,
-            modifiers: Vec::new(),
-        });
+    // The header may continue over several lines inside its parentheses
+    let mut header_end = start_line;
+    let mut paren_depth = 0i32;
+    for (index, line) in lines.iter().enumerate().skip(start_line) {
+        let code = line.split('#').next().unwrap_or("");
+        paren_depth += code.matches(|c| c == '(' || c == '[').count() as i32;
+        paren_depth -= code.matches(|c| c == ')' || c == ']').count() as i32;
+        header_end = index;
+        if paren_depth <= 0 {
+            break;
+        }
     }
     
-    // Parse imports
-    let import_regex = Regex::new(r"(?m)^import\s+([^\n]+)").unwrap();
-    for captures in import_regex.captures_iter(content) {
-        let path = captures.get(1).unwrap().as_str().to_string();
-        let line = get_line_number(content, captures.get(0).unwrap().start());
+    let mut end_line = header_end;
+    for (index, line) in lines.iter().enumerate().skip(header_end + 1) {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
         
-        imports.push(ImportInfo {
-            path,
-            line,
-        });
+        if indentation(line) <= indent {
+            break;
+        }
+        end_line = index;
     }
+    
+    end_line
 }
 
 // Get line number from position
@@ -2518,7 +6638,7 @@ fn get_line_number(content: &str, position: usize) -> usize {
 
 // Find references
 pub fn find_references(content: &str, position: usize, language_id: &str) -> Vec<Reference> {
-    let state = EDITOR_STATE.lock().unwrap();
+    let state = editor_state();
     
     if !state.initialized {
         return Vec::new();
@@ -2563,6 +6683,17 @@ fn find_references_with_tree_sitter(content: &str, position: usize, parser: &Par
     // Traverse the tree to find references
     find_references_in_tree(&mut cursor, content, &node_text, &mut references);
     
+    // Where scopes are resolved, keep only the identifiers bound to the same declaration as the one
+    // under the cursor, so shadowed names and unrelated locals of the same spelling are left out.
+    // Names without a local declaration are grouped together, as before
+    if matches!(language_config.id.as_str(), "rust" | "javascript" | "typescript" | "tsx") && is_reference_identifier(node.kind()) {
+        let mut resolver = SemanticTokenCollector::new(content, &language_config.id);
+        resolver.visit(tree.root_node());
+        
+        let target = resolver.bindings.get(&node.start_byte()).copied();
+        references.retain(|reference| resolver.bindings.get(&reference.start).copied() == target);
+    }
+    
     references
 }
 
@@ -2573,7 +6704,7 @@ fn find_references_in_tree(cursor: &mut TreeCursor, content: &str, target_text:
         let node = cursor.node();
         
         // Check if this node is a reference to the target
-        if node.kind() == "identifier" {
+        if is_reference_identifier(node.kind()) && !is_in_string_or_comment(&node) {
             let node_text = node.utf8_text(content.as_bytes()).unwrap_or("");
             
             if node_text == target_text {
@@ -2614,6 +6745,24 @@ fn find_references_in_tree(cursor: &mut TreeCursor, content: &str, target_text:
     }
 }
 
+// Whether an identifier sits inside a string or comment node, rather than an interpolation within one
+#[cfg(feature = "tree-sitter-support")]
+fn is_in_string_or_comment(node: &Node) -> bool {
+    let mut current = node.parent();
+    while let Some(parent) = current {
+        let kind = parent.kind();
+        if matches!(kind, "interpolation" | "template_substitution" | "interpolated_expression") {
+            return false;
+        }
+        if kind.contains("string") || kind.contains("comment") {
+            return true;
+        }
+        current = parent.parent();
+    }
+    
+    false
+}
+
 // Find references with regex
 fn find_references_with_regex(content: &str, position: usize, language_config: &LanguageConfig) -> Vec<Reference> {
     let mut references = Vec::new();
@@ -2625,12 +6774,18 @@ fn find_references_with_regex(content: &str, position: usize, language_config: &
         return references;
     }
     
-    // Find all occurrences of the word
+    // Find all occurrences of the word outside strings and comments
     let pattern = format!(r"\b{}\b", regex::escape(&word));
     let regex = Regex::new(&pattern).unwrap();
+    let spans = comment_and_string_spans(content, language_config);
     
     for mat in regex.find_iter(content) {
         let start = mat.start();
+        let span_index = spans.partition_point(|&(span_start, _)| span_start <= start);
+        if span_index > 0 && start < spans[span_index - 1].1 {
+            continue;
+        }
+        
         let end = mat.end();
         let line = get_line_number(content, start);
         let column = get_column_number(content, start);
@@ -2646,24 +6801,69 @@ fn find_references_with_regex(content: &str, position: usize, language_config: &
     references
 }
 
+// Byte ranges of comments and string literals, in order. Scanning left to right means a
+// comment marker inside a string (or a quote inside a comment) does not open a new span
+fn comment_and_string_spans(content: &str, language_config: &LanguageConfig) -> Vec<(usize, usize)> {
+    let bytes = content.as_bytes();
+    let line_comment = language_config.comment_line.as_deref().map(str::as_bytes);
+    let block_comment = language_config.comment_block_start.as_deref().map(str::as_bytes)
+        .zip(language_config.comment_block_end.as_deref().map(str::as_bytes));
+    let single_quote_strings = language_config.string_delimiters.iter().any(|delimiter| delimiter == "'");
+    
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        let end = if line_comment.map_or(false, |marker| rest.starts_with(marker)) {
+            (i..bytes.len()).find(|&j| bytes[j] == b'\n').unwrap_or(bytes.len())
+        } else if let Some((open, close)) = block_comment.filter(|(open, _)| rest.starts_with(open)) {
+            (i + open.len()..bytes.len())
+                .find(|&j| bytes[j..].starts_with(close))
+                .map_or(bytes.len(), |j| j + close.len())
+        } else if single_quote_strings && rest.starts_with(b"'''") {
+            (i + 3..bytes.len())
+                .find(|&j| bytes[j..].starts_with(b"'''"))
+                .map_or(bytes.len(), |j| j + 3)
+        } else if matches!(bytes[i], b'"' | b'\'' | b'`' | b'r') {
+            // Comment markers were handled above, so only literals are taken from here
+            match skip_comment_or_literal(bytes, i, single_quote_strings) {
+                Some(end) => end,
+                None => {
+                    i += 1;
+                    continue;
+                }
+            }
+        } else {
+            i += 1;
+            continue;
+        };
+        
+        spans.push((i, end));
+        i = end.max(i + 1);
+    }
+    
+    spans
+}
+
 // Get word at position
 fn get_word_at_position(content: &str, position: usize) -> String {
-    if position >= content.len() {
+    if position >= content.len() || !content.is_char_boundary(position) {
         return String::new();
     }
     
-    let mut start = position;
-    let mut end = position;
-    
     // Find start of word
-    while start > 0 && is_word_char(content.chars().nth(start - 1).unwrap()) {
-        start -= 1;
-    }
+    let start = content[..position]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_word_char(c))
+        .last()
+        .map_or(position, |(index, _)| index);
     
     // Find end of word
-    while end < content.len() && is_word_char(content.chars().nth(end).unwrap()) {
-        end += 1;
-    }
+    let end = content[position..]
+        .char_indices()
+        .find(|&(_, c)| !is_word_char(c))
+        .map_or(content.len(), |(index, _)| position + index);
     
     content[start..end].to_string()
 }
@@ -2677,4 +6877,664 @@ fn is_word_char(c: char) -> bool {
 fn get_column_number(content: &str, position: usize) -> usize {
     let line_start = content[..position].rfind('\n').map_or(0, |i| i + 1);
     position - line_start
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn poisoned_editor_state_is_recovered() {
+        initialize_editor().unwrap();
+        
+        // Panic while holding the lock, as a failing unwrap inside an editor call would
+        let _ = std::thread::spawn(|| {
+            let _state = editor_state();
+            panic!("editor call failed");
+        })
+        .join();
+        assert!(EDITOR_STATE.is_poisoned());
+        
+        assert!(initialize_editor().unwrap());
+        let highlights = highlight_syntax("fn main() {}", "rust").highlights;
+        assert!(highlights.iter().any(|h| h.start == 0 && h.end == 2 && h.type_ == "keyword"));
+    }
+    
+    // A Rust file of roughly 2000 lines
+    fn large_rust_source() -> String {
+        let block = "/* helper */\nfn add(left: u64, right: u64) -> u64 {\n    let message = \"adding\"; // trace\n    left + right\n}\n\n";
+        block.repeat(2000 / block.lines().count())
+    }
+    
+    // Rust language config with and without its precompiled highlight patterns
+    fn rust_configs() -> (LanguageConfig, LanguageConfig) {
+        initialize_editor().unwrap();
+        let precompiled = editor_state().languages.get("rust").unwrap().clone();
+        let mut uncompiled = precompiled.clone();
+        uncompiled.highlight_patterns = None;
+        (precompiled, uncompiled)
+    }
+    
+    fn spans(highlights: &[SyntaxHighlight]) -> Vec<(usize, usize, String)> {
+        highlights.iter().map(|h| (h.start, h.end, h.type_.clone())).collect()
+    }
+    
+    #[test]
+    fn precompiled_patterns_match_compiling_on_the_fly() {
+        let (precompiled, uncompiled) = rust_configs();
+        assert!(precompiled.highlight_patterns.is_some());
+        
+        let content = large_rust_source();
+        assert_eq!(
+            spans(&highlight_with_regex(&content, &precompiled, true)),
+            spans(&highlight_with_regex(&content, &uncompiled, true))
+        );
+    }
+    
+    #[test]
+    fn longest_operator_wins() {
+        let (precompiled, _) = rust_configs();
+        let highlights = highlight_with_regex("a >>= b", &precompiled, true);
+        
+        assert!(highlights.iter().any(|h| h.start == 2 && h.end == 5 && h.type_ == "operator"));
+        assert!(!highlights.iter().any(|h| h.start == 2 && h.end == 3 && h.type_ == "operator"));
+    }
+    
+    // Benchmark, run with `cargo test --release -- --ignored --nocapture highlight_benchmark`
+    #[test]
+    #[ignore]
+    fn highlight_benchmark() {
+        let (precompiled, uncompiled) = rust_configs();
+        let content = large_rust_source();
+        let iterations = 20;
+        
+        let time = |config: &LanguageConfig| {
+            let start = std::time::Instant::now();
+            for _ in 0..iterations {
+                highlight_with_regex(&content, config, true);
+            }
+            start.elapsed() / iterations
+        };
+        
+        let uncompiled_time = time(&uncompiled);
+        let precompiled_time = time(&precompiled);
+        println!(
+            "{} lines: {:?} per highlight compiling patterns per call, {:?} with precompiled patterns",
+            content.lines().count(),
+            uncompiled_time,
+            precompiled_time
+        );
+        assert!(precompiled_time < uncompiled_time);
+    }
+    
+    // Type of the highlight spanning exactly `text`'s first occurrence in `content`
+    fn highlight_type_of(highlights: &[SyntaxHighlight], content: &str, text: &str) -> Option<String> {
+        let start = content.find(text).unwrap();
+        highlights
+            .iter()
+            .find(|h| h.start == start && h.end == start + text.len())
+            .map(|h| h.type_.clone())
+    }
+    
+    #[test]
+    fn markdown_inline_syntax_is_highlighted() {
+        initialize_editor().unwrap();
+        let content = "# Title\n\n*italic* text\n**bold** text\nSee `code` and [a link](https://example.com).\n";
+        let highlights = highlight_syntax(content, "markdown").highlights;
+        
+        assert_eq!(highlight_type_of(&highlights, content, "# Title").as_deref(), Some("heading"));
+        assert_eq!(highlight_type_of(&highlights, content, "*italic*").as_deref(), Some("italic"));
+        assert_eq!(highlight_type_of(&highlights, content, "**bold**").as_deref(), Some("bold"));
+        assert_eq!(highlight_type_of(&highlights, content, "`code`").as_deref(), Some("code"));
+        assert_eq!(highlight_type_of(&highlights, content, "[a link](https://example.com)").as_deref(), Some("link"));
+    }
+    
+    #[test]
+    fn markdown_rust_fence_is_highlighted_as_rust() {
+        initialize_editor().unwrap();
+        let content = "Call fn here\n\n```rust\nfn main() {}\n```\n";
+        let highlights = highlight_syntax(content, "markdown").highlights;
+        
+        // The fence body is highlighted as Rust at its offset in the document
+        let fn_start = content.find("fn main").unwrap();
+        assert!(highlights.iter().any(|h| h.start == fn_start && h.end == fn_start + 2 && h.type_ == "keyword"));
+        
+        // Prose outside the fence isn't
+        let prose_fn = content.find("fn").unwrap();
+        assert!(!highlights.iter().any(|h| h.start == prose_fn && h.type_ == "keyword"));
+        
+        // The fence lines themselves are code
+        assert_eq!(highlight_type_of(&highlights, content, "```rust\n").as_deref(), Some("code"));
+    }
+    
+    // Structure of `content` from the regex parser, whatever features are enabled
+    fn regex_structure(content: &str, language_id: &str) -> CodeStructure {
+        initialize_editor().unwrap();
+        let language_config = editor_state().languages.get(language_id).unwrap().clone();
+        parse_structure_with_regex(content, &language_config)
+    }
+    
+    fn function_lines(structure: &CodeStructure, name: &str) -> (usize, usize) {
+        let function = structure.functions.iter().find(|function| function.name == name).unwrap();
+        (function.start_line, function.end_line)
+    }
+    
+    fn class_lines(structure: &CodeStructure, name: &str) -> (usize, usize) {
+        let class = structure.classes.iter().find(|class| class.name == name).unwrap();
+        (class.start_line, class.end_line)
+    }
+    
+    #[test]
+    fn one_line_function_ends_on_its_own_line() {
+        let content = "fn one() -> u32 { 1 }\n\nfn two() {\n    let s = \"}\";\n    s.len();\n}\n";
+        let structure = regex_structure(content, "rust");
+        
+        assert_eq!(function_lines(&structure, "one"), (0, 0));
+        assert_eq!(function_lines(&structure, "two"), (2, 5));
+    }
+    
+    #[test]
+    fn fifty_line_class_ends_at_its_closing_brace() {
+        let mut content = String::from("public class Big {\n");
+        for index in 0..48 {
+            content.push_str(&format!("    int field{};\n", index));
+        }
+        content.push_str("}\n\nclass After {\n}\n");
+        
+        let structure = regex_structure(&content, "java");
+        assert_eq!(class_lines(&structure, "Big"), (0, 49));
+        assert_eq!(class_lines(&structure, "After"), (51, 52));
+    }
+    
+    #[test]
+    fn fifty_line_python_class_ends_at_its_last_indented_line() {
+        let mut content = String::from("class Big:\n");
+        for index in 0..24 {
+            content.push_str(&format!("    def method{}(self):\n        return {}\n", index, index));
+        }
+        content.push_str("    # trailing comment\n    value = 1\n\ndef after():\n    pass\n");
+        
+        let structure = regex_structure(&content, "python");
+        assert_eq!(class_lines(&structure, "Big"), (0, 50));
+        assert_eq!(function_lines(&structure, "after"), (52, 53));
+    }
+    
+    #[test]
+    fn tsx_component_structure_is_parsed() {
+        initialize_editor().unwrap();
+        assert_eq!(detect_language("App.tsx", "").as_deref(), Some("tsx"));
+        assert_eq!(detect_language("app.ts", "").as_deref(), Some("typescript"));
+        
+        let content = concat!(
+            "import React from 'react';\n",
+            "\n",
+            "export function Greeting({ name }: { name: string }) {\n",
+            "  return <div className=\"greeting\">Hello {name}</div>;\n",
+            "}\n",
+            "\n",
+            "export class Counter extends React.Component<Props> {\n",
+            "  render() {\n",
+            "    return <button onClick={() => this.setState({})}>+</button>;\n",
+            "  }\n",
+            "}\n",
+        );
+        let structure = parse_code_structure(content, "tsx");
+        
+        assert_eq!(function_lines(&structure, "Greeting"), (2, 4));
+        assert_eq!(class_lines(&structure, "Counter"), (6, 10));
+    }
+    
+    #[test]
+    fn reindenting_two_lines_gives_two_edits() {
+        let original = "fn main() {\nlet a = 1;\n    let b = 2;\nlet c = 3;\n}\n";
+        let formatted = "fn main() {\n    let a = 1;\n    let b = 2;\n    let c = 3;\n}\n";
+        
+        let edits = diff_text_edits(original, formatted);
+        let edits: Vec<(usize, usize, &str)> = edits.iter().map(|e| (e.start_byte, e.end_byte, e.new_text.as_str())).collect();
+        assert_eq!(edits, vec![(12, 23, "    let a = 1;\n"), (38, 49, "    let c = 3;\n")]);
+        
+        assert_eq!(apply_edits(original, diff_text_edits(original, formatted)).unwrap(), formatted);
+    }
+    
+    #[test]
+    fn identical_documents_need_no_edits() {
+        let content = "a\nb\nc";
+        assert!(diff_text_edits(content, content).is_empty());
+    }
+    
+    #[test]
+    fn inserted_and_removed_lines_round_trip() {
+        let original = "one\ntwo\nthree\nfour\nfive";
+        let updated = "zero\none\nthree\nfour\n4.5\nfive\n";
+        
+        assert_eq!(apply_edits(original, diff_text_edits(original, updated)).unwrap(), updated);
+    }
+    
+    // Byte offsets of every occurrence of `word` in `content`
+    fn occurrences(content: &str, word: &str) -> Vec<usize> {
+        content.match_indices(word).map(|(start, _)| start).collect()
+    }
+    
+    fn reference_starts(references: &[Reference]) -> Vec<usize> {
+        references.iter().map(|reference| reference.start).collect()
+    }
+    
+    #[test]
+    fn references_skip_comments_and_strings() {
+        let content = "fn main() {\n    let data = load();\n    // data is loaded here\n    let s = \"data\";\n    use_it(data);\n}\n";
+        let data = occurrences(content, "data");
+        assert_eq!(data.len(), 4);
+        let expected = vec![data[0], data[3]];
+        
+        let (precompiled, _) = rust_configs();
+        assert_eq!(reference_starts(&find_references_with_regex(content, data[0], &precompiled)), expected);
+        assert_eq!(reference_starts(&find_references(content, data[0], "rust")), expected);
+    }
+    
+    #[test]
+    fn references_skip_block_comments_and_single_quoted_strings() {
+        initialize_editor().unwrap();
+        let content = "/* data */\nconst data = 1;\nconsole.log('data', data);\n";
+        let data = occurrences(content, "data");
+        
+        assert_eq!(reference_starts(&find_references(content, data[1], "javascript")), vec![data[1], data[3]]);
+    }
+    
+    #[cfg(feature = "tree-sitter-support")]
+    #[test]
+    fn references_resolve_to_the_binding_under_the_cursor() {
+        initialize_editor().unwrap();
+        let content = "fn a() {\n    let x = 1;\n    x + 1;\n}\n\nfn b(x: i32) -> i32 {\n    x * 2\n}\n";
+        let x = occurrences(content, "x");
+        assert_eq!(x.len(), 4);
+        
+        assert_eq!(reference_starts(&find_references(content, x[1], "rust")), vec![x[0], x[1]]);
+        assert_eq!(reference_starts(&find_references(content, x[2], "rust")), vec![x[2], x[3]]);
+    }
+    
+    #[cfg(feature = "tree-sitter-support")]
+    #[test]
+    fn javascript_references_resolve_to_the_binding_under_the_cursor() {
+        initialize_editor().unwrap();
+        let content = "function a() {\n  let x = 1;\n  return x;\n}\nfunction b(x) {\n  return x * 2;\n}\n";
+        let x = occurrences(content, "x");
+        assert_eq!(x.len(), 4);
+        
+        assert_eq!(reference_starts(&find_references(content, x[0], "javascript")), vec![x[0], x[1]]);
+        assert_eq!(reference_starts(&find_references(content, x[3], "javascript")), vec![x[2], x[3]]);
+    }
+    
+    #[cfg(feature = "tree-sitter-support")]
+    #[test]
+    fn tsx_references_resolve_to_the_binding_under_the_cursor() {
+        initialize_editor().unwrap();
+        let content = "function A() {\n  const x = 1;\n  return <div>{x}</div>;\n}\nfunction B(x: number) {\n  return <span>{x}</span>;\n}\n";
+        let x = occurrences(content, "x");
+        assert_eq!(x.len(), 4);
+        
+        assert_eq!(reference_starts(&find_references(content, x[0], "tsx")), vec![x[0], x[1]]);
+        assert_eq!(reference_starts(&find_references(content, x[3], "tsx")), vec![x[2], x[3]]);
+    }
+    
+    #[test]
+    fn regex_references_match_every_use_of_the_name() {
+        initialize_editor().unwrap();
+        let content = "function A() {\n  const x = 1;\n  return <div title=\"x\">{x}</div>;\n}\nfunction B(x: number) {\n  return x * 2;\n}\n";
+        let x = occurrences(content, "x");
+        assert_eq!(x.len(), 5);
+        
+        // Without a syntax tree there are no scopes, so every use outside strings is grouped by name
+        let tsx = editor_state().languages.get("tsx").unwrap().clone();
+        assert_eq!(reference_starts(&find_references_with_regex(content, x[0], &tsx)), vec![x[0], x[2], x[3], x[4]]);
+    }
+    
+    // Indentation for a new line with the cursor at `|` in `content`
+    fn indent_at_cursor(content: &str, language_id: &str, indent_unit: &str) -> String {
+        let position = content.find('|').unwrap();
+        let content = content.replacen('|', "", 1);
+        indent_for_new_line(&content, position, language_id, indent_unit)
+    }
+    
+    #[test]
+    fn new_line_after_rust_brace_is_indented() {
+        assert_eq!(indent_at_cursor("fn main() {|", "rust", "    "), "    ");
+        assert_eq!(indent_at_cursor("fn main() {|}", "rust", "    "), "    ");
+        assert_eq!(indent_at_cursor("fn main() {\n    if ready {|\n    }\n}", "rust", "    "), "        ");
+        assert_eq!(indent_at_cursor("fn main() {|", "rust", "\t"), "\t");
+    }
+    
+    #[test]
+    fn new_line_keeps_indentation_otherwise() {
+        assert_eq!(indent_at_cursor("fn main() {\n    let x = 1;|\n}", "rust", "    "), "    ");
+        // A brace inside a string doesn't open a block
+        assert_eq!(indent_at_cursor("fn main() {\n    let s = \"{\";|\n}", "rust", "    "), "    ");
+    }
+    
+    #[test]
+    fn new_line_before_closing_brace_is_dedented() {
+        assert_eq!(indent_at_cursor("fn main() {\n    let x = 1;\n    |}", "rust", "    "), "");
+        assert_eq!(indent_at_cursor("\tif ready {\n\t\tgo();\n\t\t|}", "javascript", "\t"), "\t");
+    }
+    
+    #[test]
+    fn tab_indented_python_is_reindented_with_spaces() {
+        let content = "def f(x):\n\tif x:\n\t\treturn 1\n\treturn 2\n";
+        
+        assert_eq!(reindent_python(content, "    ").unwrap(), "def f(x):\n    if x:\n        return 1\n    return 2\n");
+    }
+    
+    #[test]
+    fn space_indented_python_keeps_continuation_lines_aligned() {
+        let content = "class A:\n  def f(self):\n    return [1,\n            2]\n";
+        
+        assert_eq!(reindent_python(content, "    ").unwrap(), "class A:\n    def f(self):\n        return [1,\n                2]\n");
+        assert_eq!(reindent_python(content, "").unwrap(), content);
+    }
+    
+    #[test]
+    fn mixed_tabs_and_spaces_in_python_are_reported() {
+        let content = "if x:\n\ty = 1\n        z = 2\n";
+        
+        let diagnostic = reindent_python(content, "    ").unwrap_err();
+        assert_eq!(diagnostic.message, "Inconsistent use of tabs and spaces in indentation");
+        assert_eq!(diagnostic.line, 3);
+    }
+    
+    #[test]
+    fn new_line_after_python_colon_is_indented() {
+        assert_eq!(indent_at_cursor("def main():|", "python", "    "), "    ");
+        assert_eq!(indent_at_cursor("def main():\n    if ready:  # note|", "python", "    "), "        ");
+        assert_eq!(indent_at_cursor("def main():\n    return 1|", "python", "    "), "");
+        assert_eq!(indent_at_cursor("def main():\n    x = \"a:\"|", "python", "    "), "    ");
+    }
+    
+    #[test]
+    fn minified_json_expands_to_indented_output() {
+        let minified = r#"{"name":"app","tags":["a","b"],"build":{"debug":true,"level":2}}"#;
+        let expected = concat!(
+            "{\n",
+            "  \"name\": \"app\",\n",
+            "  \"tags\": [\n",
+            "    \"a\",\n",
+            "    \"b\"\n",
+            "  ],\n",
+            "  \"build\": {\n",
+            "    \"debug\": true,\n",
+            "    \"level\": 2\n",
+            "  }\n",
+            "}",
+        );
+        
+        assert_eq!(format_json(minified, "").unwrap(), expected);
+        assert_eq!(format_json(&format!("{}\n", minified), "").unwrap(), format!("{}\n", expected));
+        assert_eq!(format_json("[1,[2]]", "\t").unwrap(), "[\n\t1,\n\t[\n\t\t2\n\t]\n]");
+    }
+    
+    #[test]
+    fn json_numbers_escapes_and_repeated_keys_are_kept() {
+        let content = r#"{"big":12345678901234567890123,"float":1.0,"exp":1E400,"name":"caf\u00e9","big":2}"#;
+        let expected = concat!(
+            "{\n",
+            "  \"big\": 12345678901234567890123,\n",
+            "  \"float\": 1.0,\n",
+            "  \"exp\": 1E400,\n",
+            "  \"name\": \"caf\\u00e9\",\n",
+            "  \"big\": 2\n",
+            "}",
+        );
+        
+        assert_eq!(format_json(content, "").unwrap(), expected);
+        assert_eq!(format_json("{ \"a\": [ ], \"b\": {}, \"c\": \"[,]\" }", "").unwrap(), "{\n  \"a\": [],\n  \"b\": {},\n  \"c\": \"[,]\"\n}");
+    }
+    
+    #[test]
+    fn invalid_json_is_returned_verbatim() {
+        initialize_editor().unwrap();
+        
+        let invalid = "{\n  \"name\": \"app\",\n  \"tags\": [\"a\", \n";
+        let diagnostic = format_json(invalid, "").unwrap_err();
+        assert!(diagnostic.message.starts_with("Invalid JSON"));
+        assert_eq!(diagnostic.line, 4);
+        
+        let (formatted, diagnostic) = format_code_with_diagnostic(invalid, "json");
+        assert_eq!(formatted, invalid);
+        assert!(diagnostic.is_some());
+    }
+    
+    #[test]
+    fn nested_divs_get_one_level_each() {
+        let content = "<div><div class=\"a\"><div><p>Hi <b>there</b></p><span>x</span></div></div></div>\n";
+        let expected = concat!(
+            "<div>\n",
+            "  <div class=\"a\">\n",
+            "    <div>\n",
+            "      <p>Hi <b>there</b></p>\n",
+            "      <span>x</span>\n",
+            "    </div>\n",
+            "  </div>\n",
+            "</div>\n",
+        );
+        
+        assert_eq!(format_markup(content, true, "  ").unwrap(), expected);
+        assert_eq!(format_markup(expected, true, "").unwrap(), expected);
+    }
+    
+    #[test]
+    fn verbatim_elements_only_apply_to_html() {
+        let content = "<style name=\"AppTheme\"><item name=\"color\">#000</item></style>";
+        
+        let html = parse_markup(content, true).unwrap();
+        assert!(matches!(html.as_slice(), [MarkupNode::Raw(raw)] if raw == content));
+        
+        let xml = parse_markup(content, false).unwrap();
+        match xml.as_slice() {
+            [MarkupNode::Element { name, children, .. }] => {
+                assert_eq!(name, "style");
+                assert!(matches!(children.as_slice(), [MarkupNode::Element { name, .. }] if name == "item"));
+            }
+            _ => panic!("expected a single <style> element"),
+        }
+    }
+    
+    #[test]
+    fn compressed_css_rule_expands() {
+        let compressed = "a,b{color:red;margin:0 auto}@media (max-width:600px){.x{display:none}}";
+        let expected = concat!(
+            "a,\n",
+            "b {\n",
+            "  color: red;\n",
+            "  margin: 0 auto;\n",
+            "}\n",
+            "\n",
+            "@media (max-width:600px) {\n",
+            "  .x {\n",
+            "    display: none;\n",
+            "  }\n",
+            "}",
+        );
+        
+        assert_eq!(format_css(compressed, "  ", false).unwrap(), expected);
+        assert_eq!(format_css(expected, "  ", false).unwrap(), expected);
+    }
+    
+    #[test]
+    fn css_property_sort_is_stable() {
+        let content = ".x{z-index:1;color:red;/* fallback */background:blue;color:blue}";
+        let expected = concat!(
+            ".x {\n",
+            "  /* fallback */\n",
+            "  background: blue;\n",
+            "  color: red;\n",
+            "  color: blue;\n",
+            "  z-index: 1;\n",
+            "}",
+        );
+        
+        let sorted = format_css(content, "  ", true).unwrap();
+        assert_eq!(sorted, expected);
+        assert_eq!(format_css(&sorted, "  ", true).unwrap(), sorted);
+    }
+    
+    #[test]
+    fn trailing_whitespace_inside_multi_line_strings_is_kept() {
+        let toml = "a = 1  \nb = \"\"\"\nkept  \n\"\"\"  \n";
+        assert_eq!(normalize_whitespace(toml, "toml", true, true, true), "a = 1\nb = \"\"\"\nkept  \n\"\"\"\n");
+        
+        let python = "def f():  \n    return '''\n    kept\t\n    '''\n";
+        assert_eq!(normalize_whitespace(python, "python", true, true, true), "def f():\n    return '''\n    kept\t\n    '''\n");
+        
+        assert_eq!(normalize_whitespace("x = 1  \n", "rust", true, true, true), "x = 1\n");
+    }
+    
+    #[test]
+    fn markdown_whitespace_is_left_alone() {
+        let markdown = "line break  \nnext\n\n\n";
+        assert_eq!(normalize_whitespace(markdown, "markdown", true, true, true), markdown);
+    }
+    
+    // Index of a byte offset in a Java String of the same text
+    fn java_index(content: &str, byte_offset: usize) -> usize {
+        content[..byte_offset].encode_utf16().count()
+    }
+    
+    #[test]
+    fn utf16_offsets_count_surrogate_pairs() {
+        let content = "a😀é b";
+        let offsets = Utf16Offsets::new(content);
+        
+        for (byte_offset, _) in content.char_indices() {
+            assert_eq!(offsets.to_utf16(byte_offset), java_index(content, byte_offset));
+            assert_eq!(offsets.to_byte(java_index(content, byte_offset)), byte_offset);
+        }
+        assert_eq!(offsets.to_utf16(content.len()), 6);
+        
+        // Inside the surrogate pair, or inside the emoji's bytes, counts from its start
+        assert_eq!(offsets.to_byte(2), 1);
+        assert_eq!(offsets.to_utf16(3), 1);
+    }
+    
+    #[test]
+    fn utf16_highlights_and_references_line_up_after_an_emoji() {
+        initialize_editor().unwrap();
+        let content = "fn main() {\n    let s = \"😀\"; let value = 1;\n    use_it(value);\n}\n";
+        
+        let highlights = highlight_syntax_utf16(content, "rust").highlights;
+        let second_let = content.rfind("let").unwrap();
+        assert!(highlights.iter().any(|h| h.start == java_index(content, second_let) && h.end - h.start == 3));
+        
+        let value = occurrences(content, "value");
+        let references = find_references_utf16(content, java_index(content, value[1]), "rust");
+        let expected: Vec<usize> = value.iter().map(|&start| java_index(content, start)).collect();
+        assert_eq!(reference_starts(&references), expected);
+        
+        let line_start = content[..value[0]].rfind('\n').unwrap() + 1;
+        assert_eq!(references[0].column, java_index(content, value[0]) - java_index(content, line_start));
+    }
+    
+    #[test]
+    fn nested_editor_ops_restore_the_outer_op() {
+        let outer = EditorOp::begin("nested_op_outer");
+        {
+            let _inner = EditorOp::begin("nested_op_inner");
+            assert!(cancel_editor_op("nested_op_inner"));
+            assert!(is_cancelled());
+        }
+        
+        // The inner cancellation must not leak into the outer operation, which is still cancellable
+        assert!(!is_cancelled());
+        assert!(cancel_editor_op("nested_op_outer"));
+        assert!(is_cancelled());
+        
+        drop(outer);
+        assert!(!is_cancelled());
+        assert!(!cancel_editor_op("nested_op_outer"));
+    }
+    
+    #[test]
+    fn documents_over_the_highlight_limit_are_degraded() {
+        initialize_editor().unwrap();
+        let content = "fn main() { let value = Some(parse(1)); }";
+        
+        // The limit is only lowered while the state stays locked, so other tests never see it
+        let mut state = editor_state();
+        let full = document_highlights(&state, content, "rust");
+        state.max_highlight_bytes = 16;
+        let cheap = document_highlights(&state, content, "rust");
+        state.max_highlight_bytes = DEFAULT_MAX_HIGHLIGHT_BYTES;
+        drop(state);
+        
+        assert!(!full.degraded);
+        assert!(cheap.degraded);
+        assert!(cheap.highlights.iter().any(|h| h.start == 0 && h.end == 2 && h.type_ == "keyword"));
+        let parse_start = content.find("parse").unwrap();
+        assert!(!cheap.highlights.iter().any(|h| h.start == parse_start));
+        assert!(!highlight_syntax(content, "rust").degraded);
+    }
+    
+    #[test]
+    fn cancellable_operations_share_a_result_shape() {
+        initialize_editor().unwrap();
+        let highlights = serde_json::to_value(highlight_syntax_for_document("shape_doc", "fn main() {}", "rust")).unwrap();
+        let structure = serde_json::to_value(parse_code_structure_for_document("shape_doc", "fn main() {}", "rust")).unwrap();
+        
+        for value in [&highlights, &structure] {
+            assert_eq!(value["cancelled"], serde_json::json!(false));
+            assert!(value["result"].is_object());
+        }
+        assert_eq!(highlights["result"]["degraded"], serde_json::json!(false));
+        assert!(highlights["result"]["highlights"].is_array());
+    }
+    
+    // Plugin hook answering onCompletionRequest for the "plugin-completion-test" language only,
+    // so completions in other tests are unaffected
+    struct FakeCompletionHook;
+    
+    impl pluginsystem::PluginHook for FakeCompletionHook {
+        fn execute(&self, payload: &HookPayload) -> Result<String> {
+            match payload {
+                HookPayload::CompletionRequest(request) if request.language == "plugin-completion-test" => {
+                    Ok(r#"[{"label": "x", "kind": "function", "insert_text": "x()"}, {"label": "bad", "kind": "unknown", "insert_text": ""}]"#.to_string())
+                }
+                _ => Ok("[]".to_string()),
+            }
+        }
+    }
+    
+    #[test]
+    fn plugin_completions_are_merged() {
+        initialize_editor().unwrap();
+        let dir = pluginsystem::load_test_plugin("com.example.completion-test", &["onCompletionRequest"], Arc::new(FakeCompletionHook));
+        
+        let list = get_completion_list("x", 1, "plugin-completion-test");
+        let plugin_items: Vec<&CompletionItem> = list.items.iter().filter(|item| item.label == "x").collect();
+        
+        pluginsystem::unload_plugin("com.example.completion-test").unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        
+        assert_eq!(plugin_items.len(), 1);
+        assert_eq!(plugin_items[0].kind, CompletionKind::Function);
+        assert_eq!(plugin_items[0].insert_text, "x()");
+        // The malformed item is dropped without losing the good one
+        assert!(!list.items.iter().any(|item| item.label == "bad"));
+    }
+    
+    #[test]
+    fn files_under_an_unresolved_root_are_reindexed() {
+        initialize_editor().unwrap();
+        let dir = std::env::temp_dir().join(format!("anyoneide-project-index-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "fn first() {}\n").unwrap();
+        
+        // `src/..` is the project directory only once the `..` is resolved
+        let index = index_project(&dir.join("src").join("..").to_string_lossy(), Vec::new()).unwrap();
+        assert_eq!(Path::new(&index.root), fs::canonicalize(&dir).unwrap());
+        
+        // Watches report the resolved path of the file
+        fs::write(dir.join("src/lib.rs"), "fn first() {}\nfn second() {}\n").unwrap();
+        let watched = fs::canonicalize(dir.join("src/lib.rs")).unwrap();
+        assert_eq!(reindex_file(&watched.to_string_lossy()).unwrap(), 2);
+        assert_eq!(reindex_file("src/lib.rs").unwrap(), 2);
+        
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,8 @@
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 use serde::{Serialize, Deserialize};
 use anyhow::{Result, anyhow};
 use lazy_static::lazy_static;
@@ -55,20 +58,76 @@ pub struct ExtensionResult {
     pub success: bool,
     pub data: String,
     pub error: Option<String>,
+    // "ok", "error", "timeout" or "panic"
+    pub status: String,
 }
 
+impl ExtensionResult {
+    fn failure(status: &str, error: String) -> Self {
+        Self {
+            success: false,
+            data: String::new(),
+            error: Some(error),
+            status: status.to_string(),
+        }
+    }
+}
+
+// A problem found in the data an extension is registered with
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExtensionDataError {
+    pub field: String,
+    pub message: String,
+}
+
+// What registering an extension of one type takes beyond the ExtensionMetadata fields
+struct ExtensionSchema {
+    extension_type: &'static str,
+    // Whether "supported_languages" must list a language, for handlers that refuse every
+    // language they don't list
+    needs_languages: bool,
+    // Configuration keys that must be set, with the values each may take; no values means any
+    // non-empty value
+    required_configuration: &'static [(&'static str, &'static [&'static str])],
+}
+
+// Schemas of the built-in extension types. Any other type registers a custom extension, which
+// only needs the ExtensionMetadata fields
+const EXTENSION_SCHEMAS: [ExtensionSchema; 10] = [
+    // Syntax support for the languages listed, recognized by comma-separated "file_extensions"
+    ExtensionSchema { extension_type: "language_support", needs_languages: true, required_configuration: &[("file_extensions", &[])] },
+    ExtensionSchema { extension_type: "theme", needs_languages: false, required_configuration: &[("theme_kind", &["light", "dark"])] },
+    ExtensionSchema { extension_type: "formatter", needs_languages: true, required_configuration: &[] },
+    ExtensionSchema { extension_type: "linter", needs_languages: true, required_configuration: &[] },
+    ExtensionSchema { extension_type: "debugger", needs_languages: true, required_configuration: &[("debug_adapter", &[])] },
+    ExtensionSchema { extension_type: "build_system", needs_languages: false, required_configuration: &[("build_file", &[])] },
+    ExtensionSchema { extension_type: "vcs_provider", needs_languages: false, required_configuration: &[("vcs", &[])] },
+    // Terminal commands the extension adds, comma-separated
+    ExtensionSchema { extension_type: "terminal_enhancement", needs_languages: false, required_configuration: &[("commands", &[])] },
+    ExtensionSchema { extension_type: "editor_enhancement", needs_languages: false, required_configuration: &[] },
+    ExtensionSchema { extension_type: "snippet", needs_languages: true, required_configuration: &[] },
+];
+
+// How long an extension may run when neither the caller nor the extension's
+// "timeout_ms" configuration sets a limit
+pub(crate) const DEFAULT_EXTENSION_TIMEOUT_MS: u64 = 5000;
+
 // Global extension registry
 lazy_static! {
     static ref EXTENSIONS: Mutex<HashMap<String, Extension>> = Mutex::new(HashMap::new());
 }
 
-// Register an extension
+// Register an extension, checking its data against the schema for `extension_type` first
 pub fn register_extension(extension_id: &str, extension_type: &str, extension_data: &str) -> Result<()> {
-    let mut extensions = EXTENSIONS.lock().unwrap();
-    
-    // Parse extension data
-    let metadata: ExtensionMetadata = serde_json::from_str(extension_data)
-        .map_err(|e| anyhow!("Failed to parse extension data: {}", e))?;
+    register_extension_checked(extension_id, extension_type, extension_data).map_err(|errors| {
+        let messages: Vec<String> = errors.iter().map(|error| error.message.clone()).collect();
+        anyhow!("Invalid extension data: {}", messages.join("; "))
+    })
+}
+
+// Register an extension, returning every problem found in its data when it is invalid
+pub fn register_extension_checked(extension_id: &str, extension_type: &str, extension_data: &str) -> std::result::Result<(), Vec<ExtensionDataError>> {
+    let metadata = validate_extension_data(extension_type, extension_data)?;
     
     // Create extension handler based on type
     let handler: Arc<dyn ExtensionHandler + Send + Sync> = match extension_type {
@@ -86,7 +145,7 @@ pub fn register_extension(extension_id: &str, extension_type: &str, extension_da
     };
     
     // Register extension
-    extensions.insert(extension_id.to_string(), Extension {
+    EXTENSIONS.lock().unwrap().insert(extension_id.to_string(), Extension {
         metadata,
         enabled: true,
         handler,
@@ -95,6 +154,156 @@ pub fn register_extension(extension_id: &str, extension_type: &str, extension_da
     Ok(())
 }
 
+// Check extension data against the schema of its extension type, collecting every problem
+// instead of stopping at the first. All types need the ExtensionMetadata fields, with an
+// "extension_type" naming the type the extension is registered as, plus what EXTENSION_SCHEMAS
+// lists for the type
+pub fn validate_extension_data(extension_type: &str, extension_data: &str) -> std::result::Result<ExtensionMetadata, Vec<ExtensionDataError>> {
+    let value: serde_json::Value = serde_json::from_str(extension_data)
+        .map_err(|e| vec![extension_data_error("extension_data", format!("Invalid JSON: {}", e))])?;
+    
+    let object = match value.as_object() {
+        Some(object) => object,
+        None => return Err(vec![extension_data_error("extension_data", "Extension data must be a JSON object".to_string())]),
+    };
+    
+    let schema = EXTENSION_SCHEMAS.iter().find(|schema| schema.extension_type == extension_type);
+    let mut errors = Vec::new();
+    
+    for field in ["id", "name", "version", "description", "author"] {
+        match object.get(field) {
+            None => errors.push(extension_data_error(field, format!("Missing required field '{}'", field))),
+            // Description and author may be left blank
+            Some(serde_json::Value::String(value)) if value.trim().is_empty() && !matches!(field, "description" | "author") => {
+                errors.push(extension_data_error(field, format!("Field '{}' must not be empty", field)));
+            }
+            Some(serde_json::Value::String(_)) => {}
+            Some(_) => errors.push(extension_data_error(field, format!("Field '{}' must be a string", field))),
+        }
+    }
+    
+    match object.get("extension_type") {
+        None => errors.push(extension_data_error("extension_type", "Missing required field 'extension_type'".to_string())),
+        Some(value) if !names_extension_type(value, extension_type, schema.is_none()) => {
+            errors.push(extension_data_error(
+                "extension_type",
+                format!("Field 'extension_type' is {} but the extension is registered as {}", value, extension_type),
+            ));
+        }
+        Some(_) => {}
+    }
+    
+    for field in ["supported_languages", "dependencies"] {
+        match object.get(field) {
+            None => errors.push(extension_data_error(field, format!("Missing required field '{}'", field))),
+            Some(serde_json::Value::Array(items)) if items.iter().all(|item| item.is_string()) => {}
+            Some(_) => errors.push(extension_data_error(field, format!("Field '{}' must be an array of strings", field))),
+        }
+    }
+    
+    match object.get("configuration") {
+        None => errors.push(extension_data_error("configuration", "Missing required field 'configuration'".to_string())),
+        Some(serde_json::Value::Object(configuration)) => {
+            for (key, value) in configuration {
+                match value.as_str() {
+                    None => errors.push(extension_data_error("configuration", format!("Configuration value '{}' must be a string", key))),
+                    // execute_extension reads the timeout from here
+                    Some(timeout) if key == "timeout_ms" && !timeout.parse::<u64>().map_or(false, |timeout| timeout > 0) => {
+                        errors.push(extension_data_error("configuration", format!("Configuration 'timeout_ms' must be a positive number of milliseconds, got '{}'", timeout)));
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        Some(_) => errors.push(extension_data_error("configuration", "Field 'configuration' must be an object of strings".to_string())),
+    }
+    
+    if let Some(schema) = schema {
+        // A missing or mistyped list was reported above, so only an empty one is left to catch
+        let no_languages = object
+            .get("supported_languages")
+            .and_then(|languages| languages.as_array())
+            .map_or(false, |languages| languages.is_empty());
+        if no_languages && schema.needs_languages {
+            errors.push(extension_data_error(
+                "supported_languages",
+                format!("A {} extension must list at least one supported language", extension_type),
+            ));
+        }
+        
+        // Likewise a configuration that is not an object was reported already
+        if let Some(configuration) = object.get("configuration").and_then(|configuration| configuration.as_object()) {
+            for (key, allowed) in schema.required_configuration {
+                match configuration.get(*key).and_then(|value| value.as_str()) {
+                    None if !configuration.contains_key(*key) => errors.push(extension_data_error(
+                        "configuration",
+                        format!("A {} extension needs the configuration '{}'", extension_type, key),
+                    )),
+                    Some(value) if value.trim().is_empty() => {
+                        errors.push(extension_data_error("configuration", format!("Configuration '{}' must not be empty", key)));
+                    }
+                    Some(value) if !allowed.is_empty() && !allowed.contains(&value) => errors.push(extension_data_error(
+                        "configuration",
+                        format!("Configuration '{}' must be one of {}, got '{}'", key, allowed.join(", "), value),
+                    )),
+                    _ => {}
+                }
+            }
+        }
+    }
+    
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    
+    // Store the type the extension is registered as, whichever way the data spelled it
+    let mut value = value;
+    value["extension_type"] = serde_json::to_value(extension_type_from_name(extension_type)).unwrap_or_default();
+    
+    serde_json::from_value(value).map_err(|e| vec![extension_data_error("extension_data", format!("Invalid extension data: {}", e))])
+}
+
+// Whether the "extension_type" of extension data names `extension_type`. Besides the serialized
+// ExtensionType ("LanguageSupport", {"Custom": "name"}), the registration name ("language_support")
+// and the app's enum names ("LANGUAGE_SUPPORT", or "CUSTOM" for a custom type) are accepted
+fn names_extension_type(value: &serde_json::Value, extension_type: &str, custom: bool) -> bool {
+    let normalize = |name: &str| name.replace('_', "").to_lowercase();
+    
+    match value {
+        serde_json::Value::String(name) => {
+            normalize(name) == normalize(extension_type) || (custom && normalize(name) == "custom")
+        }
+        serde_json::Value::Object(object) => {
+            custom && object.len() == 1 && object.get("Custom").and_then(|name| name.as_str()) == Some(extension_type)
+        }
+        _ => false,
+    }
+}
+
+// The ExtensionType registered under a name; names without a schema are custom types
+fn extension_type_from_name(extension_type: &str) -> ExtensionType {
+    match extension_type {
+        "language_support" => ExtensionType::LanguageSupport,
+        "theme" => ExtensionType::Theme,
+        "formatter" => ExtensionType::Formatter,
+        "linter" => ExtensionType::Linter,
+        "debugger" => ExtensionType::Debugger,
+        "build_system" => ExtensionType::BuildSystem,
+        "vcs_provider" => ExtensionType::VcsProvider,
+        "terminal_enhancement" => ExtensionType::TerminalEnhancement,
+        "editor_enhancement" => ExtensionType::EditorEnhancement,
+        "snippet" => ExtensionType::Snippet,
+        other => ExtensionType::Custom(other.to_string()),
+    }
+}
+
+fn extension_data_error(field: &str, message: String) -> ExtensionDataError {
+    ExtensionDataError {
+        field: field.to_string(),
+        message,
+    }
+}
+
 // Unregister an extension
 pub fn unregister_extension(extension_id: &str) -> Result<()> {
     let mut extensions = EXTENSIONS.lock().unwrap();
@@ -117,37 +326,73 @@ pub fn get_registered_extensions() -> Vec<ExtensionMetadata> {
         .collect()
 }
 
-// Execute an extension
-pub fn execute_extension(extension_id: &str, data: &str) -> Result<ExtensionResult> {
-    let extensions = EXTENSIONS.lock().unwrap();
-    
-    if let Some(extension) = extensions.get(extension_id) {
+// Execute an extension on a worker thread, giving up after `timeout_ms`. A timeout of 0
+// uses the extension's "timeout_ms" configuration, or DEFAULT_EXTENSION_TIMEOUT_MS
+pub fn execute_extension(extension_id: &str, data: &str, timeout_ms: u64) -> Result<ExtensionResult> {
+    // Take what the worker needs and release the registry, so a hung extension
+    // does not block every other extension call
+    let (handler, timeout_ms) = {
+        let extensions = EXTENSIONS.lock().unwrap();
+        
+        let extension = match extensions.get(extension_id) {
+            Some(extension) => extension,
+            None => return Ok(ExtensionResult::failure("error", format!("Extension not found: {}", extension_id))),
+        };
+        
         if !extension.enabled {
-            return Ok(ExtensionResult {
-                success: false,
-                data: String::new(),
-                error: Some("Extension is disabled".to_string()),
-            });
+            return Ok(ExtensionResult::failure("error", "Extension is disabled".to_string()));
         }
         
-        match extension.handler.execute(data) {
-            Ok(result) => Ok(ExtensionResult {
-                success: true,
-                data: result,
-                error: None,
-            }),
-            Err(e) => Ok(ExtensionResult {
-                success: false,
-                data: String::new(),
-                error: Some(e.to_string()),
-            }),
-        }
-    } else {
-        Ok(ExtensionResult {
-            success: false,
-            data: String::new(),
-            error: Some(format!("Extension not found: {}", extension_id)),
+        let timeout_ms = if timeout_ms > 0 {
+            timeout_ms
+        } else {
+            extension.metadata.configuration
+                .get("timeout_ms")
+                .and_then(|value| value.parse::<u64>().ok())
+                .filter(|&value| value > 0)
+                .unwrap_or(DEFAULT_EXTENSION_TIMEOUT_MS)
+        };
+        
+        (Arc::clone(&extension.handler), timeout_ms)
+    };
+    
+    let (sender, receiver) = mpsc::channel();
+    let data = data.to_string();
+    
+    thread::Builder::new()
+        .name(format!("extension-{}", extension_id))
+        .spawn(move || {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| handler.execute(&data)));
+            // The receiver is gone if the call already timed out
+            let _ = sender.send(outcome);
         })
+        .map_err(|e| anyhow!("Failed to start extension thread: {}", e))?;
+    
+    // Threads cannot be killed, so an overrunning extension is left to finish in the
+    // background and its result is dropped
+    let result = match receiver.recv_timeout(Duration::from_millis(timeout_ms)) {
+        Ok(Ok(Ok(result))) => ExtensionResult {
+            success: true,
+            data: result,
+            error: None,
+            status: "ok".to_string(),
+        },
+        Ok(Ok(Err(e))) => ExtensionResult::failure("error", e.to_string()),
+        Ok(Err(payload)) => ExtensionResult::failure("panic", format!("Extension panicked: {}", panic_message(&*payload))),
+        Err(mpsc::RecvTimeoutError::Timeout) => ExtensionResult::failure("timeout", format!("Extension timed out after {} ms", timeout_ms)),
+        Err(mpsc::RecvTimeoutError::Disconnected) => ExtensionResult::failure("panic", "Extension thread exited without a result".to_string()),
+    };
+    
+    Ok(result)
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
     }
 }
 
@@ -916,4 +1161,80 @@ impl ExtensionHandler for CustomHandler {
     fn shutdown(&self) -> Result<()> {
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // Extension data of the given type, as the app sends it, with `configuration` and languages
+    fn extension_data(extension_type: &str, languages: &[&str], configuration: serde_json::Value) -> String {
+        serde_json::json!({
+            "id": "com.example.extension",
+            "name": "Example",
+            "description": "",
+            "version": "1.0",
+            "author": "",
+            "extension_type": extension_type,
+            "supported_languages": languages,
+            "dependencies": [],
+            "configuration": configuration
+        })
+        .to_string()
+    }
+    
+    fn error_messages(extension_type: &str, data: &str) -> Vec<String> {
+        validate_extension_data(extension_type, data)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| format!("{}: {}", error.field, error.message))
+            .collect()
+    }
+    
+    #[test]
+    fn every_spelling_of_the_registered_type_is_accepted() {
+        let configuration = serde_json::json!({ "theme_kind": "dark" });
+        for spelling in ["Theme", "theme", "THEME"] {
+            let metadata = validate_extension_data("theme", &extension_data(spelling, &[], configuration.clone())).unwrap();
+            assert!(matches!(metadata.extension_type, ExtensionType::Theme));
+        }
+        
+        let metadata = validate_extension_data("my_tool", &extension_data("CUSTOM", &[], serde_json::json!({}))).unwrap();
+        assert!(matches!(metadata.extension_type, ExtensionType::Custom(name) if name == "my_tool"));
+    }
+    
+    #[test]
+    fn type_in_the_data_must_match_the_registration() {
+        let data = extension_data("Linter", &["rust"], serde_json::json!({ "theme_kind": "dark" }));
+        assert_eq!(
+            error_messages("theme", &data),
+            vec!["extension_type: Field 'extension_type' is \"Linter\" but the extension is registered as theme"]
+        );
+        
+        // CUSTOM only stands for types without a schema
+        let data = extension_data("CUSTOM", &[], serde_json::json!({ "theme_kind": "dark" }));
+        assert_eq!(error_messages("theme", &data).len(), 1);
+    }
+    
+    #[test]
+    fn schema_fields_are_required_per_type() {
+        assert_eq!(
+            error_messages("theme", &extension_data("THEME", &[], serde_json::json!({ "theme_kind": "sepia" }))),
+            vec!["configuration: Configuration 'theme_kind' must be one of light, dark, got 'sepia'"]
+        );
+        assert_eq!(
+            error_messages("language_support", &extension_data("LANGUAGE_SUPPORT", &[], serde_json::json!({}))),
+            vec![
+                "supported_languages: A language_support extension must list at least one supported language",
+                "configuration: A language_support extension needs the configuration 'file_extensions'",
+            ]
+        );
+        assert_eq!(
+            error_messages("terminal_enhancement", &extension_data("TERMINAL_ENHANCEMENT", &[], serde_json::json!({ "commands": " " }))),
+            vec!["configuration: Configuration 'commands' must not be empty"]
+        );
+        
+        let data = extension_data("LANGUAGE_SUPPORT", &["kotlin"], serde_json::json!({ "file_extensions": "kt,kts" }));
+        assert!(validate_extension_data("language_support", &data).is_ok());
+    }
+}
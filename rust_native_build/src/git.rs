@@ -0,0 +1,416 @@
+use std::path::Path;
+use std::process::Command;
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, anyhow};
+
+// A changed file as reported by `git status`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileStatus {
+    pub path: String,
+    // Set for renames and copies
+    pub original_path: Option<String>,
+    pub staged: bool,
+    pub unstaged: bool,
+    // Two-letter XY code from porcelain v2, e.g. "M.", ".M", "A.", "UU", "??"
+    pub status_code: String,
+}
+
+// Result of a git command that changes the repository
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GitCommandOutput {
+    pub success: bool,
+    pub output: String,
+    pub error: Option<String>,
+    pub exit_code: i32,
+}
+
+impl GitCommandOutput {
+    fn failure(error: String) -> Self {
+        Self {
+            success: false,
+            output: String::new(),
+            error: Some(error),
+            exit_code: -1,
+        }
+    }
+}
+
+// Working tree status of a repository
+pub fn status(repo_path: &str) -> Result<Vec<FileStatus>> {
+    let output = run_git(repo_path, &["status", "--porcelain=v2", "-z", "--untracked-files=all"])?;
+    Ok(parse_porcelain_v2(&output))
+}
+
+// Parse NUL-separated `git status --porcelain=v2 -z` output
+pub fn parse_porcelain_v2(output: &str) -> Vec<FileStatus> {
+    let mut files = Vec::new();
+    let mut entries = output.split('\0').filter(|entry| !entry.is_empty());
+    
+    while let Some(entry) = entries.next() {
+        let (kind, rest) = entry.split_at(1);
+        let rest = rest.strip_prefix(' ').unwrap_or(rest);
+        
+        match kind {
+            // 1 XY sub mH mI mW hH hI path
+            "1" => {
+                if let Some((code, path)) = split_status_fields(rest, 6) {
+                    files.push(changed_file(code, path, None));
+                }
+            }
+            // 2 XY sub mH mI mW hH hI Xscore path, followed by the original path as its own entry
+            "2" => {
+                if let Some((code, path)) = split_status_fields(rest, 7) {
+                    let original_path = entries.next().map(|path| path.to_string());
+                    files.push(changed_file(code, path, original_path));
+                }
+            }
+            // u XY sub m1 m2 m3 mW h1 h2 h3 path: unmerged, needs resolving in the working tree
+            "u" => {
+                if let Some((code, path)) = split_status_fields(rest, 8) {
+                    files.push(FileStatus {
+                        path: path.to_string(),
+                        original_path: None,
+                        staged: false,
+                        unstaged: true,
+                        status_code: code.to_string(),
+                    });
+                }
+            }
+            "?" => files.push(FileStatus {
+                path: rest.to_string(),
+                original_path: None,
+                staged: false,
+                unstaged: true,
+                status_code: "??".to_string(),
+            }),
+            // Headers (#) and ignored files (!)
+            _ => {}
+        }
+    }
+    
+    files
+}
+
+// Split "XY <fields...> path" into the XY code and the path, skipping `skipped` fields in between.
+// The path is taken whole since it may contain spaces
+fn split_status_fields(rest: &str, skipped: usize) -> Option<(&str, &str)> {
+    let mut parts = rest.splitn(skipped + 2, ' ');
+    let code = parts.next()?;
+    let path = parts.nth(skipped)?;
+    Some((code, path))
+}
+
+fn changed_file(code: &str, path: &str, original_path: Option<String>) -> FileStatus {
+    let mut flags = code.chars();
+    let index = flags.next().unwrap_or('.');
+    let worktree = flags.next().unwrap_or('.');
+    
+    FileStatus {
+        path: path.to_string(),
+        original_path,
+        staged: index != '.',
+        unstaged: worktree != '.',
+        status_code: code.to_string(),
+    }
+}
+
+// Unstaged changes in the working tree, for the whole repository or a single file.
+// Untracked files are shown as entirely added
+pub fn diff(repo_path: &str, file: Option<String>) -> Result<String> {
+    // Outside a repository `git diff` silently behaves like `--no-index`
+    run_git(repo_path, &["rev-parse", "--git-dir"])?;
+    
+    let file = match file {
+        Some(file) => file,
+        None => return run_git(repo_path, &["diff", "--no-color", "--no-ext-diff"]),
+    };
+    
+    let tracked = run_git(repo_path, &["ls-files", "--error-unmatch", "--", &file]).is_ok();
+    if tracked {
+        return run_git(repo_path, &["diff", "--no-color", "--no-ext-diff", "--", &file]);
+    }
+    
+    if !Path::new(repo_path).join(&file).is_file() {
+        return Err(anyhow!("File not found in repository: {}", file));
+    }
+    
+    // `git diff --no-index` exits with 1 when the files differ, which is always the case here
+    let output = git_command(repo_path)
+        .args(["diff", "--no-color", "--no-ext-diff", "--no-index", "--", "/dev/null", &file])
+        .output()
+        .map_err(|e| anyhow!("Failed to run git: {}", e))?;
+    
+    match output.status.code() {
+        Some(0) | Some(1) => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+        _ => Err(anyhow!("git diff failed: {}", String::from_utf8_lossy(&output.stderr).trim())),
+    }
+}
+
+// Stage files, or every change when no paths are given
+pub fn stage(repo_path: &str, paths: &[String]) -> GitCommandOutput {
+    let mut args = vec!["add".to_string()];
+    if paths.is_empty() {
+        args.push("--all".to_string());
+    } else {
+        args.push("--".to_string());
+        args.extend(paths.iter().cloned());
+    }
+    
+    run_git_command(repo_path, &args)
+}
+
+// Move staged changes back to the working tree, or unstage everything when no paths are given
+pub fn unstage(repo_path: &str, paths: &[String]) -> GitCommandOutput {
+    let has_commits = run_git(repo_path, &["rev-parse", "--verify", "--quiet", "HEAD"]).is_ok();
+    
+    // Before the first commit there is no HEAD to reset to, so files are dropped from the index instead
+    let mut args: Vec<String> = if has_commits {
+        vec!["reset".to_string(), "--quiet".to_string()]
+    } else {
+        vec!["rm".to_string(), "--cached".to_string(), "-r".to_string(), "--quiet".to_string()]
+    };
+    
+    args.push("--".to_string());
+    if paths.is_empty() {
+        args.push(".".to_string());
+    } else {
+        args.extend(paths.iter().cloned());
+    }
+    
+    run_git_command(repo_path, &args)
+}
+
+// Commit the staged changes. `author` is in git's "Name <email>" form
+pub fn commit(repo_path: &str, message: &str, author: Option<String>) -> GitCommandOutput {
+    if message.trim().is_empty() {
+        return GitCommandOutput::failure("Commit message cannot be empty".to_string());
+    }
+    
+    if let Err(e) = run_git(repo_path, &["rev-parse", "--git-dir"]) {
+        return GitCommandOutput::failure(e.to_string());
+    }
+    
+    // `git diff --cached --quiet` exits with 1 when something is staged
+    let nothing_staged = git_command(repo_path)
+        .args(["diff", "--cached", "--quiet"])
+        .status()
+        .map_or(false, |status| status.success());
+    if nothing_staged {
+        return GitCommandOutput::failure("No staged changes to commit".to_string());
+    }
+    
+    let mut args = vec!["commit".to_string(), "-m".to_string(), message.to_string()];
+    if let Some(author) = author {
+        args.push(format!("--author={}", author));
+    }
+    
+    run_git_command(repo_path, &args)
+}
+
+// Run a git command, capturing its output and any error text instead of failing
+fn run_git_command(repo_path: &str, args: &[String]) -> GitCommandOutput {
+    if !Path::new(repo_path).is_dir() {
+        return GitCommandOutput::failure(format!("Directory not found: {}", repo_path));
+    }
+    
+    let output = match git_command(repo_path).args(args).output() {
+        Ok(output) => output,
+        Err(e) => return GitCommandOutput::failure(format!("Failed to run git (is it installed?): {}", e)),
+    };
+    
+    let stdout = String::from_utf8_lossy(&output.stdout).trim_end().to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    
+    let error = if output.status.success() {
+        None
+    } else if stderr.contains("not a git repository") {
+        Some(format!("Not a git repository: {}", repo_path))
+    } else if stderr.is_empty() {
+        Some(stdout.clone())
+    } else {
+        Some(stderr)
+    };
+    
+    GitCommandOutput {
+        success: output.status.success(),
+        output: stdout,
+        error,
+        exit_code: output.status.code().unwrap_or(-1),
+    }
+}
+
+fn git_command(repo_path: &str) -> Command {
+    let mut command = Command::new("git");
+    command
+        .current_dir(repo_path)
+        // Read-only queries should not take locks that block the user's own git commands
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .env("GIT_TERMINAL_PROMPT", "0");
+    command
+}
+
+// Run git in a repository and return its stdout
+fn run_git(repo_path: &str, args: &[&str]) -> Result<String> {
+    if !Path::new(repo_path).is_dir() {
+        return Err(anyhow!("Directory not found: {}", repo_path));
+    }
+    
+    let output = git_command(repo_path)
+        .args(args)
+        .output()
+        .map_err(|e| anyhow!("Failed to run git (is it installed?): {}", e))?;
+    
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("not a git repository") {
+            return Err(anyhow!("Not a git repository: {}", repo_path));
+        }
+        return Err(anyhow!("git {} failed: {}", args[0], stderr.trim()));
+    }
+    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeGitStatus(
+    env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    repo_path: jni::objects::JString,
+) -> jni::sys::jstring {
+    let repo_path: String = env
+        .get_string(repo_path)
+        .expect("Failed to get repository path string")
+        .into();
+    
+    let json = match status(&repo_path) {
+        Ok(files) => serde_json::to_string(&files).unwrap_or_else(|_| "[]".to_string()),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeGitDiff(
+    env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    repo_path: jni::objects::JString,
+    file: jni::objects::JString,
+) -> jni::sys::jstring {
+    let repo_path: String = env
+        .get_string(repo_path)
+        .expect("Failed to get repository path string")
+        .into();
+    
+    let file: String = env
+        .get_string(file)
+        .expect("Failed to get file string")
+        .into();
+    let file = if file.is_empty() { None } else { Some(file) };
+    
+    let json = match diff(&repo_path, file) {
+        Ok(diff) => serde_json::json!({ "diff": diff }).to_string(),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeGitStage(
+    env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    repo_path: jni::objects::JString,
+    paths_json: jni::objects::JString,
+) -> jni::sys::jstring {
+    let repo_path: String = env
+        .get_string(repo_path)
+        .expect("Failed to get repository path string")
+        .into();
+    
+    let paths_json: String = env
+        .get_string(paths_json)
+        .expect("Failed to get paths JSON string")
+        .into();
+    
+    let result = match serde_json::from_str::<Vec<String>>(&paths_json) {
+        Ok(paths) => stage(&repo_path, &paths),
+        Err(e) => GitCommandOutput::failure(format!("Invalid paths JSON: {}", e)),
+    };
+    
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeGitUnstage(
+    env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    repo_path: jni::objects::JString,
+    paths_json: jni::objects::JString,
+) -> jni::sys::jstring {
+    let repo_path: String = env
+        .get_string(repo_path)
+        .expect("Failed to get repository path string")
+        .into();
+    
+    let paths_json: String = env
+        .get_string(paths_json)
+        .expect("Failed to get paths JSON string")
+        .into();
+    
+    let result = match serde_json::from_str::<Vec<String>>(&paths_json) {
+        Ok(paths) => unstage(&repo_path, &paths),
+        Err(e) => GitCommandOutput::failure(format!("Invalid paths JSON: {}", e)),
+    };
+    
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeGitCommit(
+    env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    repo_path: jni::objects::JString,
+    message: jni::objects::JString,
+    author: jni::objects::JString,
+) -> jni::sys::jstring {
+    let repo_path: String = env
+        .get_string(repo_path)
+        .expect("Failed to get repository path string")
+        .into();
+    
+    let message: String = env
+        .get_string(message)
+        .expect("Failed to get commit message string")
+        .into();
+    
+    let author: String = env
+        .get_string(author)
+        .expect("Failed to get author string")
+        .into();
+    let author = if author.is_empty() { None } else { Some(author) };
+    
+    let result = commit(&repo_path, &message, author);
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
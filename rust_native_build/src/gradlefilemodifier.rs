@@ -4,11 +4,14 @@ use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 use anyhow::{Result, anyhow};
 use regex::Regex;
+use crate::buildsystem::gradle_command;
+use crate::sdkmanager::SdkManager;
 
 // Gradle build analysis
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GradleBuildAnalysis {
     pub file_path: String,
+    pub dsl: GradleDsl,
     pub dependencies: Vec<GradleDependency>,
     pub plugins: Vec<String>,
     pub issues: Vec<GradleBuildIssue>,
@@ -18,6 +21,14 @@ pub struct GradleBuildAnalysis {
     pub build_tools_version: Option<String>,
 }
 
+// Gradle build script dialect
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GradleDsl {
+    Groovy,
+    Kotlin,
+}
+
 // Gradle dependency
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GradleDependency {
@@ -100,6 +111,32 @@ pub struct DependencyUpdate {
     pub new_version: String,
 }
 
+// Resolved dependency graph of one Gradle configuration
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DependencyTree {
+    pub project_path: String,
+    pub configuration: String,
+    pub dependencies: Vec<DependencyNode>,
+    // Number of nodes whose requested version was replaced by conflict resolution
+    pub conflicts: usize,
+}
+
+// Dependency tree node. Project dependencies have an empty group and version and the
+// project path, e.g. ":lib", as name
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DependencyNode {
+    pub group: String,
+    pub name: String,
+    pub version: String,
+    // Version asked for when Gradle resolved a different one ("1.1.0 -> 1.5.0")
+    pub requested_version: Option<String>,
+    pub conflict_resolved: bool,
+    // Already listed earlier in the tree, so its children are left out ("(*)")
+    pub omitted: bool,
+    pub failed: bool,
+    pub children: Vec<DependencyNode>,
+}
+
 // Analyze Gradle build file
 pub fn analyze_gradle_file(file_path: &str) -> Result<GradleBuildAnalysis> {
     // Check if file exists
@@ -110,6 +147,7 @@ pub fn analyze_gradle_file(file_path: &str) -> Result<GradleBuildAnalysis> {
     
     // Read file content
     let content = fs::read_to_string(path)?;
+    let dsl = detect_dsl(file_path);
     
     // Parse dependencies
     let dependencies = extract_dependencies(&content);
@@ -118,7 +156,7 @@ pub fn analyze_gradle_file(file_path: &str) -> Result<GradleBuildAnalysis> {
     let plugins = extract_plugins(&content);
     
     // Find issues
-    let issues = find_issues(&content, &dependencies);
+    let issues = find_issues(&content, &dependencies, dsl);
     
     // Extract SDK versions
     let compile_sdk_version = extract_compile_sdk_version(&content);
@@ -128,6 +166,7 @@ pub fn analyze_gradle_file(file_path: &str) -> Result<GradleBuildAnalysis> {
     
     Ok(GradleBuildAnalysis {
         file_path: file_path.to_string(),
+        dsl,
         dependencies,
         plugins,
         issues,
@@ -142,6 +181,7 @@ pub fn analyze_gradle_file(file_path: &str) -> Result<GradleBuildAnalysis> {
 pub fn optimize_gradle_file(file_path: &str) -> Result<OptimizationResult> {
     // Analyze the build file
     let analysis = analyze_gradle_file(file_path)?;
+    let dsl = analysis.dsl;
     
     // Read file content
     let mut content = fs::read_to_string(file_path)?;
@@ -167,19 +207,19 @@ pub fn optimize_gradle_file(file_path: &str) -> Result<OptimizationResult> {
                 issues_fixed.push(issue.description.clone());
             }
             GradleBuildIssueType::MissingJavaCompatibility => {
-                content = ensure_java_compatibility(&content);
+                content = ensure_java_compatibility(&content, dsl);
                 issues_fixed.push(issue.description.clone());
             }
             GradleBuildIssueType::MissingKotlinJvmTarget => {
-                content = ensure_kotlin_jvm_target(&content);
+                content = ensure_kotlin_jvm_target(&content, dsl);
                 issues_fixed.push(issue.description.clone());
             }
             GradleBuildIssueType::MissingProguardConfig => {
-                content = ensure_proguard_config(&content);
+                content = ensure_proguard_config(&content, dsl);
                 issues_fixed.push(issue.description.clone());
             }
             GradleBuildIssueType::MissingBuildFeatures => {
-                content = ensure_build_features(&content);
+                content = ensure_build_features(&content, dsl);
                 issues_fixed.push(issue.description.clone());
             }
         }
@@ -201,25 +241,54 @@ pub fn optimize_gradle_file(file_path: &str) -> Result<OptimizationResult> {
 pub fn add_dependency(file_path: &str, dependency: &str, configuration: &str) -> Result<()> {
     // Read file content
     let mut content = fs::read_to_string(file_path)?;
+    let dsl = detect_dsl(file_path);
     
-    // Check if dependency already exists
-    let dependency_regex = Regex::new(&format!(r"{}\s+['\"]{}['\"]", configuration, regex::escape(dependency)))?;
+    // Check if dependency already exists, in either dialect
+    let dependency_regex = Regex::new(&format!(r#"{}(?:\s+|\s*\(\s*)['"]{}['"]"#, regex::escape(configuration), regex::escape(dependency)))?;
     if dependency_regex.is_match(&content) {
         return Err(anyhow!("Dependency already exists: {}", dependency));
     }
     
-    // Find dependencies block
-    let dependencies_regex = Regex::new(r"dependencies\s*\{")?;
-    if let Some(dependencies_match) = dependencies_regex.find(&content) {
-        // Insert dependency
-        let insert_position = dependencies_match.end();
-        let dependency_line = format!("\n    {} '{}'", configuration, dependency);
+    let dependency_declaration = match dsl {
+        GradleDsl::Groovy => format!("{} '{}'", configuration, dependency),
+        GradleDsl::Kotlin => format!("{}(\"{}\")", configuration, dependency),
+    };
+    
+    // Find the top-level dependencies block
+    if let Some((open_brace, close_brace)) = find_top_level_block(&content, "dependencies")? {
+        let block_indent = line_indent(&content, open_brace);
+        let body = &content[open_brace + 1..close_brace];
+        
+        // Reuse the indentation of the existing entries, falling back to one level deeper than the block
+        let entry_indent = body
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .map(|line| line[..line.len() - line.trim_start().len()].to_string())
+            .unwrap_or_else(|| format!("{}    ", block_indent));
         
+        // Insert after the last non-blank line of the block so the closing brace keeps its position
+        let insert_position = match body.trim_end().is_empty() {
+            true => open_brace + 1,
+            false => open_brace + 1 + body.trim_end().len(),
+        };
+        let dependency_line = format!("\n{}{}", entry_indent, dependency_declaration);
         content.insert_str(insert_position, &dependency_line);
-    } else {
+        
+        // An empty single-line block needs its closing brace moved onto its own line
+        let closing = insert_position + dependency_line.len();
+        if !content[closing..].starts_with('\n') && content[closing..].trim_start_matches(|c| c == ' ' || c == '\t').starts_with('}') {
+            let trailing_whitespace = content[closing..].len() - content[closing..].trim_start_matches(|c| c == ' ' || c == '\t').len();
+            content.replace_range(closing..closing + trailing_whitespace, &format!("\n{}", block_indent));
+        }
+    } else if has_gradle_structure(&content) {
         // Add dependencies block
-        let dependencies_block = format!("\ndependencies {{\n    {} '{}'\n}}\n", configuration, dependency);
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        let dependencies_block = format!("\ndependencies {{\n    {}\n}}\n", dependency_declaration);
         content.push_str(&dependencies_block);
+    } else {
+        return Err(anyhow!("Not a recognizable Gradle build file: {}", file_path));
     }
     
     // Write updated content
@@ -228,13 +297,91 @@ pub fn add_dependency(file_path: &str, dependency: &str, configuration: &str) ->
     Ok(())
 }
 
+// Find the braces of a block declared at the top level of a Gradle file
+fn find_top_level_block(content: &str, name: &str) -> Result<Option<(usize, usize)>> {
+    let bytes = content.as_bytes();
+    let mut depth = 0usize;
+    let mut open_brace = None;
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        match bytes[i] {
+            // Skip comments
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = content[i + 2..].find("*/").map(|end| i + 2 + end + 2).unwrap_or(bytes.len());
+                continue;
+            }
+            // Skip string literals
+            quote @ (b'\'' | b'"') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'{' => {
+                if depth == 0 && open_brace.is_none() && content[..i].trim_end().ends_with(name) {
+                    let prefix = content[..i].trim_end();
+                    let before_name = prefix[..prefix.len() - name.len()].chars().last();
+                    if before_name.map_or(true, |c| !c.is_alphanumeric() && c != '_' && c != '.') {
+                        open_brace = Some(i);
+                    }
+                }
+                depth += 1;
+            }
+            b'}' => {
+                if depth == 0 {
+                    return Err(anyhow!("Unbalanced braces in Gradle file"));
+                }
+                depth -= 1;
+                if depth == 0 {
+                    if let Some(open) = open_brace {
+                        return Ok(Some((open, i)));
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    
+    if depth != 0 {
+        return Err(anyhow!("Unbalanced braces in Gradle file"));
+    }
+    
+    Ok(None)
+}
+
+// Get the leading whitespace of the line containing a position
+fn line_indent(content: &str, position: usize) -> String {
+    let line_start = content[..position].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    content[line_start..position]
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect()
+}
+
+// Check if content looks like a Gradle build script
+fn has_gradle_structure(content: &str) -> bool {
+    let structure_regex = Regex::new(r"(?m)^\s*(plugins|android|buildscript|allprojects|subprojects|repositories|apply|java|kotlin)\b").unwrap();
+    structure_regex.is_match(content)
+}
+
 // Remove dependency from Gradle file
 pub fn remove_dependency(file_path: &str, dependency: &str) -> Result<()> {
     // Read file content
     let content = fs::read_to_string(file_path)?;
     
-    // Find and remove dependency
-    let dependency_regex = Regex::new(&format!(r"\s+\w+\s+['\"]{}['\"]", regex::escape(dependency)))?;
+    // Find and remove dependency, matching both `conf 'dep'` and `conf("dep")`
+    let dependency_regex = Regex::new(&format!(r#"\s+\w+(?:\s+['"]{0}['"]|\s*\(\s*['"]{0}['"]\s*\))"#, regex::escape(dependency)))?;
     let updated_content = dependency_regex.replace_all(&content, "").to_string();
     
     // Write updated content
@@ -267,6 +414,7 @@ pub fn update_dependencies(file_path: &str) -> Result<OptimizationResult> {
 pub fn fix_common_issues(file_path: &str) -> Result<OptimizationResult> {
     // Read file content
     let mut content = fs::read_to_string(file_path)?;
+    let dsl = detect_dsl(file_path);
     
     let mut issues_fixed = Vec::new();
     
@@ -277,32 +425,32 @@ pub fn fix_common_issues(file_path: &str) -> Result<OptimizationResult> {
     }
     
     // Fix deprecated configurations
-    if content.contains("compile ") || content.contains("testCompile ") {
+    if uses_deprecated_configurations(&content) {
         content = fix_deprecated_configurations(&content);
         issues_fixed.push("Updated deprecated dependency configurations".to_string());
     }
     
     // Fix missing Java compatibility
     if !content.contains("sourceCompatibility") || !content.contains("targetCompatibility") {
-        content = ensure_java_compatibility(&content);
+        content = ensure_java_compatibility(&content, dsl);
         issues_fixed.push("Added Java compatibility settings".to_string());
     }
     
     // Fix missing Kotlin JVM target
     if content.contains("kotlin") && !content.contains("jvmTarget") {
-        content = ensure_kotlin_jvm_target(&content);
+        content = ensure_kotlin_jvm_target(&content, dsl);
         issues_fixed.push("Added Kotlin JVM target".to_string());
     }
     
     // Fix missing ProGuard configuration
-    if content.contains("minifyEnabled true") && !content.contains("proguardFiles") {
-        content = ensure_proguard_config(&content);
+    if minify_enabled_regex().is_match(&content) && !content.contains("proguardFiles") {
+        content = ensure_proguard_config(&content, dsl);
         issues_fixed.push("Added ProGuard configuration".to_string());
     }
     
     // Fix missing build features
     if content.contains("compose") && !content.contains("buildFeatures") {
-        content = ensure_build_features(&content);
+        content = ensure_build_features(&content, dsl);
         issues_fixed.push("Added missing build features".to_string());
     }
     
@@ -370,12 +518,180 @@ pub fn generate_build_report(project_path: &str) -> Result<GradleBuildReport> {
     })
 }
 
+// Resolved dependency tree of the app module, or of the root project when there is no app
+// module, from `gradle dependencies` run through the project's wrapper or the SDK manager's
+// Gradle. Defaults to the debug runtime classpath for the app
+pub fn dependency_tree(project_path: &str, configuration: Option<String>, sdk_manager: &SdkManager) -> Result<DependencyTree> {
+    let project_dir = Path::new(project_path);
+    let mut cmd = gradle_command(project_dir, sdk_manager)?;
+    
+    let has_app_module = project_dir.join("app/build.gradle").exists() || project_dir.join("app/build.gradle.kts").exists();
+    let (task, default_configuration) = if has_app_module {
+        (":app:dependencies", "debugRuntimeClasspath")
+    } else {
+        ("dependencies", "runtimeClasspath")
+    };
+    let configuration = configuration
+        .filter(|configuration| !configuration.trim().is_empty())
+        .unwrap_or_else(|| default_configuration.to_string());
+    
+    let output = cmd
+        .current_dir(project_dir)
+        .args([task, "--configuration", &configuration, "--console=plain"])
+        .output()
+        .map_err(|e| anyhow!("Failed to run Gradle: {}", e))?;
+    
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("Gradle dependencies task failed: {}", gradle_failure_reason(&stderr)));
+    }
+    
+    let dependencies = parse_dependency_tree(&String::from_utf8_lossy(&output.stdout));
+    let conflicts = count_conflicts(&dependencies);
+    
+    Ok(DependencyTree {
+        project_path: project_path.to_string(),
+        configuration,
+        dependencies,
+        conflicts,
+    })
+}
+
+// Build the node tree from the ASCII tree Gradle prints, where every level of nesting is
+// indented by five characters ("|    " or "     ") before the "+--- " or "\--- " marker
+fn parse_dependency_tree(output: &str) -> Vec<DependencyNode> {
+    let mut roots = Vec::new();
+    // Path from the top level to the last node seen
+    let mut stack: Vec<DependencyNode> = Vec::new();
+    
+    for line in output.lines() {
+        let marker = match line.find("+--- ").or_else(|| line.find("\\--- ")) {
+            Some(marker) => marker,
+            None => continue,
+        };
+        
+        let depth = marker / 5;
+        while stack.len() > depth {
+            let node = stack.pop().unwrap();
+            attach_dependency(&mut stack, &mut roots, node);
+        }
+        
+        stack.push(parse_dependency_node(&line[marker + 5..]));
+    }
+    
+    while let Some(node) = stack.pop() {
+        attach_dependency(&mut stack, &mut roots, node);
+    }
+    
+    roots
+}
+
+fn attach_dependency(stack: &mut [DependencyNode], roots: &mut Vec<DependencyNode>, node: DependencyNode) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(node),
+        None => roots.push(node),
+    }
+}
+
+// Parse one entry, e.g. "androidx.annotation:annotation:1.1.0 -> 1.5.0 (*)",
+// "com.google.guava:guava -> 31.1-android" or "project :lib"
+fn parse_dependency_node(entry: &str) -> DependencyNode {
+    let mut entry = entry.trim();
+    let mut omitted = false;
+    let mut failed = false;
+    
+    // Trailing markers: (*) listed before, (c) constraint, (n) not resolved
+    loop {
+        if let Some(rest) = entry.strip_suffix("(*)") {
+            omitted = true;
+            entry = rest.trim_end();
+        } else if let Some(rest) = entry.strip_suffix("(c)").or_else(|| entry.strip_suffix("(n)")) {
+            entry = rest.trim_end();
+        } else if let Some(rest) = entry.strip_suffix("FAILED") {
+            failed = true;
+            entry = rest.trim_end();
+        } else {
+            break;
+        }
+    }
+    
+    let (coordinates, resolved) = match entry.split_once(" -> ") {
+        Some((coordinates, resolved)) => (coordinates.trim(), Some(resolved.trim())),
+        None => (entry, None),
+    };
+    
+    if let Some(project) = coordinates.strip_prefix("project ") {
+        return DependencyNode {
+            group: String::new(),
+            name: project.trim().to_string(),
+            version: String::new(),
+            requested_version: None,
+            conflict_resolved: false,
+            omitted,
+            failed,
+            children: Vec::new(),
+        };
+    }
+    
+    let mut parts = coordinates.splitn(3, ':');
+    let group = parts.next().unwrap_or_default().to_string();
+    let name = parts.next().unwrap_or_default().to_string();
+    let requested = parts.next().map(|version| version.to_string());
+    
+    let (version, requested_version) = match (requested, resolved) {
+        (Some(requested), Some(resolved)) => (resolved.to_string(), Some(requested)),
+        // "group:name -> version" is a dependency declared without a version
+        (None, Some(resolved)) => (resolved.to_string(), None),
+        (Some(requested), None) => (requested, None),
+        (None, None) => (String::new(), None),
+    };
+    
+    DependencyNode {
+        group,
+        // Rich versions such as "{strictly 1.8.0}" that resolve to the pinned version aren't conflicts
+        conflict_resolved: requested_version.as_ref().map_or(false, |requested| {
+            requested.trim_matches(|c| c == '{' || c == '}').split_whitespace().last() != Some(version.as_str())
+        }),
+        name,
+        version,
+        requested_version,
+        omitted,
+        failed,
+        children: Vec::new(),
+    }
+}
+
+fn count_conflicts(nodes: &[DependencyNode]) -> usize {
+    nodes
+        .iter()
+        .map(|node| node.conflict_resolved as usize + count_conflicts(&node.children))
+        .sum()
+}
+
+// The "What went wrong" section of a failed Gradle run, or its last line of output
+fn gradle_failure_reason(stderr: &str) -> String {
+    let lines: Vec<&str> = stderr.lines().map(str::trim).collect();
+    
+    if let Some(start) = lines.iter().position(|line| *line == "* What went wrong:") {
+        let reason: Vec<&str> = lines[start + 1..]
+            .iter()
+            .take_while(|line| !line.is_empty() && !line.starts_with("* "))
+            .copied()
+            .collect();
+        if !reason.is_empty() {
+            return reason.join(" ");
+        }
+    }
+    
+    lines.iter().rev().find(|line| !line.is_empty()).map(|line| line.to_string()).unwrap_or_else(|| "no output".to_string())
+}
+
 // Extract dependencies from Gradle file
 fn extract_dependencies(content: &str) -> Vec<GradleDependency> {
     let mut dependencies = Vec::new();
     
     // Match dependencies
-    let dependency_regex = Regex::new(r"(\w+)\s+['\"]([^:]+):([^:]+):([^'\"]+)['\"]").unwrap();
+    let dependency_regex = Regex::new(r#"(\w+)(?:\s+|\s*\(\s*)['"]([^:'"]+):([^:'"]+):([^'"]+)['"]"#).unwrap();
     
     for capture in dependency_regex.captures_iter(content) {
         let configuration = capture[1].to_string();
@@ -399,7 +715,7 @@ fn extract_plugins(content: &str) -> Vec<String> {
     let mut plugins = Vec::new();
     
     // Match plugins
-    let plugin_regex = Regex::new(r"id\s+['\"]([^'\"]+)['\"]").unwrap();
+    let plugin_regex = Regex::new(r#"id(?:\s+|\s*\(\s*)['"]([^'"]+)['"]"#).unwrap();
     
     for capture in plugin_regex.captures_iter(content) {
         plugins.push(capture[1].to_string());
@@ -409,7 +725,7 @@ fn extract_plugins(content: &str) -> Vec<String> {
 }
 
 // Find issues in Gradle file
-fn find_issues(content: &str, dependencies: &[GradleDependency]) -> Vec<GradleBuildIssue> {
+fn find_issues(content: &str, dependencies: &[GradleDependency], dsl: GradleDsl) -> Vec<GradleBuildIssue> {
     let mut issues = Vec::new();
     
     // Check for missing repositories
@@ -432,7 +748,7 @@ fn find_issues(content: &str, dependencies: &[GradleDependency]) -> Vec<GradleBu
     }
     
     // Check for deprecated configurations
-    if content.contains("compile ") {
+    if content.contains("compile ") || content.contains("compile(") {
         issues.push(GradleBuildIssue {
             issue_type: GradleBuildIssueType::DeprecatedConfiguration,
             description: "Using deprecated 'compile' configuration".to_string(),
@@ -441,7 +757,7 @@ fn find_issues(content: &str, dependencies: &[GradleDependency]) -> Vec<GradleBu
         });
     }
     
-    if content.contains("testCompile ") {
+    if content.contains("testCompile ") || content.contains("testCompile(") {
         issues.push(GradleBuildIssue {
             issue_type: GradleBuildIssueType::DeprecatedConfiguration,
             description: "Using deprecated 'testCompile' configuration".to_string(),
@@ -483,7 +799,7 @@ fn find_issues(content: &str, dependencies: &[GradleDependency]) -> Vec<GradleBu
     }
     
     // Check for missing ProGuard configuration
-    if content.contains("minifyEnabled true") && !content.contains("proguardFiles") {
+    if minify_enabled_regex().is_match(content) && !content.contains("proguardFiles") {
         issues.push(GradleBuildIssue {
             issue_type: GradleBuildIssueType::MissingProguardConfig,
             description: "Missing ProGuard configuration".to_string(),
@@ -498,7 +814,10 @@ fn find_issues(content: &str, dependencies: &[GradleDependency]) -> Vec<GradleBu
             issue_type: GradleBuildIssueType::MissingBuildFeatures,
             description: "Missing build features configuration".to_string(),
             severity: IssueSeverity::Warning,
-            suggestion: "Add buildFeatures block with compose = true".to_string(),
+            suggestion: match dsl {
+                GradleDsl::Groovy => "Add buildFeatures block with compose true".to_string(),
+                GradleDsl::Kotlin => "Add buildFeatures block with compose = true".to_string(),
+            },
         });
     }
     
@@ -635,11 +954,14 @@ fn update_dependency_versions(content: &str) -> (String, Vec<DependencyUpdate>)
 }
 
 // Ensure Java compatibility
-fn ensure_java_compatibility(content: &str) -> String {
+fn ensure_java_compatibility(content: &str, dsl: GradleDsl) -> String {
     if content.contains("compileOptions {") {
         content.to_string()
     } else {
-        let java_compatibility_block = "compileOptions {\n        sourceCompatibility JavaVersion.VERSION_1_8\n        targetCompatibility JavaVersion.VERSION_1_8\n    }\n    ";
+        let java_compatibility_block = match dsl {
+            GradleDsl::Groovy => "compileOptions {\n        sourceCompatibility JavaVersion.VERSION_1_8\n        targetCompatibility JavaVersion.VERSION_1_8\n    }\n    ",
+            GradleDsl::Kotlin => "compileOptions {\n        sourceCompatibility = JavaVersion.VERSION_1_8\n        targetCompatibility = JavaVersion.VERSION_1_8\n    }\n    ",
+        };
         
         if content.contains("android {") {
             content.replace(
@@ -653,11 +975,14 @@ fn ensure_java_compatibility(content: &str) -> String {
 }
 
 // Ensure Kotlin JVM target
-fn ensure_kotlin_jvm_target(content: &str) -> String {
+fn ensure_kotlin_jvm_target(content: &str, dsl: GradleDsl) -> String {
     if content.contains("kotlinOptions {") {
         content.to_string()
     } else {
-        let kotlin_options_block = "kotlinOptions {\n        jvmTarget = '1.8'\n    }\n    ";
+        let kotlin_options_block = match dsl {
+            GradleDsl::Groovy => "kotlinOptions {\n        jvmTarget = '1.8'\n    }\n    ",
+            GradleDsl::Kotlin => "kotlinOptions {\n        jvmTarget = \"1.8\"\n    }\n    ",
+        };
         
         if content.contains("compileOptions {") {
             content.replace(
@@ -676,26 +1001,30 @@ fn ensure_kotlin_jvm_target(content: &str) -> String {
 }
 
 // Ensure ProGuard configuration
-fn ensure_proguard_config(content: &str) -> String {
+fn ensure_proguard_config(content: &str, dsl: GradleDsl) -> String {
     if content.contains("proguardFiles") {
         content.to_string()
     } else {
-        content.replace(
-            "minifyEnabled true",
-            "minifyEnabled true\n            proguardFiles getDefaultProguardFile('proguard-android-optimize.txt'), 'proguard-rules.pro'"
-        )
+        let proguard_files = match dsl {
+            GradleDsl::Groovy => "proguardFiles getDefaultProguardFile('proguard-android-optimize.txt'), 'proguard-rules.pro'",
+            GradleDsl::Kotlin => "proguardFiles(getDefaultProguardFile(\"proguard-android-optimize.txt\"), \"proguard-rules.pro\")",
+        };
+        
+        minify_enabled_regex()
+            .replace_all(content, |caps: &regex::Captures| format!("{}\n            {}", &caps[0], proguard_files))
+            .to_string()
     }
 }
 
 // Ensure build features
-fn ensure_build_features(content: &str) -> String {
+fn ensure_build_features(content: &str, dsl: GradleDsl) -> String {
     if content.contains("buildFeatures {") {
         content.to_string()
     } else {
-        let build_features_block = if content.contains("compose") {
-            "buildFeatures {\n        compose true\n    }\n    "
-        } else {
-            "buildFeatures {\n        viewBinding true\n    }\n    "
+        let feature = if content.contains("compose") { "compose" } else { "viewBinding" };
+        let build_features_block = match dsl {
+            GradleDsl::Groovy => format!("buildFeatures {{\n        {} true\n    }}\n    ", feature),
+            GradleDsl::Kotlin => format!("buildFeatures {{\n        {} = true\n    }}\n    ", feature),
         };
         
         if content.contains("compileOptions {") {
@@ -714,6 +1043,27 @@ fn ensure_build_features(content: &str) -> String {
     }
 }
 
+// Detect the build script dialect from the file extension
+fn detect_dsl(file_path: &str) -> GradleDsl {
+    if file_path.ends_with(".kts") {
+        GradleDsl::Kotlin
+    } else {
+        GradleDsl::Groovy
+    }
+}
+
+// Check for deprecated dependency configurations in either dialect
+fn uses_deprecated_configurations(content: &str) -> bool {
+    ["compile ", "compile(", "testCompile ", "testCompile("]
+        .iter()
+        .any(|configuration| content.contains(configuration))
+}
+
+// Match `minifyEnabled true` (Groovy) and `isMinifyEnabled = true` (Kotlin)
+fn minify_enabled_regex() -> Regex {
+    Regex::new(r"(?:isMinifyEnabled|minifyEnabled)\s*=?\s*true").unwrap()
+}
+
 // Extract compile SDK version
 fn extract_compile_sdk_version(content: &str) -> Option<String> {
     let regex = Regex::new(r"compileSdk\s*=?\s*(\d+)").unwrap();
@@ -734,7 +1084,7 @@ fn extract_target_sdk_version(content: &str) -> Option<String> {
 
 // Extract build tools version
 fn extract_build_tools_version(content: &str) -> Option<String> {
-    let regex = Regex::new(r"buildToolsVersion\s*=?\s*['\"]([^'\"]+)['\"]").unwrap();
+    let regex = Regex::new(r#"buildToolsVersion\s*=?\s*['"]([^'"]+)['"]"#).unwrap();
     regex.captures(content).map(|cap| cap[1].to_string())
 }
 
@@ -826,4 +1176,238 @@ fn detect_agp_version(modules: &[GradleModuleReport]) -> Option<String> {
     }
     
     None
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // Write a build script into a fresh scratch directory and return its path
+    fn write_build_file(test_name: &str, file_name: &str, content: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("anyoneide-gradle-{}-{}", test_name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        
+        let path = dir.join(file_name);
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+    
+    #[test]
+    fn add_dependency_appends_to_existing_block() {
+        let path = write_build_file(
+            "existing-block",
+            "build.gradle",
+            "plugins {\n    id 'java'\n}\n\ndependencies {\n    implementation 'com.google.code.gson:gson:2.10.1'\n}\n",
+        );
+        
+        add_dependency(&path, "junit:junit:4.13.2", "testImplementation").unwrap();
+        
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "plugins {\n    id 'java'\n}\n\ndependencies {\n    implementation 'com.google.code.gson:gson:2.10.1'\n    testImplementation 'junit:junit:4.13.2'\n}\n"
+        );
+    }
+    
+    #[test]
+    fn add_dependency_keeps_existing_indentation() {
+        let path = write_build_file(
+            "indentation",
+            "build.gradle",
+            "apply plugin: 'java'\n\ndependencies {\n\timplementation 'a:b:1.0'\n}\n",
+        );
+        
+        add_dependency(&path, "c:d:2.0", "implementation").unwrap();
+        
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "apply plugin: 'java'\n\ndependencies {\n\timplementation 'a:b:1.0'\n\timplementation 'c:d:2.0'\n}\n"
+        );
+    }
+    
+    #[test]
+    fn add_dependency_creates_missing_block() {
+        let path = write_build_file("missing-block", "build.gradle", "plugins {\n    id 'java'\n}");
+        
+        add_dependency(&path, "junit:junit:4.13.2", "testImplementation").unwrap();
+        
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "plugins {\n    id 'java'\n}\n\ndependencies {\n    testImplementation 'junit:junit:4.13.2'\n}\n"
+        );
+    }
+    
+    #[test]
+    fn add_dependency_expands_empty_single_line_block() {
+        let path = write_build_file("empty-block", "build.gradle", "plugins { id 'java' }\ndependencies {}\n");
+        
+        add_dependency(&path, "a:b:1.0", "implementation").unwrap();
+        
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "plugins { id 'java' }\ndependencies {\n    implementation 'a:b:1.0'\n}\n"
+        );
+    }
+    
+    #[test]
+    fn add_dependency_ignores_nested_dependencies_blocks() {
+        let path = write_build_file(
+            "nested-block",
+            "build.gradle",
+            "buildscript {\n    dependencies {\n        classpath 'x:y:1.0'\n    }\n}\n\ndependencies {\n    implementation 'a:b:1.0'\n}\n",
+        );
+        
+        add_dependency(&path, "c:d:2.0", "implementation").unwrap();
+        
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "buildscript {\n    dependencies {\n        classpath 'x:y:1.0'\n    }\n}\n\ndependencies {\n    implementation 'a:b:1.0'\n    implementation 'c:d:2.0'\n}\n"
+        );
+    }
+    
+    #[test]
+    fn add_dependency_rejects_duplicates_and_unknown_files() {
+        let path = write_build_file("duplicate", "build.gradle", "dependencies {\n    implementation 'a:b:1.0'\n}\n");
+        assert!(add_dependency(&path, "a:b:1.0", "implementation").is_err());
+        
+        let path = write_build_file("unrecognized", "build.gradle", "hello world\n");
+        assert!(add_dependency(&path, "a:b:1.0", "implementation").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello world\n");
+    }
+    
+    #[test]
+    fn add_dependency_uses_groovy_syntax_for_gradle_files() {
+        let path = write_build_file("groovy-dsl", "build.gradle", "plugins {\n    id 'java'\n}\n\ndependencies {\n}\n");
+        
+        add_dependency(&path, "a:b:1.0", "implementation").unwrap();
+        
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("    implementation 'a:b:1.0'\n"));
+        assert!(!content.contains("implementation(\""));
+    }
+    
+    #[test]
+    fn add_dependency_uses_kotlin_syntax_for_kts_files() {
+        let path = write_build_file("kotlin-dsl", "build.gradle.kts", "plugins {\n    id(\"java\")\n}\n\ndependencies {\n}\n");
+        
+        add_dependency(&path, "a:b:1.0", "implementation").unwrap();
+        
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("    implementation(\"a:b:1.0\")\n"));
+        assert!(!content.contains("implementation 'a:b:1.0'"));
+        
+        // Already declared, even though it was added in the other dialect's form
+        assert!(add_dependency(&path, "a:b:1.0", "implementation").is_err());
+    }
+    
+    #[test]
+    fn remove_dependency_handles_both_dialects() {
+        let groovy = write_build_file("remove-groovy", "build.gradle", "dependencies {\n    implementation 'a:b:1.0'\n    implementation 'c:d:2.0'\n}\n");
+        remove_dependency(&groovy, "a:b:1.0").unwrap();
+        assert_eq!(fs::read_to_string(&groovy).unwrap(), "dependencies {\n    implementation 'c:d:2.0'\n}\n");
+        
+        let kotlin = write_build_file("remove-kotlin", "build.gradle.kts", "dependencies {\n    implementation(\"a:b:1.0\")\n    implementation(\"c:d:2.0\")\n}\n");
+        remove_dependency(&kotlin, "a:b:1.0").unwrap();
+        assert_eq!(fs::read_to_string(&kotlin).unwrap(), "dependencies {\n    implementation(\"c:d:2.0\")\n}\n");
+    }
+    
+    #[test]
+    fn fix_common_issues_emits_kotlin_syntax_for_kts_files() {
+        let path = write_build_file(
+            "fix-kotlin",
+            "build.gradle.kts",
+            "plugins {\n    kotlin(\"android\")\n}\n\nandroid {\n    buildTypes {\n        release {\n            isMinifyEnabled = true\n        }\n    }\n}\n",
+        );
+        
+        fix_common_issues(&path).unwrap();
+        
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("sourceCompatibility = JavaVersion.VERSION_1_8"));
+        assert!(content.contains("jvmTarget = \"1.8\""));
+        assert!(content.contains("proguardFiles(getDefaultProguardFile(\"proguard-android-optimize.txt\"), \"proguard-rules.pro\")"));
+        assert!(!content.contains('\''));
+    }
+    
+    #[test]
+    fn fix_common_issues_emits_groovy_syntax_for_gradle_files() {
+        let path = write_build_file(
+            "fix-groovy",
+            "build.gradle",
+            "plugins {\n    id 'kotlin-android'\n}\n\nandroid {\n    buildTypes {\n        release {\n            minifyEnabled true\n        }\n    }\n}\n",
+        );
+        
+        fix_common_issues(&path).unwrap();
+        
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("sourceCompatibility JavaVersion.VERSION_1_8"));
+        assert!(content.contains("jvmTarget = '1.8'"));
+        assert!(content.contains("proguardFiles getDefaultProguardFile('proguard-android-optimize.txt'), 'proguard-rules.pro'"));
+        assert!(!content.contains("(\""));
+    }
+    
+    #[test]
+    fn analysis_reports_the_dsl() {
+        let groovy = write_build_file("analyze-groovy", "build.gradle", "plugins {\n    id 'java'\n}\n");
+        let kotlin = write_build_file("analyze-kotlin", "build.gradle.kts", "plugins {\n    id(\"java\")\n}\n");
+        
+        assert_eq!(analyze_gradle_file(&groovy).unwrap().dsl, GradleDsl::Groovy);
+        assert_eq!(analyze_gradle_file(&kotlin).unwrap().dsl, GradleDsl::Kotlin);
+        assert_eq!(serde_json::to_value(GradleDsl::Kotlin).unwrap(), serde_json::json!("kotlin"));
+    }
+    
+    #[test]
+    fn dependency_tree_is_parsed_from_gradle_output() {
+        let output = r"
+> Task :app:dependencies
+
+debugRuntimeClasspath - Resolved configuration for runtime for variant: debug
++--- project :lib
+|    \--- com.squareup.okhttp3:okhttp:4.9.0 -> 4.12.0
+|         \--- com.squareup.okio:okio:3.6.0 (*)
++--- androidx.core:core-ktx:1.9.0
+|    +--- androidx.annotation:annotation:1.1.0 -> 1.5.0 (*)
+|    \--- org.jetbrains.kotlin:kotlin-stdlib:1.8.0 (c)
++--- org.jetbrains.kotlin:kotlin-stdlib:{strictly 1.8.0} -> 1.8.0 (c)
++--- com.example:missing:1.0 FAILED
++--- com.google.guava:guava -> 31.1-android
+\--- com.google.code.gson:gson:2.10.1 (n)
+
+(c) - A dependency constraint, not a dependency. The dependency affected by the constraint occurs elsewhere in the tree.
+(*) - Indicates repeated occurrences of a transitive dependency subtree.
+";
+        let roots = parse_dependency_tree(output);
+        assert_eq!(roots.len(), 6);
+        
+        // Depth follows the column of the marker
+        let lib = &roots[0];
+        assert_eq!((lib.group.as_str(), lib.name.as_str(), lib.version.as_str()), ("", ":lib", ""));
+        let okhttp = &lib.children[0];
+        assert_eq!((okhttp.version.as_str(), okhttp.requested_version.as_deref()), ("4.12.0", Some("4.9.0")));
+        assert!(okhttp.conflict_resolved);
+        assert_eq!(okhttp.children[0].name, "okio");
+        assert!(okhttp.children[0].omitted);
+        
+        let core = &roots[1];
+        assert_eq!(core.children.len(), 2);
+        let annotation = &core.children[0];
+        assert_eq!((annotation.version.as_str(), annotation.requested_version.as_deref()), ("1.5.0", Some("1.1.0")));
+        assert!(annotation.conflict_resolved && annotation.omitted);
+        let constraint = &core.children[1];
+        assert_eq!(constraint.version, "1.8.0");
+        assert!(!constraint.omitted && !constraint.failed && !constraint.conflict_resolved);
+        
+        // A strict version that resolves to itself is not a conflict
+        let strict = &roots[2];
+        assert_eq!((strict.version.as_str(), strict.requested_version.as_deref()), ("1.8.0", Some("{strictly 1.8.0}")));
+        assert!(!strict.conflict_resolved);
+        
+        assert!(roots[3].failed);
+        assert_eq!(roots[3].version, "1.0");
+        
+        let guava = &roots[4];
+        assert_eq!((guava.version.as_str(), guava.requested_version.as_deref()), ("31.1-android", None));
+        assert!(!guava.conflict_resolved);
+        
+        assert_eq!((roots[5].name.as_str(), roots[5].version.as_str()), ("gson", "2.10.1"));
+        assert_eq!(count_conflicts(&roots), 2);
+    }
+}
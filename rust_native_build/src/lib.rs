@@ -23,6 +23,18 @@ mod extensions;
 mod pluginsystem;
 mod gradlefilemodifier;
 mod sdkmanager;
+mod git;
+mod search;
+
+lazy_static! {
+    // Toolchain used by the build entry points, set from the SDK manager when cargo is not on PATH
+    static ref BUILD_TOOLCHAIN: Mutex<Option<buildsystem::ToolchainPaths>> = Mutex::new(None);
+}
+
+// Get the configured build toolchain, if any
+fn build_toolchain() -> Option<buildsystem::ToolchainPaths> {
+    BUILD_TOOLCHAIN.lock().unwrap().clone()
+}
 
 // Build output message
 #[derive(Serialize, Deserialize)]
@@ -87,6 +99,31 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Compan
     output.into_raw()
 }
 
+// Update the build configuration from JSON; fields left out keep their defaults, e.g.
+// {"enable_error_recovery": false} for builds started afterwards
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeSetBuildConfig(
+    env: JNIEnv,
+    _class: JClass,
+    config_json: JString,
+) -> jstring {
+    let config_json: String = env
+        .get_string(config_json)
+        .expect("Failed to get config JSON string")
+        .into();
+    
+    let json = match serde_json::from_str::<buildsystem::BuildConfig>(&config_json) {
+        Ok(config) => {
+            buildsystem::set_build_config(config);
+            serde_json::json!({ "success": true }).to_string()
+        }
+        Err(e) => serde_json::json!({ "success": false, "error": format!("Invalid build config: {}", e) }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
 // Check if a project is a valid Rust project
 #[no_mangle]
 pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeIsValidRustProject(
@@ -119,216 +156,1921 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Compan
         .expect("Failed to get project path string")
         .into();
     
-    let project_info = buildsystem::get_project_info(&project_path);
-    let json = serde_json::to_string(&project_info).unwrap_or_else(|_| "{}".to_string());
+    let project_info = buildsystem::get_project_info(&project_path);
+    let json = serde_json::to_string(&project_info).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Check build system health
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeCheckBuildSystemHealth(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    // An empty path checks the toolchain without a project
+    let project_path = Some(project_path.as_str()).filter(|path| !path.is_empty());
+    let health_check = buildsystem::check_build_system_health(project_path);
+    let json = serde_json::to_string(&health_check).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Build with the Rust toolchain managed under an SDK root; an empty root goes back to cargo on PATH
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeSetBuildToolchain(
+    env: JNIEnv,
+    _class: JClass,
+    sdk_root: JString,
+) -> jni::sys::jboolean {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    if sdk_root.is_empty() {
+        *BUILD_TOOLCHAIN.lock().unwrap() = None;
+        return 1; // true
+    }
+    
+    let sdk_manager = sdkmanager::SdkManager::new(Path::new(&sdk_root));
+    if !sdk_manager.is_rust_installed() {
+        return 0; // false
+    }
+    
+    *BUILD_TOOLCHAIN.lock().unwrap() = Some(buildsystem::ToolchainPaths::from_sdk_manager(&sdk_manager));
+    1 // true
+}
+
+// Build a project
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeBuildProject(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    build_type: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let build_type: String = env
+        .get_string(build_type)
+        .expect("Failed to get build type string")
+        .into();
+    
+    let result = buildsystem::build_project(&project_path, &build_type, build_toolchain().as_ref());
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Build a project with JSON options: {"profile": ..., "target": ..., "dry_run": ...}. An empty
+// string builds the dev profile for the host
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeBuildProjectWithOptions(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    options_json: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let options_json: String = env
+        .get_string(options_json)
+        .expect("Failed to get options JSON string")
+        .into();
+    
+    let options = if options_json.trim().is_empty() {
+        Ok(buildsystem::BuildOptions::default())
+    } else {
+        serde_json::from_str::<buildsystem::BuildOptions>(&options_json)
+    };
+    
+    let json = match options {
+        Ok(options) => {
+            let result = buildsystem::build_project_with_options(&project_path, &options, build_toolchain().as_ref());
+            serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string())
+        }
+        Err(e) => serde_json::json!({ "success": false, "error": format!("Invalid build options: {}", e) }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Build a Cargo, Gradle or hybrid project according to the files it contains
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeBuildAuto(
+    env: JNIEnv,
+    _class: JClass,
+    sdk_root: JString,
+    project_path: JString,
+    build_type: JString,
+) -> jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let build_type: String = env
+        .get_string(build_type)
+        .expect("Failed to get build type string")
+        .into();
+    
+    let sdk_manager = sdkmanager::SdkManager::new(Path::new(&sdk_root));
+    let result = buildsystem::build_auto(&project_path, &build_type, &sdk_manager, build_toolchain().as_ref());
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Run Gradle tasks given as a JSON array, e.g. ["assembleDebug"], with a JSON array of extra
+// arguments; empty strings mean no tasks or arguments
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeRunGradle(
+    env: JNIEnv,
+    _class: JClass,
+    sdk_root: JString,
+    project_path: JString,
+    tasks_json: JString,
+    args_json: JString,
+) -> jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let tasks_json: String = env
+        .get_string(tasks_json)
+        .expect("Failed to get tasks JSON string")
+        .into();
+    
+    let args_json: String = env
+        .get_string(args_json)
+        .expect("Failed to get args JSON string")
+        .into();
+    
+    let parse_list = |json: &str| -> serde_json::Result<Vec<String>> {
+        if json.trim().is_empty() { Ok(Vec::new()) } else { serde_json::from_str(json) }
+    };
+    
+    let json = match (parse_list(&tasks_json), parse_list(&args_json)) {
+        (Ok(tasks), Ok(args)) => {
+            let sdk_manager = sdkmanager::SdkManager::new(Path::new(&sdk_root));
+            let result = buildsystem::run_gradle(&project_path, tasks, args, &sdk_manager);
+            serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string())
+        }
+        (Err(e), _) | (_, Err(e)) => serde_json::json!({ "success": false, "error": format!("Invalid tasks or arguments: {}", e) }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// List the build profiles a project defines, for use as a build type
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeListBuildProfiles(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let json = match buildsystem::list_build_profiles(&project_path) {
+        Ok(profiles) => serde_json::json!({ "success": true, "profiles": profiles }).to_string(),
+        Err(e) => serde_json::json!({ "success": false, "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Start a build in the background and return its build id
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeStartBuild(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    build_type: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let build_type: String = env
+        .get_string(build_type)
+        .expect("Failed to get build type string")
+        .into();
+    
+    let build_id = buildsystem::start_build(&project_path, &build_type, build_toolchain());
+    
+    let output = env.new_string(build_id).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Cancel a background build
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeCancelBuild(
+    env: JNIEnv,
+    _class: JClass,
+    build_id: JString,
+) -> jni::sys::jboolean {
+    let build_id: String = env
+        .get_string(build_id)
+        .expect("Failed to get build id string")
+        .into();
+    
+    match buildsystem::cancel_build(&build_id) {
+        Ok(_) => 1, // true
+        Err(_) => 0, // false
+    }
+}
+
+// Poll a background build for its status
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativePollBuild(
+    env: JNIEnv,
+    _class: JClass,
+    build_id: JString,
+) -> jstring {
+    let build_id: String = env
+        .get_string(build_id)
+        .expect("Failed to get build id string")
+        .into();
+    
+    let json = match buildsystem::poll_build(&build_id) {
+        Ok(status) => serde_json::to_string(&status).unwrap_or_else(|_| "{}".to_string()),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Poll a background build for output past a cursor
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativePollBuildOutput(
+    env: JNIEnv,
+    _class: JClass,
+    build_id: JString,
+    since_index: jni::sys::jint,
+) -> jstring {
+    let build_id: String = env
+        .get_string(build_id)
+        .expect("Failed to get build id string")
+        .into();
+    
+    let json = match buildsystem::poll_build_output(&build_id, since_index.max(0) as usize) {
+        Ok(chunk) => serde_json::to_string(&chunk).unwrap_or_else(|_| "{}".to_string()),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Start watch mode: build now and again whenever the project's sources change
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeStartWatch(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    build_type: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let build_type: String = env
+        .get_string(build_type)
+        .expect("Failed to get build type string")
+        .into();
+    
+    let json = match buildsystem::watch_build(&project_path, &build_type, build_toolchain()) {
+        Ok(watch_id) => serde_json::json!({ "watch_id": watch_id }).to_string(),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Poll a watch for the builds it has finished since the last poll
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativePollWatch(
+    env: JNIEnv,
+    _class: JClass,
+    watch_id: JString,
+) -> jstring {
+    let watch_id: String = env
+        .get_string(watch_id)
+        .expect("Failed to get watch id string")
+        .into();
+    
+    let json = match buildsystem::poll_watch(&watch_id) {
+        Ok(status) => serde_json::to_string(&status).unwrap_or_else(|_| "{}".to_string()),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Stop a watch
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeStopWatch(
+    env: JNIEnv,
+    _class: JClass,
+    watch_id: JString,
+) -> jni::sys::jboolean {
+    let watch_id: String = env
+        .get_string(watch_id)
+        .expect("Failed to get watch id string")
+        .into();
+    
+    match buildsystem::stop_watch(&watch_id) {
+        Ok(_) => 1, // true
+        Err(_) => 0, // false
+    }
+}
+
+// Clean a project
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeCleanProject(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    target: JString,
+    release: jni::sys::jint,
+    package: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let target: String = env
+        .get_string(target)
+        .expect("Failed to get target string")
+        .into();
+    
+    let package: String = env
+        .get_string(package)
+        .expect("Failed to get package string")
+        .into();
+    
+    // Empty strings and a negative release flag mean "don't restrict"
+    let options = buildsystem::CleanOptions {
+        target: Some(target).filter(|target| !target.is_empty()),
+        release: if release < 0 { None } else { Some(release != 0) },
+        package: Some(package).filter(|package| !package.is_empty()),
+    };
+    
+    let result = buildsystem::clean_project(&project_path, &options, build_toolchain().as_ref());
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Test a project
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeTestProject(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    release: jni::sys::jboolean,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let release = release != 0;
+    
+    let result = buildsystem::test_project(&project_path, release, None, build_toolchain().as_ref());
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Generate documentation
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeGenerateDocs(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    open: jni::sys::jboolean,
+    no_deps: jni::sys::jboolean,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let result = buildsystem::generate_docs(&project_path, open != 0, no_deps != 0, build_toolchain().as_ref());
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Test a project, running only tests whose names match a filter
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeTestProjectFiltered(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    release: jni::sys::jboolean,
+    test_filter: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let test_filter: String = env
+        .get_string(test_filter)
+        .expect("Failed to get test filter string")
+        .into();
+    
+    let test_filter = Some(test_filter.as_str()).filter(|filter| !filter.is_empty());
+    
+    let result = buildsystem::test_project(&project_path, release != 0, test_filter, build_toolchain().as_ref());
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Run benchmarks for a project
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeBenchProject(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    filter: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let filter: String = env
+        .get_string(filter)
+        .expect("Failed to get filter string")
+        .into();
+    
+    let filter = Some(filter.as_str()).filter(|filter| !filter.is_empty());
+    
+    let result = buildsystem::bench_project(&project_path, filter, build_toolchain().as_ref());
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Build a Rust project and report how long each crate took to compile
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeGenerateRustBuildReport(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let report = buildsystem::generate_build_report(&project_path, build_toolchain().as_ref());
+    let json = serde_json::to_string(&report).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// List the Android targets with their ABIs and whether rustup has each one installed
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeGetAndroidTargets(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let targets = buildsystem::android_targets(build_toolchain().as_ref());
+    let json = serde_json::to_string(&targets).unwrap_or_else(|_| "[]".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Check a project for errors without building it
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeCheckProject(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    target: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let target: String = env
+        .get_string(target)
+        .expect("Failed to get target string")
+        .into();
+    
+    let target = Some(target.as_str()).filter(|target| !target.is_empty());
+    
+    let result = buildsystem::check_project(&project_path, target, build_toolchain().as_ref());
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Build for Android target
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeBuildForAndroidTarget(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    target: JString,
+    release: jni::sys::jboolean,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let target: String = env
+        .get_string(target)
+        .expect("Failed to get target string")
+        .into();
+    
+    let release = release != 0;
+    
+    let result = buildsystem::build_for_android_target(&project_path, &target, release, build_toolchain().as_ref());
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Explain why linking for an Android target fails; an empty list means no problem was found
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeDiagnoseAndroidLink(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    target: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let target: String = env
+        .get_string(target)
+        .expect("Failed to get target string")
+        .into();
+    
+    let diagnostics = buildsystem::diagnose_android_link(&project_path, &target, build_toolchain().as_ref());
+    let json = serde_json::to_string(&diagnostics).unwrap_or_else(|_| "[]".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Build several Android targets, given as a JSON array of triples; empty builds all four
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeBuildAllAndroidTargets(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    targets_json: JString,
+    release: jni::sys::jboolean,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let targets_json: String = env
+        .get_string(targets_json)
+        .expect("Failed to get targets JSON string")
+        .into();
+    
+    let targets = if targets_json.trim().is_empty() {
+        Ok(Vec::new())
+    } else {
+        serde_json::from_str::<Vec<String>>(&targets_json)
+    };
+    
+    let json = match targets {
+        Ok(targets) => {
+            let result = buildsystem::build_all_android_targets(&project_path, targets, release != 0, build_toolchain().as_ref());
+            serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string())
+        }
+        Err(e) => serde_json::json!({ "success": false, "error": format!("Invalid targets: {}", e) }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Add or update a dependency in Cargo.toml. An empty version lets cargo pick one, features is a
+// JSON array of feature names or empty
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeAddCargoDependency(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    crate_name: JString,
+    version: JString,
+    features_json: JString,
+    dev: jni::sys::jboolean,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let crate_name: String = env
+        .get_string(crate_name)
+        .expect("Failed to get crate name string")
+        .into();
+    
+    let version: String = env
+        .get_string(version)
+        .expect("Failed to get version string")
+        .into();
+    
+    let features_json: String = env
+        .get_string(features_json)
+        .expect("Failed to get features JSON string")
+        .into();
+    
+    let version = Some(version).filter(|version| !version.is_empty());
+    let features = if features_json.trim().is_empty() {
+        Ok(Vec::new())
+    } else {
+        serde_json::from_str::<Vec<String>>(&features_json).map_err(|e| anyhow!("Invalid features: {}", e))
+    };
+    
+    let result = features.and_then(|features| {
+        buildsystem::add_cargo_dependency(&project_path, &crate_name, version, features, dev != 0, build_toolchain().as_ref())
+    });
+    
+    let json = match result {
+        Ok(()) => serde_json::json!({ "success": true }).to_string(),
+        Err(e) => serde_json::json!({ "success": false, "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Remove a dependency from Cargo.toml
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeRemoveCargoDependency(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    crate_name: JString,
+    dev: jni::sys::jboolean,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let crate_name: String = env
+        .get_string(crate_name)
+        .expect("Failed to get crate name string")
+        .into();
+    
+    let json = match buildsystem::remove_cargo_dependency(&project_path, &crate_name, dev != 0, build_toolchain().as_ref()) {
+        Ok(()) => serde_json::json!({ "success": true }).to_string(),
+        Err(e) => serde_json::json!({ "success": false, "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Direct dependencies with newer releases; `offline` is set when crates.io can't be reached
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeCheckOutdated(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let report = buildsystem::check_outdated(&project_path, build_toolchain().as_ref());
+    let json = serde_json::to_string(&report).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Known-vulnerable dependencies from cargo audit; `installed` is false when cargo-audit is missing
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeAudit(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let report = buildsystem::audit(&project_path, build_toolchain().as_ref());
+    let json = serde_json::to_string(&report).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Create a Rust project from a template ("bin", "lib", "android-jni" or "workspace")
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeCreateProject(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    template: JString,
+    name: JString,
+    force: jni::sys::jboolean,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let template: String = env
+        .get_string(template)
+        .expect("Failed to get template string")
+        .into();
+    
+    let name: String = env
+        .get_string(name)
+        .expect("Failed to get name string")
+        .into();
+    
+    let json = match buildsystem::create_project(&project_path, &template, &name, force != 0) {
+        Ok(files) => serde_json::json!({ "success": true, "files": files }).to_string(),
+        Err(e) => serde_json::json!({ "success": false, "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Generate Android bindings
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeGenerateAndroidBindings(
+    env: JNIEnv,
+    _class: JClass,
+    project_path: JString,
+    package_name: JString,
+    api_level: jni::sys::jint,
+) -> jstring {
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let package_name: String = env
+        .get_string(package_name)
+        .expect("Failed to get package name string")
+        .into();
+    
+    let result = buildsystem::generate_android_bindings(&project_path, &package_name, api_level as u32);
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// List Android devices visible to adb from the SDK under sdk_root
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeAdbDevices(
+    env: JNIEnv,
+    _class: JClass,
+    sdk_root: JString,
+) -> jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let sdk_manager = sdkmanager::SdkManager::new(Path::new(&sdk_root));
+    let result = match buildsystem::adb_devices(&sdk_manager) {
+        Ok(devices) => serde_json::to_string(&devices).unwrap_or_else(|_| "[]".to_string()),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(result).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Pair with a device for wireless debugging
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeAdbPair(
+    env: JNIEnv,
+    _class: JClass,
+    sdk_root: JString,
+    host_port: JString,
+    pairing_code: JString,
+) -> jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let host_port: String = env
+        .get_string(host_port)
+        .expect("Failed to get host and port string")
+        .into();
+    
+    let pairing_code: String = env
+        .get_string(pairing_code)
+        .expect("Failed to get pairing code string")
+        .into();
+    
+    let sdk_manager = sdkmanager::SdkManager::new(Path::new(&sdk_root));
+    let result = buildsystem::adb_pair(&host_port, &pairing_code, &sdk_manager);
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Connect to a device over the network
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeAdbConnect(
+    env: JNIEnv,
+    _class: JClass,
+    sdk_root: JString,
+    host_port: JString,
+) -> jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let host_port: String = env
+        .get_string(host_port)
+        .expect("Failed to get host and port string")
+        .into();
+    
+    let sdk_manager = sdkmanager::SdkManager::new(Path::new(&sdk_root));
+    let result = buildsystem::adb_connect(&host_port, &sdk_manager);
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Zipalign and sign an APK; an empty keystore signs with the debug key and an empty output
+// path writes "<name>-signed.apk" next to the input
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeSignApk(
+    env: JNIEnv,
+    _class: JClass,
+    sdk_root: JString,
+    unsigned_apk: JString,
+    keystore: JString,
+    key_alias: JString,
+    store_pass: JString,
+    key_pass: JString,
+    output: JString,
+) -> jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let unsigned_apk: String = env
+        .get_string(unsigned_apk)
+        .expect("Failed to get unsigned APK path string")
+        .into();
+    
+    let keystore: String = env
+        .get_string(keystore)
+        .expect("Failed to get keystore path string")
+        .into();
+    
+    let key_alias: String = env
+        .get_string(key_alias)
+        .expect("Failed to get key alias string")
+        .into();
+    
+    let store_pass: String = env
+        .get_string(store_pass)
+        .expect("Failed to get store password string")
+        .into();
+    
+    let key_pass: String = env
+        .get_string(key_pass)
+        .expect("Failed to get key password string")
+        .into();
+    
+    let output: String = env
+        .get_string(output)
+        .expect("Failed to get output path string")
+        .into();
+    
+    let sdk_manager = sdkmanager::SdkManager::new(Path::new(&sdk_root));
+    let keystore = Some(keystore.as_str()).filter(|keystore| !keystore.is_empty());
+    
+    let result = buildsystem::sign_apk(&unsigned_apk, keystore, &key_alias, &store_pass, &key_pass, &output, &sdk_manager);
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Install an APK with adb; an empty serial lets adb pick the only connected device
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeAdbInstall(
+    env: JNIEnv,
+    _class: JClass,
+    sdk_root: JString,
+    apk_path: JString,
+    device_serial: JString,
+) -> jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let apk_path: String = env
+        .get_string(apk_path)
+        .expect("Failed to get APK path string")
+        .into();
+    
+    let device_serial: String = env
+        .get_string(device_serial)
+        .expect("Failed to get device serial string")
+        .into();
+    
+    let sdk_manager = sdkmanager::SdkManager::new(Path::new(&sdk_root));
+    let device_serial = Some(device_serial).filter(|serial| !serial.is_empty());
+    
+    let result = buildsystem::adb_install(&apk_path, device_serial, &sdk_manager);
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Start an adb install in the background and return its build id, for streaming with nativePollBuildOutput
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeStartAdbInstall(
+    env: JNIEnv,
+    _class: JClass,
+    sdk_root: JString,
+    apk_path: JString,
+    device_serial: JString,
+) -> jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let apk_path: String = env
+        .get_string(apk_path)
+        .expect("Failed to get APK path string")
+        .into();
+    
+    let device_serial: String = env
+        .get_string(device_serial)
+        .expect("Failed to get device serial string")
+        .into();
+    
+    let sdk_manager = sdkmanager::SdkManager::new(Path::new(&sdk_root));
+    let device_serial = Some(device_serial).filter(|serial| !serial.is_empty());
+    
+    let build_id = buildsystem::start_adb_install(&apk_path, device_serial, sdk_manager);
+    
+    let output = env.new_string(build_id).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Compiler-related functions
+
+// Compile a single .rs, .java or .kt file without a project.
+// language is "rust", "java" or "kotlin", or empty to infer it from the extension. sdk_root points at
+// the managed SDK whose compilers are preferred; empty uses the compilers on PATH.
+// Returns a CompilationResult as JSON: success, output, errors, warnings, duration_ms and artifacts,
+// or {"error": ...} when the file or compiler cannot be found.
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeCompileSingleFile(
+    env: JNIEnv,
+    _class: JClass,
+    file_path: JString,
+    language: JString,
+    output_dir: JString,
+    sdk_root: JString,
+) -> jstring {
+    let file_path: String = env
+        .get_string(file_path)
+        .expect("Failed to get file path string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let output_dir: String = env
+        .get_string(output_dir)
+        .expect("Failed to get output directory string")
+        .into();
+    
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let sdk_manager = Some(sdk_root)
+        .filter(|root| !root.is_empty())
+        .map(|root| sdkmanager::SdkManager::new(Path::new(&root)));
+    
+    let result = match compiler::compile_single_file(&file_path, &language, &output_dir, sdk_manager.as_ref()) {
+        Ok(result) => serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string()),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(result).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Editor-related functions
+
+// Initialize the editor
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeInitializeEditor(
+    env: JNIEnv,
+    _class: JClass,
+) -> jni::sys::jboolean {
+    match editor::initialize_editor() {
+        Ok(_) => 1, // true
+        Err(_) => 0, // false
+    }
+}
+
+// Detect a file's language from its name and content; returns an empty string when unknown
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeDetectLanguage(
+    env: JNIEnv,
+    _class: JClass,
+    file_name: JString,
+    content: JString,
+) -> jstring {
+    let file_name: String = env
+        .get_string(file_name)
+        .expect("Failed to get file name string")
+        .into();
+    
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language = editor::detect_language(&file_name, &content).unwrap_or_default();
+    
+    let output = env.new_string(language).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Highlight syntax. Returns {"highlights", "degraded"}, where degraded is true when the document
+// was over the size threshold and only got keywords, strings, comments and the like
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeHighlightSyntax(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    language: JString,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let highlights = editor::highlight_syntax(&content, &language);
+    let json = serde_json::to_string(&highlights).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Highlight syntax as an operation nativeCancelEditorOp can stop for `doc_id`. Returns
+// {"cancelled", "result"} like nativeParseDocumentStructure, with nativeHighlightSyntax's
+// {"highlights", "degraded"} as the result; the highlights are partial when cancelled is true
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeHighlightDocument(
+    env: JNIEnv,
+    _class: JClass,
+    doc_id: JString,
+    content: JString,
+    language: JString,
+) -> jstring {
+    let doc_id: String = env
+        .get_string(doc_id)
+        .expect("Failed to get document id string")
+        .into();
+    
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let highlights = editor::highlight_syntax_for_document(&doc_id, &content, &language);
+    let json = serde_json::to_string(&highlights).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Stop the highlighting or structure parsing running for a document, which then returns what it
+// has computed so far. Returns false when nothing is running for the document
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeCancelEditorOp(
+    env: JNIEnv,
+    _class: JClass,
+    doc_id: JString,
+) -> jni::sys::jboolean {
+    let doc_id: String = env
+        .get_string(doc_id)
+        .expect("Failed to get document id string")
+        .into();
+    
+    if editor::cancel_editor_op(&doc_id) {
+        1 // true
+    } else {
+        0 // false
+    }
+}
+
+// Highlight a JSON array of {"id", "content", "language"} documents in one call, returning a
+// JSON array of {"id", "highlights", "cancelled", "degraded"} in the same order
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeHighlightBatch(
+    env: JNIEnv,
+    _class: JClass,
+    requests_json: JString,
+) -> jstring {
+    let requests_json: String = env
+        .get_string(requests_json)
+        .expect("Failed to get requests JSON string")
+        .into();
+    
+    let json = match serde_json::from_str::<Vec<editor::HighlightRequest>>(&requests_json) {
+        Ok(requests) => serde_json::to_string(&editor::highlight_batch(requests)).unwrap_or_else(|_| "[]".to_string()),
+        Err(e) => serde_json::json!({ "error": format!("Invalid highlight requests: {}", e) }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Highlight syntax with offsets in UTF-16 code units, matching Java string indices. Returns
+// {"highlights", "degraded"} like nativeHighlightSyntax
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeHighlightSyntaxUtf16(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    language: JString,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let highlights = editor::highlight_syntax_utf16(&content, &language);
+    let json = serde_json::to_string(&highlights).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Get semantic tokens for richer highlighting; empty when the language has no tree-sitter parser
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeSemanticTokens(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    language: JString,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let tokens = editor::semantic_tokens(&content, &language);
+    let json = serde_json::to_string(&tokens).unwrap_or_else(|_| "[]".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Get code completions
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeGetCompletions(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    position: jni::sys::jint,
+    language: JString,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let completions = editor::get_completions(&content, position as usize, &language);
+    let json = serde_json::to_string(&completions).unwrap_or_else(|_| "[]".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Get code completions as {"trigger": ..., "items": [...]}, where trigger is the member-access
+// token before the cursor, e.g. "." or "::", or null when completing keywords and snippets
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeGetCompletionList(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    position: jni::sys::jint,
+    language: JString,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let completions = editor::get_completion_list(&content, position as usize, &language);
+    let json = serde_json::to_string(&completions).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// nativeGetCompletionList with the position in UTF-16 code units
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeGetCompletionListUtf16(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    position: jni::sys::jint,
+    language: JString,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let completions = editor::get_completion_list_utf16(&content, position as usize, &language);
+    let json = serde_json::to_string(&completions).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Register a user-defined completion snippet for a language
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeRegisterSnippet(
+    env: JNIEnv,
+    _class: JClass,
+    language: JString,
+    snippet_json: JString,
+) -> jstring {
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let snippet_json: String = env
+        .get_string(snippet_json)
+        .expect("Failed to get snippet JSON string")
+        .into();
+    
+    let result = serde_json::from_str::<editor::SnippetDef>(&snippet_json)
+        .map_err(|e| anyhow!("Invalid snippet: {}", e))
+        .and_then(|snippet| editor::register_snippet(&language, snippet));
+    
+    let json = match result {
+        Ok(_) => serde_json::json!({ "success": true }).to_string(),
+        Err(e) => serde_json::json!({ "success": false, "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Remove registered snippets for a language, or for all languages when empty
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeClearSnippets(
+    env: JNIEnv,
+    _class: JClass,
+    language: JString,
+) -> jni::sys::jboolean {
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    editor::clear_snippets(&language);
+    1 // true
+}
+
+// Set the options used when formatting, e.g. {"indent_unit": "  ", "sort_properties": true}
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeSetFormatOptions(
+    env: JNIEnv,
+    _class: JClass,
+    options_json: JString,
+) -> jstring {
+    let options_json: String = env
+        .get_string(options_json)
+        .expect("Failed to get options JSON string")
+        .into();
+    
+    let json = match serde_json::from_str::<editor::FormatOptions>(&options_json) {
+        Ok(options) => {
+            editor::set_format_options(options);
+            serde_json::json!({ "success": true }).to_string()
+        }
+        Err(e) => serde_json::json!({ "success": false, "error": format!("Invalid format options: {}", e) }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Set the document size in bytes above which highlighting skips tree-sitter and the slow
+// patterns. Zero or a negative size restores the default
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeSetMaxHighlightBytes(
+    _env: JNIEnv,
+    _class: JClass,
+    max_bytes: jni::sys::jlong,
+) {
+    editor::set_max_highlight_bytes(if max_bytes > 0 { max_bytes as usize } else { 0 });
+}
+
+// Format code
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeFormatCode(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    language: JString,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let formatted = editor::format_code(&content, &language);
+    
+    let output = env.new_string(formatted).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Format code, with a diagnostic when the document could not be parsed and was left unchanged
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeFormatCodeWithDiagnostic(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    language: JString,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let (formatted, diagnostic) = editor::format_code_with_diagnostic(&content, &language);
+    let json = serde_json::json!({
+        "formatted": formatted,
+        "diagnostic": diagnostic,
+    })
+    .to_string();
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Format Kotlin or Java with a specific ktlint or google-java-format executable or jar.
+// Returns {"formatted": ..., "error": ...}; on error only the whitespace was normalized
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeFormatCodeWithTool(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    language: JString,
+    tool_path: JString,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let tool_path: String = env
+        .get_string(tool_path)
+        .expect("Failed to get tool path string")
+        .into();
+    
+    let (formatted, error) = editor::format_code_with_tool(&content, &language, &tool_path);
+    let json = serde_json::json!({
+        "formatted": formatted,
+        "error": error,
+    })
+    .to_string();
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Strip trailing whitespace, trailing blank lines and/or add a final newline without formatting.
+// `language` protects multi-line strings and Markdown, see editor::normalize_whitespace
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeNormalizeWhitespace(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    language: JString,
+    strip_trailing: jni::sys::jboolean,
+    ensure_final_newline: jni::sys::jboolean,
+    trim_trailing_blank_lines: jni::sys::jboolean,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let normalized = editor::normalize_whitespace(
+        &content,
+        &language,
+        strip_trailing != 0,
+        ensure_final_newline != 0,
+        trim_trailing_blank_lines != 0,
+    );
+    
+    let output = env.new_string(normalized).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Format code, returning the minimal edits to apply instead of the whole document
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeFormatCodeEdits(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    language: JString,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
+    
+    let edits = editor::format_code_edits(&content, &language);
+    let json = serde_json::to_string(&edits).unwrap_or_else(|_| "[]".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Apply a JSON array of edits made against the same version of the document, e.g. one per cursor.
+// Returns {"success": true, "content": ...} or {"success": false, "error": ...} when edits overlap
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeApplyEdits(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    edits_json: JString,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let edits_json: String = env
+        .get_string(edits_json)
+        .expect("Failed to get edits JSON string")
+        .into();
+    
+    let result = serde_json::from_str::<Vec<editor::TextEdit>>(&edits_json)
+        .map_err(|e| anyhow!("Invalid edits: {}", e))
+        .and_then(|edits| editor::apply_edits(&content, edits));
+    
+    let json = match result {
+        Ok(content) => serde_json::json!({ "success": true, "content": content }).to_string(),
+        Err(e) => serde_json::json!({ "success": false, "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Convert a byte offset to {"line": ..., "column": ...}, both zero-based; columns are UTF-16
+// code units when `utf16` is set
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeOffsetToPosition(
+    env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    byte_offset: jni::sys::jint,
+    utf16: jni::sys::jboolean,
+) -> jstring {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
+    
+    let (line, column) = editor::offset_to_position(&content, byte_offset.max(0) as usize, utf16 != 0);
+    let json = serde_json::json!({ "line": line, "column": column }).to_string();
     
     let output = env.new_string(json).expect("Failed to create Java string");
     output.into_raw()
 }
 
-// Check build system health
+// Convert a zero-based line and column to a byte offset
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeCheckBuildSystemHealth(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativePositionToOffset(
     env: JNIEnv,
     _class: JClass,
-) -> jstring {
-    let health_check = buildsystem::check_build_system_health();
-    let json = serde_json::to_string(&health_check).unwrap_or_else(|_| "{}".to_string());
+    content: JString,
+    line: jni::sys::jint,
+    column: jni::sys::jint,
+    utf16: jni::sys::jboolean,
+) -> jni::sys::jint {
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
+        .into();
     
-    let output = env.new_string(json).expect("Failed to create Java string");
-    output.into_raw()
+    editor::position_to_offset(&content, line.max(0) as usize, column.max(0) as usize, utf16 != 0) as jni::sys::jint
 }
 
-// Build a project
+// Format the lines of a byte range; returns the whole document with only that range changed
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeBuildProject(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeFormatRange(
     env: JNIEnv,
     _class: JClass,
-    project_path: JString,
-    build_type: JString,
+    content: JString,
+    start_byte: jni::sys::jint,
+    end_byte: jni::sys::jint,
+    language: JString,
 ) -> jstring {
-    let project_path: String = env
-        .get_string(project_path)
-        .expect("Failed to get project path string")
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
         .into();
     
-    let build_type: String = env
-        .get_string(build_type)
-        .expect("Failed to get build type string")
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
         .into();
     
-    let result = buildsystem::build_project(&project_path, &build_type);
-    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    let formatted = editor::format_range(&content, start_byte.max(0) as usize, end_byte.max(0) as usize, &language);
     
-    let output = env.new_string(json).expect("Failed to create Java string");
+    let output = env.new_string(formatted).expect("Failed to create Java string");
     output.into_raw()
 }
 
-// Clean a project
+// Indentation for a new line inserted at a position
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeCleanProject(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeIndentForNewLine(
     env: JNIEnv,
     _class: JClass,
-    project_path: JString,
+    content: JString,
+    position: jni::sys::jint,
+    language: JString,
+    indent_unit: JString,
 ) -> jstring {
-    let project_path: String = env
-        .get_string(project_path)
-        .expect("Failed to get project path string")
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
         .into();
     
-    let result = buildsystem::clean_project(&project_path);
-    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
     
-    let output = env.new_string(json).expect("Failed to create Java string");
+    let indent_unit: String = env
+        .get_string(indent_unit)
+        .expect("Failed to get indent unit string")
+        .into();
+    
+    let indent = editor::indent_for_new_line(&content, position.max(0) as usize, &language, &indent_unit);
+    
+    let output = env.new_string(indent).expect("Failed to create Java string");
     output.into_raw()
 }
 
-// Test a project
+// Parse code structure
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeTestProject(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeParseCodeStructure(
     env: JNIEnv,
     _class: JClass,
-    project_path: JString,
-    release: jni::sys::jboolean,
+    content: JString,
+    language: JString,
 ) -> jstring {
-    let project_path: String = env
-        .get_string(project_path)
-        .expect("Failed to get project path string")
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
         .into();
     
-    let release = release != 0;
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
     
-    let result = buildsystem::test_project(&project_path, release);
-    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    let structure = editor::parse_code_structure(&content, &language);
+    let json = serde_json::to_string(&structure).unwrap_or_else(|_| "{}".to_string());
     
     let output = env.new_string(json).expect("Failed to create Java string");
     output.into_raw()
 }
 
-// Build for Android target
+// Parse code structure as an operation nativeCancelEditorOp can stop for `doc_id`. Returns
+// {"cancelled", "result"}, where the structure is partial when cancelled is true
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeBuildForAndroidTarget(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeParseDocumentStructure(
     env: JNIEnv,
     _class: JClass,
-    project_path: JString,
-    target: JString,
-    release: jni::sys::jboolean,
+    doc_id: JString,
+    content: JString,
+    language: JString,
 ) -> jstring {
-    let project_path: String = env
-        .get_string(project_path)
-        .expect("Failed to get project path string")
+    let doc_id: String = env
+        .get_string(doc_id)
+        .expect("Failed to get document id string")
         .into();
     
-    let target: String = env
-        .get_string(target)
-        .expect("Failed to get target string")
+    let content: String = env
+        .get_string(content)
+        .expect("Failed to get content string")
         .into();
     
-    let release = release != 0;
+    let language: String = env
+        .get_string(language)
+        .expect("Failed to get language string")
+        .into();
     
-    let result = buildsystem::build_for_android_target(&project_path, &target, release);
-    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    let structure = editor::parse_code_structure_for_document(&doc_id, &content, &language);
+    let json = serde_json::to_string(&structure).unwrap_or_else(|_| "{}".to_string());
     
     let output = env.new_string(json).expect("Failed to create Java string");
     output.into_raw()
 }
 
-// Generate Android bindings
+// Index the symbols of a project for workspace symbol lookups
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeGenerateAndroidBindings(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeIndexProject(
     env: JNIEnv,
     _class: JClass,
-    project_path: JString,
-    package_name: JString,
+    root: JString,
+    languages_json: JString,
 ) -> jstring {
-    let project_path: String = env
-        .get_string(project_path)
-        .expect("Failed to get project path string")
+    let root: String = env
+        .get_string(root)
+        .expect("Failed to get root string")
         .into();
     
-    let package_name: String = env
-        .get_string(package_name)
-        .expect("Failed to get package name string")
+    let languages_json: String = env
+        .get_string(languages_json)
+        .expect("Failed to get languages JSON string")
         .into();
     
-    let result = buildsystem::generate_android_bindings(&project_path, &package_name);
-    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    // An empty string indexes every language; an empty list would too, so it is rejected rather
+    // than silently widened
+    let languages = if languages_json.trim().is_empty() {
+        Ok(Vec::new())
+    } else {
+        match serde_json::from_str::<Vec<String>>(&languages_json) {
+            Ok(languages) if languages.is_empty() => Err(anyhow!("Invalid languages: the list is empty")),
+            Ok(languages) => Ok(languages),
+            Err(e) => Err(anyhow!("Invalid languages: {}", e)),
+        }
+    };
+    
+    let json = match languages.and_then(|languages| editor::index_project(&root, languages)) {
+        Ok(index) => serde_json::json!({
+            "root": index.root,
+            "file_count": index.files.len(),
+            "symbol_count": index.symbol_count(),
+        })
+        .to_string(),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
     
     let output = env.new_string(json).expect("Failed to create Java string");
     output.into_raw()
 }
 
-// Editor-related functions
-
-// Initialize the editor
+// Look up a symbol by name in the indexed project
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeInitializeEditor(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeLookupSymbol(
     env: JNIEnv,
     _class: JClass,
-) -> jni::sys::jboolean {
-    match editor::initialize_editor() {
-        Ok(_) => 1, // true
-        Err(_) => 0, // false
-    }
+    name: JString,
+) -> jstring {
+    let name: String = env
+        .get_string(name)
+        .expect("Failed to get name string")
+        .into();
+    
+    let symbols = editor::lookup_symbol(&name);
+    let json = serde_json::to_string(&symbols).unwrap_or_else(|_| "[]".to_string());
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
 }
 
-// Highlight syntax
+// Update the project index for one changed file
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeHighlightSyntax(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeReindexFile(
     env: JNIEnv,
     _class: JClass,
-    content: JString,
-    language: JString,
+    path: JString,
 ) -> jstring {
-    let content: String = env
-        .get_string(content)
-        .expect("Failed to get content string")
-        .into();
-    
-    let language: String = env
-        .get_string(language)
-        .expect("Failed to get language string")
+    let path: String = env
+        .get_string(path)
+        .expect("Failed to get path string")
         .into();
     
-    let highlights = editor::highlight_syntax(&content, &language);
-    let json = serde_json::to_string(&highlights).unwrap_or_else(|_| "[]".to_string());
+    let json = match editor::reindex_file(&path) {
+        Ok(count) => serde_json::json!({ "symbol_count": count }).to_string(),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
     
     let output = env.new_string(json).expect("Failed to create Java string");
     output.into_raw()
 }
 
-// Get code completions
+// Watch a file for changes made outside the editor
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeGetCompletions(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeWatchFile(
     env: JNIEnv,
     _class: JClass,
-    content: JString,
-    position: jni::sys::jint,
-    language: JString,
+    path: JString,
 ) -> jstring {
-    let content: String = env
-        .get_string(content)
-        .expect("Failed to get content string")
+    let path: String = env
+        .get_string(path)
+        .expect("Failed to get path string")
         .into();
     
-    let language: String = env
-        .get_string(language)
-        .expect("Failed to get language string")
-        .into();
+    let json = match editor::watch_file(&path) {
+        Ok(watch_id) => serde_json::json!({ "watch_id": watch_id }).to_string(),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
     
-    let completions = editor::get_completions(&content, position as usize, &language);
-    let json = serde_json::to_string(&completions).unwrap_or_else(|_| "[]".to_string());
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Take the changes to watched files since the last poll
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativePollFileEvents(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let events = editor::poll_file_events();
+    let json = serde_json::to_string(&events).unwrap_or_else(|_| "[]".to_string());
     
     let output = env.new_string(json).expect("Failed to create Java string");
     output.into_raw()
 }
 
-// Format code
+// Stop watching a file
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeFormatCode(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeUnwatchFile(
+    env: JNIEnv,
+    _class: JClass,
+    watch_id: JString,
+) -> jni::sys::jboolean {
+    let watch_id: String = env
+        .get_string(watch_id)
+        .expect("Failed to get watch id string")
+        .into();
+    
+    match editor::unwatch_file(&watch_id) {
+        Ok(_) => 1, // true
+        Err(_) => 0, // false
+    }
+}
+
+// Find references
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeFindReferences(
     env: JNIEnv,
     _class: JClass,
     content: JString,
+    position: jni::sys::jint,
     language: JString,
 ) -> jstring {
     let content: String = env
@@ -341,18 +2083,20 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_n
         .expect("Failed to get language string")
         .into();
     
-    let formatted = editor::format_code(&content, &language);
+    let references = editor::find_references(&content, position as usize, &language);
+    let json = serde_json::to_string(&references).unwrap_or_else(|_| "[]".to_string());
     
-    let output = env.new_string(formatted).expect("Failed to create Java string");
+    let output = env.new_string(json).expect("Failed to create Java string");
     output.into_raw()
 }
 
-// Parse code structure
+// Find references with the position, offsets and columns in UTF-16 code units
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeParseCodeStructure(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeFindReferencesUtf16(
     env: JNIEnv,
     _class: JClass,
     content: JString,
+    position: jni::sys::jint,
     language: JString,
 ) -> jstring {
     let content: String = env
@@ -365,16 +2109,16 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_n
         .expect("Failed to get language string")
         .into();
     
-    let structure = editor::parse_code_structure(&content, &language);
-    let json = serde_json::to_string(&structure).unwrap_or_else(|_| "{}".to_string());
+    let references = editor::find_references_utf16(&content, position as usize, &language);
+    let json = serde_json::to_string(&references).unwrap_or_else(|_| "[]".to_string());
     
     let output = env.new_string(json).expect("Failed to create Java string");
     output.into_raw()
 }
 
-// Find references
+// Get hover information for the symbol at a position
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeFindReferences(
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeHoverInfo(
     env: JNIEnv,
     _class: JClass,
     content: JString,
@@ -391,8 +2135,8 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_n
         .expect("Failed to get language string")
         .into();
     
-    let references = editor::find_references(&content, position as usize, &language);
-    let json = serde_json::to_string(&references).unwrap_or_else(|_| "[]".to_string());
+    let hover = editor::hover_info(&content, position as usize, &language);
+    let json = serde_json::to_string(&hover).unwrap_or_else(|_| "null".to_string());
     
     let output = env.new_string(json).expect("Failed to create Java string");
     output.into_raw()
@@ -400,7 +2144,7 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_n
 
 // Plugin system functions
 
-// Load plugin
+// Load plugin from a directory with a plugin.json manifest, reporting every manifest problem found
 #[no_mangle]
 pub extern "C" fn Java_com_anyoneide_app_core_RustPluginManager_00024Companion_nativeLoadPlugin(
     env: JNIEnv,
@@ -450,7 +2194,7 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustPluginManager_00024Companion_n
     output.into_raw()
 }
 
-// Execute plugin hook
+// Execute plugin hook; data is the JSON payload for the hook and the result carries a status saying why a call failed
 #[no_mangle]
 pub extern "C" fn Java_com_anyoneide_app_core_RustPluginManager_00024Companion_nativeExecutePluginHook(
     env: JNIEnv,
@@ -513,6 +2257,40 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustExtensionManager_00024Companio
     }
 }
 
+// Register an extension after checking its data against the schema for its type. Returns
+// {"success": true} or {"success": false, "errors": [{"field", "message"}]} listing every problem
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustExtensionManager_00024Companion_nativeRegisterExtensionChecked(
+    env: JNIEnv,
+    _class: JClass,
+    extension_id: JString,
+    extension_type: JString,
+    extension_data: JString,
+) -> jstring {
+    let extension_id: String = env
+        .get_string(extension_id)
+        .expect("Failed to get extension ID string")
+        .into();
+    
+    let extension_type: String = env
+        .get_string(extension_type)
+        .expect("Failed to get extension type string")
+        .into();
+    
+    let extension_data: String = env
+        .get_string(extension_data)
+        .expect("Failed to get extension data string")
+        .into();
+    
+    let json = match extensions::register_extension_checked(&extension_id, &extension_type, &extension_data) {
+        Ok(()) => serde_json::json!({ "success": true }).to_string(),
+        Err(errors) => serde_json::json!({ "success": false, "errors": errors }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
 // Unregister extension
 #[no_mangle]
 pub extern "C" fn Java_com_anyoneide_app_core_RustExtensionManager_00024Companion_nativeUnregisterExtension(
@@ -551,6 +2329,7 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustExtensionManager_00024Companio
     _class: JClass,
     extension_id: JString,
     data: JString,
+    timeout_ms: jni::sys::jlong,
 ) -> jstring {
     let extension_id: String = env
         .get_string(extension_id)
@@ -562,7 +2341,8 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustExtensionManager_00024Companio
         .expect("Failed to get data string")
         .into();
     
-    let result = extensions::execute_extension(&extension_id, &data);
+    // Zero or a negative timeout means the extension's own limit
+    let result = extensions::execute_extension(&extension_id, &data, timeout_ms.max(0) as u64);
     let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
     
     let output = env.new_string(json).expect("Failed to create Java string");
@@ -720,6 +2500,40 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustGradleManager_00024Companion_n
     output.into_raw()
 }
 
+// Resolved dependency tree of a Gradle configuration; an empty configuration uses the default
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustGradleManager_00024Companion_nativeGradleDependencyTree(
+    env: JNIEnv,
+    _class: JClass,
+    sdk_root: JString,
+    project_path: JString,
+    configuration: JString,
+) -> jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let project_path: String = env
+        .get_string(project_path)
+        .expect("Failed to get project path string")
+        .into();
+    
+    let configuration: String = env
+        .get_string(configuration)
+        .expect("Failed to get configuration string")
+        .into();
+    
+    let sdk_manager = sdkmanager::SdkManager::new(Path::new(&sdk_root));
+    let json = match gradlefilemodifier::dependency_tree(&project_path, Some(configuration), &sdk_manager) {
+        Ok(tree) => serde_json::to_string(&tree).unwrap_or_else(|_| "{}".to_string()),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env.new_string(json).expect("Failed to create Java string");
+    output.into_raw()
+}
+
 // SDK Manager functions
 
 // Get SDK status
@@ -763,7 +2577,8 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Compan
     
     let sdk_manager = sdkmanager::SdkManager::new(Path::new(&sdk_root));
     
-    match sdk_manager.install_component(&component_id) {
+    // Always resume partial downloads left by an earlier attempt
+    match sdk_manager.install_component(&component_id, true) {
         Ok(progress_iter) => {
             // Convert progress iterator to JSON array
             let progress_vec: Vec<sdkmanager::InstallationProgress> = progress_iter.collect();
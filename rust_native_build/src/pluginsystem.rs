@@ -1,28 +1,54 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
 use serde::{Serialize, Deserialize};
 use anyhow::{Result, anyhow};
 use lazy_static::lazy_static;
+use crate::extensions::DEFAULT_EXTENSION_TIMEOUT_MS;
 
-// Plugin metadata
+// Range of plugin API versions this host can load
+pub const MIN_PLUGIN_API_VERSION: u32 = 1;
+pub const MAX_PLUGIN_API_VERSION: u32 = 1;
+
+// Plugin manifest, read from the plugin.json at the root of a plugin directory.
+//
+// Required fields:
+//   id          - unique identifier, letters, digits, '.', '_' and '-' only
+//   name        - display name
+//   version     - plugin version string
+//   entry       - entry point file, relative to the plugin directory
+//   hooks       - names of the hooks the plugin handles
+//   api_version - plugin API version the plugin was written against
+//
+// Everything else is optional and only used for display and lookup.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct PluginMetadata {
+pub struct PluginManifest {
     pub id: String,
     pub name: String,
-    pub description: String,
     pub version: String,
+    pub entry: String,
+    pub hooks: Vec<String>,
+    pub api_version: u32,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
     pub author: String,
+    #[serde(default)]
     pub category: String,
+    #[serde(default)]
     pub tags: Vec<String>,
+    #[serde(default)]
     pub extension_points: Vec<String>,
+    #[serde(default)]
     pub dependencies: Vec<PluginDependency>,
+    #[serde(default)]
     pub min_ide_version: String,
+    #[serde(default)]
     pub icon_url: Option<String>,
-    pub screenshots: Vec<String>,
-    pub rating: f32,
-    pub download_count: u64,
 }
 
 // Plugin dependency
@@ -30,103 +56,355 @@ pub struct PluginMetadata {
 pub struct PluginDependency {
     pub id: String,
     pub min_version: String,
+    #[serde(default)]
     pub optional: bool,
 }
 
+// A problem found in a plugin manifest
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestError {
+    pub field: String,
+    pub message: String,
+}
+
+// Plugin load result
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PluginLoadResult {
+    pub success: bool,
+    pub manifest: Option<PluginManifest>,
+    pub errors: Vec<ManifestError>,
+}
+
 // Plugin instance
-#[derive(Debug)]
 struct Plugin {
-    pub metadata: PluginMetadata,
+    pub metadata: PluginManifest,
     pub path: PathBuf,
     pub enabled: bool,
     pub hooks: HashMap<String, Arc<dyn PluginHook + Send + Sync>>,
+    // Terminal commands listed by the registerCommands hook when it was registered
+    pub commands: HashSet<String>,
 }
 
 // Plugin hook trait
-trait PluginHook {
-    fn execute(&self, data: &str) -> Result<String>;
+pub trait PluginHook {
+    fn execute(&self, payload: &HookPayload) -> Result<String>;
+}
+
+// Hooks the host dispatches to plugins. A plugin lists the ones it handles in its manifest,
+// and each is called with a JSON payload of the matching shape:
+//   onFileSave          {"path": string, "content": string}
+//   onBuildStart        {"project_path": string, "build_type": string}
+//   onBuildComplete     {"project_path": string, "success": bool, "duration_ms": number, "artifacts": [string]}
+//   onCompletionRequest {"path": string, "content": string, "line": number, "column": number,
+//                        "position": number, "language": string}
+//   registerCommands    {}
+//   runCommand          {"command": string, "args": [string], "working_dir": string}
+// onCompletionRequest handlers return a JSON array of completion items, which are offered
+// alongside the editor's own completions. registerCommands handlers return a JSON array of
+// terminal command names; typing one in a terminal session calls the plugin's runCommand, whose
+// result is shown as the command's output
+pub const KNOWN_HOOKS: [&str; 6] = [
+    "onFileSave",
+    "onBuildStart",
+    "onBuildComplete",
+    "onCompletionRequest",
+    "registerCommands",
+    "runCommand",
+];
+
+// onFileSave payload
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileSavePayload {
+    pub path: String,
+    pub content: String,
+}
+
+// onBuildStart payload
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BuildStartPayload {
+    pub project_path: String,
+    pub build_type: String,
+}
+
+// onBuildComplete payload
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BuildCompletePayload {
+    pub project_path: String,
+    pub success: bool,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub artifacts: Vec<String>,
+}
+
+// onCompletionRequest payload; line and column are zero-based, and position is the cursor's
+// byte offset into content
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompletionRequestPayload {
+    pub path: String,
+    pub content: String,
+    pub line: u32,
+    pub column: u32,
+    #[serde(default)]
+    pub position: usize,
+    #[serde(default)]
+    pub language: String,
+}
+
+// registerCommands payload; the hook takes no input
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RegisterCommandsPayload {}
+
+// runCommand payload. `args` are the words after the command name, already unquoted and expanded
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunCommandPayload {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub working_dir: String,
+}
+
+// Typed payload handed to a hook
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum HookPayload {
+    FileSave(FileSavePayload),
+    BuildStart(BuildStartPayload),
+    BuildComplete(BuildCompletePayload),
+    CompletionRequest(CompletionRequestPayload),
+    RegisterCommands(RegisterCommandsPayload),
+    RunCommand(RunCommandPayload),
+}
+
+impl HookPayload {
+    // Parse the JSON payload for a known hook
+    pub fn parse(hook_name: &str, data: &str) -> Result<HookPayload> {
+        let payload = match hook_name {
+            "onFileSave" => HookPayload::FileSave(serde_json::from_str(data)?),
+            "onBuildStart" => HookPayload::BuildStart(serde_json::from_str(data)?),
+            "onBuildComplete" => HookPayload::BuildComplete(serde_json::from_str(data)?),
+            "onCompletionRequest" => HookPayload::CompletionRequest(serde_json::from_str(data)?),
+            "registerCommands" => HookPayload::RegisterCommands(serde_json::from_str(data)?),
+            "runCommand" => HookPayload::RunCommand(serde_json::from_str(data)?),
+            _ => return Err(anyhow!("Unknown hook: {}", hook_name)),
+        };
+        
+        Ok(payload)
+    }
+}
+
+// Outcome of a hook call
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HookStatus {
+    Ok,
+    PluginNotFound,
+    PluginDisabled,
+    UnknownHook,
+    HookNotRegistered,
+    InvalidPayload,
+    HookFailed,
+    TimedOut,
 }
 
 // Plugin hook result
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PluginHookResult {
     pub success: bool,
+    pub status: HookStatus,
     pub data: String,
     pub error: Option<String>,
 }
 
+impl PluginHookResult {
+    fn failed(status: HookStatus, error: String) -> Self {
+        PluginHookResult {
+            success: false,
+            status,
+            data: String::new(),
+            error: Some(error),
+        }
+    }
+}
+
 // Global plugin registry
 lazy_static! {
     static ref PLUGINS: Mutex<HashMap<String, Plugin>> = Mutex::new(HashMap::new());
 }
 
 // Load a plugin
-pub fn load_plugin(plugin_path: &str) -> Result<PluginMetadata> {
+pub fn load_plugin(plugin_path: &str) -> PluginLoadResult {
     let path = Path::new(plugin_path);
     
+    let manifest = match read_manifest(path) {
+        Ok(manifest) => manifest,
+        Err(errors) => {
+            return PluginLoadResult {
+                success: false,
+                manifest: None,
+                errors,
+            };
+        }
+    };
+    
+    // Register plugin
+    let mut plugins = PLUGINS.lock().unwrap();
+    
+    // Create plugin instance
+    let plugin = Plugin {
+        metadata: manifest.clone(),
+        path: path.to_path_buf(),
+        enabled: true,
+        hooks: HashMap::new(),
+        commands: HashSet::new(),
+    };
+    
+    // Register plugin
+    plugins.insert(manifest.id.clone(), plugin);
+    
+    PluginLoadResult {
+        success: true,
+        manifest: Some(manifest),
+        errors: Vec::new(),
+    }
+}
+
+// Read and validate the manifest of a plugin directory
+pub fn read_manifest(plugin_dir: &Path) -> std::result::Result<PluginManifest, Vec<ManifestError>> {
     // Check if path exists
-    if !path.exists() {
-        return Err(anyhow!("Plugin path does not exist: {}", plugin_path));
+    if !plugin_dir.exists() {
+        return Err(vec![manifest_error("path", format!("Plugin path does not exist: {}", plugin_dir.to_string_lossy()))]);
     }
     
     // Check if it's a directory
-    if !path.is_dir() {
-        return Err(anyhow!("Plugin path is not a directory: {}", plugin_path));
+    if !plugin_dir.is_dir() {
+        return Err(vec![manifest_error("path", format!("Plugin path is not a directory: {}", plugin_dir.to_string_lossy()))]);
     }
     
     // Check for plugin.json
-    let plugin_json_path = path.join("plugin.json");
+    let plugin_json_path = plugin_dir.join("plugin.json");
     if !plugin_json_path.exists() {
-        return Err(anyhow!("Plugin manifest not found: {}", plugin_json_path.to_string_lossy()));
+        return Err(vec![manifest_error("plugin.json", format!("Plugin manifest not found: {}", plugin_json_path.to_string_lossy()))]);
     }
     
     // Read plugin.json
-    let plugin_json = fs::read_to_string(plugin_json_path)?;
+    let plugin_json = fs::read_to_string(&plugin_json_path)
+        .map_err(|e| vec![manifest_error("plugin.json", format!("Failed to read plugin manifest: {}", e))])?;
+    
+    let manifest = parse_manifest(&plugin_json)?;
+    
+    // The entry point has to stay inside the plugin directory and exist
+    let entry = Path::new(&manifest.entry);
+    if entry.is_absolute() || entry.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+        return Err(vec![manifest_error("entry", "Entry point must be a path inside the plugin directory".to_string())]);
+    }
+    
+    if !plugin_dir.join(entry).is_file() {
+        return Err(vec![manifest_error("entry", format!("Entry point not found: {}", manifest.entry))]);
+    }
+    
+    Ok(manifest)
+}
+
+// Parse and validate plugin.json content, collecting every problem instead of stopping at the first
+pub fn parse_manifest(content: &str) -> std::result::Result<PluginManifest, Vec<ManifestError>> {
+    let value: serde_json::Value = serde_json::from_str(content)
+        .map_err(|e| vec![manifest_error("plugin.json", format!("Invalid JSON: {}", e))])?;
+    
+    let object = match value.as_object() {
+        Some(object) => object,
+        None => return Err(vec![manifest_error("plugin.json", "Manifest must be a JSON object".to_string())]),
+    };
+    
+    let mut errors = Vec::new();
     
-    // Parse plugin metadata
-    let metadata: PluginMetadata = serde_json::from_str(&plugin_json)?;
+    // Required string fields
+    for field in ["id", "name", "version", "entry"] {
+        match object.get(field) {
+            None => errors.push(manifest_error(field, format!("Missing required field '{}'", field))),
+            Some(serde_json::Value::String(value)) if value.trim().is_empty() => {
+                errors.push(manifest_error(field, format!("Field '{}' must not be empty", field)));
+            }
+            Some(serde_json::Value::String(_)) => {}
+            Some(_) => errors.push(manifest_error(field, format!("Field '{}' must be a string", field))),
+        }
+    }
+    
+    if let Some(id) = object.get("id").and_then(|id| id.as_str()) {
+        if !id.is_empty() && !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-') {
+            errors.push(manifest_error("id", format!("Plugin ID contains invalid characters: {}", id)));
+        }
+    }
     
-    // Check for plugin ID
-    if metadata.id.is_empty() {
-        return Err(anyhow!("Plugin ID is empty"));
+    // Hooks
+    match object.get("hooks") {
+        None => errors.push(manifest_error("hooks", "Missing required field 'hooks'".to_string())),
+        Some(serde_json::Value::Array(hooks)) => {
+            let mut seen = Vec::new();
+            for hook in hooks {
+                match hook.as_str() {
+                    Some(name) if name.trim().is_empty() => {
+                        errors.push(manifest_error("hooks", "Hook names must not be empty".to_string()));
+                    }
+                    Some(name) if !KNOWN_HOOKS.contains(&name) => {
+                        errors.push(manifest_error("hooks", format!("Unknown hook: {}", name)));
+                    }
+                    Some(name) if seen.contains(&name) => {
+                        errors.push(manifest_error("hooks", format!("Hook listed more than once: {}", name)));
+                    }
+                    Some(name) => seen.push(name),
+                    None => errors.push(manifest_error("hooks", "Hook names must be strings".to_string())),
+                }
+            }
+        }
+        Some(_) => errors.push(manifest_error("hooks", "Field 'hooks' must be an array of strings".to_string())),
     }
     
-    // Check for plugin name
-    if metadata.name.is_empty() {
-        return Err(anyhow!("Plugin name is empty"));
+    // API version
+    match object.get("api_version") {
+        None => errors.push(manifest_error("api_version", "Missing required field 'api_version'".to_string())),
+        Some(value) => match value.as_u64() {
+            Some(version) if version < MIN_PLUGIN_API_VERSION as u64 || version > MAX_PLUGIN_API_VERSION as u64 => {
+                errors.push(manifest_error("api_version", format!(
+                    "Unsupported plugin API version {}, this host supports {} to {}",
+                    version, MIN_PLUGIN_API_VERSION, MAX_PLUGIN_API_VERSION
+                )));
+            }
+            Some(_) => {}
+            None => errors.push(manifest_error("api_version", "Field 'api_version' must be a non-negative integer".to_string())),
+        },
     }
     
-    // Check for plugin version
-    if metadata.version.is_empty() {
-        return Err(anyhow!("Plugin version is empty"));
+    if !errors.is_empty() {
+        return Err(errors);
     }
     
+    // Required fields are in place; anything left is a type problem in the optional ones
+    let manifest: PluginManifest = serde_json::from_value(value)
+        .map_err(|e| vec![manifest_error("plugin.json", format!("Invalid manifest: {}", e))])?;
+    
     // Check for plugin dependencies
-    for dependency in &metadata.dependencies {
+    for dependency in &manifest.dependencies {
         if dependency.id.is_empty() {
-            return Err(anyhow!("Plugin dependency ID is empty"));
+            errors.push(manifest_error("dependencies", "Plugin dependency ID is empty".to_string()));
         }
         
         if dependency.min_version.is_empty() {
-            return Err(anyhow!("Plugin dependency minimum version is empty"));
+            errors.push(manifest_error("dependencies", format!("Minimum version is empty for dependency: {}", dependency.id)));
         }
     }
     
-    // Register plugin
-    let mut plugins = PLUGINS.lock().unwrap();
-    
-    // Create plugin instance
-    let plugin = Plugin {
-        metadata: metadata.clone(),
-        path: path.to_path_buf(),
-        enabled: true,
-        hooks: HashMap::new(),
-    };
-    
-    // Register plugin
-    plugins.insert(metadata.id.clone(), plugin);
-    
-    Ok(metadata)
+    if errors.is_empty() {
+        Ok(manifest)
+    } else {
+        Err(errors)
+    }
+}
+
+fn manifest_error(field: &str, message: String) -> ManifestError {
+    ManifestError {
+        field: field.to_string(),
+        message,
+    }
 }
 
 // Unload a plugin
@@ -141,7 +419,7 @@ pub fn unload_plugin(plugin_id: &str) -> Result<()> {
 }
 
 // Get loaded plugins
-pub fn get_loaded_plugins() -> Vec<PluginMetadata> {
+pub fn get_loaded_plugins() -> Vec<PluginManifest> {
     let plugins = PLUGINS.lock().unwrap();
     
     plugins.values()
@@ -149,54 +427,212 @@ pub fn get_loaded_plugins() -> Vec<PluginMetadata> {
         .collect()
 }
 
-// Execute plugin hook
-pub fn execute_plugin_hook(plugin_id: &str, hook_name: &str, data: &str) -> Result<PluginHookResult> {
-    let plugins = PLUGINS.lock().unwrap();
+// Execute plugin hook. `data` is the JSON payload documented for the hook next to KNOWN_HOOKS.
+pub fn execute_plugin_hook(plugin_id: &str, hook_name: &str, data: &str) -> PluginHookResult {
+    let (hook, payload) = match find_hook(plugin_id, hook_name, data) {
+        Ok(found) => found,
+        Err(result) => return result,
+    };
     
-    if let Some(plugin) = plugins.get(plugin_id) {
+    hook_result(hook_name, hook.execute(&payload))
+}
+
+// Execute plugin hook on its own thread, giving up after `timeout`. As with extensions, a
+// handler that overruns is left to finish in the background and its result is dropped
+pub fn execute_plugin_hook_with_timeout(plugin_id: &str, hook_name: &str, data: &str, timeout: Duration) -> PluginHookResult {
+    let (hook, payload) = match find_hook(plugin_id, hook_name, data) {
+        Ok(found) => found,
+        Err(result) => return result,
+    };
+    
+    match execute_hook_on_thread(plugin_id, hook, payload, timeout) {
+        Some(outcome) => hook_result(hook_name, outcome),
+        None => PluginHookResult::failed(
+            HookStatus::TimedOut,
+            format!("Hook {} of plugin {} timed out after {} ms", hook_name, plugin_id, timeout.as_millis()),
+        ),
+    }
+}
+
+// The handler and parsed payload for a hook call, or the result reporting why it can't be made
+fn find_hook(plugin_id: &str, hook_name: &str, data: &str) -> std::result::Result<(Arc<dyn PluginHook + Send + Sync>, HookPayload), PluginHookResult> {
+    // Look the handler up under the lock, but run it without holding the registry
+    let hook = {
+        let plugins = PLUGINS.lock().unwrap();
+        
+        let plugin = match plugins.get(plugin_id) {
+            Some(plugin) => plugin,
+            None => return Err(PluginHookResult::failed(HookStatus::PluginNotFound, format!("Plugin not found: {}", plugin_id))),
+        };
+        
         if !plugin.enabled {
-            return Ok(PluginHookResult {
-                success: false,
-                data: String::new(),
-                error: Some("Plugin is disabled".to_string()),
-            });
+            return Err(PluginHookResult::failed(HookStatus::PluginDisabled, format!("Plugin is disabled: {}", plugin_id)));
+        }
+        
+        if !KNOWN_HOOKS.contains(&hook_name) {
+            return Err(PluginHookResult::failed(HookStatus::UnknownHook, format!("Unknown hook: {}", hook_name)));
+        }
+        
+        if !plugin.metadata.hooks.iter().any(|hook| hook == hook_name) {
+            return Err(PluginHookResult::failed(
+                HookStatus::HookNotRegistered,
+                format!("Plugin {} does not declare hook {} in its manifest", plugin_id, hook_name),
+            ));
         }
         
-        if let Some(hook) = plugin.hooks.get(hook_name) {
-            match hook.execute(data) {
-                Ok(result) => Ok(PluginHookResult {
-                    success: true,
-                    data: result,
-                    error: None,
-                }),
-                Err(e) => Ok(PluginHookResult {
-                    success: false,
-                    data: String::new(),
-                    error: Some(e.to_string()),
-                }),
+        match plugin.hooks.get(hook_name) {
+            Some(hook) => Arc::clone(hook),
+            None => {
+                return Err(PluginHookResult::failed(
+                    HookStatus::HookNotRegistered,
+                    format!("Plugin {} has no handler attached for hook {}", plugin_id, hook_name),
+                ));
             }
-        } else {
-            Ok(PluginHookResult {
-                success: false,
-                data: String::new(),
-                error: Some(format!("Hook not found: {}", hook_name)),
-            })
         }
-    } else {
-        Ok(PluginHookResult {
-            success: false,
-            data: String::new(),
-            error: Some(format!("Plugin not found: {}", plugin_id)),
-        })
+    };
+    
+    match HookPayload::parse(hook_name, data) {
+        Ok(payload) => Ok((hook, payload)),
+        Err(e) => Err(PluginHookResult::failed(HookStatus::InvalidPayload, format!("Invalid payload for {}: {}", hook_name, e))),
+    }
+}
+
+// Result of a hook call that ran, successfully or not
+fn hook_result(hook_name: &str, outcome: Result<String>) -> PluginHookResult {
+    match outcome {
+        Ok(result) => PluginHookResult {
+            success: true,
+            status: HookStatus::Ok,
+            data: result,
+            error: None,
+        },
+        Err(e) => PluginHookResult::failed(HookStatus::HookFailed, format!("Hook {} failed: {}", hook_name, e)),
+    }
+}
+
+// Run a handler on its own thread, returning None when it is still running after `timeout`.
+// A panicking handler fails the call instead of taking the caller down
+fn execute_hook_on_thread(plugin_id: &str, hook: Arc<dyn PluginHook + Send + Sync>, payload: HookPayload, timeout: Duration) -> Option<Result<String>> {
+    let (sender, receiver) = mpsc::channel();
+    
+    let spawned = thread::Builder::new()
+        .name(format!("plugin-{}", plugin_id))
+        .spawn(move || {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| hook.execute(&payload)));
+            // The receiver is gone if the call already timed out
+            let _ = sender.send(outcome);
+        });
+    if let Err(e) = spawned {
+        return Some(Err(anyhow!("Failed to start plugin thread: {}", e)));
+    }
+    
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok(outcome)) => Some(outcome),
+        Ok(Err(_)) => Some(Err(anyhow!("Hook handler panicked"))),
+        Err(_) => None,
+    }
+}
+
+// Call `hook_name` on every enabled plugin with a handler for it, each on its own thread, and
+// collect (plugin id, result) from the handlers that succeed before `timeout` runs out, ordered
+// by plugin id. As with extensions, a handler that overruns is left to finish in the background
+// and its result is dropped
+pub fn broadcast_plugin_hook(hook_name: &str, payload: &HookPayload, timeout: Duration) -> Vec<(String, String)> {
+    // Take the handlers and release the registry, so a slow plugin does not block other plugin calls
+    let handlers: Vec<(String, Arc<dyn PluginHook + Send + Sync>)> = {
+        let plugins = PLUGINS.lock().unwrap();
+        
+        plugins.values()
+            .filter(|plugin| plugin.enabled)
+            .filter_map(|plugin| plugin.hooks.get(hook_name).map(|hook| (plugin.metadata.id.clone(), Arc::clone(hook))))
+            .collect()
+    };
+    
+    if handlers.is_empty() {
+        return Vec::new();
+    }
+    
+    let deadline = Instant::now() + timeout;
+    let payload = Arc::new(payload.clone());
+    let (sender, receiver) = mpsc::channel();
+    
+    for (plugin_id, hook) in handlers {
+        let sender = sender.clone();
+        let payload = Arc::clone(&payload);
+        
+        let _ = thread::Builder::new()
+            .name(format!("plugin-{}", plugin_id))
+            .spawn(move || {
+                let outcome = panic::catch_unwind(AssertUnwindSafe(|| hook.execute(&payload)));
+                // The receiver is gone if the call already timed out
+                let _ = sender.send((plugin_id, outcome));
+            });
     }
+    
+    // Once every handler has answered the channel disconnects and the loop ends early
+    drop(sender);
+    
+    let mut results = Vec::new();
+    while let Ok((plugin_id, outcome)) = receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        if let Ok(Ok(data)) = outcome {
+            results.push((plugin_id, data));
+        }
+    }
+    
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    results
 }
 
-// Register plugin hook
+// The enabled plugin that registered `command` through its registerCommands hook. When several
+// did, the first by plugin id wins
+pub fn find_command_plugin(command: &str) -> Option<String> {
+    let plugins = PLUGINS.lock().unwrap();
+    
+    plugins.values()
+        .filter(|plugin| plugin.enabled && plugin.commands.contains(command))
+        .map(|plugin| plugin.metadata.id.clone())
+        .min()
+}
+
+// Register plugin hook. A registerCommands handler is called once here and the command names it
+// returns are kept for find_command_plugin; a handler that fails or times out is not registered
 pub fn register_plugin_hook(plugin_id: &str, hook_name: &str, hook: Arc<dyn PluginHook + Send + Sync>) -> Result<()> {
+    {
+        let plugins = PLUGINS.lock().unwrap();
+        
+        match plugins.get(plugin_id) {
+            Some(plugin) if !plugin.metadata.hooks.iter().any(|hook| hook == hook_name) => {
+                return Err(anyhow!("Plugin {} does not declare hook {} in its manifest", plugin_id, hook_name));
+            }
+            Some(_) => {}
+            None => return Err(anyhow!("Plugin not found: {}", plugin_id)),
+        }
+    }
+    
+    // The handler runs without the registry lock, since a plugin may call back into it
+    let commands = if hook_name == "registerCommands" {
+        let payload = HookPayload::RegisterCommands(RegisterCommandsPayload {});
+        let timeout = Duration::from_millis(DEFAULT_EXTENSION_TIMEOUT_MS);
+        
+        let data = match execute_hook_on_thread(plugin_id, Arc::clone(&hook), payload, timeout) {
+            Some(Ok(data)) => data,
+            Some(Err(e)) => return Err(anyhow!("registerCommands of plugin {} failed: {}", plugin_id, e)),
+            None => return Err(anyhow!("registerCommands of plugin {} timed out", plugin_id)),
+        };
+        let names: Vec<String> = serde_json::from_str(&data)
+            .map_err(|e| anyhow!("registerCommands of plugin {} must return a JSON array of command names: {}", plugin_id, e))?;
+        Some(names.into_iter().collect::<HashSet<String>>())
+    } else {
+        None
+    };
+    
     let mut plugins = PLUGINS.lock().unwrap();
     
     if let Some(plugin) = plugins.get_mut(plugin_id) {
         plugin.hooks.insert(hook_name.to_string(), hook);
+        if let Some(commands) = commands {
+            plugin.commands = commands;
+        }
         Ok(())
     } else {
         Err(anyhow!("Plugin not found: {}", plugin_id))
@@ -209,6 +645,9 @@ pub fn unregister_plugin_hook(plugin_id: &str, hook_name: &str) -> Result<()> {
     
     if let Some(plugin) = plugins.get_mut(plugin_id) {
         if plugin.hooks.remove(hook_name).is_some() {
+            if hook_name == "registerCommands" {
+                plugin.commands.clear();
+            }
             Ok(())
         } else {
             Err(anyhow!("Hook not found: {}", hook_name))
@@ -254,7 +693,7 @@ pub fn is_plugin_enabled(plugin_id: &str) -> bool {
 }
 
 // Get plugin metadata
-pub fn get_plugin_metadata(plugin_id: &str) -> Option<PluginMetadata> {
+pub fn get_plugin_metadata(plugin_id: &str) -> Option<PluginManifest> {
     let plugins = PLUGINS.lock().unwrap();
     
     plugins.get(plugin_id).map(|plugin| plugin.metadata.clone())
@@ -298,7 +737,7 @@ pub fn check_plugin_dependencies(plugin_id: &str) -> Result<Vec<String>> {
 }
 
 // Get plugins by category
-pub fn get_plugins_by_category(category: &str) -> Vec<PluginMetadata> {
+pub fn get_plugins_by_category(category: &str) -> Vec<PluginManifest> {
     let plugins = PLUGINS.lock().unwrap();
     
     plugins.values()
@@ -308,7 +747,7 @@ pub fn get_plugins_by_category(category: &str) -> Vec<PluginMetadata> {
 }
 
 // Get plugins by tag
-pub fn get_plugins_by_tag(tag: &str) -> Vec<PluginMetadata> {
+pub fn get_plugins_by_tag(tag: &str) -> Vec<PluginManifest> {
     let plugins = PLUGINS.lock().unwrap();
     
     plugins.values()
@@ -318,7 +757,7 @@ pub fn get_plugins_by_tag(tag: &str) -> Vec<PluginMetadata> {
 }
 
 // Get plugins by extension point
-pub fn get_plugins_by_extension_point(extension_point: &str) -> Vec<PluginMetadata> {
+pub fn get_plugins_by_extension_point(extension_point: &str) -> Vec<PluginManifest> {
     let plugins = PLUGINS.lock().unwrap();
     
     plugins.values()
@@ -328,7 +767,7 @@ pub fn get_plugins_by_extension_point(extension_point: &str) -> Vec<PluginMetada
 }
 
 // Search plugins
-pub fn search_plugins(query: &str) -> Vec<PluginMetadata> {
+pub fn search_plugins(query: &str) -> Vec<PluginManifest> {
     let plugins = PLUGINS.lock().unwrap();
     
     plugins.values()
@@ -339,4 +778,177 @@ pub fn search_plugins(query: &str) -> Vec<PluginMetadata> {
         })
         .map(|plugin| plugin.metadata.clone())
         .collect()
-}
\ No newline at end of file
+}
+
+// Load a plugin with a manifest naming `hooks`, all of them run by `hook`, for tests in other
+// modules. Returns the plugin's directory, to remove once the plugin is unloaded
+#[cfg(test)]
+pub(crate) fn load_test_plugin(plugin_id: &str, hooks: &[&str], hook: Arc<dyn PluginHook + Send + Sync>) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("anyoneide-{}-{}", plugin_id, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.js"), "").unwrap();
+    
+    let manifest = serde_json::json!({
+        "id": plugin_id,
+        "name": plugin_id,
+        "version": "1.0",
+        "entry": "main.js",
+        "hooks": hooks,
+        "api_version": 1,
+    });
+    fs::write(dir.join("plugin.json"), manifest.to_string()).unwrap();
+    
+    assert!(load_plugin(&dir.to_string_lossy()).success);
+    for hook_name in hooks {
+        register_plugin_hook(plugin_id, hook_name, hook.clone()).unwrap();
+    }
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const VALID_MANIFEST: &str = r#"{
+        "id": "com.example.formatter",
+        "name": "Formatter",
+        "version": "1.2.0",
+        "entry": "main.js",
+        "hooks": ["onFileSave", "onBuildStart"],
+        "api_version": 1,
+        "tags": ["format"]
+    }"#;
+    
+    // Fields reported by a manifest that failed to parse
+    fn error_fields(content: &str) -> Vec<String> {
+        parse_manifest(content)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| error.field)
+            .collect()
+    }
+    
+    // Plugin directory holding the given plugin.json and, optionally, an entry point file
+    fn plugin_dir(test_name: &str, manifest: &str, entry: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("anyoneide-plugin-{}-{}", test_name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("plugin.json"), manifest).unwrap();
+        if let Some(entry) = entry {
+            fs::write(dir.join(entry), "").unwrap();
+        }
+        dir
+    }
+    
+    #[test]
+    fn valid_manifest_parses() {
+        let manifest = parse_manifest(VALID_MANIFEST).unwrap();
+        
+        assert_eq!(manifest.id, "com.example.formatter");
+        assert_eq!(manifest.entry, "main.js");
+        assert_eq!(manifest.hooks, vec!["onFileSave", "onBuildStart"]);
+        assert_eq!(manifest.api_version, 1);
+        assert_eq!(manifest.tags, vec!["format"]);
+        assert!(manifest.description.is_empty());
+    }
+    
+    #[test]
+    fn invalid_json_is_reported() {
+        assert_eq!(error_fields("{ not json"), vec!["plugin.json"]);
+        assert_eq!(error_fields("[1, 2]"), vec!["plugin.json"]);
+    }
+    
+    #[test]
+    fn every_missing_required_field_is_reported() {
+        assert_eq!(
+            error_fields("{}"),
+            vec!["id", "name", "version", "entry", "hooks", "api_version"]
+        );
+    }
+    
+    #[test]
+    fn wrongly_typed_and_empty_fields_are_reported() {
+        let errors = parse_manifest(r#"{
+            "id": "bad id!",
+            "name": "",
+            "version": 2,
+            "entry": "main.js",
+            "hooks": "onFileSave",
+            "api_version": "1"
+        }"#).unwrap_err();
+        
+        let messages: Vec<String> = errors.iter().map(|error| format!("{}: {}", error.field, error.message)).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "name: Field 'name' must not be empty",
+                "version: Field 'version' must be a string",
+                "id: Plugin ID contains invalid characters: bad id!",
+                "hooks: Field 'hooks' must be an array of strings",
+                "api_version: Field 'api_version' must be a non-negative integer",
+            ]
+        );
+    }
+    
+    #[test]
+    fn unknown_and_duplicate_hooks_are_reported() {
+        let errors = parse_manifest(r#"{
+            "id": "hooks", "name": "Hooks", "version": "1.0", "entry": "main.js", "api_version": 1,
+            "hooks": ["onFileSave", "onFileOpen", "onFileSave"]
+        }"#).unwrap_err();
+        
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, vec!["Unknown hook: onFileOpen", "Hook listed more than once: onFileSave"]);
+    }
+    
+    #[test]
+    fn unsupported_api_version_is_reported() {
+        let manifest = VALID_MANIFEST.replace("\"api_version\": 1", &format!("\"api_version\": {}", MAX_PLUGIN_API_VERSION + 1));
+        let errors = parse_manifest(&manifest).unwrap_err();
+        
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "api_version");
+        assert!(errors[0].message.starts_with("Unsupported plugin API version"));
+    }
+    
+    #[test]
+    fn entry_must_exist_inside_the_plugin_directory() {
+        let missing = plugin_dir("missing-entry", VALID_MANIFEST, None);
+        let errors = read_manifest(&missing).unwrap_err();
+        assert_eq!(errors[0].message, "Entry point not found: main.js");
+        
+        let escaping = plugin_dir("escaping-entry", &VALID_MANIFEST.replace("main.js", "../main.js"), None);
+        let errors = read_manifest(&escaping).unwrap_err();
+        assert_eq!(errors[0].message, "Entry point must be a path inside the plugin directory");
+        
+        let _ = fs::remove_dir_all(missing);
+        let _ = fs::remove_dir_all(escaping);
+    }
+    
+    #[test]
+    fn load_plugin_reports_the_manifest() {
+        let manifest = VALID_MANIFEST.replace("com.example.formatter", "com.example.load-test");
+        let dir = plugin_dir("load", &manifest, Some("main.js"));
+        
+        let result = load_plugin(&dir.to_string_lossy());
+        assert!(result.success, "{:?}", result.errors);
+        assert_eq!(result.manifest.unwrap().id, "com.example.load-test");
+        assert!(get_loaded_plugins().iter().any(|plugin| plugin.id == "com.example.load-test" && plugin.version == "1.2.0"));
+        
+        unload_plugin("com.example.load-test").unwrap();
+        let _ = fs::remove_dir_all(dir);
+    }
+    
+    #[test]
+    fn load_plugin_without_manifest_fails_with_errors() {
+        let dir = std::env::temp_dir().join(format!("anyoneide-plugin-no-manifest-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        
+        let result = load_plugin(&dir.to_string_lossy());
+        assert!(!result.success);
+        assert!(result.manifest.is_none());
+        assert_eq!(result.errors[0].field, "plugin.json");
+        
+        let _ = fs::remove_dir_all(dir);
+    }
+}
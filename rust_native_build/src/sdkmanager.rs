@@ -1,10 +1,15 @@
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::process::{Command, Stdio};
-use std::io::{BufRead, BufReader};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::process::{Command, Stdio, Child, ChildStdout};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use serde::{Serialize, Deserialize};
 use anyhow::{Result, anyhow};
+use lazy_static::lazy_static;
+use walkdir::WalkDir;
 
 // SDK Component types
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -37,8 +42,23 @@ pub struct SdkComponent {
     pub size_mb: f64,
     pub description: String,
     pub dependencies: Vec<String>,
+    pub checksum: Option<String>,
 }
 
+// How long `sdkmanager --list` results are reused before querying again
+const REMOTE_PACKAGE_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+// Cached remote package lists, keyed by Android SDK directory
+lazy_static! {
+    static ref REMOTE_PACKAGE_CACHE: Mutex<HashMap<PathBuf, (Instant, Vec<SdkComponent>)>> = Mutex::new(HashMap::new());
+}
+
+// SHA-256 of commandlinetools-linux-9477386_latest.zip
+const CMDLINE_TOOLS_SHA256: &str = "bd1aa17c7ef10066949c88dc6c9c8d536be27f992a1f3b5a584f9bd2ba5646a0";
+
+// SHA-1 of android-ndk-r25c-linux.zip; Google only publishes a SHA-1 for NDK archives
+const NDK_R25C_SHA1: &str = "53af80a1cce9144025b81c78c8cd556bff42bd0e";
+
 // Installation progress
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum InstallationProgress {
@@ -69,7 +89,23 @@ pub struct SdkManagerStatus {
     pub installed_components: Vec<SdkComponent>,
 }
 
+// Disk usage of a single managed SDK directory
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SdkDirectoryUsage {
+    pub name: String,
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+// Disk usage of the whole managed SDK
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SdkDiskUsage {
+    pub components: Vec<SdkDirectoryUsage>,
+    pub total_bytes: u64,
+}
+
 // SDK Manager
+#[derive(Clone)]
 pub struct SdkManager {
     sdk_root: PathBuf,
     android_sdk_dir: PathBuf,
@@ -78,6 +114,7 @@ pub struct SdkManager {
     gradle_dir: PathBuf,
     ndk_dir: PathBuf,
     rust_dir: PathBuf,
+    system_android_sdk_dir: Option<PathBuf>,
 }
 
 impl SdkManager {
@@ -110,7 +147,50 @@ impl SdkManager {
             gradle_dir,
             ndk_dir,
             rust_dir,
+            system_android_sdk_dir: detect_system_android_sdk(),
+        }
+    }
+    
+    // Create an SDK Manager for an Android SDK that is already installed on the
+    // system (ANDROID_HOME, ANDROID_SDK_ROOT or a common install location).
+    // Nothing is created on disk.
+    pub fn from_env() -> Option<SdkManager> {
+        let android_sdk_dir = detect_system_android_sdk()?;
+        
+        Some(Self {
+            sdk_root: android_sdk_dir.clone(),
+            jdk_dir: android_sdk_dir.join("jdk"),
+            kotlin_dir: android_sdk_dir.join("kotlin"),
+            gradle_dir: android_sdk_dir.join("gradle"),
+            ndk_dir: android_sdk_dir.join("ndk"),
+            rust_dir: android_sdk_dir.join("rust"),
+            system_android_sdk_dir: Some(android_sdk_dir.clone()),
+            android_sdk_dir,
+        })
+    }
+    
+    // SDK directories to resolve tools against, in order: ANDROID_HOME / ANDROID_SDK_ROOT,
+    // the managed SDK, then a system SDK found in a common install location
+    fn android_sdk_search_dirs(&self) -> Vec<PathBuf> {
+        let mut sdk_dirs = android_sdk_dirs_from_env();
+        sdk_dirs.push(self.android_sdk_dir.clone());
+        sdk_dirs.extend(self.system_android_sdk_dir.clone());
+        sdk_dirs
+    }
+    
+    // platform-tools directory of the first SDK that has adb
+    fn platform_tools_dir(&self) -> PathBuf {
+        let adb_name = if cfg!(windows) { "adb.exe" } else { "adb" };
+        let managed = self.android_sdk_dir.join("platform-tools");
+        
+        for sdk_dir in self.android_sdk_search_dirs() {
+            let platform_tools = sdk_dir.join("platform-tools");
+            if platform_tools.join(adb_name).exists() {
+                return platform_tools;
+            }
         }
+        
+        managed
     }
     
     // Get SDK status
@@ -181,7 +261,7 @@ impl SdkManager {
     
     // Check if Android SDK is installed
     pub fn is_android_sdk_installed(&self) -> bool {
-        let platform_tools_dir = self.android_sdk_dir.join("platform-tools");
+        let platform_tools_dir = self.platform_tools_dir();
         let adb_path = platform_tools_dir.join(if cfg!(windows) { "adb.exe" } else { "adb" });
         
         adb_path.exists()
@@ -292,9 +372,11 @@ impl SdkManager {
         if let Ok(entries) = fs::read_dir(&self.ndk_dir) {
             for entry in entries.filter_map(Result::ok) {
                 let path = entry.path();
-                if path.is_dir() && path.file_name().map_or(false, |name| {
+                // Managed installs are named android-ndk-*, Android Studio uses ndk/<version>
+                let has_ndk_build = path.join(if cfg!(windows) { "ndk-build.cmd" } else { "ndk-build" }).exists();
+                if path.is_dir() && (has_ndk_build || path.file_name().map_or(false, |name| {
                     name.to_string_lossy().contains("ndk")
-                }) {
+                })) {
                     return path;
                 }
             }
@@ -331,7 +413,38 @@ impl SdkManager {
     
     // Get ADB path
     pub fn get_adb_path(&self) -> PathBuf {
-        self.android_sdk_dir.join("platform-tools").join(if cfg!(windows) { "adb.exe" } else { "adb" })
+        self.platform_tools_dir().join(if cfg!(windows) { "adb.exe" } else { "adb" })
+    }
+    
+    // Newest installed build-tools version directory of the first SDK that has any
+    pub fn get_build_tools_dir(&self) -> Option<PathBuf> {
+        self.android_sdk_search_dirs().into_iter().find_map(|sdk_dir| {
+            fs::read_dir(sdk_dir.join("build-tools"))
+                .ok()?
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .max_by_key(|path| {
+                    // "34.0.0" or "35.0.0-rc1"; compare the numeric parts
+                    path.file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .split(|c: char| c == '.' || c == '-')
+                        .map(|part| part.parse::<u32>().unwrap_or(0))
+                        .collect::<Vec<u32>>()
+                })
+        })
+    }
+    
+    // Get keytool path
+    pub fn get_keytool_path(&self) -> PathBuf {
+        let jdk_path = self.get_jdk_path();
+        jdk_path.join("bin").join(if cfg!(windows) { "keytool.exe" } else { "keytool" })
+    }
+    
+    // Debug keystore used to sign APKs when no release keystore is given
+    pub fn get_debug_keystore_path(&self) -> PathBuf {
+        self.android_sdk_dir.join("debug.keystore")
     }
     
     // Get emulator path
@@ -359,6 +472,7 @@ impl SdkManager {
             size_mb: 150.0,
             description: "Android Software Development Kit".to_string(),
             dependencies: Vec::new(),
+            checksum: Some(CMDLINE_TOOLS_SHA256.to_string()),
         });
         
         // Platform tools
@@ -372,6 +486,7 @@ impl SdkManager {
             size_mb: 30.0,
             description: "Android Debug Bridge (adb) and other tools".to_string(),
             dependencies: vec!["android-sdk".to_string()],
+            checksum: None,
         });
         
         // Build tools
@@ -385,6 +500,7 @@ impl SdkManager {
             size_mb: 50.0,
             description: "Android SDK Build Tools 34.0.0".to_string(),
             dependencies: vec!["android-sdk".to_string()],
+            checksum: None,
         });
         
         // Platform
@@ -398,6 +514,7 @@ impl SdkManager {
             size_mb: 70.0,
             description: "Android SDK Platform 34".to_string(),
             dependencies: vec!["android-sdk".to_string()],
+            checksum: None,
         });
         
         // JDK
@@ -411,6 +528,7 @@ impl SdkManager {
             size_mb: 200.0,
             description: "OpenJDK 17".to_string(),
             dependencies: Vec::new(),
+            checksum: None,
         });
         
         // Kotlin
@@ -424,6 +542,7 @@ impl SdkManager {
             size_mb: 50.0,
             description: "Kotlin Programming Language".to_string(),
             dependencies: vec!["jdk-17".to_string()],
+            checksum: None,
         });
         
         // Gradle
@@ -437,6 +556,7 @@ impl SdkManager {
             size_mb: 100.0,
             description: "Gradle Build Tool".to_string(),
             dependencies: vec!["jdk-17".to_string()],
+            checksum: None,
         });
         
         // NDK
@@ -450,6 +570,7 @@ impl SdkManager {
             size_mb: 500.0,
             description: "Android Native Development Kit".to_string(),
             dependencies: vec!["android-sdk".to_string()],
+            checksum: Some(NDK_R25C_SHA1.to_string()),
         });
         
         // CMake
@@ -463,6 +584,7 @@ impl SdkManager {
             size_mb: 30.0,
             description: "CMake build system".to_string(),
             dependencies: vec!["android-sdk".to_string()],
+            checksum: None,
         });
         
         // Rust
@@ -476,11 +598,161 @@ impl SdkManager {
             size_mb: 150.0,
             description: "Rust Programming Language".to_string(),
             dependencies: Vec::new(),
+            checksum: None,
         });
         
         components
     }
     
+    // List packages known to sdkmanager, both installed and available remotely.
+    // Results are cached per SDK root for a few minutes.
+    pub fn list_remote_packages(&self) -> Result<Vec<SdkComponent>> {
+        if let Some((fetched_at, packages)) = REMOTE_PACKAGE_CACHE.lock().unwrap().get(&self.android_sdk_dir) {
+            if fetched_at.elapsed() < REMOTE_PACKAGE_CACHE_TTL {
+                return Ok(packages.clone());
+            }
+        }
+        
+        let sdkmanager_path = self.get_sdkmanager_path();
+        if !sdkmanager_path.exists() {
+            return Err(anyhow!(
+                "sdkmanager not found at {}; install the Android SDK command-line tools first",
+                sdkmanager_path.to_string_lossy()
+            ));
+        }
+        
+        let output = Command::new(&sdkmanager_path)
+            .arg("--list")
+            .arg(format!("--sdk_root={}", self.android_sdk_dir.to_string_lossy()))
+            .envs(self.java_environment())
+            .stdin(Stdio::null())
+            .output()?;
+        
+        if !output.status.success() {
+            return Err(anyhow!(
+                "sdkmanager --list failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        
+        let packages = self.parse_sdkmanager_list(&String::from_utf8_lossy(&output.stdout));
+        
+        REMOTE_PACKAGE_CACHE
+            .lock()
+            .unwrap()
+            .insert(self.android_sdk_dir.clone(), (Instant::now(), packages.clone()));
+        
+        Ok(packages)
+    }
+    
+    // Parse the tables printed by `sdkmanager --list`
+    fn parse_sdkmanager_list(&self, output: &str) -> Vec<SdkComponent> {
+        let mut packages: Vec<SdkComponent> = Vec::new();
+        let mut section = "";
+        
+        for line in output.lines() {
+            let trimmed = line.trim();
+            
+            if trimmed.starts_with("Installed packages") {
+                section = "installed";
+                continue;
+            } else if trimmed.starts_with("Available Packages") {
+                section = "available";
+                continue;
+            } else if trimmed.starts_with("Available Updates") {
+                section = "updates";
+                continue;
+            }
+            
+            if section.is_empty() || section == "updates" {
+                continue;
+            }
+            
+            let columns: Vec<&str> = trimmed.split('|').map(|column| column.trim()).collect();
+            if columns.len() < 3 || columns[0] == "Path" || columns[0].starts_with("---") {
+                continue;
+            }
+            
+            let id = columns[0].to_string();
+            let version = columns[1].to_string();
+            let description = columns[2].to_string();
+            let installed = section == "installed";
+            
+            // Installed packages report their location; otherwise derive it from the id
+            let relative_path = if installed && columns.len() > 3 {
+                columns[3].to_string()
+            } else {
+                id.replace(';', "/")
+            };
+            
+            if let Some(existing) = packages.iter_mut().find(|package| package.id == id) {
+                existing.installed = existing.installed || installed;
+                continue;
+            }
+            
+            packages.push(SdkComponent {
+                id: id.clone(),
+                name: description.clone(),
+                version,
+                component_type: component_type_for_package(&id),
+                path: self.android_sdk_dir.join(relative_path).to_string_lossy().to_string(),
+                installed,
+                size_mb: 0.0,
+                description,
+                dependencies: Vec::new(),
+                checksum: None,
+            });
+        }
+        
+        packages
+    }
+    
+    // Names of the Android virtual devices the emulator can start
+    pub fn list_avds(&self) -> Result<Vec<String>> {
+        let emulator = self.get_emulator_path();
+        if !emulator.exists() {
+            return Err(anyhow!("Android emulator not found. Install the emulator package from the SDK manager"));
+        }
+        
+        let output = self.execute_command(&[&emulator.to_string_lossy(), "-list-avds"], None)?;
+        
+        // Newer emulators also print "INFO    | ..." log lines; AVD names can't contain spaces
+        Ok(output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.contains(char::is_whitespace))
+            .map(str::to_string)
+            .collect())
+    }
+    
+    // Get disk usage per managed SDK directory
+    pub fn disk_usage(&self) -> SdkDiskUsage {
+        let directories = [
+            ("android", &self.android_sdk_dir),
+            ("jdk", &self.jdk_dir),
+            ("kotlin", &self.kotlin_dir),
+            ("gradle", &self.gradle_dir),
+            ("ndk", &self.ndk_dir),
+            ("rust", &self.rust_dir),
+        ];
+        
+        let components: Vec<SdkDirectoryUsage> = directories
+            .iter()
+            .map(|(name, dir)| SdkDirectoryUsage {
+                name: name.to_string(),
+                path: dir.to_string_lossy().to_string(),
+                size_bytes: directory_size(dir),
+            })
+            .collect();
+        
+        let total_bytes = components.iter().map(|component| component.size_bytes).sum();
+        
+        SdkDiskUsage {
+            components,
+            total_bytes,
+        }
+    }
+    
     // Get installed components
     pub fn get_installed_components(&self) -> Vec<SdkComponent> {
         self.get_available_components()
@@ -489,49 +761,69 @@ impl SdkManager {
             .collect()
     }
     
-    // Install Android SDK
-    pub fn install_android_sdk(&self, version: &str) -> Result<impl Iterator<Item = InstallationProgress>> {
+    // Install Android SDK command-line tools and platform-tools
+    pub fn install_android_sdk(&self, version: &str, resume: bool) -> Result<Box<dyn Iterator<Item = InstallationProgress>>> {
         let download_url = "https://dl.google.com/android/repository/commandlinetools-linux-9477386_latest.zip";
         let download_path = self.android_sdk_dir.join("cmdline-tools.zip");
+        let staging_dir = self.android_sdk_dir.join(".cmdline-tools-staging");
         
-        let progress_vec = vec![
-            InstallationProgress::Started { 
-                message: format!("Installing Android SDK {}", version) 
-            },
-            InstallationProgress::Downloading { 
-                progress: 0, 
-                total_size: 100_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 50, 
-                total_size: 100_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 100, 
-                total_size: 100_000_000 
-            },
-            InstallationProgress::Extracting { 
-                message: "Extracting Android SDK tools...".to_string() 
-            },
-            InstallationProgress::Installing { 
-                message: "Installing platform-tools...".to_string() 
-            },
-            InstallationProgress::Installing { 
-                message: format!("Installing build-tools;{}.0.0...", version) 
-            },
-            InstallationProgress::Installing { 
-                message: format!("Installing platforms;android-{}...", version) 
-            },
-            InstallationProgress::Completed { 
-                message: format!("Android SDK {} installed successfully", version) 
-            },
-        ];
+        let android_sdk_dir = self.android_sdk_dir.clone();
+        let sdkmanager_path = self.get_sdkmanager_path();
+        let java_environment = self.java_environment();
+        let layout_staging_dir = staging_dir.clone();
+        
+        let task = InstallationTask::new(
+            &format!("Android SDK {}", version),
+            download_url,
+            &download_path,
+            &staging_dir,
+            ArchiveFormat::Zip,
+        )
+        .with_resume(resume)
+        .with_checksum(Some(CMDLINE_TOOLS_SHA256))
+        .with_post_install("Installing platform-tools...", move || {
+            // The archive contains a top-level cmdline-tools directory, but
+            // sdkmanager expects to live in cmdline-tools/latest
+            let latest_dir = android_sdk_dir.join("cmdline-tools").join("latest");
+            if latest_dir.exists() {
+                fs::remove_dir_all(&latest_dir)?;
+            }
+            fs::create_dir_all(android_sdk_dir.join("cmdline-tools"))?;
+            fs::rename(layout_staging_dir.join("cmdline-tools"), &latest_dir)?;
+            let _ = fs::remove_dir_all(&layout_staging_dir);
+            
+            // Install platform-tools, accepting the license prompts
+            let mut child = Command::new(&sdkmanager_path)
+                .arg(format!("--sdk_root={}", android_sdk_dir.to_string_lossy()))
+                .arg("platform-tools")
+                .envs(java_environment)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            
+            if let Some(mut stdin) = child.stdin.take() {
+                for _ in 0..20 {
+                    let _ = stdin.write_all(b"y\n");
+                }
+            }
+            
+            let output = child.wait_with_output()?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "sdkmanager failed to install platform-tools: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+            }
+            
+            Ok(())
+        });
         
-        Ok(progress_vec.into_iter())
+        Ok(Box::new(task))
     }
     
     // Install JDK
-    pub fn install_jdk(&self, version: &str) -> Result<impl Iterator<Item = InstallationProgress>> {
+    pub fn install_jdk(&self, version: &str, resume: bool) -> Result<Box<dyn Iterator<Item = InstallationProgress>>> {
         let jdk_url = match version {
             "11" => "https://github.com/adoptium/temurin11-binaries/releases/download/jdk-11.0.20%2B8/OpenJDK11U-jdk_aarch64_linux_hotspot_11.0.20_8.tar.gz",
             "17" => "https://github.com/adoptium/temurin17-binaries/releases/download/jdk-17.0.8%2B7/OpenJDK17U-jdk_aarch64_linux_hotspot_17.0.8_7.tar.gz",
@@ -541,231 +833,262 @@ impl SdkManager {
         
         let download_path = self.jdk_dir.join(format!("jdk-{}.tar.gz", version));
         
-        let progress_vec = vec![
-            InstallationProgress::Started { 
-                message: format!("Installing JDK {}", version) 
-            },
-            InstallationProgress::Downloading { 
-                progress: 0, 
-                total_size: 200_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 50, 
-                total_size: 200_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 100, 
-                total_size: 200_000_000 
-            },
-            InstallationProgress::Extracting { 
-                message: format!("Extracting JDK {}...", version) 
-            },
-            InstallationProgress::Installing { 
-                message: "Setting up JDK environment...".to_string() 
-            },
-            InstallationProgress::Completed { 
-                message: format!("JDK {} installed successfully", version) 
-            },
-        ];
+        let task = InstallationTask::new(
+            &format!("JDK {}", version),
+            jdk_url,
+            &download_path,
+            &self.jdk_dir,
+            ArchiveFormat::TarGz,
+        )
+        .with_resume(resume);
         
-        Ok(progress_vec.into_iter())
+        Ok(Box::new(task))
     }
     
     // Install Kotlin compiler
-    pub fn install_kotlin(&self, version: &str) -> Result<impl Iterator<Item = InstallationProgress>> {
+    pub fn install_kotlin(&self, version: &str, resume: bool) -> Result<Box<dyn Iterator<Item = InstallationProgress>>> {
         let kotlin_url = format!("https://github.com/JetBrains/kotlin/releases/download/v{}/kotlin-compiler-{}.zip", version, version);
         let download_path = self.kotlin_dir.join(format!("kotlin-compiler-{}.zip", version));
         
-        let progress_vec = vec![
-            InstallationProgress::Started { 
-                message: format!("Installing Kotlin compiler {}", version) 
-            },
-            InstallationProgress::Downloading { 
-                progress: 0, 
-                total_size: 50_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 50, 
-                total_size: 50_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 100, 
-                total_size: 50_000_000 
-            },
-            InstallationProgress::Extracting { 
-                message: "Extracting Kotlin compiler...".to_string() 
-            },
-            InstallationProgress::Installing { 
-                message: "Setting up Kotlin environment...".to_string() 
-            },
-            InstallationProgress::Completed { 
-                message: format!("Kotlin compiler {} installed successfully", version) 
-            },
-        ];
+        let task = InstallationTask::new(
+            &format!("Kotlin compiler {}", version),
+            &kotlin_url,
+            &download_path,
+            &self.kotlin_dir,
+            ArchiveFormat::Zip,
+        )
+        .with_resume(resume);
         
-        Ok(progress_vec.into_iter())
+        Ok(Box::new(task))
     }
     
     // Install Gradle
-    pub fn install_gradle(&self, version: &str) -> Result<impl Iterator<Item = InstallationProgress>> {
+    pub fn install_gradle(&self, version: &str, resume: bool) -> Result<Box<dyn Iterator<Item = InstallationProgress>>> {
         let gradle_url = format!("https://services.gradle.org/distributions/gradle-{}-bin.zip", version);
         let download_path = self.gradle_dir.join(format!("gradle-{}.zip", version));
         
-        let progress_vec = vec![
-            InstallationProgress::Started { 
-                message: format!("Installing Gradle {}", version) 
-            },
-            InstallationProgress::Downloading { 
-                progress: 0, 
-                total_size: 100_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 50, 
-                total_size: 100_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 100, 
-                total_size: 100_000_000 
-            },
-            InstallationProgress::Extracting { 
-                message: "Extracting Gradle...".to_string() 
-            },
-            InstallationProgress::Installing { 
-                message: "Setting up Gradle environment...".to_string() 
-            },
-            InstallationProgress::Completed { 
-                message: format!("Gradle {} installed successfully", version) 
-            },
-        ];
+        let task = InstallationTask::new(
+            &format!("Gradle {}", version),
+            &gradle_url,
+            &download_path,
+            &self.gradle_dir,
+            ArchiveFormat::Zip,
+        )
+        .with_resume(resume);
         
-        Ok(progress_vec.into_iter())
+        Ok(Box::new(task))
     }
     
     // Install NDK
-    pub fn install_ndk(&self, version: &str) -> Result<impl Iterator<Item = InstallationProgress>> {
-        let ndk_url = "https://dl.google.com/android/repository/android-ndk-r25c-linux.zip";
+    pub fn install_ndk(&self, version: &str, resume: bool) -> Result<Box<dyn Iterator<Item = InstallationProgress>>> {
+        let ndk_url = match version {
+            "25.2.9519653" => "https://dl.google.com/android/repository/android-ndk-r25c-linux.zip",
+            _ => return Err(anyhow!("Unsupported NDK version: {}", version)),
+        };
+        
         let download_path = self.ndk_dir.join(format!("android-ndk-{}.zip", version));
         
-        let progress_vec = vec![
-            InstallationProgress::Started { 
-                message: format!("Installing Android NDK {}", version) 
-            },
-            InstallationProgress::Downloading { 
-                progress: 0, 
-                total_size: 500_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 25, 
-                total_size: 500_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 50, 
-                total_size: 500_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 75, 
-                total_size: 500_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 100, 
-                total_size: 500_000_000 
-            },
-            InstallationProgress::Extracting { 
-                message: "Extracting Android NDK...".to_string() 
-            },
-            InstallationProgress::Installing { 
-                message: "Setting up Android NDK environment...".to_string() 
-            },
-            InstallationProgress::Completed { 
-                message: format!("Android NDK {} installed successfully", version) 
-            },
-        ];
+        let task = InstallationTask::new(
+            &format!("Android NDK {}", version),
+            ndk_url,
+            &download_path,
+            &self.ndk_dir,
+            ArchiveFormat::Zip,
+        )
+        .with_resume(resume)
+        .with_sha1_checksum(NDK_R25C_SHA1);
         
-        Ok(progress_vec.into_iter())
+        Ok(Box::new(task))
     }
     
     // Install Rust
-    pub fn install_rust(&self, channel: &str) -> Result<impl Iterator<Item = InstallationProgress>> {
+    pub fn install_rust(&self, channel: &str, resume: bool) -> Result<Box<dyn Iterator<Item = InstallationProgress>>> {
         let rustup_url = "https://sh.rustup.rs";
         let rustup_script = self.rust_dir.join("rustup-init.sh");
         
-        let progress_vec = vec![
-            InstallationProgress::Started { 
-                message: format!("Installing Rust {}", channel) 
-            },
-            InstallationProgress::Downloading { 
-                progress: 0, 
-                total_size: 1_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 50, 
-                total_size: 1_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 100, 
-                total_size: 1_000_000 
-            },
-            InstallationProgress::Installing { 
-                message: "Running Rust installer...".to_string() 
-            },
-            InstallationProgress::Installing { 
-                message: "Installing Rust components...".to_string() 
-            },
-            InstallationProgress::Installing { 
-                message: "Adding Android targets...".to_string() 
-            },
-            InstallationProgress::Completed { 
-                message: format!("Rust {} installed successfully", channel) 
-            },
-        ];
+        let channel_name = channel.to_string();
+        let script_path = rustup_script.clone();
+        let cargo_home = self.rust_dir.join("cargo");
+        let rustup_home = self.rust_dir.join("rustup");
+        let rustup_path = self.get_rustup_path();
+        
+        let task = InstallationTask::new(
+            &format!("Rust {}", channel),
+            rustup_url,
+            &rustup_script,
+            &self.rust_dir,
+            ArchiveFormat::None,
+        )
+        .with_resume(resume)
+        .with_post_install("Running Rust installer...", move || {
+            let status = Command::new("sh")
+                .arg(&script_path)
+                .arg("-y")
+                .arg("--no-modify-path")
+                .arg("--default-toolchain")
+                .arg(&channel_name)
+                .env("CARGO_HOME", &cargo_home)
+                .env("RUSTUP_HOME", &rustup_home)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()?;
+            
+            if !status.success() {
+                return Err(anyhow!("rustup-init exited with code {}", status.code().unwrap_or(-1)));
+            }
+            
+            let _ = fs::remove_file(&script_path);
+            
+            // Add Android targets
+            let status = Command::new(&rustup_path)
+                .arg("target")
+                .arg("add")
+                .arg("aarch64-linux-android")
+                .arg("armv7-linux-androideabi")
+                .arg("x86_64-linux-android")
+                .arg("i686-linux-android")
+                .env("CARGO_HOME", &cargo_home)
+                .env("RUSTUP_HOME", &rustup_home)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()?;
+            
+            if !status.success() {
+                return Err(anyhow!("Failed to add Android targets"));
+            }
+            
+            Ok(())
+        });
         
-        Ok(progress_vec.into_iter())
+        Ok(Box::new(task))
     }
     
-    // Install SDK component
-    pub fn install_component(&self, component_id: &str) -> Result<impl Iterator<Item = InstallationProgress>> {
+    // Install SDK component along with any missing dependencies. With resume set,
+    // interrupted downloads continue from where they stopped.
+    pub fn install_component(&self, component_id: &str, resume: bool) -> Result<Box<dyn Iterator<Item = InstallationProgress>>> {
         let components = self.get_available_components();
-        let component = components.iter().find(|c| c.id == component_id)
-            .ok_or_else(|| anyhow!("Component not found: {}", component_id))?;
+        if !components.iter().any(|c| c.id == component_id) {
+            return Err(anyhow!("Component not found: {}", component_id));
+        }
+        
+        let order = match resolve_dependency_order(&components, component_id) {
+            Ok(order) => order,
+            Err(e) => {
+                return Ok(Box::new(std::iter::once(InstallationProgress::Failed {
+                    message: format!("Failed to resolve dependencies for {}", component_id),
+                    error: Some(e.to_string()),
+                })));
+            }
+        };
+        
+        // Dependencies that are already installed are skipped; the requested
+        // component itself is always (re)installed
+        let queue = order
+            .into_iter()
+            .filter(|component| component.id == component_id || !component.installed)
+            .collect();
+        
+        Ok(Box::new(DependencyInstallation {
+            manager: self.clone(),
+            target_id: component_id.to_string(),
+            resume,
+            queue,
+            current: None,
+            finished: false,
+        }))
+    }
+    
+    // Install a single SDK component without looking at its dependencies
+    fn install_single_component(&self, component: &SdkComponent, resume: bool) -> Result<Box<dyn Iterator<Item = InstallationProgress>>> {
+        let component_id = component.id.as_str();
         
         match component.component_type {
-            SdkComponentType::AndroidSdk => self.install_android_sdk("34"),
-            SdkComponentType::Jdk => self.install_jdk("17"),
-            SdkComponentType::Kotlin => self.install_kotlin("1.9.20"),
-            SdkComponentType::Gradle => self.install_gradle("8.4"),
-            SdkComponentType::Ndk => self.install_ndk("25.2.9519653"),
-            SdkComponentType::Rust => self.install_rust("stable"),
+            SdkComponentType::AndroidSdk => self.install_android_sdk("34", resume),
+            SdkComponentType::Jdk => self.install_jdk("17", resume),
+            SdkComponentType::Kotlin => self.install_kotlin(&component.version, resume),
+            SdkComponentType::Gradle => self.install_gradle(&component.version, resume),
+            SdkComponentType::Ndk => self.install_ndk(&component.version, resume),
+            SdkComponentType::Rust => self.install_rust(&component.version, resume),
             _ => {
-                // For other components, use sdkmanager
-                if self.is_android_sdk_installed() {
-                    self.install_android_component(component_id)
-                } else {
-                    Err(anyhow!("Android SDK not installed"))
-                }
+                // Platforms, build-tools, system images etc. have no direct
+                // download URL, so delegate to Google's sdkmanager
+                self.install_via_sdkmanager(component_id)
             }
         }
     }
     
-    // Install Android component using sdkmanager
-    fn install_android_component(&self, component_id: &str) -> Result<impl Iterator<Item = InstallationProgress>> {
+    // Install an Android package (e.g. "platforms;android-34") using sdkmanager,
+    // accepting licenses automatically and streaming its output as progress
+    pub fn install_via_sdkmanager(&self, package: &str) -> Result<Box<dyn Iterator<Item = InstallationProgress>>> {
         let sdkmanager_path = self.get_sdkmanager_path();
         if !sdkmanager_path.exists() {
-            return Err(anyhow!("sdkmanager not found"));
+            return Err(anyhow!(
+                "sdkmanager not found at {}; install the Android SDK command-line tools first",
+                sdkmanager_path.to_string_lossy()
+            ));
         }
         
-        let progress_vec = vec![
-            InstallationProgress::Started { 
-                message: format!("Installing component: {}", component_id) 
-            },
-            InstallationProgress::Installing { 
-                message: format!("Running sdkmanager for {}", component_id) 
-            },
-            InstallationProgress::Completed { 
-                message: format!("Component {} installed successfully", component_id) 
-            },
-        ];
+        let mut child = Command::new(&sdkmanager_path)
+            .arg(format!("--sdk_root={}", self.android_sdk_dir.to_string_lossy()))
+            .arg(package)
+            .envs(self.java_environment())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        
+        // Answer every license prompt with "y" until sdkmanager closes stdin
+        if let Some(mut stdin) = child.stdin.take() {
+            thread::spawn(move || {
+                while stdin.write_all(b"y\n").is_ok() {}
+            });
+        }
+        
+        // Collect stderr in the background so a full pipe can't stall the install
+        let stderr_output = Arc::new(Mutex::new(String::new()));
+        if let Some(stderr) = child.stderr.take() {
+            let stderr_output = stderr_output.clone();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stderr);
+                let mut buffer = String::new();
+                let _ = reader.read_to_string(&mut buffer);
+                *stderr_output.lock().unwrap() = buffer;
+            });
+        }
+        
+        let stdout = child.stdout.take().map(BufReader::new);
+        
+        Ok(Box::new(SdkManagerInstallation {
+            package: package.to_string(),
+            child: Some(child),
+            stdout,
+            stderr_output,
+            started: false,
+        }))
+    }
+    
+    // JAVA_HOME for tools that need a JVM, when the managed JDK is installed
+    pub fn java_environment(&self) -> Vec<(String, String)> {
+        let mut environment = Vec::new();
+        
+        if self.is_jdk_installed() {
+            environment.push(("JAVA_HOME".to_string(), self.get_jdk_path().to_string_lossy().to_string()));
+        }
+        
+        environment
+    }
+    
+    // CARGO_HOME and RUSTUP_HOME for the managed toolchain; its cargo and rustc are rustup proxies
+    // that need to find their homes
+    pub fn rust_environment(&self) -> Vec<(String, String)> {
+        let mut environment = Vec::new();
         
-        Ok(progress_vec.into_iter())
+        if self.is_rust_installed() {
+            environment.push(("CARGO_HOME".to_string(), self.rust_dir.join("cargo").to_string_lossy().to_string()));
+            environment.push(("RUSTUP_HOME".to_string(), self.rust_dir.join("rustup").to_string_lossy().to_string()));
+        }
+        
+        environment
     }
     
     // Uninstall SDK component
@@ -856,8 +1179,11 @@ impl SdkManager {
         
         // Set environment variables
         if self.is_android_sdk_installed() {
-            cmd.env("ANDROID_HOME", &self.android_sdk_dir);
-            cmd.env("ANDROID_SDK_ROOT", &self.android_sdk_dir);
+            // May be a system SDK rather than the managed one
+            let platform_tools_dir = self.platform_tools_dir();
+            let android_home = platform_tools_dir.parent().unwrap_or(&self.android_sdk_dir);
+            cmd.env("ANDROID_HOME", android_home);
+            cmd.env("ANDROID_SDK_ROOT", android_home);
         }
         
         if self.is_jdk_installed() {
@@ -899,57 +1225,692 @@ impl SdkManager {
     }
     
     // Download file with progress tracking
-    pub fn download_file(&self, url: &str, destination: &Path) -> Result<impl Iterator<Item = InstallationProgress>> {
-        // In a real implementation, this would download the file with progress tracking
-        // For now, we'll just return a simulated progress
+    pub fn download_file(&self, url: &str, destination: &Path) -> Result<Box<dyn Iterator<Item = InstallationProgress>>> {
+        let name = destination
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| url.to_string());
         
-        let progress_vec = vec![
-            InstallationProgress::Started { 
-                message: format!("Downloading {}", url) 
-            },
-            InstallationProgress::Downloading { 
-                progress: 0, 
-                total_size: 100_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 25, 
-                total_size: 100_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 50, 
-                total_size: 100_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 75, 
-                total_size: 100_000_000 
-            },
-            InstallationProgress::Downloading { 
-                progress: 100, 
-                total_size: 100_000_000 
-            },
-            InstallationProgress::Completed { 
-                message: format!("Downloaded {} to {}", url, destination.to_string_lossy()) 
-            },
-        ];
+        let task = InstallationTask::new(&name, url, destination, destination, ArchiveFormat::None);
         
-        Ok(progress_vec.into_iter())
+        Ok(Box::new(task))
     }
     
     // Extract zip file
     pub fn extract_zip(&self, zip_file: &Path, destination: &Path) -> Result<()> {
-        // In a real implementation, this would extract the zip file
-        // For now, we'll just return success
-        
-        Ok(())
+        extract_archive(zip_file, destination, ArchiveFormat::Zip)
     }
     
     // Extract tar.gz file
     pub fn extract_tar_gz(&self, tar_gz_file: &Path, destination: &Path) -> Result<()> {
-        // In a real implementation, this would extract the tar.gz file
-        // For now, we'll just return success
+        extract_archive(tar_gz_file, destination, ArchiveFormat::TarGz)
+    }
+}
+
+// SDK directories named by ANDROID_HOME and ANDROID_SDK_ROOT, in that order
+fn android_sdk_dirs_from_env() -> Vec<PathBuf> {
+    ["ANDROID_HOME", "ANDROID_SDK_ROOT"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+// Find an existing Android SDK from the environment or common install locations
+fn detect_system_android_sdk() -> Option<PathBuf> {
+    let mut candidates = android_sdk_dirs_from_env();
+    
+    if let Ok(home) = std::env::var("HOME") {
+        candidates.push(Path::new(&home).join("Android").join("Sdk"));
+        candidates.push(Path::new(&home).join("Library").join("Android").join("sdk"));
+    }
+    
+    if let Ok(local_app_data) = std::env::var("LOCALAPPDATA") {
+        candidates.push(Path::new(&local_app_data).join("Android").join("Sdk"));
+    }
+    
+    for path in &["/opt/android-sdk", "/usr/local/android-sdk", "/usr/lib/android-sdk"] {
+        candidates.push(PathBuf::from(path));
+    }
+    
+    candidates.into_iter().find(|dir| {
+        dir.join("platform-tools").is_dir() || dir.join("cmdline-tools").is_dir() || dir.join("platforms").is_dir()
+    })
+}
+
+// Recursively sum file sizes, skipping entries that can't be read
+fn directory_size(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+// Map an sdkmanager package id such as "build-tools;34.0.0" to a component type
+fn component_type_for_package(package_id: &str) -> SdkComponentType {
+    let prefix = package_id.split(';').next().unwrap_or(package_id);
+    
+    match prefix {
+        "build-tools" => SdkComponentType::BuildTools,
+        "platform-tools" => SdkComponentType::PlatformTools,
+        "platforms" => SdkComponentType::Platform,
+        "system-images" => SdkComponentType::SystemImages,
+        "emulator" => SdkComponentType::Emulator,
+        "ndk" | "ndk-bundle" => SdkComponentType::Ndk,
+        _ => SdkComponentType::Other(prefix.to_string()),
+    }
+}
+
+// Order a component after its transitive dependencies, failing on cycles or unknown ids
+fn resolve_dependency_order(components: &[SdkComponent], component_id: &str) -> Result<Vec<SdkComponent>> {
+    fn visit(
+        id: &str,
+        components: &[SdkComponent],
+        visiting: &mut Vec<String>,
+        visited: &mut HashSet<String>,
+        order: &mut Vec<SdkComponent>,
+    ) -> Result<()> {
+        if visited.contains(id) {
+            return Ok(());
+        }
+        
+        if let Some(position) = visiting.iter().position(|v| v == id) {
+            let mut cycle = visiting[position..].to_vec();
+            cycle.push(id.to_string());
+            return Err(anyhow!("Dependency cycle detected: {}", cycle.join(" -> ")));
+        }
+        
+        let component = components.iter().find(|c| c.id == id).ok_or_else(|| match visiting.last() {
+            Some(parent) => anyhow!("Unknown component '{}' required by '{}'", id, parent),
+            None => anyhow!("Component not found: {}", id),
+        })?;
+        
+        visiting.push(id.to_string());
+        for dependency in &component.dependencies {
+            visit(dependency, components, visiting, visited, order)?;
+        }
+        visiting.pop();
+        
+        visited.insert(id.to_string());
+        order.push(component.clone());
         
         Ok(())
     }
+    
+    let mut order = Vec::new();
+    visit(component_id, components, &mut Vec::new(), &mut HashSet::new(), &mut order)?;
+    
+    Ok(order)
+}
+
+// Installs queued components one after another, stopping at the first failure.
+// Installers are created lazily so checks like "is the Android SDK installed"
+// see the result of the dependencies installed before them.
+struct DependencyInstallation {
+    manager: SdkManager,
+    target_id: String,
+    resume: bool,
+    queue: VecDeque<SdkComponent>,
+    current: Option<Box<dyn Iterator<Item = InstallationProgress>>>,
+    finished: bool,
+}
+
+impl Iterator for DependencyInstallation {
+    type Item = InstallationProgress;
+    
+    fn next(&mut self) -> Option<InstallationProgress> {
+        loop {
+            if self.finished {
+                return None;
+            }
+            
+            if let Some(current) = &mut self.current {
+                match current.next() {
+                    Some(progress @ InstallationProgress::Failed { .. }) => {
+                        self.finished = true;
+                        return Some(progress);
+                    }
+                    Some(progress) => return Some(progress),
+                    None => self.current = None,
+                }
+            }
+            
+            let component = self.queue.pop_front()?;
+            
+            match self.manager.install_single_component(&component, self.resume) {
+                Ok(progress) => {
+                    self.current = Some(progress);
+                    
+                    if component.id != self.target_id {
+                        return Some(InstallationProgress::Started {
+                            message: format!("Installing dependency {} for {}", component.name, self.target_id),
+                        });
+                    }
+                }
+                Err(e) => {
+                    self.finished = true;
+                    return Some(InstallationProgress::Failed {
+                        message: format!("Failed to install {}", component.id),
+                        error: Some(e.to_string()),
+                    });
+                }
+            }
+        }
+    }
+}
+
+// A running sdkmanager install, yielding each line of its output as progress
+struct SdkManagerInstallation {
+    package: String,
+    child: Option<Child>,
+    stdout: Option<BufReader<ChildStdout>>,
+    stderr_output: Arc<Mutex<String>>,
+    started: bool,
+}
+
+impl Iterator for SdkManagerInstallation {
+    type Item = InstallationProgress;
+    
+    fn next(&mut self) -> Option<InstallationProgress> {
+        if !self.started {
+            self.started = true;
+            return Some(InstallationProgress::Started {
+                message: format!("Installing {} with sdkmanager", self.package),
+            });
+        }
+        
+        if let Some(stdout) = &mut self.stdout {
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match stdout.read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {
+                        // Progress bars redraw with \r; only the latest state is interesting
+                        let text = String::from_utf8_lossy(&line);
+                        let message = text
+                            .split('\r')
+                            .map(|part| part.trim())
+                            .filter(|part| !part.is_empty())
+                            .last()
+                            .unwrap_or("")
+                            .to_string();
+                        
+                        if !message.is_empty() {
+                            return Some(InstallationProgress::Installing { message });
+                        }
+                    }
+                }
+            }
+            self.stdout = None;
+        }
+        
+        let mut child = self.child.take()?;
+        
+        match child.wait() {
+            Ok(status) if status.success() => Some(InstallationProgress::Completed {
+                message: format!("{} installed successfully", self.package),
+            }),
+            Ok(status) => {
+                // Give the stderr thread a moment to finish after the process exits
+                thread::sleep(Duration::from_millis(100));
+                let stderr = self.stderr_output.lock().unwrap().trim().to_string();
+                
+                Some(InstallationProgress::Failed {
+                    message: format!("sdkmanager failed to install {}", self.package),
+                    error: Some(if stderr.is_empty() {
+                        format!("Exit code {}", status.code().unwrap_or(-1))
+                    } else {
+                        stderr
+                    }),
+                })
+            }
+            Err(e) => Some(InstallationProgress::Failed {
+                message: format!("sdkmanager failed to install {}", self.package),
+                error: Some(e.to_string()),
+            }),
+        }
+    }
+}
+
+// Archive formats understood by the installer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+    None,
+}
+
+// Stages of an installation, advanced one step per call to next()
+enum InstallStage {
+    Start,
+    Connect,
+    #[cfg(feature = "download-support")]
+    Download(DownloadState),
+    Verify,
+    Extract,
+    Unpack,
+    PostInstall,
+    RunPostInstall,
+    Complete,
+    Done,
+}
+
+// An in-flight HTTP download
+#[cfg(feature = "download-support")]
+struct DownloadState {
+    response: reqwest::blocking::Response,
+    file: fs::File,
+    downloaded: u64,
+    total_size: u64,
+    last_progress: u32,
+}
+
+// Number of bytes read between progress reports when the size is unknown
+#[cfg(feature = "download-support")]
+const UNKNOWN_SIZE_REPORT_INTERVAL: u64 = 1024 * 1024;
+
+// Lazily downloads, extracts and sets up a component, yielding progress
+// as each step actually happens
+pub struct InstallationTask {
+    name: String,
+    url: String,
+    download_path: PathBuf,
+    extract_dir: PathBuf,
+    format: ArchiveFormat,
+    expected_checksum: Option<(ChecksumAlgorithm, String)>,
+    resume: bool,
+    post_install: Option<(String, Box<dyn FnOnce() -> Result<()>>)>,
+    stage: InstallStage,
+}
+
+impl InstallationTask {
+    pub fn new(name: &str, url: &str, download_path: &Path, extract_dir: &Path, format: ArchiveFormat) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            download_path: download_path.to_path_buf(),
+            extract_dir: extract_dir.to_path_buf(),
+            format,
+            expected_checksum: None,
+            resume: false,
+            post_install: None,
+            stage: InstallStage::Start,
+        }
+    }
+    
+    // Verify the downloaded file against a SHA-256 checksum before extracting it
+    pub fn with_checksum(mut self, sha256: Option<&str>) -> Self {
+        self.expected_checksum = sha256.map(|checksum| (ChecksumAlgorithm::Sha256, checksum.to_lowercase()));
+        self
+    }
+    
+    // Verify against a SHA-1 checksum, for archives that only publish one
+    pub fn with_sha1_checksum(mut self, sha1: &str) -> Self {
+        self.expected_checksum = Some((ChecksumAlgorithm::Sha1, sha1.to_lowercase()));
+        self
+    }
+    
+    // Continue from a previous partial download instead of starting over
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+    
+    // Downloads are written here and only renamed once complete and verified
+    fn part_path(&self) -> PathBuf {
+        let mut path = self.download_path.clone().into_os_string();
+        path.push(".part");
+        PathBuf::from(path)
+    }
+    
+    // Run an extra setup step after the archive has been extracted
+    pub fn with_post_install<F>(mut self, message: &str, step: F) -> Self
+    where
+        F: FnOnce() -> Result<()> + 'static,
+    {
+        self.post_install = Some((message.to_string(), Box::new(step)));
+        self
+    }
+    
+    fn fail(&mut self, message: &str, error: anyhow::Error) -> InstallationProgress {
+        self.stage = InstallStage::Done;
+        InstallationProgress::Failed {
+            message: format!("{}: {}", message, self.name),
+            error: Some(error.to_string()),
+        }
+    }
+    
+    // Start the HTTP request and open the partial file, resuming with a
+    // Range request when a previous attempt left bytes behind
+    #[cfg(feature = "download-support")]
+    fn connect(&self) -> Result<DownloadState> {
+        if let Some(parent) = self.download_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        
+        let part_path = self.part_path();
+        let client = reqwest::blocking::Client::builder()
+            .timeout(None)
+            .build()?;
+        
+        let offset = if self.resume {
+            fs::metadata(&part_path).map(|metadata| metadata.len()).unwrap_or(0)
+        } else {
+            0
+        };
+        
+        if offset > 0 {
+            let response = client
+                .get(&self.url)
+                .header(reqwest::header::RANGE, format!("bytes={}-", offset))
+                .send()?;
+            
+            // 206 means the server honoured the range, so keep appending
+            if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+                let remaining = response.content_length().unwrap_or(0);
+                let file = fs::OpenOptions::new().append(true).open(&part_path)?;
+                let total_size = if remaining > 0 { offset + remaining } else { 0 };
+                
+                return Ok(DownloadState {
+                    response,
+                    file,
+                    downloaded: offset,
+                    total_size,
+                    last_progress: if total_size > 0 { ((offset * 100) / total_size) as u32 } else { 0 },
+                });
+            }
+            
+            // A plain 200 restarts the file; anything else (e.g. 416) gets a fresh request
+            if response.status() != reqwest::StatusCode::OK {
+                return self.connect_fresh(&client, &part_path);
+            }
+            
+            let total_size = response.content_length().unwrap_or(0);
+            let file = fs::File::create(&part_path)?;
+            
+            return Ok(DownloadState {
+                response,
+                file,
+                downloaded: 0,
+                total_size,
+                last_progress: 0,
+            });
+        }
+        
+        self.connect_fresh(&client, &part_path)
+    }
+    
+    #[cfg(feature = "download-support")]
+    fn connect_fresh(&self, client: &reqwest::blocking::Client, part_path: &Path) -> Result<DownloadState> {
+        let response = client.get(&self.url).send()?.error_for_status()?;
+        let total_size = response.content_length().unwrap_or(0);
+        let file = fs::File::create(part_path)?;
+        
+        Ok(DownloadState {
+            response,
+            file,
+            downloaded: 0,
+            total_size,
+            last_progress: 0,
+        })
+    }
+    
+    #[cfg(not(feature = "download-support"))]
+    fn connect(&self) -> Result<()> {
+        Err(anyhow!("Download support is not enabled; rebuild with the download-support feature"))
+    }
+}
+
+#[cfg(feature = "download-support")]
+impl DownloadState {
+    // Read until the progress percentage changes; returns true once the download is finished
+    fn advance(&mut self) -> Result<bool> {
+        let mut buffer = [0u8; 64 * 1024];
+        let mut since_report = 0u64;
+        
+        loop {
+            let read = self.response.read(&mut buffer)?;
+            if read == 0 {
+                self.file.flush()?;
+                
+                if self.total_size > 0 && self.downloaded != self.total_size {
+                    return Err(anyhow!(
+                        "Download ended early: received {} of {} bytes",
+                        self.downloaded,
+                        self.total_size
+                    ));
+                }
+                
+                return Ok(true);
+            }
+            
+            self.file.write_all(&buffer[..read])?;
+            self.downloaded += read as u64;
+            since_report += read as u64;
+            
+            if self.total_size > 0 {
+                let progress = ((self.downloaded * 100) / self.total_size).min(100) as u32;
+                if progress > self.last_progress {
+                    self.last_progress = progress;
+                    return Ok(false);
+                }
+            } else if since_report >= UNKNOWN_SIZE_REPORT_INTERVAL {
+                return Ok(false);
+            }
+        }
+    }
+}
+
+impl Iterator for InstallationTask {
+    type Item = InstallationProgress;
+    
+    fn next(&mut self) -> Option<InstallationProgress> {
+        loop {
+            match std::mem::replace(&mut self.stage, InstallStage::Done) {
+                InstallStage::Start => {
+                    self.stage = InstallStage::Connect;
+                    return Some(InstallationProgress::Started {
+                        message: format!("Installing {}", self.name),
+                    });
+                }
+                #[cfg(feature = "download-support")]
+                InstallStage::Connect => match self.connect() {
+                    Ok(state) => {
+                        let total_size = state.total_size;
+                        self.stage = InstallStage::Download(state);
+                        return Some(InstallationProgress::Downloading { progress: 0, total_size });
+                    }
+                    Err(e) => return Some(self.fail("Failed to download", e)),
+                },
+                #[cfg(not(feature = "download-support"))]
+                InstallStage::Connect => match self.connect() {
+                    Ok(()) => self.stage = InstallStage::Extract,
+                    Err(e) => return Some(self.fail("Failed to download", e)),
+                },
+                #[cfg(feature = "download-support")]
+                InstallStage::Download(mut state) => match state.advance() {
+                    Ok(finished) => {
+                        // Unknown sizes report the bytes received so far
+                        let (progress, total_size) = if state.total_size > 0 {
+                            (state.last_progress, state.total_size)
+                        } else {
+                            (if finished { 100 } else { 0 }, state.downloaded)
+                        };
+                        
+                        self.stage = if finished {
+                            InstallStage::Verify
+                        } else {
+                            InstallStage::Download(state)
+                        };
+                        
+                        return Some(InstallationProgress::Downloading { progress, total_size });
+                    }
+                    Err(e) => {
+                        drop(state);
+                        
+                        // Keep the partial file around so the next attempt can resume
+                        if !self.resume {
+                            let _ = fs::remove_file(self.part_path());
+                        }
+                        
+                        return Some(self.fail("Failed to download", e));
+                    }
+                },
+                InstallStage::Verify => {
+                    let part_path = self.part_path();
+                    
+                    if let Some((algorithm, expected)) = self.expected_checksum.clone() {
+                        match digest_file(&part_path, algorithm) {
+                            Ok(actual) if actual == expected => {}
+                            Ok(actual) => {
+                                // Never extract a corrupt or tampered archive
+                                let _ = fs::remove_file(&part_path);
+                                self.stage = InstallStage::Done;
+                                return Some(InstallationProgress::Failed {
+                                    message: "checksum mismatch".to_string(),
+                                    error: Some(format!(
+                                        "{}: expected {} {}, got {}",
+                                        part_path.to_string_lossy(),
+                                        algorithm.name(),
+                                        expected,
+                                        actual
+                                    )),
+                                });
+                            }
+                            Err(e) => return Some(self.fail("Failed to verify", e)),
+                        }
+                    }
+                    
+                    if let Err(e) = fs::rename(&part_path, &self.download_path) {
+                        return Some(self.fail("Failed to save download", e.into()));
+                    }
+                    
+                    self.stage = InstallStage::Extract;
+                }
+                InstallStage::Extract => {
+                    if self.format == ArchiveFormat::None {
+                        self.stage = InstallStage::PostInstall;
+                        continue;
+                    }
+                    
+                    self.stage = InstallStage::Unpack;
+                    return Some(InstallationProgress::Extracting {
+                        message: format!("Extracting {}...", self.name),
+                    });
+                }
+                InstallStage::Unpack => {
+                    if let Err(e) = extract_archive(&self.download_path, &self.extract_dir, self.format) {
+                        return Some(self.fail("Failed to extract", e));
+                    }
+                    
+                    // The archive is no longer needed once extracted
+                    let _ = fs::remove_file(&self.download_path);
+                    self.stage = InstallStage::PostInstall;
+                }
+                InstallStage::PostInstall => {
+                    if let Some((message, _)) = &self.post_install {
+                        let message = message.clone();
+                        self.stage = InstallStage::RunPostInstall;
+                        return Some(InstallationProgress::Installing { message });
+                    }
+                    
+                    self.stage = InstallStage::Complete;
+                }
+                InstallStage::RunPostInstall => {
+                    if let Some((_, step)) = self.post_install.take() {
+                        if let Err(e) = step() {
+                            return Some(self.fail("Failed to set up", e));
+                        }
+                    }
+                    
+                    self.stage = InstallStage::Complete;
+                }
+                InstallStage::Complete => {
+                    return Some(InstallationProgress::Completed {
+                        message: format!("{} installed successfully", self.name),
+                    });
+                }
+                InstallStage::Done => return None,
+            }
+        }
+    }
+}
+
+// Digest used to verify a downloaded archive
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ChecksumAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha1 => "SHA-1",
+            ChecksumAlgorithm::Sha256 => "SHA-256",
+        }
+    }
+}
+
+// Compute the lowercase hex digest of a file
+#[cfg(feature = "download-support")]
+fn digest_file(path: &Path, algorithm: ChecksumAlgorithm) -> Result<String> {
+    use sha2::Digest;
+    
+    fn hash<D: Digest + std::io::Write>(path: &Path) -> Result<Vec<u8>> {
+        let mut file = fs::File::open(path)?;
+        let mut hasher = D::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        Ok(hasher.finalize().to_vec())
+    }
+    
+    let digest = match algorithm {
+        ChecksumAlgorithm::Sha1 => hash::<sha1::Sha1>(path)?,
+        ChecksumAlgorithm::Sha256 => hash::<sha2::Sha256>(path)?,
+    };
+    
+    Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(not(feature = "download-support"))]
+fn digest_file(_path: &Path, _algorithm: ChecksumAlgorithm) -> Result<String> {
+    Err(anyhow!("Checksum verification is not enabled; rebuild with the download-support feature"))
+}
+
+// Extract an archive into the destination directory
+#[cfg(feature = "download-support")]
+fn extract_archive(archive: &Path, destination: &Path, format: ArchiveFormat) -> Result<()> {
+    fs::create_dir_all(destination)?;
+    
+    match format {
+        ArchiveFormat::Zip => {
+            let file = fs::File::open(archive)?;
+            let mut zip = zip::ZipArchive::new(file)?;
+            zip.extract(destination)?;
+        }
+        ArchiveFormat::TarGz => {
+            let file = fs::File::open(archive)?;
+            let decoder = flate2::read::GzDecoder::new(file);
+            let mut tar = tar::Archive::new(decoder);
+            tar.unpack(destination)?;
+        }
+        ArchiveFormat::None => {}
+    }
+    
+    Ok(())
+}
+
+#[cfg(not(feature = "download-support"))]
+fn extract_archive(_archive: &Path, _destination: &Path, format: ArchiveFormat) -> Result<()> {
+    match format {
+        ArchiveFormat::None => Ok(()),
+        _ => Err(anyhow!("Archive extraction is not enabled; rebuild with the download-support feature")),
+    }
 }
 
 // JNI functions
@@ -995,7 +1956,8 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Compan
     
     let sdk_manager = SdkManager::new(Path::new(&sdk_root));
     
-    match sdk_manager.install_component(&component_id) {
+    // Always resume partial downloads left by an earlier attempt
+    match sdk_manager.install_component(&component_id, true) {
         Ok(progress_iter) => {
             // Convert progress iterator to JSON array
             let progress_vec: Vec<InstallationProgress> = progress_iter.collect();
@@ -1098,4 +2060,149 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Compan
             output.into_raw()
         }
     }
-}
\ No newline at end of file
+}
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeListRemoteSdkPackages(
+    env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    sdk_root: jni::objects::JString,
+) -> jni::sys::jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let sdk_manager = SdkManager::new(Path::new(&sdk_root));
+    
+    match sdk_manager.list_remote_packages() {
+        Ok(packages) => {
+            let json = serde_json::to_string(&packages).unwrap_or_else(|_| "[]".to_string());
+            let output = env
+                .new_string(json)
+                .expect("Failed to create Java string");
+            output.into_raw()
+        }
+        Err(e) => {
+            let error_json = serde_json::json!({ "error": e.to_string() }).to_string();
+            let output = env
+                .new_string(error_json)
+                .expect("Failed to create Java string");
+            output.into_raw()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeListAvds(
+    env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    sdk_root: jni::objects::JString,
+) -> jni::sys::jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let sdk_manager = SdkManager::new(Path::new(&sdk_root));
+    
+    let json = match sdk_manager.list_avds() {
+        Ok(avds) => serde_json::to_string(&avds).unwrap_or_else(|_| "[]".to_string()),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustNativeBuildManager_00024Companion_nativeGetSdkDiskUsage(
+    env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    sdk_root: jni::objects::JString,
+) -> jni::sys::jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let sdk_manager = SdkManager::new(Path::new(&sdk_root));
+    let usage = sdk_manager.disk_usage();
+    
+    let json = serde_json::to_string(&usage).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn component(id: &str, dependencies: &[&str]) -> SdkComponent {
+        SdkComponent {
+            id: id.to_string(),
+            name: id.to_string(),
+            version: "1.0".to_string(),
+            component_type: SdkComponentType::Other(id.to_string()),
+            path: String::new(),
+            installed: false,
+            size_mb: 0.0,
+            description: String::new(),
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            checksum: None,
+        }
+    }
+    
+    fn ids(order: &[SdkComponent]) -> Vec<&str> {
+        order.iter().map(|c| c.id.as_str()).collect()
+    }
+    
+    #[test]
+    fn dependencies_come_before_dependents() {
+        let components = vec![
+            component("kotlin", &["jdk"]),
+            component("gradle", &["jdk", "kotlin"]),
+            component("jdk", &[]),
+        ];
+        
+        let order = resolve_dependency_order(&components, "gradle").unwrap();
+        assert_eq!(ids(&order), vec!["jdk", "kotlin", "gradle"]);
+    }
+    
+    #[test]
+    fn shared_dependencies_are_listed_once() {
+        let components = vec![
+            component("app", &["a", "b"]),
+            component("a", &["base"]),
+            component("b", &["base"]),
+            component("base", &[]),
+        ];
+        
+        let order = resolve_dependency_order(&components, "app").unwrap();
+        assert_eq!(ids(&order), vec!["base", "a", "b", "app"]);
+    }
+    
+    #[test]
+    fn cycles_are_reported() {
+        let components = vec![
+            component("a", &["b"]),
+            component("b", &["c"]),
+            component("c", &["a"]),
+        ];
+        
+        let error = resolve_dependency_order(&components, "a").unwrap_err().to_string();
+        assert_eq!(error, "Dependency cycle detected: a -> b -> c -> a");
+    }
+    
+    #[test]
+    fn unknown_dependencies_are_reported() {
+        let components = vec![component("kotlin", &["jdk"])];
+        
+        let error = resolve_dependency_order(&components, "kotlin").unwrap_err().to_string();
+        assert_eq!(error, "Unknown component 'jdk' required by 'kotlin'");
+    }
+}
@@ -0,0 +1,225 @@
+use std::fs;
+use std::path::Path;
+use regex::{Regex, RegexBuilder};
+use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, anyhow};
+
+// Results returned when the caller does not set a limit
+const DEFAULT_MAX_RESULTS: usize = 1000;
+
+// Files larger than this are skipped rather than read into memory
+const MAX_SEARCH_FILE_BYTES: u64 = 8 * 1024 * 1024;
+
+// How much of the start of a file is checked for NUL bytes to detect binaries
+const BINARY_CHECK_BYTES: usize = 8192;
+
+// Longer lines, e.g. in minified files, are cut down to an excerpt around each match
+const MAX_LINE_TEXT_BYTES: usize = 500;
+
+// Project-wide search options
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SearchOptions {
+    pub case_insensitive: bool,
+    // Treat the pattern as a regular expression instead of literal text
+    pub regex: bool,
+    // Only search files matching one of these globs, e.g. "*.rs" or "src/**"
+    pub glob_include: Vec<String>,
+    // Skip files matching any of these globs
+    pub glob_exclude: Vec<String>,
+    // 0 means DEFAULT_MAX_RESULTS
+    pub max_results: usize,
+}
+
+impl SearchOptions {
+    fn result_limit(&self) -> usize {
+        if self.max_results == 0 { DEFAULT_MAX_RESULTS } else { self.max_results }
+    }
+}
+
+// A single match. Lines and columns are 1-based, match offsets are bytes into `line_text`,
+// which is an excerpt when the line is very long
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchMatch {
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub line_text: String,
+    pub match_start: usize,
+    pub match_end: usize,
+}
+
+// Search every file under `root` for `pattern`, skipping files excluded by .gitignore,
+// .ignore, hidden files and binaries. Stops once `max_results` matches have been found
+pub fn grep(root: &str, pattern: &str, options: &SearchOptions) -> Result<Vec<SearchMatch>> {
+    let root_path = Path::new(root);
+    if !root_path.is_dir() {
+        return Err(anyhow!("Directory not found: {}", root));
+    }
+    
+    let matcher = build_matcher(pattern, options)?;
+    let max_results = options.result_limit();
+    
+    let mut overrides = OverrideBuilder::new(root_path);
+    for glob in &options.glob_include {
+        overrides.add(glob).map_err(|e| anyhow!("Invalid include glob {}: {}", glob, e))?;
+    }
+    for glob in &options.glob_exclude {
+        overrides.add(&format!("!{}", glob)).map_err(|e| anyhow!("Invalid exclude glob {}: {}", glob, e))?;
+    }
+    let overrides = overrides.build().map_err(|e| anyhow!("Invalid search globs: {}", e))?;
+    
+    let walker = WalkBuilder::new(root_path)
+        // Honour .gitignore in projects that are not git repositories yet
+        .require_git(false)
+        .overrides(overrides)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    
+    let mut matches = Vec::new();
+    
+    for entry in walker.filter_map(|entry| entry.ok()) {
+        if !entry.file_type().map_or(false, |file_type| file_type.is_file()) {
+            continue;
+        }
+        
+        let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if size > MAX_SEARCH_FILE_BYTES {
+            continue;
+        }
+        
+        let relative_path = entry
+            .path()
+            .strip_prefix(root_path)
+            .unwrap_or(entry.path())
+            .to_string_lossy()
+            .replace('\\', "/");
+        
+        search_file(entry.path(), &relative_path, &matcher, max_results, &mut matches);
+        if matches.len() >= max_results {
+            break;
+        }
+    }
+    
+    Ok(matches)
+}
+
+fn build_matcher(pattern: &str, options: &SearchOptions) -> Result<Regex> {
+    if pattern.is_empty() {
+        return Err(anyhow!("Search pattern cannot be empty"));
+    }
+    
+    let source = if options.regex { pattern.to_string() } else { regex::escape(pattern) };
+    
+    RegexBuilder::new(&source)
+        .case_insensitive(options.case_insensitive)
+        .build()
+        .map_err(|e| anyhow!("Invalid search pattern: {}", e))
+}
+
+// Append the matches in one file, stopping at `max_results` in total
+fn search_file(path: &Path, relative_path: &str, matcher: &Regex, max_results: usize, matches: &mut Vec<SearchMatch>) {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(_) => return,
+    };
+    
+    if bytes[..bytes.len().min(BINARY_CHECK_BYTES)].contains(&0) {
+        return;
+    }
+    
+    let content = String::from_utf8_lossy(&bytes);
+    
+    for (index, line) in content.lines().enumerate() {
+        for found in matcher.find_iter(line) {
+            // Empty matches, e.g. from `^` or `a*`, carry no location worth showing
+            if found.start() == found.end() {
+                continue;
+            }
+            
+            let (line_text, match_start, match_end) = line_excerpt(line, found.start(), found.end());
+            matches.push(SearchMatch {
+                path: relative_path.to_string(),
+                line: index + 1,
+                column: line[..found.start()].chars().count() + 1,
+                line_text,
+                match_start,
+                match_end,
+            });
+            
+            if matches.len() >= max_results {
+                return;
+            }
+        }
+    }
+}
+
+// The line, or a window of it starting a little before the match, with the match offsets
+// moved into that window
+fn line_excerpt(line: &str, start: usize, end: usize) -> (String, usize, usize) {
+    if line.len() <= MAX_LINE_TEXT_BYTES {
+        return (line.to_string(), start, end);
+    }
+    
+    let mut from = start.saturating_sub(MAX_LINE_TEXT_BYTES / 4);
+    while !line.is_char_boundary(from) {
+        from -= 1;
+    }
+    
+    let mut to = (from + MAX_LINE_TEXT_BYTES).min(line.len());
+    while !line.is_char_boundary(to) {
+        to += 1;
+    }
+    
+    (line[from..to].to_string(), start - from, end.min(to) - from)
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustEditorManager_00024Companion_nativeGrep(
+    env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    root: jni::objects::JString,
+    pattern: jni::objects::JString,
+    options_json: jni::objects::JString,
+) -> jni::sys::jstring {
+    let root: String = env
+        .get_string(root)
+        .expect("Failed to get root string")
+        .into();
+    
+    let pattern: String = env
+        .get_string(pattern)
+        .expect("Failed to get pattern string")
+        .into();
+    
+    let options_json: String = env
+        .get_string(options_json)
+        .expect("Failed to get options JSON string")
+        .into();
+    
+    // An empty options string means the defaults
+    let options = if options_json.trim().is_empty() {
+        Ok(SearchOptions::default())
+    } else {
+        serde_json::from_str::<SearchOptions>(&options_json)
+    };
+    
+    let json = match options {
+        Ok(options) => match grep(&root, &pattern, &options) {
+            Ok(matches) => serde_json::json!({
+                "truncated": matches.len() >= options.result_limit(),
+                "matches": matches,
+            })
+            .to_string(),
+            Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+        },
+        Err(e) => serde_json::json!({ "error": format!("Invalid search options: {}", e) }).to_string(),
+    };
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
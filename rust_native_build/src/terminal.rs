@@ -2,8 +2,9 @@ use jni::JNIEnv;
 use jni::objects::{JClass, JString};
 use jni::sys::jstring;
 use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
-use std::process::{Command, Stdio, Child, ChildStdin, ChildStdout, ChildStderr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::process::{Command, Stdio, Child, ChildStdin};
 use std::io::{BufRead, BufReader, Write, Read};
 use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
@@ -13,9 +14,15 @@ use serde_json;
 use std::env;
 use std::fs;
 use anyhow::{Result, anyhow};
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
 use lazy_static::lazy_static;
+use regex::Regex;
 use uuid::Uuid;
 
+use crate::extensions;
+use crate::pluginsystem::{self, RunCommandPayload};
+use crate::sdkmanager::SdkManager;
+
 // Terminal session data
 struct TerminalSession {
     id: String,
@@ -23,18 +30,91 @@ struct TerminalSession {
     environment: HashMap<String, String>,
     current_process: Option<TerminalProcess>,
     history: Vec<String>,
+    // Oldest entries are dropped once the history grows past this
+    history_limit: usize,
+    // Don't record a command that repeats the previous entry
+    skip_duplicate_history: bool,
     created_at: u64,
     last_activity: u64,
 }
 
+// Emulator flags for launch_avd
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AvdLaunchOptions {
+    // Run headless, e.g. when the device is only used through adb
+    pub no_window: bool,
+    // Cold boot and don't save a snapshot on exit
+    pub no_snapshot: bool,
+    // GPU emulation mode such as "auto", "host" or "swiftshader_indirect"
+    pub gpu: Option<String>,
+}
+
 // Terminal process
 struct TerminalProcess {
     process: Child,
     stdin: Option<ChildStdin>,
-    stdout_reader: Option<BufReader<ChildStdout>>,
-    stderr_reader: Option<BufReader<ChildStderr>>,
+    stdout_buffer: OutputBuffer,
+    stderr_buffer: OutputBuffer,
     command: String,
     start_time: u64,
+    // Report stdout as parsed logcat lines as well as raw text
+    parse_logcat: bool,
+}
+
+// Maximum number of lines kept per output stream before the oldest are dropped
+const OUTPUT_BUFFER_CAPACITY: usize = 10000;
+
+// Default history length, also exported to the shell as HISTSIZE
+const DEFAULT_HISTORY_LIMIT: usize = 1000;
+
+// Default cap on the bytes kept from each output stream of a one-shot command
+const DEFAULT_OUTPUT_LIMIT_BYTES: usize = 16 * 1024 * 1024;
+
+// Current per-stream cap for execute_command, see nativeSetOutputLimit
+static OUTPUT_LIMIT_BYTES: AtomicUsize = AtomicUsize::new(DEFAULT_OUTPUT_LIMIT_BYTES);
+
+// Ring buffer of output lines, filled by a background reader thread
+#[derive(Clone)]
+struct OutputBuffer {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl OutputBuffer {
+    fn new() -> Self {
+        Self {
+            lines: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+    
+    fn push(&self, line: String) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() >= OUTPUT_BUFFER_CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+    
+    // Take everything currently buffered
+    fn drain(&self) -> Vec<String> {
+        self.lines.lock().unwrap().drain(..).collect()
+    }
+    
+    // Copy everything currently buffered without consuming it
+    fn peek(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+// How execute_command turns a command's output bytes into text
+#[derive(Clone, Copy)]
+enum OutputEncoding {
+    // UTF-8, with invalid sequences replaced
+    Utf8,
+    // A byte order mark, else UTF-8 if the output is valid UTF-8, else the given fallback
+    Detect(&'static Encoding),
+    // A charset the caller named
+    Declared(&'static Encoding),
 }
 
 // Terminal information
@@ -74,9 +154,60 @@ struct CommandOutput {
     timestamp: u64,
 }
 
+// Rules applied before a command is run through su. Commands matching any deny pattern
+// are refused; with `require_confirmation` every other command must be re-submitted confirmed
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RootCommandPolicy {
+    pub deny_patterns: Vec<String>,
+    pub require_confirmation: bool,
+}
+
+// Paths `rm` in the terminal and safe_remove won't delete. "/", HOME and, on Android, the app's
+// data directory are always protected; `protected_paths` adds more. Deleting any ancestor of a
+// protected path is refused too. `allow_dangerous_remove` switches the guard off
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RemoveGuardPolicy {
+    pub protected_paths: Vec<String>,
+    pub allow_dangerous_remove: bool,
+}
+
+// What the policy says about a root command that was not refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootCommandDecision {
+    Allow,
+    NeedsConfirmation,
+}
+
+// A logcat line in threadtime format, split so the UI can color by level
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LogcatLine {
+    pub timestamp: Option<String>,
+    pub pid: Option<u32>,
+    pub tid: Option<u32>,
+    pub level: Option<String>,
+    pub tag: Option<String>,
+    pub message: String,
+}
+
 // Global sessions storage
 lazy_static! {
     static ref SESSIONS: Arc<Mutex<HashMap<String, TerminalSession>>> = Arc::new(Mutex::new(HashMap::new()));
+    
+    // The root command policy with its deny patterns compiled
+    static ref ROOT_COMMAND_POLICY: Mutex<(RootCommandPolicy, Vec<Regex>)> = Mutex::new((RootCommandPolicy::default(), Vec::new()));
+    
+    // Result of the last su probe. Probing can show a superuser prompt, so it is only
+    // repeated when the UI asks for a refresh
+    static ref ROOT_AVAILABLE: Mutex<Option<bool>> = Mutex::new(None);
+    
+    static ref REMOVE_GUARD_POLICY: Mutex<RemoveGuardPolicy> = Mutex::new(RemoveGuardPolicy::default());
+    
+    // "10-16 12:34:56.789  1234  5678 I ActivityManager: message"
+    static ref LOGCAT_LINE_REGEX: Regex = Regex::new(
+        r"^(\d{2}-\d{2}\s+\d{2}:\d{2}:\d{2}\.\d{3})\s+(\d+)\s+(\d+)\s+([VDIWEFAS])\s+(.*?)\s*:(?:\s(.*))?$"
+    ).unwrap();
 }
 
 // Create a new terminal session
@@ -91,37 +222,10 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
         .expect("Failed to get working directory string")
         .into();
     
-    let session_id = format!("terminal_{}", Uuid::new_v4().to_string());
-    
-    let mut sessions = SESSIONS.lock().unwrap();
-    
-    // Create environment variables
-    let mut environment = HashMap::new();
-    for (key, value) in env::vars() {
-        environment.insert(key, value);
-    }
-    
-    // Add custom environment variables
-    environment.insert("TERM".to_string(), "xterm-256color".to_string());
-    environment.insert("LANG".to_string(), "en_US.UTF-8".to_string());
-    environment.insert("HOME".to_string(), working_dir.clone());
-    environment.insert("PS1".to_string(), "\\[\\e[32m\\]\\u@\\h:\\[\\e[34m\\]\\w\\[\\e[0m\\]\\$ ".to_string());
-    environment.insert("HISTSIZE".to_string(), "1000".to_string());
-    environment.insert("HISTFILESIZE".to_string(), "2000".to_string());
+    let session = new_session(&working_dir);
+    let session_id = session.id.clone();
     
-    // Create session
-    sessions.insert(
-        session_id.clone(),
-        TerminalSession {
-            id: session_id.clone(),
-            working_directory: PathBuf::from(working_dir),
-            environment,
-            current_process: None,
-            history: Vec::new(),
-            created_at: current_time_millis(),
-            last_activity: current_time_millis(),
-        },
-    );
+    SESSIONS.lock().unwrap().insert(session_id.clone(), session);
     
     let output = env
         .new_string(session_id)
@@ -176,14 +280,14 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
         .into();
     
     // Execute the command
+    let environment: HashMap<String, String> = env::vars().collect();
     let start_time = Instant::now();
-    let output = execute_command(&command, &working_dir);
+    let output = execute_command(&command, &working_dir, &environment, None, OutputEncoding::Utf8);
     
-    // Add execution time
+    // Add execution time (working directory is set by execute_command, cd may change it)
     let mut output_with_time = output;
     output_with_time.execution_time_ms = start_time.elapsed().as_millis() as u64;
     output_with_time.command = command;
-    output_with_time.working_directory = working_dir;
     output_with_time.timestamp = current_time_millis();
     
     // Convert output to JSON
@@ -195,21 +299,151 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
     output.into_raw()
 }
 
+// Execute a command in a session, keeping the effect of built-ins like cd and export
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeExecuteInSession(
+    env: JNIEnv,
+    _class: JClass,
+    session_id: JString,
+    command: JString,
+) -> jstring {
+    let session_id: String = env
+        .get_string(session_id)
+        .expect("Failed to get session ID string")
+        .into();
+    
+    let command: String = env
+        .get_string(command)
+        .expect("Failed to get command string")
+        .into();
+    
+    let json = match execute_in_session(&session_id, &command) {
+        Ok(output) => serde_json::to_string(&output).unwrap_or_else(|_| "{}".to_string()),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Execute a command with `stdin_data` piped to its stdin
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeExecuteCommandWithInput(
+    env: JNIEnv,
+    _class: JClass,
+    command: JString,
+    working_dir: JString,
+    stdin_data: JString,
+) -> jstring {
+    let command: String = env
+        .get_string(command)
+        .expect("Failed to get command string")
+        .into();
+    
+    let working_dir: String = env
+        .get_string(working_dir)
+        .expect("Failed to get working directory string")
+        .into();
+    
+    let stdin_data: String = env
+        .get_string(stdin_data)
+        .expect("Failed to get stdin data string")
+        .into();
+    
+    let environment: HashMap<String, String> = env::vars().collect();
+    let output = execute_command(&command, &working_dir, &environment, Some(&stdin_data), OutputEncoding::Utf8);
+    
+    let json = serde_json::to_string(&output).unwrap_or_else(|_| "{}".to_string());
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Execute a command and decode its output with the given charset. An empty label means UTF-8
+// with invalid bytes replaced, "auto" detects the encoding, anything else is an encoding label
+// such as "ISO-8859-1", "Shift_JIS" or "GBK"
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeExecuteCommandWithEncoding(
+    env: JNIEnv,
+    _class: JClass,
+    command: JString,
+    working_dir: JString,
+    encoding: JString,
+) -> jstring {
+    let command: String = env
+        .get_string(command)
+        .expect("Failed to get command string")
+        .into();
+    
+    let working_dir: String = env
+        .get_string(working_dir)
+        .expect("Failed to get working directory string")
+        .into();
+    
+    let encoding: String = env
+        .get_string(encoding)
+        .expect("Failed to get encoding string")
+        .into();
+    
+    let environment: HashMap<String, String> = env::vars().collect();
+    let json = match output_encoding(&encoding, &environment) {
+        Ok(encoding) => {
+            let output = execute_command(&command, &working_dir, &environment, None, encoding);
+            serde_json::to_string(&output).unwrap_or_else(|_| "{}".to_string())
+        }
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
 // Execute a root command
 #[no_mangle]
 pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeExecuteRootCommand(
     env: JNIEnv,
     _class: JClass,
     command: JString,
+    confirmed: jni::sys::jboolean,
 ) -> jstring {
     let command: String = env
         .get_string(command)
         .expect("Failed to get command string")
         .into();
     
-    // Execute the root command
+    let decision = check_root_command(&command, confirmed != 0);
+    
+    // Execute the root command unless the policy refuses it or wants it confirmed first
     let start_time = Instant::now();
-    let output = execute_root_command(&command);
+    let output = match decision {
+        Ok(RootCommandDecision::Allow) => execute_root_command(&command),
+        Ok(RootCommandDecision::NeedsConfirmation) => CommandOutput {
+            success: false,
+            output: Vec::new(),
+            error_output: vec!["Root command requires confirmation".to_string()],
+            exit_code: -1,
+            execution_time_ms: 0,
+            command: command.clone(),
+            working_directory: "/".to_string(),
+            timestamp: 0,
+        },
+        Err(ref e) => CommandOutput {
+            success: false,
+            output: Vec::new(),
+            error_output: vec![e.to_string()],
+            exit_code: -1,
+            execution_time_ms: 0,
+            command: command.clone(),
+            working_directory: "/".to_string(),
+            timestamp: 0,
+        },
+    };
     
     // Add execution time
     let mut output_with_time = output;
@@ -218,8 +452,87 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
     output_with_time.working_directory = "/".to_string();
     output_with_time.timestamp = current_time_millis();
     
-    // Convert output to JSON
-    let json = serde_json::to_string(&output_with_time).unwrap_or_else(|_| "{}".to_string());
+    // Convert output to JSON, flagging commands the UI has to confirm and send again
+    let mut result = serde_json::to_value(&output_with_time).unwrap_or_else(|_| serde_json::json!({}));
+    result["needs_confirmation"] = serde_json::json!(matches!(decision, Ok(RootCommandDecision::NeedsConfirmation)));
+    let json = result.to_string();
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Replace the root command policy
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeSetRootPolicy(
+    env: JNIEnv,
+    _class: JClass,
+    policy_json: JString,
+) -> jstring {
+    let policy_json: String = env
+        .get_string(policy_json)
+        .expect("Failed to get policy JSON string")
+        .into();
+    
+    let result = serde_json::from_str::<RootCommandPolicy>(&policy_json)
+        .map_err(|e| anyhow!("Invalid root command policy: {}", e))
+        .and_then(set_root_command_policy);
+    
+    let json = match result {
+        Ok(_) => serde_json::json!({ "success": true }).to_string(),
+        Err(e) => serde_json::json!({ "success": false, "error": e.to_string() }).to_string(),
+    };
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Replace the list of paths rm and nativeSafeRemove refuse to delete
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeSetRemoveGuardPolicy(
+    env: JNIEnv,
+    _class: JClass,
+    policy_json: JString,
+) -> jstring {
+    let policy_json: String = env
+        .get_string(policy_json)
+        .expect("Failed to get policy JSON string")
+        .into();
+    
+    let json = match serde_json::from_str::<RemoveGuardPolicy>(&policy_json) {
+        Ok(policy) => {
+            *REMOVE_GUARD_POLICY.lock().unwrap() = policy;
+            serde_json::json!({ "success": true }).to_string()
+        }
+        Err(e) => serde_json::json!({ "success": false, "error": format!("Invalid remove guard policy: {}", e) }).to_string(),
+    };
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Delete a file or directory, refusing protected paths
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeSafeRemove(
+    env: JNIEnv,
+    _class: JClass,
+    path: JString,
+    recursive: jni::sys::jboolean,
+) -> jstring {
+    let path: String = env
+        .get_string(path)
+        .expect("Failed to get path string")
+        .into();
+    
+    let json = match safe_remove(&path, recursive != 0) {
+        Ok(_) => serde_json::json!({ "success": true }).to_string(),
+        Err(e) => serde_json::json!({ "success": false, "error": e.to_string() }).to_string(),
+    };
     
     let output = env
         .new_string(json)
@@ -240,6 +553,19 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
     }
 }
 
+// Probe for root again, e.g. after the user granted access, and return the new result
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeRefreshRootStatus(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jni::sys::jboolean {
+    if refresh_root_status() {
+        1 // true
+    } else {
+        0 // false
+    }
+}
+
 // Get terminal information
 #[no_mangle]
 pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeGetTerminalInfo(
@@ -302,14 +628,20 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
     output.into_raw()
 }
 
-// Set an environment variable
+// Set an environment variable in one session
 #[no_mangle]
 pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeSetEnvironmentVariable(
     env: JNIEnv,
     _class: JClass,
+    session_id: JString,
     name: JString,
     value: JString,
 ) -> jni::sys::jboolean {
+    let session_id: String = env
+        .get_string(session_id)
+        .expect("Failed to get session ID string")
+        .into();
+    
     let name: String = env
         .get_string(name)
         .expect("Failed to get name string")
@@ -320,15 +652,38 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
         .expect("Failed to get value string")
         .into();
     
-    // Set the environment variable
-    env::set_var(name, value);
+    match set_session_env(&session_id, &name, &value) {
+        Ok(_) => 1, // true
+        Err(_) => 0, // false
+    }
+}
+
+// Set an environment variable for the whole process. Existing sessions keep their own
+// environment; sessions created afterwards inherit the new value
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeSetGlobalEnv(
+    env: JNIEnv,
+    _class: JClass,
+    name: JString,
+    value: JString,
+) -> jni::sys::jboolean {
+    let name: String = env
+        .get_string(name)
+        .expect("Failed to get name string")
+        .into();
     
-    // Update environment in all sessions
-    let mut sessions = SESSIONS.lock().unwrap();
-    for (_, session) in sessions.iter_mut() {
-        session.environment.insert(name.clone(), value.clone());
+    let value: String = env
+        .get_string(value)
+        .expect("Failed to get value string")
+        .into();
+    
+    // set_var panics on these rather than returning an error
+    if name.is_empty() || name.contains('=') || name.contains('\0') || value.contains('\0') {
+        return 0; // false
     }
     
+    env::set_var(name, value);
+    
     1 // true
 }
 
@@ -379,25 +734,14 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
     let mut sessions = SESSIONS.lock().unwrap();
     
     if let Some(session) = sessions.get_mut(&session_id) {
-        let new_dir = if directory.starts_with('/') {
+        // Expand ~, $VAR and ${VAR} using the session environment
+        let directory = match expand_variables(&directory, &session.environment) {
+            Ok(directory) => directory,
+            Err(_) => return 0, // false
+        };
+        
+        let new_dir = if Path::new(&directory).is_absolute() {
             PathBuf::from(directory)
-        } else if directory == "~" || directory == "$HOME" {
-            if let Some(home) = session.environment.get("HOME") {
-                PathBuf::from(home)
-            } else {
-                PathBuf::from("/")
-            }
-        } else if directory.starts_with("~/") || directory.starts_with("$HOME/") {
-            if let Some(home) = session.environment.get("HOME") {
-                let rel_path = if directory.starts_with("~/") {
-                    &directory[2..]
-                } else {
-                    &directory[6..]
-                };
-                PathBuf::from(home).join(rel_path)
-            } else {
-                PathBuf::from("/")
-            }
         } else {
             session.working_directory.join(directory)
         };
@@ -477,16 +821,26 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
                     let stdout = process.stdout.take();
                     let stderr = process.stderr.take();
                     
-                    let stdout_reader = stdout.map(BufReader::new);
-                    let stderr_reader = stderr.map(BufReader::new);
+                    // Stream output into buffers from background threads
+                    let stdout_buffer = OutputBuffer::new();
+                    let stderr_buffer = OutputBuffer::new();
+                    
+                    if let Some(stdout) = stdout {
+                        spawn_output_reader(stdout, stdout_buffer.clone());
+                    }
+                    
+                    if let Some(stderr) = stderr {
+                        spawn_output_reader(stderr, stderr_buffer.clone());
+                    }
                     
                     session.current_process = Some(TerminalProcess {
                         process,
                         stdin,
-                        stdout_reader,
-                        stderr_reader,
+                        stdout_buffer,
+                        stderr_buffer,
                         command: "interactive shell".to_string(),
                         start_time: current_time_millis(),
+                        parse_logcat: false,
                     });
                     
                     session.last_activity = current_time_millis();
@@ -520,34 +874,169 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
     output.into_raw()
 }
 
-// Send input to interactive shell
+// Start streaming adb logcat into a new session.
+// Output is read with nativeReadOutput and the stream is stopped with nativeStopCommand.
 #[no_mangle]
-pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeSendInput(
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeStartLogcat(
     env: JNIEnv,
     _class: JClass,
-    session_id: JString,
-    input: JString,
+    sdk_root: JString,
+    device_serial: JString,
+    filter: JString,
+    parsed: jni::sys::jboolean,
 ) -> jstring {
-    let session_id: String = env
-        .get_string(session_id)
-        .expect("Failed to get session ID string")
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
         .into();
     
-    let input: String = env
-        .get_string(input)
-        .expect("Failed to get input string")
+    let device_serial: String = env
+        .get_string(device_serial)
+        .expect("Failed to get device serial string")
         .into();
     
-    let mut sessions = SESSIONS.lock().unwrap();
+    let filter: String = env
+        .get_string(filter)
+        .expect("Failed to get filter string")
+        .into();
     
-    let result = if let Some(session) = sessions.get_mut(&session_id) {
-        if let Some(terminal_process) = &mut session.current_process {
-            if let Some(stdin) = &mut terminal_process.stdin {
-                match writeln!(stdin, "{}", input) {
-                    Ok(_) => {
-                        session.history.push(input);
-                        session.last_activity = current_time_millis();
-                        
+    let device_serial = if device_serial.is_empty() { None } else { Some(device_serial) };
+    let filter = if filter.is_empty() { None } else { Some(filter) };
+    
+    let adb_path = SdkManager::new(Path::new(&sdk_root)).get_adb_path();
+    
+    let result = if !adb_path.exists() {
+        serde_json::json!({
+            "success": false,
+            "message": "adb not found. Install Android SDK platform-tools from the SDK manager"
+        })
+    } else {
+        match start_logcat(&adb_path, device_serial, filter, parsed != 0) {
+            Ok(session_id) => {
+                serde_json::json!({
+                    "success": true,
+                    "session_id": session_id
+                })
+            }
+            Err(e) => {
+                serde_json::json!({
+                    "success": false,
+                    "message": e.to_string()
+                })
+            }
+        }
+    };
+    
+    let json = result.to_string();
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Boot an Android virtual device in a new session. The emulator's output is read with
+// nativeReadOutput; nativeStopAvd shuts it down
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeLaunchAvd(
+    env: JNIEnv,
+    _class: JClass,
+    sdk_root: JString,
+    avd_name: JString,
+    options_json: JString,
+) -> jstring {
+    let sdk_root: String = env
+        .get_string(sdk_root)
+        .expect("Failed to get SDK root string")
+        .into();
+    
+    let avd_name: String = env
+        .get_string(avd_name)
+        .expect("Failed to get AVD name string")
+        .into();
+    
+    let options_json: String = env
+        .get_string(options_json)
+        .expect("Failed to get options JSON string")
+        .into();
+    
+    // An empty options string means the defaults
+    let options = if options_json.trim().is_empty() {
+        Ok(AvdLaunchOptions::default())
+    } else {
+        serde_json::from_str::<AvdLaunchOptions>(&options_json).map_err(|e| anyhow!("Invalid launch options: {}", e))
+    };
+    
+    let sdk_manager = SdkManager::new(Path::new(&sdk_root));
+    let result = match options.and_then(|options| launch_avd(&sdk_manager, &avd_name, &options)) {
+        Ok(session_id) => {
+            serde_json::json!({
+                "success": true,
+                "session_id": session_id
+            })
+        }
+        Err(e) => {
+            serde_json::json!({
+                "success": false,
+                "message": e.to_string()
+            })
+        }
+    };
+    
+    let json = result.to_string();
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Shut down an emulator started with nativeLaunchAvd
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeStopAvd(
+    env: JNIEnv,
+    _class: JClass,
+    session_id: JString,
+) -> jni::sys::jboolean {
+    let session_id: String = env
+        .get_string(session_id)
+        .expect("Failed to get session ID string")
+        .into();
+    
+    match stop_avd(&session_id) {
+        Ok(_) => 1, // true
+        Err(_) => 0, // false
+    }
+}
+
+// Send input to interactive shell
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeSendInput(
+    env: JNIEnv,
+    _class: JClass,
+    session_id: JString,
+    input: JString,
+) -> jstring {
+    let session_id: String = env
+        .get_string(session_id)
+        .expect("Failed to get session ID string")
+        .into();
+    
+    let input: String = env
+        .get_string(input)
+        .expect("Failed to get input string")
+        .into();
+    
+    let mut sessions = SESSIONS.lock().unwrap();
+    
+    let result = if let Some(session) = sessions.get_mut(&session_id) {
+        if let Some(terminal_process) = &mut session.current_process {
+            if let Some(stdin) = &mut terminal_process.stdin {
+                match writeln!(stdin, "{}", input) {
+                    Ok(_) => {
+                        session.push_history(input);
+                        session.last_activity = current_time_millis();
+                        
                         serde_json::json!({
                             "success": true,
                             "message": "Input sent to shell"
@@ -588,69 +1077,92 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
 }
 
 // Read output from interactive shell
+// Drains whatever the reader threads have buffered so far and returns immediately;
+// timeout_ms is kept for compatibility with existing callers.
 #[no_mangle]
 pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeReadOutput(
     env: JNIEnv,
     _class: JClass,
     session_id: JString,
-    timeout_ms: jni::sys::jlong,
+    _timeout_ms: jni::sys::jlong,
 ) -> jstring {
     let session_id: String = env
         .get_string(session_id)
         .expect("Failed to get session ID string")
         .into();
     
-    let timeout_ms = timeout_ms as u64;
-    
     let mut sessions = SESSIONS.lock().unwrap();
     
     let result = if let Some(session) = sessions.get_mut(&session_id) {
-        if let Some(terminal_process) = &mut session.current_process {
-            let mut stdout_output = Vec::new();
-            let mut stderr_output = Vec::new();
+        if let Some(terminal_process) = &session.current_process {
+            let stdout_output = terminal_process.stdout_buffer.drain();
+            let stderr_output = terminal_process.stderr_buffer.drain();
             
-            // Read stdout
-            if let Some(stdout_reader) = &mut terminal_process.stdout_reader {
-                let start_time = Instant::now();
-                let mut buffer = String::new();
-                
-                while start_time.elapsed().as_millis() < timeout_ms as u128 {
-                    match stdout_reader.read_line(&mut buffer) {
-                        Ok(0) => break, // EOF
-                        Ok(_) => {
-                            stdout_output.push(buffer.clone());
-                            buffer.clear();
-                        }
-                        Err(_) => break,
-                    }
-                }
-            }
+            let mut result = serde_json::json!({
+                "success": true,
+                "stdout": stdout_output,
+                "stderr": stderr_output,
+                "timestamp": current_time_millis()
+            });
             
-            // Read stderr
-            if let Some(stderr_reader) = &mut terminal_process.stderr_reader {
-                let start_time = Instant::now();
-                let mut buffer = String::new();
-                
-                while start_time.elapsed().as_millis() < timeout_ms as u128 {
-                    match stderr_reader.read_line(&mut buffer) {
-                        Ok(0) => break, // EOF
-                        Ok(_) => {
-                            stderr_output.push(buffer.clone());
-                            buffer.clear();
-                        }
-                        Err(_) => break,
-                    }
-                }
+            if terminal_process.parse_logcat {
+                result["logcat"] = serde_json::json!(parse_logcat_lines(&stdout_output));
             }
             
             session.last_activity = current_time_millis();
             
+            result
+        } else {
             serde_json::json!({
+                "success": false,
+                "message": "No interactive shell running in this session"
+            })
+        }
+    } else {
+        serde_json::json!({
+            "success": false,
+            "message": "Session not found"
+        })
+    };
+    
+    let json = result.to_string();
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Peek at buffered output from interactive shell without consuming it
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativePeekOutput(
+    env: JNIEnv,
+    _class: JClass,
+    session_id: JString,
+) -> jstring {
+    let session_id: String = env
+        .get_string(session_id)
+        .expect("Failed to get session ID string")
+        .into();
+    
+    let sessions = SESSIONS.lock().unwrap();
+    
+    let result = if let Some(session) = sessions.get(&session_id) {
+        if let Some(terminal_process) = &session.current_process {
+            let stdout_output = terminal_process.stdout_buffer.peek();
+            
+            let mut result = serde_json::json!({
                 "success": true,
                 "stdout": stdout_output,
-                "stderr": stderr_output,
+                "stderr": terminal_process.stderr_buffer.peek(),
                 "timestamp": current_time_millis()
-            })
+            });
+            
+            if terminal_process.parse_logcat {
+                result["logcat"] = serde_json::json!(parse_logcat_lines(&stdout_output));
+            }
+            
+            result
         } else {
             serde_json::json!({
                 "success": false,
@@ -786,8 +1298,10 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
     if let Some(session) = sessions.get_mut(&session_id) {
         match fs::read_to_string(file_path) {
             Ok(content) => {
-                let history: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-                session.history = history;
+                session.history.clear();
+                for line in content.lines() {
+                    session.push_history(line.to_string());
+                }
                 return 1; // true
             }
             Err(_) => return 0, // false
@@ -797,6 +1311,77 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
     0 // false
 }
 
+// Search command history, newest first
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeSearchHistory(
+    env: JNIEnv,
+    _class: JClass,
+    session_id: JString,
+    query: JString,
+) -> jstring {
+    let session_id: String = env
+        .get_string(session_id)
+        .expect("Failed to get session ID string")
+        .into();
+    
+    let query: String = env
+        .get_string(query)
+        .expect("Failed to get query string")
+        .into();
+    
+    let matches: Vec<_> = search_history(&session_id, &query)
+        .into_iter()
+        .map(|(index, command)| serde_json::json!({
+            "index": index,
+            "command": command
+        }))
+        .collect();
+    
+    let json = serde_json::json!({
+        "success": true,
+        "matches": matches
+    })
+    .to_string();
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Set how much history a session keeps and whether repeated commands are recorded
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeSetHistoryOptions(
+    env: JNIEnv,
+    _class: JClass,
+    session_id: JString,
+    max_entries: jni::sys::jint,
+    skip_duplicates: jni::sys::jboolean,
+) -> jni::sys::jboolean {
+    let session_id: String = env
+        .get_string(session_id)
+        .expect("Failed to get session ID string")
+        .into();
+    
+    // Zero or a negative limit restores the default
+    match set_history_options(&session_id, max_entries.max(0) as usize, skip_duplicates != 0) {
+        Ok(_) => 1, // true
+        Err(_) => 0, // false
+    }
+}
+
+// Set how many bytes of each output stream a one-shot command keeps
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeSetOutputLimit(
+    _env: JNIEnv,
+    _class: JClass,
+    max_bytes: jni::sys::jlong,
+) {
+    // Zero or a negative limit restores the default
+    let limit = if max_bytes > 0 { max_bytes as usize } else { DEFAULT_OUTPUT_LIMIT_BYTES };
+    OUTPUT_LIMIT_BYTES.store(limit, Ordering::Relaxed);
+}
+
 // Get session information
 #[no_mangle]
 pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeGetSessionInfo(
@@ -956,12 +1541,87 @@ pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion
     output.into_raw()
 }
 
+// Get the process tree
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeGetProcessTree(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let tree = process_tree();
+    
+    // Convert to JSON
+    let json = serde_json::to_string(&tree).unwrap_or_else(|_| "[]".to_string());
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
+// Kill a process and all of its descendants
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeKillProcessTree(
+    _env: JNIEnv,
+    _class: JClass,
+    pid: jni::sys::jint,
+) -> jni::sys::jboolean {
+    if pid <= 0 {
+        return 0; // false
+    }
+    
+    match kill_process_tree(pid as u32) {
+        Ok(_) => 1, // true
+        Err(_) => 0, // false
+    }
+}
+
+// Parse ANSI escape codes into styled spans
+#[no_mangle]
+pub extern "C" fn Java_com_anyoneide_app_core_RustTerminalManager_00024Companion_nativeParseAnsi(
+    env: JNIEnv,
+    _class: JClass,
+    input: JString,
+) -> jstring {
+    let input: String = env
+        .get_string(input)
+        .expect("Failed to get input string")
+        .into();
+    
+    let spans = parse_ansi(&input);
+    
+    // Convert to JSON
+    let json = serde_json::to_string(&spans).unwrap_or_else(|_| "[]".to_string());
+    
+    let output = env
+        .new_string(json)
+        .expect("Failed to create Java string");
+    output.into_raw()
+}
+
 // Helper function to execute a command
-fn execute_command(command: &str, working_dir: &str) -> CommandOutput {
-    let mut output_lines = Vec::new();
-    let mut error_lines = Vec::new();
+// `stdin_data`, when given, is written to the command's stdin, which is then closed, and
+// `encoding` says how stdout and stderr are decoded
+fn execute_command(command: &str, working_dir: &str, environment: &HashMap<String, String>, stdin_data: Option<&str>, encoding: OutputEncoding) -> CommandOutput {
     let start_time = Instant::now();
     
+    // Expand the working directory itself (e.g. "~/project" or "$PROJECT_DIR")
+    let working_dir = match expand_variables(working_dir, environment) {
+        Ok(dir) => dir,
+        Err(e) => {
+            return CommandOutput {
+                success: false,
+                output: Vec::new(),
+                error_output: vec![e.to_string()],
+                exit_code: 1,
+                execution_time_ms: 0,
+                command: command.to_string(),
+                working_directory: working_dir.to_string(),
+                timestamp: current_time_millis(),
+            };
+        }
+    };
+    let working_dir = working_dir.as_str();
+    
     // Handle built-in commands
     if command.trim() == "clear" {
         return CommandOutput {
@@ -976,9 +1636,28 @@ fn execute_command(command: &str, working_dir: &str) -> CommandOutput {
         };
     }
     
-    if command.starts_with("cd ") {
-        let dir = command[3..].trim();
-        let path = if dir.starts_with('/') {
+    if command.trim() == "cd" || command.starts_with("cd ") {
+        let raw_dir = command.trim()[2..].trim();
+        let raw_dir = if raw_dir.is_empty() { "~" } else { raw_dir };
+        
+        let dir = match expand_variables(raw_dir, environment) {
+            Ok(dir) => dir,
+            Err(e) => {
+                return CommandOutput {
+                    success: false,
+                    output: Vec::new(),
+                    error_output: vec![format!("cd: {}", e)],
+                    exit_code: 1,
+                    execution_time_ms: 0,
+                    command: command.to_string(),
+                    working_directory: working_dir.to_string(),
+                    timestamp: current_time_millis(),
+                };
+            }
+        };
+        let dir = dir.as_str();
+        
+        let path = if Path::new(dir).is_absolute() {
             PathBuf::from(dir)
         } else {
             PathBuf::from(working_dir).join(dir)
@@ -1009,35 +1688,52 @@ fn execute_command(command: &str, working_dir: &str) -> CommandOutput {
         }
     }
     
-    // Execute command with shell
-    let result = Command::new("sh")
-        .arg("-c")
-        .arg(command)
+    if let Err(e) = check_remove_command(command, Path::new(working_dir), environment) {
+        return CommandOutput {
+            success: false,
+            output: Vec::new(),
+            error_output: vec![e.to_string()],
+            exit_code: 1,
+            execution_time_ms: 0,
+            command: command.to_string(),
+            working_directory: working_dir.to_string(),
+            timestamp: current_time_millis(),
+        };
+    }
+    
+    // Execute command with shell
+    let result = shell_command(command)
         .current_dir(working_dir)
+        .env_clear()
+        .envs(environment)
+        .stdin(if stdin_data.is_some() { Stdio::piped() } else { Stdio::inherit() })
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn();
     
     match result {
         Ok(mut child) => {
-            // Read stdout
-            if let Some(stdout) = child.stdout.take() {
-                let reader = BufReader::new(stdout);
-                for line in reader.lines() {
-                    if let Ok(line) = line {
-                        output_lines.push(line);
-                    }
+            // Feed stdin from its own thread so a command that writes a lot before reading all of
+            // its input can't block on a full stdout pipe. Dropping the handle sends EOF
+            let stdin_writer = match (child.stdin.take(), stdin_data) {
+                (Some(mut stdin), Some(data)) => {
+                    let data = data.to_string();
+                    Some(thread::spawn(move || {
+                        // Commands like `head` may exit without reading everything; that's not an error
+                        let _ = stdin.write_all(data.as_bytes());
+                    }))
                 }
-            }
+                _ => None,
+            };
             
-            // Read stderr
-            if let Some(stderr) = child.stderr.take() {
-                let reader = BufReader::new(stderr);
-                for line in reader.lines() {
-                    if let Ok(line) = line {
-                        error_lines.push(line);
-                    }
-                }
+            // Read stderr on its own thread so neither pipe can fill up and stall the command
+            let limit = OUTPUT_LIMIT_BYTES.load(Ordering::Relaxed);
+            let stderr_reader = child.stderr.take().map(|stderr| thread::spawn(move || read_output_lines(stderr, limit, encoding)));
+            let output_lines = child.stdout.take().map(|stdout| read_output_lines(stdout, limit, encoding)).unwrap_or_default();
+            let error_lines = stderr_reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+            
+            if let Some(writer) = stdin_writer {
+                let _ = writer.join();
             }
             
             // Wait for the process to complete
@@ -1083,7 +1779,706 @@ fn execute_command(command: &str, working_dir: &str) -> CommandOutput {
     }
 }
 
+// Run a command in a session. Built-ins that read or change the session's state (cd, pwd,
+// export, unset, env, history) are handled here, since running them through `sh -c` would lose
+// their effect, and commands registered by plugins go to the plugin; anything else runs in the
+// shell with the session's directory and environment
+pub fn execute_in_session(session_id: &str, command: &str) -> Result<CommandOutput> {
+    let (working_dir, environment) = {
+        let mut sessions = SESSIONS.lock().unwrap();
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
+        
+        session.push_history(command.to_string());
+        session.last_activity = current_time_millis();
+        
+        if let Some(output) = handle_builtin(session, command) {
+            return Ok(output);
+        }
+        
+        (session.working_directory.to_string_lossy().to_string(), session.environment.clone())
+    };
+    
+    // Plugins run without the sessions lock, so a slow one doesn't stall the other terminals
+    if let Some(output) = run_plugin_command(command, &working_dir, &environment) {
+        return Ok(output);
+    }
+    
+    Ok(execute_command(command, &working_dir, &environment, None, OutputEncoding::Utf8))
+}
+
+// Handle a session built-in, or return None for anything the shell should run. Commands with
+// pipes, redirections, command lists or substitutions always go to the shell
+fn handle_builtin(session: &mut TerminalSession, command: &str) -> Option<CommandOutput> {
+    let command = command.trim();
+    if has_shell_syntax(command) {
+        return None;
+    }
+    
+    let (name, rest) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+    let working_dir = session.working_directory.to_string_lossy().to_string();
+    
+    let (output, errors) = match name {
+        "cd" => {
+            // execute_command resolves the directory without spawning anything
+            let output = execute_command(command, &working_dir, &session.environment, None, OutputEncoding::Utf8);
+            if output.success {
+                session.working_directory = PathBuf::from(&output.working_directory);
+                session.environment.insert("PWD".to_string(), output.working_directory.clone());
+            }
+            return Some(output);
+        }
+        "pwd" => (vec![working_dir.clone()], Vec::new()),
+        // `env NAME=value command` runs a command, so only the bare form is a built-in
+        "env" if rest.trim().is_empty() => {
+            let mut variables: Vec<String> = session.environment.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+            variables.sort();
+            (variables, Vec::new())
+        }
+        "export" | "unset" => {
+            let args = match split_builtin_args(rest, &session.environment) {
+                Ok(args) => args,
+                Err(e) => return Some(builtin_output(command, &working_dir, Vec::new(), vec![format!("{}: {}", name, e)])),
+            };
+            
+            if name == "export" && args.is_empty() {
+                let mut variables: Vec<String> = session.environment.iter().map(|(key, value)| format!("export {}=\"{}\"", key, value)).collect();
+                variables.sort();
+                return Some(builtin_output(command, &working_dir, variables, Vec::new()));
+            }
+            
+            let mut errors = Vec::new();
+            for arg in args {
+                match (name, arg.split_once('=')) {
+                    ("export", Some((key, value))) if is_valid_variable_name(key) => {
+                        session.environment.insert(key.to_string(), value.to_string());
+                    }
+                    // Every variable is exported already, so `export NAME` has nothing to do
+                    ("export", None) if is_valid_variable_name(&arg) => {}
+                    ("unset", None) if is_valid_variable_name(&arg) => {
+                        session.environment.remove(&arg);
+                    }
+                    _ => errors.push(format!("{}: `{}': not a valid identifier", name, arg)),
+                }
+            }
+            (Vec::new(), errors)
+        }
+        "history" => match rest.trim() {
+            "" => (numbered_history(&session.history, session.history.len()), Vec::new()),
+            count => match count.parse::<usize>() {
+                Ok(count) => (numbered_history(&session.history, count), Vec::new()),
+                Err(_) => (Vec::new(), vec![format!("history: {}: numeric argument required", count)]),
+            },
+        },
+        _ => return None,
+    };
+    
+    Some(builtin_output(command, &working_dir, output, errors))
+}
+
+// Run a command a plugin registered through its registerCommands hook, passing the arguments to
+// the plugin's runCommand hook, or return None when the first word is not a plugin command.
+// As with built-ins, commands using shell syntax always go to the shell. A runCommand that takes
+// longer than an extension may fails the command
+fn run_plugin_command(command: &str, working_dir: &str, environment: &HashMap<String, String>) -> Option<CommandOutput> {
+    let command = command.trim();
+    if has_shell_syntax(command) {
+        return None;
+    }
+    
+    // Arguments the shell would reject, e.g. an unbound variable, are left for it to report
+    let words = split_builtin_args(command, environment).ok()?;
+    let (name, args) = words.split_first()?;
+    let plugin_id = pluginsystem::find_command_plugin(name)?;
+    
+    let start_time = Instant::now();
+    let payload = RunCommandPayload {
+        command: name.clone(),
+        args: args.to_vec(),
+        working_dir: working_dir.to_string(),
+    };
+    let data = serde_json::to_string(&payload).unwrap_or_else(|_| "{}".to_string());
+    let timeout = Duration::from_millis(extensions::DEFAULT_EXTENSION_TIMEOUT_MS);
+    let result = pluginsystem::execute_plugin_hook_with_timeout(&plugin_id, "runCommand", &data, timeout);
+    
+    let mut output = if result.success {
+        builtin_output(command, working_dir, result.data.lines().map(|line| line.to_string()).collect(), Vec::new())
+    } else {
+        let error = result.error.unwrap_or_else(|| format!("{}: plugin command failed", name));
+        builtin_output(command, working_dir, Vec::new(), vec![error])
+    };
+    output.execution_time_ms = start_time.elapsed().as_millis() as u64;
+    
+    Some(output)
+}
+
+// Whether a command uses pipes, redirections, command lists or substitutions
+fn has_shell_syntax(command: &str) -> bool {
+    command.contains(|c| matches!(c, '|' | '&' | ';' | '<' | '>' | '`')) || command.contains("$(")
+}
+
+// The last `count` history entries numbered from 1 like bash's `history`
+fn numbered_history(history: &[String], count: usize) -> Vec<String> {
+    let skip = history.len().saturating_sub(count);
+    history
+        .iter()
+        .enumerate()
+        .skip(skip)
+        .map(|(index, command)| format!("{:5}  {}", index + 1, command))
+        .collect()
+}
+
+fn builtin_output(command: &str, working_dir: &str, output: Vec<String>, error_output: Vec<String>) -> CommandOutput {
+    CommandOutput {
+        success: error_output.is_empty(),
+        exit_code: if error_output.is_empty() { 0 } else { 1 },
+        output,
+        error_output,
+        execution_time_ms: 0,
+        command: command.to_string(),
+        working_directory: working_dir.to_string(),
+        timestamp: current_time_millis(),
+    }
+}
+
+// Split built-in arguments like the shell: whitespace separates words, single quotes keep text
+// literally, double quotes keep spaces but still expand $VAR, and a backslash escapes the next
+// character outside single quotes
+fn split_builtin_args(args: &str, environment: &HashMap<String, String>) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // Unquoted or double-quoted text waiting for variable expansion
+    let mut pending = String::new();
+    let mut in_word = false;
+    let mut chars = args.chars();
+    
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                word.push_str(&expand_variables(&std::mem::take(&mut pending), environment)?);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow!("unexpected end of input looking for a matching '")),
+                    }
+                }
+                in_word = true;
+            }
+            '"' => {
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            word.push_str(&expand_variables(&std::mem::take(&mut pending), environment)?);
+                            word.extend(chars.next());
+                        }
+                        Some(c) => pending.push(c),
+                        None => return Err(anyhow!("unexpected end of input looking for a matching \"")),
+                    }
+                }
+                in_word = true;
+            }
+            '\\' => {
+                word.push_str(&expand_variables(&std::mem::take(&mut pending), environment)?);
+                word.extend(chars.next());
+                in_word = true;
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    word.push_str(&expand_variables(&std::mem::take(&mut pending), environment)?);
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                pending.push(c);
+                in_word = true;
+            }
+        }
+    }
+    
+    if in_word {
+        word.push_str(&expand_variables(&pending, environment)?);
+        words.push(word);
+    }
+    
+    Ok(words)
+}
+
+fn is_valid_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Remove a file or directory unless the remove guard protects it. Without `recursive` only
+// files and empty directories can be removed
+pub fn safe_remove(path: &str, recursive: bool) -> Result<()> {
+    let environment: HashMap<String, String> = env::vars().collect();
+    let path = Path::new(path);
+    check_remove(path, &environment)?;
+    
+    let metadata = fs::symlink_metadata(path).map_err(|e| anyhow!("Cannot remove {}: {}", path.display(), e))?;
+    let result = if !metadata.is_dir() {
+        fs::remove_file(path)
+    } else if recursive {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_dir(path)
+    };
+    
+    result.map_err(|e| anyhow!("Cannot remove {}: {}", path.display(), e))
+}
+
+// Refuse a command whose `rm` would delete a protected path. Every part of a command list or
+// pipeline is checked; globs are judged by the directory they expand in, so `rm -rf ~/*` counts
+// as deleting HOME
+fn check_remove_command(command: &str, working_dir: &Path, environment: &HashMap<String, String>) -> Result<()> {
+    for part in command.split(|c| matches!(c, ';' | '&' | '|' | '\n')) {
+        let is_rm = part
+            .split_whitespace()
+            .next()
+            .map_or(false, |program| Path::new(program).file_name().map_or(false, |name| name == "rm"));
+        if !is_rm {
+            continue;
+        }
+        
+        // The shell would expand an unset variable to nothing, turning `rm -rf $DIR/` into `rm -rf /`
+        let words = split_builtin_args(part, environment)
+            .map_err(|e| anyhow!("rm: refusing to run, {}", e))?;
+        
+        let mut end_of_options = false;
+        for word in words.into_iter().skip(1) {
+            if !end_of_options && word == "--" {
+                end_of_options = true;
+                continue;
+            }
+            if !end_of_options && word.starts_with('-') && word.len() > 1 {
+                continue;
+            }
+            
+            let target = match word.find(|c| matches!(c, '*' | '?' | '[')) {
+                // The directory the glob is expanded in
+                Some(glob_start) => match word[..glob_start].rfind('/') {
+                    Some(slash) => working_dir.join(&word[..=slash]),
+                    None => working_dir.to_path_buf(),
+                },
+                None => working_dir.join(&word),
+            };
+            check_remove(&target, environment)?;
+        }
+    }
+    
+    Ok(())
+}
+
+// Error if removing `path` would delete a protected path or one of its ancestors
+fn check_remove(path: &Path, environment: &HashMap<String, String>) -> Result<()> {
+    let policy = REMOVE_GUARD_POLICY.lock().unwrap().clone();
+    if policy.allow_dangerous_remove {
+        return Ok(());
+    }
+    
+    let target = normalize_path(path);
+    
+    let mut protected = vec![PathBuf::from("/")];
+    protected.extend(environment.get("HOME").filter(|home| !home.is_empty()).map(PathBuf::from));
+    protected.extend(app_data_dirs());
+    protected.extend(policy.protected_paths.iter().map(PathBuf::from));
+    
+    for protected_path in protected.iter().map(|path| normalize_path(path)) {
+        if protected_path == target {
+            return Err(anyhow!("Refusing to delete {}: it is a protected path", target.display()));
+        }
+        if protected_path.starts_with(&target) {
+            return Err(anyhow!("Refusing to delete {}: it contains the protected path {}", target.display(), protected_path.display()));
+        }
+    }
+    
+    Ok(())
+}
+
+// Resolve symlinks when the path exists, otherwise drop "." and ".." components lexically
+fn normalize_path(path: &Path) -> PathBuf {
+    if let Ok(canonical) = fs::canonicalize(path) {
+        return canonical;
+    }
+    
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    
+    normalized
+}
+
+// The app's private data directories on Android, from the process name, which is the package
+// name (with a ":process" suffix for secondary processes)
+fn app_data_dirs() -> Vec<PathBuf> {
+    if !cfg!(target_os = "android") {
+        return Vec::new();
+    }
+    
+    let cmdline = fs::read("/proc/self/cmdline").unwrap_or_default();
+    let process_name = String::from_utf8_lossy(cmdline.split(|byte| *byte == 0).next().unwrap_or_default()).to_string();
+    let package = process_name.split(':').next().unwrap_or_default();
+    
+    if package.is_empty() || package.contains('/') {
+        return Vec::new();
+    }
+    
+    vec![Path::new("/data/data").join(package), Path::new("/data/user/0").join(package)]
+}
+
+// Read a command's output in fixed-size chunks, keeping at most `limit` bytes, and split it
+// into lines. Bytes past the limit are read and discarded so the command can still finish, and
+// a marker line records the truncation. The bytes are decoded with `encoding`; undecodable
+// bytes are replaced rather than failing the read
+fn read_output_lines(mut reader: impl Read, limit: usize, encoding: OutputEncoding) -> Vec<String> {
+    let mut captured = Vec::new();
+    let mut total = 0usize;
+    let mut chunk = [0u8; 8192];
+    
+    loop {
+        match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => {
+                if captured.len() < limit {
+                    let keep = read.min(limit - captured.len());
+                    captured.extend_from_slice(&chunk[..keep]);
+                }
+                total += read;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+    
+    let mut lines: Vec<String> = decode_output(&captured, encoding).lines().map(|line| line.to_string()).collect();
+    if total > captured.len() {
+        lines.push(format!("[output truncated: showing {} of {} bytes]", captured.len(), total));
+    }
+    
+    lines
+}
+
+// Parse an encoding label for execute_command: "" is UTF-8, "auto" detects the encoding and
+// anything else must be a label encoding_rs knows
+fn output_encoding(label: &str, environment: &HashMap<String, String>) -> Result<OutputEncoding> {
+    let label = label.trim();
+    if label.is_empty() {
+        return Ok(OutputEncoding::Utf8);
+    }
+    
+    if label.eq_ignore_ascii_case("auto") {
+        return Ok(OutputEncoding::Detect(locale_encoding(environment)));
+    }
+    
+    Encoding::for_label(label.as_bytes())
+        .map(OutputEncoding::Declared)
+        .ok_or_else(|| anyhow!("Unknown encoding: {}", label))
+}
+
+// The legacy charset named by the locale, e.g. "de_DE.ISO-8859-1@euro", for output that is not
+// UTF-8. Without one, or when the locale is UTF-8 anyway, windows-1252 is used since it decodes
+// every byte
+fn locale_encoding(environment: &HashMap<String, String>) -> &'static Encoding {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| environment.get(*name))
+        .find(|value| !value.is_empty());
+    
+    locale
+        .and_then(|locale| locale.split_once('.'))
+        .map(|(_, charset)| charset.split('@').next().unwrap_or(charset))
+        .and_then(|charset| Encoding::for_label(charset.as_bytes()))
+        .filter(|encoding| *encoding != UTF_8)
+        .unwrap_or(WINDOWS_1252)
+}
+
+// Turn captured output bytes into text
+fn decode_output(bytes: &[u8], encoding: OutputEncoding) -> String {
+    match encoding {
+        OutputEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+        OutputEncoding::Declared(encoding) => encoding.decode_with_bom_removal(bytes).0.into_owned(),
+        OutputEncoding::Detect(fallback) => {
+            if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+                return encoding.decode_without_bom_handling(&bytes[bom_length..]).0.into_owned();
+            }
+            
+            match std::str::from_utf8(bytes) {
+                Ok(text) => text.to_string(),
+                // Output cut off by the size limit can end inside a character; only treat it as
+                // another charset when the bad bytes come earlier
+                Err(e) if e.error_len().is_none() => String::from_utf8_lossy(bytes).into_owned(),
+                Err(_) => fallback.decode_without_bom_handling(bytes).0.into_owned(),
+            }
+        }
+    }
+}
+
+// Expand a leading ~ and $VAR / ${VAR} references using the given environment
+fn expand_variables(input: &str, environment: &HashMap<String, String>) -> Result<String> {
+    let lookup = |name: &str| -> Result<String> {
+        environment
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("{}: unbound variable", name))
+    };
+    
+    let mut result = String::new();
+    let mut rest = input;
+    
+    // A leading ~ (alone or followed by /) refers to HOME
+    if rest == "~" || rest.starts_with("~/") {
+        result.push_str(&lookup("HOME")?);
+        rest = &rest[1..];
+    }
+    
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+        
+        match chars.peek() {
+            Some('{') => {
+                chars.next();
+                let mut name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+                
+                if !closed || name.is_empty() {
+                    return Err(anyhow!("bad substitution"));
+                }
+                
+                result.push_str(&lookup(&name)?);
+            }
+            Some(&next) if next.is_ascii_alphabetic() || next == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        name.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                
+                result.push_str(&lookup(&name)?);
+            }
+            _ => result.push('$'),
+        }
+    }
+    
+    Ok(result)
+}
+
+// Create a session with the default terminal environment; the caller registers it in SESSIONS
+fn new_session(working_dir: &str) -> TerminalSession {
+    let session_id = format!("terminal_{}", Uuid::new_v4().to_string());
+    
+    // Create environment variables
+    let mut environment = HashMap::new();
+    for (key, value) in env::vars() {
+        environment.insert(key, value);
+    }
+    
+    // Add custom environment variables
+    environment.insert("TERM".to_string(), "xterm-256color".to_string());
+    environment.insert("LANG".to_string(), "en_US.UTF-8".to_string());
+    environment.insert("HOME".to_string(), working_dir.to_string());
+    environment.insert("PS1".to_string(), "\\[\\e[32m\\]\\u@\\h:\\[\\e[34m\\]\\w\\[\\e[0m\\]\\$ ".to_string());
+    environment.insert("HISTSIZE".to_string(), DEFAULT_HISTORY_LIMIT.to_string());
+    environment.insert("HISTFILESIZE".to_string(), "2000".to_string());
+    
+    TerminalSession {
+        id: session_id,
+        working_directory: PathBuf::from(working_dir),
+        environment,
+        current_process: None,
+        history: Vec::new(),
+        history_limit: DEFAULT_HISTORY_LIMIT,
+        skip_duplicate_history: true,
+        created_at: current_time_millis(),
+        last_activity: current_time_millis(),
+    }
+}
+
+impl TerminalSession {
+    // Record a command, skipping blank lines and, if enabled, a repeat of the previous entry
+    fn push_history(&mut self, command: String) {
+        if command.trim().is_empty() {
+            return;
+        }
+        
+        if self.skip_duplicate_history && self.history.last() == Some(&command) {
+            return;
+        }
+        
+        self.history.push(command);
+        self.trim_history();
+    }
+    
+    fn trim_history(&mut self) {
+        if self.history.len() > self.history_limit {
+            let excess = self.history.len() - self.history_limit;
+            self.history.drain(..excess);
+        }
+    }
+}
+
+// Find history entries containing `query`, newest first, for a Ctrl-R style reverse search.
+// Each command is returned once, at the index of its most recent use
+pub fn search_history(session_id: &str, query: &str) -> Vec<(usize, String)> {
+    let sessions = SESSIONS.lock().unwrap();
+    
+    let session = match sessions.get(session_id) {
+        Some(session) => session,
+        None => return Vec::new(),
+    };
+    
+    let mut seen = HashSet::new();
+    
+    session.history
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, command)| command.contains(query))
+        .filter(|(_, command)| seen.insert(command.as_str()))
+        .map(|(index, command)| (index, command.clone()))
+        .collect()
+}
+
+// Set a variable in one session's environment without touching the process or other sessions
+pub fn set_session_env(session_id: &str, name: &str, value: &str) -> Result<()> {
+    if name.is_empty() || name.contains('=') || name.contains('\0') {
+        return Err(anyhow!("Invalid environment variable name: {}", name));
+    }
+    
+    let mut sessions = SESSIONS.lock().unwrap();
+    
+    let session = sessions
+        .get_mut(session_id)
+        .ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
+    
+    session.environment.insert(name.to_string(), value.to_string());
+    session.last_activity = current_time_millis();
+    
+    Ok(())
+}
+
+// Change a session's history length and duplicate handling. A limit of 0 means DEFAULT_HISTORY_LIMIT
+pub fn set_history_options(session_id: &str, max_entries: usize, skip_duplicates: bool) -> Result<()> {
+    let mut sessions = SESSIONS.lock().unwrap();
+    
+    let session = sessions
+        .get_mut(session_id)
+        .ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
+    
+    session.history_limit = if max_entries == 0 { DEFAULT_HISTORY_LIMIT } else { max_entries };
+    session.skip_duplicate_history = skip_duplicates;
+    session.environment.insert("HISTSIZE".to_string(), session.history_limit.to_string());
+    session.trim_history();
+    
+    Ok(())
+}
+
+// Read lines from a child stream into an output buffer until EOF. Invalid UTF-8 is replaced
+fn spawn_output_reader<R: Read + Send + 'static>(stream: R, buffer: OutputBuffer) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+        
+        loop {
+            line.clear();
+            // Read bytes rather than a String so one invalid UTF-8 byte can't end the stream
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break, // EOF
+                Ok(_) => buffer.push(String::from_utf8_lossy(&line).into_owned()),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    });
+}
+
+// Build a command that runs the given command line through the platform shell
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+// Build a command that runs the given command line through cmd.exe
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let comspec = env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_string());
+    let mut cmd = Command::new(comspec);
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+// Root commands rely on su, which does not exist on Windows
+#[cfg(windows)]
+fn execute_root_command(command: &str) -> CommandOutput {
+    CommandOutput {
+        success: false,
+        output: Vec::new(),
+        error_output: vec!["Root commands are not supported on this platform".to_string()],
+        exit_code: -1,
+        execution_time_ms: 0,
+        command: format!("su -c '{}'", command),
+        working_directory: "/".to_string(),
+        timestamp: current_time_millis(),
+    }
+}
+
+// Replace the root command policy. Nothing changes if any deny pattern is not a valid regex
+pub fn set_root_command_policy(policy: RootCommandPolicy) -> Result<()> {
+    let patterns = policy.deny_patterns
+        .iter()
+        .map(|pattern| Regex::new(pattern).map_err(|e| anyhow!("Invalid deny pattern {}: {}", pattern, e)))
+        .collect::<Result<Vec<_>>>()?;
+    
+    *ROOT_COMMAND_POLICY.lock().unwrap() = (policy, patterns);
+    
+    Ok(())
+}
+
+// Check a command against the root command policy. Denied commands are an error even when confirmed
+pub fn check_root_command(command: &str, confirmed: bool) -> Result<RootCommandDecision> {
+    let policy = ROOT_COMMAND_POLICY.lock().unwrap();
+    let (policy, patterns) = &*policy;
+    
+    if let Some(pattern) = patterns.iter().find(|pattern| pattern.is_match(command)) {
+        return Err(anyhow!("Root command refused by policy (matches {})", pattern.as_str()));
+    }
+    
+    if policy.require_confirmation && !confirmed {
+        return Ok(RootCommandDecision::NeedsConfirmation);
+    }
+    
+    Ok(RootCommandDecision::Allow)
+}
+
 // Helper function to execute a root command
+#[cfg(not(windows))]
 fn execute_root_command(command: &str) -> CommandOutput {
     let mut output_lines = Vec::new();
     let mut error_lines = Vec::new();
@@ -1161,8 +2556,32 @@ fn execute_root_command(command: &str) -> CommandOutput {
     }
 }
 
-// Check if root is available
+// Check if root is available, probing only the first time
 fn is_root_available() -> bool {
+    let mut root_available = ROOT_AVAILABLE.lock().unwrap();
+    
+    // The lock is held while probing so concurrent callers don't each spawn su
+    *root_available.get_or_insert_with(probe_root)
+}
+
+// Discard the cached root status and probe again
+fn refresh_root_status() -> bool {
+    let mut root_available = ROOT_AVAILABLE.lock().unwrap();
+    
+    let available = probe_root();
+    *root_available = Some(available);
+    available
+}
+
+// Check if root is available
+#[cfg(windows)]
+fn probe_root() -> bool {
+    false
+}
+
+// Check if root is available
+#[cfg(not(windows))]
+fn probe_root() -> bool {
     let result = Command::new("su")
         .arg("-c")
         .arg("id -u")
@@ -1173,20 +2592,19 @@ fn is_root_available() -> bool {
     match result {
         Ok(mut child) => {
             // Read stdout
+            let mut is_root = false;
             if let Some(stdout) = child.stdout.take() {
                 let mut reader = BufReader::new(stdout);
                 let mut output = String::new();
                 if reader.read_line(&mut output).is_ok() {
-                    let output = output.trim();
-                    if output == "0" {
-                        return true;
-                    }
+                    is_root = output.trim() == "0";
                 }
             }
             
+            // Reap su even when the answer is already known
             match child.wait() {
-                Ok(status) => status.success(),
-                Err(_) => false,
+                Ok(status) => is_root || status.success(),
+                Err(_) => is_root,
             }
         }
         Err(_) => false,
@@ -1238,7 +2656,26 @@ fn get_os_info() -> String {
     info
 }
 
+// Get shell path, preferring PowerShell when it is on the PATH
+#[cfg(windows)]
+fn get_shell_path() -> String {
+    if let Ok(path) = env::var("PATH") {
+        for shell in ["pwsh.exe", "powershell.exe"] {
+            for dir in env::split_paths(&path) {
+                let candidate = dir.join(shell);
+                if candidate.exists() {
+                    return candidate.to_string_lossy().to_string();
+                }
+            }
+        }
+    }
+    
+    // Fall back to cmd.exe
+    env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_string())
+}
+
 // Get shell path
+#[cfg(not(windows))]
 fn get_shell_path() -> String {
     // Try to get shell from environment
     if let Ok(shell) = env::var("SHELL") {
@@ -1350,6 +2787,375 @@ pub fn syntax_highlight_terminal_output(output: &str) -> String {
     highlighted
 }
 
+// Styled run of terminal text
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StyledSpan {
+    pub text: String,
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    pub bold: bool,
+    pub underline: bool,
+}
+
+// Current SGR state while parsing
+#[derive(Clone, Default, PartialEq)]
+struct AnsiStyle {
+    fg: Option<String>,
+    bg: Option<String>,
+    bold: bool,
+    underline: bool,
+}
+
+// Parse ANSI escape codes into styled spans. SGR codes (colors, bold, underline,
+// reset) are applied; other escape sequences are dropped.
+pub fn parse_ansi(input: &str) -> Vec<StyledSpan> {
+    let mut spans = Vec::new();
+    let mut style = AnsiStyle::default();
+    let mut text = String::new();
+    let mut chars = input.chars().peekable();
+    
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            text.push(c);
+            continue;
+        }
+        
+        match chars.peek() {
+            // CSI: ESC [ params final-byte
+            Some('[') => {
+                chars.next();
+                let mut params = String::new();
+                let mut final_byte = None;
+                
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        final_byte = Some(c);
+                        break;
+                    }
+                    params.push(c);
+                }
+                
+                if final_byte == Some('m') {
+                    let mut new_style = style.clone();
+                    apply_sgr(&params, &mut new_style);
+                    
+                    if new_style != style {
+                        push_span(&mut spans, &mut text, &style);
+                        style = new_style;
+                    }
+                }
+            }
+            // OSC: ESC ] ... terminated by BEL or ESC \
+            Some(']') => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Other two-character escapes
+            Some(_) => {
+                chars.next();
+            }
+            None => {}
+        }
+    }
+    
+    push_span(&mut spans, &mut text, &style);
+    
+    spans
+}
+
+// Flush accumulated text as a span with the given style
+fn push_span(spans: &mut Vec<StyledSpan>, text: &mut String, style: &AnsiStyle) {
+    if text.is_empty() {
+        return;
+    }
+    
+    spans.push(StyledSpan {
+        text: std::mem::take(text),
+        fg: style.fg.clone(),
+        bg: style.bg.clone(),
+        bold: style.bold,
+        underline: style.underline,
+    });
+}
+
+// Apply an SGR parameter list such as "1;31" to a style
+fn apply_sgr(params: &str, style: &mut AnsiStyle) {
+    let codes: Vec<u32> = if params.is_empty() {
+        vec![0]
+    } else {
+        params.split(';').map(|code| code.parse().unwrap_or(0)).collect()
+    };
+    
+    let mut i = 0;
+    while i < codes.len() {
+        match codes[i] {
+            0 => *style = AnsiStyle::default(),
+            1 => style.bold = true,
+            4 => style.underline = true,
+            22 => style.bold = false,
+            24 => style.underline = false,
+            code @ 30..=37 => style.fg = Some(ansi_color_name(code - 30, false)),
+            code @ 90..=97 => style.fg = Some(ansi_color_name(code - 90, true)),
+            39 => style.fg = None,
+            code @ 40..=47 => style.bg = Some(ansi_color_name(code - 40, false)),
+            code @ 100..=107 => style.bg = Some(ansi_color_name(code - 100, true)),
+            49 => style.bg = None,
+            // Extended colors: 38/48;5;n or 38/48;2;r;g;b
+            code @ (38 | 48) => {
+                let color = match codes.get(i + 1) {
+                    Some(5) => {
+                        let color = codes.get(i + 2).map(|n| format!("ansi256:{}", n));
+                        i += 2;
+                        color
+                    }
+                    Some(2) => {
+                        let color = match (codes.get(i + 2), codes.get(i + 3), codes.get(i + 4)) {
+                            (Some(r), Some(g), Some(b)) => Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+                            _ => None,
+                        };
+                        i += 4;
+                        color
+                    }
+                    _ => None,
+                };
+                
+                if code == 38 {
+                    style.fg = color;
+                } else {
+                    style.bg = color;
+                }
+            }
+            _ => {}
+        }
+        
+        i += 1;
+    }
+}
+
+// Name of one of the 8 basic ANSI colors
+fn ansi_color_name(index: u32, bright: bool) -> String {
+    let name = match index {
+        0 => "black",
+        1 => "red",
+        2 => "green",
+        3 => "yellow",
+        4 => "blue",
+        5 => "magenta",
+        6 => "cyan",
+        _ => "white",
+    };
+    
+    if bright {
+        format!("bright_{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+// Logcat functions
+
+// Spawn `adb logcat` in a new session and return its id.
+// Lines stream into the session's output buffers exactly like an interactive shell.
+pub fn start_logcat(adb_path: &Path, device_serial: Option<String>, filter: Option<String>, parsed: bool) -> Result<String> {
+    let mut command = Command::new(adb_path);
+    
+    if let Some(serial) = &device_serial {
+        command.arg("-s").arg(serial);
+    }
+    
+    command.arg("logcat").arg("-v").arg("threadtime");
+    
+    if let Some(filter) = &filter {
+        command.args(logcat_filter_specs(filter));
+    }
+    
+    let mut process = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start adb logcat: {}", e))?;
+    
+    let stdout_buffer = OutputBuffer::new();
+    let stderr_buffer = OutputBuffer::new();
+    
+    if let Some(stdout) = process.stdout.take() {
+        spawn_output_reader(stdout, stdout_buffer.clone());
+    }
+    
+    if let Some(stderr) = process.stderr.take() {
+        spawn_output_reader(stderr, stderr_buffer.clone());
+    }
+    
+    let mut session = new_session(&env::temp_dir().to_string_lossy());
+    session.current_process = Some(TerminalProcess {
+        process,
+        stdin: None,
+        stdout_buffer,
+        stderr_buffer,
+        command: "adb logcat".to_string(),
+        start_time: current_time_millis(),
+        parse_logcat: parsed,
+    });
+    
+    let session_id = session.id.clone();
+    SESSIONS.lock().unwrap().insert(session_id.clone(), session);
+    
+    Ok(session_id)
+}
+
+// Start the emulator for `avd_name` in a new session and return its id
+pub fn launch_avd(sdk_manager: &SdkManager, avd_name: &str, options: &AvdLaunchOptions) -> Result<String> {
+    let avds = sdk_manager.list_avds()?;
+    if avds.is_empty() {
+        return Err(anyhow!("No Android virtual devices found. Create one with avdmanager first"));
+    }
+    if !avds.iter().any(|avd| avd == avd_name) {
+        return Err(anyhow!("AVD not found: {}. Available: {}", avd_name, avds.join(", ")));
+    }
+    
+    let emulator_path = sdk_manager.get_emulator_path();
+    let mut command = Command::new(&emulator_path);
+    command.arg("-avd").arg(avd_name);
+    
+    if options.no_window {
+        command.arg("-no-window");
+    }
+    if options.no_snapshot {
+        command.arg("-no-snapshot");
+    }
+    if let Some(gpu) = options.gpu.as_deref().filter(|gpu| !gpu.is_empty()) {
+        command.arg("-gpu").arg(gpu);
+    }
+    
+    // The emulator finds system images relative to the SDK it belongs to
+    if let Some(android_sdk_dir) = emulator_path.parent().and_then(Path::parent) {
+        command.env("ANDROID_HOME", android_sdk_dir).env("ANDROID_SDK_ROOT", android_sdk_dir);
+    }
+    
+    let mut process = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start emulator: {}", e))?;
+    
+    let stdout_buffer = OutputBuffer::new();
+    let stderr_buffer = OutputBuffer::new();
+    
+    if let Some(stdout) = process.stdout.take() {
+        spawn_output_reader(stdout, stdout_buffer.clone());
+    }
+    
+    if let Some(stderr) = process.stderr.take() {
+        spawn_output_reader(stderr, stderr_buffer.clone());
+    }
+    
+    let mut session = new_session(&env::temp_dir().to_string_lossy());
+    session.current_process = Some(TerminalProcess {
+        process,
+        stdin: None,
+        stdout_buffer,
+        stderr_buffer,
+        command: format!("emulator -avd {}", avd_name),
+        start_time: current_time_millis(),
+        parse_logcat: false,
+    });
+    
+    let session_id = session.id.clone();
+    SESSIONS.lock().unwrap().insert(session_id.clone(), session);
+    
+    Ok(session_id)
+}
+
+// Stop an emulator session. The launcher runs qemu as a child process, so the whole tree is
+// killed; the session stays open so its last output can still be read
+pub fn stop_avd(session_id: &str) -> Result<()> {
+    let pid = {
+        let mut sessions = SESSIONS.lock().unwrap();
+        let session = sessions.get_mut(session_id).ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
+        session.last_activity = current_time_millis();
+        let terminal_process = session
+            .current_process
+            .as_ref()
+            .ok_or_else(|| anyhow!("No emulator running in session {}", session_id))?;
+        terminal_process.process.id()
+    };
+    
+    kill_process_tree(pid)?;
+    
+    // The process and its output buffers stay in the session; the killed child is only reaped
+    let mut sessions = SESSIONS.lock().unwrap();
+    if let Some(terminal_process) = sessions.get_mut(session_id).and_then(|session| session.current_process.as_mut()) {
+        let _ = terminal_process.process.wait();
+    }
+    
+    Ok(())
+}
+
+// Turn a user filter into logcat filterspecs.
+// Full specs such as "ActivityManager:I *:S" pass through; bare tag names show only those tags.
+fn logcat_filter_specs(filter: &str) -> Vec<String> {
+    let tokens: Vec<&str> = filter.split_whitespace().collect();
+    
+    if tokens.is_empty() || tokens.iter().any(|token| token.contains(':')) {
+        return tokens.iter().map(|token| token.to_string()).collect();
+    }
+    
+    let mut specs: Vec<String> = tokens.iter().map(|tag| format!("{}:V", tag)).collect();
+    specs.push("*:S".to_string());
+    specs
+}
+
+// Parse a logcat line; lines that are not in threadtime format (e.g. "--------- beginning of main")
+// come back with only the message set
+pub fn parse_logcat_line(line: &str) -> LogcatLine {
+    let line = line.trim_end_matches(|c| c == '\n' || c == '\r');
+    
+    match LOGCAT_LINE_REGEX.captures(line) {
+        Some(captures) => LogcatLine {
+            timestamp: Some(captures[1].to_string()),
+            pid: captures[2].parse().ok(),
+            tid: captures[3].parse().ok(),
+            level: Some(logcat_level_name(&captures[4]).to_string()),
+            tag: Some(captures[5].to_string()),
+            message: captures.get(6).map(|m| m.as_str().to_string()).unwrap_or_default(),
+        },
+        None => LogcatLine {
+            timestamp: None,
+            pid: None,
+            tid: None,
+            level: None,
+            tag: None,
+            message: line.to_string(),
+        },
+    }
+}
+
+fn parse_logcat_lines(lines: &[String]) -> Vec<LogcatLine> {
+    lines.iter().map(|line| parse_logcat_line(line)).collect()
+}
+
+fn logcat_level_name(priority: &str) -> &'static str {
+    match priority {
+        "V" => "verbose",
+        "D" => "debug",
+        "I" => "info",
+        "W" => "warn",
+        "E" => "error",
+        "F" | "A" => "fatal",
+        _ => "silent",
+    }
+}
+
 // Process management functions
 
 // List processes
@@ -1404,8 +3210,89 @@ pub fn kill_process(pid: u32) -> Result<()> {
     }
 }
 
-// Process information
+// Build a parent/child tree of running processes.
+// Returns an empty tree when the process list is unavailable (e.g. no ps on the device).
+pub fn process_tree() -> Vec<ProcessNode> {
+    let processes = match list_processes() {
+        Ok(processes) => processes,
+        Err(_) => return Vec::new(),
+    };
+    
+    let pids: HashSet<u32> = processes.iter().map(|process| process.pid).collect();
+    let mut children: HashMap<u32, Vec<ProcessInfo>> = HashMap::new();
+    let mut roots = Vec::new();
+    
+    for process in processes {
+        // Processes whose parent is not listed (or is themselves) become roots
+        if process.ppid == process.pid || !pids.contains(&process.ppid) {
+            roots.push(process);
+        } else {
+            children.entry(process.ppid).or_default().push(process);
+        }
+    }
+    
+    roots
+        .into_iter()
+        .map(|process| build_process_node(process, &mut children))
+        .collect()
+}
+
+// Attach children to a process; each child is removed from the map so cycles can't recurse forever
+fn build_process_node(info: ProcessInfo, children: &mut HashMap<u32, Vec<ProcessInfo>>) -> ProcessNode {
+    let child_processes = children.remove(&info.pid).unwrap_or_default();
+    
+    let child_nodes = child_processes
+        .into_iter()
+        .map(|child| build_process_node(child, children))
+        .collect();
+    
+    ProcessNode {
+        info,
+        children: child_nodes,
+    }
+}
+
+// Kill a process and all of its descendants, children first
+pub fn kill_process_tree(pid: u32) -> Result<()> {
+    let mut descendants = Vec::new();
+    
+    if let Ok(processes) = list_processes() {
+        let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+        for process in &processes {
+            if process.ppid != process.pid {
+                children.entry(process.ppid).or_default().push(process.pid);
+            }
+        }
+        
+        // Collect descendants breadth-first, then kill them deepest-first
+        let mut queue = VecDeque::from(vec![pid]);
+        while let Some(current) = queue.pop_front() {
+            if let Some(child_pids) = children.remove(&current) {
+                for child_pid in child_pids {
+                    descendants.push(child_pid);
+                    queue.push_back(child_pid);
+                }
+            }
+        }
+    }
+    
+    // Children may exit on their own once their parent goes, so ignore failures there
+    for child_pid in descendants.iter().rev() {
+        let _ = kill_process(*child_pid);
+    }
+    
+    kill_process(pid)
+}
+
+// Process tree node
 #[derive(Serialize, Deserialize)]
+pub struct ProcessNode {
+    pub info: ProcessInfo,
+    pub children: Vec<ProcessNode>,
+}
+
+// Process information
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ProcessInfo {
     pub pid: u32,
     pub ppid: u32,
@@ -1415,4 +3302,292 @@ pub struct ProcessInfo {
     pub vsz: u64,
     pub rss: u64,
     pub command: String,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn open_session() -> String {
+        let session = new_session(&env::temp_dir().to_string_lossy());
+        let session_id = session.id.clone();
+        SESSIONS.lock().unwrap().insert(session_id.clone(), session);
+        session_id
+    }
+    
+    #[test]
+    fn sessions_hold_their_own_value_for_a_variable() {
+        let first = open_session();
+        let second = open_session();
+        
+        set_session_env(&first, "ANYONEIDE_SESSION_VAR", "first").unwrap();
+        set_session_env(&second, "ANYONEIDE_SESSION_VAR", "second").unwrap();
+        
+        {
+            let sessions = SESSIONS.lock().unwrap();
+            assert_eq!(sessions[&first].environment["ANYONEIDE_SESSION_VAR"], "first");
+            assert_eq!(sessions[&second].environment["ANYONEIDE_SESSION_VAR"], "second");
+        }
+        assert!(env::var("ANYONEIDE_SESSION_VAR").is_err());
+        
+        #[cfg(unix)]
+        {
+            let output = execute_in_session(&first, "echo $ANYONEIDE_SESSION_VAR").unwrap();
+            assert_eq!(output.output, vec!["first".to_string()]);
+            let output = execute_in_session(&second, "echo $ANYONEIDE_SESSION_VAR").unwrap();
+            assert_eq!(output.output, vec!["second".to_string()]);
+        }
+        
+        let mut sessions = SESSIONS.lock().unwrap();
+        sessions.remove(&first);
+        sessions.remove(&second);
+    }
+    
+    #[test]
+    fn invalid_session_variable_names_are_rejected() {
+        let session_id = open_session();
+        
+        assert!(set_session_env(&session_id, "", "x").is_err());
+        assert!(set_session_env(&session_id, "A=B", "x").is_err());
+        assert!(set_session_env("terminal_missing", "NAME", "x").is_err());
+        
+        SESSIONS.lock().unwrap().remove(&session_id);
+    }
+    
+    #[test]
+    fn huge_blob_without_newlines_is_truncated() {
+        let blob = std::io::repeat(b'x').take(64 * 1024 * 1024);
+        let lines = read_output_lines(blob, 1024 * 1024, OutputEncoding::Utf8);
+        
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), 1024 * 1024);
+        assert_eq!(lines[1], format!("[output truncated: showing {} of {} bytes]", 1024 * 1024, 64 * 1024 * 1024));
+    }
+    
+    #[test]
+    fn binary_output_is_decoded_lossily() {
+        let lines = read_output_lines(&[0xff, b'a', b'\n', 0xc3, b'\n'][..], 1024, OutputEncoding::Utf8);
+        assert_eq!(lines, vec!["\u{fffd}a".to_string(), "\u{fffd}".to_string()]);
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn command_emitting_a_huge_blob_finishes_with_capped_output() {
+        let output = execute_command(
+            "head -c 20000000 /dev/zero",
+            &env::temp_dir().to_string_lossy(),
+            &env::vars().collect(),
+            None,
+            OutputEncoding::Utf8,
+        );
+        
+        assert!(output.success);
+        assert_eq!(output.output.len(), 2);
+        assert_eq!(output.output[0].len(), DEFAULT_OUTPUT_LIMIT_BYTES);
+        assert!(output.output[1].starts_with("[output truncated"));
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn stdin_data_is_piped_to_the_command() {
+        let output = execute_command("cat", &env::temp_dir().to_string_lossy(), &env::vars().collect(), Some("a\nb\n"), OutputEncoding::Utf8);
+        
+        assert!(output.success);
+        assert_eq!(output.output, vec!["a", "b"]);
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn command_closing_stdin_early_still_finishes() {
+        // Far more input than a pipe holds, so writing it fails once `head` has exited
+        let input = "line\n".repeat(1_000_000);
+        let output = execute_command("head -n1", &env::temp_dir().to_string_lossy(), &env::vars().collect(), Some(&input), OutputEncoding::Utf8);
+        
+        assert!(output.success);
+        assert_eq!(output.output, vec!["line"]);
+    }
+    
+    fn builtin_session() -> TerminalSession {
+        new_session(&env::temp_dir().to_string_lossy())
+    }
+    
+    #[test]
+    fn pwd_builtin_reports_the_session_directory() {
+        let mut session = builtin_session();
+        let output = handle_builtin(&mut session, "pwd").unwrap();
+        assert!(output.success);
+        assert_eq!(output.output, vec![env::temp_dir().to_string_lossy().to_string()]);
+    }
+    
+    #[test]
+    fn cd_builtin_moves_the_session() {
+        let target = env::temp_dir().join(format!("anyoneide_cd_{}", Uuid::new_v4()));
+        fs::create_dir_all(&target).unwrap();
+        let mut session = builtin_session();
+        
+        let output = handle_builtin(&mut session, &format!("cd {}", target.display())).unwrap();
+        assert!(output.success, "{:?}", output.error_output);
+        let pwd = handle_builtin(&mut session, "pwd").unwrap();
+        assert_eq!(PathBuf::from(&pwd.output[0]).canonicalize().unwrap(), target.canonicalize().unwrap());
+        assert_eq!(session.environment["PWD"], pwd.output[0]);
+        
+        fs::remove_dir_all(&target).unwrap();
+    }
+    
+    #[test]
+    fn env_builtin_lists_sorted_variables() {
+        let mut session = builtin_session();
+        session.environment.insert("ANYONEIDE_ENV_BUILTIN".to_string(), "yes".to_string());
+        let output = handle_builtin(&mut session, "env").unwrap();
+        
+        assert!(output.output.contains(&"ANYONEIDE_ENV_BUILTIN=yes".to_string()));
+        let mut sorted = output.output.clone();
+        sorted.sort();
+        assert_eq!(output.output, sorted);
+        // With a command to run, env is left to the shell
+        assert!(handle_builtin(&mut session, "env FOO=bar true").is_none());
+    }
+    
+    #[test]
+    fn export_builtin_sets_session_variables() {
+        let mut session = builtin_session();
+        let output = handle_builtin(&mut session, "export ANYONEIDE_EXPORTED=\"two words\" ANYONEIDE_COPY=$HOME/x").unwrap();
+        assert!(output.success, "{:?}", output.error_output);
+        assert_eq!(session.environment["ANYONEIDE_EXPORTED"], "two words");
+        assert_eq!(session.environment["ANYONEIDE_COPY"], format!("{}/x", session.environment["HOME"]));
+        assert!(env::var("ANYONEIDE_EXPORTED").is_err());
+    }
+    
+    #[test]
+    fn bare_export_lists_variables() {
+        let mut session = builtin_session();
+        session.environment.insert("ANYONEIDE_LISTED".to_string(), "v".to_string());
+        let output = handle_builtin(&mut session, "export").unwrap();
+        assert!(output.output.contains(&"export ANYONEIDE_LISTED=\"v\"".to_string()));
+    }
+    
+    #[test]
+    fn unset_builtin_removes_session_variables() {
+        let mut session = builtin_session();
+        session.environment.insert("ANYONEIDE_UNSET_ME".to_string(), "v".to_string());
+        let output = handle_builtin(&mut session, "unset ANYONEIDE_UNSET_ME").unwrap();
+        assert!(output.success);
+        assert!(!session.environment.contains_key("ANYONEIDE_UNSET_ME"));
+    }
+    
+    #[test]
+    fn invalid_identifiers_are_reported() {
+        let mut session = builtin_session();
+        let output = handle_builtin(&mut session, "export 1BAD=x").unwrap();
+        assert!(!output.success);
+        assert_eq!(output.exit_code, 1);
+        assert_eq!(output.error_output, vec!["export: `1BAD=x': not a valid identifier".to_string()]);
+        
+        let output = handle_builtin(&mut session, "unset A=B").unwrap();
+        assert_eq!(output.error_output, vec!["unset: `A=B': not a valid identifier".to_string()]);
+    }
+    
+    #[test]
+    fn history_builtin_numbers_entries() {
+        let mut session = builtin_session();
+        session.history = vec!["ls".to_string(), "pwd".to_string(), "history".to_string()];
+        let output = handle_builtin(&mut session, "history").unwrap();
+        assert_eq!(output.output, vec!["    1  ls", "    2  pwd", "    3  history"]);
+    }
+    
+    #[test]
+    fn history_builtin_limits_to_the_last_entries() {
+        let mut session = builtin_session();
+        session.history = vec!["ls".to_string(), "pwd".to_string(), "history 2".to_string()];
+        let output = handle_builtin(&mut session, "history 2").unwrap();
+        assert_eq!(output.output, vec!["    2  pwd", "    3  history 2"]);
+        
+        let output = handle_builtin(&mut session, "history x").unwrap();
+        assert_eq!(output.error_output, vec!["history: x: numeric argument required".to_string()]);
+    }
+    
+    #[test]
+    fn shell_syntax_is_not_a_builtin() {
+        let mut session = builtin_session();
+        assert!(handle_builtin(&mut session, "pwd | cat").is_none());
+        assert!(handle_builtin(&mut session, "export A=$(date)").is_none());
+        assert!(handle_builtin(&mut session, "ls").is_none());
+    }
+    
+    // Plugin hooks providing a terminal command that greets its arguments
+    struct FakeCommandHook(&'static str);
+    
+    impl pluginsystem::PluginHook for FakeCommandHook {
+        fn execute(&self, payload: &pluginsystem::HookPayload) -> Result<String> {
+            match payload {
+                pluginsystem::HookPayload::RegisterCommands(_) => Ok(format!(r#"["{}"]"#, self.0)),
+                pluginsystem::HookPayload::RunCommand(run) => Ok(format!("hello {}", run.args.join(" "))),
+                _ => Err(anyhow!("unexpected hook")),
+            }
+        }
+    }
+    
+    // Load a plugin whose registerCommands and runCommand hooks provide `command`
+    fn load_command_plugin(plugin_id: &str, command: &'static str) -> PathBuf {
+        pluginsystem::load_test_plugin(plugin_id, &["registerCommands", "runCommand"], Arc::new(FakeCommandHook(command)))
+    }
+    
+    #[test]
+    fn plugin_commands_run_through_the_plugin() {
+        let dir = load_command_plugin("com.example.greet-command", "greet-plugin");
+        let session_id = open_session();
+        
+        assert_eq!(pluginsystem::find_command_plugin("greet-plugin").as_deref(), Some("com.example.greet-command"));
+        let output = execute_in_session(&session_id, "greet-plugin 'big world'").unwrap();
+        
+        SESSIONS.lock().unwrap().remove(&session_id);
+        pluginsystem::unload_plugin("com.example.greet-command").unwrap();
+        let _ = fs::remove_dir_all(dir);
+        
+        assert!(output.success, "{:?}", output.error_output);
+        assert_eq!(output.output, vec!["hello big world".to_string()]);
+        assert!(pluginsystem::find_command_plugin("greet-plugin").is_none());
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn unknown_commands_fall_through_to_the_shell() {
+        let dir = load_command_plugin("com.example.fallthrough-command", "fallthrough-plugin");
+        let session_id = open_session();
+        
+        assert!(pluginsystem::find_command_plugin("echo").is_none());
+        let echoed = execute_in_session(&session_id, "echo from-the-shell").unwrap();
+        let missing = execute_in_session(&session_id, "anyoneide-no-such-command").unwrap();
+        
+        SESSIONS.lock().unwrap().remove(&session_id);
+        pluginsystem::unload_plugin("com.example.fallthrough-command").unwrap();
+        let _ = fs::remove_dir_all(dir);
+        
+        assert_eq!(echoed.output, vec!["from-the-shell".to_string()]);
+        assert!(!missing.success);
+        assert_eq!(missing.exit_code, 127);
+    }
+    
+    #[test]
+    fn cd_expands_home_in_the_path() {
+        let home = env::temp_dir().join(format!("anyoneide_home_{}", Uuid::new_v4()));
+        fs::create_dir_all(home.join("sub")).unwrap();
+        let mut environment = HashMap::new();
+        environment.insert("HOME".to_string(), home.to_string_lossy().to_string());
+        
+        let output = execute_command("cd $HOME/sub", "/", &environment, None, OutputEncoding::Utf8);
+        let _ = fs::remove_dir_all(&home);
+        
+        assert!(output.success, "{:?}", output.error_output);
+        assert_eq!(PathBuf::from(output.working_directory), home.join("sub"));
+    }
+    
+    #[test]
+    fn cd_to_an_unset_variable_fails() {
+        let output = execute_command("cd ${ANYONEIDE_UNSET_VAR}", "/", &HashMap::new(), None, OutputEncoding::Utf8);
+        
+        assert!(!output.success);
+        assert_eq!(output.working_directory, "/");
+        assert_eq!(output.error_output, vec!["cd: ANYONEIDE_UNSET_VAR: unbound variable".to_string()]);
+    }
+}